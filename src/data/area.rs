@@ -1,17 +1,14 @@
-use std::{fmt, io::Cursor};
+use std::{fmt, io::{self, Cursor}};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use uuid::Uuid;
 
-use egui::{Color32, Pos2, Rect, Vec2};
+use egui::{Pos2, Rect, Vec2};
 
-use crate::{engine::compression::segment_wrap, utils::{log_write, LogLevel}};
+use crate::{engine::compression::segment_wrap, utils::{log_write, new_uuid, LogLevel}};
 
 use super::{Compilable, TopLevelSegment};
 
-pub const AREA_RECT_COLOR: Color32 = Color32::from_rgba_premultiplied(0x60, 0x00, 0x00, 0x40);
-pub const AREA_RECT_COLOR_SELECTED: Color32 = Color32::from_rgba_premultiplied(0x80, 0x10, 0x10, 0x50);
-
 #[derive(Debug,Clone,PartialEq,Default)]
 pub struct TriggerData {
     pub triggers: Vec<Trigger>
@@ -37,7 +34,7 @@ impl TopLevelSegment for TriggerData {
     }
 }
 impl TriggerData {
-    pub fn new(byte_data: &[u8]) -> Self {
+    pub fn new(byte_data: &[u8]) -> io::Result<Self> {
         let mut rdr = Cursor::new(byte_data);
         let seg_end: usize = byte_data.len();
         let mut ret: TriggerData = TriggerData::default(); // Empty
@@ -49,13 +46,30 @@ impl TriggerData {
                 }
                 Ok(left_x) => left_x,
             };
-            let top_y = rdr.read_u16::<LittleEndian>().expect("top_y in TriggerData");
-            let right_x = rdr.read_u16::<LittleEndian>().expect("right_x in TriggerData");
-            let bottom_y = rdr.read_u16::<LittleEndian>().expect("bottom_y in TriggerData");
+            let top_y = rdr.read_u16::<LittleEndian>()?;
+            let right_x = rdr.read_u16::<LittleEndian>()?;
+            let bottom_y = rdr.read_u16::<LittleEndian>()?;
             let t = Trigger::new(left_x, top_y, right_x, bottom_y);
             ret.triggers.push(t);
         }
-        ret
+        Ok(ret)
+    }
+
+    /// Moves every Trigger by dx/dy tiles, removing the ones that go past an edge
+    pub fn shift(&mut self, dx: i32, dy: i32, width: u16, height: u16) -> usize {
+        let initial_len = self.triggers.len();
+        self.triggers.retain_mut(|trigger| {
+            let (left, right) = (trigger.left_x as i32 + dx, trigger.right_x as i32 + dx);
+            let (top, bottom) = (trigger.top_y as i32 + dy, trigger.bottom_y as i32 + dy);
+            // Right and bottom are the far edges, so they can equal the size
+            if left < 0 || top < 0 || right > width as i32 || bottom > height as i32 {
+                return false;
+            }
+            (trigger.left_x, trigger.right_x) = (left as u16, right as u16);
+            (trigger.top_y, trigger.bottom_y) = (top as u16, bottom as u16);
+            true
+        });
+        initial_len - self.triggers.len()
     }
 
     pub fn delete(&mut self, uuid: Uuid) -> bool {
@@ -97,7 +111,7 @@ impl Compilable for Trigger {
 impl Trigger {
     pub fn new(left_x: u16,top_y: u16,right_x: u16,bottom_y: u16) -> Self {
         Self {
-            left_x, top_y, right_x, bottom_y, uuid: Uuid::new_v4()
+            left_x, top_y, right_x, bottom_y, uuid: new_uuid()
         }
     }
     pub fn get_rect(&self, top_left_screen: Pos2, tile_width_px: f32, tile_height_px: f32) -> Rect {
@@ -9,9 +9,9 @@ use std::{error::Error, fmt::{self, Display}, io::{Cursor, Read}, path::Path};
 
 use byteorder::{LittleEndian, ReadBytesExt};
 
-use crate::{engine::compression::{lamezip77_lz10_decomp, segment_wrap}, utils::{header_to_string, log_write, LogLevel}};
+use crate::{engine::compression::{lamezip77_lz10_decomp, segment_wrap}, utils::{header_to_string, log_write, tile_has_pixels, LogLevel}};
 
-use super::{scendata::{anmz::AnmzDataSegment, colz::CollisionData, imbz::ImbzData, imgb::ImgbData, info::ScenInfoData, mpbz::MapTileDataSegment, plan::AnimatedPaletteData, pltb::PltbData, rast::RastData, scrl::ScrollData, ScenSegment, ScenSegmentWrapper}, types::Palette, TopLevelSegment};
+use super::{scendata::{anmz::AnmzDataSegment, colz::CollisionData, imbz::ImbzData, imgb::ImgbData, info::ScenInfoData, mpbz::MapTileDataSegment, plan::AnimatedPaletteData, pltb::PltbData, rast::RastData, scrl::ScrollData, GenericScenSegment, ScenSegment, ScenSegmentWrapper}, types::{MapTileRecordData, Palette}, TopLevelSegment};
 
 #[derive(Debug,Clone,PartialEq,Default)]
 pub struct BackgroundData {
@@ -30,36 +30,67 @@ impl fmt::Display for BackgroundData {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum BackgroundDataError {
     FailedToCreateINFO,
-    UnknownSCENSegment(String),
     MismatchInLoadedSegments(usize, usize),
+    /// Header, then what went wrong
+    BadSubSegment(String, String),
 }
 impl Display for BackgroundDataError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::FailedToCreateINFO => f.write_fmt(format_args!("Failed to create INFO")),
-            Self::UnknownSCENSegment(s) => f.write_fmt(format_args!("Unknown segment in SCEN: {s}")),
             Self::MismatchInLoadedSegments(a, b) => f.write_fmt(format_args!("Mismatch in loaded segments versus load count: {a} vs {b}")),
+            Self::BadSubSegment(header, reason) => f.write_fmt(format_args!("Could not read sub-segment '{header}': {reason}")),
         }
     }
 }
 impl Error for BackgroundDataError {}
 
+fn bad_sub_segment(header: String, reason: impl Display) -> BackgroundDataError {
+    let err = BackgroundDataError::BadSubSegment(header, reason.to_string());
+    log_write(&err, LogLevel::Error);
+    err
+}
+
 impl BackgroundData {
+    /// Rough heap size in bytes, for limiting undo memory
+    pub fn estimate_memory_size(&self) -> usize {
+        let preview_size = self.pixel_tiles_preview.as_ref().map_or(0, |p| p.len());
+        let segments_size: usize = self.scen_segments.iter().map(|seg| match seg {
+            ScenSegmentWrapper::INFO(_) | ScenSegmentWrapper::SCRL(_) => 0,
+            ScenSegmentWrapper::COLZ(colz) => colz.col_tiles.len(),
+            ScenSegmentWrapper::PLTB(pltb) => pltb.palettes.len() * size_of::<Palette>(),
+            ScenSegmentWrapper::MPBZ(mpbz) => mpbz.tiles.len() * size_of::<MapTileRecordData>(),
+            ScenSegmentWrapper::ANMZ(anmz) => anmz.frame_holds.len() + anmz.pixeltiles.len() + anmz._raw_decomp.len(),
+            ScenSegmentWrapper::IMGB(imgb) => imgb.pixel_tiles.len(),
+            ScenSegmentWrapper::IMBZ(imbz) => imbz.pixel_tiles.len(),
+            ScenSegmentWrapper::PLAN(plan) => plan._raw.len(),
+            ScenSegmentWrapper::RAST(rast) => rast._raw.len(),
+            ScenSegmentWrapper::Unknown(unkn) => unkn.raw_bytes.len(),
+        }).sum();
+        size_of::<Self>() + preview_size + segments_size
+    }
+
     pub fn new(vec: &[u8], project_directory: &Path) -> Result<BackgroundData, BackgroundDataError> {
         // Since the issue is commonly tied to a specific background, this should stick out
         log_write("> Creating SCEN...", LogLevel::Debug);
         let mut ret: BackgroundData = BackgroundData::default();
         let mut info_store = ScenInfoData::default();
         let mut rdr = Cursor::new(vec);
-        let file_end_pos: u64 = vec.len().try_into().unwrap();
+        let file_end_pos: u64 = vec.len() as u64;
         let mut test_load_count: usize = 0;
         while rdr.position() < file_end_pos {
             test_load_count += 1;
             // Data for loading loop    
-            let seg_header = rdr.read_u32::<LittleEndian>().unwrap();
-            let seg_internal_length = rdr.read_u32::<LittleEndian>().unwrap();
+            let (Ok(seg_header), Ok(seg_internal_length)) = (rdr.read_u32::<LittleEndian>(), rdr.read_u32::<LittleEndian>()) else {
+                return Err(bad_sub_segment(String::from("????"), "Data ended inside a sub-segment header"));
+            };
             let seg_header_str = header_to_string(&seg_header);
             log_write(format!("Reading sub-segment '{}' with size 0x{:X}",seg_header_str,seg_internal_length), LogLevel::Debug);
+            // Checked up front, so a bad length can't ask for a huge buffer
+            if rdr.position() + seg_internal_length as u64 > file_end_pos {
+                return Err(bad_sub_segment(seg_header_str, format!("Size 0x{:X} is past the end of the SCEN",seg_internal_length)));
+            }
+            let read_err = |e: std::io::Error| bad_sub_segment(seg_header_str.clone(), e);
 
             match seg_header_str.as_str() {
                 "INFO" => {
@@ -84,7 +115,7 @@ impl BackgroundData {
                 }
                 "COLZ" => {
                     let mut compressed_buffer: Vec<u8> = vec![0;seg_internal_length as usize];
-                    let _read_res: Result<(), std::io::Error> = rdr.read_exact(&mut compressed_buffer);
+                    rdr.read_exact(&mut compressed_buffer).map_err(read_err)?;
                     let colz_obj = CollisionData::new(&compressed_buffer);
                     ret.scen_segments.push(ScenSegmentWrapper::COLZ(colz_obj));
                 }
@@ -99,7 +130,7 @@ impl BackgroundData {
                         let count_16: u32 = seg_internal_length / (16*2);
                         let mut index: u32 = 0;
                         while index < count_16 {
-                            let pal = Palette::from_cursor(&mut rdr,16);
+                            let pal = Palette::from_cursor(&mut rdr,16).map_err(read_err)?;
                             pal_vec.push(pal);
                             index += 1;
                         }
@@ -110,7 +141,7 @@ impl BackgroundData {
                         // But the garbage data will never be read anyway so...
                         // Future issue may be if this is the last segment with nothing after it
                         let start_pos = rdr.position();
-                        pal_vec.push(Palette::from_cursor(&mut rdr, 256));
+                        pal_vec.push(Palette::from_cursor(&mut rdr, 256).map_err(read_err)?);
                         rdr.set_position(start_pos + seg_internal_length as u64);
                     }
                     let pltb = PltbData::from_pal_vec(pal_vec);
@@ -119,9 +150,9 @@ impl BackgroundData {
                 }
                 "MPBZ" => {
                     let mut buffer: Vec<u8> = vec![0;seg_internal_length as usize];
-                    let _read_res = rdr.read_exact(&mut buffer);
+                    rdr.read_exact(&mut buffer).map_err(read_err)?;
                     let mp_decomp = lamezip77_lz10_decomp(&buffer);
-                    let mpbz = MapTileDataSegment::from_decomped_vec(&mp_decomp,info_store.layer_width);
+                    let mpbz = MapTileDataSegment::from_decomped_vec(&mp_decomp,info_store.layer_width).map_err(read_err)?;
                     // Probably get rid of this eventually, or only activate in debug mode
                     mpbz.test_against_raw_decomp(Some(&info_store), &mp_decomp);
                     let mpbz_wrapped = ScenSegmentWrapper::MPBZ(mpbz);
@@ -129,7 +160,7 @@ impl BackgroundData {
                 }
                 "IMGB" => {
                     let mut buffer: Vec<u8> = vec![0;seg_internal_length as usize];
-                    let _read_res = rdr.read_exact(&mut buffer);
+                    rdr.read_exact(&mut buffer).map_err(read_err)?;
                     let imgb_data = ImgbData::new(buffer.clone());
                     ret.scen_segments.push(ScenSegmentWrapper::IMGB(imgb_data));
                     // Update preview
@@ -140,7 +171,7 @@ impl BackgroundData {
                 }
                 "IMBZ" => {
                     let mut imbz_comped_buffer: Vec<u8> = vec![0;seg_internal_length as usize];
-                    let _read_res = rdr.read_exact(&mut imbz_comped_buffer);
+                    rdr.read_exact(&mut imbz_comped_buffer).map_err(read_err)?;
                     let wrapped = ScenSegmentWrapper::IMBZ(ImbzData::new(&imbz_comped_buffer));
                     ret.scen_segments.push(wrapped);
 
@@ -153,7 +184,7 @@ impl BackgroundData {
                 }
                 "ANMZ" => {
                     let mut buffer: Vec<u8> = vec![0;seg_internal_length as usize];
-                    let _read_res = rdr.read_exact(&mut buffer);
+                    rdr.read_exact(&mut buffer).map_err(read_err)?;
                     let anmz_decomped = lamezip77_lz10_decomp(&buffer);
                     // The real one to use for previews
                     let anmz_data = match AnmzDataSegment::from_decomp(anmz_decomped) {
@@ -166,58 +197,33 @@ impl BackgroundData {
                     ret.scen_segments.push(ScenSegmentWrapper::ANMZ(anmz_data));
                 }
                 "SCRL" => {
-                    let scrl = ScrollData::new(&mut rdr);
+                    let scrl = ScrollData::new(&mut rdr).map_err(read_err)?;
                     let scrl_seg = ScenSegmentWrapper::SCRL(scrl);
                     ret.scen_segments.push(scrl_seg);
                 }
                 "PLAN" => {
                     let mut buffer: Vec<u8> = vec![0;seg_internal_length as usize];
-                    let _read_res = rdr.read_exact(&mut buffer);
+                    rdr.read_exact(&mut buffer).map_err(read_err)?;
                     let plan = AnimatedPaletteData::new(buffer);
                     ret.scen_segments.push(ScenSegmentWrapper::PLAN(plan));
                 }
                 "RAST" => {
                     let mut buffer: Vec<u8> = vec![0;seg_internal_length as usize];
-                    let _read_res = rdr.read_exact(&mut buffer);
+                    rdr.read_exact(&mut buffer).map_err(read_err)?;
                     let rast = RastData::new(buffer);
                     ret.scen_segments.push(ScenSegmentWrapper::RAST(rast));
                 }
                 _ => {
-                    // I wrote a script to check every single one
-                    // This should not be possible
-                    let error = BackgroundDataError::UnknownSCENSegment(seg_header_str);
-                    log_write(&error, LogLevel::Error);
-                    return Err(error);
-                    // let mut _buffer: Vec<u8> = vec![0;seg_internal_length as usize];
-                    // let _read_res = rdr.read_exact(&mut _buffer);
+                    // None in the base game, but hacks could add them
+                    log_write(format!("SCEN sub-segment '{}' unhandled, storing raw data",&seg_header_str), LogLevel::Warn);
+                    let mut buffer: Vec<u8> = vec![0;seg_internal_length as usize];
+                    rdr.read_exact(&mut buffer).map_err(read_err)?;
+                    ret.scen_segments.push(ScenSegmentWrapper::Unknown(GenericScenSegment::new(buffer, seg_header_str)));
                 }
             }
         }
 
-        // Apply ANMZ preview //
-        if let Some(anmz_data) = ret.get_anmz().cloned() {
-            let mut cur_vram_offset: usize = anmz_data.vram_offset as usize;
-            if info_store.color_mode > 0x1 {
-                log_write("Color Modes above 1 may be poorly supported", LogLevel::Warn);
-            }
-            if info_store.is_256_colorpal_mode() {
-                cur_vram_offset *= 64;
-            } else {
-                cur_vram_offset *= 32;
-            }
-            if let Some(pixeltiles) = &mut ret.pixel_tiles_preview {
-                for pixeltile in &anmz_data.pixeltiles {
-                    // This could probably be done more efficiently
-                    while cur_vram_offset >= pixeltiles.len() {
-                        pixeltiles.push(0x00);
-                    }
-                    pixeltiles[cur_vram_offset] = *pixeltile;
-                    cur_vram_offset += 1;
-                }
-            } else {
-                log_write("Unable to unwrap pixeltiles when creating ANMZ", LogLevel::Error);
-            }
-        }
+        ret.apply_anmz_preview(&info_store);
 
         if ret.scen_segments.len() != test_load_count {
             let mismatch_msg = BackgroundDataError::MismatchInLoadedSegments(ret.scen_segments.len(),test_load_count);
@@ -230,6 +236,135 @@ impl BackgroundData {
         Ok(ret)
     }
 
+    /// Writes the ANMZ's first frame over the preview pixels, where it sits in VRAM
+    fn apply_anmz_preview(&mut self, info: &ScenInfoData) {
+        let Some(anmz_data) = self.get_anmz().cloned() else {
+            return;
+        };
+        let mut cur_vram_offset: usize = anmz_data.vram_offset as usize;
+        if info.color_mode > 0x1 {
+            log_write("Color Modes above 1 may be poorly supported", LogLevel::Warn);
+        }
+        if info.is_256_colorpal_mode() {
+            cur_vram_offset *= 64;
+        } else {
+            cur_vram_offset *= 32;
+        }
+        if let Some(pixeltiles) = &mut self.pixel_tiles_preview {
+            for pixeltile in &anmz_data.pixeltiles {
+                // This could probably be done more efficiently
+                while cur_vram_offset >= pixeltiles.len() {
+                    pixeltiles.push(0x00);
+                }
+                pixeltiles[cur_vram_offset] = *pixeltile;
+                cur_vram_offset += 1;
+            }
+        } else {
+            log_write("Unable to unwrap pixeltiles when creating ANMZ", LogLevel::Error);
+        }
+    }
+
+    /// Reads the tileset again, from the IMBZ file INFO points to or the IMGB/IMBZ
+    /// segment, for when it was replaced outside the editor. False if none was found
+    pub fn reload_pixel_tiles_preview(&mut self, project_directory: &Path) -> bool {
+        let Some(info) = self.get_info().cloned() else {
+            log_write("No INFO to reload the tileset from", LogLevel::Error);
+            return false;
+        };
+        let mut pixels: Option<Vec<u8>> = Option::None;
+        if info.imbz_filename_noext.is_some() {
+            pixels = info.get_imbz_pixels(project_directory.to_path_buf());
+        }
+        // Same as loading, a segment after INFO replaces it
+        for seg in &self.scen_segments {
+            match seg {
+                ScenSegmentWrapper::IMGB(imgb) => pixels = Some(imgb.pixel_tiles.clone()),
+                ScenSegmentWrapper::IMBZ(imbz) => pixels = Some(imbz.pixel_tiles.clone()),
+                _ => { /* Not tiles */ }
+            }
+        }
+        let Some(pixels) = pixels else {
+            log_write(format!("No tileset found to reload on BG {}",info.which_bg), LogLevel::Warn);
+            return false;
+        };
+        self.pixel_tiles_preview = Some(pixels);
+        self.apply_anmz_preview(&info);
+        true
+    }
+
+    /// Create an empty layer from scratch, no tiles and no collision set
+    /// 
+    /// The INFO is based on an existing one so the VRAM settings (char/screen base
+    /// blocks, color mode, tileset) are known good, only the size and offsets change
+    pub fn new_blank(base_info: &ScenInfoData, pltb: PltbData, width: u16, height: u16, with_collision: bool) -> Self {
+        log_write(format!("Creating blank SCEN for BG {} with size 0x{:X}/0x{:X}",base_info.which_bg,width,height), LogLevel::Debug);
+        let info = ScenInfoData {
+            layer_width: width,
+            layer_height: height,
+            x_offset_px: 0,
+            y_offset_px: 0,
+            ..base_info.clone()
+        };
+        let tile_count = (width as usize) * (height as usize);
+        let mpbz = MapTileDataSegment {
+            tiles: vec![MapTileRecordData::default(); tile_count],
+            tile_offset: 0,
+            bottom_trim: 0
+        };
+        let mut ret = BackgroundData::default();
+        ret.scen_segments.push(ScenSegmentWrapper::INFO(info));
+        ret.scen_segments.push(ScenSegmentWrapper::PLTB(pltb));
+        ret.scen_segments.push(ScenSegmentWrapper::MPBZ(mpbz));
+        if with_collision {
+            // Collision tiles are 2x2
+            let colz = CollisionData {
+                col_tiles: vec![0x00; (width as usize / 2) * (height as usize / 2)]
+            };
+            ret.scen_segments.push(ScenSegmentWrapper::COLZ(colz));
+        }
+        ret
+    }
+
+    /// Adds delta to the palette row of each tile at map_indexes, clamped to 0-15
+    ///
+    /// Blank tiles are skipped. Returns how many tiles changed, and how many of
+    /// those now use a row past the ones this layer's PLTB provides
+    pub fn shift_palettes(&mut self, map_indexes: &[u32], delta: i16) -> (usize, usize) {
+        let row_count = self.get_pltb().map_or(0, |pltb| pltb.palettes.len());
+        let Some(mpbz) = self.get_mpbz_mut() else {
+            log_write("No MPBZ when shifting palettes", LogLevel::Error);
+            return (0, 0);
+        };
+        let mut changed: usize = 0;
+        let mut over_rows: usize = 0;
+        for map_index in map_indexes {
+            let Some(tile_index) = mpbz.tile_index(*map_index) else { continue };
+            let tile = &mut mpbz.tiles[tile_index];
+            if tile.to_short() == 0x0000 {
+                continue;
+            }
+            let new_palette = (tile.palette_id as i16 + delta).clamp(0, 15) as u16;
+            if new_palette == tile.palette_id {
+                continue;
+            }
+            tile.palette_id = new_palette;
+            changed += 1;
+            if new_palette as usize >= row_count {
+                over_rows += 1;
+            }
+        }
+        (changed, over_rows)
+    }
+
+    /// Moves tiles and collision by dx/dy tiles, returns how many of each went off the edge
+    pub fn shift_contents(&mut self, dx: i32, dy: i32) -> (usize, usize) {
+        let info = self.get_info().expect("INFO is always there");
+        let (width, height) = (info.layer_width, info.layer_height);
+        let lost_tiles = self.get_mpbz_mut().map_or(0, |mpbz| mpbz.shift(dx, dy, width, height));
+        let lost_col = self.get_colz_mut().map_or(0, |colz| colz.shift(dx, dy, width, height));
+        (lost_tiles, lost_col)
+    }
+
     pub fn get_mpbz_mut(&mut self) -> Option<&mut MapTileDataSegment> {
         for seg in &mut self.scen_segments {
             if let ScenSegmentWrapper::MPBZ(mpbz) = seg {
@@ -293,6 +428,36 @@ impl BackgroundData {
         Option::None
     }
 
+    /// The MPBZ length and what INFO says it should be, if they differ
+    pub fn tile_length_mismatch(&self) -> Option<(usize, usize)> {
+        let info = self.get_info()?;
+        let mpbz = self.get_mpbz()?;
+        let expected = mpbz.expected_len(info.layer_width, info.layer_height);
+        (mpbz.tiles.len() != expected).then_some((mpbz.tiles.len(), expected))
+    }
+
+    /// Map tiles with no pixel data in the tileset, see tile_has_pixels.
+    /// Zero if there is no tileset loaded to check against
+    pub fn error_tile_count(&self) -> usize {
+        let (Some(info), Some(mpbz), Some(pixel_tiles)) = (self.get_info(), self.get_mpbz(), &self.pixel_tiles_preview) else {
+            return 0;
+        };
+        let is_256 = info.is_256_colorpal_mode();
+        mpbz.tiles.iter().filter(|tile| !tile_has_pixels(pixel_tiles.len(), tile.tile_id, is_256)).count()
+    }
+
+    /// See MapTileDataSegment::repair_length
+    pub fn repair_tile_length(&mut self) {
+        let Some(info) = self.get_info() else {
+            log_write("No INFO when repairing MPBZ length", LogLevel::Error);
+            return;
+        };
+        let (width, height) = (info.layer_width, info.layer_height);
+        if let Some(mpbz) = self.get_mpbz_mut() {
+            mpbz.repair_length(width, height);
+        }
+    }
+
     pub fn get_info(&self) -> Option<&ScenInfoData> {
         for seg in &self.scen_segments {
             if let ScenSegmentWrapper::INFO(info) = seg {
@@ -312,7 +477,7 @@ impl BackgroundData {
     }
 
     pub fn increase_width(&mut self, new_width: u16) -> Option<u16> {
-        if new_width % 2 != 0 {
+        if !new_width.is_multiple_of(2) {
             log_write(format!("Cannot make width odd (0x{:X})",new_width),LogLevel::Warn);
             return None;
         }
@@ -336,7 +501,7 @@ impl BackgroundData {
     }
 
     pub fn decrease_width(&mut self, new_width: u16) -> Option<u16> {
-        if new_width % 2 != 0 {
+        if !new_width.is_multiple_of(2) {
             log_write(format!("Cannot make width odd (0x{:X})",new_width),LogLevel::Warn);
             return None;
         }
@@ -363,7 +528,7 @@ impl BackgroundData {
         let info_c = self.get_info().expect("INFO is always there");
         let layer_width = info_c.layer_width;
 
-        if new_height % 2 != 0 {
+        if !new_height.is_multiple_of(2) {
             log_write(format!("Cannot make height odd (0x{:X})",new_height),LogLevel::Warn);
             return None;
         }
@@ -401,3 +566,112 @@ impl TopLevelSegment for BackgroundData {
         String::from("SCEN")
     }
 }
+
+#[cfg(test)]
+mod tests_backgrounddata {
+    use super::*;
+
+    #[test]
+    fn test_new_blank() {
+        let base_info = ScenInfoData {
+            layer_width: 0x40, layer_height: 0x20,
+            x_offset_px: 8, y_offset_px: -8,
+            which_bg: 2, layer_order: 1,
+            imbz_filename_noext: Some("tileset".to_string()),
+            ..Default::default()
+        };
+        let pltb = PltbData::from_pal_vec(vec![Palette::default(); 3]);
+        let bg = BackgroundData::new_blank(&base_info, pltb, 6, 4, true);
+        // Size is replaced and offsets reset, everything else comes from the base
+        let info = bg.get_info().expect("Blank layer has INFO");
+        assert_eq!((info.layer_width,info.layer_height),(6,4));
+        assert_eq!((info.x_offset_px,info.y_offset_px),(0,0));
+        assert_eq!((info.which_bg,info.layer_order),(2,1));
+        assert_eq!(info.imbz_filename_noext.as_deref(),Some("tileset"));
+        assert_eq!(bg.get_pltb().map(|pltb| pltb.palettes.len()),Some(3));
+        let mpbz = bg.get_mpbz().expect("Blank layer has MPBZ");
+        assert_eq!(mpbz.tiles.len(),6 * 4);
+        assert!(mpbz.tiles.iter().all(|tile| tile.to_short() == 0x0000));
+        // Collision tiles are 2x2
+        let colz = bg.get_colz().expect("Collision was asked for");
+        assert_eq!(colz.col_tiles,vec![0x00; 3 * 2]);
+        let no_colz = BackgroundData::new_blank(&base_info, PltbData::from_pal_vec(Vec::new()), 6, 4, false);
+        assert!(no_colz.get_colz().is_none());
+    }
+
+    #[test]
+    fn test_shift_palettes() {
+        let pltb = PltbData::from_pal_vec(vec![Palette::default(); 2]);
+        let mut bg = BackgroundData::new_blank(&ScenInfoData::default(), pltb, 4, 2, false);
+        {
+            let mpbz = bg.get_mpbz_mut().unwrap();
+            mpbz.tiles[0] = MapTileRecordData::new(0x0001);
+            mpbz.tiles[1] = MapTileRecordData::new(0xF002);
+            mpbz.tiles[2] = MapTileRecordData::new(0x1003);
+        }
+        // Index 3 is blank, index 9 is past the end
+        let (changed, over_rows) = bg.shift_palettes(&[0, 1, 2, 3, 9], 1);
+        assert_eq!(changed,2);
+        assert_eq!(over_rows,1);
+        let mpbz = bg.get_mpbz().unwrap();
+        assert_eq!(mpbz.tiles[0].palette_id,1);
+        assert_eq!(mpbz.tiles[1].palette_id,15);
+        assert_eq!(mpbz.tiles[2].palette_id,2);
+        assert_eq!(mpbz.tiles[3].to_short(),0);
+        let (changed, _) = bg.shift_palettes(&[0, 1, 2], -4);
+        assert_eq!(changed,3);
+        assert_eq!(bg.get_mpbz().unwrap().tiles[0].palette_id,0);
+    }
+
+    #[test]
+    fn test_error_tile_count() {
+        let pltb = PltbData::from_pal_vec(vec![Palette::default()]);
+        let info = ScenInfoData { color_mode: 0, ..Default::default() };
+        let mut bg = BackgroundData::new_blank(&info, pltb, 2, 2, false);
+        assert_eq!(bg.error_tile_count(),0);
+        // Two 16 color tiles
+        bg.pixel_tiles_preview = Some(vec![0x11; 64]);
+        {
+            let mpbz = bg.get_mpbz_mut().unwrap();
+            mpbz.tiles[1] = MapTileRecordData::new(0x0001);
+            mpbz.tiles[2] = MapTileRecordData::new(0x0002);
+            mpbz.tiles[3] = MapTileRecordData::new(0x03FF);
+        }
+        assert_eq!(bg.error_tile_count(),2);
+        bg.pixel_tiles_preview = Some(Vec::new());
+        assert_eq!(bg.error_tile_count(),4);
+    }
+
+    #[test]
+    fn test_reload_pixel_tiles_preview() {
+        let pltb = PltbData::from_pal_vec(vec![Palette::default()]);
+        let mut bg = BackgroundData::new_blank(&ScenInfoData::default(), pltb, 2, 2, false);
+        let no_project = std::env::temp_dir().join("stork_test_no_project");
+        assert!(!bg.reload_pixel_tiles_preview(&no_project));
+        bg.scen_segments.push(ScenSegmentWrapper::IMGB(ImgbData::new(vec![0x12; 32])));
+        bg.pixel_tiles_preview = Some(vec![0xFF; 32]);
+        assert!(bg.reload_pixel_tiles_preview(&no_project));
+        assert_eq!(bg.pixel_tiles_preview,Some(vec![0x12; 32]));
+    }
+
+    #[test]
+    fn test_unknown_scen_segment_round_trip() {
+        let pltb = PltbData::from_pal_vec(vec![Palette { _pal_len: 16, ..Default::default() }]);
+        let info = ScenInfoData { color_mode: 0, ..Default::default() };
+        let mut bg = BackgroundData::new_blank(&info, pltb, 2, 2, false);
+        // Odd length and a header byte past ASCII
+        let unknown = GenericScenSegment::new(vec![0xAB, 0xCD, 0xEF], String::from("\u{E9}XYZ"));
+        bg.scen_segments.insert(1, ScenSegmentWrapper::Unknown(unknown));
+        let compiled = bg.compile();
+        let no_project = std::env::temp_dir().join("stork_test_no_project");
+        let loaded = BackgroundData::new(&compiled, &no_project).expect("Unknown sub-segments load");
+        assert_eq!(loaded.scen_segments.len(),bg.scen_segments.len());
+        let Some(ScenSegmentWrapper::Unknown(loaded_unknown)) = loaded.scen_segments.get(1) else {
+            panic!("Unknown sub-segment was not kept in place");
+        };
+        assert_eq!(loaded_unknown.header,"\u{E9}XYZ");
+        // Padded to 4 bytes when saved, like every other segment
+        assert_eq!(loaded_unknown.raw_bytes,vec![0xAB, 0xCD, 0xEF, 0x00]);
+        assert_eq!(loaded.compile(),compiled);
+    }
+}
@@ -35,7 +35,7 @@ impl SoftRockBackdrop {
 
         let end_len = byte_data.len() as u64;
         while rdr.position() < end_len {
-            let tile_short = rdr.read_u16::<LittleEndian>().expect("BLKZ tile read");
+            let tile_short = utils::read_u16(&mut rdr)?;
             ret.tiles.push(MapTileRecordData::new(tile_short));
         }
         let calced_len = (ret.width as usize) * (ret.height as usize);
@@ -1,10 +1,10 @@
-use std::{fs, io::Cursor, path::{Path, PathBuf}};
+use std::{error::Error, fmt::Display, fs, io::{Cursor, Read}, path::{Path, PathBuf}};
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use uuid::Uuid;
 
-use crate::{engine::compression::segment_wrap, utils::{self, log_write, LogLevel}};
+use crate::{engine::compression::segment_wrap, utils::{self, log_write, new_uuid, LogLevel}};
 
-use super::Compilable;
+use super::{limits::{FormatLimitIssue, MAX_ENTRANCES, MAX_EXITS, MAX_EXIT_TARGET_INDEX}, Compilable};
 
 /// CRSB (Course Binary)
 #[derive(Clone,Debug)]
@@ -31,112 +31,128 @@ impl Compilable for CourseInfo {
             comp.append(&mut data_bytes);
         }
         // Pad to 4 bytes, all must be
-        while comp.len() % 4 != 0 {
+        while !comp.len().is_multiple_of(4) {
             comp.push(0x00);
         }
         comp
     }
 }
 impl CourseInfo {
-    pub fn new(abs_path: &PathBuf, label: String) -> Self {
+    pub fn new(abs_path: &PathBuf, label: String) -> Result<Self, CourseParseError> {
         // It is uncompressed
         let file_bytes = match fs::read(abs_path) {
             Err(error) => {
-                utils::log_write(format!("Failed to read Course file: '{}'", error), utils::LogLevel::Error);
-                return CourseInfo::default();
+                let read_err = CourseParseError::FileRead(error.to_string());
+                utils::log_write(&read_err, utils::LogLevel::Error);
+                return Err(read_err);
             }
             Ok(b) => b,
         };
-        let mut rdr: Cursor<&Vec<u8>> = Cursor::new(&file_bytes);
-        let file_header = match rdr.read_u32::<LittleEndian>() {
-            Err(error) => {
-                utils::log_write(format!("Failed to read file header: '{}'", error), utils::LogLevel::Error);
-                return CourseInfo::default();
-            }
-            Ok(h) => h,
-        };
+        let src_filename = abs_path.to_str().unwrap_or("UNWRAP FAILURE").to_owned();
+        CourseInfo::from_bytes(&file_bytes, src_filename, label)
+    }
+
+    /// Parse CRSB bytes, never panics on malformed or truncated data
+    pub fn from_bytes(file_bytes: &[u8], src_filename: String, label: String) -> Result<Self, CourseParseError> {
+        let mut rdr: Cursor<&[u8]> = Cursor::new(file_bytes);
+        let file_header = crsb_read_u32(&mut rdr, || "CRSB header".to_owned())?;
         if utils::header_to_string(&file_header) != "CRSB" {
             utils::log_write("Course data header was not CRSB", utils::LogLevel::Warn);
         }
         // Okay, checks are out of the way. Lets start reading!
-        let _crsb_internal_size = rdr.read_u32::<LittleEndian>().unwrap();
-        let cscn_count: u32 = rdr.read_u32::<LittleEndian>().unwrap();
+        let _crsb_internal_size = crsb_read_u32(&mut rdr, || "CRSB size".to_owned())?;
+        let cscn_count: u32 = crsb_read_u32(&mut rdr, || "CSCN count".to_owned())?;
         // Time for the CSCN loop
         let mut cscn_vec: Vec<CourseMapInfo> = Vec::new();
         let mut cscn_index: u32 = 0;
         while cscn_index < cscn_count {
             // Begin reading a CSCN segment
-            let cscn_header = rdr.read_u32::<LittleEndian>().unwrap();
+            let cscn_header = crsb_read_u32(&mut rdr, || format!("CSCN {cscn_index} header"))?;
             let cscn_header_string = utils::header_to_string(&cscn_header);
             if cscn_header_string != "CSCN" {
                 utils::log_write(format!("Wrong header, expected CSCN, got '{}'/0x{:08X}",cscn_header_string,&cscn_header), utils::LogLevel::Warn);
             }
-            let _cscn_internal_size: u32 = rdr.read_u32::<LittleEndian>().unwrap();
-            let cscn_entrance_count: u16 = rdr.read_u16::<LittleEndian>().unwrap();
+            let cscn_internal_size: u32 = crsb_read_u32(&mut rdr, || format!("CSCN {cscn_index} size"))?;
+            let cscn_end = rdr.position() + cscn_internal_size as u64;
+            let mut reserved = CscnReserved::default();
+            let cscn_entrance_count: u16 = crsb_read_u16(&mut rdr, || format!("CSCN {cscn_index} entrance count"))?;
             let mut cscn_entrance_vec: Vec<MapEntrance> = Vec::new();
-            let cscn_exit_count: u8 = rdr.read_u8().unwrap();
+            let cscn_exit_count: u8 = crsb_read_u8(&mut rdr, || format!("CSCN {cscn_index} exit count"))?;
             let mut cscn_exit_vec: Vec<MapExit> = Vec::new();
-            let cscn_music_id: u8 = rdr.read_u8().unwrap();
-            // 16 bytes are reserved for the file name, even if not filled
-            let index_after = rdr.position() + 16;
-            // Read the map file name
-            let mpdz_name_noext:String = utils::read_c_string(&mut rdr);
-            // Jumps ahead for padding (0x02033224 ?)
-            rdr.set_position(index_after);
+            let cscn_music_id: u8 = crsb_read_u8(&mut rdr, || format!("CSCN {cscn_index} music"))?;
+            // 16 bytes are reserved for the file name, even if not filled (0x02033224 ?)
+            let mut name_buffer: [u8; 16] = [0; 16];
+            let name_position = rdr.position();
+            if rdr.read_exact(&mut name_buffer).is_err() {
+                return Err(CourseParseError::UnexpectedEof(name_position, format!("CSCN {cscn_index} map file name")));
+            }
+            let name_len = name_buffer.iter().position(|b| *b == 0x00).unwrap_or(name_buffer.len());
+            let Ok(mpdz_name_noext) = String::from_utf8(name_buffer[..name_len].to_vec()) else {
+                return Err(CourseParseError::InvalidFileName(name_position, cscn_index));
+            };
+            // Whatever is after the terminator, usually zeros
+            if name_len < name_buffer.len() {
+                reserved.name_field[name_len + 1..].copy_from_slice(&name_buffer[name_len + 1..]);
+            }
             // First up: entrance loop
             let mut entrance_index: u16 = 0;
             while entrance_index < cscn_entrance_count {
-                let entrance_x: u16 = rdr.read_u16::<LittleEndian>().unwrap();
-                let entrance_y: u16 = rdr.read_u16::<LittleEndian>().unwrap();
-                let entrance_flags: u16 = rdr.read_u16::<LittleEndian>().unwrap();
+                let entrance_x: u16 = crsb_read_u16(&mut rdr, || format!("CSCN {cscn_index} entrance {entrance_index} x"))?;
+                let entrance_y: u16 = crsb_read_u16(&mut rdr, || format!("CSCN {cscn_index} entrance {entrance_index} y"))?;
+                let entrance_flags: u16 = crsb_read_u16(&mut rdr, || format!("CSCN {cscn_index} entrance {entrance_index} flags"))?;
                 let entrance: MapEntrance = MapEntrance {
                     entrance_x, entrance_y, entrance_flags,
                     label: format!("Entrance 0x{:X}",entrance_index),
-                    uuid: Uuid::new_v4()
+                    uuid: new_uuid()
                 };
                 cscn_entrance_vec.push(entrance); // Hand it over
                 entrance_index += 1;
             }
             // Since entrance data is only 6 bytes... Not divisible by 4!
-            while rdr.position() % 4 != 0 {
-                let _ = rdr.read_u8();
-            }
+            reserved.entrance_padding = crsb_align_4(&mut rdr, || format!("CSCN {cscn_index} entrance padding"))?;
             // Exit loop time
             let mut exit_index: u8 = 0;
             while exit_index < cscn_exit_count {
-                let exit_x: u16 = rdr.read_u16::<LittleEndian>().unwrap();
-                //println!("exit_x = {:04X}",exit_x);
-                let exit_y: u16 = rdr.read_u16::<LittleEndian>().unwrap();
-                let exit_type: u16 = rdr.read_u16::<LittleEndian>().unwrap();
-                let target_map_raw: u8 = rdr.read_u8().unwrap();
-                let target_map_entrance_raw: u8 = rdr.read_u8().unwrap();
-                //println!("target_map_entrance = {:02X}",&target_map_entrance);
+                let exit_x: u16 = crsb_read_u16(&mut rdr, || format!("CSCN {cscn_index} exit {exit_index} x"))?;
+                let exit_y: u16 = crsb_read_u16(&mut rdr, || format!("CSCN {cscn_index} exit {exit_index} y"))?;
+                let exit_type: u16 = crsb_read_u16(&mut rdr, || format!("CSCN {cscn_index} exit {exit_index} type"))?;
+                let target_map_raw: u8 = crsb_read_u8(&mut rdr, || format!("CSCN {cscn_index} exit {exit_index} target map"))?;
+                let target_map_entrance_raw: u8 = crsb_read_u8(&mut rdr, || format!("CSCN {cscn_index} exit {exit_index} target entrance"))?;
                 let exit: MapExit = MapExit {
                     exit_x, exit_y, exit_type, target_map_raw, target_map_entrance_raw,
-                    label: format!("Exit 0x{:X}",exit_index), uuid: Uuid::new_v4(),
+                    label: format!("Exit 0x{:X}",exit_index), uuid: new_uuid(),
                     target_map: Uuid::nil(), target_map_entrance: Uuid::nil()
                 };
                 cscn_exit_vec.push(exit);
                 exit_index += 1;
             }
+            // Anything left before the stated end isn't known, keep it as-is
+            let exits_end = rdr.position();
+            if cscn_end > exits_end && cscn_end <= file_bytes.len() as u64 {
+                reserved.trailing = file_bytes[exits_end as usize..cscn_end as usize].to_vec();
+                rdr.set_position(cscn_end);
+            } else if cscn_end != exits_end {
+                log_write(format!("CSCN {cscn_index} size says it ends at 0x{cscn_end:X}, but its data ends at 0x{exits_end:X}"), LogLevel::Warn);
+            }
             let cscn: CourseMapInfo = CourseMapInfo {
                 map_music: cscn_music_id,
                 label: format!("0x{:X}: {}",cscn_index,&mpdz_name_noext),
                 map_filename_noext: mpdz_name_noext,
                 map_entrances: cscn_entrance_vec,
                 map_exits: cscn_exit_vec,
-                uuid: Uuid::new_v4()
+                reserved,
+                uuid: new_uuid()
             };
             cscn_vec.push(cscn); // Move it in
             cscn_index += 1;
         } // CSCN Loop over
         let mut ret = CourseInfo {
             level_map_data: cscn_vec,
-            src_filename: abs_path.to_str().unwrap_or("UNWRAP FAILURE").to_owned(),
+            src_filename,
             label,
         };
         ret.update_exit_uuids();
-        ret
+        Ok(ret)
     }
 
     pub fn wrap(&mut self) -> Vec<u8> {
@@ -203,6 +219,32 @@ impl CourseInfo {
         }
     }
 
+    /// Anything compile would have to cut off, see limits
+    pub fn limit_issues(&self) -> Vec<FormatLimitIssue> {
+        let mut issues: Vec<FormatLimitIssue> = Vec::new();
+        for map in &self.level_map_data {
+            if map.map_entrances.len() > MAX_ENTRANCES {
+                issues.push(FormatLimitIssue::TooManyEntrances { map: map.map_filename_noext.clone(), count: map.map_entrances.len() });
+            }
+            if map.map_exits.len() > MAX_EXITS {
+                issues.push(FormatLimitIssue::TooManyExits { map: map.map_filename_noext.clone(), count: map.map_exits.len() });
+            }
+            for exit in &map.map_exits {
+                // Missing targets are reset to the first map by fix_exits
+                let Some(target_map) = self.level_map_data.iter().position(|m| m.uuid == exit.target_map) else {
+                    continue;
+                };
+                let target_entrance = self.level_map_data[target_map].map_entrances.iter()
+                    .position(|e| e.uuid == exit.target_map_entrance).unwrap_or(0);
+                if target_map > MAX_EXIT_TARGET_INDEX || target_entrance > MAX_EXIT_TARGET_INDEX {
+                    issues.push(FormatLimitIssue::ExitTargetOutOfRange { map: map.map_filename_noext.clone(),
+                        exit: exit.label.clone(), target_map, target_entrance });
+                }
+            }
+        }
+        issues
+    }
+
     pub fn fix_exits(&mut self) {
         // First, we fix from UUIDs
         // Those are what we use most, and what will be broken
@@ -257,36 +299,107 @@ impl CourseInfo {
             _ => {}
         };
         // The file path is valid
-        let mut four_num: u32 = 0;
-        loop {
-            four_num += 1;
-            let new_file_name = format!("{}{:04}.mpdz",&template_file[0..3],four_num);
-            let new_path = utils::nitrofs_abs(root_path.to_path_buf(), &new_file_name);
-            let Ok(new_path_exists) = fs::exists(&new_path) else {
-                log_write("New Template path existence check failed", LogLevel::Error);
-                continue;
+        let file_name_noext = match next_map_file_name(root_path, &template_file[0..3]) {
+            Ok(name) => name,
+            Err(error) => {
+                log_write(format!("New Template path existence check failed: '{error}'"), LogLevel::Error);
+                return;
+            }
+        };
+        let new_path = utils::nitrofs_abs(root_path.to_path_buf(), &format!("{}.mpdz",file_name_noext));
+        match fs::copy(&source_file_path, &new_path) {
+            Ok(_) => {
+                log_write(format!("Successfully copied '{}' to '{}'",source_file_path.display(),new_path.display()), LogLevel::Log);
+                // Now add the map to the data files
+                self.add_map_info(file_name_noext);
+            },
+            Err(e) => {
+                log_write(format!("Error in template file copy: '{}'",e), LogLevel::Error);
+            }
+        }
+    }
+
+    /// Add a CSCN entry for a map file that already exists
+    pub fn add_map_info(&mut self, file_name_noext: String) {
+        let new_course = CourseMapInfo::from_template(file_name_noext);
+        self.fix_exits(); // Make sure everything is synced up before we add
+        self.level_map_data.push(new_course);
+        self.update_exit_uuids(); // Then fix the UUIDs (raws will be okay)
+    }
+
+    /// Add a CSCN entry for a copy of a map from another Course
+    ///
+    /// Entrances and Exits are kept, with new UUIDs. Exits can't lead back
+    /// to the other Course, so fix_exits points them at the first map
+    pub fn add_map_copy(&mut self, source: &CourseMapInfo, file_name_noext: String) {
+        let mut copy = source.clone();
+        copy.map_filename_noext = file_name_noext.clone();
+        copy.label = file_name_noext;
+        copy.uuid = new_uuid();
+        for entrance in &mut copy.map_entrances {
+            entrance.uuid = new_uuid();
+        }
+        for exit in &mut copy.map_exits {
+            exit.uuid = new_uuid();
+        }
+        self.level_map_data.push(copy);
+        self.fix_exits();
+    }
+
+    /// See CourseMapInfo::find_overlaps, for every map
+    pub fn find_overlaps(&self, allow_differing_exit_types: bool) -> Vec<WarpOverlap> {
+        self.level_map_data.iter().enumerate()
+            .flat_map(|(map_index, map)| map.find_overlaps(map_index, allow_differing_exit_types))
+            .collect()
+    }
+
+    /// Every Exit in the Course that leads to this Entrance, with its map index
+    pub fn exits_targeting(&self, entrance_uuid: &Uuid) -> Vec<(usize, &MapExit)> {
+        self.level_map_data.iter().enumerate()
+            .flat_map(|(map_index, map)| map.map_exits.iter().map(move |exit| (map_index, exit)))
+            .filter(|(_, exit)| exit.target_map_entrance == *entrance_uuid)
+            .collect()
+    }
+
+    /// Where taking an Exit puts the player, as the map index and Entrance
+    pub fn follow_exit(&self, exit: &MapExit) -> Result<(usize, Uuid), FlowLinkError> {
+        let Some(map_index) = self.level_map_data.iter().position(|map| map.uuid == exit.target_map) else {
+            return Err(FlowLinkError::MissingMap);
+        };
+        if self.level_map_data[map_index].get_entrance(&exit.target_map_entrance).is_none() {
+            return Err(FlowLinkError::MissingEntrance);
+        }
+        Ok((map_index, exit.target_map_entrance))
+    }
+
+    /// Steps through the Course from the level start (Entrance 0 of the first map),
+    /// taking `chosen_exits` in order. Stops at a choice that isn't on the current
+    /// map, or at the first broken link
+    pub fn walk_flow(&self, chosen_exits: &[Uuid]) -> Vec<FlowStep> {
+        let Some(start) = self.level_map_data.first().and_then(|map| map.map_entrances.first()) else {
+            return Vec::new();
+        };
+        let mut steps = vec![FlowStep { map_index: 0, entrance: start.uuid, exit: None, link_error: None, revisit: false }];
+        for exit_uuid in chosen_exits {
+            let cur_map_index = steps.last().expect("Always a start step").map_index;
+            let Some(exit) = self.level_map_data[cur_map_index].map_exits.iter().find(|exit| exit.uuid == *exit_uuid) else {
+                break;
             };
-            if !new_path_exists {
-                // It's good! Copy it
-                let copy_res = fs::copy(&source_file_path, &new_path);
-                match copy_res {
-                    Ok(_) => {
-                        log_write(format!("Successfully copied '{}' to '{}'",source_file_path.display(),new_path.display()), LogLevel::Log);
-                        let file_name_noext = new_file_name.replace(".mpdz", "");
-                        // Now add the map to the data files
-                        let new_course = CourseMapInfo::from_template(file_name_noext);
-                        self.fix_exits(); // Make sure everything is synced up before we add
-                        self.level_map_data.push(new_course);
-                        self.update_exit_uuids(); // Then fix the UUIDs (raws will be okay)
-                        return;
-                    },
-                    Err(e) => {
-                        log_write(format!("Error in template file copy: '{}'",e), LogLevel::Error);
-                        return;
-                    }
+            let followed = self.follow_exit(exit);
+            let cur_step = steps.last_mut().expect("Always a start step");
+            cur_step.exit = Some(*exit_uuid);
+            match followed {
+                Ok((map_index, entrance)) => {
+                    let revisit = steps.iter().any(|step| step.map_index == map_index && step.entrance == entrance);
+                    steps.push(FlowStep { map_index, entrance, exit: None, link_error: None, revisit });
                 }
-            } // Otherwise, continue
+                Err(e) => {
+                    cur_step.link_error = Some(e);
+                    break;
+                }
+            }
         }
+        steps
     }
 
     pub fn delete_map_info_by_index(&mut self, index: usize) -> bool {
@@ -300,6 +413,113 @@ impl CourseInfo {
     }
 }
 
+fn crsb_read_u32(rdr: &mut Cursor<&[u8]>, what: impl Fn() -> String) -> Result<u32, CourseParseError> {
+    let position = rdr.position();
+    rdr.read_u32::<LittleEndian>().map_err(|_| CourseParseError::UnexpectedEof(position, what()))
+}
+
+fn crsb_read_u16(rdr: &mut Cursor<&[u8]>, what: impl Fn() -> String) -> Result<u16, CourseParseError> {
+    let position = rdr.position();
+    rdr.read_u16::<LittleEndian>().map_err(|_| CourseParseError::UnexpectedEof(position, what()))
+}
+
+fn crsb_read_u8(rdr: &mut Cursor<&[u8]>, what: impl Fn() -> String) -> Result<u8, CourseParseError> {
+    let position = rdr.position();
+    rdr.read_u8().map_err(|_| CourseParseError::UnexpectedEof(position, what()))
+}
+
+/// Skip forward to the next 4 byte boundary, without looping forever at EOF.
+/// Returns the skipped bytes, so they can be written back the same
+fn crsb_align_4(rdr: &mut Cursor<&[u8]>, what: impl Fn() -> String) -> Result<Vec<u8>, CourseParseError> {
+    let position = rdr.position();
+    let aligned = position.next_multiple_of(4);
+    if aligned > rdr.get_ref().len() as u64 {
+        return Err(CourseParseError::UnexpectedEof(position, what()));
+    }
+    let skipped = rdr.get_ref()[position as usize..aligned as usize].to_vec();
+    rdr.set_position(aligned);
+    Ok(skipped)
+}
+
+/// The first unused `<prefix>0001` style map name, without the extension
+pub fn next_map_file_name(export_folder: &Path, prefix: &str) -> std::io::Result<String> {
+    let mut four_num: u32 = 0;
+    loop {
+        four_num += 1;
+        let file_name_noext = format!("{}{:04}",prefix,four_num);
+        let new_path = utils::nitrofs_abs(export_folder.to_path_buf(), &format!("{}.mpdz",file_name_noext));
+        if !fs::exists(&new_path)? {
+            return Ok(file_name_noext);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CourseParseError {
+    FileRead(String),
+    /// Position in the file, then what was being read
+    UnexpectedEof(u64, String),
+    InvalidFileName(u64, u32),
+}
+impl Display for CourseParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FileRead(error) => f.write_fmt(format_args!("Failed to read Course file: '{error}'")),
+            Self::UnexpectedEof(position, what) => f.write_fmt(format_args!("Unexpected EOF at 0x{position:X} reading {what}")),
+            Self::InvalidFileName(position, cscn_index) => f.write_fmt(format_args!("Invalid map file name at 0x{position:X} in CSCN {cscn_index}")),
+        }
+    }
+}
+impl Error for CourseParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowLinkError {
+    MissingMap,
+    MissingEntrance
+}
+impl Display for FlowLinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingMap => f.write_str("Target map no longer exists"),
+            Self::MissingEntrance => f.write_str("Target Entrance no longer exists on that map"),
+        }
+    }
+}
+
+/// One map visited by CourseInfo::walk_flow
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlowStep {
+    pub map_index: usize,
+    /// Where the player arrives on this map
+    pub entrance: Uuid,
+    /// Which Exit is taken out of this map, None at the end of the walk
+    pub exit: Option<Uuid>,
+    /// Set if the taken Exit leads nowhere
+    pub link_error: Option<FlowLinkError>,
+    /// This Entrance was already reached earlier in the walk
+    pub revisit: bool
+}
+
+/// Length of the CSCN map file name field, including the terminator
+pub const CSCN_NAME_FIELD_LEN: usize = 16;
+
+/// CSCN bytes with no known use, kept so files edited by other tools
+/// come back out unchanged
+#[derive(Debug,Clone,PartialEq,Default)]
+pub struct CscnReserved {
+    /// The name field after the name's terminator, zero over the name itself
+    pub name_field: [u8;CSCN_NAME_FIELD_LEN],
+    /// Between the Entrances and the 4 byte aligned Exits
+    pub entrance_padding: Vec<u8>,
+    /// After the Exits, up to the CSCN's stated size
+    pub trailing: Vec<u8>
+}
+impl CscnReserved {
+    pub fn is_all_zero(&self) -> bool {
+        self.name_field.iter().chain(&self.entrance_padding).chain(&self.trailing).all(|b| *b == 0x00)
+    }
+}
+
 /// CSCN (Info about map relative to the Level)
 #[derive(Debug,Clone,PartialEq)]
 pub struct CourseMapInfo {
@@ -308,43 +528,83 @@ pub struct CourseMapInfo {
     pub map_music: u8,
     pub map_filename_noext: String,
     pub label: String,
+    pub reserved: CscnReserved,
     pub uuid: Uuid
 }
 impl Compilable for CourseMapInfo {
     fn compile(&self) -> Vec<u8> {
         let mut comp: Vec<u8> = vec![];
+        // Saving checks limit_issues first, so this is only a last resort
+        if self.map_entrances.len() > MAX_ENTRANCES || self.map_exits.len() > MAX_EXITS {
+            log_write(format!("Cutting off Entrances/Exits past the limit in '{}': {}/{}",
+                self.map_filename_noext,self.map_entrances.len(),self.map_exits.len()), LogLevel::Error);
+        }
+        let entrance_count = self.map_entrances.len().min(MAX_ENTRANCES);
+        let exit_count = self.map_exits.len().min(MAX_EXITS);
         // Entrance Count
-        let _ = comp.write_u16::<LittleEndian>(self.map_entrances.len() as u16);
+        let _ = comp.write_u16::<LittleEndian>(entrance_count as u16);
         // Exit Count
-        let _ = comp.write_u8(self.map_exits.len() as u8);
+        let _ = comp.write_u8(exit_count as u8);
         // Music ID
         let _ = comp.write_u8(self.map_music);
-        // MPDZ name
-        for b in self.map_filename_noext.bytes() { comp.push(b) }
-        comp.push(0x00); // Null terminator
-        // Why there are 8 bytes here, I know not
-        for _spacer in 0..8 {
-            comp.push(0x00);
+        // MPDZ name, in a fixed field with whatever was after the terminator
+        let mut name_field = self.reserved.name_field;
+        let name_bytes = self.map_filename_noext.as_bytes();
+        if name_bytes.len() >= CSCN_NAME_FIELD_LEN {
+            log_write(format!("Map file name '{}' is too long, cutting it to {} characters",
+                self.map_filename_noext,CSCN_NAME_FIELD_LEN - 1), LogLevel::Error);
         }
+        let name_len = name_bytes.len().min(CSCN_NAME_FIELD_LEN - 1);
+        name_field[..name_len].copy_from_slice(&name_bytes[..name_len]);
+        name_field[name_len] = 0x00; // Null terminator
+        comp.extend_from_slice(&name_field);
         // Now do the loops
-        for enter in &self.map_entrances {
+        for enter in &self.map_entrances[..entrance_count] {
             let mut entrance = enter.compile();
             comp.append(&mut entrance);
         }
-        while comp.len() % 4 != 0 {
+        // Original padding only fits if the Entrance count kept the same alignment
+        let padding_len = comp.len().next_multiple_of(4) - comp.len();
+        if self.reserved.entrance_padding.len() == padding_len {
+            comp.extend_from_slice(&self.reserved.entrance_padding);
+        }
+        while !comp.len().is_multiple_of(4) {
             comp.push(0x00);
         }
-        for exit in &self.map_exits {
+        for exit in &self.map_exits[..exit_count] {
             let mut exit_bytes = exit.compile();
             comp.append(&mut exit_bytes);
         }
-        while comp.len() % 4 != 0 {
+        comp.extend_from_slice(&self.reserved.trailing);
+        while !comp.len().is_multiple_of(4) {
             comp.push(0x00);
         }
         comp
     }
 }
 impl CourseMapInfo {
+    /// Moves Entrances and Exits by dx/dy tiles. They are clamped to the
+    /// edges instead of removed, since Exits elsewhere may target them
+    pub fn shift_warps(&mut self, dx: i32, dy: i32, width: u16, height: u16) -> usize {
+        let mut clamped: usize = 0;
+        let mut shift = |x: &mut u16, y: &mut u16| {
+            let new_x = *x as i32 + dx;
+            let new_y = *y as i32 + dy;
+            let fixed_x = new_x.clamp(0, (width as i32 - 1).max(0));
+            let fixed_y = new_y.clamp(0, (height as i32 - 1).max(0));
+            if fixed_x != new_x || fixed_y != new_y {
+                clamped += 1;
+            }
+            (*x, *y) = (fixed_x as u16, fixed_y as u16);
+        };
+        for entrance in &mut self.map_entrances {
+            shift(&mut entrance.entrance_x, &mut entrance.entrance_y);
+        }
+        for exit in &mut self.map_exits {
+            shift(&mut exit.exit_x, &mut exit.exit_y);
+        }
+        clamped
+    }
     fn wrap(&self) -> Vec<u8> {
         let uncomped_bytes: Vec<u8> = self.compile();
         // SCEN files are not compressed, though sub-segments are
@@ -368,7 +628,7 @@ impl CourseMapInfo {
         let new_ent = MapEntrance {
             entrance_x: 0, entrance_y: 0,
             entrance_flags: 0x8009, // TODO: Better default?
-            label, uuid: Uuid::new_v4()
+            label, uuid: new_uuid()
         };
         let ret_uuid = new_ent.uuid;
         self.map_entrances.push(new_ent);
@@ -385,7 +645,7 @@ impl CourseMapInfo {
             target_map_entrance_raw: 0xff,
             target_map_entrance: Uuid::nil(),
             label: format!("Exit 0x{:X}",new_index),
-            uuid: Uuid::new_v4()
+            uuid: new_uuid()
         };
         let ret_uuid = new_exit.uuid;
         self.map_exits.push(new_exit);
@@ -412,6 +672,42 @@ impl CourseMapInfo {
         }
     }
 
+    /// Pairs of Entrances and Exits whose 2x2 tile boxes share a tile
+    ///
+    /// Stacked Exits warp unpredictably in-game, but stacking ones of different
+    /// types can be intentional, so allow_differing_exit_types skips those
+    pub fn find_overlaps(&self, map_index: usize, allow_differing_exit_types: bool) -> Vec<WarpOverlap> {
+        let mut overlaps: Vec<WarpOverlap> = Vec::new();
+        for (i, first) in self.map_entrances.iter().enumerate() {
+            for second in &self.map_entrances[i+1..] {
+                if warp_boxes_overlap(first.entrance_x, first.entrance_y, second.entrance_x, second.entrance_y) {
+                    overlaps.push(WarpOverlap {
+                        map_index, kind: WarpKind::Entrance,
+                        first: first.uuid, second: second.uuid,
+                        label: format!("{} / {}",first.label,second.label),
+                        x: first.entrance_x, y: first.entrance_y
+                    });
+                }
+            }
+        }
+        for (i, first) in self.map_exits.iter().enumerate() {
+            for second in &self.map_exits[i+1..] {
+                if allow_differing_exit_types && first.exit_type != second.exit_type {
+                    continue;
+                }
+                if warp_boxes_overlap(first.exit_x, first.exit_y, second.exit_x, second.exit_y) {
+                    overlaps.push(WarpOverlap {
+                        map_index, kind: WarpKind::Exit,
+                        first: first.uuid, second: second.uuid,
+                        label: format!("{} / {}",first.label,second.label),
+                        x: first.exit_x, y: first.exit_y
+                    });
+                }
+            }
+        }
+        overlaps
+    }
+
     pub fn from_template(name_no_ext: String) -> Self {
         CourseMapInfo {
             map_entrances: vec![MapEntrance::default()],
@@ -419,11 +715,36 @@ impl CourseMapInfo {
             map_music: 0,
             map_filename_noext: name_no_ext.clone(),
             label: name_no_ext,
-            uuid: Uuid::new_v4()
+            reserved: CscnReserved::default(),
+            uuid: new_uuid()
         }
     }
 }
 
+/// Entrances and Exits are drawn and triggered as 2x2 tiles
+fn warp_boxes_overlap(a_x: u16, a_y: u16, b_x: u16, b_y: u16) -> bool {
+    a_x.abs_diff(b_x) < 2 && a_y.abs_diff(b_y) < 2
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum WarpKind {
+    Entrance,
+    Exit
+}
+
+/// Two Entrances or two Exits on the same map sharing tiles
+#[derive(Debug,Clone,PartialEq)]
+pub struct WarpOverlap {
+    pub map_index: usize,
+    pub kind: WarpKind,
+    pub first: Uuid,
+    pub second: Uuid,
+    pub label: String,
+    /// Tile position of the first one
+    pub x: u16,
+    pub y: u16
+}
+
 #[derive(Debug,Clone,PartialEq)]
 pub struct MapEntrance {
     pub entrance_x: u16,
@@ -438,7 +759,7 @@ impl Default for MapEntrance {
             entrance_x: 2, entrance_y: 2,
             entrance_flags: 0x8000, // 1-1
             label: format!("Entrance {:02X}",rand::random::<u8>()),
-            uuid: Uuid::new_v4()
+            uuid: new_uuid()
         }
     }
 }
@@ -477,7 +798,7 @@ impl Default for MapExit {
             exit_type: 0x5, // Blue Door
             target_map_raw: 0, target_map: Uuid::nil(),
             target_map_entrance_raw: 0, target_map_entrance: Uuid::nil(),
-            label: format!("Exit {:02X}",rand::random::<u8>()), uuid: Uuid::new_v4()
+            label: format!("Exit {:02X}",rand::random::<u8>()), uuid: new_uuid()
         }
     }
 }
@@ -550,3 +871,184 @@ pub fn exit_type_name(exit_type: u16) -> String {
 //     START_BOTTOM = 2,
 //     START_TOP_2 = 3
 // };
+
+#[cfg(test)]
+mod tests_course_file {
+    use super::*;
+
+    fn sample_course_bytes() -> Vec<u8> {
+        let mut map_info = CourseMapInfo::from_template("01k0001".to_owned());
+        map_info.map_music = 0x9;
+        map_info.map_entrances.push(MapEntrance::default());
+        let mut course = CourseInfo {
+            level_map_data: vec![map_info],
+            src_filename: "test.crsb".to_owned(),
+            label: "Test".to_owned()
+        };
+        course.fix_exits();
+        course.wrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = sample_course_bytes();
+        let course = CourseInfo::from_bytes(&bytes, "test.crsb".to_owned(), "Test".to_owned())
+            .expect("Sample course should parse");
+        assert_eq!(course.level_map_data.len(),1);
+        let map = &course.level_map_data[0];
+        assert_eq!(map.map_filename_noext,"01k0001");
+        assert_eq!(map.map_music,0x9);
+        assert_eq!(map.map_entrances.len(),2);
+        assert_eq!(map.map_exits.len(),1);
+    }
+
+    #[test]
+    fn test_reserved_round_trip() {
+        let mut course = CourseInfo { level_map_data: vec![CourseMapInfo::from_template("01k0001".to_owned())], ..Default::default() };
+        course.fix_exits();
+        let mut bytes = course.wrap();
+        // CRSB header, count, CSCN header, then counts and music
+        let cscn_start = 0xC;
+        let name_field = cscn_start + 0x8 + 0x4;
+        // After "01k0001" and its terminator
+        bytes[name_field + 0x8] = 0xAB;
+        bytes[name_field + 0xF] = 0xCD;
+        // One Entrance is 6 bytes, then 2 bytes of padding
+        let padding = name_field + 0x10 + 0x6;
+        bytes[padding + 1] = 0xEF;
+        let course = CourseInfo::from_bytes(&bytes, "test.crsb".to_owned(), "Test".to_owned())
+            .expect("Sample course should parse");
+        let map = &course.level_map_data[0];
+        assert_eq!(map.map_filename_noext,"01k0001");
+        assert_eq!((map.reserved.name_field[0x8],map.reserved.name_field[0xF]),(0xAB,0xCD));
+        assert_eq!(map.reserved.entrance_padding,vec![0x00, 0xEF]);
+        assert!(!map.reserved.is_all_zero());
+        let mut course = course;
+        assert_eq!(course.wrap(),bytes);
+    }
+
+    #[test]
+    fn test_trailing_round_trip() {
+        let mut map_info = CourseMapInfo::from_template("01k0001".to_owned());
+        map_info.reserved.trailing = vec![0x01, 0x02, 0x03, 0x04];
+        let mut course = CourseInfo { level_map_data: vec![map_info], ..Default::default() };
+        course.fix_exits();
+        let bytes = course.wrap();
+        let parsed = CourseInfo::from_bytes(&bytes, "test.crsb".to_owned(), "Test".to_owned())
+            .expect("Course with trailing data should parse");
+        assert_eq!(parsed.level_map_data[0].reserved.trailing,vec![0x01, 0x02, 0x03, 0x04]);
+        assert_eq!(parsed.level_map_data[0].map_exits.len(),1);
+    }
+
+    #[test]
+    fn test_walk_flow() {
+        // Templates come with one Entrance and one unlinked Exit
+        let mut first = CourseMapInfo::from_template("01k0001".to_owned());
+        let second = CourseMapInfo::from_template("01k0002".to_owned());
+        let start = first.map_entrances[0].uuid;
+        let target = second.map_entrances[0].uuid;
+        let mut exit = MapExit { target_map: second.uuid, target_map_entrance: target, ..Default::default() };
+        let exit_uuid = exit.uuid;
+        first.map_exits.push(exit.clone());
+        let mut course = CourseInfo { level_map_data: vec![first, second], ..Default::default() };
+        assert_eq!(course.exits_targeting(&target).len(),1);
+        assert!(course.exits_targeting(&start).is_empty());
+        let steps = course.walk_flow(&[exit_uuid]);
+        assert_eq!(steps.len(),2);
+        assert_eq!((steps[0].entrance,steps[0].exit),(start,Some(exit_uuid)));
+        assert_eq!((steps[1].map_index,steps[1].entrance),(1,target));
+        // Point it at a map that isn't in the Course
+        exit.target_map = Uuid::new_v4();
+        course.level_map_data[0].map_exits = vec![exit];
+        let steps = course.walk_flow(&[exit_uuid]);
+        assert_eq!(steps.len(),1);
+        assert_eq!(steps[0].link_error,Some(FlowLinkError::MissingMap));
+    }
+
+    #[test]
+    fn test_truncated_no_panic() {
+        let bytes = sample_course_bytes();
+        for len in 0..bytes.len() {
+            let res = CourseInfo::from_bytes(&bytes[..len], "test.crsb".to_owned(), "Test".to_owned());
+            // Only the final padding can be cut off and still be valid
+            if len < bytes.len() - 3 {
+                assert!(res.is_err(),"Truncated to 0x{:X} should fail",len);
+            }
+        }
+    }
+
+    #[test]
+    fn test_garbage_no_panic() {
+        // Simple LCG, so it's the same every run
+        let mut seed: u32 = 0x1234_5678;
+        let header = sample_course_bytes();
+        for _ in 0..500 {
+            let mut bytes = header[..12].to_vec();
+            for _ in 0..(seed % 0x80) {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                bytes.push((seed >> 24) as u8);
+            }
+            let _ = CourseInfo::from_bytes(&bytes, "test.crsb".to_owned(), "Test".to_owned());
+        }
+    }
+
+    #[test]
+    fn test_eof_message() {
+        let bytes = sample_course_bytes();
+        let err = CourseInfo::from_bytes(&bytes[..0x14], "test.crsb".to_owned(), "Test".to_owned())
+            .expect_err("Should fail inside the first CSCN");
+        assert!(matches!(err, CourseParseError::UnexpectedEof(_, _)));
+        assert!(err.to_string().contains("CSCN 0"));
+    }
+
+    #[test]
+    fn test_find_overlaps() {
+        let mut map_info = CourseMapInfo::from_template("01k0001".to_owned());
+        map_info.map_entrances[0].entrance_x = 4;
+        map_info.map_entrances[0].entrance_y = 4;
+        map_info.map_entrances.push(MapEntrance { entrance_x: 5, entrance_y: 5, ..Default::default() });
+        map_info.map_entrances.push(MapEntrance { entrance_x: 6, entrance_y: 4, ..Default::default() });
+        let overlaps = map_info.find_overlaps(0, false);
+        // Only the first two touch, 4 and 6 are side by side
+        assert_eq!(overlaps.len(),2);
+        assert!(overlaps.iter().all(|o| o.kind == WarpKind::Entrance));
+        let mut door = MapExit { exit_x: 0x20, exit_y: 0x20, exit_type: 0x5, ..Default::default() };
+        map_info.map_exits = vec![door.clone()];
+        door.uuid = Uuid::new_v4();
+        door.exit_type = 0x4;
+        map_info.map_exits.push(door);
+        assert_eq!(map_info.find_overlaps(0, false).iter().filter(|o| o.kind == WarpKind::Exit).count(),1);
+        assert_eq!(map_info.find_overlaps(0, true).iter().filter(|o| o.kind == WarpKind::Exit).count(),0);
+    }
+
+    #[test]
+    fn test_limit_issues() {
+        let mut map_info = CourseMapInfo::from_template("01k0001".to_owned());
+        map_info.map_entrances = vec![MapEntrance::default(); MAX_ENTRANCES];
+        map_info.map_exits = vec![MapExit::default(); MAX_EXITS];
+        let mut course = CourseInfo { level_map_data: vec![map_info], ..Default::default() };
+        assert!(course.limit_issues().is_empty());
+        course.level_map_data[0].map_entrances.push(MapEntrance::default());
+        course.level_map_data[0].map_exits.push(MapExit::default());
+        let issues = course.limit_issues();
+        assert_eq!(issues.len(),2);
+        assert!(matches!(issues[0],FormatLimitIssue::TooManyEntrances { count: 0x10000, .. }));
+        assert!(matches!(issues[1],FormatLimitIssue::TooManyExits { count: 0x100, .. }));
+        // Cut off at the limit instead of wrapping to 0
+        let compiled = course.level_map_data[0].compile();
+        assert_eq!(u16::from_le_bytes([compiled[0], compiled[1]]) as usize,MAX_ENTRANCES);
+        assert_eq!(compiled[2] as usize,MAX_EXITS);
+    }
+
+    #[test]
+    fn test_exit_target_limit() {
+        let mut maps: Vec<CourseMapInfo> = (0..=MAX_EXIT_TARGET_INDEX + 1).map(|_| CourseMapInfo::from_template("01k0001".to_owned())).collect();
+        let target = &maps[MAX_EXIT_TARGET_INDEX];
+        let exit = MapExit { target_map: target.uuid, target_map_entrance: target.map_entrances[0].uuid, ..Default::default() };
+        maps[0].map_exits = vec![exit.clone()];
+        let mut course = CourseInfo { level_map_data: maps, ..Default::default() };
+        assert!(course.limit_issues().is_empty());
+        course.level_map_data[0].map_exits[0].target_map = course.level_map_data[MAX_EXIT_TARGET_INDEX + 1].uuid;
+        assert!(matches!(course.limit_issues()[..],[FormatLimitIssue::ExitTargetOutOfRange { target_map: 0x100, target_entrance: 0, .. }]));
+    }
+}
@@ -0,0 +1,211 @@
+// Synthetic maps and Courses for tests, built from the same types the editor
+// saves so they never go stale against the format code
+//
+// Nothing here is in real ROM data, so there are no copyright concerns
+
+use std::{fs, ops::Deref, path::{Path, PathBuf}};
+
+use crate::utils::{new_uuid, nitrofs_abs};
+
+use super::{area::{Trigger, TriggerData}, backgrounddata::BackgroundData, course_file::{CourseInfo, CourseMapInfo, MapEntrance}, mapfile::{MapData, TopLevelSegmentWrapper}, path::{PathDatabase, PathLine, PathPoint}, scendata::{imgb::ImgbData, info::ScenInfoData, pltb::PltbData, ScenSegmentWrapper}, sprites::LevelSprite, types::{MapTileRecordData, Palette}, GenericTopLevelSegment};
+
+/// An empty folder under the temp dir, removed on drop so a failing assert
+/// doesn't leave it behind. The pid keeps parallel test runs apart
+pub struct TempFolder(PathBuf);
+impl TempFolder {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("stork_test_{name}_{}",std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).expect("Temp dir is writable");
+        Self(path)
+    }
+}
+impl Deref for TempFolder {
+    type Target = Path;
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+impl Drop for TempFolder {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+pub const SAMPLE_MAP_NAME: &str = "01k0001";
+pub const SAMPLE_MAP_2_NAME: &str = "01k0002";
+pub const SAMPLE_COURSE_NAME: &str = "1-1_D3";
+
+/// A 16 color BG2 with collision and an IMGB tileset, a 256 color BG1, three
+/// Sprites, one Path, two Triggers, and a segment the editor doesn't know
+pub fn sample_map(file_path: &Path) -> MapData {
+    let bg2_info = ScenInfoData { which_bg: 2, color_mode: 0, ..Default::default() };
+    let mut bg2 = BackgroundData::new_blank(&bg2_info, PltbData::from_pal_vec(vec![Palette { _pal_len: 16, ..Default::default() }; 2]), 8, 6, true);
+    {
+        let mpbz = bg2.get_mpbz_mut().expect("Blank layers have MPBZ");
+        mpbz.tiles[0] = MapTileRecordData::new(0x0001);
+        mpbz.tiles[9] = MapTileRecordData::new(0x1002);
+        mpbz.tiles[47] = MapTileRecordData::new(0x0C03);
+    }
+    if let Some(colz) = bg2.get_colz_mut() {
+        colz.col_tiles[0] = 0x01;
+        colz.col_tiles[11] = 0x1A;
+    }
+    // Four 16 color tiles
+    bg2.scen_segments.push(ScenSegmentWrapper::IMGB(ImgbData::new((0..128).map(|b| b as u8).collect())));
+    let bg1_info = ScenInfoData { which_bg: 1, color_mode: 1, ..Default::default() };
+    let bg1 = BackgroundData::new_blank(&bg1_info, PltbData::from_pal_vec(vec![Palette { _pal_len: 256, ..Default::default() }]), 4, 4, false);
+    let mut map = MapData::new_blank(file_path, vec![bg2, bg1]).expect("Sample layers are valid");
+    let setd = map.get_setd().expect("Blank maps have SETD");
+    // Settings are always a multiple of 4 long in the game's maps, since SETD gets padded
+    setd.sprites.push(LevelSprite::new(0x0001, 2, 3, Vec::new()));
+    setd.sprites.push(LevelSprite::new(0x00A5, 6, 4, vec![0x01, 0x00, 0x02, 0x00]));
+    setd.sprites.push(LevelSprite::new(0x0122, 7, 5, vec![0xFF, 0xEE, 0x10, 0x00]));
+    let mut path = PathDatabase { path_count: 1, lines: Vec::new() };
+    path.lines.push(PathLine { points: vec![
+        PathPoint::new(0x0400, 0x0010, 2 << 15, 2 << 15),
+        PathPoint::new(0x0000, 0x0000, 4 << 15, 2 << 15)
+    ], uuid: new_uuid() });
+    map.segments.push(TopLevelSegmentWrapper::PATH(path));
+    map.segments.push(TopLevelSegmentWrapper::AREA(TriggerData { triggers: vec![
+        Trigger::new(0, 0, 2, 2),
+        Trigger::new(4, 1, 8, 6)
+    ]}));
+    map.segments.push(TopLevelSegmentWrapper::Unknown(GenericTopLevelSegment::new(vec![0xDE, 0xAD, 0xBE, 0xEF], String::from("ZZZZ"))));
+    map
+}
+
+/// Two maps, with the first map's Exit leading to the second map's second Entrance
+pub fn sample_course() -> CourseInfo {
+    let mut first = CourseMapInfo::from_template(SAMPLE_MAP_NAME.to_owned());
+    first.map_music = 0x3;
+    first.map_exits[0].exit_x = 6;
+    first.map_exits[0].exit_y = 4;
+    let mut second = CourseMapInfo::from_template(SAMPLE_MAP_2_NAME.to_owned());
+    second.map_music = 0x9;
+    second.map_entrances.push(MapEntrance { entrance_x: 10, entrance_y: 12, ..Default::default() });
+    first.map_exits[0].target_map = second.uuid;
+    first.map_exits[0].target_map_entrance = second.map_entrances[1].uuid;
+    let mut course = CourseInfo {
+        level_map_data: vec![first, second],
+        src_filename: format!("{SAMPLE_COURSE_NAME}.crsb"),
+        label: SAMPLE_COURSE_NAME.to_owned()
+    };
+    course.fix_exits();
+    course
+}
+
+/// Writes the sample Course and its maps in the NitroFS layout, returning their paths
+pub fn write_sample_project(export_folder: &Path) -> (PathBuf, Vec<PathBuf>) {
+    let _ = fs::remove_dir_all(export_folder);
+    fs::create_dir_all(nitrofs_abs(export_folder.to_path_buf(), "")).expect("Temp dir is writable");
+    let mut map_paths: Vec<PathBuf> = Vec::new();
+    for name in [SAMPLE_MAP_NAME, SAMPLE_MAP_2_NAME] {
+        let map_path = nitrofs_abs(export_folder.to_path_buf(), &format!("{name}.mpdz"));
+        fs::write(&map_path, sample_map(&map_path).package()).expect("Temp dir is writable");
+        map_paths.push(map_path);
+    }
+    let crsb_path = nitrofs_abs(export_folder.to_path_buf(), &format!("{SAMPLE_COURSE_NAME}.crsb"));
+    fs::write(&crsb_path, sample_course().wrap()).expect("Temp dir is writable");
+    (crsb_path, map_paths)
+}
+
+#[cfg(test)]
+mod tests_fixtures {
+    use crate::{engine::compression::lamezip77_lz10_decomp, utils::seed_uuids};
+
+    use super::*;
+
+    #[test]
+    fn test_sample_map_load() {
+        let export_folder = TempFolder::new("fixtures_load");
+        let (_, map_paths) = write_sample_project(&export_folder);
+        let mut map = MapData::new(&map_paths[0], &export_folder).expect("Sample map loads");
+        let scen_count = map.segments.iter().filter(|seg| matches!(seg, TopLevelSegmentWrapper::SCEN(_))).count();
+        assert_eq!(scen_count,2);
+        assert_eq!(map.segments.len(),6);
+        assert_eq!(map.unhandled_headers,vec![String::from("ZZZZ")]);
+        assert_eq!(map.get_dimensions(),(8, 6));
+        assert_eq!(map.get_bg_with_colz(),Some(2));
+        {
+            let bg2 = map.get_background(2).expect("BG2 loaded");
+            assert_eq!(bg2.get_pltb().expect("PLTB loaded").palettes.len(),2);
+            assert_eq!(bg2.pixel_tiles_preview.as_ref().map(|p| p.len()),Some(128));
+            assert_eq!(bg2.error_tile_count(),0);
+            let mpbz = bg2.get_mpbz().expect("MPBZ loaded");
+            assert_eq!(mpbz.tiles.len(),48);
+            assert_eq!(mpbz.tiles[9].to_short(),0x1002);
+            assert_eq!(mpbz.tiles[47].to_short(),0x0C03);
+            let colz = bg2.get_colz().expect("COLZ loaded");
+            assert_eq!((colz.col_tiles[0],colz.col_tiles[11]),(0x01, 0x1A));
+        }
+        assert!(map.get_background(1).expect("BG1 loaded").get_info().expect("INFO loaded").is_256_colorpal_mode());
+        let sprites = &map.get_setd().expect("SETD loaded").sprites;
+        assert_eq!(sprites.len(),3);
+        assert_eq!((sprites[1].object_id,sprites[1].x_position,sprites[1].y_position),(0x00A5, 6, 4));
+        assert_eq!(sprites[2].settings,vec![0xFF, 0xEE, 0x10, 0x00]);
+        let path = map.get_path().expect("PATH loaded");
+        assert_eq!(path.lines.len(),1);
+        assert_eq!(path.lines[0].points.len(),2);
+        assert_eq!(map.get_area().expect("AREA loaded").triggers.len(),2);
+    }
+
+    #[test]
+    fn test_sample_map_round_trip() {
+        let export_folder = TempFolder::new("fixtures_map_round_trip");
+        let (_, map_paths) = write_sample_project(&export_folder);
+        let loaded = MapData::new(&map_paths[0], &export_folder).expect("Sample map loads");
+        // Loading changes nothing that gets saved
+        assert_eq!(loaded.compile(),sample_map(&map_paths[0]).compile());
+        let packaged = loaded.package();
+        let reparsed = MapData::from_bytes(&lamezip77_lz10_decomp(&packaged), String::new(), &export_folder).expect("Packaged map parses");
+        assert_eq!(reparsed.compile(),loaded.compile());
+        // A second save is byte for byte the same as the first
+        assert_eq!(reparsed.package(),packaged);
+    }
+
+    #[test]
+    fn test_loads_are_deterministic() {
+        let export_folder = TempFolder::new("fixtures_deterministic");
+        let (crsb_path, map_paths) = write_sample_project(&export_folder);
+        seed_uuids(1);
+        let first_map = MapData::new(&map_paths[0], &export_folder).expect("Sample map loads");
+        let first_course = CourseInfo::new(&crsb_path, SAMPLE_COURSE_NAME.to_owned()).expect("Sample Course loads");
+        seed_uuids(1);
+        let second_map = MapData::new(&map_paths[0], &export_folder).expect("Sample map loads");
+        let second_course = CourseInfo::new(&crsb_path, SAMPLE_COURSE_NAME.to_owned()).expect("Sample Course loads");
+        // UUIDs included, so whole maps can be compared after a save and load
+        assert!(first_map == second_map);
+        assert_eq!(first_course.level_map_data,second_course.level_map_data);
+        let resaved = export_folder.join("resaved.mpdz");
+        fs::write(&resaved, first_map.package()).expect("Temp dir is writable");
+        seed_uuids(1);
+        let mut reloaded = MapData::new(&resaved, &export_folder).expect("Resaved map loads");
+        reloaded.src_file = first_map.src_file.clone();
+        assert!(reloaded == first_map);
+    }
+
+    #[test]
+    fn test_sample_course_round_trip() {
+        let export_folder = TempFolder::new("fixtures_course_round_trip");
+        let (crsb_path, _) = write_sample_project(&export_folder);
+        let mut course = CourseInfo::new(&crsb_path, SAMPLE_COURSE_NAME.to_owned()).expect("Sample Course loads");
+        assert_eq!(course.level_map_data.len(),2);
+        let (first, second) = (&course.level_map_data[0], &course.level_map_data[1]);
+        assert_eq!((first.map_filename_noext.as_str(),first.map_music),(SAMPLE_MAP_NAME, 0x3));
+        assert_eq!((second.map_filename_noext.as_str(),second.map_music),(SAMPLE_MAP_2_NAME, 0x9));
+        assert_eq!(second.map_entrances.len(),2);
+        assert_eq!((second.map_entrances[1].entrance_x,second.map_entrances[1].entrance_y),(10, 12));
+        let exit = &first.map_exits[0];
+        assert_eq!((exit.exit_x,exit.exit_y),(6, 4));
+        assert_eq!((exit.target_map_raw,exit.target_map_entrance_raw),(1, 1));
+        // The raw targets are turned back into UUIDs on load
+        assert_eq!(exit.target_map,second.uuid);
+        assert_eq!(exit.target_map_entrance,second.map_entrances[1].uuid);
+        let saved = fs::read(&crsb_path).expect("Course was written");
+        assert_eq!(course.wrap(),saved);
+        let reparsed = CourseInfo::from_bytes(&saved, String::new(), String::new()).expect("Saved Course parses");
+        assert_eq!(reparsed.level_map_data.len(),2);
+        assert_eq!(reparsed.level_map_data[0].map_exits[0].target_map_entrance_raw,1);
+    }
+}
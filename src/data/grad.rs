@@ -1,6 +1,7 @@
 use std::io::{Cursor, Write};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use egui::Color32;
 
 use crate::{engine::compression::segment_wrap, utils::{self, log_write, read_fixed_string_cursor, LogLevel}};
 
@@ -56,6 +57,11 @@ impl TopLevelSegment for GradientData {
     }
 }
 impl GradientData {
+    /// Top to bottom
+    pub fn get_colors(&self) -> Vec<Color32> {
+        self.color_shorts.iter().map(utils::color_from_u16).collect()
+    }
+
     pub fn new(bytedata: &[u8]) -> Option<Self> {
         let mut ret = GradientData::default();
         let mut rdr = Cursor::new(bytedata);
@@ -65,25 +71,25 @@ impl GradientData {
             log_write(format!("Did not find GINF header, instead got '{}'",ginf_header), LogLevel::Error);
             return None;
         }
-        let ginf_size = rdr.read_u32::<LittleEndian>().unwrap();
+        let ginf_size = utils::read_u32(&mut rdr)?;
         if ginf_size != 0xc {
             log_write(format!("GINF was not 0xC bytes, was instead {:X}",ginf_size), LogLevel::Error);
             return None;
         }
-        ret.color_count = utils::read_u16(&mut rdr)?; //rdr.read_u16::<LittleEndian>().unwrap();
-        ret.unknown1 = utils::read_i16(&mut rdr)?;//rdr.read_i16::<LittleEndian>().unwrap();
+        ret.color_count = utils::read_u16(&mut rdr)?;
+        ret.unknown1 = utils::read_i16(&mut rdr)?;
         ret.unknown2 = utils::read_u16(&mut rdr)?;
         ret._padding = utils::read_u16(&mut rdr)?; // Just in case it's something else
         if ret._padding != 0x0000 {
             log_write(format!("GINF padding was not padding after all! Value was '{:X}', tell creator this",ret._padding), LogLevel::Warn);
         }
-        ret.y_offset = rdr.read_u32::<LittleEndian>().unwrap();
+        ret.y_offset = utils::read_u32(&mut rdr)?;
         let gcol_header: String = read_fixed_string_cursor(&mut rdr, 4);
         if gcol_header != "GCOL" {
             log_write(format!("Did not find GCOL header, instead got '{}'",gcol_header), LogLevel::Error);
             return None;
         }
-        let gcol_size: u32 = rdr.read_u32::<LittleEndian>().unwrap();
+        let gcol_size: u32 = utils::read_u32(&mut rdr)?;
         if gcol_size / 2 != ret.color_count as u32 {
             log_write(format!("Mismatch in GCOL size / 2 vs color_count: {:X} vs {:X}",gcol_size/2,ret.color_count), LogLevel::Error);
         }
@@ -0,0 +1,42 @@
+// Caps on counts and lengths the file formats can store
+//
+// Compiling past these used to wrap silently, giving a corrupted CSCN or SETD.
+// Compile now cuts off at the cap instead, and saving refuses until they're fixed
+
+use std::fmt::Display;
+
+/// Entrance count is a u16 in CSCN
+pub const MAX_ENTRANCES: usize = u16::MAX as usize;
+/// Exit count is a u8 in CSCN
+pub const MAX_EXITS: usize = u8::MAX as usize;
+/// Exit targets store the map and entrance index as u8s
+pub const MAX_EXIT_TARGET_INDEX: usize = u8::MAX as usize;
+/// Sprite settings length is a u16 in SETD
+pub const MAX_SPRITE_SETTINGS: usize = u16::MAX as usize;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatLimitIssue {
+    TooManyEntrances { map: String, count: usize },
+    TooManyExits { map: String, count: usize },
+    /// The target map or its entrance is past index 255
+    ExitTargetOutOfRange { map: String, exit: String, target_map: usize, target_entrance: usize },
+    SpriteSettingsTooLong { object_id: u16, x: u16, y: u16, len: usize },
+    /// settings_length is what's written, so the settings after it would be misread
+    SpriteSettingsLengthMismatch { object_id: u16, x: u16, y: u16, declared: u16, actual: usize }
+}
+impl Display for FormatLimitIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooManyEntrances { map, count } =>
+                f.write_fmt(format_args!("{map} has {count} Entrances, the most a map can have is {MAX_ENTRANCES}")),
+            Self::TooManyExits { map, count } =>
+                f.write_fmt(format_args!("{map} has {count} Exits, the most a map can have is {MAX_EXITS}")),
+            Self::ExitTargetOutOfRange { map, exit, target_map, target_entrance } =>
+                f.write_fmt(format_args!("{map} - {exit} targets map {target_map} entrance {target_entrance}, both must be {MAX_EXIT_TARGET_INDEX} or lower")),
+            Self::SpriteSettingsTooLong { object_id, x, y, len } =>
+                f.write_fmt(format_args!("Sprite 0x{object_id:X} at {x}/{y} has {len} bytes of settings, the most is {MAX_SPRITE_SETTINGS}")),
+            Self::SpriteSettingsLengthMismatch { object_id, x, y, declared, actual } =>
+                f.write_fmt(format_args!("Sprite 0x{object_id:X} at {x}/{y} says it has {declared} bytes of settings, but has {actual}"))
+        }
+    }
+}
@@ -6,16 +6,17 @@
 // It is not read from constantly by the graphics engine,
 // rather it is copied on demand for performance
 
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::fmt::Display;
 use std::fs;
-use std::io::Cursor;
+use std::io::{Cursor, Read};
 use std::path::{Path, PathBuf};
 use byteorder::{LittleEndian, ReadBytesExt};
 use uuid::Uuid;
 use crate::engine::compression::{lamezip77_lz10_recomp, segment_wrap_u32};
-use crate::load::SPRITE_METADATA;
-use crate::utils::{header_to_string, log_write};
+use crate::load::sprite_meta;
+use crate::utils::{get_x_pos_of_map_index, get_y_pos_of_map_index, header_to_string, log_write, new_uuid, xy_to_index};
 use crate::{engine::compression, utils::{self, LogLevel}};
 
 use super::alph::AlphaData;
@@ -24,6 +25,7 @@ use super::backgrounddata::BackgroundData;
 use super::blkz::SoftRockBackdrop;
 use super::brak::BrakData;
 use super::grad::GradientData;
+use super::limits::FormatLimitIssue;
 use super::path::PathDatabase;
 use super::segments::DataSegment;
 use super::sprites::{LevelSprite, LevelSpriteSet};
@@ -103,24 +105,29 @@ impl Default for MapData {
             src_file: String::from("ERROR"),
             map_name: String::from("ERROR"),
             segments: Vec::new(),
-            uuid: Uuid::new_v4(),
+            uuid: new_uuid(),
             unhandled_headers: Vec::new()
         }
     }
 }
 impl MapData {
     pub fn new(filename_abs: &PathBuf, project_folder: &Path) -> Result<Self, MapDataError> {
-        let mut ret: MapData = MapData {
-            src_file: filename_abs.to_string_lossy().to_string(),
-            ..Default::default()
-        };
         if matches!(fs::exists(filename_abs), Err(_) | Ok(false)) {
             let file_exists_err = MapDataError::FileNotExist(filename_abs.display().to_string());
             log_write(&file_exists_err, LogLevel::Error);
             return Err(file_exists_err);
         }
         let file_bytes: Vec<u8> = compression::decompress_file(filename_abs);
-        let mut rdr = Cursor::new(&file_bytes);
+        MapData::from_bytes(&file_bytes, filename_abs.to_string_lossy().to_string(), project_folder)
+    }
+
+    /// Parse already decompressed MPDZ bytes, starting with the SET header
+    pub fn from_bytes(file_bytes: &[u8], src_file: String, project_folder: &Path) -> Result<Self, MapDataError> {
+        let mut ret: MapData = MapData {
+            src_file,
+            ..Default::default()
+        };
+        let mut rdr = Cursor::new(file_bytes);
         let file_header = match rdr.read_u32::<LittleEndian>() {
             Err(_) => {
                 let master_header_err = MapDataError::MasterHeaderNotFound;
@@ -138,13 +145,21 @@ impl MapData {
             log_write(&set_missing_msg, LogLevel::Error);
             return Err(set_missing_msg);
         }
-        let _ = rdr.read_u32::<LittleEndian>().unwrap();
+        if rdr.read_u32::<LittleEndian>().is_err() {
+            return Err(bad_segment("SET", "Data ended before its size"));
+        }
         let mut segments: Vec<DataSegment> = vec![];
         let file_end_pos: u64 = file_bytes.len() as u64;
         while rdr.position() < file_end_pos {
-            let section_head: u32 = rdr.read_u32::<LittleEndian>().unwrap();
-            let section_size: usize = rdr.read_u32::<LittleEndian>().unwrap() as usize;
-            let internal_vec = (0..section_size).map(|_| rdr.read_u8().unwrap()).collect();
+            let (Ok(section_head), Ok(section_size)) = (rdr.read_u32::<LittleEndian>(), rdr.read_u32::<LittleEndian>()) else {
+                return Err(bad_segment("????", "Data ended inside a segment header"));
+            };
+            let section_header = header_to_string(&section_head);
+            if rdr.position() + section_size as u64 > file_end_pos {
+                return Err(bad_segment(&section_header, format!("Size 0x{:X} is past the end of the file",section_size)));
+            }
+            let mut internal_vec: Vec<u8> = vec![0; section_size as usize];
+            rdr.read_exact(&mut internal_vec).map_err(|e| bad_segment(&section_header, e))?;
             let cur_segment: DataSegment = DataSegment { header: section_head, internal_data: internal_vec };
             segments.push(cur_segment);
         }
@@ -153,76 +168,162 @@ impl MapData {
             let seg_header: u32 = segment.header;
             let seg_header = utils::header_to_string(&seg_header);
             log_write(format!("Parsing top level Segment '{}' with size 0x{:X}",seg_header,segment.internal_data.len()), LogLevel::Debug);
-            match seg_header.as_str() {
-                "SCEN" => {
-                    if let Ok(bg) = BackgroundData::new(&segment.internal_data, project_folder) {
-                        ret.segments.push(TopLevelSegmentWrapper::SCEN(bg));
-                    } else {
-                        let bg_fail_msg = MapDataError::FailedGenerateBackground;
-                        log_write(&bg_fail_msg, LogLevel::Error);
-                        return Err(bg_fail_msg);
-                    }
-                }
-                "SETD" => {
-                    let setd = LevelSpriteSet::new(&segment.internal_data);
-                    let scount = setd.sprites.len();
-                    log_write(format!("Loaded {}/0x{:X} Sprites for the level",scount,scount), LogLevel::Debug);
-                    ret.segments.push(TopLevelSegmentWrapper::SETD(setd));
-                }
-                "GRAD" => {
-                    let grad = match GradientData::new(&segment.internal_data) {
-                        Some(g) => g,
-                        None => {
-                            log_write("Failed to load GRAD", LogLevel::Error);
-                            continue;
-                        },
-                    };
-                    ret.segments.push(TopLevelSegmentWrapper::GRAD(grad));
-                }
-                "AREA" => {
-                    let area = TriggerData::new(&segment.internal_data);
-                    ret.segments.push(TopLevelSegmentWrapper::AREA(area));
-                }
-                "PATH" => {
-                    let path = PathDatabase::new(&segment.internal_data);
-                    ret.segments.push(TopLevelSegmentWrapper::PATH(path));
-                }
-                "ALPH" => {
-                    let alph = match AlphaData::new(&segment.internal_data) {
-                        Some(a) => a,
-                        None => {
-                            log_write("Failed to load ALPH", LogLevel::Error);
-                            continue;
-                        },
-                    };
-                    ret.segments.push(TopLevelSegmentWrapper::ALPH(alph));
-                }
-                "BLKZ" => {
-                    let blkz = match SoftRockBackdrop::new(&segment.internal_data) {
-                        Some(b) => b,
-                        None => {
-                            log_write("failed to load BLKZ", LogLevel::Error);
-                            continue;
-                        },
-                    };
-                    ret.segments.push(TopLevelSegmentWrapper::BLKZ(blkz));
-                }
-                "BRAK" => {
-                    let brak = BrakData::new(segment.internal_data.clone());
-                    ret.segments.push(TopLevelSegmentWrapper::BRAK(brak));
-                }
-                _ => {
-                    log_write(format!("Level DataSegment header '{}' unhandled",&seg_header), LogLevel::Warn);
-                    let unkn = GenericTopLevelSegment::new(segment.internal_data.clone(), seg_header.clone());
-                    ret.unhandled_headers.push(seg_header);
-                    ret.segments.push(TopLevelSegmentWrapper::Unknown(unkn));
-                }
+            let Some(parsed) = MapData::parse_segment(&seg_header, &segment.internal_data, project_folder)? else {
+                continue;
+            };
+            if let TopLevelSegmentWrapper::Unknown(_) = parsed {
+                ret.unhandled_headers.push(seg_header);
             }
+            ret.segments.push(parsed);
         } // End loop for segments
 
         Ok(ret)
     }
 
+    /// Parse the internal data of one top level segment, None if it should be skipped
+    pub fn parse_segment(seg_header: &str, internal_data: &[u8], project_folder: &Path) -> Result<Option<TopLevelSegmentWrapper>, MapDataError> {
+        let ret = match seg_header {
+            "SCEN" => {
+                let bg = BackgroundData::new(internal_data, project_folder).map_err(|e| bad_segment(seg_header, e))?;
+                TopLevelSegmentWrapper::SCEN(bg)
+            }
+            "SETD" => {
+                let setd = LevelSpriteSet::new(internal_data).map_err(|e| bad_segment(seg_header, e))?;
+                let scount = setd.sprites.len();
+                log_write(format!("Loaded {}/0x{:X} Sprites for the level",scount,scount), LogLevel::Debug);
+                TopLevelSegmentWrapper::SETD(setd)
+            }
+            "GRAD" => {
+                let grad = match GradientData::new(internal_data) {
+                    Some(g) => g,
+                    None => {
+                        log_write("Failed to load GRAD", LogLevel::Error);
+                        return Ok(Option::None);
+                    },
+                };
+                TopLevelSegmentWrapper::GRAD(grad)
+            }
+            "AREA" => {
+                let area = TriggerData::new(internal_data).map_err(|e| bad_segment(seg_header, e))?;
+                TopLevelSegmentWrapper::AREA(area)
+            }
+            "PATH" => {
+                let path = PathDatabase::new(internal_data).map_err(|e| bad_segment(seg_header, e))?;
+                TopLevelSegmentWrapper::PATH(path)
+            }
+            "ALPH" => {
+                let alph = match AlphaData::new(internal_data) {
+                    Some(a) => a,
+                    None => {
+                        log_write("Failed to load ALPH", LogLevel::Error);
+                        return Ok(Option::None);
+                    },
+                };
+                TopLevelSegmentWrapper::ALPH(alph)
+            }
+            "BLKZ" => {
+                let blkz = match SoftRockBackdrop::new(internal_data) {
+                    Some(b) => b,
+                    None => {
+                        log_write("failed to load BLKZ", LogLevel::Error);
+                        return Ok(Option::None);
+                    },
+                };
+                TopLevelSegmentWrapper::BLKZ(blkz)
+            }
+            "BRAK" => {
+                let brak = BrakData::new(internal_data.to_vec());
+                TopLevelSegmentWrapper::BRAK(brak)
+            }
+            _ => {
+                log_write(format!("Level DataSegment header '{}' unhandled",seg_header), LogLevel::Warn);
+                let unkn = GenericTopLevelSegment::new(internal_data.to_vec(), seg_header.to_owned());
+                TopLevelSegmentWrapper::Unknown(unkn)
+            }
+        };
+        Ok(Some(ret))
+    }
+
+    /// Build a new map from already created layers, with an empty SETD
+    /// 
+    /// Nothing is written to disk here, see `package` for that
+    pub fn new_blank(filename_abs: &Path, layers: Vec<BackgroundData>) -> Result<Self, MapDataError> {
+        let mut ret = MapData {
+            src_file: filename_abs.to_string_lossy().to_string(),
+            map_name: filename_abs.file_stem().unwrap_or_default().to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        for layer in layers {
+            ret.segments.push(TopLevelSegmentWrapper::SCEN(layer));
+        }
+        ret.segments.push(TopLevelSegmentWrapper::SETD(LevelSpriteSet::default()));
+        if let Err(e) = ret.validate_minimal() {
+            log_write(format!("Blank map failed validation: '{e}'"), LogLevel::Error);
+            return Err(e);
+        }
+        Ok(ret)
+    }
+
+    /// Checks that the segments the game can't do without are all present
+    pub fn validate_minimal(&self) -> Result<(), MapDataError> {
+        let mut scen_count: usize = 0;
+        let mut has_colz = false;
+        for seg in &self.segments {
+            let TopLevelSegmentWrapper::SCEN(scen) = seg else {
+                continue;
+            };
+            scen_count += 1;
+            let Some(info) = scen.get_info() else {
+                return Err(MapDataError::MissingSegment("INFO"));
+            };
+            if scen.get_pltb().is_none() {
+                return Err(MapDataError::MissingLayerSegment(info.which_bg, "PLTB"));
+            }
+            if scen.get_mpbz().is_none() {
+                return Err(MapDataError::MissingLayerSegment(info.which_bg, "MPBZ"));
+            }
+            if scen.get_colz().is_some() {
+                has_colz = true;
+            }
+        }
+        if scen_count == 0 {
+            return Err(MapDataError::MissingSegment("SCEN"));
+        }
+        if !has_colz {
+            return Err(MapDataError::MissingSegment("COLZ"));
+        }
+        if !self.segments.iter().any(|seg| matches!(seg, TopLevelSegmentWrapper::SETD(_))) {
+            return Err(MapDataError::MissingSegment("SETD"));
+        }
+        Ok(())
+    }
+
+    /// Rough heap size in bytes, for limiting undo memory
+    pub fn estimate_memory_size(&self) -> usize {
+        let segments_size: usize = self.segments.iter().map(|seg| match seg {
+            TopLevelSegmentWrapper::SCEN(scen) => scen.estimate_memory_size(),
+            TopLevelSegmentWrapper::SETD(setd) => setd.sprites.iter()
+                .map(|spr| size_of::<LevelSprite>() + spr.settings.len()).sum(),
+            // Small enough that the compiled size is close enough
+            other => other.compile().len(),
+        }).sum();
+        size_of::<Self>() + segments_size
+    }
+
+    /// Width and height in tiles of the largest BG layer
+    pub fn get_dimensions(&self) -> (u16, u16) {
+        let mut dimensions: (u16, u16) = (0, 0);
+        for seg in &self.segments {
+            if let TopLevelSegmentWrapper::SCEN(scen) = seg {
+                if let Some(info) = scen.get_info() {
+                    dimensions.0 = dimensions.0.max(info.layer_width);
+                    dimensions.1 = dimensions.1.max(info.layer_height);
+                }
+            }
+        }
+        dimensions
+    }
+
     pub fn get_background(&mut self, which_background: u8) -> Option<&mut BackgroundData> {
         for seg in &mut self.segments {
             if let TopLevelSegmentWrapper::SCEN(scen) = seg {
@@ -288,6 +389,87 @@ impl MapData {
         Option::None
     }
 
+    /// Layers whose MPBZ doesn't match their INFO size, see BackgroundData::tile_length_mismatch
+    pub fn tile_length_issues(&self) -> Vec<TileLengthIssue> {
+        let mut issues: Vec<TileLengthIssue> = Vec::new();
+        for seg in &self.segments {
+            if let TopLevelSegmentWrapper::SCEN(scen) = seg {
+                if let (Some(info), Some((actual, expected))) = (scen.get_info(), scen.tile_length_mismatch()) {
+                    issues.push(TileLengthIssue { which_bg: info.which_bg, actual, expected });
+                }
+            }
+        }
+        issues
+    }
+
+    /// Layers with tiles past the end of their tileset, see BackgroundData::error_tile_count
+    pub fn error_tile_issues(&self) -> Vec<ErrorTileIssue> {
+        let mut issues: Vec<ErrorTileIssue> = Vec::new();
+        for seg in &self.segments {
+            if let TopLevelSegmentWrapper::SCEN(scen) = seg {
+                let count = scen.error_tile_count();
+                if let (Some(info), true) = (scen.get_info(), count > 0) {
+                    issues.push(ErrorTileIssue { which_bg: info.which_bg, count });
+                }
+            }
+        }
+        issues
+    }
+
+    /// Sprite IDs with no metadata, sorted, with the settings length most of them use
+    pub fn unknown_sprite_issues(&self) -> Vec<UnknownSpriteIssue> {
+        let mut lengths: BTreeMap<u16, BTreeMap<u16, usize>> = BTreeMap::new();
+        for seg in &self.segments {
+            if let TopLevelSegmentWrapper::SETD(setd) = seg {
+                for sprite in setd.sprites.iter().filter(|spr| sprite_meta(spr.object_id).is_none()) {
+                    *lengths.entry(sprite.object_id).or_default().entry(sprite.settings_length).or_default() += 1;
+                }
+            }
+        }
+        lengths.into_iter().map(|(object_id, by_len)| {
+            let count = by_len.values().sum();
+            let settings_len = by_len.iter().max_by_key(|(_, len_count)| **len_count).map_or(0, |(len, _)| *len);
+            UnknownSpriteIssue { object_id, settings_len, count }
+        }).collect()
+    }
+
+    /// Anything compile would have to cut off, see limits
+    pub fn limit_issues(&self) -> Vec<FormatLimitIssue> {
+        let mut issues: Vec<FormatLimitIssue> = Vec::new();
+        for seg in &self.segments {
+            if let TopLevelSegmentWrapper::SETD(setd) = seg {
+                issues.extend(setd.sprites.iter().flat_map(LevelSprite::limit_issues));
+            }
+        }
+        issues
+    }
+
+    pub fn repair_tile_lengths(&mut self) {
+        for seg in &mut self.segments {
+            if let TopLevelSegmentWrapper::SCEN(scen) = seg {
+                if scen.tile_length_mismatch().is_some() {
+                    scen.repair_tile_length();
+                }
+            }
+        }
+    }
+
+    /// Each BG index with its INFO layer_order, the priority the DS draws it with
+    pub fn bg_priorities(&self) -> Vec<(u8, u8)> {
+        self.segments.iter().filter_map(|seg| match seg {
+            TopLevelSegmentWrapper::SCEN(scen) => scen.get_info().map(|info| (info.which_bg, info.layer_order)),
+            _ => None
+        }).collect()
+    }
+
+    /// BG indexes back to front. A higher priority value is further back, and on
+    /// a tie the higher BG is, same as the DS. Usually BG3, BG2, then BG1
+    pub fn bg_draw_order(&self) -> Vec<u8> {
+        let mut priorities = self.bg_priorities();
+        priorities.sort_by(|a, b| b.1.cmp(&a.1).then(b.0.cmp(&a.0)));
+        priorities.into_iter().map(|(which_bg, _)| which_bg).collect()
+    }
+
     pub fn get_bg_with_colz(&self) -> Option<u8> {
         for seg in &self.segments {
             if let TopLevelSegmentWrapper::SCEN(scen) = seg {
@@ -329,6 +511,91 @@ impl MapData {
     // Functions for updating the data itself //
     ////////////////////////////////////////////
 
+    /// Moves everything in the map by dx/dy tiles, which must be even for collision
+    ///
+    /// Each BG uses its own size, everything else uses the largest one.
+    /// Entrances and Exits are in the Course, see CourseMapInfo::shift_warps
+    pub fn shift_contents(&mut self, dx: i32, dy: i32) -> MapShiftReport {
+        let (width, height) = self.get_dimensions();
+        let mut report = MapShiftReport::default();
+        for seg in &mut self.segments {
+            match seg {
+                TopLevelSegmentWrapper::SCEN(scen) => {
+                    let (lost_tiles, lost_col) = scen.shift_contents(dx, dy);
+                    report.tiles += lost_tiles;
+                    report.collision += lost_col;
+                }
+                TopLevelSegmentWrapper::SETD(setd) => report.sprites += setd.shift(dx, dy, width, height),
+                TopLevelSegmentWrapper::AREA(area) => report.triggers += area.shift(dx, dy, width, height),
+                TopLevelSegmentWrapper::PATH(path) => report.path_lines += path.shift(dx, dy, width, height),
+                TopLevelSegmentWrapper::BLKZ(blkz) => {
+                    // Kept whole inside the map
+                    let max_x = (width as i32 - blkz.width as i32).max(0);
+                    let max_y = (height as i32 - blkz.height as i32).max(0);
+                    let new_x = (blkz.x_offset as i32 + dx).clamp(0, max_x);
+                    let new_y = (blkz.y_offset as i32 + dy).clamp(0, max_y);
+                    if new_x != blkz.x_offset as i32 + dx || new_y != blkz.y_offset as i32 + dy {
+                        report.soft_rock_clamped = true;
+                    }
+                    (blkz.x_offset, blkz.y_offset) = (new_x as u16, new_y as u16);
+                }
+                _ => { /* Nothing positional */ }
+            }
+        }
+        log_write(format!("Shifted map contents by {}/{}: {}",dx,dy,report), LogLevel::Log);
+        report
+    }
+
+    /// Empties everything inside bounds on the chosen layers. The BG the selection
+    /// was made on loses exactly its selected indexes instead, since those may not
+    /// fill the bounds. Collision squares partly inside are cleared too
+    pub fn clear_selection(&mut self, bounds: TileBounds, selected: Option<(u8, &[u32])>, layers: ClearLayers) -> MapClearReport {
+        let mut report = MapClearReport::default();
+        for which_bg in 1..=3u8 {
+            if !layers.bgs[which_bg as usize - 1] {
+                continue;
+            }
+            let Some(bg) = self.get_background(which_bg) else { continue };
+            let (Some(info), Some(mpbz)) = (bg.get_info(), bg.get_mpbz()) else { continue };
+            let (width, height) = (info.layer_width as u32, info.layer_height as u32);
+            let indexes: Vec<u32> = match selected {
+                Some((selected_bg, selected_indexes)) if selected_bg == which_bg => selected_indexes.to_vec(),
+                _ => bounds.indexes(width, height)
+            };
+            let filled: Vec<u32> = indexes.into_iter().filter(|index| mpbz.get_tile(*index).to_short() != 0).collect();
+            for index in filled {
+                if self.delete_bg_tile_by_map_index(which_bg, index) {
+                    report.tiles += 1;
+                }
+            }
+        }
+        if layers.collision && let Some(colz_bg) = self.get_bg_with_colz() && let Some(bg) = self.get_background(colz_bg) {
+            let col_width = bg.get_info().map_or(0, |info| info.layer_width as u32 / 2);
+            if let Some(colz) = bg.get_colz_mut() && col_width != 0 {
+                let col_height = colz.col_tiles.len() as u32 / col_width;
+                let filled: Vec<u32> = bounds.collision_squares().indexes(col_width, col_height).into_iter()
+                    .filter(|col_index| colz.col_tiles[*col_index as usize] != 0x00).collect();
+                for col_index in &filled {
+                    colz.col_tiles[*col_index as usize] = 0x00;
+                }
+                report.collision += filled.len();
+            }
+        }
+        if layers.sprites && let Some(setd) = self.get_setd() {
+            let initial_len = setd.sprites.len();
+            setd.sprites.retain(|sprite| !bounds.contains(sprite.x_position as u32, sprite.y_position as u32));
+            report.sprites += initial_len - setd.sprites.len();
+        }
+        if layers.paths && let Some(path) = self.get_path() {
+            report.path_points += path.remove_points(|point| {
+                let (x, y) = point.tile_pos();
+                bounds.contains(x, y)
+            });
+        }
+        log_write(format!("Cleared selection: {report}"), LogLevel::Log);
+        report
+    }
+
     /// Move a sprite in the map data
     pub fn move_sprite(&mut self, sprite_uuid: Uuid, new_x: u16, new_y: u16) {
         let sprite_set = self.get_setd().expect("Expected SETD to exist");
@@ -355,6 +622,27 @@ impl MapData {
         }
     }
 
+    /// Keeps the position, settings are resized to the new Sprite's length
+    ///
+    /// Returns false if the Sprite or the new ID's metadata is missing
+    pub fn change_sprite_object_id(&mut self, sprite_uuid: Uuid, new_id: u16) -> bool {
+        let Some(sprite_meta) = sprite_meta(new_id) else {
+            log_write(format!("No Sprite metadata found for 0x{new_id:X}, cannot change ID"),LogLevel::Error);
+            return false;
+        };
+        let sprite_set = self.get_setd().expect("Expected SETD to exist");
+        let Some(spr) = sprite_set.sprites.iter_mut().find(|spr| spr.uuid == sprite_uuid) else {
+            log_write(format!("Sprite '{}' not found when changing object ID",sprite_uuid),LogLevel::Error);
+            return false;
+        };
+        log_write(format!("Changing Sprite object ID from 0x{:X} to 0x{:X}",spr.object_id,new_id), LogLevel::Log);
+        spr.object_id = new_id;
+        spr.settings_length = sprite_meta.default_settings_len;
+        // Keep what overlaps, the rest is zeroes like a newly placed Sprite
+        spr.settings.resize(sprite_meta.default_settings_len as usize, 0);
+        true
+    }
+
     pub fn add_sprite(&mut self, sprite: LevelSprite) -> Uuid {
         let uuid = sprite.uuid;
         self.get_setd().expect("Expected SETD to exist").sprites.push(sprite);
@@ -367,10 +655,13 @@ impl MapData {
             log_write("SETD not loaded when placing sprite".to_owned(),LogLevel::Error);
             return Uuid::nil();
         };
-        let Some(sprite_meta) = SPRITE_METADATA.get(&sprite_id) else {
+        let Some(sprite_meta) = sprite_meta(sprite_id) else {
             log_write(format!("No Sprite metadata found for 0x{sprite_id:X}"),LogLevel::Error);
             return Uuid::nil();
         };
+        if sprite_meta.is_unknown() {
+            log_write(format!("Placing unknown Sprite 0x{sprite_id:X}, it may crash the game"),LogLevel::Warn);
+        }
         let new_sprite = LevelSprite::new(sprite_id, x, y, vec![0;sprite_meta.default_settings_len as usize]);
         let ret = new_sprite.uuid;
         sprite_set.sprites.push(new_sprite);
@@ -383,6 +674,17 @@ impl MapData {
                 .sprites.iter().find(|&spr| spr.uuid == sprite_uuid).cloned()
     }
 
+    /// Returns how many Sprites were moved, see LevelSpriteSet::move_sprites
+    pub fn move_sprites(&mut self, sprite_uuids: &[Uuid], dx: i32, dy: i32) -> usize {
+        let sprite_set = self.get_setd().expect("Expected SETD to exist");
+        sprite_set.move_sprites(sprite_uuids, dx, dy)
+    }
+
+    pub fn delete_sprites(&mut self, sprite_uuids: &[Uuid]) -> usize {
+        let sprite_set = self.get_setd().expect("Expected SETD to exist");
+        sprite_set.delete_sprites(sprite_uuids)
+    }
+
     /// Returns true if deleted successfully
     pub fn delete_sprite_by_uuid(&mut self, sprite_uuid: Uuid) -> bool {
         let sprite_set = self.get_setd().expect("Expected SETD to exist");
@@ -421,12 +723,12 @@ impl MapData {
             return false;
         };
         if let Some(tiles_segment) = bg.get_mpbz_mut() {
-            if (map_index as usize) > tiles_segment.tiles.len() {
-                log_write(format!("Overflow in delete_bg_tile_by_map_index: {} >= {}",&map_index,&tiles_segment.tiles.len()), LogLevel::Error);
-                return false;
-            }
+            // Anything past the trimmed bottom is already blank
+            let Some(tile_index) = tiles_segment.tile_index(map_index) else {
+                return true;
+            };
             let empty_record: MapTileRecordData = MapTileRecordData::default();
-            tiles_segment.tiles[map_index as usize] = empty_record;
+            tiles_segment.tiles[tile_index] = empty_record;
             // Ultimately the palette doesn't really matter since the tile is 0, transparent...
         }
         true
@@ -442,13 +744,19 @@ impl MapData {
             log_write(format!("Failed to get_background '{}' in place_bg_tile_at_map_index",which_background), LogLevel::Error);
             return false;
         };
+        let Some(info) = bg.get_info() else {
+            log_write(format!("No INFO for bg '{}' in place_bg_tile_at_map_index",which_background), LogLevel::Error);
+            return false;
+        };
+        let (layer_width, layer_height) = (info.layer_width, info.layer_height);
+        if map_index >= (layer_width as u32) * (layer_height as u32) {
+            // May be pasted out of bounds
+            log_write(format!("Overflow in place_bg_tile_at_map_index: 0x{:X}",&map_index), LogLevel::Error);
+            return false;
+        }
         if let Some(tiles_segment) = bg.get_mpbz_mut() {
-            if (map_index as usize) > tiles_segment.tiles.len() {
-                // May be pasted out of bounds
-                log_write(format!("Overflow in place_bg_tile_at_map_index {} >= {}",&map_index,&tiles_segment.tiles.len()), LogLevel::Error);
-                return false;
-            }
-            tiles_segment.tiles[map_index as usize] = MapTileRecordData::new(tile);
+            let tile_index = tiles_segment.editable_index(map_index, layer_width);
+            tiles_segment.tiles[tile_index] = MapTileRecordData::new(tile);
         } else {
             log_write(format!("Could not find map tiles for bg '{}' in place_bg_tile_at_map_index",which_background), LogLevel::Error);
             return false;
@@ -458,21 +766,323 @@ impl MapData {
 
 }
 
+/// What was lost or moved off the edges by MapData::shift_contents
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MapShiftReport {
+    pub tiles: usize,
+    pub collision: usize,
+    pub sprites: usize,
+    pub triggers: usize,
+    pub path_lines: usize,
+    /// Entrances and Exits are clamped to the edge rather than removed
+    pub warps_clamped: usize,
+    pub soft_rock_clamped: bool
+}
+impl MapShiftReport {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+impl Display for MapShiftReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return f.write_str("nothing out of bounds");
+        }
+        let mut parts: Vec<String> = Vec::new();
+        for (count, name) in [
+            (self.tiles, "BG tiles"), (self.collision, "collision tiles"), (self.sprites, "Sprites"),
+            (self.triggers, "Triggers"), (self.path_lines, "Path Lines")
+        ] {
+            if count > 0 {
+                parts.push(format!("{count} {name} removed"));
+            }
+        }
+        if self.warps_clamped > 0 {
+            parts.push(format!("{} Entrances/Exits moved to the edge",self.warps_clamped));
+        }
+        if self.soft_rock_clamped {
+            parts.push(String::from("Soft rock moved to the edge"));
+        }
+        f.write_str(&parts.join(", "))
+    }
+}
+
+/// Tiles from left/top to right/bottom, inclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileBounds {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32
+}
+impl TileBounds {
+    /// Around map indexes on a layer that wide
+    pub fn from_indexes(indexes: &[u32], layer_width: u32) -> Option<Self> {
+        let positions: Vec<(u32, u32)> = indexes.iter().map(|index| (
+            get_x_pos_of_map_index(*index, &layer_width) as u32,
+            get_y_pos_of_map_index(*index, &layer_width) as u32
+        )).collect();
+        Self::from_positions(&positions)
+    }
+
+    pub fn from_positions(positions: &[(u32, u32)]) -> Option<Self> {
+        let (first_x, first_y) = *positions.first()?;
+        let mut bounds = TileBounds { left: first_x, top: first_y, right: first_x, bottom: first_y };
+        for (x, y) in positions {
+            bounds.left = bounds.left.min(*x);
+            bounds.top = bounds.top.min(*y);
+            bounds.right = bounds.right.max(*x);
+            bounds.bottom = bounds.bottom.max(*y);
+        }
+        Some(bounds)
+    }
+
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        (self.left..=self.right).contains(&x) && (self.top..=self.bottom).contains(&y)
+    }
+
+    /// The collision squares these touch, in collision units, each covers 2x2 tiles
+    pub fn collision_squares(&self) -> Self {
+        TileBounds { left: self.left / 2, top: self.top / 2, right: self.right / 2, bottom: self.bottom / 2 }
+    }
+
+    /// Map indexes inside, on a layer of that size
+    pub fn indexes(&self, layer_width: u32, layer_height: u32) -> Vec<u32> {
+        if layer_width == 0 || layer_height == 0 {
+            return Vec::new();
+        }
+        let right = self.right.min(layer_width - 1);
+        let bottom = self.bottom.min(layer_height - 1);
+        (self.top..=bottom).flat_map(|y| (self.left..=right).map(move |x| xy_to_index(x, y, &layer_width))).collect()
+    }
+}
+
+/// Layers MapData::clear_selection empties
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClearLayers {
+    /// BG 1 to 3
+    pub bgs: [bool; 3],
+    pub collision: bool,
+    pub sprites: bool,
+    pub paths: bool
+}
+impl Default for ClearLayers {
+    fn default() -> Self {
+        Self { bgs: [true; 3], collision: true, sprites: true, paths: true }
+    }
+}
+
+/// What MapData::clear_selection removed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MapClearReport {
+    pub tiles: usize,
+    pub collision: usize,
+    pub sprites: usize,
+    pub path_points: usize
+}
+impl MapClearReport {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+impl Display for MapClearReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return f.write_str("nothing");
+        }
+        let parts: Vec<String> = [
+            (self.tiles, "tiles"), (self.collision, "collision cells"),
+            (self.sprites, "sprites"), (self.path_points, "path points")
+        ].iter().filter(|(count, _)| *count > 0).map(|(count, name)| format!("{count} {name}")).collect();
+        f.write_str(&parts.join(", "))
+    }
+}
+
+/// A BG whose tile count doesn't match its width and height
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileLengthIssue {
+    pub which_bg: u8,
+    pub actual: usize,
+    pub expected: usize
+}
+impl Display for TileLengthIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let change = if self.actual < self.expected { "padded with blank tiles" } else { "cut off at the end" };
+        write!(f, "BG {} has 0x{:X} tiles instead of 0x{:X}, would be {}",self.which_bg,self.actual,self.expected,change)
+    }
+}
+
+/// A Sprite ID missing from sprites.csv, with the settings length inferred from the map
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownSpriteIssue {
+    pub object_id: u16,
+    pub settings_len: u16,
+    pub count: usize
+}
+impl Display for UnknownSpriteIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Sprite 0x{:X} is not in sprites.csv, {} placed with settings length 0x{:X}",self.object_id,self.count,self.settings_len)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorTileIssue {
+    pub which_bg: u8,
+    pub count: usize
+}
+impl Display for ErrorTileIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "BG {} has {} tiles past the end of its tileset, drawn red here",self.which_bg,self.count)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MapDataError {
     FileNotExist(String),
     MasterHeaderNotFound,
     HeaderWasntSet([char; 3]),
-    FailedGenerateBackground,
+    /// Header, then what went wrong
+    BadSegment(String, String),
+    MissingSegment(&'static str),
+    MissingLayerSegment(u8, &'static str),
+    FileWriteFailed(String),
+}
+fn bad_segment(header: &str, reason: impl Display) -> MapDataError {
+    let err = MapDataError::BadSegment(header.to_owned(), reason.to_string());
+    log_write(&err, LogLevel::Error);
+    err
 }
+
 impl Display for MapDataError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::MasterHeaderNotFound => f.write_str("Error getting master header from MapData"),
             Self::FileNotExist(path) => f.write_fmt(format_args!("File does not exist: {path}")),
             Self::HeaderWasntSet([a,b,c]) => f.write_fmt(format_args!("MapData master header was not 'SET', was instead '{a}{b}{c}'")),
-            Self::FailedGenerateBackground => f.write_str("Failed to generate BackgroundData in MapData"),
+            Self::BadSegment(header, reason) => f.write_fmt(format_args!("Could not read segment '{header}': {reason}")),
+            Self::MissingSegment(header) => f.write_fmt(format_args!("MapData is missing required segment '{header}'")),
+            Self::MissingLayerSegment(which_bg, header) => f.write_fmt(format_args!("BG {which_bg} is missing required segment '{header}'")),
+            Self::FileWriteFailed(path) => f.write_fmt(format_args!("Failed to write MapData file: {path}")),
         }
     }
 }
 impl Error for MapDataError {}
+
+#[cfg(test)]
+mod tests_mapfile {
+    use crate::data::{fixtures::sample_map, scendata::ScenSegmentWrapper};
+
+    use super::*;
+
+    #[test]
+    fn test_truncated_no_panic() {
+        let map = sample_map(std::path::Path::new("test.mpdz"));
+        let bytes = segment_wrap_u32(map.compile(), 0x00544553);
+        let project = std::path::Path::new("unused");
+        assert!(MapData::from_bytes(&bytes, String::from("test.mpdz"), project).is_ok());
+        for len in 0..bytes.len() {
+            let _ = MapData::from_bytes(&bytes[..len], String::from("test.mpdz"), project);
+        }
+        let cut = MapData::from_bytes(&bytes[..bytes.len() - 1], String::from("test.mpdz"), project);
+        assert!(matches!(cut, Err(MapDataError::BadSegment(_, _))));
+        // Sizes inside a SCEN can be wrong even when the SCEN itself fits
+        let scen = map.segments[0].compile();
+        for len in 0..scen.len() {
+            let _ = BackgroundData::new(&scen[..len], project);
+        }
+        let setd = MapData::parse_segment("SETD", &[0x01, 0x00, 0x04], project).expect_err("Sprite cut off");
+        assert_eq!(setd.to_string(),"Could not read segment 'SETD': failed to fill whole buffer");
+    }
+
+    #[test]
+    fn test_validate_minimal() {
+        let map = sample_map(std::path::Path::new("test.mpdz"));
+        assert_eq!(map.validate_minimal(),Ok(()));
+        // No sprites segment
+        let mut no_setd = map.clone();
+        no_setd.segments.retain(|seg| !matches!(seg, TopLevelSegmentWrapper::SETD(_)));
+        assert_eq!(no_setd.validate_minimal(),Err(MapDataError::MissingSegment("SETD")));
+        // No layer carries collision
+        let mut no_colz = map.clone();
+        for seg in &mut no_colz.segments {
+            if let TopLevelSegmentWrapper::SCEN(scen) = seg {
+                scen.scen_segments.retain(|sub| !matches!(sub, ScenSegmentWrapper::COLZ(_)));
+            }
+        }
+        assert_eq!(no_colz.validate_minimal(),Err(MapDataError::MissingSegment("COLZ")));
+        // A layer without tiles
+        let mut no_mpbz = map.clone();
+        if let Some(bg) = no_mpbz.get_background(1) {
+            bg.scen_segments.retain(|sub| !matches!(sub, ScenSegmentWrapper::MPBZ(_)));
+        }
+        assert_eq!(no_mpbz.validate_minimal(),Err(MapDataError::MissingLayerSegment(1, "MPBZ")));
+        // A layer without palettes
+        let mut no_pltb = map.clone();
+        if let Some(bg) = no_pltb.get_background(2) {
+            bg.scen_segments.retain(|sub| !matches!(sub, ScenSegmentWrapper::PLTB(_)));
+        }
+        assert_eq!(no_pltb.validate_minimal(),Err(MapDataError::MissingLayerSegment(2, "PLTB")));
+        // No layers at all
+        let no_layers = MapData::new_blank(std::path::Path::new("test.mpdz"), Vec::new());
+        assert_eq!(no_layers.err(),Some(MapDataError::MissingSegment("SCEN")));
+    }
+
+    #[test]
+    fn test_bg_draw_order() {
+        let mut map = sample_map(std::path::Path::new("test.mpdz"));
+        let set_priority = |map: &mut MapData, which_bg: u8, priority: u8| {
+            if let Some(ScenSegmentWrapper::INFO(info)) = map.get_background(which_bg).and_then(|bg| bg.scen_segments.first_mut()) {
+                info.layer_order = priority;
+            }
+        };
+        set_priority(&mut map, 1, 1);
+        set_priority(&mut map, 2, 1);
+        // Ties go by BG index
+        assert_eq!(map.bg_draw_order(),vec![2, 1]);
+        set_priority(&mut map, 2, 0);
+        assert_eq!(map.bg_draw_order(),vec![1, 2]);
+        assert_eq!(map.bg_priorities(),vec![(2, 0), (1, 1)]);
+    }
+
+    #[test]
+    fn test_shift_soft_rock() {
+        let mut map = sample_map(std::path::Path::new("test.mpdz"));
+        map.segments.push(TopLevelSegmentWrapper::BLKZ(SoftRockBackdrop { x_offset: 2, y_offset: 1, width: 4, height: 2,
+            tiles: vec![MapTileRecordData::new(1); 8] }));
+        let soft_rock_pos = |map: &MapData| map.get_blkz().map(|blkz| (blkz.x_offset, blkz.y_offset));
+        assert!(!map.shift_contents(2, 2).soft_rock_clamped);
+        assert_eq!(soft_rock_pos(&map),Some((4, 3)));
+        // The 8x6 map only fits the 4x2 backdrop up to 4/4
+        assert!(map.shift_contents(10, 10).soft_rock_clamped);
+        assert_eq!(soft_rock_pos(&map),Some((4, 4)));
+        assert!(map.shift_contents(-10, -10).soft_rock_clamped);
+        assert_eq!(soft_rock_pos(&map),Some((0, 0)));
+    }
+
+    #[test]
+    fn test_clear_selection() {
+        let mut map = sample_map(std::path::Path::new("test.mpdz"));
+        // Tiles 1,1 and 2,1, with 1,1 the only one filled
+        let selected: Vec<u32> = vec![9, 10];
+        let bounds = TileBounds::from_indexes(&selected, 8).expect("Indexes given");
+        assert_eq!(bounds,TileBounds { left: 1, top: 1, right: 2, bottom: 1 });
+        let no_sprites = ClearLayers { sprites: false, ..Default::default() };
+        let mut cleared = map.clone();
+        let report = cleared.clear_selection(bounds, Some((2, &selected)), no_sprites);
+        // The collision square at 0,0 is partly inside
+        assert_eq!(report,MapClearReport { tiles: 1, collision: 1, sprites: 0, path_points: 0 });
+        assert_eq!(report.to_string(),"1 tiles, 1 collision cells");
+        let bg2 = cleared.get_background(2).expect("Sample has BG2");
+        assert_eq!(bg2.get_mpbz().expect("Sample has MPBZ").tiles[0].to_short(),0x0001);
+        assert_eq!(bg2.get_colz().expect("Sample has COLZ").col_tiles[11],0x1A);
+        // Sprites and both Path points, which drops the Line
+        let bounds = TileBounds::from_positions(&[(2, 2), (6, 4)]).expect("Positions given");
+        let report = map.clear_selection(bounds, None, ClearLayers::default());
+        assert_eq!(report,MapClearReport { tiles: 0, collision: 1, sprites: 2, path_points: 2 });
+        assert_eq!(map.get_setd().expect("Sample has SETD").sprites.len(),1);
+        let path = map.get_path().expect("Sample has PATH");
+        assert!(path.lines.is_empty());
+        assert_eq!(path.path_count,0);
+        assert_eq!(MapClearReport::default().to_string(),"nothing");
+    }
+}
@@ -14,6 +14,9 @@ pub mod path;
 pub mod alph;
 pub mod blkz;
 pub mod brak;
+pub mod limits;
+#[cfg(test)]
+pub mod fixtures;
 
 pub trait Compilable {
     /// This creates a byte vector readable by Yoshi's Island DS.
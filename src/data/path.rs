@@ -1,9 +1,9 @@
-use std::io::Cursor;
+use std::io::{self, Cursor};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use uuid::Uuid;
 
-use crate::{engine::compression::segment_wrap, utils::{log_write, LogLevel}};
+use crate::{engine::compression::segment_wrap, utils::{log_write, new_uuid, LogLevel}};
 
 use super::{Compilable, TopLevelSegment};
 
@@ -13,13 +13,13 @@ pub struct PathDatabase {
     pub lines: Vec<PathLine>
 }
 impl PathDatabase {
-    pub fn new(byte_data: &[u8]) -> Self {
+    pub fn new(byte_data: &[u8]) -> io::Result<Self> {
         let mut ret: PathDatabase = PathDatabase::default();
         let mut rdr = Cursor::new(byte_data);
         let path_count = match rdr.read_u32::<LittleEndian>() {
             Err(error) => {
                 log_write(format!("Failed to get path_count from PathDatabase: '{error}'"), LogLevel::Error);
-                return ret;
+                return Ok(ret);
             }
             Ok(c) => c,
         };
@@ -32,23 +32,59 @@ impl PathDatabase {
                 let angle = match rdr.read_i16::<LittleEndian>() {
                     Err(error) => {
                         log_write(format!("Failed to read Path angle: '{error}'"), LogLevel::Error);
-                        return ret;
+                        return Ok(ret);
                     },
                     Ok(a) => a,
                 };
-                let distance = rdr.read_i16::<LittleEndian>().expect("distance i16 in PathDatabase");
-                let x_fine = rdr.read_u32::<LittleEndian>().expect("x_fine u32 in PathDatabase");
-                let y_fine = rdr.read_u32::<LittleEndian>().expect("y_fine u32 in PathDatabase");
+                let distance = rdr.read_i16::<LittleEndian>()?;
+                let x_fine = rdr.read_u32::<LittleEndian>()?;
+                let y_fine = rdr.read_u32::<LittleEndian>()?;
                 let point = PathPoint::new(angle, distance, x_fine, y_fine);
                 points.push(point);
                 if distance == 0x0000 {
                     break;
                 }
             }
-            ret.lines.push(PathLine { points, uuid: Uuid::new_v4() });
+            ret.lines.push(PathLine { points, uuid: new_uuid() });
             path_index += 1;
         }
-        ret
+        Ok(ret)
+    }
+
+    /// Moves every point by dx/dy tiles, removing Lines with any point off the map
+    pub fn shift(&mut self, dx: i32, dy: i32, width: u16, height: u16) -> usize {
+        let initial_len = self.lines.len();
+        // Fine coordinates have the tile in the upper bits
+        let (dx_fine, dy_fine) = ((dx as i64) << 15, (dy as i64) << 15);
+        let (max_x, max_y) = ((width as i64) << 15, (height as i64) << 15);
+        self.lines.retain_mut(|line| {
+            let fits = line.points.iter().all(|point| {
+                let (x, y) = (point.x_fine as i64 + dx_fine, point.y_fine as i64 + dy_fine);
+                (0..max_x).contains(&x) && (0..max_y).contains(&y)
+            });
+            if fits {
+                for point in &mut line.points {
+                    point.x_fine = (point.x_fine as i64 + dx_fine) as u32;
+                    point.y_fine = (point.y_fine as i64 + dy_fine) as u32;
+                }
+            }
+            fits
+        });
+        self.path_count = self.lines.len() as u32;
+        initial_len - self.lines.len()
+    }
+
+    /// Removes every point `remove` matches, along with Lines left with fewer
+    /// than two points. Returns how many points were removed
+    pub fn remove_points(&mut self, remove: impl Fn(&PathPoint) -> bool) -> usize {
+        let initial_count: usize = self.lines.iter().map(|line| line.points.len()).sum();
+        for line in &mut self.lines {
+            line.points.retain(|point| !remove(point));
+        }
+        self.lines.retain(|line| line.points.len() >= 2);
+        self.path_count = self.lines.len() as u32;
+        self.fix_term();
+        initial_count - self.lines.iter().map(|line| line.points.len()).sum::<usize>()
     }
 
     pub fn delete_line(&mut self, line_uuid: Uuid) -> bool {
@@ -111,7 +147,7 @@ impl Default for PathLine {
     fn default() -> Self {
         Self {
             points: Vec::new(),
-            uuid: Uuid::new_v4()
+            uuid: new_uuid()
         }
     }
 }
@@ -140,16 +176,21 @@ pub struct PathPoint {
 impl PathPoint {
     pub fn new(angle: i16, distance: i16, x_fine: u32, y_fine: u32) -> Self {
         Self {
-            angle, distance, x_fine, y_fine, uuid: Uuid::new_v4()
+            angle, distance, x_fine, y_fine, uuid: new_uuid()
         }
     }
+
+    /// The tile it's on, which is in the upper bits of the fine coordinates
+    pub fn tile_pos(&self) -> (u32, u32) {
+        (self.x_fine >> 15, self.y_fine >> 15)
+    }
 }
 impl Default for PathPoint {
     fn default() -> Self {
         Self {
             angle: 0, distance: 0x0000,
             x_fine: 0, y_fine: 0,
-            uuid: Uuid::new_v4()
+            uuid: new_uuid()
         }
     }
 }
@@ -52,7 +52,7 @@ impl AnmzDataSegment {
             frame_index += 1;
         }
         // Pad to 4 bytes
-        while rdr.position() % 4 != 0 {
+        while !rdr.position().is_multiple_of(4) {
             let _ = rdr.read_u8();
         }
 
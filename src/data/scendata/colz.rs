@@ -4,14 +4,19 @@ use crate::{engine::compression::{lamezip77_lz10_decomp, lamezip77_lz10_recomp,
 
 use super::{info::ScenInfoData, ScenSegment};
 
-pub const COLLISION_BG_COLOR: Color32 = Color32::from_rgba_premultiplied(0x40, 0x40, 0x60, 0x40);
-pub const COLLISION_BG_COLOR_PASSABLE: Color32 = Color32::from_rgba_premultiplied(0x10, 0x40, 0x10, 0x40);
-pub const COLLISION_BG_COLOR_LAVA: Color32 = Color32::from_rgba_premultiplied(0x80, 0x00, 0x00, 0x40);
-pub const COLLISION_BG_COLOR_WATER_STILL: Color32 = Color32::from_rgba_premultiplied(0x00, 0x00, 0x80, 0x80);
-pub const COLLISION_BG_COLOR_SOFT_ROCK: Color32 = Color32::from_rgba_premultiplied(0x80, 0x80, 0x00, 0x40);
-pub const COLLISION_OUTLINE_COLOR: Color32 = Color32::from_rgba_premultiplied(0x40, 0x40, 0x60, 0xff);
 pub const COLLISION_SQUARE: Vec2 = Vec2::new(16.0, 16.0);
 
+/// Fill colors for each kind of collision tile, part of ThemeColors
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct CollisionColors {
+    pub solid: Color32,
+    pub passable: Color32,
+    pub lava: Color32,
+    pub water_still: Color32,
+    pub soft_rock: Color32,
+    pub outline: Color32
+}
+
 #[derive(Debug,Clone,PartialEq,Default)]
 pub struct CollisionData {
     /// Just keep it the same, it's just u8s
@@ -27,11 +32,11 @@ impl CollisionData {
     }
     pub fn increase_width(&mut self, old_width: u16, increase_by: usize) {
         // Tiles are 2x2
-        if increase_by % 2 != 0 {
+        if !increase_by.is_multiple_of(2) {
             log_write(format!("increase_by was not even: 0x{:X}",increase_by), LogLevel::Error);
             return;
         }
-        if old_width % 2 != 0 {
+        if !old_width.is_multiple_of(2) {
             log_write(format!("old_width was not even: 0x{:X}",old_width), LogLevel::Error);
             return;
         }
@@ -68,6 +73,79 @@ impl CollisionData {
             idx += old_width;
         }
     }
+    /// Moves every collision tile by dx/dy BG tiles, which must be even since
+    /// collision is 2x2. Returns how many non-empty ones went off the edge
+    pub fn shift(&mut self, dx: i32, dy: i32, layer_width: u16, layer_height: u16) -> usize {
+        if dx % 2 != 0 || dy % 2 != 0 {
+            log_write(format!("Collision shift was not even: {}/{}",dx,dy), LogLevel::Error);
+            return 0;
+        }
+        let (dx, dy) = (dx / 2, dy / 2);
+        let (width, height) = (layer_width as i32 / 2, layer_height as i32 / 2);
+        let mut shifted: Vec<u8> = vec![0x00; self.col_tiles.len()];
+        let mut lost: usize = 0;
+        for (index, col_tile) in self.col_tiles.iter().enumerate() {
+            if *col_tile == 0x00 {
+                continue;
+            }
+            let (x, y) = (index as i32 % width + dx, index as i32 / width + dy);
+            let new_index = (y * width + x) as usize;
+            if x < 0 || y < 0 || x >= width || y >= height || new_index >= shifted.len() {
+                lost += 1;
+                continue;
+            }
+            shifted[new_index] = *col_tile;
+        }
+        self.col_tiles = shifted;
+        lost
+    }
+
+    /// Indexes of every non-empty tile touching `local_rect`, which is in pixels
+    /// from the top left of the layer. Works off the whole layer, not what's rendered
+    pub fn indexes_in_rect(&self, local_rect: Rect, layer_width: u16) -> Vec<usize> {
+        let width = layer_width as usize / 2;
+        if width == 0 || !local_rect.is_positive() || local_rect.max.x < 0.0 || local_rect.max.y < 0.0 {
+            return Vec::new();
+        }
+        let height = self.col_tiles.len() / width;
+        let first_x = (local_rect.min.x.max(0.0) / COLLISION_SQUARE.x) as usize;
+        let first_y = (local_rect.min.y.max(0.0) / COLLISION_SQUARE.y) as usize;
+        let last_x = ((local_rect.max.x / COLLISION_SQUARE.x) as usize).min(width.saturating_sub(1));
+        let last_y = ((local_rect.max.y / COLLISION_SQUARE.y) as usize).min(height.saturating_sub(1));
+        let mut indexes: Vec<usize> = Vec::new();
+        for y in first_y..=last_y {
+            for x in first_x..=last_x {
+                let index = y * width + x;
+                if self.col_tiles.get(index).is_some_and(|col_tile| *col_tile != 0x00) {
+                    indexes.push(index);
+                }
+            }
+        }
+        indexes
+    }
+
+    /// Sets every tile in the rectangle with corners at two collision indexes.
+    /// Returns how many tiles changed
+    pub fn fill_rect(&mut self, corner_a: usize, corner_b: usize, layer_width: u16, col_type: u8) -> usize {
+        let width = layer_width as usize / 2;
+        if width == 0 || corner_a >= self.col_tiles.len() || corner_b >= self.col_tiles.len() {
+            return 0;
+        }
+        let (ax, ay) = (corner_a % width, corner_a / width);
+        let (bx, by) = (corner_b % width, corner_b / width);
+        let mut changed: usize = 0;
+        for y in ay.min(by)..=ay.max(by) {
+            for x in ax.min(bx)..=ax.max(bx) {
+                let col_tile = &mut self.col_tiles[y * width + x];
+                if *col_tile != col_type {
+                    *col_tile = col_type;
+                    changed += 1;
+                }
+            }
+        }
+        changed
+    }
+
     pub fn change_height(&mut self, new_height: u16, current_width: u16) {
         log_write(format!("Changing COLZ height to {:X}",new_height), LogLevel::Debug);
         let new_len = (new_height as u32 / 2) * (current_width as u32 / 2);
@@ -91,55 +169,55 @@ impl ScenSegment for CollisionData {
     }
 }
 
-fn draw_collision_polygon(painter: &Painter, pos_vec: Vec<Pos2>, bg_color: Color32) {
-    let shap = Shape::convex_polygon(pos_vec, bg_color, Stroke::new(1.0, COLLISION_OUTLINE_COLOR));
+fn draw_collision_polygon(painter: &Painter, pos_vec: Vec<Pos2>, bg_color: Color32, outline: Color32) {
+    let shap = Shape::convex_polygon(pos_vec, bg_color, Stroke::new(1.0, outline));
     painter.add(shap);
 }
 
-pub fn draw_collision(painter: &Painter, rect: &Rect, col_type: u8) {
+pub fn draw_collision(painter: &Painter, rect: &Rect, col_type: u8, colors: &CollisionColors) {
     puffin::profile_function!();
     match col_type {
         0x00 => { /* Blank */ },
-        0x01 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.right_bottom(),rect.left_bottom()],COLLISION_BG_COLOR),
-        0x02 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.right_bottom(),rect.left_bottom()],COLLISION_BG_COLOR_PASSABLE),
-        0x03 => draw_collision_polygon(painter, vec![rect.left_bottom(),rect.right_center(),rect.right_bottom()],COLLISION_BG_COLOR),
-        0x04 => draw_collision_polygon(painter, vec![rect.left_center(),rect.right_top(),rect.right_bottom(),rect.left_bottom()],COLLISION_BG_COLOR),
-        0x05 => draw_collision_polygon(painter, vec![rect.left_bottom(),rect.center_top(),rect.right_top(),rect.right_bottom()],COLLISION_BG_COLOR),
-        0x06 => draw_collision_polygon(painter, vec![rect.right_top(),rect.right_bottom(),rect.center_bottom()],COLLISION_BG_COLOR),
-        0x07 => draw_collision_polygon(painter, vec![rect.left_bottom(),rect.right_top(),rect.right_bottom()],COLLISION_BG_COLOR),
-        0x09 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.right_bottom(),rect.left_bottom()], COLLISION_BG_COLOR_LAVA),
-        0x12 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.right_bottom(),rect.left_bottom()], COLLISION_BG_COLOR_WATER_STILL),
-        0x14 => draw_collision_polygon(painter, vec![rect.left_bottom(),rect.right_center(),rect.right_bottom()], COLLISION_BG_COLOR_PASSABLE),
-        0x15 => draw_collision_polygon(painter, vec![rect.left_center(),rect.right_top(),rect.right_bottom(),rect.left_bottom()], COLLISION_BG_COLOR_PASSABLE),
-        0x16 => draw_collision_polygon(painter, vec![rect.left_bottom(),rect.center_top(),rect.right_top(),rect.right_bottom()], COLLISION_BG_COLOR_PASSABLE),
-        0x17 => draw_collision_polygon(painter, vec![rect.center_bottom(),rect.right_top(),rect.right_bottom()], COLLISION_BG_COLOR_PASSABLE),
-        0x18 => draw_collision_polygon(painter, vec![rect.left_bottom(),rect.right_top(),rect.right_bottom()], COLLISION_BG_COLOR_PASSABLE),
+        0x01 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.right_bottom(),rect.left_bottom()], colors.solid, colors.outline),
+        0x02 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.right_bottom(),rect.left_bottom()], colors.passable, colors.outline),
+        0x03 => draw_collision_polygon(painter, vec![rect.left_bottom(),rect.right_center(),rect.right_bottom()], colors.solid, colors.outline),
+        0x04 => draw_collision_polygon(painter, vec![rect.left_center(),rect.right_top(),rect.right_bottom(),rect.left_bottom()], colors.solid, colors.outline),
+        0x05 => draw_collision_polygon(painter, vec![rect.left_bottom(),rect.center_top(),rect.right_top(),rect.right_bottom()], colors.solid, colors.outline),
+        0x06 => draw_collision_polygon(painter, vec![rect.right_top(),rect.right_bottom(),rect.center_bottom()], colors.solid, colors.outline),
+        0x07 => draw_collision_polygon(painter, vec![rect.left_bottom(),rect.right_top(),rect.right_bottom()], colors.solid, colors.outline),
+        0x09 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.right_bottom(),rect.left_bottom()], colors.lava, colors.outline),
+        0x12 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.right_bottom(),rect.left_bottom()], colors.water_still, colors.outline),
+        0x14 => draw_collision_polygon(painter, vec![rect.left_bottom(),rect.right_center(),rect.right_bottom()], colors.passable, colors.outline),
+        0x15 => draw_collision_polygon(painter, vec![rect.left_center(),rect.right_top(),rect.right_bottom(),rect.left_bottom()], colors.passable, colors.outline),
+        0x16 => draw_collision_polygon(painter, vec![rect.left_bottom(),rect.center_top(),rect.right_top(),rect.right_bottom()], colors.passable, colors.outline),
+        0x17 => draw_collision_polygon(painter, vec![rect.center_bottom(),rect.right_top(),rect.right_bottom()], colors.passable, colors.outline),
+        0x18 => draw_collision_polygon(painter, vec![rect.left_bottom(),rect.right_top(),rect.right_bottom()], colors.passable, colors.outline),
         0x1A => { /* Coin */ },
-        0x1B => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.right_bottom(),rect.left_bottom()], COLLISION_BG_COLOR_SOFT_ROCK),
-        0x1F => draw_collision_polygon(painter, vec![rect.left_bottom(),rect.right_top(),rect.right_bottom()], COLLISION_BG_COLOR_PASSABLE),
-        0x43 => draw_collision_polygon(painter, vec![rect.left_center(),rect.right_bottom(),rect.left_bottom()],COLLISION_BG_COLOR),
-        0x44 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_center(),rect.right_bottom(),rect.left_bottom()],COLLISION_BG_COLOR),
-        0x45 => draw_collision_polygon(painter, vec![rect.left_top(),rect.center_top(),rect.right_bottom(),rect.left_bottom()],COLLISION_BG_COLOR),
-        0x46 => draw_collision_polygon(painter, vec![rect.left_top(),rect.center_bottom(),rect.left_bottom()],COLLISION_BG_COLOR),
-        0x47 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_bottom(),rect.left_bottom()],COLLISION_BG_COLOR),
-        0x54 => draw_collision_polygon(painter, vec![rect.left_center(),rect.right_bottom(),rect.left_bottom()], COLLISION_BG_COLOR_PASSABLE),
-        0x55 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_center(),rect.right_bottom(),rect.left_bottom()], COLLISION_BG_COLOR_PASSABLE),
-        0x56 => draw_collision_polygon(painter, vec![rect.left_top(),rect.center_top(),rect.right_bottom(),rect.left_bottom()], COLLISION_BG_COLOR_PASSABLE),
-        0x57 => draw_collision_polygon(painter, vec![rect.left_top(),rect.center_bottom(),rect.left_bottom()], COLLISION_BG_COLOR_PASSABLE),
-        0x58 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_bottom(),rect.left_bottom()], COLLISION_BG_COLOR_PASSABLE),
-        0x83 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.right_center()], COLLISION_BG_COLOR),
-        0x84 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.right_bottom(),rect.left_center()], COLLISION_BG_COLOR),
-        0x85 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.right_bottom(),rect.center_bottom()], COLLISION_BG_COLOR),
-        0x86 => draw_collision_polygon(painter, vec![rect.center_top(),rect.right_top(),rect.right_bottom()], COLLISION_BG_COLOR),
-        0x87 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.right_bottom()],COLLISION_BG_COLOR),
-        0xC3 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.left_center()],COLLISION_BG_COLOR),
-        0xC4 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.right_center(),rect.left_bottom()],COLLISION_BG_COLOR),
-        0xC5 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.center_bottom(),rect.left_bottom()], COLLISION_BG_COLOR),
-        0xC6 => draw_collision_polygon(painter, vec![rect.left_top(),rect.center_top(),rect.left_bottom()], COLLISION_BG_COLOR),
-        0xC7 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.left_bottom()],COLLISION_BG_COLOR),
+        0x1B => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.right_bottom(),rect.left_bottom()], colors.soft_rock, colors.outline),
+        0x1F => draw_collision_polygon(painter, vec![rect.left_bottom(),rect.right_top(),rect.right_bottom()], colors.passable, colors.outline),
+        0x43 => draw_collision_polygon(painter, vec![rect.left_center(),rect.right_bottom(),rect.left_bottom()], colors.solid, colors.outline),
+        0x44 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_center(),rect.right_bottom(),rect.left_bottom()], colors.solid, colors.outline),
+        0x45 => draw_collision_polygon(painter, vec![rect.left_top(),rect.center_top(),rect.right_bottom(),rect.left_bottom()], colors.solid, colors.outline),
+        0x46 => draw_collision_polygon(painter, vec![rect.left_top(),rect.center_bottom(),rect.left_bottom()], colors.solid, colors.outline),
+        0x47 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_bottom(),rect.left_bottom()], colors.solid, colors.outline),
+        0x54 => draw_collision_polygon(painter, vec![rect.left_center(),rect.right_bottom(),rect.left_bottom()], colors.passable, colors.outline),
+        0x55 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_center(),rect.right_bottom(),rect.left_bottom()], colors.passable, colors.outline),
+        0x56 => draw_collision_polygon(painter, vec![rect.left_top(),rect.center_top(),rect.right_bottom(),rect.left_bottom()], colors.passable, colors.outline),
+        0x57 => draw_collision_polygon(painter, vec![rect.left_top(),rect.center_bottom(),rect.left_bottom()], colors.passable, colors.outline),
+        0x58 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_bottom(),rect.left_bottom()], colors.passable, colors.outline),
+        0x83 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.right_center()], colors.solid, colors.outline),
+        0x84 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.right_bottom(),rect.left_center()], colors.solid, colors.outline),
+        0x85 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.right_bottom(),rect.center_bottom()], colors.solid, colors.outline),
+        0x86 => draw_collision_polygon(painter, vec![rect.center_top(),rect.right_top(),rect.right_bottom()], colors.solid, colors.outline),
+        0x87 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.right_bottom()], colors.solid, colors.outline),
+        0xC3 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.left_center()], colors.solid, colors.outline),
+        0xC4 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.right_center(),rect.left_bottom()], colors.solid, colors.outline),
+        0xC5 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.center_bottom(),rect.left_bottom()], colors.solid, colors.outline),
+        0xC6 => draw_collision_polygon(painter, vec![rect.left_top(),rect.center_top(),rect.left_bottom()], colors.solid, colors.outline),
+        0xC7 => draw_collision_polygon(painter, vec![rect.left_top(),rect.right_top(),rect.left_bottom()], colors.solid, colors.outline),
         _ => {
             // Unknown, put text
-            painter.rect_filled(*rect, 0.0, COLLISION_BG_COLOR);
+            painter.rect_filled(*rect, 0.0, colors.solid);
             painter.text(
                 rect.left_top()+Vec2::new(1.0, 1.0), Align2::LEFT_TOP,
                 format!("{:02X}",col_type),
@@ -155,3 +233,39 @@ pub fn draw_collision(painter: &Painter, rect: &Rect, col_type: u8) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests_colz {
+    use super::*;
+
+    #[test]
+    fn test_indexes_in_rect() {
+        // 8x4 BG tiles, so 4x2 collision tiles
+        let col = CollisionData { col_tiles: vec![
+            0x01, 0x00, 0x01, 0x01,
+            0x01, 0x1A, 0x00, 0x01
+        ] };
+        let all = Rect::from_min_max(Pos2::new(-10.0, -10.0), Pos2::new(1000.0, 1000.0));
+        assert_eq!(col.indexes_in_rect(all, 8),vec![0, 2, 3, 4, 5, 7]);
+        // Middle two columns, both rows
+        let middle = Rect::from_min_max(Pos2::new(20.0, 4.0), Pos2::new(40.0, 20.0));
+        assert_eq!(col.indexes_in_rect(middle, 8),vec![2, 5]);
+        assert!(col.indexes_in_rect(Rect::NOTHING, 8).is_empty());
+        assert!(col.indexes_in_rect(Rect::from_min_max(Pos2::new(-20.0, -20.0), Pos2::new(-1.0, -1.0)), 8).is_empty());
+    }
+
+    #[test]
+    fn test_fill_rect() {
+        let mut col = CollisionData { col_tiles: vec![
+            0x01, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00
+        ] };
+        // Corners in either order
+        assert_eq!(col.fill_rect(6, 0, 8, 0x01),5);
+        assert_eq!(col.col_tiles,vec![
+            0x01, 0x01, 0x01, 0x00,
+            0x01, 0x01, 0x01, 0x00
+        ]);
+        assert_eq!(col.fill_rect(3, 8, 8, 0x01),0);
+    }
+}
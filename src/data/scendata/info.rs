@@ -68,9 +68,9 @@ impl ScenInfoData {
         }
         let after_position: u64 = rdr.position();
         let mut read_length = after_position - initial_position;
-        if read_length % 4 != 0 {
+        if !read_length.is_multiple_of(4) {
             log_write(format!("INFO read size not 4 byte aligned; size was 0x{:X}, padding",read_length), LogLevel::Debug);
-            while read_length % 4 != 0 {
+            while !read_length.is_multiple_of(4) {
                 let _ = rdr.read_u8();
                 read_length = rdr.position() - initial_position;
             }
@@ -141,7 +141,7 @@ impl ScenSegment for ScenInfoData {
         let mut str_vec = imbz_filename_noext.bytes().collect();
         comp.append(&mut str_vec);
         comp.push(0x00); // Null terminator
-        while comp.len() % 4 != 0 {
+        while !comp.len().is_multiple_of(4) {
             comp.push(0x00);
         }
 
@@ -11,6 +11,8 @@ use pltb::PltbData;
 use rast::RastData;
 use scrl::ScrollData;
 
+use crate::engine::compression::segment_wrap_u32;
+
 pub mod info;
 pub mod pltb;
 pub mod mpbz;
@@ -34,7 +36,9 @@ pub enum ScenSegmentWrapper {
     IMGB(ImgbData),
     IMBZ(ImbzData),
     PLAN(AnimatedPaletteData),
-    RAST(RastData)
+    RAST(RastData),
+    /// Kept as-is so it survives a save
+    Unknown(GenericScenSegment)
 }
 
 pub trait ScenSegment {
@@ -58,7 +62,8 @@ impl ScenSegment for ScenSegmentWrapper {
             Self::IMGB(imgb) => imgb.compile(info),
             Self::IMBZ(imbz) => imbz.compile(info),
             Self::PLAN(plan) => plan.compile(info),
-            Self::RAST(rast) => rast.compile(info)
+            Self::RAST(rast) => rast.compile(info),
+            Self::Unknown(unkn) => unkn.compile(info)
         }
     }
 
@@ -73,7 +78,8 @@ impl ScenSegment for ScenSegmentWrapper {
             Self::IMGB(imgb) => imgb.wrap(info),
             Self::IMBZ(imbz) => imbz.wrap(info),
             Self::PLAN(plan) => plan.wrap(info),
-            Self::RAST(rast) => rast.wrap(info)
+            Self::RAST(rast) => rast.wrap(info),
+            Self::Unknown(unkn) => unkn.wrap(info)
         }
     }
 
@@ -88,7 +94,37 @@ impl ScenSegment for ScenSegmentWrapper {
             Self::IMGB(imgb) => imgb.header(),
             Self::IMBZ(imbz) => imbz.header(),
             Self::PLAN(plan) => plan.header(),
-            Self::RAST(rast) => rast.header()
+            Self::RAST(rast) => rast.header(),
+            Self::Unknown(unkn) => unkn.header()
         }
     }
 }
+
+/// A SCEN sub-segment the editor doesn't understand, same as GenericTopLevelSegment
+#[derive(Debug,Clone,PartialEq)]
+pub struct GenericScenSegment {
+    pub raw_bytes: Vec<u8>,
+    pub header: String,
+}
+
+impl GenericScenSegment {
+    pub fn new(data: Vec<u8>, header: String) -> Self {
+        Self { raw_bytes: data, header }
+    }
+}
+
+impl ScenSegment for GenericScenSegment {
+    fn compile(&self, _info: Option<&ScenInfoData>) -> Vec<u8> {
+        self.raw_bytes.clone()
+    }
+
+    fn wrap(&self, info: Option<&ScenInfoData>) -> Vec<u8> {
+        // Back from the chars header_to_string made, so bytes past ASCII survive
+        let magic = self.header.chars().rev().fold(0u32, |acc, c| (acc << 8) | (c as u32 & 0xFF));
+        segment_wrap_u32(self.compile(info), magic)
+    }
+
+    fn header(&self) -> String {
+        self.header.clone()
+    }
+}
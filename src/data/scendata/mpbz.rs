@@ -1,6 +1,6 @@
 // Map tiles. Has some possible extra data at the top
 
-use std::io::Cursor;
+use std::io::{self, Cursor};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
@@ -16,19 +16,19 @@ pub struct MapTileDataSegment {
 }
 
 impl MapTileDataSegment {
-    pub fn from_decomped_vec(mp_decomp: &[u8], layer_width: u16) -> Self {
+    pub fn from_decomped_vec(mp_decomp: &[u8], layer_width: u16) -> io::Result<Self> {
         let mut mpbz_vec: Vec<MapTileRecordData> = Vec::new();
         let mut count_tiles: u32 = mp_decomp.len() as u32 / 2;
         let tile_offset: u16;
         let bottom_trim: u16;
         let mut rdr2 = Cursor::new(mp_decomp);
         // Check for offsets
-        let first = rdr2.read_u16::<LittleEndian>().unwrap();
+        let first = rdr2.read_u16::<LittleEndian>()?;
         if first == 0xffff {
             // There's special data
-            tile_offset = rdr2.read_u16::<LittleEndian>().unwrap();
-            bottom_trim = rdr2.read_u16::<LittleEndian>().unwrap();
-            let offset: u32 = (layer_width * tile_offset) as u32;
+            tile_offset = rdr2.read_u16::<LittleEndian>()?;
+            bottom_trim = rdr2.read_u16::<LittleEndian>()?;
+            let offset: u32 = layer_width as u32 * tile_offset as u32;
             let blank = MapTileRecordData::new(0x0000);
             for _ in 0..offset {
                 mpbz_vec.push(blank);
@@ -43,7 +43,7 @@ impl MapTileDataSegment {
         // Now load the tiles themselves
         let mut tile_index = 0;
         while tile_index < count_tiles {
-            let short: u16 = rdr2.read_u16::<LittleEndian>().unwrap();
+            let short: u16 = rdr2.read_u16::<LittleEndian>()?;
             let tile = MapTileRecordData::new(short);
             // UPDATED: STOP MODIFYING THE TILES THEMSELVES //
             // The following is an overflow-less "short += 0x1000; // 0201c730 ?"
@@ -54,11 +54,118 @@ impl MapTileDataSegment {
             mpbz_vec.push(tile); // Hand it over
             tile_index += 1;
         }
-        Self {
+        Ok(Self {
             tiles: mpbz_vec,
             bottom_trim,
             tile_offset
+        })
+    }
+
+    /// First map row stored in the file, the tiles above it are blank padding
+    pub fn start_row(&self) -> u16 {
+        self.tile_offset
+    }
+
+    /// The tiles vector is padded out to map row 0 on load, so a visual map
+    /// index is also the vector index. None if it is past the trimmed bottom
+    pub fn tile_index(&self, map_index: u32) -> Option<usize> {
+        let index = map_index as usize;
+        if index < self.tiles.len() {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    /// How many times each tile ID is placed, indexed by tile ID
+    ///
+    /// The padding above the start row is counted as tile 0
+    pub fn tile_usage_counts(&self) -> Vec<u32> {
+        // tile_id is 10 bits
+        let mut counts: Vec<u32> = vec![0; 0x400];
+        for tile in &self.tiles {
+            counts[tile.tile_id as usize] += 1;
+        }
+        counts
+    }
+
+    /// None for the padding above the start row and the trimmed area, since
+    /// no tile is stored there, unlike a placed tile 0
+    pub fn get_stored_tile(&self, map_index: u32, layer_width: u16) -> Option<MapTileRecordData> {
+        let row = map_index / (layer_width.max(1) as u32);
+        if row < self.tile_offset as u32 {
+            return None;
+        }
+        self.tile_index(map_index).map(|index| self.tiles[index])
+    }
+
+    /// Blank for anything in the trimmed area
+    pub fn get_tile(&self, map_index: u32) -> MapTileRecordData {
+        self.tile_index(map_index).map_or(MapTileRecordData::new(0x0000), |index| self.tiles[index])
+    }
+
+    /// Map indexes of the tiles touching `start` (not diagonally) that are the
+    /// same as it, including start. The trimmed area reads as blank, like get_tile
+    pub fn matching_region(&self, start: u32, layer_width: u16, layer_height: u16) -> Vec<u32> {
+        let (width, height) = (layer_width as u32, layer_height as u32);
+        if start >= width * height {
+            return Vec::new();
         }
+        let target = self.get_tile(start);
+        let mut visited: Vec<bool> = vec![false; (width * height) as usize];
+        let mut to_check: Vec<u32> = vec![start];
+        let mut region: Vec<u32> = Vec::new();
+        visited[start as usize] = true;
+        while let Some(map_index) = to_check.pop() {
+            if self.get_tile(map_index) != target {
+                continue;
+            }
+            region.push(map_index);
+            let (x, y) = (map_index % width, map_index / width);
+            let mut neighbors: Vec<u32> = Vec::new();
+            if x > 0 { neighbors.push(map_index - 1); }
+            if x + 1 < width { neighbors.push(map_index + 1); }
+            if y > 0 { neighbors.push(map_index - width); }
+            if y + 1 < height { neighbors.push(map_index + width); }
+            for neighbor in neighbors {
+                if !visited[neighbor as usize] {
+                    visited[neighbor as usize] = true;
+                    to_check.push(neighbor);
+                }
+            }
+        }
+        region.sort();
+        region
+    }
+
+    /// Map indexes of every tile with the same graphics as `tile`, flipped or not
+    pub fn indexes_with_tile(&self, tile: MapTileRecordData, layer_width: u16, layer_height: u16) -> Vec<u32> {
+        (0..(layer_width as u32 * layer_height as u32)).filter(|map_index| {
+            let other = self.get_tile(*map_index);
+            other.tile_id == tile.tile_id && other.palette_id == tile.palette_id
+        }).collect()
+    }
+
+    /// Like tile_index, but makes the tile part of the saved data first
+    ///
+    /// Editing above the start row moves the start up, otherwise the padding
+    /// would be skipped when compiling. Editing below the end adds empty rows
+    pub fn editable_index(&mut self, map_index: u32, layer_width: u16) -> usize {
+        let row = (map_index / (layer_width.max(1) as u32)) as u16;
+        if row < self.tile_offset {
+            log_write(format!("Moving MPBZ start row from 0x{:X} to 0x{:X}",self.tile_offset,row), LogLevel::Debug);
+            self.tile_offset = row;
+        }
+        let index = map_index as usize;
+        if index >= self.tiles.len() {
+            let width = layer_width.max(1) as usize;
+            let old_rows = self.tiles.len().div_ceil(width);
+            let new_rows = (index / width) + 1;
+            self.tiles.resize(new_rows * width, MapTileRecordData::new(0x0000));
+            self.bottom_trim = self.bottom_trim.saturating_sub((new_rows - old_rows) as u16);
+            log_write(format!("Extended MPBZ to 0x{:X} rows",new_rows), LogLevel::Debug);
+        }
+        index
     }
 
     #[allow(dead_code)]
@@ -91,9 +198,87 @@ impl MapTileDataSegment {
         }
     }
 
+    /// Moves every tile by dx/dy tiles, returns how many non-blank tiles went off the edge
+    ///
+    /// The stored rows move with the tiles, so the padding stays the same size
+    pub fn shift(&mut self, dx: i32, dy: i32, width: u16, height: u16) -> usize {
+        let (width, height) = (width as i32, height as i32);
+        let stored_start = self.tile_offset as i32;
+        let stored_end = height - self.bottom_trim as i32;
+        let mut shifted: Vec<MapTileRecordData> = vec![MapTileRecordData::new(0x0000); (width * height).max(0) as usize];
+        let mut lost: usize = 0;
+        for (index, tile) in self.tiles.iter().enumerate() {
+            if tile.to_short() == 0x0000 {
+                continue;
+            }
+            let (x, y) = (index as i32 % width + dx, index as i32 / width + dy);
+            if x < 0 || y < 0 || x >= width || y >= height {
+                lost += 1;
+                continue;
+            }
+            shifted[(y * width + x) as usize] = *tile;
+        }
+        let new_start = (stored_start + dy).clamp(0, height);
+        let new_end = (stored_end + dy).clamp(new_start, height);
+        shifted.truncate((new_end * width) as usize);
+        self.tiles = shifted;
+        self.tile_offset = new_start as u16;
+        self.bottom_trim = (height - new_end) as u16;
+        lost
+    }
+
+    /// Tiles now go all the way down, so nothing is trimmed anymore
     pub fn change_height(&mut self, new_height: u16, width: u16) {
         let new_len = (new_height as u32) * (width as u32);
         self.tiles.resize(new_len as usize, MapTileRecordData::new(0x0000));
+        self.bottom_trim = 0;
+        self.tile_offset = self.tile_offset.min(new_height);
+    }
+
+    /// Rows above the start are not saved, so anything moved above it is blanked
+    /// to match what will be reloaded
+    pub fn set_start_row(&mut self, row: u16, width: u16) {
+        let width = width.max(1) as usize;
+        let stored_rows = self.tiles.len().div_ceil(width) as u16;
+        let row = row.min(stored_rows);
+        if row > self.tile_offset {
+            let start = self.tile_offset as usize * width;
+            let end = (row as usize * width).min(self.tiles.len());
+            for tile in &mut self.tiles[start..end] {
+                *tile = MapTileRecordData::new(0x0000);
+            }
+        }
+        log_write(format!("Set MPBZ start row to 0x{:X}",row), LogLevel::Debug);
+        self.tile_offset = row;
+    }
+
+    /// Cuts or restores rows at the bottom, which can't go past the start row
+    pub fn set_bottom_trim(&mut self, trim: u16, width: u16, height: u16) {
+        let trim = trim.min(height.saturating_sub(self.tile_offset));
+        let new_len = (height - trim) as usize * width as usize;
+        self.tiles.resize(new_len, MapTileRecordData::new(0x0000));
+        log_write(format!("Set MPBZ bottom trim to 0x{:X}",trim), LogLevel::Debug);
+        self.bottom_trim = trim;
+    }
+
+    /// What the tiles vector should hold for this INFO size, trimmed rows aren't in it
+    pub fn expected_len(&self, width: u16, height: u16) -> usize {
+        let stored_rows = height.saturating_sub(self.bottom_trim) as usize;
+        stored_rows * width as usize
+    }
+
+    /// Pads with blank tiles or cuts off the end to match expected_len
+    pub fn repair_length(&mut self, width: u16, height: u16) {
+        let expected = self.expected_len(width, height);
+        log_write(format!("Repairing MPBZ length from 0x{:X} to 0x{:X}",self.tiles.len(),expected), LogLevel::Log);
+        self.tiles.resize(expected, MapTileRecordData::new(0x0000));
+    }
+
+    /// Blank tiles for the whole layer, with no offset or trim
+    pub fn clear(&mut self, width: u16, height: u16) {
+        self.tiles = vec![MapTileRecordData::new(0x0000); width as usize * height as usize];
+        self.tile_offset = 0;
+        self.bottom_trim = 0;
     }
 }
 
@@ -138,3 +323,144 @@ impl ScenSegment for MapTileDataSegment {
         String::from("MPBZ")
     }
 }
+
+#[cfg(test)]
+mod tests_mpbz {
+    use super::*;
+
+    fn info_with_width(layer_width: u16) -> ScenInfoData {
+        ScenInfoData { layer_width, ..Default::default() }
+    }
+
+    /// 4 wide, starts at row 2, 2 rows of tiles
+    fn offset_mpbz_bytes() -> Vec<u8> {
+        let mut bytes: Vec<u8> = vec![0xff, 0xff, 0x02, 0x00, 0x00, 0x00];
+        for tile in 1..=8_u16 {
+            bytes.extend_from_slice(&tile.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_offset_indexes() {
+        let mpbz = MapTileDataSegment::from_decomped_vec(&offset_mpbz_bytes(), 4).expect("Valid MPBZ");
+        assert_eq!(mpbz.start_row(),2);
+        assert_eq!(mpbz.tiles.len(),16);
+        // Row 2 column 0 is the first stored tile
+        assert_eq!(mpbz.tile_index(8),Some(8));
+        assert_eq!(mpbz.get_tile(8).to_short(),1);
+        assert_eq!(mpbz.get_tile(0).to_short(),0);
+        assert_eq!(mpbz.tile_index(16),None);
+        assert_eq!(mpbz.compile(Some(&info_with_width(4))),offset_mpbz_bytes());
+    }
+
+    #[test]
+    fn test_stored_tile() {
+        let mpbz = MapTileDataSegment::from_decomped_vec(&offset_mpbz_bytes(), 4).expect("Valid MPBZ");
+        // Padding rows are not stored, even though they read as blank
+        assert_eq!(mpbz.get_tile(0).to_short(),0);
+        assert!(mpbz.get_stored_tile(0, 4).is_none());
+        assert_eq!(mpbz.get_stored_tile(8, 4).map(|t| t.to_short()),Some(1));
+        assert!(mpbz.get_stored_tile(16, 4).is_none());
+    }
+
+    #[test]
+    fn test_tile_usage_counts() {
+        let mpbz = MapTileDataSegment::from_decomped_vec(&offset_mpbz_bytes(), 4).expect("Valid MPBZ");
+        let counts = mpbz.tile_usage_counts();
+        assert_eq!(counts.len(),0x400);
+        // 8 padding tiles
+        assert_eq!(counts[0],8);
+        assert!((1..=8).all(|tile_id| counts[tile_id] == 1));
+        assert_eq!(counts[9],0);
+    }
+
+    #[test]
+    fn test_shift() {
+        let mut mpbz = MapTileDataSegment::from_decomped_vec(&offset_mpbz_bytes(), 4).expect("Valid MPBZ");
+        // 4x4 map, tiles 1-8 on rows 2 and 3
+        let lost = mpbz.shift(1, -1, 4, 4);
+        assert_eq!(lost,2); // Right column
+        assert_eq!(mpbz.start_row(),1);
+        assert_eq!(mpbz.get_tile(5).to_short(),1);
+        assert_eq!(mpbz.get_tile(4).to_short(),0);
+        assert_eq!(mpbz.get_tile(13).to_short(),0);
+        let compiled = mpbz.compile(Some(&info_with_width(4)));
+        let reloaded = MapTileDataSegment::from_decomped_vec(&compiled, 4).expect("Valid MPBZ");
+        assert_eq!(reloaded.get_tile(10).to_short(),6);
+        // Everything falls off the bottom
+        assert_eq!(mpbz.shift(0, 4, 4, 4),6);
+        assert!(mpbz.tiles.iter().all(|tile| tile.to_short() == 0));
+    }
+
+    #[test]
+    fn test_edit_above_offset() {
+        let mut mpbz = MapTileDataSegment::from_decomped_vec(&offset_mpbz_bytes(), 4).expect("Valid MPBZ");
+        let index = mpbz.editable_index(5, 4);
+        assert_eq!(index,5);
+        mpbz.tiles[index] = MapTileRecordData::new(0x42);
+        assert_eq!(mpbz.start_row(),1);
+        // Reload keeps the edit in the same place
+        let compiled = mpbz.compile(Some(&info_with_width(4)));
+        let reloaded = MapTileDataSegment::from_decomped_vec(&compiled, 4).expect("Valid MPBZ");
+        assert_eq!(reloaded.start_row(),1);
+        assert_eq!(reloaded.get_tile(5).to_short(),0x42);
+        assert_eq!(reloaded.get_tile(8).to_short(),1);
+        assert_eq!(reloaded.tiles.len(),16);
+    }
+
+    #[test]
+    fn test_offsets_round_trip() {
+        // 4x6 map, so there are 2 trimmed rows under the stored ones
+        let mut mpbz = MapTileDataSegment::from_decomped_vec(&offset_mpbz_bytes(), 4).expect("Valid MPBZ");
+        mpbz.set_bottom_trim(2, 4, 6);
+        assert_eq!(mpbz.tiles.len(),16);
+        mpbz.tiles[10] = MapTileRecordData::new(0x42);
+        let compiled = mpbz.compile(Some(&info_with_width(4)));
+        assert_eq!(&compiled[0..6],&[0xff, 0xff, 0x02, 0x00, 0x02, 0x00]);
+        let reloaded = MapTileDataSegment::from_decomped_vec(&compiled, 4).expect("Valid MPBZ");
+        assert_eq!(reloaded,mpbz);
+        // Moving the start down drops the rows above it
+        mpbz.set_start_row(3, 4);
+        assert_eq!(mpbz.get_tile(10).to_short(),0);
+        let reloaded = MapTileDataSegment::from_decomped_vec(&mpbz.compile(Some(&info_with_width(4))), 4).expect("Valid MPBZ");
+        assert_eq!(reloaded,mpbz);
+        // Resizing fills to the bottom
+        mpbz.change_height(8, 4);
+        assert_eq!((mpbz.start_row(),mpbz.bottom_trim),(3,0));
+    }
+
+    #[test]
+    fn test_repair_length() {
+        let mut mpbz = MapTileDataSegment::from_decomped_vec(&offset_mpbz_bytes(), 4).expect("Valid MPBZ");
+        assert_eq!(mpbz.expected_len(4, 4),16);
+        mpbz.tiles.truncate(5);
+        mpbz.repair_length(4, 4);
+        assert_eq!(mpbz.tiles.len(),16);
+        assert_eq!(mpbz.get_tile(15).to_short(),0);
+        mpbz.bottom_trim = 1;
+        mpbz.repair_length(4, 4);
+        assert_eq!(mpbz.tiles.len(),12);
+    }
+
+    #[test]
+    fn test_edit_below_end() {
+        let mut mpbz = MapTileDataSegment::from_decomped_vec(&offset_mpbz_bytes(), 4).expect("Valid MPBZ");
+        let index = mpbz.editable_index(21, 4);
+        assert_eq!(index,21);
+        assert_eq!(mpbz.tiles.len(),24);
+        assert_eq!(mpbz.start_row(),2);
+    }
+
+    #[test]
+    fn test_matching_region() {
+        let mut mpbz = MapTileDataSegment::from_decomped_vec(&offset_mpbz_bytes(), 4).expect("Valid MPBZ");
+        // Blank padding rows are one region, stopping at the stored tiles
+        assert_eq!(mpbz.matching_region(0, 4, 4),(0..8).collect::<Vec<u32>>());
+        mpbz.tiles[9] = MapTileRecordData::new(0x0401); // Tile 1 flipped
+        mpbz.tiles[12] = MapTileRecordData::new(0x0001);
+        assert_eq!(mpbz.matching_region(8, 4, 4),vec![8, 12]);
+        assert_eq!(mpbz.indexes_with_tile(MapTileRecordData::new(0x0001), 4, 4),vec![8, 9, 12]);
+        assert!(mpbz.matching_region(16, 4, 4).is_empty());
+    }
+}
@@ -1,3 +1,5 @@
+use std::io;
+
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
 use crate::{engine::compression::segment_wrap, utils::{log_write, LogLevel}};
@@ -12,18 +14,18 @@ pub struct ScrollData {
 }
 
 impl ScrollData {
-    pub fn new<T: ReadBytesExt>(rdr: &mut T) -> Self {
+    pub fn new<T: ReadBytesExt>(rdr: &mut T) -> io::Result<Self> {
         let left_vel = match rdr.read_i32::<LittleEndian>() {
             Err(error) => {
                 log_write(format!("Could not read Left Velocity: '{error}'"), LogLevel::Error);
-                return ScrollData::default();
+                return Ok(ScrollData::default());
             }
             Ok(v) => v,
         };
-        Self {
+        Ok(Self {
             left_velocity: left_vel,
-            up_velocity: rdr.read_i32::<LittleEndian>().expect("Up Velocity SCRL")
-        }
+            up_velocity: rdr.read_i32::<LittleEndian>()?
+        })
     }
 }
 
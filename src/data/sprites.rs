@@ -1,12 +1,13 @@
-use std::{fmt, io::{Cursor, Read, Write}};
+use std::{collections::BTreeMap, fmt, io::{self, Cursor, Read, Write}};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use egui::{emath, pos2, Color32, ColorImage, Pos2, Rect, TextureHandle, Vec2};
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::{engine::{compression::segment_wrap, displayengine::DisplayEngine}, utils::{self, color_image_from_pal, log_write, pixel_byte_array_to_nibbles, LogLevel}};
+use crate::{engine::{compression::segment_wrap, displayengine::DisplayEngine}, utils::{self, color_image_from_pal, log_write, new_uuid, pixel_byte_array_to_nibbles, LogLevel}};
 
-use super::{segments::DataSegment, types::Palette, TopLevelSegment};
+use super::{limits::{FormatLimitIssue, MAX_SPRITE_SETTINGS}, segments::DataSegment, types::Palette, TopLevelSegment};
 
 /// Info on sprites to draw on the map, does not contain render data
 #[derive(Clone,Debug,PartialEq)]
@@ -36,53 +37,115 @@ impl fmt::Display for LevelSprite {
     }
 }
 impl LevelSprite {
-    pub fn from_cursor<T: ReadBytesExt>(rdr: &mut T)  -> Self {
+    pub fn from_cursor<T: ReadBytesExt>(rdr: &mut T)  -> io::Result<Self> {
         let mut spr = LevelSprite {
-            object_id: rdr.read_u16::<LittleEndian>().unwrap(),
-            settings_length: rdr.read_u16::<LittleEndian>().unwrap(),
-            x_position: rdr.read_u16::<LittleEndian>().unwrap(),
-            y_position: rdr.read_u16::<LittleEndian>().unwrap(),
-            uuid: Uuid::new_v4(),
+            object_id: rdr.read_u16::<LittleEndian>()?,
+            settings_length: rdr.read_u16::<LittleEndian>()?,
+            x_position: rdr.read_u16::<LittleEndian>()?,
+            y_position: rdr.read_u16::<LittleEndian>()?,
+            uuid: new_uuid(),
             ..Default::default()
         };
         let mut setting_index: u16 = 0;
         while setting_index < spr.settings_length {
-            let setting_byte = rdr.read_u8().unwrap();
+            let setting_byte = rdr.read_u8()?;
             spr.settings.push(setting_byte);
             setting_index += 1;
         }
-        spr
+        Ok(spr)
     }
     #[allow(dead_code)] // only for debug, so may not be used
-    pub fn from_vec(vec: &mut Vec<u8>) -> Self {
+    pub fn from_vec(vec: &mut Vec<u8>) -> io::Result<Self> {
         let mut rdr: Cursor<&Vec<u8>> = Cursor::new(vec);
         LevelSprite::from_cursor(&mut rdr)
     }
     pub fn compile(&self) -> Vec<u8> {
         let mut comp: Vec<u8> = vec![];
+        // Saving checks limit_issues first, so this is only a last resort
+        let settings = &self.settings[..self.settings.len().min(MAX_SPRITE_SETTINGS)];
+        if settings.len() < self.settings.len() {
+            log_write(format!("Cutting off Sprite 0x{:X} settings past the limit: {}",self.object_id,self.settings.len()), LogLevel::Error);
+        }
         // Maybe get rid of the warning for no applications someday
         let _ = comp.write_u16::<LittleEndian>(self.object_id);
         let _ = comp.write_u16::<LittleEndian>(self.settings_length);
         let _ = comp.write_u16::<LittleEndian>(self.x_position);
         let _ = comp.write_u16::<LittleEndian>(self.y_position);
-        let _ = comp.write(self.settings.as_slice());
+        let _ = comp.write(settings);
         comp
     }
+    pub fn limit_issues(&self) -> Vec<FormatLimitIssue> {
+        let (object_id, x, y) = (self.object_id, self.x_position, self.y_position);
+        let mut issues: Vec<FormatLimitIssue> = Vec::new();
+        if self.settings.len() > MAX_SPRITE_SETTINGS {
+            issues.push(FormatLimitIssue::SpriteSettingsTooLong { object_id, x, y, len: self.settings.len() });
+        } else if self.settings_length as usize != self.settings.len() {
+            issues.push(FormatLimitIssue::SpriteSettingsLengthMismatch { object_id, x, y,
+                declared: self.settings_length, actual: self.settings.len() });
+        }
+        issues
+    }
     pub fn new(id: u16, x_pos: u16, y_pos: u16, settings: Vec<u8>) -> Self {
         LevelSprite {
             object_id: id, settings_length: settings.len() as u16,
             x_position: x_pos, y_position: y_pos,
-            settings, uuid: Uuid::new_v4()
+            settings, uuid: new_uuid()
         }
     }
 }
 
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum SpriteIssueKind {
+    /// Should never happen, selecting or deleting one affects the other
+    DuplicateUuid,
+    /// Same Sprite at the same position, usually placed twice by accident
+    Stacked
+}
+
+/// A group of Sprites found by find_sprite_issues
+#[derive(Clone,PartialEq,Debug)]
+pub struct SpriteIssue {
+    pub kind: SpriteIssueKind,
+    pub object_id: u16,
+    pub x_position: u16,
+    pub y_position: u16,
+    pub uuids: Vec<Uuid>
+}
+
+/// Stacking different Sprites is common and intentional, so only identical IDs count
+pub fn find_sprite_issues(sprites: &[LevelSprite]) -> Vec<SpriteIssue> {
+    let mut issues: Vec<SpriteIssue> = Vec::new();
+    let mut by_uuid: BTreeMap<Uuid, Vec<&LevelSprite>> = BTreeMap::new();
+    let mut by_position: BTreeMap<(u16, u16, u16), Vec<&LevelSprite>> = BTreeMap::new();
+    for sprite in sprites {
+        by_uuid.entry(sprite.uuid).or_default().push(sprite);
+        by_position.entry((sprite.y_position, sprite.x_position, sprite.object_id)).or_default().push(sprite);
+    }
+    for group in by_uuid.values().filter(|group| group.len() > 1) {
+        issues.push(SpriteIssue {
+            kind: SpriteIssueKind::DuplicateUuid,
+            object_id: group[0].object_id,
+            x_position: group[0].x_position, y_position: group[0].y_position,
+            uuids: vec![group[0].uuid]
+        });
+    }
+    for group in by_position.values().filter(|group| group.len() > 1) {
+        issues.push(SpriteIssue {
+            kind: SpriteIssueKind::Stacked,
+            object_id: group[0].object_id,
+            x_position: group[0].x_position, y_position: group[0].y_position,
+            uuids: group.iter().map(|spr| spr.uuid).collect()
+        });
+    }
+    issues
+}
+
 #[derive(Clone,PartialEq,Debug,Default)]
 pub struct LevelSpriteSet {
     pub sprites: Vec<LevelSprite>
 }
 impl LevelSpriteSet {
-    pub fn new(byte_data: &[u8]) -> Self {
+    pub fn new(byte_data: &[u8]) -> io::Result<Self> {
         let mut rdr = Cursor::new(byte_data);
         let seg_end: usize = byte_data.len();
         let mut seg: LevelSpriteSet = LevelSpriteSet::default();
@@ -92,10 +155,55 @@ impl LevelSpriteSet {
                 log_write("Overflow when reading SETD", LogLevel::Error);
                 break;
             }
-            let sprite: LevelSprite = LevelSprite::from_cursor(&mut rdr);
+            let sprite: LevelSprite = LevelSprite::from_cursor(&mut rdr)?;
             seg.sprites.push(sprite);
         }
-        seg
+        Ok(seg)
+    }
+
+    /// Moves every Sprite by dx/dy tiles, removing the ones that go off the map
+    pub fn shift(&mut self, dx: i32, dy: i32, width: u16, height: u16) -> usize {
+        let initial_len = self.sprites.len();
+        self.sprites.retain_mut(|spr| {
+            let (x, y) = (spr.x_position as i32 + dx, spr.y_position as i32 + dy);
+            if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+                return false;
+            }
+            (spr.x_position, spr.y_position) = (x as u16, y as u16);
+            true
+        });
+        initial_len - self.sprites.len()
+    }
+
+    /// Moves the listed Sprites together by dx/dy tiles. The step is cut short on
+    /// an axis rather than push any of them below 0, so the group keeps its shape.
+    /// Returns how many moved
+    pub fn move_sprites(&mut self, sprite_uuids: &[Uuid], dx: i32, dy: i32) -> usize {
+        let (min_x, min_y) = self.sprites.iter()
+            .filter(|spr| sprite_uuids.contains(&spr.uuid))
+            .fold((i32::MAX, i32::MAX), |(x, y), spr| (x.min(spr.x_position as i32), y.min(spr.y_position as i32)));
+        if min_x == i32::MAX {
+            return 0; // None of them are here
+        }
+        let dx = dx.max(-min_x);
+        let dy = dy.max(-min_y);
+        if dx == 0 && dy == 0 {
+            return 0;
+        }
+        let mut moved: usize = 0;
+        for spr in self.sprites.iter_mut().filter(|spr| sprite_uuids.contains(&spr.uuid)) {
+            spr.x_position = (spr.x_position as i32 + dx).min(u16::MAX as i32) as u16;
+            spr.y_position = (spr.y_position as i32 + dy).min(u16::MAX as i32) as u16;
+            moved += 1;
+        }
+        moved
+    }
+
+    /// Removes every listed Sprite in one pass, returns how many were found
+    pub fn delete_sprites(&mut self, sprite_uuids: &[Uuid]) -> usize {
+        let initial_len = self.sprites.len();
+        self.sprites.retain(|spr| !sprite_uuids.contains(&spr.uuid));
+        initial_len - self.sprites.len()
     }
 
     pub fn trim(&mut self, width: u16, height: u16) -> usize {
@@ -133,7 +241,10 @@ pub struct SpriteMetadata {
     pub sprite_id: u16,
     pub name: String,
     pub description: String,
-    pub default_settings_len: u16
+    pub default_settings_len: u16,
+    /// Pixels to move the graphics from the tile position, to match the game
+    pub render_offset_x: i16,
+    pub render_offset_y: i16
 }
 impl Default for SpriteMetadata {
     fn default() -> Self {
@@ -141,10 +252,36 @@ impl Default for SpriteMetadata {
             sprite_id: 0xfffe,
             name: "ERROR".to_owned(),
             description: "Error".to_owned(),
-            default_settings_len: 0xfffe
+            default_settings_len: 0xfffe,
+            render_offset_x: 0,
+            render_offset_y: 0
         }
     }
 }
+impl SpriteMetadata {
+    /// Untested Sprites, placing them can crash the game
+    pub fn is_unknown(&self) -> bool {
+        // Or a stub added for an ID missing from sprites.csv
+        self.name.eq_ignore_ascii_case("unknown") || self.name.starts_with("Unknown 0x")
+    }
+
+    /// Stands in for a Sprite missing from sprites.csv, using its own settings length
+    pub fn inferred(sprite: &LevelSprite) -> Self {
+        Self {
+            sprite_id: sprite.object_id,
+            name: format!("Unknown 0x{:X}",sprite.object_id),
+            description: format!("Not in sprites.csv, settings length 0x{:X} inferred from this Sprite",sprite.settings_length),
+            default_settings_len: sprite.settings_length,
+            render_offset_x: 0,
+            render_offset_y: 0
+        }
+    }
+
+    pub fn render_offset(&self) -> Vec2 {
+        Vec2::new(self.render_offset_x as f32, self.render_offset_y as f32)
+    }
+}
+
 impl fmt::Display for SpriteMetadata {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f,"SpriteMetadata [ sprite_id=0x{:X}, name='{}', description='{}', settings_len=0x{:X} ]",
@@ -152,6 +289,120 @@ impl fmt::Display for SpriteMetadata {
     }
 }
 
+/// Known-good settings for a Sprite, from assets/sprite_presets.json
+#[derive(Serialize,Deserialize,Clone,Debug,PartialEq)]
+pub struct SpritePreset {
+    pub object_id: u16,
+    pub label: String,
+    pub settings: Vec<u8>
+}
+
+#[derive(Serialize,Deserialize,Clone,Debug,Default)]
+pub struct SpritePresets {
+    pub presets: Vec<SpritePreset>
+}
+impl SpritePresets {
+    pub fn for_sprite(&self, object_id: u16) -> Vec<&SpritePreset> {
+        self.presets.iter().filter(|preset| preset.object_id == object_id).collect()
+    }
+}
+
+#[derive(Serialize,Deserialize,Clone,Copy,Debug,PartialEq,Eq)]
+pub enum SpriteRangeShape {
+    /// Around the Sprite, size is the radius
+    Circle,
+    /// Sprite-wide strip out from the Sprite, size is the length
+    Line
+}
+
+#[derive(Serialize,Deserialize,Clone,Copy,Debug,PartialEq,Eq)]
+pub enum RangeDirection {
+    Down,
+    Up,
+    Right,
+    Left
+}
+
+fn default_size_len() -> usize {
+    1
+}
+
+/// How far a Sprite reaches, worked out from its settings. From assets/sprite_ranges.json
+#[derive(Serialize,Deserialize,Clone,Debug,PartialEq)]
+pub struct SpriteRange {
+    pub object_id: u16,
+    pub label: String,
+    pub shape: SpriteRangeShape,
+    /// Settings byte the size starts at
+    pub size_byte: usize,
+    /// 1 or 2 bytes, little endian
+    #[serde(default = "default_size_len")]
+    pub size_len: usize,
+    /// Pixels per unit of size
+    pub unit_px: f32,
+    /// Some Sprites treat a size of 0 as a default
+    #[serde(default)]
+    pub zero_means: Option<u32>,
+    /// Settings byte that picks from `directions`, otherwise the first is used
+    #[serde(default)]
+    pub direction_byte: Option<usize>,
+    #[serde(default)]
+    pub directions: Vec<RangeDirection>
+}
+impl SpriteRange {
+    /// Size in pixels, None if the settings are too short
+    pub fn size_px(&self, settings: &[u8]) -> Option<f32> {
+        let bytes = settings.get(self.size_byte..self.size_byte + self.size_len)?;
+        let mut size = bytes.iter().rev().fold(0u32, |acc, b| (acc << 8) | *b as u32);
+        if size == 0 {
+            size = self.zero_means.unwrap_or(0);
+        }
+        Some(size as f32 * self.unit_px)
+    }
+
+    pub fn direction(&self, settings: &[u8]) -> Option<RangeDirection> {
+        let index = match self.direction_byte {
+            Some(byte) => *settings.get(byte)? as usize,
+            None => 0
+        };
+        self.directions.get(index).copied()
+    }
+
+    /// The area covered when the Sprite is drawn in `sprite_rect`
+    pub fn area(&self, sprite: &LevelSprite, sprite_rect: Rect) -> Option<SpriteRangeArea> {
+        let size = self.size_px(&sprite.settings)?;
+        match self.shape {
+            SpriteRangeShape::Circle => Some(SpriteRangeArea::Circle(sprite_rect.center(), size)),
+            SpriteRangeShape::Line => {
+                let rect = match self.direction(&sprite.settings)? {
+                    RangeDirection::Down => Rect::from_min_max(sprite_rect.left_top(), sprite_rect.right_top() + Vec2::new(0.0, size)),
+                    RangeDirection::Up => Rect::from_min_max(sprite_rect.left_bottom() - Vec2::new(0.0, size), sprite_rect.right_bottom()),
+                    RangeDirection::Right => Rect::from_min_max(sprite_rect.left_top(), sprite_rect.left_bottom() + Vec2::new(size, 0.0)),
+                    RangeDirection::Left => Rect::from_min_max(sprite_rect.right_top() - Vec2::new(size, 0.0), sprite_rect.right_bottom())
+                };
+                Some(SpriteRangeArea::Rect(rect))
+            }
+        }
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum SpriteRangeArea {
+    /// Center and radius
+    Circle(Pos2, f32),
+    Rect(Rect)
+}
+
+#[derive(Serialize,Deserialize,Clone,Debug,Default)]
+pub struct SpriteRanges {
+    pub ranges: Vec<SpriteRange>
+}
+impl SpriteRanges {
+    pub fn for_sprite(&self, object_id: u16) -> Option<&SpriteRange> {
+        self.ranges.iter().find(|range| range.object_id == object_id)
+    }
+}
+
 fn get_graphics_segment(de: &mut DisplayEngine, archive_name_local_ext: String, segment_index: usize) -> SpriteGraphicsSegment {
     let arch_graphics = de.get_render_archive(&archive_name_local_ext);
     let graphics_segment = &arch_graphics.segments[segment_index];
@@ -416,3 +667,83 @@ fn get_sprite_dims_from_flag_value(val: u16) -> Pos2 {
         }
     }
 }
+
+#[cfg(test)]
+mod tests_sprites {
+    use super::*;
+
+    #[test]
+    fn test_sprite_range_area() {
+        let pipe = SpriteRange {
+            object_id: 0x23, label: String::from("Pipe length"), shape: SpriteRangeShape::Line,
+            size_byte: 2, size_len: 2, unit_px: 16.0, zero_means: Some(1),
+            direction_byte: Some(0), directions: vec![RangeDirection::Down, RangeDirection::Up]
+        };
+        let sprite_rect = Rect::from_min_size(Pos2::new(32.0, 32.0), Vec2::new(16.0, 16.0));
+        let up = LevelSprite { object_id: 0x23, settings_length: 4, settings: vec![1, 0, 0x03, 0x01], ..Default::default() };
+        assert_eq!(pipe.size_px(&up.settings),Some(0x103 as f32 * 16.0));
+        let short_up = LevelSprite { settings: vec![1, 0, 2, 0], ..up.clone() };
+        assert_eq!(pipe.area(&short_up, sprite_rect),Some(SpriteRangeArea::Rect(Rect::from_min_max(Pos2::new(32.0, 16.0), Pos2::new(48.0, 48.0)))));
+        // Zero falls back, unknown directions and short settings draw nothing
+        assert_eq!(pipe.size_px(&[0, 0, 0, 0]),Some(16.0));
+        assert!(pipe.area(&LevelSprite { settings: vec![5, 0, 2, 0], ..up.clone() }, sprite_rect).is_none());
+        assert!(pipe.area(&LevelSprite { settings: vec![1, 0], ..up }, sprite_rect).is_none());
+    }
+
+    #[test]
+    fn test_find_sprite_issues() {
+        let sprite = |object_id: u16, x_position: u16| LevelSprite {
+            object_id, x_position, y_position: 0x20, uuid: Uuid::new_v4(), ..Default::default()
+        };
+        let mut sprites = vec![sprite(0x36, 0x10), sprite(0x36, 0x10), sprite(0x37, 0x10), sprite(0x36, 0x12)];
+        let issues = find_sprite_issues(&sprites);
+        assert_eq!(issues.len(),1);
+        assert_eq!(issues[0].kind,SpriteIssueKind::Stacked);
+        assert_eq!(issues[0].uuids,vec![sprites[0].uuid,sprites[1].uuid]);
+        sprites[3].uuid = sprites[2].uuid;
+        let issues = find_sprite_issues(&sprites);
+        assert_eq!(issues.len(),2);
+        assert_eq!(issues[0].kind,SpriteIssueKind::DuplicateUuid);
+        assert_eq!(issues[0].uuids,vec![sprites[2].uuid]);
+    }
+
+    #[test]
+    fn test_move_sprites() {
+        let sprite = |x_position: u16, y_position: u16| LevelSprite { x_position, y_position, uuid: Uuid::new_v4(), ..Default::default() };
+        let mut set = LevelSpriteSet { sprites: vec![sprite(1, 5), sprite(4, 4), sprite(9, 2)] };
+        let selected = vec![set.sprites[0].uuid, set.sprites[1].uuid];
+        // Every selected Sprite moves exactly once
+        assert_eq!(set.move_sprites(&selected, 1, -1),2);
+        assert_eq!((set.sprites[0].x_position, set.sprites[0].y_position),(2, 4));
+        assert_eq!((set.sprites[1].x_position, set.sprites[1].y_position),(5, 3));
+        assert_eq!((set.sprites[2].x_position, set.sprites[2].y_position),(9, 2));
+        // Stops at the edge without squashing the group
+        assert_eq!(set.move_sprites(&selected, -3, 0),2);
+        assert_eq!((set.sprites[0].x_position, set.sprites[1].x_position),(0, 3));
+        assert_eq!(set.move_sprites(&selected, -1, 0),0);
+        assert_eq!(set.delete_sprites(&selected),2);
+        assert_eq!(set.sprites.len(),1);
+    }
+
+    #[test]
+    fn test_shift() {
+        let sprite = |x_position: u16, y_position: u16| LevelSprite { x_position, y_position, ..Default::default() };
+        let mut set = LevelSpriteSet { sprites: vec![sprite(0, 0), sprite(4, 4), sprite(9, 2)] };
+        assert_eq!(set.shift(-2, 2, 10, 10),1);
+        assert_eq!(set.sprites.len(),2);
+        assert_eq!((set.sprites[0].x_position, set.sprites[0].y_position),(2, 6));
+        assert_eq!((set.sprites[1].x_position, set.sprites[1].y_position),(7, 4));
+    }
+
+    #[test]
+    fn test_limit_issues() {
+        let mut sprite = LevelSprite::new(0x36, 4, 5, vec![0; MAX_SPRITE_SETTINGS]);
+        assert!(sprite.limit_issues().is_empty());
+        sprite.settings.push(0);
+        assert!(matches!(sprite.limit_issues()[..],[FormatLimitIssue::SpriteSettingsTooLong { len: 0x10000, .. }]));
+        // Header, then the settings cut off at the limit
+        assert_eq!(sprite.compile().len(),8 + MAX_SPRITE_SETTINGS);
+        let mismatched = LevelSprite { settings: vec![1, 2], settings_length: 4, ..Default::default() };
+        assert!(matches!(mismatched.limit_issues()[..],[FormatLimitIssue::SpriteSettingsLengthMismatch { declared: 4, actual: 2, .. }]));
+    }
+}
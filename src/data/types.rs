@@ -1,6 +1,7 @@
-use std::{fmt::{self, Debug}, io::Cursor};
+use std::{cell::Cell, collections::HashMap, fmt::{self, Debug}, io::{self, Cursor}, sync::Arc};
 
 use egui::{Color32, TextureHandle};
+use serde::{Deserialize, Serialize};
 use strum::EnumIter;
 
 use crate::utils::{self, log_write, LogLevel};
@@ -69,21 +70,21 @@ impl Palette {
             _pal_len: pal_len
         }
     }
-    pub fn from_cursor(rdr: &mut Cursor<&[u8]>, pal_len: usize) -> Self {
+    pub fn from_cursor(rdr: &mut Cursor<&[u8]>, pal_len: usize) -> io::Result<Self> {
         let mut cols: [PalColor; 256] = [PalColor::default(); 256];
         let mut i: usize = 0;
         while i < pal_len {
-            let short: u16 = rdr.read_u16::<LittleEndian>().unwrap();
+            let short: u16 = rdr.read_u16::<LittleEndian>()?;
             let color = utils::color_from_u16(&short);
             cols[i].color = color;
             cols[i]._short = short;
             cols[i]._addr = rdr.position() as u32;
             i += 1;
         }
-        Self {
+        Ok(Self {
             colors: cols,
             _pal_len: pal_len
-        }
+        })
     }
 }
 
@@ -166,7 +167,7 @@ impl MapTileRecordData {
 }
 
 #[allow(clippy::upper_case_acronyms)]
-#[derive(Debug, PartialEq, Eq, Clone, Copy, EnumIter)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, EnumIter, Serialize, Deserialize)]
 pub enum CurrentLayer {
     BG1 = 1,
     BG2 = 2,
@@ -178,29 +179,248 @@ pub enum CurrentLayer {
 }
 
 
-pub type TileCache = Vec<Vec<Option<TextureHandle>>>;
+pub const TILE_CACHE_PALETTES: usize = 16;
+pub const TILE_CACHE_TILES: usize = 1024;
+/// Textures kept per BG layer before the least recently drawn are dropped
+pub const DEFAULT_TILE_CACHE_BUDGET: usize = 4096;
 
-pub fn wipe_tile_cache(tc: &mut TileCache) {
-    for subarr in tc {
-        for value in subarr {
-            let none: Option<TextureHandle> = Option::None;
-            *value = none;
+struct CachedTexture {
+    handle: TextureHandle,
+    /// TileCache clock at the last lookup
+    last_drawn: Cell<u64>
+}
+
+struct CachedPixels {
+    pixels: Arc<[u8]>,
+    /// TileCache clock at the last lookup
+    last_used: u64
+}
+
+/// Textures for one BG layer, by palette then tile index
+///
+/// Most tiles are only ever drawn with one palette, so each palette's row is
+/// only allocated once something is drawn with it. Decoded pixels don't depend
+/// on the palette, so those are kept once per tile and shared between palettes.
+/// Past `budget` textures (or decoded tiles), the least recently drawn ones are
+/// dropped. Anything drawn since `begin_frame` is kept even over budget, since
+/// dropping it would only rebuild it again next frame
+pub struct TileCache {
+    textures: Vec<Option<Vec<Option<CachedTexture>>>>,
+    pixels: HashMap<usize,CachedPixels>,
+    /// Ticks on every lookup, lookups only have &self
+    clock: Cell<u64>,
+    /// Clock when the current frame started drawing
+    frame_start: u64,
+    count: usize,
+    budget: usize
+}
+impl Default for TileCache {
+    fn default() -> Self {
+        Self {
+            textures: Vec::new(),
+            pixels: HashMap::new(),
+            clock: Cell::new(0),
+            frame_start: 0,
+            count: 0,
+            budget: DEFAULT_TILE_CACHE_BUDGET
         }
     }
 }
+impl TileCache {
+    pub fn texture_count(&self) -> usize {
+        self.count
+    }
 
-pub fn get_cached_texture(tc: &TileCache, global_palette_index: usize, tile_index: usize) -> Option<&TextureHandle> {
-    if global_palette_index >= 16 {
-        log_write(format!("texture cache: global_palette_index out of bounds: {}",global_palette_index), utils::LogLevel::Error);
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget.max(1);
+        if self.count > self.budget {
+            self.evict_down_to(self.budget);
+        }
+        if self.pixels.len() > self.budget {
+            self.evict_pixels_down_to(self.budget);
+        }
+    }
+
+    /// Call before drawing a frame, what's drawn after this won't be evicted until the next one
+    pub fn begin_frame(&mut self) {
+        self.frame_start = self.clock.get();
+    }
+
+    /// Drops the least recently drawn textures until `target` are left,
+    /// or until only ones drawn this frame are left
+    fn evict_down_to(&mut self, target: usize) {
+        puffin::profile_function!();
+        let ages: Vec<u64> = self.textures.iter().flatten().flatten().flatten()
+            .map(|cached| cached.last_drawn.get()).collect();
+        let Some(cutoff) = lru_cutoff(ages, target, self.frame_start) else {
+            return;
+        };
+        for row in self.textures.iter_mut().flatten() {
+            for slot in row.iter_mut() {
+                if slot.as_ref().is_some_and(|cached| cached.last_drawn.get() <= cutoff) {
+                    *slot = Option::None;
+                    self.count -= 1;
+                }
+            }
+        }
+        log_write(format!("Tile cache over budget, {} textures left",self.count), utils::LogLevel::Debug);
+    }
+
+    /// Same as evict_down_to, for decoded tiles
+    fn evict_pixels_down_to(&mut self, target: usize) {
+        let ages: Vec<u64> = self.pixels.values().map(|cached| cached.last_used).collect();
+        let Some(cutoff) = lru_cutoff(ages, target, self.frame_start) else {
+            return;
+        };
+        self.pixels.retain(|_, cached| cached.last_used > cutoff);
+    }
+}
+
+/// The last clock value to drop so only `target` are left, never past `frame_start`.
+/// Ties can drop a few extra
+fn lru_cutoff(ages: Vec<u64>, target: usize, frame_start: u64) -> Option<u64> {
+    let total = ages.len();
+    let mut evictable: Vec<u64> = ages.into_iter().filter(|age| *age <= frame_start).collect();
+    let to_drop = total.saturating_sub(target).min(evictable.len());
+    if to_drop == 0 {
         return Option::None;
     }
-    if tile_index >= 1024 {
+    let (_, cutoff, _) = evictable.select_nth_unstable(to_drop - 1);
+    Some(*cutoff)
+}
+
+pub fn wipe_tile_cache(tc: &mut TileCache) {
+    tc.textures.clear();
+    tc.pixels.clear();
+    tc.count = 0;
+}
+
+fn tile_cache_in_bounds(global_palette_index: usize, tile_index: usize) -> bool {
+    if global_palette_index >= TILE_CACHE_PALETTES {
+        log_write(format!("texture cache: global_palette_index out of bounds: {}",global_palette_index), utils::LogLevel::Error);
+        return false;
+    }
+    if tile_index >= TILE_CACHE_TILES {
         log_write(format!("texture cache: tile_index out of bounds: {}",tile_index), utils::LogLevel::Error);
+        return false;
+    }
+    true
+}
+
+pub fn get_cached_texture(tc: &TileCache, global_palette_index: usize, tile_index: usize) -> Option<&TextureHandle> {
+    if !tile_cache_in_bounds(global_palette_index, tile_index) {
         return Option::None;
     }
-    tc[global_palette_index][tile_index].as_ref()
+    let cached = tc.textures.get(global_palette_index)?.as_ref()?[tile_index].as_ref()?;
+    tc.clock.set(tc.clock.get() + 1);
+    cached.last_drawn.set(tc.clock.get());
+    Some(&cached.handle)
 }
 
 pub fn set_cached_texture(tc: &mut TileCache, global_palette_index: usize, tile_index: usize, tex: TextureHandle) {
-    tc[global_palette_index][tile_index] = Some(tex);
+    if !tile_cache_in_bounds(global_palette_index, tile_index) {
+        return;
+    }
+    if tc.count >= tc.budget {
+        // Some headroom, so a full cache doesn't scan on every new texture
+        tc.evict_down_to(tc.budget.saturating_sub(tc.budget / 8 + 1));
+    }
+    if tc.textures.len() <= global_palette_index {
+        tc.textures.resize_with(global_palette_index + 1, || Option::None);
+    }
+    tc.clock.set(tc.clock.get() + 1);
+    let row = tc.textures[global_palette_index].get_or_insert_with(|| (0..TILE_CACHE_TILES).map(|_| Option::None).collect());
+    let old = row[tile_index].replace(CachedTexture { handle: tex, last_drawn: Cell::new(tc.clock.get()) });
+    if old.is_none() {
+        tc.count += 1;
+    }
+}
+
+/// Palette indexes for a tile, only decoded the first time any palette needs it
+pub fn get_cached_pixels(tc: &mut TileCache, tile_index: usize, decode: impl FnOnce() -> Vec<u8>) -> Arc<[u8]> {
+    if !tc.pixels.contains_key(&tile_index) && tc.pixels.len() >= tc.budget {
+        tc.evict_pixels_down_to(tc.budget.saturating_sub(tc.budget / 8 + 1));
+    }
+    tc.clock.set(tc.clock.get() + 1);
+    let cached = tc.pixels.entry(tile_index).or_insert_with(|| CachedPixels { pixels: decode().into(), last_used: 0 });
+    cached.last_used = tc.clock.get();
+    cached.pixels.clone()
+}
+
+#[cfg(test)]
+mod tests_types {
+    use super::*;
+
+    #[test]
+    fn test_tile_cache() {
+        let ctx = egui::Context::default();
+        let mut tc = TileCache::default();
+        assert!(get_cached_texture(&tc, 3, 10).is_none());
+        let tex = ctx.load_texture("tile16", egui::ColorImage::new([8, 8], Color32::RED), egui::TextureOptions::NEAREST);
+        set_cached_texture(&mut tc, 3, 10, tex.clone());
+        assert_eq!(get_cached_texture(&tc, 3, 10).map(|t| t.id()),Some(tex.id()));
+        assert!(get_cached_texture(&tc, 2, 10).is_none());
+        assert!(get_cached_texture(&tc, 16, 10).is_none());
+        // Only the palettes up to the one drawn get a slot, and only it gets a row
+        assert_eq!(tc.textures.len(),4);
+        assert_eq!(tc.textures.iter().filter(|row| row.is_some()).count(),1);
+        // Decoded once, then shared by any palette
+        let first = get_cached_pixels(&mut tc, 10, || vec![1; 64]);
+        let second = get_cached_pixels(&mut tc, 10, || panic!("Should not decode again"));
+        assert!(Arc::ptr_eq(&first, &second));
+        wipe_tile_cache(&mut tc);
+        assert!(get_cached_texture(&tc, 3, 10).is_none());
+        assert!(tc.pixels.is_empty());
+    }
+
+    #[test]
+    fn test_tile_cache_budget() {
+        let ctx = egui::Context::default();
+        let mut tc = TileCache::default();
+        tc.set_budget(8);
+        let tex = ctx.load_texture("tile16", egui::ColorImage::new([8, 8], Color32::RED), egui::TextureOptions::NEAREST);
+        tc.begin_frame();
+        for tile_index in 0..8 {
+            set_cached_texture(&mut tc, 0, tile_index, tex.clone());
+        }
+        assert_eq!(tc.texture_count(),8);
+        // Everything was drawn this frame, so going over budget keeps it all
+        set_cached_texture(&mut tc, 0, 8, tex.clone());
+        assert_eq!(tc.texture_count(),9);
+        // Next frame, tile 0 was drawn most recently, so it outlives 1-8
+        tc.begin_frame();
+        assert!(get_cached_texture(&tc, 0, 0).is_some());
+        set_cached_texture(&mut tc, 1, 0, tex.clone());
+        assert!(tc.texture_count() <= 8);
+        assert!(get_cached_texture(&tc, 0, 0).is_some());
+        assert!(get_cached_texture(&tc, 1, 0).is_some());
+        assert!(get_cached_texture(&tc, 0, 1).is_none());
+        // Both were drawn this frame
+        tc.set_budget(1);
+        assert_eq!(tc.texture_count(),2);
+        tc.begin_frame();
+        assert!(get_cached_texture(&tc, 1, 0).is_some());
+        tc.set_budget(1);
+        assert_eq!(tc.texture_count(),1);
+        assert!(get_cached_texture(&tc, 1, 0).is_some());
+    }
+
+    #[test]
+    fn test_tile_pixels_budget() {
+        let mut tc = TileCache::default();
+        tc.set_budget(4);
+        tc.begin_frame();
+        for tile_index in 0..6 {
+            get_cached_pixels(&mut tc, tile_index, || vec![tile_index as u8; 64]);
+        }
+        // All used this frame
+        assert_eq!(tc.pixels.len(),6);
+        tc.begin_frame();
+        get_cached_pixels(&mut tc, 5, || panic!("Still cached"));
+        get_cached_pixels(&mut tc, 6, || vec![6; 64]);
+        assert!(tc.pixels.len() <= 4);
+        assert!(tc.pixels.contains_key(&5));
+        assert!(tc.pixels.contains_key(&6));
+        assert!(!tc.pixels.contains_key(&0));
+    }
 }
@@ -0,0 +1,113 @@
+// Zip of everything a bug report needs, so it can be attached in one go
+//
+// Nothing is sent anywhere. The zip is stored without compression, since
+// the log and map files are small
+
+use std::{fs, io::{Cursor, Write}, path::Path};
+
+use zip::{result::ZipResult, write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::utils::{log_write, LogLevel};
+
+pub const LOG_FILENAME: &str = "stork.log";
+
+/// What was open when the bundle was made
+#[derive(Debug, Clone, Default)]
+pub struct BugBundleInfo {
+    pub editor_version: String,
+    pub game_version: String,
+    /// Game code, maker code and build date, see RomStamp
+    pub rom_stamp: Option<String>,
+    /// e.g. "Course 1-3"
+    pub course_label: Option<String>,
+    pub course_file: Option<String>,
+    pub map_file: Option<String>,
+    pub map_index: Option<usize>
+}
+impl BugBundleInfo {
+    /// Pre-filled issue body, pasted into the GitHub issue
+    pub fn issue_template(&self) -> String {
+        let unknown = String::from("(none loaded)");
+        let mut text = String::new();
+        text.push_str("### What happened\n\n\n");
+        text.push_str("### Steps to reproduce\n\n1. \n\n");
+        text.push_str("### Context\n\n");
+        text.push_str(&format!("- Stork version: {}\n",self.editor_version));
+        text.push_str(&format!("- Game version: {}\n",self.game_version));
+        if let Some(rom_stamp) = &self.rom_stamp {
+            text.push_str(&format!("- ROM: {}\n",rom_stamp));
+        }
+        text.push_str(&format!("- Level: {}\n",self.course_label.as_ref().unwrap_or(&unknown)));
+        text.push_str(&format!("- Course file: {}\n",self.course_file.as_ref().unwrap_or(&unknown)));
+        let map = match (&self.map_file, self.map_index) {
+            (Some(map_file), Some(map_index)) => format!("{} (map {})",map_file,map_index + 1),
+            (Some(map_file), None) => map_file.clone(),
+            _ => unknown.clone()
+        };
+        text.push_str(&format!("- Map: {}\n\n",map));
+        text.push_str("stork.log and the other details are in the attached report bundle\n");
+        text
+    }
+}
+
+/// Builds the zip in memory. `map_file` is the name and bytes of the .mpdz, if allowed
+pub fn build_bug_bundle(info: &BugBundleInfo, log: Option<&[u8]>, map_file: Option<(&str, &[u8])>) -> ZipResult<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    zip.start_file("issue.md", options)?;
+    zip.write_all(info.issue_template().as_bytes())?;
+    match log {
+        Some(log) => {
+            zip.start_file(LOG_FILENAME, options)?;
+            zip.write_all(log)?;
+        }
+        None => log_write(format!("No {} to add to the bug bundle",LOG_FILENAME), LogLevel::Warn)
+    }
+    if let Some((name, bytes)) = map_file {
+        zip.start_file(name, options)?;
+        zip.write_all(bytes)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Reads the log (and map, if given) from disk and writes the zip
+pub fn write_bug_bundle(path: &Path, info: &BugBundleInfo, map_path: Option<&Path>) -> std::io::Result<()> {
+    let log = fs::read(LOG_FILENAME).ok();
+    let map_bytes = match map_path {
+        Some(map_path) => Some(fs::read(map_path)?),
+        None => None
+    };
+    let map_name = map_path.and_then(|p| p.file_name()).map(|name| name.to_string_lossy().to_string());
+    let map_file = map_name.as_deref().zip(map_bytes.as_deref());
+    let zipped = build_bug_bundle(info, log.as_deref(), map_file)?;
+    fs::File::create(path)?.write_all(&zipped)?;
+    log_write(format!("Wrote bug report bundle to '{}'",path.display()), LogLevel::Log);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests_bug_bundle {
+    use super::*;
+    use crate::engine::displayengine::RomStamp;
+
+    #[test]
+    fn test_build_bug_bundle() {
+        let info = BugBundleInfo {
+            editor_version: String::from("0.0.1"),
+            map_file: Some(String::from("1-1_main")),
+            map_index: Some(0),
+            rom_stamp: Some(RomStamp { build_date: String::from("061009.0352"), game_code: Some(String::from("AYWE")), maker_code: None }.to_string()),
+            ..Default::default()
+        };
+        assert!(info.issue_template().contains("- Map: 1-1_main (map 1)"));
+        assert!(info.issue_template().contains("- ROM: AYWE, maker ??, built 061009.0352"));
+        let zipped = build_bug_bundle(&info, Some(b"log line"), Some(("1-1_main.mpdz", &[1, 2, 3]))).expect("Zips");
+        let mut archive = zip::ZipArchive::new(Cursor::new(zipped)).expect("Reads back");
+        assert_eq!(archive.len(),3);
+        assert!(archive.by_name("issue.md").is_ok());
+        let mut log = String::new();
+        std::io::Read::read_to_string(&mut archive.by_name(LOG_FILENAME).expect("Has the log"), &mut log).expect("Stored");
+        assert_eq!(log,"log line");
+        assert_eq!(archive.by_name("1-1_main.mpdz").expect("Has the map").compression(),CompressionMethod::Stored);
+    }
+}
@@ -98,7 +98,7 @@ pub fn segment_wrap(data: Vec<u8>, magic: String) -> Vec<u8> {
         return ret;
     }
     let mut internal_data = data;
-    while internal_data.len() % 4 != 0 {
+    while !internal_data.len().is_multiple_of(4) {
         internal_data.push(0x00);
     }
     let internal_len = internal_data.len() as u32;
@@ -106,7 +106,7 @@ pub fn segment_wrap(data: Vec<u8>, magic: String) -> Vec<u8> {
     ret.append(&mut magic_vec);
     let _ = ret.write_u32::<LittleEndian>(internal_len);
     ret.append(&mut internal_data);
-    while ret.len() % 4 != 0 {
+    while !ret.len().is_multiple_of(4) {
         ret.push(0x00);
     }
     ret
@@ -115,7 +115,7 @@ pub fn segment_wrap(data: Vec<u8>, magic: String) -> Vec<u8> {
 pub fn segment_wrap_u32(data: Vec<u8>, magic: u32) -> Vec<u8> {
     let mut ret: Vec<u8> = vec![];
     let mut internal_data = data.clone();
-    while internal_data.len() % 4 != 0 {
+    while !internal_data.len().is_multiple_of(4) {
         internal_data.push(0x00);
     }
     let internal_len = internal_data.len() as u32;
@@ -1,14 +1,16 @@
 // Consider this the NDS' graphical memory and settings, plus helpers
 
-use std::{collections::HashMap, error::Error, fmt::{self, Display}, fs::{self, read_to_string}, io::Cursor, path::PathBuf};
+use std::{collections::HashMap, error::Error, fmt::{self, Display}, fs::{self, read_to_string}, io::Cursor, path::{Path, PathBuf}};
 
 use egui::{Pos2, Rect};
 use serde_yml::Value;
 use uuid::Uuid;
 
-use crate::{data::{area::TriggerSettings, backgrounddata::BackgroundData, course_file::{CourseInfo, MapExit}, grad::GradientData, mapfile::{MapData, MapDataError}, path::{PathDatabase, PathSettings}, rarc::RenderArchive, sprites::LevelSprite, types::{CurrentLayer, MapTileRecordData, Palette, TileCache}, TopLevelSegment}, gui::{gui::{BgSelectData, StorkTheme}, windows::{brushes::{Brush, BrushSettings}, course_win::CourseSettings}}, utils::{self, log_write, nitrofs_abs}};
+use crate::{data::{area::TriggerSettings, backgrounddata::BackgroundData, course_file::{CourseInfo, CourseParseError, MapExit, WarpKind}, grad::GradientData, mapfile::{MapData, MapDataError, UnknownSpriteIssue}, path::{PathDatabase, PathSettings}, rarc::RenderArchive, sprites::LevelSprite, types::{wipe_tile_cache, CurrentLayer, MapTileRecordData, Palette, TileCache, DEFAULT_TILE_CACHE_BUDGET}, TopLevelSegment}, gui::{maingrid::{GridMenu, PathGeometry}, gui::{BgSelectData, GridBackground, NumberBase, SpriteBoxMode, StorkTheme, ThemeColors, TileDim, TileNumberOverlay}, windows::{brushes::{Brush, BrushSettings}, course_win::CourseSettings, paths_win::PathAngle}}, utils::{self, log_write, nitrofs_abs, Arm9ReadError}};
 
-use crate::utils::LogLevel;
+use crate::{load::{add_sprite_stub, sprite_meta}, utils::LogLevel};
+
+use super::segment_state::SegmentBaseline;
 
 /// Global, not specifically tied to individual layer data
 pub struct DisplaySettings {
@@ -24,7 +26,41 @@ pub struct DisplaySettings {
     pub show_breakable_rock: bool,
     pub show_triggers: bool,
     pub stork_theme: StorkTheme,
-    pub show_box_for_rendered: bool
+    pub sprite_box_mode: SpriteBoxMode,
+    pub number_base: NumberBase,
+    /// GRAD, or the backdrop color, behind BG3
+    pub show_gradient: bool,
+    /// Outline duplicate and stacked Sprites, see find_sprite_issues
+    pub highlight_sprite_issues: bool,
+    /// Reach of Sprites listed in sprite_ranges.json
+    pub show_sprite_ranges: bool,
+    /// Tile coordinates along the top and left of the grid
+    pub show_rulers: bool,
+    /// Layer to switch to once a Level is loaded
+    pub start_layer: CurrentLayer,
+    /// Turn on a layer's visibility when it becomes current, instead of blocking edits
+    pub auto_show_current_layer: bool,
+    /// Move pasted BG blocks so their top-left is on an even tile, like Brush stamping
+    pub snap_bg_paste_to_even: bool,
+    /// How BG tiles fade while editing Collision
+    pub collision_tile_dim: TileDim,
+    /// Changing Level stays on the same map index, instead of the last one viewed there
+    pub keep_map_index: bool,
+    /// Still drawn, but ignore all clicks and edits even when current
+    pub locked_layers: Vec<CurrentLayer>,
+    /// Overlay colors on the main grid, only the preset is kept between sessions
+    pub theme_colors: ThemeColors,
+    /// Not kept between sessions, drawing the text is slow
+    pub tile_number_overlay: TileNumberOverlay,
+    /// Most textures each BG layer's tile cache keeps, see apply_tile_cache_budget
+    pub tile_cache_budget: usize,
+    pub grid_background: GridBackground,
+    /// Only used with GridBackground::Solid
+    pub grid_background_color: [u8; 3],
+    /// Switching layers opens the window used to edit it, see Gui::on_layer_changed
+    pub auto_open_companion: bool,
+    /// Ease jumps and keyboard scrolling on the main grid instead of cutting to them
+    pub smooth_scrolling: bool
 }
 
 impl Default for DisplaySettings {
@@ -44,7 +80,28 @@ impl Default for DisplaySettings {
             show_breakable_rock: false,
             show_triggers: true,
             stork_theme: StorkTheme::Auto,
-            show_box_for_rendered: true
+            sprite_box_mode: SpriteBoxMode::All,
+            // Most of the data is best understood in hex
+            number_base: NumberBase::Hex,
+            show_gradient: true,
+            highlight_sprite_issues: true,
+            show_sprite_ranges: false,
+            show_rulers: false,
+            start_layer: CurrentLayer::Sprites,
+            auto_show_current_layer: false,
+            snap_bg_paste_to_even: true,
+            collision_tile_dim: TileDim::Light,
+            keep_map_index: false,
+            locked_layers: Vec::new(),
+            theme_colors: ThemeColors::default(),
+            tile_number_overlay: TileNumberOverlay::Off,
+            tile_cache_budget: DEFAULT_TILE_CACHE_BUDGET,
+            grid_background: GridBackground::Panel,
+            // Classic for transparency, nothing in the game is this color
+            grid_background_color: [0xFF, 0x00, 0xFF],
+            // New users miss that Collision needs its window to pick a type
+            auto_open_companion: true,
+            smooth_scrolling: true
         }
     }
 }
@@ -53,6 +110,55 @@ impl DisplaySettings {
     pub fn is_cur_layer_bg(&self) -> bool {
         (self.current_layer == CurrentLayer::BG1) || (self.current_layer == CurrentLayer::BG2) || (self.current_layer == CurrentLayer::BG3)
     }
+
+    pub fn is_layer_visible(&self, layer: CurrentLayer) -> bool {
+        match layer {
+            CurrentLayer::BG1 => self.show_bg1,
+            CurrentLayer::BG2 => self.show_bg2,
+            CurrentLayer::BG3 => self.show_bg3,
+            CurrentLayer::Sprites => self.show_sprites,
+            CurrentLayer::Collision => self.show_col,
+            CurrentLayer::Paths => self.show_paths,
+            CurrentLayer::Triggers => self.show_triggers
+        }
+    }
+
+    pub fn set_layer_visible(&mut self, layer: CurrentLayer, visible: bool) {
+        match layer {
+            CurrentLayer::BG1 => self.show_bg1 = visible,
+            CurrentLayer::BG2 => self.show_bg2 = visible,
+            CurrentLayer::BG3 => self.show_bg3 = visible,
+            CurrentLayer::Sprites => self.show_sprites = visible,
+            CurrentLayer::Collision => self.show_col = visible,
+            CurrentLayer::Paths => self.show_paths = visible,
+            CurrentLayer::Triggers => self.show_triggers = visible
+        }
+    }
+
+    /// Editing is blocked on hidden layers, since changes can't be seen
+    pub fn is_cur_layer_hidden(&self) -> bool {
+        !self.is_layer_visible(self.current_layer)
+    }
+
+    pub fn is_layer_locked(&self, layer: CurrentLayer) -> bool {
+        self.locked_layers.contains(&layer)
+    }
+
+    pub fn set_layer_locked(&mut self, layer: CurrentLayer, locked: bool) {
+        self.locked_layers.retain(|l| *l != layer);
+        if locked {
+            self.locked_layers.push(layer);
+        }
+    }
+
+    pub fn is_cur_layer_locked(&self) -> bool {
+        self.is_layer_locked(self.current_layer)
+    }
+
+    /// Hidden or locked, either way editing is blocked
+    pub fn is_cur_layer_blocked(&self) -> bool {
+        self.is_cur_layer_hidden() || self.is_cur_layer_locked()
+    }
 }
 
 #[allow(clippy::upper_case_acronyms)]
@@ -87,6 +193,27 @@ pub fn get_gameversion_prettyname(gv: &GameVersion) -> String {
     }
 }
 
+/// Fills palettes 1 onwards with the PLTB of each BG in order, and
+/// stores where each BG starts so tiles can find their palette
+pub fn assign_bg_palettes(map: &mut MapData, palettes: &mut [Palette;16]) {
+    let mut pal_index: usize = 1; // 0 is the universal palette
+    for which in 1..4_u8 {
+        let Some(bg_data) = map.get_background(which) else { continue };
+        if let Some(palette) = bg_data.get_pltb_mut().cloned() {
+            bg_data._pal_offset = pal_index as u8 - 1; // -1 to deal with universal palette
+            for p in &palette.palettes {
+                if pal_index < 16 {
+                    palettes[pal_index] = *p;
+                }
+                // else { // For some reason, there's more. But not used?
+                //     log_write(format!("Palette Overflow, discarding"), LogLevel::Warn);
+                // }
+                pal_index += 1;
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum DisplayEngineError {
     FailedToOpen(&'static str, std::io::Error),
@@ -99,6 +226,10 @@ pub enum DisplayEngineError {
     UnknownRegionalVersion(&'static str),
     UnsupportedRegionalVersion(&'static str),
     CouldNotFindIn(&'static str, &'static str),
+    /// A key header.yaml should always have, from a hand-edited or partial extract
+    MissingHeaderKey(&'static str),
+    UnknownGameCode(String),
+    Arm9TooSmall(Arm9ReadError)
 }
 impl Display for DisplayEngineError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -112,12 +243,95 @@ impl Display for DisplayEngineError {
             Self::BadLogicGameVersion(game_ver) => f.write_fmt(format_args!("Game version {game_ver:?} should not be hit here")),
             Self::UnknownRegionalVersion(version) => f.write_fmt(format_args!("Unknown {version} version")),
             Self::UnsupportedRegionalVersion(version) => f.write_fmt(format_args!("{version} unsupported")),
-            Self::CouldNotFindIn(from, to) => f.write_fmt(format_args!("Could not find {from} in {to}"))
+            Self::CouldNotFindIn(from, to) => f.write_fmt(format_args!("Could not find {from} in {to}")),
+            Self::MissingHeaderKey(key) => f.write_fmt(format_args!(
+                "header.yaml is missing {key}. Copy header.yaml from a fresh extract of the ROM, or re-extract it with Open ROM")),
+            Self::UnknownGameCode(code) => f.write_fmt(format_args!(
+                "Unknown gamecode '{code}' in header.yaml, Yoshi's Island DS is AYWE (USA) or AYWP (EUR)")),
+            Self::Arm9TooSmall(error) => f.write_fmt(format_args!("{error}"))
         }
     }
 }
 impl Error for DisplayEngineError {}
 
+/// Where "1-1_D3" is, to tell the revisions apart
+fn version_string_addr(game_version: GameVersion) -> u64 {
+    match game_version {
+        GameVersion::USA10 => 0x0e1e6e,
+        GameVersion::USA11 => 0x0e20ae,
+        _ => {
+            log_write(format!("No version string address for {}",get_gameversion_prettyname(&game_version)), LogLevel::Fatal);
+            unreachable!()
+        }
+    }
+}
+
+/// Array of Course filename pointers, 0-1 first
+fn level_array_addr(game_version: GameVersion) -> u32 {
+    match game_version {
+        GameVersion::USA10 => 0x000d8f20,
+        GameVersion::USA11 => 0x000d9178,
+        _ => {
+            log_write(format!("Attempted to use version {} in USA level loader",get_gameversion_prettyname(&game_version)), LogLevel::Fatal);
+            unreachable!()
+        }
+    }
+}
+/// Level IDs go up to 99, plus 0-1
+const LEVEL_ARRAY_LEN: u64 = 100 * 4;
+
+fn universal_palette_addr(game_version: GameVersion) -> u64 {
+    match game_version {
+        // To find, look for 68 50 15 00 32 0a d0 01..
+        GameVersion::USA10 => 0x0d6f40, // 0x020d6f40
+        GameVersion::USA11 => 0x0d7198, // 0x020d7198
+        _ => {
+            log_write(format!("Attempting to update graphics with unsupported version '{}'",get_gameversion_prettyname(&game_version)), LogLevel::Fatal);
+            unreachable!()
+        }
+    }
+}
+/// 16 colors, 2 bytes each
+const UNIVERSAL_PALETTE_LEN: u64 = 16 * 2;
+
+/// Every hardcoded read, checked once on load so a trimmed or foreign
+/// arm9.bin fails there instead of partway through editing
+fn check_arm9_tables(arm9: &[u8], game_version: GameVersion) -> Result<(), Arm9ReadError> {
+    if !matches!(game_version, GameVersion::USA10|GameVersion::USA11) {
+        return Ok(()); // Refused later anyway
+    }
+    utils::check_arm9_read(arm9, "Version string", version_string_addr(game_version), 6)?;
+    utils::check_arm9_read(arm9, "Level filename array", level_array_addr(game_version) as u64, LEVEL_ARRAY_LEN)?;
+    utils::check_arm9_read(arm9, "Universal palette", universal_palette_addr(game_version), UNIVERSAL_PALETTE_LEN)?;
+    utils::check_arm9_read(arm9, "SinCos table", utils::sin_cos_table_addr(game_version) as u64, utils::SIN_COS_TABLE_LEN)?;
+    Ok(())
+}
+
+/// Region from header.yaml's gamecode, the revision comes later from stamp.rc
+fn read_header(yaml: &Value, stamp: &mut RomStamp) -> Result<GameVersion, DisplayEngineError> {
+    let Some(game_code) = yaml["gamecode"].as_str() else {
+        return Err(DisplayEngineError::MissingHeaderKey("gamecode"));
+    };
+    let game_ver = match game_code {
+        "AYWE"=> GameVersion::USAXX,
+        "AYWP"=> GameVersion::EURXX,
+        "AYWJ"=> GameVersion::JAP, // Only one Japanese version
+        "AYWK"=> GameVersion::KOR, // Only one Korean version
+        _=> return Err(DisplayEngineError::UnknownGameCode(game_code.to_string()))
+    };
+    log_write(format!("Found game version header: '{}'",game_code), LogLevel::Debug);
+    stamp.game_code = Some(game_code.to_string());
+    // Only informational, so not having it is fine
+    match yaml["makercode"].as_str() {
+        Some("01") => log_write("Game is unmodified".to_owned(), LogLevel::Log),
+        Some("63") => log_write("Game was edited with Stork".to_owned(), LogLevel::Log),
+        Some(maker_code) => log_write(format!("Unusual makercode: '{}'",maker_code), LogLevel::Warn),
+        None => log_write("header.yaml has no makercode", LogLevel::Warn)
+    }
+    stamp.maker_code = yaml["makercode"].as_str().map(str::to_string);
+    Ok(game_ver)
+}
+
 pub struct SpriteDragStatus {
     pub start_x: f32,
     pub start_y: f32,
@@ -137,8 +351,10 @@ pub struct ColDragStatus {
     pub end_pos: Pos2,
     pub selecting_rect: Rect,
     pub dragging: bool,
-    /// Once set to true, delete everything underneath selection, then set to false
-    pub delete_under: bool
+    /// Set by Escape mid-drag, so letting go doesn't delete anything
+    pub cancelled: bool,
+    /// How many tiles letting go would delete, for the tooltip
+    pub pending_count: usize
 }
 impl Default for ColDragStatus {
     fn default() -> Self {
@@ -146,7 +362,8 @@ impl Default for ColDragStatus {
             start_pos: Pos2::new(0.0, 0.0),
             end_pos: Pos2::new(0.0, 0.0),
             selecting_rect: Rect::NOTHING,
-            dragging: false, delete_under: false
+            dragging: false, cancelled: false,
+            pending_count: 0
         }
     }
 }
@@ -167,37 +384,154 @@ impl Default for SpriteClipboard {
 
 #[derive(Clone,Copy,Debug)]
 pub struct BgClipboardSelectedTile {
-    pub tile: MapTileRecordData,
+    /// None where no tile is stored, which paste skips. A real tile 0 is pasted
+    pub tile: Option<MapTileRecordData>,
     pub x_offset: i32,
     pub y_offset: i32
 }
 impl fmt::Display for BgClipboardSelectedTile {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f,"BgClipboardSelectedTile [ Tile=0x{:X}, xOffset=0x{:X}, yOffset=0x{:X} ]",self.tile.to_short(),self.x_offset,self.y_offset)
+        let tile_str = self.tile.map_or(String::from("None"), |tile| format!("0x{:X}",tile.to_short()));
+        write!(f,"BgClipboardSelectedTile [ Tile={}, xOffset=0x{:X}, yOffset=0x{:X} ]",tile_str,self.x_offset,self.y_offset)
     }
 }
 
 #[derive(Clone,Debug,Default)]
 pub struct BgClipboard {
-    pub tiles: Vec<BgClipboardSelectedTile>
+    pub tiles: Vec<BgClipboardSelectedTile>,
+    /// Where the selection was copied from in tiles, for Paste in Place
+    pub top_left: Pos2
 }
 impl BgClipboard {
     pub fn clear(&mut self) {
         self.tiles.clear();
+        self.top_left = Pos2::ZERO;
+    }
+    /// Where offset 0/0 goes when pasting at the hovered tile. With `snap_even`
+    /// the block's top-left is moved back onto an even tile
+    pub fn paste_origin(&self, hover_tile: Pos2, snap_even: bool) -> Pos2 {
+        if !snap_even {
+            return hover_tile;
+        }
+        let min_x = self.tiles.iter().map(|t| t.x_offset).min().unwrap_or(0);
+        let min_y = self.tiles.iter().map(|t| t.y_offset).min().unwrap_or(0);
+        let block_x = hover_tile.x as i32 + min_x;
+        let block_y = hover_tile.y as i32 + min_y;
+        // Round down, including below 0
+        let even_x = block_x - block_x.rem_euclid(2);
+        let even_y = block_y - block_y.rem_euclid(2);
+        Pos2::new((even_x - min_x) as f32, (even_y - min_y) as f32)
+    }
+    /// Width and height in tiles of the block the copied tiles fit in
+    pub fn dimensions(&self) -> (u16, u16) {
+        if self.tiles.is_empty() {
+            return (0, 0);
+        }
+        let (min_x, max_x) = self.tiles.iter().fold((i32::MAX, i32::MIN), |(lo, hi), t| (lo.min(t.x_offset), hi.max(t.x_offset)));
+        let (min_y, max_y) = self.tiles.iter().fold((i32::MAX, i32::MIN), |(lo, hi), t| (lo.min(t.y_offset), hi.max(t.y_offset)));
+        ((max_x - min_x + 1) as u16, (max_y - min_y + 1) as u16)
+    }
+    /// Unstored spots and gaps in the block become empty Brush spots
+    pub fn to_brush(&self, tileset: String) -> Option<Brush> {
+        let (width, height) = self.dimensions();
+        if width == 0 || width > u8::MAX as u16 || height > u8::MAX as u16 {
+            return None;
+        }
+        let min_x = self.tiles.iter().map(|t| t.x_offset).min().unwrap_or(0);
+        let min_y = self.tiles.iter().map(|t| t.y_offset).min().unwrap_or(0);
+        let mut brush_tiles: Vec<Option<u16>> = vec![None; width as usize * height as usize];
+        for tile in &self.tiles {
+            let index = (tile.y_offset - min_y) as usize * width as usize + (tile.x_offset - min_x) as usize;
+            brush_tiles[index] = tile.tile.map(|t| t.to_short());
+        }
+        Some(Brush {
+            tileset,
+            name: String::from("From Clipboard"),
+            width: width as u8,
+            height: height as u8,
+            tiles: brush_tiles,
+            ..Default::default()
+        })
+    }
+}
+
+/// Only the settings bytes, not the Sprite itself
+#[derive(Clone,Debug)]
+pub struct SpriteSettingsClipboard {
+    pub object_id: u16,
+    pub settings: Vec<u8>
+}
+impl SpriteSettingsClipboard {
+    /// The settings must be the length the target Sprite type expects
+    pub fn fits(&self, target: &LevelSprite) -> bool {
+        let Some(sprite_meta) = sprite_meta(target.object_id) else {
+            return false;
+        };
+        sprite_meta.default_settings_len as usize == self.settings.len() &&
+            target.settings_length as usize == self.settings.len()
     }
 }
 
 #[derive(Default)]
 pub struct Clipboard {
     pub sprite_clip: SpriteClipboard,
-    pub bg_clip: BgClipboard
+    pub bg_clip: BgClipboard,
+    pub settings_clip: Option<SpriteSettingsClipboard>
+}
+
+/// ARM9 tables used while rendering, parsed once on load
+#[derive(Clone,Debug)]
+pub struct Arm9Tables {
+    /// Indexed by angle >> 4
+    pub sin_cos: Vec<PathAngle>,
+    pub universal_palette: Palette
+}
+impl Arm9Tables {
+    pub fn parse(arm9: &[u8], game_version: GameVersion) -> Result<Self, Arm9ReadError> {
+        check_arm9_tables(arm9, game_version)?;
+        let mut sin_cos: Vec<PathAngle> = Vec::with_capacity(SIN_COS_ENTRIES);
+        for entry in 0..SIN_COS_ENTRIES {
+            sin_cos.push(utils::get_sin_cos_table_value(arm9, (entry << 4) as u16, game_version)?);
+        }
+        let mut cur = Cursor::new(arm9);
+        cur.set_position(universal_palette_addr(game_version));
+        let universal_palette = Palette::from_cursor(&mut cur, 16).expect("check_arm9_tables covers the palette");
+        Ok(Self { sin_cos, universal_palette })
+    }
+
+    pub fn sin_cos(&self, angle: u16) -> PathAngle {
+        self.sin_cos[(angle >> 4) as usize]
+    }
+}
+/// One per 16 angle units
+const SIN_COS_ENTRIES: usize = 0x1000;
+
+/// What the extracted ROM says about itself, for diagnostics
+#[derive(Clone,Debug,Default)]
+pub struct RomStamp {
+    /// From stamp.rc, which decides the revision
+    pub build_date: String,
+    pub game_code: Option<String>,
+    pub maker_code: Option<String>
+}
+impl fmt::Display for RomStamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, maker {}, built {}",
+            self.game_code.as_deref().unwrap_or("????"),self.maker_code.as_deref().unwrap_or("??"),self.build_date)
+    }
 }
 
 /// NDS Graphical data and memory, tailored for YIDS
 pub struct DisplayEngine {
     pub loaded_map: MapData,
+    /// Each segment as loaded, for the Map/BG Segments windows
+    pub segment_baseline: SegmentBaseline,
     pub map_index: Option<usize>,
+    /// Last map index viewed per Course file name, for this session
+    pub last_map_indexes: HashMap<String,u32>,
     pub loaded_course: CourseInfo,
+    /// The Course's bytes as last read or written, to tell whether it needs saving
+    pub saved_course: Vec<u8>,
     pub bg_palettes: [Palette;16],
     pub bg_layer_1: Option<BackgroundData>,
     pub bg_layer_2: Option<BackgroundData>,
@@ -206,20 +540,35 @@ pub struct DisplayEngine {
     pub tile_cache_bg2: TileCache,
     pub tile_cache_bg3: TileCache,
     pub level_sprites: Vec<LevelSprite>,
+    /// Sprite IDs the loaded map uses that sprites.csv lacks, found when the map loads
+    pub unknown_sprite_issues: Vec<UnknownSpriteIssue>,
     pub gradient_data: Option<GradientData>,
     pub path_data: Option<PathDatabase>,
+    /// Built from path_data whenever it changes
+    pub path_geometry: PathGeometry,
     pub path_settings: PathSettings,
     pub loaded_archives: HashMap<String,RenderArchive>,
     pub loaded_arm9: Option<Vec<u8>>,
+    pub arm9_tables: Option<Arm9Tables>,
     pub game_version: GameVersion,
+    pub rom_stamp: RomStamp,
     pub display_settings: DisplaySettings,
     pub selected_sprite_uuids: Vec<Uuid>,
     pub selected_sprite_to_place: Option<u16>,
     pub col_tile_to_place: u8,
     pub latest_sprite_settings: String,
+    pub latest_sprite_object_id: u16,
+    /// For shifting the palette rows of selected BG tiles
+    pub palette_shift_delta: i16,
     pub sprite_search_query: String,
     pub sprite_drag_status: SpriteDragStatus,
     pub col_selector_status: ColDragStatus,
+    /// Collision index picked by Fill Rect in the grid menu, the next click fills to it
+    pub col_fill_anchor: Option<usize>,
+    /// Layer left by the last change_layer, until the GUI catches up, see Gui::on_layer_changed
+    pub left_layer: Option<CurrentLayer>,
+    /// Right-click menu on the grid, see render_primary_grid
+    pub grid_menu: Option<GridMenu>,
     pub unsaved_changes: bool,
     pub export_folder: PathBuf,
     pub current_brush: Brush,
@@ -232,8 +581,12 @@ pub struct DisplayEngine {
     pub trigger_settings: TriggerSettings,
     pub bg_sel_data: BgSelectData,
     pub tile_hover_pos: Pos2,
+    /// Tile under the pointer on the Sprites layer, None when off the grid
+    pub sprite_hover_tile: Option<Pos2>,
     pub selected_preview_tile: Option<usize>,
     pub tile_preview_pal: usize,
+    /// tile_preview_pal for BG 1-3, swapped in and out by change_layer
+    pub layer_preview_pals: [usize; 3],
     pub needs_bg_tile_refresh: bool
 }
 
@@ -241,18 +594,25 @@ impl Default for DisplayEngine {
     fn default() -> Self {
         Self {
             loaded_map: MapData::default(),
+            segment_baseline: SegmentBaseline::default(),
             map_index: Option::None,
+            last_map_indexes: HashMap::new(),
             loaded_course: CourseInfo::default(),
+            saved_course: Vec::new(),
             bg_palettes: Default::default(),
             bg_layer_1: Option::None, bg_layer_2: Option::None, bg_layer_3: Option::None,
             loaded_arm9: Option::None,
+            arm9_tables: Option::None,
             game_version: GameVersion::Unknown,
-            tile_cache_bg1: vec![vec![Option::None;1024];16],
-            tile_cache_bg2: vec![vec![Option::None;1024];16],
-            tile_cache_bg3: vec![vec![Option::None;1024];16],
+            rom_stamp: RomStamp::default(),
+            tile_cache_bg1: TileCache::default(),
+            tile_cache_bg2: TileCache::default(),
+            tile_cache_bg3: TileCache::default(),
             level_sprites: Vec::new(),
+            unknown_sprite_issues: Vec::new(),
             gradient_data: Option::None,
             path_data: Option::None,
+            path_geometry: PathGeometry::default(),
             path_settings: PathSettings::default(),
             display_settings: DisplaySettings::default(),
             loaded_archives: HashMap::new(),
@@ -260,9 +620,14 @@ impl Default for DisplayEngine {
             selected_sprite_to_place: Option::None,
             col_tile_to_place: 0x1, // Basic square
             latest_sprite_settings: String::from(""),
+            latest_sprite_object_id: 0,
+            palette_shift_delta: 1,
             sprite_search_query: String::from(""),
             sprite_drag_status: SpriteDragStatus::default(),
             col_selector_status: ColDragStatus::default(),
+            col_fill_anchor: Option::None,
+            left_layer: Option::None,
+            grid_menu: Option::None,
             unsaved_changes: false,
             export_folder: PathBuf::new(),
             current_brush: Brush::default(),
@@ -275,14 +640,105 @@ impl Default for DisplayEngine {
             trigger_settings: TriggerSettings::default(),
             bg_sel_data: BgSelectData::default(),
             tile_hover_pos: Pos2::ZERO,
+            sprite_hover_tile: Option::None,
             selected_preview_tile: None,
             tile_preview_pal: 0,
+            layer_preview_pals: [0; 3],
             needs_bg_tile_refresh: false
         }
     }
 }
 
+/// Index into layer_preview_pals, None for non-BG layers
+fn preview_pal_slot(layer: CurrentLayer) -> Option<usize> {
+    match layer {
+        CurrentLayer::BG1 => Some(0),
+        CurrentLayer::BG2 => Some(1),
+        CurrentLayer::BG3 => Some(2),
+        _ => None
+    }
+}
+
 impl DisplayEngine {
+    /// Layer-specific selections and brushes don't carry over
+    pub fn change_layer(&mut self, new_layer: CurrentLayer) {
+        if self.display_settings.current_layer == new_layer {
+            return;
+        }
+        log_write("Cleaning up due to layer change", LogLevel::Debug);
+        if let Some(slot) = preview_pal_slot(self.display_settings.current_layer) {
+            self.layer_preview_pals[slot] = self.tile_preview_pal;
+        }
+        if let Some(slot) = preview_pal_slot(new_layer) {
+            self.tile_preview_pal = self.layer_preview_pals[slot];
+            self.needs_bg_tile_refresh = true;
+        }
+        // Keep the first one if the layer changes again before the GUI sees it
+        self.left_layer.get_or_insert(self.display_settings.current_layer);
+        self.display_settings.current_layer = new_layer;
+        if self.display_settings.auto_show_current_layer && !self.display_settings.is_layer_visible(new_layer) {
+            log_write(format!("Showing hidden layer {:?} since it is now current",new_layer), LogLevel::Debug);
+            self.display_settings.set_layer_visible(new_layer, true);
+            self.graphics_update_needed = true;
+        }
+        self.brush_settings.cur_selected_brush = Option::None;
+        self.brush_settings.current_brush_edited = false;
+        self.current_brush.clear();
+        self.clipboard.bg_clip.clear();
+        self.bg_sel_data.clear();
+        self.selected_preview_tile = None;
+        self.col_fill_anchor = None;
+    }
+
+    /// layer_preview_pals, including the palette in use on the current layer
+    pub fn preview_pals(&self) -> [usize; 3] {
+        let mut pals = self.layer_preview_pals;
+        if let Some(slot) = preview_pal_slot(self.display_settings.current_layer) {
+            pals[slot] = self.tile_preview_pal;
+        }
+        pals
+    }
+
+    /// Re-reads a BG's tileset from the project and drops its cached textures
+    pub fn reload_tileset(&mut self, which_bg: u8) -> bool {
+        let export_folder = self.export_folder.clone();
+        let Some(bg) = self.loaded_map.get_background(which_bg) else {
+            log_write(format!("No BG {} to reload the tileset of",which_bg), LogLevel::Warn);
+            return false;
+        };
+        if !bg.reload_pixel_tiles_preview(&export_folder) {
+            return false;
+        }
+        log_write(format!("Reloaded tileset for BG {}",which_bg), LogLevel::Log);
+        match which_bg {
+            1 => wipe_tile_cache(&mut self.tile_cache_bg1),
+            2 => wipe_tile_cache(&mut self.tile_cache_bg2),
+            _ => wipe_tile_cache(&mut self.tile_cache_bg3)
+        }
+        self.graphics_update_needed = true;
+        self.needs_bg_tile_refresh = true;
+        true
+    }
+
+    /// Passes display_settings.tile_cache_budget on to the caches, dropping textures if it shrank
+    pub fn apply_tile_cache_budget(&mut self) {
+        let budget = self.display_settings.tile_cache_budget;
+        self.tile_cache_bg1.set_budget(budget);
+        self.tile_cache_bg2.set_budget(budget);
+        self.tile_cache_bg3.set_budget(budget);
+    }
+
+    pub fn tile_cache_texture_count(&self) -> usize {
+        self.tile_cache_bg1.texture_count() + self.tile_cache_bg2.texture_count() + self.tile_cache_bg3.texture_count()
+    }
+
+    pub fn set_preview_pals(&mut self, pals: [usize; 3]) {
+        self.layer_preview_pals = pals.map(|pal| pal.min(15));
+        if let Some(slot) = preview_pal_slot(self.display_settings.current_layer) {
+            self.tile_preview_pal = self.layer_preview_pals[slot];
+        }
+    }
+
     pub fn new(extract_dir: PathBuf) -> Result<DisplayEngine, DisplayEngineError> {
         let mut de = DisplayEngine::default(); // Everything is empty
 
@@ -310,27 +766,14 @@ impl DisplayEngine {
             Ok(s) => s,
         };
         let yaml: Value = serde_yml::from_str(&yaml_content).map_err(|_| DisplayEngineError::FailedToParse("header.yaml"))?;
-        if let Some(game_code) = yaml["gamecode"].as_str() {
-            // Does not get the revision, do that later
-            let game_ver = match game_code {
-                "AYWE"=> GameVersion::USAXX,
-                "AYWP"=> GameVersion::EURXX,
-                "AYWJ"=> GameVersion::JAP, // Only one Japanese version
-                "AYWK"=> GameVersion::KOR, // Only one Korean version
-                _=> GameVersion::Unknown
-            };
-            log_write(format!("Found game version header: '{}'",game_code), LogLevel::Debug);
-            de.game_version = game_ver;
-        }
-        if let Some(maker_code) = yaml["makercode"].as_str() {
-            if maker_code == "01" {
-                log_write("Game is unmodified".to_owned(), LogLevel::Log);
-            } else if maker_code == "63" {
-                log_write("Game was edited with Stork".to_owned(), LogLevel::Log);
-            } else {
-                log_write(format!("Unusual makercode: '{}'",maker_code), LogLevel::Warn);
+        de.game_version = match read_header(&yaml, &mut de.rom_stamp) {
+            Ok(game_ver) => game_ver,
+            Err(header_err) => {
+                log_write(&header_err, LogLevel::Error);
+                return Err(header_err);
             }
-        }
+        };
+        de.rom_stamp.build_date = build_date.clone();
 
         // Open and check ARM9 Binary //
         let mut arm9_path: PathBuf = PathBuf::from(&extract_dir);
@@ -394,9 +837,20 @@ impl DisplayEngine {
         // Version checks //
         let got_contents = de.loaded_arm9.as_ref().expect("ARM9 was loaded properly");
         let game_version = de.game_version;
+        // Only USA is supported past here, so there's always tables
+        if matches!(game_version, GameVersion::USA10|GameVersion::USA11) {
+            match Arm9Tables::parse(got_contents, game_version) {
+                Ok(tables) => de.arm9_tables = Some(tables),
+                Err(e) => {
+                    let too_small = DisplayEngineError::Arm9TooSmall(e);
+                    log_write(&too_small, LogLevel::Error);
+                    return Err(too_small);
+                }
+            }
+        }
         match game_version {
             GameVersion::USA10 => {
-                let found_str = utils::read_fixed_string(got_contents, 0xe1e6e, 6);
+                let found_str = utils::read_fixed_string(got_contents, version_string_addr(game_version), 6);
                 if !found_str.eq("1-1_D3") {
                     let unk_ver1 = DisplayEngineError::CouldNotFindIn("1-1_D3", "USA 1.0");
                     log_write(&unk_ver1, LogLevel::Error);
@@ -404,7 +858,7 @@ impl DisplayEngine {
                 }
             },
             GameVersion::USA11 => {
-                let found_str2 = utils::read_fixed_string(got_contents, 0x0e20ae, 6);
+                let found_str2 = utils::read_fixed_string(got_contents, version_string_addr(game_version), 6);
                 if !found_str2.eq("1-1_D3") {
                     let unk_ver2 = DisplayEngineError::CouldNotFindIn("1-1_D3", "USA 1.1");
                     log_write(&unk_ver2, LogLevel::Error);
@@ -440,7 +894,7 @@ impl DisplayEngine {
         Ok(de)
     }
 
-    fn get_level_filename(&self, world_index: &u32, level_index: &u32) -> String {
+    pub fn get_level_filename(&self, world_index: &u32, level_index: &u32) -> String {
         let game_ver = self.game_version;
         let filename_res = match game_ver {
             GameVersion::USA10 => self.get_level_filename_usa(world_index, level_index,GameVersion::USA10),
@@ -455,6 +909,10 @@ impl DisplayEngine {
             Ok(s) => {
                 s
             }
+            Err(e @ GetLevelFilenameError::Arm9TooSmall(_)) => {
+                log_write(&e, LogLevel::Error);
+                "Error".to_owned()
+            }
             Err(e) => {
                 log_write(format!("filename_res failed somehow: {}",e), LogLevel::Fatal);
                 "Error".to_owned()
@@ -499,18 +957,12 @@ impl DisplayEngine {
             _ => {}
         }
 
-        let level_array_addr = match game_version {
-            GameVersion::USA10 => 0x000d8f20,
-            GameVersion::USA11 => 0x000d9178,
-            _ => {
-                log_write(format!("Attempted to use version {} in USA level loader",get_gameversion_prettyname(&game_version)), LogLevel::Fatal);
-                unreachable!()
-            }
-        };
         let offset = level_id * 4; // u32 = 4 bytes
-        let array_internal_address = level_array_addr + offset;
+        let array_internal_address = level_array_addr(game_version) + offset;
         // Make this the smarter way eventually
         if let Some(arm9_binary) = &self.loaded_arm9 {
+            utils::check_arm9_read(arm9_binary, "Level filename array", array_internal_address as u64, 4)
+                .map_err(GetLevelFilenameError::Arm9TooSmall)?;
             let mut rdr: Cursor<&Vec<u8>> = Cursor::new(arm9_binary);
             rdr.set_position(array_internal_address as u64);
             let string_address: u32 = match utils::read_address(&mut rdr) {
@@ -521,6 +973,8 @@ impl DisplayEngine {
                     return Err(err_msg)
                 },
             };
+            utils::check_arm9_read(arm9_binary, "Level filename", string_address as u64, 1)
+                .map_err(GetLevelFilenameError::Arm9TooSmall)?;
             rdr.set_position(string_address as u64);
             let level_name = utils::read_c_string(&mut rdr);
             Ok(level_name)
@@ -580,41 +1034,79 @@ impl DisplayEngine {
     
     pub fn load_level(&mut self, world_index: u32, level_index: u32, map_index: u32) -> Result<(), LoadLevelError> {
         log_write(format!("Loading World {} Level {} Map {}",&world_index+1,&level_index+1,&map_index+1), LogLevel::Log);
-        let map_index_store = self.map_index; // Backup
-        self.map_index = Some(map_index as usize);
-        let mut initial_level_name = self.get_level_filename(&world_index, &level_index);
-        initial_level_name.push_str(".crsb");
-        let crsb_path = nitrofs_abs(self.export_folder.to_path_buf(), &initial_level_name);
-        let crsb = CourseInfo::new(&crsb_path,format!("Course {}-{}",world_index+1,level_index+1));
-        log_write(format!("Loaded Course '{}' from '{}'",&crsb.label,&crsb.src_filename), LogLevel::Log);
-        if (map_index as usize) >= crsb.level_map_data.len() {
-            let err_msg = LoadLevelError::OutOfBounds(map_index, crsb.level_map_data.len());
-            log_write(&err_msg, LogLevel::Error);
-            // Revert
-            self.map_index = map_index_store;
-            return Err(err_msg);
-        }
-        let mut map_name = crsb.level_map_data[map_index as usize].map_filename_noext.clone();
-        let noext_name = map_name.clone();
-        let loaded_course_store = self.loaded_course.clone(); // Backup
-        self.loaded_course = crsb;
-        map_name.push_str(".mpdz");
-        let map_path = nitrofs_abs(self.export_folder.to_path_buf(), &map_name);
-        let loaded_map_res = match MapData::new(&map_path, &self.export_folder) {
-            Ok(x) => x,
-            Err(e) => {
-                // Revert
-                self.map_index = map_index_store;
-                self.loaded_course = loaded_course_store;
+        let initial_level_name = self.get_level_filename(&world_index, &level_index);
+        self.load_course(&initial_level_name, format!("Course {}-{}",world_index+1,level_index+1), map_index)
+    }
 
-                let err_msg = LoadLevelError::FailedLoadMapData(e);
-                log_write(&err_msg, LogLevel::Error);
-                return Err(err_msg);
-            }
+    /// The map index to return to for a Course, 0 if it was never viewed
+    pub fn get_last_map_index(&self, course_name_noext: &str) -> u32 {
+        self.last_map_indexes.get(course_name_noext).copied().unwrap_or(0)
+    }
+
+    /// The map index to open a Course at. With keep_map_index, this is the loaded
+    /// map's index, clamped to the Course's map count
+    pub fn map_index_for_course(&self, course_name_noext: &str) -> u32 {
+        let current = match self.map_index {
+            Some(current) if self.display_settings.keep_map_index => current as u32,
+            _ => return self.get_last_map_index(course_name_noext)
         };
+        match self.peek_course(course_name_noext) {
+            Ok(course) => current.min(course.level_map_data.len().saturating_sub(1) as u32),
+            Err(_) => current // Loading falls back to the first map
+        }
+    }
+
+    /// The file name of the loaded Course, without the extension
+    pub fn loaded_course_name(&self) -> String {
+        Path::new(&self.loaded_course.src_filename).file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    /// Reads a Course file without loading it, for previews
+    pub fn peek_course(&self, course_name_noext: &str) -> Result<CourseInfo, CourseParseError> {
+        let crsb_path = nitrofs_abs(self.export_folder.to_path_buf(), &format!("{}.crsb",course_name_noext));
+        CourseInfo::new(&crsb_path, course_name_noext.to_string())
+    }
+
+    /// Checks the disk only, the loaded map may have been deleted out from under it
+    pub fn map_file_exists(&self, map_filename_noext: &str) -> bool {
+        nitrofs_abs(self.export_folder.to_path_buf(), &format!("{}.mpdz",map_filename_noext)).is_file()
+    }
+
+    /// Uses the loaded map if it matches, since it may have unsaved changes
+    pub fn peek_map_dimensions(&self, map_filename_noext: &str) -> Result<(u16, u16), MapDataError> {
+        if self.loaded_map.map_name == map_filename_noext {
+            return Ok(self.loaded_map.get_dimensions());
+        }
+        let map_path = nitrofs_abs(self.export_folder.to_path_buf(), &format!("{}.mpdz",map_filename_noext));
+        let map = MapData::new(&map_path, &self.export_folder)?;
+        Ok(map.get_dimensions())
+    }
+
+    /// Same as `load_level`, but directly from the Course file name (no extension)
+    ///
+    /// This allows loading Courses that have no World/Level index, like kuppa
+    pub fn load_course(&mut self, course_name_noext: &str, label: String, map_index: u32) -> Result<(), LoadLevelError> {
+        // Nothing changes until both are read, so a failure leaves the loaded map as it was
+        let loaded = read_course_map(&self.export_folder, course_name_noext, label, map_index)?;
+        self.apply_course_map(loaded);
+        Ok(()) // Could something useful be returned?
+    }
 
-        self.loaded_map = loaded_map_res;
-        self.loaded_map.map_name = noext_name;
+    /// Whether the Course differs from what was last read or written
+    pub fn course_changed(&mut self) -> bool {
+        self.loaded_course.wrap() != self.saved_course
+    }
+
+    /// Swaps in a Course and map from read_course_map, then builds the graphics
+    pub fn apply_course_map(&mut self, loaded: LoadedCourseMap) {
+        self.map_index = Some(loaded.map_index as usize);
+        self.loaded_course = loaded.course;
+        self.saved_course = self.loaded_course.wrap();
+        self.loaded_map = loaded.map;
+        self.segment_baseline = SegmentBaseline::new(&self.loaded_map);
+        self.last_map_indexes.insert(loaded.course_name_noext, loaded.map_index);
 
         let seg_count = &self.loaded_map.segments.len();
         let mapped: Vec<String> = self.loaded_map.segments.iter().map(|x| x.header()).collect();
@@ -623,77 +1115,40 @@ impl DisplayEngine {
         
         // Do it manually the first time, don't wait for refresh
         self.update_graphics_from_mapdata();
-        Ok(()) // Could something useful be returned?
     }
 
     pub fn get_render_archive(&mut self, archive_name_local: &str) -> &RenderArchive {
         if self.loaded_archives.contains_key(archive_name_local) {
-            let arc_opt = self.loaded_archives.get(archive_name_local).expect("Error with RenderArchive get");
-            arc_opt
+            self.loaded_archives.get(archive_name_local).expect("Error with RenderArchive get")
         } else {
             let archive_name_full = nitrofs_abs(self.export_folder.to_path_buf(), archive_name_local).display().to_string();
             let rarc = RenderArchive::new(archive_name_full, self.export_folder.to_path_buf());
             self.loaded_archives.insert(archive_name_local.to_string(), rarc);
-            let ret = self.loaded_archives.get(archive_name_local).expect("Error with RenderArchive get post creation");
-            ret
+            self.loaded_archives.get(archive_name_local).expect("Error with RenderArchive get post creation")
+        }
+    }
+
+    /// The universal palette stored in ARM9, which is always palette 0
+    pub fn get_universal_palette(&self) -> Option<Palette> {
+        if let Some(tables) = &self.arm9_tables {
+            Some(tables.universal_palette)
+        } else {
+            log_write("Could not load ARM9 to get universal palette", LogLevel::Error);
+            None
         }
     }
 
     /// Copies data from MapData to graphics engine
     pub fn update_graphics_from_mapdata(&mut self) {
         // Initialize palettes //
-        let mut pal_index: usize = 0;
-        let gv = self.game_version;
-        let unipal_addr: u64 = match gv {
-            // To find, look for 68 50 15 00 32 0a d0 01..
-            GameVersion::USA10 => 0x0d6f40, // 0x020d6f40
-            GameVersion::USA11 => 0x0d7198, // 0x020d7198
-            _ => {
-                log_write(format!("Attempting to update graphics with unsupported version '{}'",get_gameversion_prettyname(&gv)), LogLevel::Fatal);
-                unreachable!()
-            }
-        }; 
-        if let Some(arm9_binary) = &self.loaded_arm9 {
-            let mut cur = Cursor::new(arm9_binary.as_slice());
-            cur.set_position(unipal_addr);
-            let pal = Palette::from_cursor(&mut cur, 16);
-            self.bg_palettes[pal_index] = pal;
-        } else {
-            log_write("Could not load ARM9 to get universal palette", LogLevel::Error);
+        if let Some(pal) = self.get_universal_palette() {
+            self.bg_palettes[0] = pal;
         }
-        pal_index += 1;
+        assign_bg_palettes(&mut self.loaded_map, &mut self.bg_palettes);
 
         // BG loop //
         for which in 1..4_u8 { // This is 1,2,3; 4 is excluded
-            let bg: Option<&mut BackgroundData> = self.loaded_map.get_background(which);
-            if let Some(bg_data) = bg {
-                // Palette
-                if let Some(palette) = bg_data.get_pltb_mut().cloned() {
-                    bg_data._pal_offset = pal_index as u8 - 1; // -1 to deal with universal palette
-                    for p in &palette.palettes {
-                        if pal_index < 16 {
-                            self.bg_palettes[pal_index] = *p;
-                        }
-                        // else { // For some reason, there's more. But not used?
-                        //     log_write(format!("Palette Overflow, discarding"), LogLevel::Warn);
-                        // }
-                        pal_index += 1;
-                    }
-                }
-                // Setting to specific graphic memory
-                // It is one way, copy it
-                if which == 1 {
-                    self.bg_layer_1 = Some(bg_data.clone());
-                } else if which == 2 {
-                    self.bg_layer_2 = Some(bg_data.clone());
-                } else if which == 3 {
-                    self.bg_layer_3 = Some(bg_data.clone());
-                } else {
-                    log_write(format!("Unusual which_bg in update_graphics_from_map: {}",which), LogLevel::Error);
-                }
-            } else {
-                //log_write(format!("Did not get BG from get_background in graphics update"), LogLevel::Warn);
-            }
+            self.update_bg_layer(which);
         }
         // SETD (Sprites) //
         self.level_sprites.clear();
@@ -711,14 +1166,113 @@ impl DisplayEngine {
 
         // PATH (Paths) //
         if let Some(path) = self.loaded_map.get_path() {
-            self.path_data = Some(path.clone());
+            // Graphics updates happen on every edit, most don't touch paths
+            if self.path_data.as_ref() != Some(path) {
+                self.path_geometry = PathGeometry::build(path, self.arm9_tables.as_ref());
+                self.path_data = Some(path.clone());
+            }
+        }
+    }
+
+    /// Copies one BG to its graphic memory, for edits that only touch that layer
+    /// and don't need the rest of update_graphics_from_mapdata
+    pub fn update_bg_layer(&mut self, which: u8) {
+        let Some(bg_data) = self.loaded_map.get_background(which) else {
+            return;
+        };
+        // It is one way, copy it
+        match which {
+            1 => self.bg_layer_1 = Some(bg_data.clone()),
+            2 => self.bg_layer_2 = Some(bg_data.clone()),
+            3 => self.bg_layer_3 = Some(bg_data.clone()),
+            _ => log_write(format!("Unusual which_bg in update_bg_layer: {}",which), LogLevel::Error)
         }
     }
 
+    /// Shifts the palette rows of the selected tiles on which_bg, see shift_palettes
+    pub fn shift_selected_palettes(&mut self, which_bg: u8, delta: i16) {
+        let Some(bg) = self.loaded_map.get_background(which_bg) else {
+            return;
+        };
+        let (changed, over_rows) = bg.shift_palettes(&self.bg_sel_data.selected_map_indexes, delta);
+        log_write(format!("Shifted palette of {} tiles by {}",changed,delta), LogLevel::Log);
+        if over_rows > 0 {
+            log_write(format!("{} tiles now use palette rows this layer does not have",over_rows), LogLevel::Warn);
+        }
+        if changed > 0 {
+            // The tile cache is per palette, so new rows get their own entries
+            // and nothing needs wiping. Only this layer's copy is stale
+            self.map_edited();
+            self.update_bg_layer(which_bg);
+        }
+    }
+
+    /// Create a new empty map using the tilesets of the current map, then add it to the Course
+    /// 
+    /// The map file is written immediately, like with templates, but the Course still needs saving
+    pub fn add_blank_map(&mut self) -> Result<String, MapDataError> {
+        let settings = self.course_settings.blank_map.clone();
+        // Round down to even, odd sizes break collision
+        let width = settings.width & !1;
+        let height = settings.height & !1;
+        let layer_count = (settings.layer_count as usize).min(3);
+        // Collision goes wherever it was in the source, or the first layer if none
+        let colz_layer = (0..layer_count).find(|layer_index| {
+            self.loaded_map.get_background(settings.source_layers[*layer_index])
+                .is_some_and(|bg| bg.get_colz().is_some())
+        }).unwrap_or(0);
+        let mut layers: Vec<BackgroundData> = Vec::new();
+        for layer_index in 0..layer_count {
+            let source_bg = settings.source_layers[layer_index];
+            let Some(source) = self.loaded_map.get_background(source_bg) else {
+                return Err(MapDataError::MissingLayerSegment(source_bg, "SCEN"));
+            };
+            let Some(pltb) = source.get_pltb().cloned() else {
+                return Err(MapDataError::MissingLayerSegment(source_bg, "PLTB"));
+            };
+            let mut info = source.get_info().expect("Every BG has INFO").clone();
+            info.which_bg = layer_index as u8 + 1;
+            layers.push(BackgroundData::new_blank(&info, pltb, width, height, layer_index == colz_layer));
+        }
+        // Find an unused file name
+        let mut four_num: u32 = 0;
+        let (file_name_noext, new_path) = loop {
+            four_num += 1;
+            let name_noext = format!("blk{:04}",four_num);
+            let path = nitrofs_abs(self.export_folder.to_path_buf(), &format!("{}.mpdz",name_noext));
+            if matches!(fs::exists(&path), Ok(false)) {
+                break (name_noext, path);
+            }
+        };
+        let new_map = MapData::new_blank(&new_path, layers)?;
+        if let Err(error) = fs::write(&new_path, new_map.package()) {
+            log_write(format!("Failed to write blank map: '{error}'"), LogLevel::Error);
+            return Err(MapDataError::FileWriteFailed(new_path.display().to_string()));
+        }
+        log_write(format!("Created blank map '{}'",new_path.display()), LogLevel::Log);
+        self.loaded_course.add_map_info(file_name_noext.clone());
+        Ok(file_name_noext)
+    }
+
     pub fn get_loaded_sprite_by_uuid(&self, uuid: &Uuid) -> Option<&LevelSprite> {
         self.level_sprites.iter().find(|&sprite| sprite.uuid == *uuid)
     }
 
+    /// Names an unknown ID in the project's sprites.csv, false if it couldn't be written
+    pub fn add_sprite_stub(&mut self, issue: &UnknownSpriteIssue) -> bool {
+        match add_sprite_stub(&self.export_folder, issue.object_id, issue.settings_len) {
+            Ok(_) => {
+                self.unknown_sprite_issues.retain(|unknown| unknown.object_id != issue.object_id);
+                self.graphics_update_needed = true;
+                true
+            }
+            Err(error) => {
+                log_write(format!("Failed to add stub for Sprite 0x{:X}: {error}",issue.object_id), LogLevel::Error);
+                false
+            }
+        }
+    }
+
     pub fn get_selected_exit_mut(&mut self) -> Option<&mut MapExit> {
         let selected_exit_uuid = self.course_settings.selected_exit?;
         let selected_map_index = self.course_settings.selected_map?;
@@ -727,12 +1281,193 @@ impl DisplayEngine {
             log_write("Selected map index out of bounds", LogLevel::Warn);
         }
         let selected_map = &mut self.loaded_course.level_map_data[selected_map_index];
-        let map_exit = selected_map.get_exit(&selected_exit_uuid);
-        map_exit
+        selected_map.get_exit(&selected_exit_uuid)
+    }
+
+    /// Adds an Entrance or Exit to the loaded map at a tile, then selects it in Course Settings
+    pub fn quick_create_warp(&mut self, kind: WarpKind, tile_x: u16, tile_y: u16) {
+        let Some(map_index) = self.map_index else {
+            log_write("No map loaded to create a warp on", LogLevel::Warn);
+            return;
+        };
+        if map_index >= self.loaded_course.level_map_data.len() {
+            log_write("Loaded map index out of bounds for quick-create", LogLevel::Error);
+            return;
+        }
+        self.course_settings.selected_map = Some(map_index);
+        let map_info = &mut self.loaded_course.level_map_data[map_index];
+        match kind {
+            WarpKind::Entrance => {
+                let new_uuid = map_info.add_entrance();
+                if let Some(entrance) = map_info.get_entrance_mut(&new_uuid) {
+                    entrance.entrance_x = tile_x;
+                    entrance.entrance_y = tile_y;
+                }
+                self.course_settings.selected_entrance = Some(new_uuid);
+            }
+            WarpKind::Exit => {
+                let new_uuid = map_info.add_exit();
+                if let Some(exit) = map_info.get_exit(&new_uuid) {
+                    exit.exit_x = tile_x;
+                    exit.exit_y = tile_y;
+                }
+                // New exits have error ids
+                self.loaded_course.fix_exits();
+                self.course_settings.selected_exit = Some(new_uuid);
+            }
+        }
+        log_write(format!("Quick-created {kind:?} at 0x{tile_x:X}/0x{tile_y:X}"), LogLevel::Log);
+        self.course_settings.focus_new_warp = Some(kind);
+        self.graphics_update_needed = true;
+        self.unsaved_changes = true;
+    }
+
+    /// Anything that changes loaded_map calls this, so the segment windows
+    /// check their states again instead of comparing every frame
+    pub fn map_edited(&mut self) {
+        self.unsaved_changes = true;
+        self.segment_baseline.mark_dirty();
+    }
+
+    /// Replaces the matching tiles touching map_index with the Brush, repeated
+    /// out from the clicked square like stamping. Empty Brush spots are left alone
+    pub fn flood_fill_bg(&mut self, which_bg: u8, map_index: u32) {
+        let brush = self.current_brush.clone();
+        if brush.width == 0 || brush.tiles.iter().all(Option::is_none) {
+            log_write("Cannot flood fill, the Brush is empty", LogLevel::Warn);
+            return;
+        }
+        let Some(bg) = self.loaded_map.get_background(which_bg) else { return };
+        let (Some(info), Some(map_tiles)) = (bg.get_info(), bg.get_mpbz()) else {
+            log_write(format!("No MapTiles on BG {} to flood fill",which_bg), LogLevel::Error);
+            return;
+        };
+        let layer_width = info.layer_width as u32;
+        let region = map_tiles.matching_region(map_index, info.layer_width, info.layer_height);
+        // Brush lines up with the even square that was clicked
+        let origin_x = (map_index % layer_width) as i64 & !1;
+        let origin_y = (map_index / layer_width) as i64 & !1;
+        let (brush_width, brush_height) = (brush.width as i64, brush.height.max(1) as i64);
+        let mut placed: usize = 0;
+        for index in &region {
+            let brush_x = ((*index % layer_width) as i64 - origin_x).rem_euclid(brush_width);
+            let brush_y = ((*index / layer_width) as i64 - origin_y).rem_euclid(brush_height);
+            if let Some(Some(tile)) = brush.tiles.get((brush_y * brush_width + brush_x) as usize) {
+                self.loaded_map.place_bg_tile_at_map_index(which_bg, *index, *tile);
+                placed += 1;
+            }
+        }
+        log_write(format!("Flood filled {} tiles on BG {}",placed,which_bg), LogLevel::Log);
+        if placed > 0 {
+            self.graphics_update_needed = true;
+            self.map_edited();
+        }
+    }
+
+    /// Selects every tile on the layer with the same graphics as the one at map_index
+    pub fn select_same_bg_tile(&mut self, which_bg: u8, map_index: u32) {
+        let Some(bg) = self.loaded_map.get_background(which_bg) else { return };
+        let (Some(info), Some(map_tiles)) = (bg.get_info(), bg.get_mpbz()) else { return };
+        let layer_width = info.layer_width;
+        let tile = map_tiles.get_tile(map_index);
+        let indexes = map_tiles.indexes_with_tile(tile, layer_width, info.layer_height);
+        log_write(format!("Selected {} tiles matching tile 0x{:X}",indexes.len(),tile.tile_id), LogLevel::Log);
+        self.bg_sel_data.clear();
+        self.bg_sel_data.selected_map_indexes = indexes;
+        self.bg_sel_data.selection_width = self.bg_sel_data.get_selection_width(layer_width);
+        self.bg_sel_data.selection_height = self.bg_sel_data.get_selection_height(layer_width);
+    }
+
+    /// Makes the Brush from the selection if map_index is in it, otherwise from
+    /// the 2x2 square there
+    pub fn load_brush_from_bg(&mut self, which_bg: u8, map_index: u32) {
+        let Some(bg) = self.loaded_map.get_background(which_bg) else { return };
+        let (Some(info), Some(map_tiles)) = (bg.get_info(), bg.get_mpbz()) else { return };
+        let layer_width = info.layer_width as u32;
+        let indexes: Vec<u32> = if self.bg_sel_data.selected_map_indexes.contains(&map_index) {
+            self.bg_sel_data.selected_map_indexes.clone()
+        } else {
+            let (x, y) = ((map_index % layer_width) & !1, (map_index / layer_width) & !1);
+            [(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)].into_iter()
+                .filter(|(x, y)| *x < layer_width && *y < info.layer_height as u32)
+                .map(|(x, y)| y * layer_width + x).collect()
+        };
+        let mut square = BgSelectData { selected_map_indexes: indexes, ..Default::default() };
+        let tileset = info.imbz_filename_noext.clone().unwrap_or_else(|| "N/A".to_string());
+        let Some(mut brush) = square.to_clipboard(info.layer_width, map_tiles).to_brush(tileset) else {
+            log_write("Selection is too large for a Brush", LogLevel::Warn);
+            return;
+        };
+        brush.name = String::from("From Map");
+        log_write(format!("Loaded Brush from map: {}",brush), LogLevel::Log);
+        self.current_brush = brush;
+        self.brush_settings.cur_selected_brush = Option::None;
+        self.brush_settings.current_brush_edited = false;
     }
 
 }
 
+/// A Course and one of its maps, read but not loaded. There are no graphics
+/// yet, so it can be read off the UI thread, see map_load
+pub struct LoadedCourseMap {
+    pub course_name_noext: String,
+    pub map_index: u32,
+    pub course: CourseInfo,
+    pub map: MapData
+}
+
+/// Reads a Course and one of its maps from the project, without touching what's loaded
+pub fn read_course_map(export_folder: &Path, course_name_noext: &str, label: String, map_index: u32) -> Result<LoadedCourseMap, LoadLevelError> {
+    let crsb_path = nitrofs_abs(export_folder.to_path_buf(), &format!("{}.crsb",course_name_noext));
+    let crsb = match CourseInfo::new(&crsb_path,label) {
+        Ok(c) => c,
+        Err(e) => {
+            let err_msg = LoadLevelError::FailedLoadCourse(e);
+            log_write(&err_msg, LogLevel::Error);
+            return Err(err_msg);
+        }
+    };
+    log_write(format!("Loaded Course '{}' from '{}'",&crsb.label,&crsb.src_filename), LogLevel::Log);
+    if (map_index as usize) >= crsb.level_map_data.len() {
+        let err_msg = LoadLevelError::OutOfBounds(map_index, crsb.level_map_data.len());
+        log_write(&err_msg, LogLevel::Error);
+        return Err(err_msg);
+    }
+    let noext_name = crsb.level_map_data[map_index as usize].map_filename_noext.clone();
+    let map_path = nitrofs_abs(export_folder.to_path_buf(), &format!("{}.mpdz",noext_name));
+    let mut map = match MapData::new(&map_path, export_folder) {
+        Ok(x) => x,
+        Err(e) => {
+            let err_msg = LoadLevelError::FailedLoadMapData(e);
+            log_write(&err_msg, LogLevel::Error);
+            return Err(err_msg);
+        }
+    };
+    map.map_name = noext_name;
+    Ok(LoadedCourseMap { course_name_noext: course_name_noext.to_string(), map_index, course: crsb, map })
+}
+
+/// Courses outside of the World/Level grid, see `get_level_filename_usa`
+pub const SPECIAL_COURSE_FILENAMES: [&str; 6] = ["0-1_D3", "ene_check_", "koopa3", "koopa2", "kuppa", "lastback"];
+
+/// What a special Course is, None if it's a regular one
+pub fn special_course_label(course_name_noext: &str) -> Option<&'static str> {
+    match course_name_noext {
+        "0-1_D3" => Some("Course 0-1"),
+        "ene_check_" => Some("Museum"),
+        "koopa3" | "koopa2" | "kuppa" | "lastback" => Some("Boss"),
+        _ => None
+    }
+}
+
+/// The label a special Course is loaded with, like "Museum (ene_check_)"
+pub fn special_course_title(course_name_noext: &str) -> String {
+    match special_course_label(course_name_noext) {
+        Some(label) => format!("{} ({})",label,course_name_noext),
+        None => format!("Course {}",course_name_noext)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum GetLevelFilenameError {
     MaxWorlds,
@@ -741,6 +1476,7 @@ enum GetLevelFilenameError {
     UnknownMulti99,
     FailedToGetStringAddress,
     NoBinary,
+    Arm9TooSmall(Arm9ReadError)
 }
 impl Display for GetLevelFilenameError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -751,6 +1487,7 @@ impl Display for GetLevelFilenameError {
             Self::UnknownMulti99 => f.write_str(">99 unknown multi"),
             Self::FailedToGetStringAddress => f.write_str("Failed to get string address in level name retrieval"),
             Self::NoBinary => f.write_str("NO BINARY"),
+            Self::Arm9TooSmall(error) => f.write_fmt(format_args!("{error}")),
         }
     }
 }
@@ -759,6 +1496,7 @@ impl Error for GetLevelFilenameError {}
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum LoadLevelError {
     OutOfBounds(u32, usize),
+    FailedLoadCourse(CourseParseError),
     FailedLoadMapData(MapDataError)
 }
 impl Display for LoadLevelError {
@@ -766,9 +1504,88 @@ impl Display for LoadLevelError {
         match self {
             Self::OutOfBounds(map_index, len) =>
                 f.write_fmt(format_args!("map_index was out of bounds in load_level: '{map_index}' >= '{len}'")),
+            Self::FailedLoadCourse(error) =>
+                f.write_fmt(format_args!("Failed to load Course: '{error}'")),
             Self::FailedLoadMapData(error) =>
                 f.write_fmt(format_args!("Failed to load MapData: '{error}'")),
         }
     }
 }
 impl Error for LoadLevelError {}
+
+#[cfg(test)]
+mod tests_displayengine {
+    use crate::{data::fixtures::TempFolder, engine::segment_state::SegmentState};
+
+    use super::*;
+
+    #[test]
+    fn test_read_header() {
+        let mut stamp = RomStamp::default();
+        let yaml: Value = serde_yml::from_str("gamecode: AYWE\nmakercode: '01'\n").expect("Valid yaml");
+        assert_eq!(read_header(&yaml, &mut stamp).expect("Header is complete"),GameVersion::USAXX);
+        assert_eq!(stamp.maker_code.as_deref(),Some("01"));
+        let yaml: Value = serde_yml::from_str("makercode: '01'\n").expect("Valid yaml");
+        let missing = read_header(&yaml, &mut RomStamp::default()).expect_err("No gamecode");
+        assert!(missing.to_string().contains("missing gamecode"));
+        let yaml: Value = serde_yml::from_str("gamecode: ABCD\n").expect("Valid yaml");
+        assert!(matches!(read_header(&yaml, &mut RomStamp::default()),Err(DisplayEngineError::UnknownGameCode(_))));
+    }
+
+    #[test]
+    fn test_check_arm9_tables() {
+        // The version string is the furthest in
+        let arm9 = vec![0u8; 0x0e1e6e + 6];
+        assert!(check_arm9_tables(&arm9, GameVersion::USA10).is_ok());
+        let short = check_arm9_tables(&arm9[..0x0d6f40], GameVersion::USA10).expect_err("Cut off before the palette");
+        assert!(short.to_string().starts_with("ARM9 smaller than expected for this version"));
+        assert_eq!(short.what,"Version string");
+        assert!(utils::get_sin_cos_table_value(&arm9[..0x0d1900], 0, GameVersion::USA10).is_err());
+        // USA 1.1's tables are further in
+        assert!(check_arm9_tables(&arm9, GameVersion::USA11).is_err());
+    }
+
+    #[test]
+    fn test_shift_selected_palettes() {
+        let mut de = DisplayEngine { loaded_map: crate::data::fixtures::sample_map(Path::new("test.mpdz")), ..Default::default() };
+        de.segment_baseline = SegmentBaseline::new(&de.loaded_map);
+        de.bg_sel_data.selected_map_indexes = vec![0, 9];
+        de.shift_selected_palettes(2, 1);
+        let bg2 = de.bg_layer_2.as_ref().expect("Copied after the shift");
+        let mpbz = bg2.get_mpbz().expect("Sample has MPBZ");
+        assert_eq!((mpbz.tiles[0].palette_id, mpbz.tiles[9].palette_id),(1, 2));
+        // Only the shifted layer is copied, the rest waits for a full update
+        assert!(de.bg_layer_1.is_none());
+        assert!(!de.graphics_update_needed);
+        assert!(de.unsaved_changes);
+        assert_eq!(de.segment_baseline.top_level_states(&de.loaded_map)[0],SegmentState::Modified);
+    }
+
+    #[test]
+    fn test_arm9_tables() {
+        let mut arm9 = vec![0u8; 0x0e1e6e + 6];
+        // Angle 0x20 is entry 2, stored y then x
+        arm9[0x0d1878 + 8..0x0d1878 + 12].copy_from_slice(&[0x34, 0x12, 0x00, 0x10]);
+        let tables = Arm9Tables::parse(&arm9, GameVersion::USA10).expect("Long enough");
+        assert_eq!(tables.sin_cos.len(),0x1000);
+        assert_eq!(tables.sin_cos(0x2f),PathAngle { x: 0x1000, y: 0x1234 });
+        assert_eq!(tables.sin_cos(0x20),utils::get_sin_cos_table_value(&arm9, 0x20, GameVersion::USA10).expect("Long enough"));
+    }
+
+    #[test]
+    fn test_special_course_load() {
+        let export_folder = TempFolder::new("special_course");
+        let (crsb_path, _) = crate::data::fixtures::write_sample_project(&export_folder);
+        let kuppa_path = nitrofs_abs(export_folder.to_path_buf(), "kuppa.crsb");
+        std::fs::copy(&crsb_path, &kuppa_path).expect("Temp dir is writable");
+        assert!(SPECIAL_COURSE_FILENAMES.iter().all(|name| special_course_label(name).is_some()));
+        assert_eq!(special_course_label("1-1_D3"),None);
+        assert_eq!(special_course_title("ene_check_"),"Museum (ene_check_)");
+        let loaded = read_course_map(&export_folder, "kuppa", special_course_title("kuppa"), 1).expect("Loads by file name");
+        assert_eq!(loaded.course.label,"Boss (kuppa)");
+        assert_eq!(loaded.course_name_noext,"kuppa");
+        // Saving writes back to the file it came from
+        assert_eq!(Path::new(&loaded.course.src_filename),kuppa_path.as_path());
+        assert_eq!(loaded.map.map_name,crate::data::fixtures::SAMPLE_MAP_2_NAME);
+    }
+}
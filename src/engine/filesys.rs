@@ -1,7 +1,16 @@
-use std::{error::Error, fmt::Display, path::{Path, PathBuf}};
+use std::{collections::BTreeMap, error::Error, fmt::Display, fs, io, path::{Path, PathBuf}};
 
 use ds_rom::rom::{raw, Rom, RomLoadOptions};
-use crate::utils::{self, log_write, LogLevel};
+use serde::{Deserialize, Serialize};
+use crate::{engine::project_settings::PROJECT_SETTINGS_FILENAME, load::PROJECT_SPRITE_CSV_FILENAME, utils::{self, log_write, LogLevel}};
+
+pub const MANIFEST_FILENAME: &str = "stork_manifest.json";
+/// Pristine copies of every extracted file, never written to after creation
+pub const ORIGINALS_FOLDER: &str = "originals";
+/// Folders Stork creates in the project, not part of the ROM
+const NON_ROM_FOLDERS: [&str; 3] = ["backups", "templates", ORIGINALS_FOLDER];
+/// Files the editor keeps in the project folder itself
+const NON_ROM_FILES: [&str; 3] = [MANIFEST_FILENAME, PROJECT_SETTINGS_FILENAME, PROJECT_SPRITE_CSV_FILENAME];
 
 /// Only a placeholder for now
 #[derive(Debug, Clone)]
@@ -44,6 +53,10 @@ pub fn extract_rom_files(nds_file: &Path, output_dir: &Path) -> Result<PathBuf,R
     match rom.save(output_dir, None) {
         Ok(_) => {
             log_write(format!("ROM contents extracted to '{}' successfully", &output_dir.display()), utils::LogLevel::Log);
+            // The extraction itself worked, so this failing is not fatal
+            if let Err(error) = create_manifest(output_dir) {
+                log_write(format!("Failed to create file manifest: {error}"), LogLevel::Error);
+            }
             let ret_dir = output_dir.to_path_buf();
             Ok(ret_dir)
         }
@@ -79,4 +92,250 @@ pub fn generate_rom(config: &str, new_nds_file: &str) -> Result<(),RomGenerateEr
             Ok(())
         }
     }
-}
\ No newline at end of file
+}
+#[derive(Debug)]
+pub enum ManifestError {
+    Io(String),
+    Parse(String),
+    NoOriginal(String)
+}
+impl Display for ManifestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(msg) => f.write_fmt(format_args!("File error: {msg}")),
+            Self::Parse(msg) => f.write_fmt(format_args!("Manifest is malformed: {msg}")),
+            Self::NoOriginal(path) => f.write_fmt(format_args!("No original copy of '{path}' to revert to")),
+        }
+    }
+}
+impl Error for ManifestError {}
+impl From<io::Error> for ManifestError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value.to_string())
+    }
+}
+
+#[derive(Serialize,Deserialize,Clone,Copy,Debug,PartialEq)]
+pub struct ManifestEntry {
+    pub size: u64,
+    pub crc32: u32
+}
+
+/// Keyed by path relative to the project folder, with forward slashes
+#[derive(Serialize,Deserialize,Clone,Debug,Default,PartialEq)]
+pub struct FileManifest {
+    pub files: BTreeMap<String,ManifestEntry>
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum FileChange {
+    Modified,
+    Added,
+    Removed
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct ModifiedFile {
+    pub rel_path: String,
+    pub change: FileChange,
+    pub original_size: Option<u64>,
+    pub current_size: Option<u64>
+}
+
+fn collect_rom_files(project_dir: &Path, dir: &Path, found: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            let is_stork_folder = dir == project_dir && path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| NON_ROM_FOLDERS.contains(&name));
+            if !is_stork_folder {
+                collect_rom_files(project_dir, &path, found)?;
+            }
+        } else if !(dir == project_dir && path.file_name().and_then(|name| name.to_str()).is_some_and(|name| NON_ROM_FILES.contains(&name))) {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn to_rel_path(project_dir: &Path, path: &Path) -> String {
+    let rel = path.strip_prefix(project_dir).unwrap_or(path);
+    rel.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+/// Hashes every ROM file currently in the project
+pub fn build_manifest(project_dir: &Path) -> Result<FileManifest, ManifestError> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    collect_rom_files(project_dir, project_dir, &mut paths)?;
+    let mut manifest = FileManifest::default();
+    for path in paths {
+        let bytes = fs::read(&path)?;
+        manifest.files.insert(to_rel_path(project_dir, &path), ManifestEntry {
+            size: bytes.len() as u64,
+            crc32: crc32fast::hash(&bytes)
+        });
+    }
+    Ok(manifest)
+}
+
+pub fn read_manifest(project_dir: &Path) -> Result<FileManifest, ManifestError> {
+    let text = fs::read_to_string(project_dir.join(MANIFEST_FILENAME))?;
+    serde_json::from_str(&text).map_err(|e| ManifestError::Parse(e.to_string()))
+}
+
+/// Treats the current files as the originals, writing the manifest and read-only copies
+pub fn create_manifest(project_dir: &Path) -> Result<FileManifest, ManifestError> {
+    let manifest = build_manifest(project_dir)?;
+    let originals_dir = project_dir.join(ORIGINALS_FOLDER);
+    for rel_path in manifest.files.keys() {
+        let original = originals_dir.join(rel_path);
+        if original.exists() {
+            // Read-only, and the first copy is the pristine one anyway
+            continue;
+        }
+        if let Some(parent) = original.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(project_dir.join(rel_path), &original)?;
+        let mut perms = fs::metadata(&original)?.permissions();
+        perms.set_readonly(true);
+        fs::set_permissions(&original, perms)?;
+    }
+    let pretty = serde_json::to_string_pretty(&manifest).map_err(|e| ManifestError::Parse(e.to_string()))?;
+    fs::write(project_dir.join(MANIFEST_FILENAME), pretty)?;
+    log_write(format!("Created manifest of {} files", manifest.files.len()), LogLevel::Log);
+    Ok(manifest)
+}
+
+/// Sorted by path, unchanged files are left out
+pub fn compare_manifests(original: &FileManifest, current: &FileManifest) -> Vec<ModifiedFile> {
+    let mut changes: Vec<ModifiedFile> = Vec::new();
+    for (rel_path, orig_entry) in &original.files {
+        match current.files.get(rel_path) {
+            Some(cur_entry) if cur_entry == orig_entry => { /* Unchanged */ }
+            Some(cur_entry) => changes.push(ModifiedFile {
+                rel_path: rel_path.clone(), change: FileChange::Modified,
+                original_size: Some(orig_entry.size), current_size: Some(cur_entry.size)
+            }),
+            None => changes.push(ModifiedFile {
+                rel_path: rel_path.clone(), change: FileChange::Removed,
+                original_size: Some(orig_entry.size), current_size: None
+            }),
+        }
+    }
+    for (rel_path, cur_entry) in &current.files {
+        if !original.files.contains_key(rel_path) {
+            changes.push(ModifiedFile {
+                rel_path: rel_path.clone(), change: FileChange::Added,
+                original_size: None, current_size: Some(cur_entry.size)
+            });
+        }
+    }
+    changes.sort_by(|a, b| a.rel_path.cmp(&b.rel_path));
+    changes
+}
+
+/// Copies the pristine file back over the current one
+pub fn revert_file(project_dir: &Path, rel_path: &str) -> Result<(), ManifestError> {
+    let original = project_dir.join(ORIGINALS_FOLDER).join(rel_path);
+    if !original.exists() {
+        return Err(ManifestError::NoOriginal(rel_path.to_string()));
+    }
+    // Read and write rather than copy, so the read-only flag stays with the original
+    let bytes = fs::read(&original)?;
+    let dest = project_dir.join(rel_path);
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&dest, bytes)?;
+    log_write(format!("Reverted '{}' to original", rel_path), LogLevel::Log);
+    Ok(())
+}
+
+fn is_project_root(dir: &Path) -> bool {
+    dir.join("header.yaml").is_file()
+}
+
+/// What generate_rom builds from, so a project folder without it can't export
+pub fn has_project_config(dir: &Path) -> bool {
+    dir.join("config.yaml").is_file()
+}
+
+/// Finds the project root from what was picked: the root itself, its config.yaml or
+/// header.yaml, a folder inside it like files/file, or the folder holding only it
+pub fn resolve_project_root(picked: &Path) -> Option<PathBuf> {
+    let start = if picked.is_file() { picked.parent()? } else { picked };
+    // files/file is the deepest place anyone would be
+    if let Some(root) = start.ancestors().take(3).find(|dir| is_project_root(dir)) {
+        return Some(root.to_path_buf());
+    }
+    let children: Vec<PathBuf> = fs::read_dir(start).ok()?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_dir() && is_project_root(path))
+        .collect();
+    match children.as_slice() {
+        [only] => Some(only.clone()),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests_filesys {
+    use crate::data::fixtures::TempFolder;
+
+    use super::*;
+
+    fn entry(size: u64, crc32: u32) -> ManifestEntry {
+        ManifestEntry { size, crc32 }
+    }
+
+    #[test]
+    fn test_compare_manifests() {
+        let mut original = FileManifest::default();
+        original.files.insert("files/file/a.mpdz".to_owned(), entry(4, 1));
+        original.files.insert("files/file/b.mpdz".to_owned(), entry(8, 2));
+        original.files.insert("files/file/c.crsb".to_owned(), entry(2, 3));
+        let mut current = original.clone();
+        current.files.insert("files/file/a.mpdz".to_owned(), entry(6, 7));
+        current.files.remove("files/file/c.crsb");
+        current.files.insert("files/file/d.mpdz".to_owned(), entry(1, 9));
+        let changes = compare_manifests(&original, &current);
+        assert_eq!(changes.len(),3);
+        assert_eq!(changes[0].rel_path,"files/file/a.mpdz");
+        assert_eq!(changes[0].change,FileChange::Modified);
+        assert_eq!(changes[0].current_size,Some(6));
+        assert_eq!(changes[1].change,FileChange::Removed);
+        assert_eq!(changes[2].change,FileChange::Added);
+        assert!(compare_manifests(&original, &original).is_empty());
+    }
+
+    #[test]
+    fn test_manifest_skips_stork_files() {
+        let project_dir = TempFolder::new("manifest");
+        fs::create_dir_all(project_dir.join("backups")).expect("Temp folder is writable");
+        for file in ["header.yaml", MANIFEST_FILENAME, PROJECT_SETTINGS_FILENAME, PROJECT_SPRITE_CSV_FILENAME] {
+            fs::write(project_dir.join(file), "{}").expect("Temp folder is writable");
+        }
+        fs::write(project_dir.join("backups").join("a.mpdz"), "").expect("Temp folder is writable");
+        let manifest = build_manifest(&project_dir).expect("Hashes");
+        assert_eq!(manifest.files.keys().collect::<Vec<_>>(),vec!["header.yaml"]);
+    }
+
+    #[test]
+    fn test_resolve_project_root() {
+        let parent = TempFolder::new("root");
+        let root = parent.join("project");
+        fs::create_dir_all(root.join("files").join("file")).expect("Temp folder is writable");
+        fs::write(root.join("header.yaml"), "gamecode: AYWE").expect("Temp folder is writable");
+        fs::write(root.join("config.yaml"), "").expect("Temp folder is writable");
+        assert_eq!(resolve_project_root(&root),Some(root.clone()));
+        assert_eq!(resolve_project_root(&root.join("config.yaml")),Some(root.clone()));
+        assert!(has_project_config(&root));
+        assert!(!has_project_config(&root.join("files")));
+        assert_eq!(resolve_project_root(&root.join("files").join("file")),Some(root.clone()));
+        assert_eq!(resolve_project_root(&parent),Some(root.clone()));
+        fs::create_dir_all(parent.join("other")).expect("Temp folder is writable");
+        assert_eq!(resolve_project_root(&parent.join("other")),None);
+    }
+}
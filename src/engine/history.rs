@@ -0,0 +1,393 @@
+// Undo history for the loaded map, based on egui's Undoer but with labels and jumping
+
+use std::collections::VecDeque;
+
+use crate::data::{course_file::{CourseMapInfo, MapEntrance, MapExit}, mapfile::{MapData, TopLevelSegmentWrapper}, scendata::ScenSegment, TopLevelSegment};
+
+/// Default maximum number of undo points kept
+pub const DEFAULT_MAX_UNDOS: usize = 100;
+/// Default memory budget for all undo points, in megabytes
+pub const DEFAULT_MAX_MEMORY_MB: usize = 512;
+/// Seconds the state must be unchanged before a new undo point is made
+const STABLE_TIME: f64 = 1.0;
+/// Seconds of constant changes before an undo point is forced
+const AUTO_SAVE_INTERVAL: f64 = 30.0;
+
+/// The map's Entrances and Exits, which are Course data but move with the
+/// map when it's shifted
+#[derive(Clone,Debug,PartialEq)]
+pub struct MapWarps {
+    pub entrances: Vec<MapEntrance>,
+    pub exits: Vec<MapExit>
+}
+impl MapWarps {
+    pub fn from_info(map_info: &CourseMapInfo) -> Self {
+        Self { entrances: map_info.map_entrances.clone(), exits: map_info.map_exits.clone() }
+    }
+    pub fn apply(&self, map_info: &mut CourseMapInfo) {
+        map_info.map_entrances = self.entrances.clone();
+        map_info.map_exits = self.exits.clone();
+    }
+}
+
+#[derive(Clone)]
+pub struct HistoryEntry {
+    pub state: MapData,
+    /// In egui input time, seconds since start
+    pub time: f64,
+    /// What changed since the previous entry
+    pub label: String,
+    /// Estimated bytes, see MapData::estimate_memory_size
+    pub size: usize,
+    /// None until something recorded them, see add_undo_with_warps
+    pub warps: Option<MapWarps>
+}
+impl HistoryEntry {
+    fn new(state: &MapData, time: f64, label: String, warps: Option<MapWarps>) -> Self {
+        Self { state: state.clone(), time, label, size: state.estimate_memory_size(), warps }
+    }
+}
+
+/// Where undo, redo, or a jump landed
+pub struct HistoryStep<'a> {
+    pub state: &'a MapData,
+    /// Set when the warps differ from the entry it came from, put them back too
+    pub warps: Option<&'a MapWarps>
+}
+
+#[derive(Clone)]
+struct Flux {
+    start_time: f64,
+    latest_change_time: f64,
+    latest_state: MapData
+}
+
+/// Same rules as egui's Undoer: a new point is made once the state has been
+/// stable for a second, or every 30 seconds if it never stabilizes
+#[derive(Clone)]
+pub struct MapHistory {
+    pub max_undos: usize,
+    /// Oldest undo points are dropped once all of them go over this
+    pub max_memory_mb: usize,
+    /// Newest at the back, the latest may (often) be the current state
+    undos: VecDeque<HistoryEntry>,
+    /// Stack of states undone, cleared once the state changes
+    redos: Vec<HistoryEntry>,
+    flux: Option<Flux>
+}
+impl Default for MapHistory {
+    fn default() -> Self {
+        Self {
+            max_undos: DEFAULT_MAX_UNDOS,
+            max_memory_mb: DEFAULT_MAX_MEMORY_MB,
+            undos: VecDeque::new(),
+            redos: Vec::new(),
+            flux: None
+        }
+    }
+}
+
+impl MapHistory {
+    pub fn has_undo(&self, current_state: &MapData) -> bool {
+        match self.undos.len() {
+            0 => false,
+            1 => self.undos.back().map(|e| &e.state) != Some(current_state),
+            _ => true,
+        }
+    }
+
+    pub fn has_redo(&self, current_state: &MapData) -> bool {
+        !self.redos.is_empty() && self.undos.back().map(|e| &e.state) == Some(current_state)
+    }
+
+    fn latest_warps(&self) -> Option<MapWarps> {
+        self.undos.back().and_then(|e| e.warps.clone())
+    }
+
+    /// The latest undo point, with its warps if they differ from `from_warps`
+    fn step_from(&self, from_warps: Option<MapWarps>) -> Option<HistoryStep<'_>> {
+        self.undos.back().map(|e| HistoryStep {
+            state: &e.state,
+            warps: e.warps.as_ref().filter(|warps| from_warps.as_ref() != Some(*warps))
+        })
+    }
+
+    pub fn undo(&mut self, current_state: &MapData, current_time: f64) -> Option<HistoryStep<'_>> {
+        if !self.has_undo(current_state) {
+            return None;
+        }
+        self.flux = None;
+        let from_warps = self.latest_warps();
+        if self.undos.back().map(|e| &e.state) == Some(current_state) {
+            let latest = self.undos.pop_back().expect("has_undo checks length");
+            self.redos.push(latest);
+        } else {
+            let label = describe_change(self.undos.back().map(|e| &e.state), current_state);
+            self.redos.push(HistoryEntry::new(current_state, current_time, label, from_warps.clone()));
+        }
+        // The undo point itself stays
+        self.step_from(from_warps)
+    }
+
+    pub fn redo(&mut self, current_state: &MapData) -> Option<HistoryStep<'_>> {
+        if !self.undos.is_empty() && self.undos.back().map(|e| &e.state) != Some(current_state) {
+            // Changed since the last undo, redos are no longer valid
+            self.redos.clear();
+            None
+        } else if let Some(entry) = self.redos.pop() {
+            let from_warps = self.latest_warps();
+            self.undos.push_back(entry);
+            self.step_from(from_warps)
+        } else {
+            None
+        }
+    }
+
+    /// Only adds a point if it differs from the latest one, it keeps the latest's warps
+    pub fn add_undo(&mut self, current_state: &MapData, current_time: f64) {
+        if self.undos.back().map(|e| &e.state) != Some(current_state) {
+            let label = describe_change(self.undos.back().map(|e| &e.state), current_state);
+            self.undos.push_back(HistoryEntry::new(current_state, current_time, label, self.latest_warps()));
+        }
+        self.enforce_limits();
+        self.flux = None;
+    }
+
+    /// For edits that also change the map's warps, like a shift. Call it
+    /// before and after, so stepping across the edit puts them back too
+    pub fn add_undo_with_warps(&mut self, current_state: &MapData, current_time: f64, warps: &MapWarps) {
+        // Older points had these warps as well
+        for entry in self.undos.iter_mut().chain(self.redos.iter_mut()) {
+            entry.warps.get_or_insert_with(|| warps.clone());
+        }
+        // Only the warps moved, still its own point
+        let warps_only = self.undos.back().is_some_and(|e| &e.state == current_state && e.warps.as_ref() != Some(warps));
+        if warps_only {
+            self.undos.push_back(HistoryEntry::new(current_state, current_time, String::from("Entrances/Exits"), Some(warps.clone())));
+            self.enforce_limits();
+            self.flux = None;
+            return;
+        }
+        self.add_undo(current_state, current_time);
+        if let Some(latest) = self.undos.back_mut() {
+            latest.warps = Some(warps.clone());
+        }
+    }
+
+    /// Removes all states, keeping the limits
+    pub fn clear(&mut self) {
+        self.undos.clear();
+        self.redos.clear();
+        self.flux = None;
+    }
+
+    /// Estimated bytes of every stored state
+    pub fn memory_usage(&self) -> usize {
+        self.undos.iter().chain(self.redos.iter()).map(|e| e.size).sum()
+    }
+
+    /// Drops the oldest undo points, always keeping the latest one
+    pub fn enforce_limits(&mut self) {
+        let max_bytes = self.max_memory_mb.saturating_mul(1024 * 1024);
+        let mut usage = self.memory_usage();
+        while self.undos.len() > 1 && (self.undos.len() > self.max_undos.max(1) || usage > max_bytes) {
+            if let Some(dropped) = self.undos.pop_front() {
+                usage -= dropped.size;
+            }
+        }
+    }
+
+    /// Call every frame, decides when to make new undo points
+    pub fn feed_state(&mut self, current_time: f64, current_state: &MapData) {
+        let Some(latest) = self.undos.back() else {
+            // Always have a starting point
+            self.add_undo(current_state, current_time);
+            return;
+        };
+        if &latest.state == current_state {
+            self.flux = None;
+            return;
+        }
+        self.redos.clear();
+        match self.flux.as_mut() {
+            None => {
+                self.flux = Some(Flux {
+                    start_time: current_time,
+                    latest_change_time: current_time,
+                    latest_state: current_state.clone()
+                });
+            }
+            Some(flux) => {
+                if &flux.latest_state == current_state {
+                    if current_time - flux.latest_change_time >= STABLE_TIME {
+                        self.add_undo(current_state, current_time);
+                    }
+                } else if current_time - flux.start_time >= AUTO_SAVE_INTERVAL {
+                    self.add_undo(current_state, current_time);
+                } else {
+                    flux.latest_change_time = current_time;
+                    flux.latest_state = current_state.clone();
+                }
+            }
+        }
+    }
+
+    /// Oldest first, undo points followed by redo points
+    pub fn entries(&self) -> Vec<&HistoryEntry> {
+        self.undos.iter().chain(self.redos.iter().rev()).collect()
+    }
+
+    /// Index into entries() matching the current state, if any
+    pub fn current_index(&self, current_state: &MapData) -> Option<usize> {
+        if self.undos.back().map(|e| &e.state) == Some(current_state) {
+            Some(self.undos.len() - 1)
+        } else {
+            None
+        }
+    }
+
+    /// Moves to any entry from entries(), keeping everything after it as redos
+    pub fn jump_to(&mut self, index: usize, current_state: &MapData, current_time: f64) -> Option<HistoryStep<'_>> {
+        // Don't lose changes that have not become an undo point yet
+        if self.undos.back().map(|e| &e.state) != Some(current_state) {
+            self.redos.clear();
+            self.add_undo(current_state, current_time);
+        }
+        let from_warps = self.latest_warps();
+        let undo_count = self.undos.len();
+        let mut all: Vec<HistoryEntry> = self.undos.drain(..).collect();
+        all.extend(self.redos.drain(..).rev());
+        if index >= all.len() {
+            // Put it back as it was
+            self.redos = all.split_off(undo_count).into_iter().rev().collect();
+            self.undos = all.into();
+            return None;
+        }
+        self.flux = None;
+        self.redos = all.split_off(index + 1).into_iter().rev().collect();
+        self.undos = all.into();
+        self.step_from(from_warps)
+    }
+}
+
+fn sprite_count(map: &MapData) -> usize {
+    map.segments.iter().map(|seg| match seg {
+        TopLevelSegmentWrapper::SETD(setd) => setd.sprites.len(),
+        _ => 0
+    }).sum()
+}
+
+/// Short summary of which segments differ, for display
+pub fn describe_change(before: Option<&MapData>, after: &MapData) -> String {
+    let Some(before) = before else {
+        return String::from("Initial state");
+    };
+    let mut changes: Vec<String> = Vec::new();
+    if before.segments.len() != after.segments.len() {
+        changes.push(format!("Segment count {} -> {}",before.segments.len(),after.segments.len()));
+    }
+    for (seg_before, seg_after) in before.segments.iter().zip(after.segments.iter()) {
+        if seg_before == seg_after {
+            continue;
+        }
+        match (seg_before, seg_after) {
+            (TopLevelSegmentWrapper::SETD(_), TopLevelSegmentWrapper::SETD(_)) => {
+                let (count_before, count_after) = (sprite_count(before), sprite_count(after));
+                if count_before != count_after {
+                    changes.push(format!("Sprites {} -> {}",count_before,count_after));
+                } else {
+                    changes.push(String::from("Sprites"));
+                }
+            }
+            (TopLevelSegmentWrapper::SCEN(bg_before), TopLevelSegmentWrapper::SCEN(bg_after)) => {
+                let which_bg = bg_after.get_info().map_or(0, |info| info.which_bg);
+                let changed_subs: Vec<String> = bg_before.scen_segments.iter().zip(bg_after.scen_segments.iter())
+                    .filter(|(sub_before, sub_after)| sub_before != sub_after)
+                    .map(|(_, sub_after)| sub_after.header())
+                    .collect();
+                if changed_subs.is_empty() {
+                    changes.push(format!("BG {}",which_bg));
+                } else {
+                    changes.push(format!("BG {} ({})",which_bg,changed_subs.join("/")));
+                }
+            }
+            _ => changes.push(seg_after.header()),
+        }
+    }
+    if changes.is_empty() {
+        String::from("Map properties")
+    } else {
+        changes.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests_history {
+    use super::*;
+
+    fn named_map(name: &str) -> MapData {
+        MapData { map_name: name.to_owned(), ..Default::default() }
+    }
+
+    #[test]
+    fn test_undo_redo_and_jump() {
+        let mut history = MapHistory::default();
+        let states = [named_map("a"), named_map("b"), named_map("c")];
+        for (i, state) in states.iter().enumerate() {
+            history.add_undo(state, i as f64);
+        }
+        assert_eq!(history.entries().len(),3);
+        assert_eq!(history.current_index(&states[2]),Some(2));
+        // Jump back to the first, the rest become redos
+        let jumped = history.jump_to(0, &states[2], 3.0).map(|step| step.state.clone());
+        assert!(jumped == Some(states[0].clone()));
+        assert_eq!(history.entries().len(),3);
+        assert_eq!(history.current_index(&states[0]),Some(0));
+        assert!(history.has_redo(&states[0]));
+        let redone = history.redo(&states[0]).map(|step| step.state.clone());
+        assert!(redone == Some(states[1].clone()));
+        // Out of bounds leaves it alone
+        assert!(history.jump_to(9, &states[1], 4.0).is_none());
+        assert_eq!(history.entries().len(),3);
+        assert_eq!(history.current_index(&states[1]),Some(1));
+    }
+
+    #[test]
+    fn test_warps_follow_undo() {
+        let mut history = MapHistory::default();
+        let unshifted = MapWarps { entrances: vec![MapEntrance { entrance_x: 2, ..Default::default() }], exits: Vec::new() };
+        let mut shifted = unshifted.clone();
+        shifted.entrances[0].entrance_x = 4;
+        let states = [named_map("a"), named_map("b"), named_map("c"), named_map("d")];
+        history.add_undo(&states[0], 0.0);
+        history.add_undo_with_warps(&states[1], 1.0, &unshifted);
+        history.add_undo_with_warps(&states[2], 2.0, &shifted);
+        // Later edits keep the shifted warps
+        history.add_undo(&states[3], 3.0);
+        assert!(history.undo(&states[3], 4.0).expect("Has undo").warps.is_none());
+        let step = history.undo(&states[2], 5.0).expect("Has undo");
+        assert_eq!(step.warps,Some(&unshifted));
+        let step = history.redo(&states[1]).expect("Has redo");
+        assert_eq!(step.warps,Some(&shifted));
+        // Points from before the shift are filled in too
+        let step = history.jump_to(0, &states[2], 6.0).expect("In bounds");
+        assert!(step.state == &states[0]);
+        assert_eq!(step.warps,Some(&unshifted));
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut history = MapHistory { max_undos: 2, ..Default::default() };
+        for i in 0..5 {
+            history.add_undo(&named_map(&i.to_string()), i as f64);
+        }
+        assert_eq!(history.entries().len(),2);
+        assert_eq!(history.entries()[0].state.map_name,"3");
+        // Over any memory budget still keeps the latest
+        history.max_memory_mb = 0;
+        history.enforce_limits();
+        assert_eq!(history.entries().len(),1);
+        assert_eq!(history.entries()[0].state.map_name,"4");
+        assert_eq!(history.memory_usage(),history.entries()[0].size);
+    }
+}
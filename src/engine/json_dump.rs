@@ -0,0 +1,304 @@
+// Plain JSON copy of the loaded map, for outside tools and bug reports
+//
+// Raw values are kept as they are in the files, with a few names added where
+// Stork already knows them. Only Sprites can be read back in, so scripts can
+// generate placements
+
+use std::{error::Error, fmt, fs::File, io::BufWriter, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{data::{course_file::{exit_type_name, CourseMapInfo}, mapfile::{MapData, TopLevelSegmentWrapper}, sprites::LevelSprite, types::MapTileRecordData}, load::sprite_meta, utils::{log_write, LogLevel}};
+
+/// Bumped when fields are renamed or removed, so scripts can tell
+pub const MAP_DUMP_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+pub struct MapDump {
+    pub dump_version: u32,
+    pub map_name: String,
+    pub width: u16,
+    pub height: u16,
+    pub layers: Vec<LayerDump>,
+    pub sprites: Vec<SpriteDump>,
+    pub paths: Vec<Vec<PathPointDump>>,
+    pub triggers: Vec<TriggerDump>,
+    /// Empty if the map wasn't loaded through a Course
+    pub entrances: Vec<EntranceDump>,
+    pub exits: Vec<ExitDump>,
+    /// Segments Stork keeps but doesn't understand
+    pub unknown_segments: Vec<String>
+}
+
+#[derive(Serialize)]
+pub struct LayerDump {
+    pub which_bg: u8,
+    pub layer_width: u16,
+    pub layer_height: u16,
+    pub x_offset_px: i16,
+    pub y_offset_px: i16,
+    pub x_scroll: u32,
+    pub y_scroll: u32,
+    pub layer_order: u8,
+    pub color_mode: u32,
+    pub tileset: Option<String>,
+    /// MPBZ values before the tiles start, see MapTileDataSegment
+    pub tile_offset: u16,
+    pub bottom_trim: u16,
+    /// Row by row, layer_width wide, as stored
+    pub tiles: Vec<TileDump>,
+    /// One per 2x2 tiles, only on the layer with COLZ
+    pub collision: Option<Vec<u8>>
+}
+
+#[derive(Serialize)]
+pub struct TileDump {
+    pub tile_id: u16,
+    pub palette_id: u16,
+    pub flip_h: bool,
+    pub flip_v: bool
+}
+impl From<&MapTileRecordData> for TileDump {
+    fn from(tile: &MapTileRecordData) -> Self {
+        Self { tile_id: tile.tile_id, palette_id: tile.palette_id, flip_h: tile.flip_h, flip_v: tile.flip_v }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SpriteDump {
+    pub object_id: u16,
+    pub name: Option<String>,
+    pub x: u16,
+    pub y: u16,
+    pub settings: Vec<u8>
+}
+
+/// Settings are optional, the Sprite's default length of zeroes is used if missing
+#[derive(Deserialize)]
+pub struct SpriteImport {
+    pub object_id: u16,
+    pub x: i64,
+    pub y: i64,
+    #[serde(default)]
+    pub settings: Option<Vec<u8>>
+}
+
+/// Either a plain list, or a whole map dump
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SpriteImportFile {
+    List(Vec<SpriteImport>),
+    Dump { sprites: Vec<SpriteImport> }
+}
+
+#[derive(Debug)]
+pub enum SpriteImportError {
+    Json(serde_json::Error),
+    UnknownSprite { index: usize, object_id: u16 },
+    /// Saving these would corrupt SETD
+    WrongSettingsLength { index: usize, object_id: u16, len: usize, expected: u16 }
+}
+impl fmt::Display for SpriteImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json(e) => write!(f, "Not a list of Sprites: {e}"),
+            Self::UnknownSprite { index, object_id } => write!(f, "Sprite {index} has unknown object_id 0x{object_id:X}"),
+            Self::WrongSettingsLength { index, object_id, len, expected } =>
+                write!(f, "Sprite {index} (0x{object_id:X}) has {len} settings bytes instead of {expected}"),
+        }
+    }
+}
+impl Error for SpriteImportError {}
+impl From<serde_json::Error> for SpriteImportError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Json(value)
+    }
+}
+
+/// Sprites with new UUIDs, and how many had to be moved inside the map
+///
+/// Nothing is returned if any object_id is missing from the metadata, or
+/// any settings are a different length than the metadata says
+pub fn sprites_from_json(json: &str, map_width: u16, map_height: u16) -> Result<(Vec<LevelSprite>, usize), SpriteImportError> {
+    let imports = match serde_json::from_str::<SpriteImportFile>(json)? {
+        SpriteImportFile::List(imports) => imports,
+        SpriteImportFile::Dump { sprites } => sprites
+    };
+    let mut sprites: Vec<LevelSprite> = Vec::with_capacity(imports.len());
+    let mut clamped_count: usize = 0;
+    for (index, import) in imports.into_iter().enumerate() {
+        let Some(meta) = sprite_meta(import.object_id) else {
+            return Err(SpriteImportError::UnknownSprite { index, object_id: import.object_id });
+        };
+        if meta.is_unknown() {
+            log_write(format!("Importing unknown Sprite 0x{:X}, it may crash the game",import.object_id), LogLevel::Warn);
+        }
+        let x = import.x.clamp(0, map_width.saturating_sub(1) as i64);
+        let y = import.y.clamp(0, map_height.saturating_sub(1) as i64);
+        if x != import.x || y != import.y {
+            clamped_count += 1;
+        }
+        let settings = import.settings.unwrap_or_else(|| vec![0; meta.default_settings_len as usize]);
+        if settings.len() != meta.default_settings_len as usize {
+            return Err(SpriteImportError::WrongSettingsLength { index, object_id: import.object_id,
+                len: settings.len(), expected: meta.default_settings_len });
+        }
+        sprites.push(LevelSprite::new(import.object_id, x as u16, y as u16, settings));
+    }
+    Ok((sprites, clamped_count))
+}
+
+#[derive(Serialize)]
+pub struct PathPointDump {
+    pub angle: i16,
+    pub distance: i16,
+    pub x_fine: u32,
+    pub y_fine: u32
+}
+
+#[derive(Serialize)]
+pub struct TriggerDump {
+    pub left_x: u16,
+    pub top_y: u16,
+    pub right_x: u16,
+    pub bottom_y: u16
+}
+
+#[derive(Serialize)]
+pub struct EntranceDump {
+    pub label: String,
+    pub x: u16,
+    pub y: u16,
+    pub flags: u16
+}
+
+#[derive(Serialize)]
+pub struct ExitDump {
+    pub label: String,
+    pub x: u16,
+    pub y: u16,
+    pub exit_type: u16,
+    pub exit_type_name: String,
+    /// Indexes into the Course, as saved
+    pub target_map: u8,
+    pub target_entrance: u8
+}
+
+pub fn dump_map(map: &MapData, map_info: Option<&CourseMapInfo>) -> MapDump {
+    let (width, height) = map.get_dimensions();
+    let mut dump = MapDump {
+        dump_version: MAP_DUMP_VERSION,
+        map_name: map.map_name.clone(),
+        width, height,
+        layers: Vec::new(),
+        sprites: Vec::new(),
+        paths: Vec::new(),
+        triggers: Vec::new(),
+        entrances: Vec::new(),
+        exits: Vec::new(),
+        unknown_segments: map.unhandled_headers.clone()
+    };
+    for seg in &map.segments {
+        match seg {
+            TopLevelSegmentWrapper::SCEN(scen) => {
+                let Some(info) = scen.get_info() else { continue };
+                let mpbz = scen.get_mpbz();
+                dump.layers.push(LayerDump {
+                    which_bg: info.which_bg,
+                    layer_width: info.layer_width,
+                    layer_height: info.layer_height,
+                    x_offset_px: info.x_offset_px,
+                    y_offset_px: info.y_offset_px,
+                    x_scroll: info.x_scroll,
+                    y_scroll: info.y_scroll,
+                    layer_order: info.layer_order,
+                    color_mode: info.color_mode,
+                    tileset: info.imbz_filename_noext.clone(),
+                    tile_offset: mpbz.map_or(0, |m| m.tile_offset),
+                    bottom_trim: mpbz.map_or(0, |m| m.bottom_trim),
+                    tiles: mpbz.map(|m| m.tiles.iter().map(TileDump::from).collect()).unwrap_or_default(),
+                    collision: scen.get_colz().map(|colz| colz.col_tiles.clone())
+                });
+            }
+            TopLevelSegmentWrapper::SETD(setd) => {
+                dump.sprites = setd.sprites.iter().map(|sprite| SpriteDump {
+                    object_id: sprite.object_id,
+                    name: sprite_meta(sprite.object_id).map(|meta| meta.name.clone()),
+                    x: sprite.x_position,
+                    y: sprite.y_position,
+                    settings: sprite.settings.clone()
+                }).collect();
+            }
+            TopLevelSegmentWrapper::PATH(path) => {
+                dump.paths = path.lines.iter().map(|line| line.points.iter().map(|point| PathPointDump {
+                    angle: point.angle, distance: point.distance, x_fine: point.x_fine, y_fine: point.y_fine
+                }).collect()).collect();
+            }
+            TopLevelSegmentWrapper::AREA(area) => {
+                dump.triggers = area.triggers.iter().map(|trigger| TriggerDump {
+                    left_x: trigger.left_x, top_y: trigger.top_y, right_x: trigger.right_x, bottom_y: trigger.bottom_y
+                }).collect();
+            }
+            _ => {}
+        }
+    }
+    if let Some(map_info) = map_info {
+        dump.entrances = map_info.map_entrances.iter().map(|entrance| EntranceDump {
+            label: entrance.label.clone(), x: entrance.entrance_x, y: entrance.entrance_y, flags: entrance.entrance_flags
+        }).collect();
+        dump.exits = map_info.map_exits.iter().map(|exit| ExitDump {
+            label: exit.label.clone(), x: exit.exit_x, y: exit.exit_y,
+            exit_type: exit.exit_type, exit_type_name: exit_type_name(exit.exit_type),
+            target_map: exit.target_map_raw, target_entrance: exit.target_map_entrance_raw
+        }).collect();
+    }
+    dump
+}
+
+pub fn write_map_dump(path: &Path, dump: &MapDump) -> std::io::Result<()> {
+    let writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(writer, dump)?;
+    log_write(format!("Dumped map '{}' to '{}'",&dump.map_name,path.display()), LogLevel::Log);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests_json_dump {
+    use super::*;
+    use crate::data::{area::{Trigger, TriggerData}, sprites::{LevelSprite, LevelSpriteSet}};
+
+    #[test]
+    fn test_dump_map() {
+        let mut map = MapData { map_name: String::from("test"), ..Default::default() };
+        let sprite = LevelSprite { object_id: 0x36, x_position: 4, y_position: 5, settings: vec![1, 2], ..Default::default() };
+        map.segments.push(TopLevelSegmentWrapper::SETD(LevelSpriteSet { sprites: vec![sprite] }));
+        let trigger = Trigger { left_x: 1, top_y: 2, right_x: 3, bottom_y: 4, uuid: uuid::Uuid::nil() };
+        map.segments.push(TopLevelSegmentWrapper::AREA(TriggerData { triggers: vec![trigger] }));
+        let map_info = CourseMapInfo::from_template(String::from("test"));
+        let dump = dump_map(&map, Some(&map_info));
+        let json: serde_json::Value = serde_json::to_value(&dump).unwrap();
+        assert_eq!(json["dump_version"],MAP_DUMP_VERSION);
+        assert_eq!(json["sprites"][0]["settings"],serde_json::json!([1, 2]));
+        assert_eq!(json["triggers"][0]["right_x"],3);
+        assert_eq!(json["entrances"].as_array().map(Vec::len),Some(1));
+        assert_eq!(json["exits"].as_array().map(Vec::len),Some(1));
+    }
+
+    #[test]
+    fn test_sprites_from_json() {
+        let json = r#"[{"object_id": 54, "x": 4, "y": 5, "settings": [1, 2, 0, 0]}, {"object_id": 54, "x": -3, "y": 900}]"#;
+        let (sprites, clamped) = sprites_from_json(json, 0x20, 0x10).unwrap();
+        assert_eq!(clamped,1);
+        assert_eq!((sprites[0].x_position,sprites[0].y_position),(4,5));
+        assert_eq!(sprites[0].settings,vec![1, 2, 0, 0]);
+        assert_eq!((sprites[1].x_position,sprites[1].y_position),(0,0xf));
+        assert_ne!(sprites[0].uuid,sprites[1].uuid);
+        // A dump can be read back in
+        let dump = r#"{"dump_version": 1, "sprites": [{"object_id": 54, "name": "Coin", "x": 1, "y": 1, "settings": [0, 0, 0, 0]}]}"#;
+        assert_eq!(sprites_from_json(dump, 0x20, 0x10).unwrap().0.len(),1);
+        assert!(matches!(sprites_from_json(r#"[{"object_id": 65535, "x": 0, "y": 0}]"#, 0x20, 0x10),
+            Err(SpriteImportError::UnknownSprite { index: 0, object_id: 0xffff })));
+        assert!(matches!(sprites_from_json(r#"[{"object_id": 0, "x": 0, "y": 0}, {"object_id": 54, "x": 0, "y": 0, "settings": [1, 2]}]"#, 0x20, 0x10),
+            Err(SpriteImportError::WrongSettingsLength { index: 1, object_id: 54, len: 2, expected: 4 })));
+    }
+}
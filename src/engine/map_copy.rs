@@ -0,0 +1,87 @@
+// Copies a map into another Course, without loading that Course
+//
+// The copied .mpdz is removed again if the destination Course can't be
+// updated, so a failure never leaves a map file no Course points to
+
+use std::{error::Error, fmt::Display, fs, io, path::Path};
+
+use crate::{data::course_file::{next_map_file_name, CourseInfo, CourseMapInfo, CourseParseError}, utils::{log_write, nitrofs_abs, LogLevel}};
+
+#[derive(Debug)]
+pub enum CopyMapError {
+    LoadCourse(CourseParseError),
+    FindName(io::Error),
+    /// The new map file name, then the error
+    CopyFile(String, io::Error),
+    WriteCourse(String, io::Error)
+}
+impl Display for CopyMapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::LoadCourse(error) => f.write_fmt(format_args!("Failed to load the destination Course: {error}")),
+            Self::FindName(error) => f.write_fmt(format_args!("Failed to find a free map file name: {error}")),
+            Self::CopyFile(name, error) => f.write_fmt(format_args!("Failed to copy the map to '{name}.mpdz': {error}")),
+            Self::WriteCourse(name, error) => f.write_fmt(format_args!("Failed to save Course '{name}': {error}")),
+        }
+    }
+}
+impl Error for CopyMapError {}
+
+/// Copies the saved file of `source` under a new name, and adds it to the end
+/// of the destination Course. Returns the new map's name, without the extension
+pub fn copy_map_to_course(export_folder: &Path, source: &CourseMapInfo, dest_course_noext: &str) -> Result<String, CopyMapError> {
+    let crsb_path = nitrofs_abs(export_folder.to_path_buf(), &format!("{}.crsb",dest_course_noext));
+    let mut dest_course = CourseInfo::new(&crsb_path, dest_course_noext.to_string()).map_err(CopyMapError::LoadCourse)?;
+    // Same prefix, so it sorts next to the original
+    let prefix: String = source.map_filename_noext.chars().take(3).collect();
+    let new_name = next_map_file_name(export_folder, &prefix).map_err(CopyMapError::FindName)?;
+    let source_path = nitrofs_abs(export_folder.to_path_buf(), &format!("{}.mpdz",source.map_filename_noext));
+    let new_path = nitrofs_abs(export_folder.to_path_buf(), &format!("{}.mpdz",new_name));
+    fs::copy(&source_path, &new_path).map_err(|error| CopyMapError::CopyFile(new_name.clone(), error))?;
+    dest_course.add_map_copy(source, new_name.clone());
+    // Written beside it first, so a failed write can't cut the Course short
+    let temp_path = crsb_path.with_extension("crsb.tmp");
+    let write_res = fs::write(&temp_path, dest_course.wrap()).and_then(|_| fs::rename(&temp_path, &crsb_path));
+    if let Err(error) = write_res {
+        let _ = fs::remove_file(&temp_path);
+        if let Err(remove_error) = fs::remove_file(&new_path) {
+            log_write(format!("Failed to remove copied map '{}': {remove_error}",new_path.display()), LogLevel::Error);
+        }
+        return Err(CopyMapError::WriteCourse(dest_course_noext.to_string(), error));
+    }
+    log_write(format!("Copied map '{}' to Course '{}' as '{}'",source.map_filename_noext,dest_course_noext,new_name), LogLevel::Log);
+    Ok(new_name)
+}
+
+#[cfg(test)]
+mod tests_map_copy {
+    use crate::data::fixtures::TempFolder;
+
+    use super::*;
+
+    #[test]
+    fn test_copy_map_to_course() {
+        let export_folder = TempFolder::new("map_copy");
+        fs::create_dir_all(nitrofs_abs(export_folder.to_path_buf(), "")).expect("Temp dir is writable");
+        let mut source = CourseMapInfo::from_template(String::from("01k0001"));
+        source.add_entrance();
+        source.map_exits[0].target_map_raw = 3;
+        source.map_exits[0].target_map = uuid::Uuid::new_v4();
+        fs::write(nitrofs_abs(export_folder.to_path_buf(), "01k0001.mpdz"), [1, 2, 3]).expect("Temp dir is writable");
+        // Missing Course, nothing copied
+        assert!(matches!(copy_map_to_course(&export_folder, &source, "1-2_D3"),Err(CopyMapError::LoadCourse(_))));
+        assert_eq!(next_map_file_name(&export_folder, "01k").expect("Readable"),"01k0002");
+        let mut dest = CourseInfo::default();
+        dest.level_map_data.push(CourseMapInfo::from_template(String::from("02k0001")));
+        fs::write(nitrofs_abs(export_folder.to_path_buf(), "1-2_D3.crsb"), dest.wrap()).expect("Temp dir is writable");
+        let new_name = copy_map_to_course(&export_folder, &source, "1-2_D3").expect("Copies");
+        assert_eq!(new_name,"01k0002");
+        assert_eq!(fs::read(nitrofs_abs(export_folder.to_path_buf(), "01k0002.mpdz")).expect("Copied"),vec![1, 2, 3]);
+        let saved = CourseInfo::new(&nitrofs_abs(export_folder.to_path_buf(), "1-2_D3.crsb"), String::new()).expect("Still parses");
+        assert_eq!(saved.level_map_data.len(),2);
+        assert_eq!(saved.level_map_data[1].map_filename_noext,"01k0002");
+        assert_eq!(saved.level_map_data[1].map_entrances.len(),2);
+        // Exits can't reach the old Course
+        assert_eq!(saved.level_map_data[1].map_exits[0].target_map_raw,0);
+    }
+}
@@ -0,0 +1,55 @@
+// Reads Courses and maps on another thread, so switching maps doesn't freeze the UI
+//
+// Only the file reading and parsing happens there. Swapping the result in and
+// building the graphics is still done on the UI thread, see Gui::poll_map_load
+
+use std::{path::PathBuf, sync::mpsc::{self, Receiver}, thread};
+
+use crate::utils::{log_write, LogLevel};
+
+use super::displayengine::{read_course_map, LoadLevelError, LoadedCourseMap};
+
+pub struct MapLoadJob {
+    pub export_folder: PathBuf,
+    pub course_name_noext: String,
+    pub label: String,
+    pub map_index: u32,
+    /// Tries the first map if map_index fails, since the Course may have lost maps
+    pub fallback_to_first: bool
+}
+impl MapLoadJob {
+    pub fn run(&self) -> Result<LoadedCourseMap, LoadLevelError> {
+        let res = read_course_map(&self.export_folder, &self.course_name_noext, self.label.clone(), self.map_index);
+        if res.is_err() && self.fallback_to_first && self.map_index != 0 {
+            log_write(format!("Could not return to map {}, loading the first map",self.map_index), LogLevel::Warn);
+            return read_course_map(&self.export_folder, &self.course_name_noext, self.label.clone(), 0);
+        }
+        res
+    }
+}
+
+pub fn spawn_map_load(job: MapLoadJob) -> Receiver<Result<LoadedCourseMap, LoadLevelError>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = tx.send(job.run());
+    });
+    rx
+}
+
+#[cfg(test)]
+mod tests_map_load {
+    use super::*;
+
+    #[test]
+    fn test_missing_course() {
+        let job = MapLoadJob {
+            export_folder: std::env::temp_dir().join("stork_test_no_project"),
+            course_name_noext: String::from("1-1_D3"),
+            label: String::from("Course 1-1"),
+            map_index: 2,
+            fallback_to_first: true
+        };
+        let res = spawn_map_load(job).recv().expect("Thread sends a result");
+        assert!(matches!(res, Err(LoadLevelError::FailedLoadCourse(_))));
+    }
+}
@@ -1,3 +1,13 @@
+pub mod bug_bundle;
 pub mod filesys;
 pub mod compression;
-pub mod displayengine;
\ No newline at end of file
+pub mod displayengine;
+pub mod history;
+pub mod project_settings;
+pub mod json_dump;
+pub mod map_copy;
+pub mod map_load;
+pub mod report;
+pub mod script;
+pub mod segment_state;
+pub mod verify;
@@ -0,0 +1,118 @@
+// Settings that belong to one project rather than the editor, kept in the
+// project folder next to the manifest
+//
+// Everything here is optional, so a missing or broken file just means defaults
+
+use std::{collections::BTreeMap, fs, io, path::{Path, PathBuf}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{data::types::CurrentLayer, utils::{log_write, LogLevel}};
+
+pub const PROJECT_SETTINGS_FILENAME: &str = "stork_project.json";
+/// Ctrl+1 to Ctrl+9
+pub const BOOKMARK_SLOTS: u8 = 9;
+
+#[derive(Serialize,Deserialize,Clone,Debug,PartialEq)]
+pub struct Bookmark {
+    /// 1-9, matching the number key
+    pub slot: u8,
+    pub name: String,
+    /// Center of the view, in grid pixels
+    pub x: f32,
+    pub y: f32,
+    pub layer: CurrentLayer
+}
+
+#[derive(Serialize,Deserialize,Clone,Debug,Default,PartialEq)]
+#[serde(default)]
+pub struct ProjectSettings {
+    /// Keyed by map filename without the extension, sorted by slot
+    pub bookmarks: BTreeMap<String,Vec<Bookmark>>,
+    /// Where the project was when last opened, to tell when it was moved
+    pub export_directory: Option<PathBuf>,
+    /// The last ROM exported, for Export to Last Location
+    pub last_rom_path: Option<PathBuf>
+}
+impl ProjectSettings {
+    pub fn load(project_dir: &Path) -> Self {
+        let path = project_dir.join(PROJECT_SETTINGS_FILENAME);
+        let Ok(text) = fs::read_to_string(&path) else {
+            log_write(format!("No {} in project, using defaults",PROJECT_SETTINGS_FILENAME), LogLevel::Debug);
+            return Self::default();
+        };
+        match serde_json::from_str(&text) {
+            Ok(settings) => settings,
+            Err(error) => {
+                log_write(format!("Failed to parse '{}', using defaults: {error}",path.display()), LogLevel::Warn);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, project_dir: &Path) -> io::Result<()> {
+        let pretty_string = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(project_dir.join(PROJECT_SETTINGS_FILENAME), pretty_string)
+    }
+
+    pub fn bookmarks(&self, map_name: &str) -> &[Bookmark] {
+        self.bookmarks.get(map_name).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn get_bookmark(&self, map_name: &str, slot: u8) -> Option<&Bookmark> {
+        self.bookmarks(map_name).iter().find(|b| b.slot == slot)
+    }
+
+    /// Overwrites the position of an existing slot, keeping its name
+    pub fn set_bookmark(&mut self, map_name: &str, slot: u8, x: f32, y: f32, layer: CurrentLayer) {
+        let map_bookmarks = self.bookmarks.entry(map_name.to_string()).or_default();
+        if let Some(existing) = map_bookmarks.iter_mut().find(|b| b.slot == slot) {
+            existing.x = x;
+            existing.y = y;
+            existing.layer = layer;
+            return;
+        }
+        map_bookmarks.push(Bookmark { slot, name: format!("Bookmark {}",slot), x, y, layer });
+        map_bookmarks.sort_by_key(|b| b.slot);
+    }
+
+    pub fn remove_bookmark(&mut self, map_name: &str, slot: u8) {
+        let Some(map_bookmarks) = self.bookmarks.get_mut(map_name) else {
+            return;
+        };
+        map_bookmarks.retain(|b| b.slot != slot);
+        if map_bookmarks.is_empty() {
+            self.bookmarks.remove(map_name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_project_settings {
+    use super::*;
+
+    #[test]
+    fn test_bookmarks() {
+        let mut settings = ProjectSettings::default();
+        settings.set_bookmark("1-1_main", 3, 100.0, 50.0, CurrentLayer::BG2);
+        settings.set_bookmark("1-1_main", 1, 10.0, 20.0, CurrentLayer::Sprites);
+        assert_eq!(settings.bookmarks("1-1_main").iter().map(|b| b.slot).collect::<Vec<u8>>(),vec![1, 3]);
+        settings.bookmarks.get_mut("1-1_main").expect("Has bookmarks")[1].name = String::from("Boss door");
+        settings.set_bookmark("1-1_main", 3, 200.0, 60.0, CurrentLayer::BG1);
+        let moved = settings.get_bookmark("1-1_main", 3).expect("Slot 3 is set");
+        assert_eq!(moved.name,"Boss door");
+        assert_eq!((moved.x, moved.y, moved.layer),(200.0, 60.0, CurrentLayer::BG1));
+        assert!(settings.get_bookmark("1-2_main", 3).is_none());
+        let json = serde_json::to_string(&settings).expect("Serializes");
+        assert_eq!(serde_json::from_str::<ProjectSettings>(&json).expect("Deserializes"),settings);
+        // Older files have no paths
+        let old: ProjectSettings = serde_json::from_str(r#"{"bookmarks":{}}"#).expect("Deserializes");
+        assert!(old.last_rom_path.is_none());
+        settings.last_rom_path = Some(PathBuf::from("out/rom.nds"));
+        let json = serde_json::to_string(&settings).expect("Serializes");
+        assert_eq!(serde_json::from_str::<ProjectSettings>(&json).expect("Deserializes").last_rom_path,settings.last_rom_path);
+        settings.remove_bookmark("1-1_main", 1);
+        settings.remove_bookmark("1-1_main", 3);
+        assert!(settings.bookmarks.is_empty());
+    }
+}
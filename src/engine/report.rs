@@ -0,0 +1,417 @@
+// Printable summary of every map in a Course, for design docs
+//
+// Loading and rendering every map takes a while, so it runs on its own
+// thread and reports back through a channel
+
+use std::{collections::BTreeMap, error::Error, fmt, fs::{self, File}, io::BufWriter, path::{Path, PathBuf}, sync::mpsc::{self, Receiver, Sender}, thread};
+
+use crate::{data::{course_file::{exit_type_name, CourseInfo, CourseMapInfo}, mapfile::{MapData, MapDataError, TopLevelSegmentWrapper}, types::Palette}, gui::windows::course_win::get_course_music_name, load::sprite_meta, utils::{self, log_write, nitrofs_abs, LogLevel}};
+
+use super::displayengine::assign_bg_palettes;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Html,
+    Markdown
+}
+impl fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Html => write!(f, "HTML"),
+            Self::Markdown => write!(f, "Markdown"),
+        }
+    }
+}
+impl ReportFormat {
+    /// Markdown for .md files, HTML for everything else
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("md") => Self::Markdown,
+            _ => Self::Html,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ReportError {
+    Io(std::io::Error),
+    Png(png::EncodingError),
+    NoMaps
+}
+impl fmt::Display for ReportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Failed to write report: {e}"),
+            Self::Png(e) => write!(f, "Failed to write thumbnail: {e}"),
+            Self::NoMaps => write!(f, "Course has no maps to report on"),
+        }
+    }
+}
+impl Error for ReportError {}
+impl From<std::io::Error> for ReportError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+impl From<png::EncodingError> for ReportError {
+    fn from(value: png::EncodingError) -> Self {
+        Self::Png(value)
+    }
+}
+
+pub enum ReportMessage {
+    /// Maps done, total maps
+    Progress(usize, usize),
+    /// The written report file
+    Finished(Result<PathBuf, ReportError>)
+}
+
+/// Everything the report thread needs, copied so the UI can keep going
+pub struct ReportJob {
+    pub course: CourseInfo,
+    pub export_folder: PathBuf,
+    pub universal_palette: Palette,
+    /// Used instead of the file when it matches, since it may have unsaved changes
+    pub loaded_map: Option<MapData>,
+    /// Thumbnails are written next to it
+    pub report_path: PathBuf,
+    pub format: ReportFormat
+}
+
+struct ExitRow {
+    label: String,
+    x: u16,
+    y: u16,
+    exit_type: u16,
+    target: String
+}
+
+struct MapReport {
+    map_index: usize,
+    label: String,
+    file_name: String,
+    width: u16,
+    height: u16,
+    music: u8,
+    /// Label, x, y
+    entrances: Vec<(String, u16, u16)>,
+    exits: Vec<ExitRow>,
+    /// Object ID to count
+    sprites: BTreeMap<u16, usize>,
+    /// Collision type to count, excluding empty
+    collision: BTreeMap<u8, usize>,
+    collision_total: usize,
+    /// File name relative to the report
+    thumbnail: Option<String>,
+    load_error: Option<String>
+}
+
+pub fn spawn_report(job: ReportJob) -> Receiver<ReportMessage> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let result = write_report(job, &tx);
+        let _ = tx.send(ReportMessage::Finished(result));
+    });
+    rx
+}
+
+fn write_report(mut job: ReportJob, tx: &Sender<ReportMessage>) -> Result<PathBuf, ReportError> {
+    let total = job.course.level_map_data.len();
+    if total == 0 {
+        return Err(ReportError::NoMaps);
+    }
+    log_write(format!("Writing level report for '{}' to '{}'",&job.course.label,job.report_path.display()), LogLevel::Log);
+    let out_dir = job.report_path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let base_name = job.report_path.file_stem().map_or(String::from("report"), |stem| stem.to_string_lossy().to_string());
+    let mut reports: Vec<MapReport> = Vec::new();
+    for (map_index, map_info) in job.course.level_map_data.iter().enumerate() {
+        let _ = tx.send(ReportMessage::Progress(map_index, total));
+        let mut report = MapReport::new(map_index, map_info, &job.course);
+        let map_res = match job.loaded_map.take_if(|map| map.map_name == map_info.map_filename_noext) {
+            Some(map) => Ok(map),
+            None => load_map(&job.export_folder, &map_info.map_filename_noext),
+        };
+        match map_res {
+            Err(e) => {
+                log_write(format!("Level report could not load '{}': {e}",&map_info.map_filename_noext), LogLevel::Warn);
+                report.load_error = Some(e.to_string());
+            }
+            Ok(mut map) => {
+                report.read_map(&map);
+                let (width_px, height_px, pixels) = composite_map_image(&mut map, &job.universal_palette);
+                if !pixels.is_empty() {
+                    let thumb_name = format!("{}_map{:02}.png",base_name,map_index);
+                    write_png(&out_dir.join(&thumb_name), width_px, height_px, &pixels)?;
+                    report.thumbnail = Some(thumb_name);
+                }
+            }
+        }
+        reports.push(report);
+    }
+    let contents = match job.format {
+        ReportFormat::Html => build_html(&job.course, &reports),
+        ReportFormat::Markdown => build_markdown(&job.course, &reports),
+    };
+    fs::write(&job.report_path, contents)?;
+    let _ = tx.send(ReportMessage::Progress(total, total));
+    log_write(format!("Level report written to '{}'",job.report_path.display()), LogLevel::Log);
+    Ok(job.report_path)
+}
+
+fn load_map(export_folder: &Path, map_filename_noext: &str) -> Result<MapData, MapDataError> {
+    let map_path = nitrofs_abs(export_folder.to_path_buf(), &format!("{}.mpdz",map_filename_noext));
+    MapData::new(&map_path, export_folder)
+}
+
+impl MapReport {
+    fn new(map_index: usize, map_info: &CourseMapInfo, course: &CourseInfo) -> Self {
+        let entrances = map_info.map_entrances.iter()
+            .map(|entrance| (entrance.label.clone(), entrance.entrance_x, entrance.entrance_y))
+            .collect();
+        let exits = map_info.map_exits.iter().map(|exit| {
+            let target_map = course.level_map_data.iter().enumerate()
+                .find(|(_, map)| map.uuid == exit.target_map);
+            let target = match target_map {
+                None => String::from("None"),
+                Some((target_index, target_map)) => {
+                    let entrance_label = target_map.map_entrances.iter()
+                        .find(|entrance| entrance.uuid == exit.target_map_entrance)
+                        .map_or("Unknown Entrance", |entrance| entrance.label.as_str());
+                    format!("Map {}: {} / {}",target_index,target_map.label,entrance_label)
+                }
+            };
+            ExitRow { label: exit.label.clone(), x: exit.exit_x, y: exit.exit_y, exit_type: exit.exit_type, target }
+        }).collect();
+        Self {
+            map_index,
+            label: map_info.label.clone(),
+            file_name: map_info.map_filename_noext.clone(),
+            width: 0,
+            height: 0,
+            music: map_info.map_music,
+            entrances,
+            exits,
+            sprites: BTreeMap::new(),
+            collision: BTreeMap::new(),
+            collision_total: 0,
+            thumbnail: None,
+            load_error: None
+        }
+    }
+
+    fn read_map(&mut self, map: &MapData) {
+        (self.width, self.height) = map.get_dimensions();
+        for seg in &map.segments {
+            match seg {
+                TopLevelSegmentWrapper::SETD(setd) => {
+                    for sprite in &setd.sprites {
+                        *self.sprites.entry(sprite.object_id).or_default() += 1;
+                    }
+                }
+                TopLevelSegmentWrapper::SCEN(scen) => {
+                    if let Some(colz) = scen.get_colz() {
+                        self.collision_total += colz.col_tiles.len();
+                        for col_tile in colz.col_tiles.iter().filter(|t| **t != 0x00) {
+                            *self.collision.entry(*col_tile).or_default() += 1;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn solid_percent(&self) -> f32 {
+        if self.collision_total == 0 {
+            return 0.0;
+        }
+        let filled: usize = self.collision.values().sum();
+        filled as f32 * 100.0 / self.collision_total as f32
+    }
+}
+
+fn sprite_name(object_id: u16) -> String {
+    sprite_meta(object_id).map_or(String::from("Unknown"), |meta| meta.name.clone())
+}
+
+/// Renders the BGs in priority order like the main grid, at one pixel per pixel
+///
+/// Returns width, height, and RGBA bytes. Empty if there are no BGs
+pub fn composite_map_image(map: &mut MapData, universal_palette: &Palette) -> (u32, u32, Vec<u8>) {
+    let mut palettes: [Palette;16] = [Palette::default();16];
+    palettes[0] = *universal_palette;
+    assign_bg_palettes(map, &mut palettes);
+    let (width, height) = map.get_dimensions();
+    let (width_px, height_px) = (width as u32 * 8, height as u32 * 8);
+    if width_px == 0 || height_px == 0 {
+        return (0, 0, Vec::new());
+    }
+    // Backdrop is the first universal color, same as the gradient fallback
+    let backdrop = universal_palette.colors[0].color;
+    let mut pixels: Vec<u8> = [backdrop.r(), backdrop.g(), backdrop.b(), 0xFF].repeat((width_px * height_px) as usize);
+    for which_bg in map.bg_draw_order() {
+        let Some(bg) = map.get_background(which_bg) else { continue };
+        let (Some(info), Some(map_tiles), Some(pixel_tiles)) = (bg.get_info(), bg.get_mpbz(), &bg.pixel_tiles_preview) else { continue };
+        let is_256 = info.is_256_colorpal_mode();
+        let bytes_per_tile: usize = if is_256 { 64 } else { 32 };
+        let pal_256 = bg.get_pltb().and_then(|pltb| pltb.palettes.first());
+        let layer_width = info.layer_width as u32;
+        for map_index in 0..(layer_width * info.layer_height as u32) {
+            let tile = map_tiles.get_tile(map_index);
+            if (tile.tile_id as usize + 1) * bytes_per_tile > pixel_tiles.len() {
+                continue;
+            }
+            let (palette, color_indexes) = if is_256 {
+                let Some(pal) = pal_256 else { continue };
+                (pal, utils::get_pixel_bytes_256(pixel_tiles, &tile.tile_id))
+            } else {
+                let pal_id = tile.get_render_pal_id(bg._pal_offset, info.color_mode);
+                let Some(pal) = palettes.get(pal_id) else { continue };
+                (pal, utils::pixel_byte_array_to_nibbles(&utils::get_pixel_bytes_16(pixel_tiles, &tile.tile_id)))
+            };
+            let tile_x = (map_index % layer_width) as i32 * 8 - info.x_offset_px as i32;
+            let tile_y = (map_index / layer_width) as i32 * 8 - info.y_offset_px as i32;
+            for (pixel_index, color_index) in color_indexes.iter().enumerate() {
+                if *color_index == 0 {
+                    continue; // Transparent
+                }
+                let mut px = (pixel_index % 8) as i32;
+                let mut py = (pixel_index / 8) as i32;
+                if tile.flip_h { px = 7 - px; }
+                if tile.flip_v { py = 7 - py; }
+                let (x, y) = (tile_x + px, tile_y + py);
+                if x < 0 || y < 0 || x >= width_px as i32 || y >= height_px as i32 {
+                    continue;
+                }
+                let color = palette.colors[*color_index as usize].color;
+                let offset = ((y as u32 * width_px + x as u32) * 4) as usize;
+                pixels[offset..offset + 4].copy_from_slice(&[color.r(), color.g(), color.b(), 0xFF]);
+            }
+        }
+    }
+    (width_px, height_px, pixels)
+}
+
+fn write_png(path: &Path, width: u32, height: u32, rgba: &[u8]) -> Result<(), ReportError> {
+    let file = File::create(path)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgba)?;
+    Ok(())
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn escape_markdown(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+fn build_html(course: &CourseInfo, reports: &[MapReport]) -> String {
+    let mut html = String::new();
+    let title = escape_html(&format!("{} ({})",course.label,course.src_filename));
+    html.push_str(&format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n"));
+    html.push_str("<style>body{font-family:sans-serif} table{border-collapse:collapse;margin-bottom:1em} td,th{border:1px solid #888;padding:2px 6px} img{max-width:100%;image-rendering:pixelated} section{page-break-inside:avoid}</style>\n");
+    html.push_str(&format!("</head>\n<body>\n<h1>{title}</h1>\n<p>{} maps</p>\n",reports.len()));
+    for report in reports {
+        html.push_str("<section>\n");
+        html.push_str(&format!("<h2>Map {}: {}</h2>\n",report.map_index,escape_html(&report.label)));
+        html.push_str(&format!("<p>File: {}.mpdz<br>Music: 0x{:02X} ({})<br>",
+            escape_html(&report.file_name),report.music,escape_html(&get_course_music_name(report.music))));
+        if let Some(error) = &report.load_error {
+            html.push_str(&format!("Failed to load: {}</p>\n</section>\n",escape_html(error)));
+            continue;
+        }
+        html.push_str(&format!("Size: {}x{} tiles ({}x{} px)</p>\n",report.width,report.height,report.width as u32*8,report.height as u32*8));
+        if let Some(thumb) = &report.thumbnail {
+            html.push_str(&format!("<img src=\"{}\" alt=\"Map {}\">\n",escape_html(thumb),report.map_index));
+        }
+        html.push_str("<h3>Entrances</h3>\n<table>\n<tr><th>#</th><th>Label</th><th>X</th><th>Y</th></tr>\n");
+        for (index, (label, x, y)) in report.entrances.iter().enumerate() {
+            html.push_str(&format!("<tr><td>{index}</td><td>{}</td><td>0x{x:X}</td><td>0x{y:X}</td></tr>\n",escape_html(label)));
+        }
+        html.push_str("</table>\n<h3>Exits</h3>\n<table>\n<tr><th>#</th><th>Label</th><th>X</th><th>Y</th><th>Type</th><th>Target</th></tr>\n");
+        for (index, exit) in report.exits.iter().enumerate() {
+            html.push_str(&format!("<tr><td>{index}</td><td>{}</td><td>0x{:X}</td><td>0x{:X}</td><td>{}</td><td>{}</td></tr>\n",
+                escape_html(&exit.label),exit.x,exit.y,escape_html(&exit_type_name(exit.exit_type)),escape_html(&exit.target)));
+        }
+        html.push_str(&format!("</table>\n<h3>Sprites ({})</h3>\n<table>\n<tr><th>ID</th><th>Name</th><th>Count</th></tr>\n",report.sprites.values().sum::<usize>()));
+        for (object_id, count) in &report.sprites {
+            html.push_str(&format!("<tr><td>0x{object_id:03X}</td><td>{}</td><td>{count}</td></tr>\n",escape_html(&sprite_name(*object_id))));
+        }
+        html.push_str(&format!("</table>\n<h3>Collision</h3>\n<p>{:.1}% of {} tiles filled</p>\n<table>\n<tr><th>Type</th><th>Count</th></tr>\n",
+            report.solid_percent(),report.collision_total));
+        for (col_type, count) in &report.collision {
+            html.push_str(&format!("<tr><td>0x{col_type:02X}</td><td>{count}</td></tr>\n"));
+        }
+        html.push_str("</table>\n</section>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn build_markdown(course: &CourseInfo, reports: &[MapReport]) -> String {
+    let mut md = String::new();
+    md.push_str(&format!("# {} ({})\n\n{} maps\n\n",escape_markdown(&course.label),course.src_filename,reports.len()));
+    for report in reports {
+        md.push_str(&format!("## Map {}: {}\n\n",report.map_index,escape_markdown(&report.label)));
+        md.push_str(&format!("- File: {}.mpdz\n- Music: 0x{:02X} ({})\n",report.file_name,report.music,get_course_music_name(report.music)));
+        if let Some(error) = &report.load_error {
+            md.push_str(&format!("- Failed to load: {}\n\n",error));
+            continue;
+        }
+        md.push_str(&format!("- Size: {}x{} tiles ({}x{} px)\n\n",report.width,report.height,report.width as u32*8,report.height as u32*8));
+        if let Some(thumb) = &report.thumbnail {
+            md.push_str(&format!("![Map {}]({})\n\n",report.map_index,thumb));
+        }
+        md.push_str("### Entrances\n\n| # | Label | X | Y |\n|---|---|---|---|\n");
+        for (index, (label, x, y)) in report.entrances.iter().enumerate() {
+            md.push_str(&format!("| {index} | {} | 0x{x:X} | 0x{y:X} |\n",escape_markdown(label)));
+        }
+        md.push_str("\n### Exits\n\n| # | Label | X | Y | Type | Target |\n|---|---|---|---|---|---|\n");
+        for (index, exit) in report.exits.iter().enumerate() {
+            md.push_str(&format!("| {index} | {} | 0x{:X} | 0x{:X} | {} | {} |\n",
+                escape_markdown(&exit.label),exit.x,exit.y,exit_type_name(exit.exit_type),escape_markdown(&exit.target)));
+        }
+        md.push_str(&format!("\n### Sprites ({})\n\n| ID | Name | Count |\n|---|---|---|\n",report.sprites.values().sum::<usize>()));
+        for (object_id, count) in &report.sprites {
+            md.push_str(&format!("| 0x{object_id:03X} | {} | {count} |\n",escape_markdown(&sprite_name(*object_id))));
+        }
+        md.push_str(&format!("\n### Collision\n\n{:.1}% of {} tiles filled\n\n| Type | Count |\n|---|---|\n",
+            report.solid_percent(),report.collision_total));
+        for (col_type, count) in &report.collision {
+            md.push_str(&format!("| 0x{col_type:02X} | {count} |\n"));
+        }
+        md.push('\n');
+    }
+    md
+}
+
+#[cfg(test)]
+mod tests_report {
+    use super::*;
+
+    #[test]
+    fn test_report_format_from_path() {
+        assert_eq!(ReportFormat::from_path(Path::new("out/report.md")),ReportFormat::Markdown);
+        assert_eq!(ReportFormat::from_path(Path::new("out/report.MD")),ReportFormat::Markdown);
+        assert_eq!(ReportFormat::from_path(Path::new("out/report.html")),ReportFormat::Html);
+        assert_eq!(ReportFormat::from_path(Path::new("out/report")),ReportFormat::Html);
+    }
+
+    #[test]
+    fn test_composite_empty_map() {
+        let mut map = MapData::default();
+        let (width, height, pixels) = composite_map_image(&mut map, &Palette::default());
+        assert_eq!((width, height),(0, 0));
+        assert!(pixels.is_empty());
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(escape_html("<a & \"b\">"),"&lt;a &amp; &quot;b&quot;&gt;");
+    }
+}
@@ -0,0 +1,504 @@
+// Small batch scripts for edits with no UI of their own, like moving every
+// Sprite of one ID on a set of maps. Scripts are Rhai (https://rhai.rs), with
+// these functions on top of the standard ones. Numbers are tiles, and a Sprite
+// is a map of #{uuid, id, x, y, settings}:
+//   map_name()                          the file name without the extension
+//   sprites() / sprites(id)             every Sprite, or only ones with that object ID
+//   move_sprites(list, dx, dy)          move a list of Sprites
+//   delete_sprites(list)                delete a list of Sprites
+//   set_sprite_id(list, id)             change their object ID
+//   set_sprite_settings(list, "hex")    replace their settings, same length only
+//   add_sprite(id, x, y)                place a new Sprite with default settings
+//   tile(bg, x, y)                      read a BG tile, the full 16 bit value with palette and flips
+//   set_tile(bg, x, y, tile)            place a BG tile
+//   clear_tile(bg, x, y)                remove a BG tile
+//   collision(x, y) / set_collision(x, y, type)   the collision under a tile
+// A top level `const MAPS = "01k*";` (or an array of them) limits which maps
+// it runs on, a trailing * matches any ending
+//
+// Every edit goes through the same MapData functions the editor uses, and a
+// map past the format limits afterwards is never applied or saved
+
+use std::{error::Error, fmt, fs, path::{Path, PathBuf}, sync::{mpsc::{self, Receiver}, Arc, Mutex}, thread};
+
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, AST};
+use uuid::Uuid;
+
+use crate::{data::mapfile::MapData, utils::{backup_file, log_write, string_to_settings, LogLevel}};
+
+use super::verify::find_project_files;
+
+/// Stops a runaway loop, the loaded map's script runs on the UI thread
+const MAX_OPERATIONS: u64 = 5_000_000;
+
+#[derive(Clone)]
+pub struct Script {
+    pub ast: AST,
+    /// Empty means every map
+    pub map_patterns: Vec<String>
+}
+
+#[derive(Debug)]
+pub enum ScriptError {
+    Parse { line: usize, message: String },
+    BadMaps,
+    ListMaps(std::io::Error)
+}
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse { line, message } => write!(f, "Line {line}: {message}"),
+            Self::BadMaps => write!(f, "MAPS must be a map name or an array of map names"),
+            Self::ListMaps(e) => write!(f, "Could not list the project's maps: {e}"),
+        }
+    }
+}
+impl Error for ScriptError {}
+
+/// Checks the whole script up front, so a typo never leaves it half run
+pub fn parse_script(text: &str) -> Result<Script, ScriptError> {
+    let ast = Engine::new().compile(text).map_err(|e| ScriptError::Parse {
+        line: e.1.line().unwrap_or_default(),
+        message: e.0.to_string()
+    })?;
+    let mut map_patterns: Vec<String> = Vec::new();
+    for (name, _, value) in ast.iter_literal_variables(true, false) {
+        if name != "MAPS" {
+            continue;
+        }
+        let values = if value.is_array() { value.into_array().unwrap_or_default() } else { vec![value] };
+        for pattern in values {
+            map_patterns.push(pattern.into_string().map_err(|_| ScriptError::BadMaps)?);
+        }
+    }
+    Ok(Script { ast, map_patterns })
+}
+
+impl Script {
+    pub fn applies_to(&self, map_name: &str) -> bool {
+        self.map_patterns.is_empty() || self.map_patterns.iter().any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => map_name.starts_with(prefix),
+            None => map_name == pattern,
+        })
+    }
+}
+
+/// File name without the extension, which is what MAPS matches
+pub fn script_map_name(map: &MapData) -> String {
+    Path::new(&map.src_file).file_stem().unwrap_or_default().to_string_lossy().to_string()
+}
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// What the registered functions share while a script runs
+struct ScriptContext {
+    map: MapData,
+    changes: Vec<String>
+}
+
+fn arg<T: TryFrom<i64>>(value: i64) -> ScriptResult<T> {
+    T::try_from(value).map_err(|_| format!("{value} is out of range").into())
+}
+
+fn bg_size(map: &mut MapData, bg: u8) -> ScriptResult<(u32, u32)> {
+    map.get_background(bg).and_then(|layer| layer.get_info())
+        .map(|info| (info.layer_width as u32, info.layer_height as u32))
+        .ok_or_else(|| format!("There is no BG {bg}").into())
+}
+
+fn bg_index(map: &mut MapData, bg: u8, x: u32, y: u32) -> ScriptResult<u32> {
+    if !(1..=3).contains(&bg) {
+        return Err(format!("BG must be 1, 2, or 3, not {bg}").into());
+    }
+    let (width, height) = bg_size(map, bg)?;
+    if x >= width || y >= height {
+        return Err(format!("{x},{y} is outside BG {bg} ({width}x{height})").into());
+    }
+    Ok(x + y * width)
+}
+
+fn read_tile(map: &mut MapData, bg: u8, map_index: u32) -> u16 {
+    map.get_background(bg).and_then(|layer| layer.get_mpbz())
+        .and_then(|mpbz| mpbz.tile_index(map_index).map(|i| mpbz.tiles[i].to_short()))
+        .unwrap_or(0)
+}
+
+/// Collision tiles are 2x2, returns the BG with them and the index
+fn col_index(map: &mut MapData, x: u32, y: u32) -> ScriptResult<(u8, usize)> {
+    let bg = map.get_bg_with_colz().ok_or("Map has no collision")?;
+    let (width, height) = bg_size(map, bg)?;
+    if x >= width || y >= height {
+        return Err(format!("{x},{y} is outside the map").into());
+    }
+    Ok((bg, ((y / 2) * (width / 2) + (x / 2)) as usize))
+}
+
+fn sprite_uuids(list: &Array) -> ScriptResult<Vec<Uuid>> {
+    list.iter().map(|item| {
+        item.read_lock::<Map>()
+            .and_then(|sprite| sprite.get("uuid").and_then(|uuid| uuid.clone().into_string().ok()))
+            .and_then(|uuid| Uuid::parse_str(&uuid).ok())
+            .ok_or_else(|| "Expected a list of Sprites from sprites()".into())
+    }).collect()
+}
+
+fn script_engine(context: &Arc<Mutex<ScriptContext>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.on_print(|text| log_write(format!("Script: {text}"), LogLevel::Log));
+    let ctx = context.clone();
+    engine.register_fn("map_name", move || script_map_name(&ctx.lock().unwrap().map));
+    let ctx = context.clone();
+    let list_sprites = move |id: Option<u16>| -> ScriptResult<Array> {
+        let mut context = ctx.lock().unwrap();
+        let setd = context.map.get_setd().ok_or("Map has no SETD")?;
+        Ok(setd.sprites.iter().filter(|spr| id.is_none_or(|id| spr.object_id == id)).map(|spr| {
+            let mut sprite = Map::new();
+            sprite.insert("uuid".into(), spr.uuid.to_string().into());
+            sprite.insert("id".into(), (spr.object_id as i64).into());
+            sprite.insert("x".into(), (spr.x_position as i64).into());
+            sprite.insert("y".into(), (spr.y_position as i64).into());
+            let settings: Array = spr.settings.iter().map(|byte| Dynamic::from(*byte as i64)).collect();
+            sprite.insert("settings".into(), settings.into());
+            Dynamic::from_map(sprite)
+        }).collect())
+    };
+    let all_sprites = list_sprites.clone();
+    engine.register_fn("sprites", move || all_sprites(None));
+    engine.register_fn("sprites", move |id: i64| list_sprites(Some(arg(id)?)));
+    let ctx = context.clone();
+    engine.register_fn("move_sprites", move |list: Array, dx: i64, dy: i64| -> ScriptResult<()> {
+        let uuids = sprite_uuids(&list)?;
+        let (dx, dy): (i32, i32) = (arg(dx)?, arg(dy)?);
+        let mut context = ctx.lock().unwrap();
+        let moved = context.map.move_sprites(&uuids, dx, dy);
+        if moved > 0 {
+            context.changes.push(format!("Moved {moved} Sprites by {dx}/{dy}"));
+        }
+        Ok(())
+    });
+    let ctx = context.clone();
+    engine.register_fn("delete_sprites", move |list: Array| -> ScriptResult<()> {
+        let uuids = sprite_uuids(&list)?;
+        let mut context = ctx.lock().unwrap();
+        let deleted = context.map.delete_sprites(&uuids);
+        if deleted > 0 {
+            context.changes.push(format!("Deleted {deleted} Sprites"));
+        }
+        Ok(())
+    });
+    let ctx = context.clone();
+    engine.register_fn("set_sprite_id", move |list: Array, new_id: i64| -> ScriptResult<()> {
+        let uuids = sprite_uuids(&list)?;
+        let new_id: u16 = arg(new_id)?;
+        let mut context = ctx.lock().unwrap();
+        for uuid in &uuids {
+            if !context.map.change_sprite_object_id(*uuid, new_id) {
+                return Err(format!("Could not change Sprites to 0x{new_id:X}").into());
+            }
+        }
+        if !uuids.is_empty() {
+            context.changes.push(format!("Changed {} Sprites to 0x{new_id:X}",uuids.len()));
+        }
+        Ok(())
+    });
+    let ctx = context.clone();
+    engine.register_fn("set_sprite_settings", move |list: Array, settings: &str| -> ScriptResult<()> {
+        let uuids = sprite_uuids(&list)?;
+        let settings = string_to_settings(settings).map_err(|e| format!("Bad settings: {e}"))?;
+        let mut context = ctx.lock().unwrap();
+        for uuid in &uuids {
+            let Some(sprite) = context.map.get_sprite_by_uuid(*uuid) else { continue };
+            if sprite.settings.len() != settings.len() {
+                return Err(format!("Sprite 0x{:X} has {} bytes of settings, not {}",sprite.object_id,sprite.settings.len(),settings.len()).into());
+            }
+            context.map.update_sprite_settings(*uuid, settings.clone());
+        }
+        if !uuids.is_empty() {
+            context.changes.push(format!("Set the settings of {} Sprites",uuids.len()));
+        }
+        Ok(())
+    });
+    let ctx = context.clone();
+    engine.register_fn("add_sprite", move |id: i64, x: i64, y: i64| -> ScriptResult<()> {
+        let (id, x, y): (u16, u16, u16) = (arg(id)?, arg(x)?, arg(y)?);
+        let mut context = ctx.lock().unwrap();
+        let (width, height) = context.map.get_dimensions();
+        if x >= width || y >= height {
+            return Err(format!("{x},{y} is outside the map").into());
+        }
+        if context.map.add_new_sprite_at(id, x, y).is_nil() {
+            return Err(format!("Could not add Sprite 0x{id:X}").into());
+        }
+        context.changes.push(format!("Added Sprite 0x{id:X} at {x},{y}"));
+        Ok(())
+    });
+    let ctx = context.clone();
+    engine.register_fn("tile", move |bg: i64, x: i64, y: i64| -> ScriptResult<i64> {
+        let (bg, x, y): (u8, u32, u32) = (arg(bg)?, arg(x)?, arg(y)?);
+        let map = &mut ctx.lock().unwrap().map;
+        let map_index = bg_index(map, bg, x, y)?;
+        Ok(read_tile(map, bg, map_index) as i64)
+    });
+    let ctx = context.clone();
+    engine.register_fn("set_tile", move |bg: i64, x: i64, y: i64, tile: i64| -> ScriptResult<()> {
+        let (bg, x, y, tile): (u8, u32, u32, u16) = (arg(bg)?, arg(x)?, arg(y)?, arg(tile)?);
+        let mut context = ctx.lock().unwrap();
+        let map_index = bg_index(&mut context.map, bg, x, y)?;
+        if read_tile(&mut context.map, bg, map_index) == tile {
+            return Ok(());
+        }
+        if !context.map.place_bg_tile_at_map_index(bg, map_index, tile) {
+            return Err(format!("Could not place a tile on BG {bg}").into());
+        }
+        context.changes.push(format!("Placed tile 0x{tile:04X} at {x},{y} on BG {bg}"));
+        Ok(())
+    });
+    let ctx = context.clone();
+    engine.register_fn("clear_tile", move |bg: i64, x: i64, y: i64| -> ScriptResult<()> {
+        let (bg, x, y): (u8, u32, u32) = (arg(bg)?, arg(x)?, arg(y)?);
+        let mut context = ctx.lock().unwrap();
+        let map_index = bg_index(&mut context.map, bg, x, y)?;
+        if read_tile(&mut context.map, bg, map_index) == 0 {
+            return Ok(());
+        }
+        context.map.delete_bg_tile_by_map_index(bg, map_index);
+        context.changes.push(format!("Cleared tile at {x},{y} on BG {bg}"));
+        Ok(())
+    });
+    let ctx = context.clone();
+    engine.register_fn("collision", move |x: i64, y: i64| -> ScriptResult<i64> {
+        let map = &mut ctx.lock().unwrap().map;
+        let (bg, index) = col_index(map, arg(x)?, arg(y)?)?;
+        let colz = map.get_background(bg).and_then(|layer| layer.get_colz()).ok_or("Map has no collision")?;
+        Ok(colz.col_tiles.get(index).copied().unwrap_or(0) as i64)
+    });
+    let ctx = context.clone();
+    engine.register_fn("set_collision", move |x: i64, y: i64, col_type: i64| -> ScriptResult<()> {
+        let (x, y, col_type): (u32, u32, u8) = (arg(x)?, arg(y)?, arg(col_type)?);
+        let mut context = ctx.lock().unwrap();
+        let (bg, index) = col_index(&mut context.map, x, y)?;
+        let colz = context.map.get_background(bg).and_then(|layer| layer.get_colz()).ok_or("Map has no collision")?;
+        if colz.col_tiles.get(index) == Some(&col_type) {
+            return Ok(());
+        }
+        if index >= colz.col_tiles.len() || !context.map.set_col_tile(bg, index as u16, col_type) {
+            return Err(format!("Could not set collision at {x},{y}").into());
+        }
+        context.changes.push(format!("Set collision at {x},{y} to 0x{col_type:X}"));
+        Ok(())
+    });
+    engine
+}
+
+/// Runs the script on the map, returning what changed. An error means the
+/// map should be thrown away, as earlier calls may have already changed it
+pub fn run_on_map(script: &Script, map: &mut MapData) -> Result<Vec<String>, String> {
+    let context = Arc::new(Mutex::new(ScriptContext { map: map.clone(), changes: Vec::new() }));
+    let engine = script_engine(&context);
+    let run = engine.run_ast(&script.ast);
+    drop(engine);
+    let context = Arc::into_inner(context).expect("Only the engine held the context").into_inner().unwrap();
+    *map = context.map;
+    match run {
+        Ok(_) => Ok(context.changes),
+        Err(mut error) => {
+            let position = error.take_position();
+            Err(match position.line() {
+                Some(line) => format!("Line {line}: {error}"),
+                None => error.to_string(),
+            })
+        }
+    }
+}
+
+/// Format limit and tile length problems, the same ones saving checks for
+pub fn validation_issues(map: &MapData) -> Vec<String> {
+    let mut issues: Vec<String> = map.limit_issues().iter().map(|issue| issue.to_string()).collect();
+    issues.extend(map.tile_length_issues().iter().map(|issue| issue.to_string()));
+    issues
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ScriptMapResult {
+    pub map_name: String,
+    pub changes: Vec<String>,
+    pub error: Option<String>,
+    pub saved: bool
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ScriptReport {
+    pub dry_run: bool,
+    /// Maps the script ran on, including ones it didn't change
+    pub maps_run: usize,
+    /// Only maps that changed or failed
+    pub results: Vec<ScriptMapResult>
+}
+impl fmt::Display for ScriptReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = if self.dry_run { "Dry run" } else { "Ran" };
+        write!(f, "{mode} on {} maps, {} changed or failed",self.maps_run,self.results.len())?;
+        for result in &self.results {
+            if let Some(error) = &result.error {
+                write!(f, "\n{}: FAILED, {error}",result.map_name)?;
+                continue;
+            }
+            let status = if result.saved { " (saved)" } else { "" };
+            write!(f, "\n{}{status}: {}",result.map_name,result.changes.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs on a copy, which is only returned if nothing failed
+pub fn run_checked(script: &Script, map: &MapData) -> (ScriptMapResult, Option<MapData>) {
+    let mut result = ScriptMapResult { map_name: script_map_name(map), ..Default::default() };
+    let mut edited = map.clone();
+    match run_on_map(script, &mut edited) {
+        Err(error) => result.error = Some(error),
+        Ok(changes) => {
+            let issues = validation_issues(&edited);
+            if !issues.is_empty() {
+                result.error = Some(format!("Would not save: {}",issues.join(", ")));
+            }
+            result.changes = changes;
+        }
+    }
+    if result.error.is_some() {
+        return (result, None);
+    }
+    (result, Some(edited))
+}
+
+fn run_on_file(script: &Script, path: &Path, export_folder: &Path, dry_run: bool) -> ScriptMapResult {
+    let map_name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let map = match MapData::new(&path.to_path_buf(), export_folder) {
+        Ok(map) => map,
+        Err(e) => return ScriptMapResult { map_name, error: Some(format!("Failed to load: {e}")), ..Default::default() },
+    };
+    let (mut result, edited) = run_checked(script, &map);
+    let Some(edited) = edited else {
+        return result;
+    };
+    if dry_run || result.changes.is_empty() {
+        return result;
+    }
+    if let Err(e) = backup_file(&export_folder.to_path_buf(), path) {
+        result.error = Some(format!("Not saved, backup failed: {e}"));
+        return result;
+    }
+    match fs::write(path, edited.package()) {
+        Ok(_) => result.saved = true,
+        Err(e) => result.error = Some(format!("Failed to save: {e}")),
+    }
+    result
+}
+
+/// Runs the script on every matching saved map, calling `progress` with (done, total)
+pub fn run_on_project(script: &Script, export_folder: &Path, dry_run: bool, mut progress: impl FnMut(usize, usize)) -> Result<ScriptReport, ScriptError> {
+    let maps: Vec<PathBuf> = find_project_files(export_folder).map_err(ScriptError::ListMaps)?.into_iter()
+        .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("mpdz"))
+        .filter(|path| script.applies_to(&path.file_stem().unwrap_or_default().to_string_lossy()))
+        .collect();
+    let total = maps.len();
+    log_write(format!("Running script on {total} maps, dry run: {dry_run}"), LogLevel::Log);
+    let mut report = ScriptReport { dry_run, maps_run: total, results: Vec::new() };
+    for (done, path) in maps.iter().enumerate() {
+        progress(done, total);
+        let result = run_on_file(script, path, export_folder, dry_run);
+        if let Some(error) = &result.error {
+            log_write(format!("Script on '{}': {error}",result.map_name), LogLevel::Warn);
+        }
+        if result.error.is_some() || !result.changes.is_empty() {
+            report.results.push(result);
+        }
+    }
+    progress(total, total);
+    Ok(report)
+}
+
+pub enum ScriptMessage {
+    /// Maps done, maps total
+    Progress(usize, usize),
+    Finished(Result<ScriptReport, ScriptError>)
+}
+
+pub fn spawn_project_script(script: Script, export_folder: PathBuf, dry_run: bool) -> Receiver<ScriptMessage> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let progress_tx = tx.clone();
+        let result = run_on_project(&script, &export_folder, dry_run, |done, total| {
+            let _ = progress_tx.send(ScriptMessage::Progress(done, total));
+        });
+        let _ = tx.send(ScriptMessage::Finished(result));
+    });
+    rx
+}
+
+#[cfg(test)]
+mod tests_script {
+    use crate::{data::fixtures::{sample_map, write_sample_project, TempFolder, SAMPLE_MAP_2_NAME, SAMPLE_MAP_NAME}, utils::nitrofs_abs};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_script() {
+        let script = parse_script("// Comment\nconst MAPS = [\"01k*\", \"02k0002\"];\nmove_sprites(sprites(0xA5), 0, -1);").expect("Parses");
+        assert_eq!(script.map_patterns,vec![String::from("01k*"),String::from("02k0002")]);
+        assert!(script.applies_to("01k0003"));
+        assert!(script.applies_to("02k0002"));
+        assert!(!script.applies_to("02k0001"));
+        assert!(parse_script("sprites();").expect("Parses").map_patterns.is_empty());
+        assert!(matches!(parse_script("let x = 1;\nmove_sprites(x"),Err(ScriptError::Parse { line: 2, .. })));
+        assert!(matches!(parse_script("const MAPS = 5;"),Err(ScriptError::BadMaps)));
+    }
+
+    #[test]
+    fn test_run_on_map() {
+        let map = sample_map(Path::new("01k0001.mpdz"));
+        let script = parse_script("move_sprites(sprites(0xA5), 0, -1);\nset_tile(2, 1, 1, 0x1004);\nset_collision(3, 3, 0x2);").expect("Parses");
+        let (result, edited) = run_checked(&script, &map);
+        assert_eq!(result.error,None);
+        assert_eq!(result.changes.len(),3);
+        let mut edited = edited.expect("Nothing failed");
+        let spr = &edited.get_setd().expect("Has SETD").sprites[1];
+        assert_eq!((spr.x_position,spr.y_position),(6, 3));
+        assert_eq!(read_tile(&mut edited, 2, 9),0x1004);
+        // 8 wide, so 4 collision tiles per row
+        assert_eq!(edited.get_background(2).and_then(|bg| bg.get_colz()).expect("Has COLZ").col_tiles[5],0x2);
+        // Failed maps are thrown away
+        let script = parse_script("delete_sprites(sprites());\nset_tile(2, 20, 0, 1);").expect("Parses");
+        let (result, edited) = run_checked(&script, &map);
+        assert!(result.error.expect("Tile is off the map").starts_with("Line 2"));
+        assert!(edited.is_none());
+        // Stops early, keeping what came before
+        let script = parse_script("delete_sprites(sprites(1));\nif tile(2, 0, 0) != 0x5 { return; }\nclear_tile(2, 0, 0);").expect("Parses");
+        let (result, _) = run_checked(&script, &map);
+        assert_eq!(result.changes,vec![String::from("Deleted 1 Sprites")]);
+        // Runaway loops fail instead of hanging
+        let script = parse_script("loop {}").expect("Parses");
+        assert!(run_checked(&script, &map).0.error.is_some());
+    }
+
+    #[test]
+    fn test_run_on_project() {
+        let export_folder = TempFolder::new("script_project");
+        write_sample_project(&export_folder);
+        let script = parse_script(&format!("const MAPS = \"{SAMPLE_MAP_2_NAME}\";\nmove_sprites(sprites(1), 1, 0);")).expect("Parses");
+        let map_path = nitrofs_abs(export_folder.to_path_buf(), &format!("{SAMPLE_MAP_2_NAME}.mpdz"));
+        let before = fs::read(&map_path).expect("Sample was written");
+        let report = run_on_project(&script, &export_folder, true, |_, _| {}).expect("Runs");
+        assert_eq!((report.maps_run,report.results.len()),(1, 1));
+        assert!(!report.results[0].saved);
+        assert_eq!(fs::read(&map_path).expect("Still there"),before);
+        let report = run_on_project(&script, &export_folder, false, |_, _| {}).expect("Runs");
+        assert!(report.results[0].saved);
+        let mut saved = MapData::new(&map_path, &export_folder).expect("Saved map loads");
+        assert_eq!(saved.get_setd().expect("Has SETD").sprites[0].x_position,3);
+        // The untouched map is left alone, and the edited one backed up
+        let other = nitrofs_abs(export_folder.to_path_buf(), &format!("{SAMPLE_MAP_NAME}.mpdz"));
+        assert!(MapData::new(&other, &export_folder).expect("Loads").get_setd().expect("Has SETD").sprites[0].x_position == 2);
+        assert_eq!(fs::read_dir(export_folder.join("backups")).expect("Backups made").count(),1);
+    }
+}
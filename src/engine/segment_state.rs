@@ -0,0 +1,287 @@
+// What each segment compiled to when the map was loaded, so changes can be
+// shown and reverted one segment at a time
+//
+// Segments are matched by header and how many of that header came before,
+// since deleting one shifts the indexes of everything after it
+
+use std::{hash::{DefaultHasher, Hash, Hasher}, path::Path};
+
+use crate::{data::{backgrounddata::BackgroundData, mapfile::{MapData, TopLevelSegmentWrapper}, scendata::ScenSegment, TopLevelSegment}, utils::{log_write, LogLevel}};
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum SegmentState {
+    Unchanged,
+    Modified,
+    /// Nothing like it when loaded, so it can't be reverted
+    Added
+}
+
+/// A request from the segment windows, handled by the Gui so it can record history
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum SegmentRevert {
+    /// Index into the map's segments
+    TopLevel(usize),
+    /// Index into the SCEN segments of that BG
+    Scen { which_bg: u8, index: usize }
+}
+
+fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Finds which loaded entry matches the segment at index, by header and occurrence
+fn find_loaded(loaded_headers: &[&str], headers: &[String], index: usize) -> Option<usize> {
+    let header = headers.get(index)?;
+    let occurrence = headers[..index].iter().filter(|h| *h == header).count();
+    loaded_headers.iter().enumerate()
+        .filter(|(_, loaded)| **loaded == header)
+        .nth(occurrence)
+        .map(|(i, _)| i)
+}
+
+#[derive(Clone)]
+struct LoadedSegment {
+    header: String,
+    hash: u64
+}
+
+#[derive(Clone)]
+struct LoadedTopLevel {
+    seg: LoadedSegment,
+    /// What it compiled to, parsed again when reverting
+    compiled: Vec<u8>
+}
+
+#[derive(Clone)]
+struct LoadedScen {
+    which_bg: u8,
+    /// Index into top_level, whose bytes hold these sub-segments
+    top_level_index: usize,
+    layer_size: (u16, u16),
+    subs: Vec<LoadedSegment>
+}
+
+/// States are only worked out again after mark_dirty, not every frame
+#[derive(Clone,Default)]
+pub struct SegmentBaseline {
+    top_level: Vec<LoadedTopLevel>,
+    /// SCEN segments by BG index
+    scen: Vec<LoadedScen>,
+    top_level_states: Option<Vec<SegmentState>>,
+    scen_states: Vec<(u8, Vec<SegmentState>)>
+}
+impl SegmentBaseline {
+    pub fn new(map: &MapData) -> Self {
+        puffin::profile_function!();
+        let mut ret = SegmentBaseline::default();
+        for seg in &map.segments {
+            let compiled = seg.compile();
+            let loaded = LoadedSegment { header: seg.header(), hash: hash_bytes(&compiled) };
+            ret.top_level.push(LoadedTopLevel { seg: loaded, compiled });
+            let TopLevelSegmentWrapper::SCEN(bg) = seg else {
+                continue;
+            };
+            let Some(info) = bg.get_info() else {
+                continue;
+            };
+            let subs = bg.scen_segments.iter()
+                .map(|sub| LoadedSegment { header: sub.header(), hash: hash_bytes(&sub.compile(Some(info))) })
+                .collect();
+            ret.scen.push(LoadedScen {
+                which_bg: info.which_bg,
+                top_level_index: ret.top_level.len() - 1,
+                layer_size: (info.layer_width, info.layer_height),
+                subs
+            });
+        }
+        ret
+    }
+
+    /// Call whenever the map is edited, so the states are checked again
+    pub fn mark_dirty(&mut self) {
+        self.top_level_states = Option::None;
+        self.scen_states.clear();
+    }
+
+    pub fn top_level_states(&mut self, map: &MapData) -> &[SegmentState] {
+        if self.top_level_states.is_none() {
+            puffin::profile_scope!("top_level_states");
+            let headers: Vec<String> = map.segments.iter().map(|seg| seg.header()).collect();
+            let loaded_headers: Vec<&str> = self.top_level.iter().map(|loaded| loaded.seg.header.as_str()).collect();
+            let matches: Vec<Option<usize>> = (0..headers.len()).map(|i| find_loaded(&loaded_headers, &headers, i)).collect();
+            let mut states = Vec::with_capacity(map.segments.len());
+            for (seg, loaded_index) in map.segments.iter().zip(matches) {
+                let state = match (loaded_index, seg) {
+                    (Option::None, _) => SegmentState::Added,
+                    // Compiling a SCEN compresses everything in it, so go by its parts instead
+                    (Some(loaded_index), TopLevelSegmentWrapper::SCEN(bg)) => self.scen_top_level_state(loaded_index, bg),
+                    // Things like UUIDs can differ without changing what gets saved
+                    (Some(loaded_index), _) if hash_bytes(&seg.compile()) == self.top_level[loaded_index].seg.hash => SegmentState::Unchanged,
+                    (Some(_), _) => SegmentState::Modified
+                };
+                states.push(state);
+            }
+            self.top_level_states = Some(states);
+        }
+        self.top_level_states.as_deref().unwrap_or_default()
+    }
+
+    fn scen_top_level_state(&mut self, loaded_index: usize, bg: &BackgroundData) -> SegmentState {
+        let Some(loaded) = self.scen.iter().find(|scen| scen.top_level_index == loaded_index) else {
+            return SegmentState::Modified;
+        };
+        let same_headers = loaded.subs.len() == bg.scen_segments.len()
+            && loaded.subs.iter().zip(&bg.scen_segments).all(|(loaded, sub)| loaded.header == sub.header());
+        if !same_headers {
+            return SegmentState::Modified;
+        }
+        if self.scen_states(bg).iter().all(|state| *state == SegmentState::Unchanged) {
+            SegmentState::Unchanged
+        } else {
+            SegmentState::Modified
+        }
+    }
+
+    pub fn scen_states(&mut self, bg: &BackgroundData) -> &[SegmentState] {
+        let Some(info) = bg.get_info() else {
+            return &[];
+        };
+        let which_bg = info.which_bg;
+        let cached = self.scen_states.iter().position(|(cached_bg, _)| *cached_bg == which_bg);
+        let cached = cached.unwrap_or_else(|| {
+            puffin::profile_scope!("scen_states");
+            let headers: Vec<String> = bg.scen_segments.iter().map(|sub| sub.header()).collect();
+            let loaded = self.scen.iter().find(|scen| scen.which_bg == which_bg);
+            let states = bg.scen_segments.iter().enumerate().map(|(i, sub)| {
+                let Some(loaded) = loaded else {
+                    return SegmentState::Added;
+                };
+                let loaded_headers: Vec<&str> = loaded.subs.iter().map(|sub| sub.header.as_str()).collect();
+                match find_loaded(&loaded_headers, &headers, i) {
+                    Option::None => SegmentState::Added,
+                    Some(loaded_index) if hash_bytes(&sub.compile(Some(info))) == loaded.subs[loaded_index].hash => SegmentState::Unchanged,
+                    Some(_) => SegmentState::Modified
+                }
+            }).collect();
+            self.scen_states.push((which_bg, states));
+            self.scen_states.len() - 1
+        });
+        &self.scen_states[cached].1
+    }
+
+    /// Puts back the segment as it was loaded, false if there is nothing to go back to
+    pub fn revert_top_level(&mut self, map: &mut MapData, index: usize, project_folder: &Path) -> bool {
+        let headers: Vec<String> = map.segments.iter().map(|seg| seg.header()).collect();
+        let loaded_headers: Vec<&str> = self.top_level.iter().map(|loaded| loaded.seg.header.as_str()).collect();
+        let Some(loaded_index) = find_loaded(&loaded_headers, &headers, index) else {
+            log_write(format!("No loaded segment to revert index {} to",index), LogLevel::Warn);
+            return false;
+        };
+        let loaded = &self.top_level[loaded_index];
+        log_write(format!("Reverting segment '{}' at index {}",loaded.seg.header,index), LogLevel::Log);
+        match MapData::parse_segment(&loaded.seg.header, &loaded.compiled, project_folder) {
+            Ok(Some(seg)) => map.segments[index] = seg,
+            Ok(Option::None) => return false,
+            Err(e) => {
+                log_write(format!("Failed to parse loaded segment again: '{e}'"), LogLevel::Error);
+                return false;
+            }
+        }
+        self.mark_dirty();
+        true
+    }
+
+    /// Same as revert_top_level, but refuses if the layer was resized since the
+    /// other SCEN segments would no longer match it
+    pub fn revert_scen(&mut self, bg: &mut BackgroundData, index: usize, project_folder: &Path) -> bool {
+        let Some(info) = bg.get_info() else {
+            log_write("No INFO when reverting SCEN segment", LogLevel::Error);
+            return false;
+        };
+        let which_bg = info.which_bg;
+        let size = (info.layer_width, info.layer_height);
+        let Some(loaded) = self.scen.iter().find(|scen| scen.which_bg == which_bg) else {
+            log_write(format!("BG {} was not loaded with the map, nothing to revert to",which_bg), LogLevel::Warn);
+            return false;
+        };
+        if loaded.layer_size != size {
+            log_write(format!("BG {} was resized, revert the whole SCEN instead",which_bg), LogLevel::Warn);
+            return false;
+        }
+        let headers: Vec<String> = bg.scen_segments.iter().map(|sub| sub.header()).collect();
+        let loaded_headers: Vec<&str> = loaded.subs.iter().map(|sub| sub.header.as_str()).collect();
+        let Some(loaded_index) = find_loaded(&loaded_headers, &headers, index) else {
+            log_write(format!("No loaded SCEN segment to revert index {} to",index), LogLevel::Warn);
+            return false;
+        };
+        let loaded_bg = match BackgroundData::new(&self.top_level[loaded.top_level_index].compiled, project_folder) {
+            Ok(loaded_bg) => loaded_bg,
+            Err(e) => {
+                log_write(format!("Failed to parse loaded SCEN again: '{e}'"), LogLevel::Error);
+                return false;
+            }
+        };
+        let Some(original) = loaded_bg.scen_segments.into_iter().nth(loaded_index) else {
+            log_write(format!("Loaded SCEN no longer has a segment at {}",loaded_index), LogLevel::Error);
+            return false;
+        };
+        log_write(format!("Reverting SCEN segment '{}' on BG {}",original.header(),which_bg), LogLevel::Log);
+        bg.scen_segments[index] = original;
+        self.mark_dirty();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests_segment_state {
+    use crate::data::{fixtures::sample_map, scendata::{colz::CollisionData, ScenSegmentWrapper}};
+
+    use super::*;
+
+    #[test]
+    fn test_segment_states_and_revert() {
+        let project = std::path::Path::new("unused");
+        let mut map = sample_map(std::path::Path::new("test.mpdz"));
+        let mut baseline = SegmentBaseline::new(&map);
+        let setd_index = map.segments.iter().position(|seg| matches!(seg, TopLevelSegmentWrapper::SETD(_))).expect("Sample has SETD");
+        assert!(baseline.top_level_states(&map).iter().all(|state| *state == SegmentState::Unchanged));
+        // New UUIDs aren't saved, so they don't count
+        map.get_setd().expect("Sample has SETD").sprites[0].uuid = uuid::Uuid::new_v4();
+        baseline.mark_dirty();
+        assert_eq!(baseline.top_level_states(&map)[setd_index],SegmentState::Unchanged);
+        map.get_background(2).expect("Sample has BG2").get_colz_mut().expect("Sample has COLZ").col_tiles[1] = 0x01;
+        // Nothing is checked again until it's marked
+        assert_eq!(baseline.top_level_states(&map)[0],SegmentState::Unchanged);
+        baseline.mark_dirty();
+        assert_eq!(baseline.top_level_states(&map)[0],SegmentState::Modified);
+        let bg2 = map.get_background(2).expect("Sample has BG2").clone();
+        let colz_index = bg2.scen_segments.iter().position(|sub| matches!(sub, ScenSegmentWrapper::COLZ(_))).expect("Sample has COLZ");
+        assert_eq!(baseline.scen_states(&bg2)[colz_index],SegmentState::Modified);
+        assert_eq!(baseline.scen_states(&bg2)[0],SegmentState::Unchanged);
+        // Removing the Unknown segment doesn't shift the matches
+        map.segments.remove(map.segments.len() - 1);
+        map.segments.insert(0, TopLevelSegmentWrapper::Unknown(crate::data::GenericTopLevelSegment::new(vec![0x01; 4], String::from("NEW!"))));
+        baseline.mark_dirty();
+        assert_eq!(baseline.top_level_states(&map)[0],SegmentState::Added);
+        assert!(!baseline.revert_top_level(&mut map, 0, project));
+        assert_eq!(baseline.top_level_states(&map)[setd_index + 1],SegmentState::Unchanged);
+        let bg2 = map.get_background(2).expect("Sample has BG2");
+        assert!(baseline.revert_scen(bg2, colz_index, project));
+        assert_eq!(bg2.get_colz().expect("Reverted COLZ").col_tiles[1],0x00);
+        assert_eq!(baseline.top_level_states(&map)[1],SegmentState::Unchanged);
+        // A resized layer can only be reverted whole
+        let bg2 = map.get_background(2).expect("Sample has BG2");
+        bg2.scen_segments[colz_index] = ScenSegmentWrapper::COLZ(CollisionData { col_tiles: Vec::new() });
+        if let Some(ScenSegmentWrapper::INFO(info)) = bg2.scen_segments.first_mut() {
+            info.layer_width = 2;
+        }
+        baseline.mark_dirty();
+        assert_eq!(baseline.top_level_states(&map)[1],SegmentState::Modified);
+        assert!(!baseline.revert_scen(map.get_background(2).expect("Sample has BG2"), colz_index, project));
+        assert!(baseline.revert_top_level(&mut map, 1, project));
+        assert_eq!(baseline.top_level_states(&map)[1],SegmentState::Unchanged);
+        assert_eq!(map.get_dimensions(),(8, 6));
+    }
+}
@@ -0,0 +1,186 @@
+// Checks that every Map and Course in a project survives a load/save cycle
+//
+// Each file is parsed, packaged like a save would, and parsed again. The
+// packaged bytes should match the file on disk exactly. Used by both
+// Tools > Verify Project and the --check command line mode
+
+use std::{fmt, fs, path::{Path, PathBuf}, sync::mpsc::{self, Receiver}, thread};
+
+use crate::{data::{course_file::CourseInfo, mapfile::MapData}, utils::{log_write, nitrofs_abs, LogLevel}};
+
+use super::compression::{decompress_file, lamezip77_lz10_decomp};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyIssue {
+    /// Packaged data did not match, lengths are of the uncompressed data
+    Differs { original_len: usize, packaged_len: usize, first_difference: usize },
+    /// The file could not be read, or one of the parses failed
+    Failed(String)
+}
+impl fmt::Display for VerifyIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Differs { original_len, packaged_len, first_difference } => write!(f,
+                "Differs at 0x{first_difference:X} (original 0x{original_len:X} bytes, packaged 0x{packaged_len:X} bytes)"),
+            Self::Failed(reason) => write!(f, "Failed: {reason}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VerifyFileResult {
+    pub file_name: String,
+    pub issue: Option<VerifyIssue>
+}
+
+pub enum VerifyMessage {
+    /// Files done, files total
+    Progress(usize, usize),
+    Finished(Vec<VerifyFileResult>)
+}
+
+/// Every MPDZ and CRSB file in the project, sorted by name
+pub fn find_project_files(export_folder: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let file_dir = nitrofs_abs(export_folder.to_path_buf(), "");
+    let mut files: Vec<PathBuf> = fs::read_dir(file_dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| matches!(path.extension().and_then(|ext| ext.to_str()), Some("mpdz") | Some("crsb")))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Index of the first mismatched byte, or the shorter length if one is a prefix
+fn first_difference(original: &[u8], packaged: &[u8]) -> Option<usize> {
+    if let Some(index) = original.iter().zip(packaged).position(|(a, b)| a != b) {
+        return Some(index);
+    }
+    if original.len() != packaged.len() {
+        return Some(original.len().min(packaged.len()));
+    }
+    None
+}
+
+fn compare_bytes(original: &[u8], packaged: &[u8]) -> Option<VerifyIssue> {
+    first_difference(original, packaged).map(|first_difference| VerifyIssue::Differs {
+        original_len: original.len(),
+        packaged_len: packaged.len(),
+        first_difference
+    })
+}
+
+fn verify_map(path: &Path, export_folder: &Path) -> Option<VerifyIssue> {
+    let original = decompress_file(&path.to_path_buf());
+    if original.is_empty() {
+        return Some(VerifyIssue::Failed(String::from("Could not read or decompress file")));
+    }
+    let src_file = path.display().to_string();
+    let map = match MapData::from_bytes(&original, src_file.clone(), export_folder) {
+        Ok(m) => m,
+        Err(e) => return Some(VerifyIssue::Failed(format!("First parse: {e}"))),
+    };
+    let packaged = lamezip77_lz10_decomp(&map.package());
+    if let Err(e) = MapData::from_bytes(&packaged, src_file, export_folder) {
+        return Some(VerifyIssue::Failed(format!("Second parse: {e}")));
+    }
+    compare_bytes(&original, &packaged)
+}
+
+fn verify_course(path: &Path) -> Option<VerifyIssue> {
+    let original = match fs::read(path) {
+        Ok(b) => b,
+        Err(e) => return Some(VerifyIssue::Failed(e.to_string())),
+    };
+    let src_file = path.display().to_string();
+    let mut course = match CourseInfo::from_bytes(&original, src_file.clone(), String::from("Verify")) {
+        Ok(c) => c,
+        Err(e) => return Some(VerifyIssue::Failed(format!("First parse: {e}"))),
+    };
+    let packaged = course.wrap();
+    if let Err(e) = CourseInfo::from_bytes(&packaged, src_file, String::from("Verify")) {
+        return Some(VerifyIssue::Failed(format!("Second parse: {e}")));
+    }
+    compare_bytes(&original, &packaged)
+}
+
+/// None means the file round-trips exactly
+pub fn verify_file(path: &Path, export_folder: &Path) -> Option<VerifyIssue> {
+    let is_course = path.extension().and_then(|ext| ext.to_str()) == Some("crsb");
+    if is_course {
+        verify_course(path)
+    } else {
+        verify_map(path, export_folder)
+    }
+}
+
+/// Verifies every project file, calling `progress` with (done, total) after each
+pub fn verify_project(export_folder: &Path, mut progress: impl FnMut(usize, usize)) -> std::io::Result<Vec<VerifyFileResult>> {
+    let files = find_project_files(export_folder)?;
+    let total = files.len();
+    log_write(format!("Verifying {total} project files"), LogLevel::Log);
+    let mut results: Vec<VerifyFileResult> = Vec::with_capacity(total);
+    for (done, path) in files.iter().enumerate() {
+        progress(done, total);
+        let file_name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let issue = verify_file(path, export_folder);
+        if let Some(issue) = &issue {
+            log_write(format!("Verify '{file_name}': {issue}"), LogLevel::Warn);
+        }
+        results.push(VerifyFileResult { file_name, issue });
+    }
+    progress(total, total);
+    Ok(results)
+}
+
+/// Runs verify_project on its own thread, a read failure finishes with no results
+pub fn spawn_verify(export_folder: PathBuf) -> Receiver<VerifyMessage> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let progress_tx = tx.clone();
+        let results = verify_project(&export_folder, |done, total| {
+            let _ = progress_tx.send(VerifyMessage::Progress(done, total));
+        }).unwrap_or_else(|e| {
+            log_write(format!("Could not list project files to verify: {e}"), LogLevel::Error);
+            Vec::new()
+        });
+        let _ = tx.send(VerifyMessage::Finished(results));
+    });
+    rx
+}
+
+/// Plain text summary, only listing files with issues
+pub fn summarize(results: &[VerifyFileResult]) -> String {
+    let issues: Vec<&VerifyFileResult> = results.iter().filter(|r| r.issue.is_some()).collect();
+    let mut summary = format!("Verified {} files, {} with issues", results.len(), issues.len());
+    for result in issues {
+        if let Some(issue) = &result.issue {
+            summary.push_str(&format!("\n{}: {}", result.file_name, issue));
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests_verify {
+    use super::*;
+
+    #[test]
+    fn test_first_difference() {
+        assert_eq!(first_difference(&[1,2,3], &[1,2,3]), None);
+        assert_eq!(first_difference(&[1,2,3], &[1,5,3]), Some(1));
+        // Padding added on save
+        assert_eq!(first_difference(&[1,2], &[1,2,0,0]), Some(2));
+    }
+
+    #[test]
+    fn test_summarize() {
+        let results = vec![
+            VerifyFileResult { file_name: String::from("a.mpdz"), issue: None },
+            VerifyFileResult { file_name: String::from("b.crsb"), issue: Some(VerifyIssue::Failed(String::from("bad"))) }
+        ];
+        let summary = summarize(&results);
+        assert!(summary.starts_with("Verified 2 files, 1 with issues"));
+        assert!(summary.contains("b.crsb: Failed: bad"));
+        assert!(!summary.contains("a.mpdz"));
+    }
+}
@@ -0,0 +1,93 @@
+// Windows that can pop out of the main window into their own OS window
+//
+// Detached windows are immediate viewports, so their contents still draw
+// straight from Gui's state every frame. Docking again loses nothing
+
+use egui::Vec2;
+
+use crate::NON_MAIN_FOCUSED;
+
+/// Shows `window` docked, or as its own OS window when `detached` is set.
+/// Closing the OS window docks it again instead of closing it
+pub fn show_detachable<'open, R>(
+    ctx: &egui::Context, window: egui::Window<'open>, title: &str, size: Vec2,
+    open: &'open mut bool, detached: &mut bool, add_contents: impl FnOnce(&mut egui::Ui) -> R
+) -> Option<R> {
+    puffin::profile_function!();
+    if !*open {
+        return None;
+    }
+    if !*detached {
+        let mut detach = false;
+        let res = window.open(open).show(ctx, |ui| {
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                if ui.small_button("⏏").on_hover_text("Detach into its own window").clicked() {
+                    detach = true;
+                }
+            });
+            add_contents(ui)
+        });
+        if detach {
+            *detached = true;
+        }
+        return res.and_then(|res| res.inner);
+    }
+    let mut dock = false;
+    // The viewport callback is FnMut, though it only runs once
+    let mut add_contents = Some(add_contents);
+    let viewport = egui::ViewportBuilder::default()
+        .with_title(title)
+        .with_inner_size(size);
+    let res = ctx.show_viewport_immediate(egui::ViewportId::from_hash_of(title), viewport, |ctx, class| {
+        if class == egui::ViewportClass::Embedded {
+            // No support for more windows, so keep it in the main one
+            dock = true;
+            return None;
+        }
+        let res = egui::CentralPanel::default().show(ctx, |ui| {
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                if ui.small_button("Dock").on_hover_text("Put back in the main window").clicked() {
+                    dock = true;
+                }
+            });
+            add_contents.take().map(|add_contents| add_contents(ui))
+        });
+        ctx.input(|i| {
+            // Keys pressed here shouldn't edit the main grid
+            if i.viewport().focused.unwrap_or(false) {
+                *NON_MAIN_FOCUSED.lock().unwrap() = true;
+            }
+            if i.viewport().close_requested() {
+                dock = true;
+            }
+        });
+        res.inner
+    });
+    if dock {
+        *detached = false;
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests_detach {
+    use super::*;
+
+    #[test]
+    fn test_docks_without_viewports() {
+        // A bare Context embeds viewports, like a backend without multi-window support
+        let ctx = egui::Context::default();
+        let mut open = true;
+        let mut detached = true;
+        let _ = ctx.run(egui::RawInput::default(), |ctx| {
+            let res = show_detachable(ctx, egui::Window::new("Test"), "Test", Vec2::new(100.0, 100.0), &mut open, &mut detached, |_ui| 5);
+            assert!(res.is_none());
+        });
+        assert!(open);
+        assert!(!detached);
+        let _ = ctx.run(egui::RawInput::default(), |ctx| {
+            let res = show_detachable(ctx, egui::Window::new("Test"), "Test", Vec2::new(100.0, 100.0), &mut open, &mut detached, |_ui| 5);
+            assert_eq!(res,Some(5));
+        });
+    }
+}
@@ -1,17 +1,18 @@
-use std::{fmt, fs::{self, DirEntry, File}, io::Write, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+use std::{collections::HashMap, fmt, fs::{self, DirEntry, File}, io::Write, path::{Path, PathBuf}, sync::mpsc::{Receiver, TryRecvError}};
 
-use egui::{util::undoer::Undoer, Align, ColorImage, Hyperlink, Id, Key, KeyboardShortcut, Modal, Modifiers, Pos2, ProgressBar, Rect, ScrollArea, TextureHandle, Vec2, Widget};
+use egui::{Color32, ColorImage, Hyperlink, Id, Key, KeyboardShortcut, Modal, Modifiers, Pos2, ProgressBar, Rect, ScrollArea, TextureHandle, Vec2, Widget};
 use rfd::FileDialog;
+use serde::{Deserialize, Serialize};
 use strum::EnumIter;
 use uuid::Uuid;
 
-use crate::{data::{mapfile::MapData, types::{wipe_tile_cache, CurrentLayer, MapTileRecordData, Palette}}, engine::{displayengine::{get_gameversion_prettyname, BgClipboardSelectedTile, DisplayEngine, DisplayEngineError, GameVersion}, filesys::{self, RomExtractError}}, utils::{self, bytes_to_hex_string, color_image_from_pal, generate_bg_tile_cache, get_backup_folder, get_template_folder, get_x_pos_of_map_index, get_y_pos_of_map_index, log_write, xy_to_index, LogLevel}, NON_MAIN_FOCUSED};
+use crate::{data::{course_file::{CourseInfo, WarpKind, WarpOverlap}, mapfile::{ErrorTileIssue, TileBounds, TileLengthIssue}, scendata::{colz::CollisionColors, mpbz::MapTileDataSegment, ScenSegmentWrapper}, types::{wipe_tile_cache, CurrentLayer, Palette}}, engine::{displayengine::{get_gameversion_prettyname, special_course_label, special_course_title, SPECIAL_COURSE_FILENAMES, BgClipboard, BgClipboardSelectedTile, DisplayEngine, DisplayEngineError, DisplaySettings, GameVersion, LoadLevelError, LoadedCourseMap}, filesys::{self, RomExtractError}, history::{HistoryStep, MapHistory, MapWarps}, segment_state::SegmentRevert, project_settings::{ProjectSettings, BOOKMARK_SLOTS, PROJECT_SETTINGS_FILENAME}, bug_bundle::{write_bug_bundle, BugBundleInfo}, json_dump::{dump_map, sprites_from_json, write_map_dump}, map_copy::copy_map_to_course, map_load::{spawn_map_load, MapLoadJob}, report::{spawn_report, ReportError, ReportFormat, ReportJob, ReportMessage}, script::{parse_script, run_checked, script_map_name, spawn_project_script, ScriptError, ScriptMessage, ScriptReport}, verify::{spawn_verify, summarize, VerifyFileResult, VerifyMessage}}, load::load_project_sprite_csv, utils::{self, backup_file, bytes_to_hex_string, color_image_from_pal, generate_bg_tile_cache, get_template_folder, get_x_pos_of_map_index, get_y_pos_of_map_index, log_write, new_uuid, nitrofs_abs, xy_to_index, LogLevel}, NON_MAIN_FOCUSED};
 
-use super::{maingrid::render_primary_grid, sidepanel::side_panel_show, spritepanel::sprite_panel_show, toppanel::top_panel_show, windows::{brushes::show_brushes_window, col_win::collision_tiles_window, course_win::show_course_settings_window, map_segs::show_map_segments_window, palettewin::palette_window_show, paths_win::show_paths_window, resize::{show_resize_modal, ResizeSettings}, saved_brushes::show_saved_brushes_window, scen_segs::show_scen_segments_window, settings::stork_settings_window, sprite_add::sprite_add_window_show, tileswin::tiles_window_show, triggers::show_triggers_window}};
+use super::{detach::show_detachable, maingrid::{draw_rulers, grid_scroll_target, render_primary_grid, GridMenuAction, GridScroll, PathGeometry, GRID_SCROLL_KEYS, GRID_SCROLL_SECS, RULER_THICKNESS}, sidepanel::side_panel_show, spritepanel::{sprite_panel_show, SpriteListState}, toppanel::top_panel_show, windows::{bookmarks_win::{show_bookmarks_window, BookmarkAction}, brushes::show_brushes_window, clear_selection::{show_clear_selection_modal, ClearSelectionSettings}, clipboard_win::show_clipboard_window, col_win::collision_tiles_window, course_win::{show_blank_map_settings, show_course_settings_window}, file_viewer::{show_file_viewer_window, ViewedFile}, level_switcher::{show_level_switcher_modal, LevelSwitcher, RecentMap, RecentMaps, SwitchTarget}, history_win::show_history_window, sprite_check::show_sprite_check_window, map_segs::show_map_segments_window, modified_files::{show_modified_files_window, ModifiedFilesState}, palettewin::palette_window_show, paths_win::show_paths_window, resize::{show_resize_modal, ResizeSettings}, shift_map::{show_shift_map_modal, ShiftMapSettings}, saved_brushes::show_saved_brushes_window, scen_segs::show_scen_segments_window, script_win::{show_script_window, ScriptWindowState}, settings::stork_settings_window, sprite_add::sprite_add_window_show, tileswin::tiles_window_show, triggers::show_triggers_window}};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-#[derive(Clone,Copy,PartialEq,Eq,EnumIter)]
+#[derive(Clone,Copy,PartialEq,Eq,EnumIter,Serialize,Deserialize)]
 pub enum StorkTheme {
     Dark,
     Light,
@@ -27,6 +28,276 @@ impl fmt::Display for StorkTheme {
         write!(f,"{}",text)
     }
 }
+impl StorkTheme {
+    pub fn apply(&self, ctx: &egui::Context) {
+        let sys_theme = ctx.system_theme().unwrap_or(egui::Theme::Dark);
+        ctx.set_theme(match self {
+            StorkTheme::Dark => egui::Theme::Dark,
+            StorkTheme::Light => egui::Theme::Light,
+            StorkTheme::Auto => sys_theme,
+        });
+    }
+}
+
+/// Which Sprites with graphics also get their true position box drawn
+#[derive(Clone,Copy,PartialEq,Eq,EnumIter,Serialize,Deserialize)]
+pub enum SpriteBoxMode {
+    All,
+    Selected,
+    /// Only Sprites without graphics, which always get one
+    #[serde(alias = "None")]
+    Hidden
+}
+impl fmt::Display for SpriteBoxMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            SpriteBoxMode::All => "All Sprites",
+            SpriteBoxMode::Selected => "Selected Sprites",
+            SpriteBoxMode::Hidden => "Unrendered Sprites only",
+        };
+        write!(f,"{}",text)
+    }
+}
+
+/// How BG tiles are faded when they aren't being edited
+#[derive(Clone,Copy,PartialEq,Eq,EnumIter,Serialize,Deserialize)]
+pub enum TileDim {
+    Light,
+    Strong,
+    /// Faded and washed toward gray, so colored overlays stand out
+    Gray
+}
+impl fmt::Display for TileDim {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            TileDim::Light => "Light",
+            TileDim::Strong => "Strong",
+            TileDim::Gray => "Grayed out",
+        };
+        write!(f,"{}",text)
+    }
+}
+
+/// What shows through transparent tiles on the main grid, beneath BG3
+#[derive(Clone,Copy,PartialEq,Eq,EnumIter,Serialize,Deserialize)]
+pub enum GridBackground {
+    /// The panel color, which some dark cave tilesets blend into
+    Panel,
+    Solid,
+    Checkerboard
+}
+impl fmt::Display for GridBackground {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            GridBackground::Panel => "Panel",
+            GridBackground::Solid => "Solid color",
+            GridBackground::Checkerboard => "Checkerboard",
+        };
+        write!(f,"{}",text)
+    }
+}
+
+/// Which set of ThemeColors the main grid uses
+#[derive(Clone,Copy,PartialEq,Eq,Debug,EnumIter,Serialize,Deserialize)]
+pub enum ThemeColorPreset {
+    Default,
+    /// Blue/orange/yellow instead of red/green/purple, for color-blind users
+    HighContrast
+}
+impl fmt::Display for ThemeColorPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            ThemeColorPreset::Default => "Default",
+            ThemeColorPreset::HighContrast => "High contrast (color-blind)",
+        };
+        write!(f,"{}",text)
+    }
+}
+
+/// Every overlay color drawn on the main grid
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub struct ThemeColors {
+    pub preset: ThemeColorPreset,
+    pub sprite_fill: Color32,
+    pub sprite_fill_selected: Color32,
+    pub sprite_issue: Color32,
+    pub sprite_range: Color32,
+    pub bg_selection_fill: Color32,
+    /// Selecting backwards
+    pub bg_selection_fill_invert: Color32,
+    pub bg_selection_stroke: Color32,
+    /// Tint on selected BG tiles
+    pub bg_selected_tile: Color32,
+    pub paste_preview_fill: Color32,
+    pub paste_preview_stroke: Color32,
+    pub collision_coin: Color32,
+    /// Entrances and Exits are drawn see-through, with more alpha when selected
+    pub entrance: Color32,
+    pub exit: Color32,
+    /// Exits leading to the selected Entrance
+    pub exit_target: Color32,
+    pub warp_overlap: Color32,
+    pub path: Color32,
+    pub path_selected: Color32,
+    pub path_point_selected: Color32,
+    /// Line and curve of the selected point
+    pub path_point_active: Color32,
+    pub layer_edge_current: Color32,
+    pub layer_edge_other: Color32,
+    pub brush_outline: Color32,
+    pub cursor_square: Color32,
+    pub collision: CollisionColors,
+    /// AREA Triggers
+    pub trigger: Color32,
+    pub trigger_selected: Color32
+}
+impl ThemeColors {
+    pub fn new(preset: ThemeColorPreset) -> Self {
+        match preset {
+            ThemeColorPreset::Default => Self {
+                preset,
+                sprite_fill: Color32::from_rgba_premultiplied(0xff, 0x00, 0xff, 0x40),
+                sprite_fill_selected: Color32::from_rgba_premultiplied(0x00, 0xff, 0x00, 0xff),
+                sprite_issue: Color32::ORANGE,
+                sprite_range: Color32::from_rgb(0x00, 0xff, 0xff),
+                bg_selection_fill: Color32::from_rgba_premultiplied(0x80, 0x65, 0xb5, 0xA0),
+                bg_selection_fill_invert: Color32::from_rgba_premultiplied(0x65, 0x80, 0xb5, 0xA0),
+                bg_selection_stroke: Color32::WHITE,
+                bg_selected_tile: Color32::PURPLE,
+                paste_preview_fill: Color32::from_rgba_premultiplied(0x00, 0x40, 0x00, 0x40),
+                paste_preview_stroke: Color32::LIGHT_GREEN,
+                collision_coin: Color32::LIGHT_BLUE,
+                entrance: Color32::from_rgb(0x00, 0xff, 0x00),
+                exit: Color32::from_rgb(0xff, 0x00, 0x00),
+                exit_target: Color32::LIGHT_BLUE,
+                warp_overlap: Color32::YELLOW,
+                path: Color32::RED,
+                path_selected: Color32::LIGHT_RED,
+                path_point_selected: Color32::ORANGE,
+                path_point_active: Color32::GREEN,
+                layer_edge_current: Color32::RED,
+                layer_edge_other: Color32::BLUE,
+                brush_outline: Color32::GREEN,
+                cursor_square: Color32::RED,
+                collision: CollisionColors {
+                    solid: Color32::from_rgba_premultiplied(0x40, 0x40, 0x60, 0x40),
+                    passable: Color32::from_rgba_premultiplied(0x10, 0x40, 0x10, 0x40),
+                    lava: Color32::from_rgba_premultiplied(0x80, 0x00, 0x00, 0x40),
+                    water_still: Color32::from_rgba_premultiplied(0x00, 0x00, 0x80, 0x80),
+                    soft_rock: Color32::from_rgba_premultiplied(0x80, 0x80, 0x00, 0x40),
+                    outline: Color32::from_rgba_premultiplied(0x40, 0x40, 0x60, 0xff)
+                },
+                trigger: Color32::from_rgba_premultiplied(0x60, 0x00, 0x00, 0x40),
+                trigger_selected: Color32::from_rgba_premultiplied(0x80, 0x10, 0x10, 0x50)
+            },
+            // Okabe-Ito colors, which stay apart with most color blindness
+            ThemeColorPreset::HighContrast => Self {
+                preset,
+                sprite_fill: Color32::from_rgba_unmultiplied(0xcc, 0x79, 0xa7, 0x60),
+                sprite_fill_selected: Color32::from_rgb(0x56, 0xb4, 0xe9),
+                sprite_issue: Color32::from_rgb(0xf0, 0xe4, 0x42),
+                sprite_range: Color32::from_rgb(0x00, 0x9e, 0x73),
+                bg_selection_fill: Color32::from_rgba_unmultiplied(0x56, 0xb4, 0xe9, 0xA0),
+                bg_selection_fill_invert: Color32::from_rgba_unmultiplied(0xe6, 0x9f, 0x00, 0xA0),
+                bg_selection_stroke: Color32::WHITE,
+                bg_selected_tile: Color32::from_rgb(0x56, 0xb4, 0xe9),
+                paste_preview_fill: Color32::from_rgba_unmultiplied(0xf0, 0xe4, 0x42, 0x40),
+                paste_preview_stroke: Color32::from_rgb(0xf0, 0xe4, 0x42),
+                collision_coin: Color32::from_rgb(0x56, 0xb4, 0xe9),
+                entrance: Color32::from_rgb(0x00, 0x72, 0xb2),
+                exit: Color32::from_rgb(0xe6, 0x9f, 0x00),
+                exit_target: Color32::from_rgb(0x56, 0xb4, 0xe9),
+                warp_overlap: Color32::from_rgb(0xf0, 0xe4, 0x42),
+                path: Color32::from_rgb(0xd5, 0x5e, 0x00),
+                path_selected: Color32::from_rgb(0xe6, 0x9f, 0x00),
+                path_point_selected: Color32::from_rgb(0xf0, 0xe4, 0x42),
+                path_point_active: Color32::from_rgb(0x56, 0xb4, 0xe9),
+                layer_edge_current: Color32::from_rgb(0xe6, 0x9f, 0x00),
+                layer_edge_other: Color32::from_rgb(0x00, 0x72, 0xb2),
+                brush_outline: Color32::from_rgb(0x56, 0xb4, 0xe9),
+                cursor_square: Color32::from_rgb(0xe6, 0x9f, 0x00),
+                collision: CollisionColors {
+                    solid: Color32::from_rgba_unmultiplied(0x00, 0x72, 0xb2, 0x60),
+                    passable: Color32::from_rgba_unmultiplied(0x00, 0x9e, 0x73, 0x60),
+                    lava: Color32::from_rgba_unmultiplied(0xd5, 0x5e, 0x00, 0x60),
+                    water_still: Color32::from_rgba_unmultiplied(0x56, 0xb4, 0xe9, 0x80),
+                    soft_rock: Color32::from_rgba_unmultiplied(0xf0, 0xe4, 0x42, 0x60),
+                    outline: Color32::WHITE
+                },
+                trigger: Color32::from_rgba_unmultiplied(0xcc, 0x79, 0xa7, 0x40),
+                trigger_selected: Color32::from_rgba_unmultiplied(0xcc, 0x79, 0xa7, 0x80)
+            }
+        }
+    }
+
+    /// Same color with a new alpha, for see-through fills
+    pub fn with_alpha(color: Color32, alpha: u8) -> Color32 {
+        Color32::from_rgba_unmultiplied(color.r(), color.g(), color.b(), alpha)
+    }
+}
+impl Default for ThemeColors {
+    fn default() -> Self {
+        Self::new(ThemeColorPreset::Default)
+    }
+}
+
+/// Text drawn on each visible tile of the current BG, for debugging layouts
+#[derive(Clone,Copy,PartialEq,Eq,EnumIter)]
+pub enum TileNumberOverlay {
+    Off,
+    TileId,
+    MapIndex
+}
+impl fmt::Display for TileNumberOverlay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            TileNumberOverlay::Off => "Off",
+            TileNumberOverlay::TileId => "Tile ID",
+            TileNumberOverlay::MapIndex => "Map Index",
+        };
+        write!(f,"{}",text)
+    }
+}
+
+/// How numbers are shown and edited in the GUI
+#[derive(Clone,Copy,PartialEq,Eq,EnumIter,Serialize,Deserialize)]
+pub enum NumberBase {
+    Hex,
+    Decimal
+}
+impl fmt::Display for NumberBase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            NumberBase::Hex => "Hexadecimal",
+            NumberBase::Decimal => "Decimal",
+        };
+        write!(f,"{}",text)
+    }
+}
+impl NumberBase {
+    /// Hex is zero-padded to hex_width and prefixed with 0x
+    pub fn fmt_num(&self, value: impl Into<i64>, hex_width: usize) -> String {
+        let value: i64 = value.into();
+        match self {
+            NumberBase::Hex => {
+                if value < 0 {
+                    format!("-0x{:0width$X}",value.unsigned_abs(),width = hex_width)
+                } else {
+                    format!("0x{:0width$X}",value,width = hex_width)
+                }
+            },
+            NumberBase::Decimal => format!("{}",value),
+        }
+    }
+
+    /// Applies the base to a DragValue, hex_width is the minimum hex digits
+    pub fn drag_value<'a>(&self, drag: egui::DragValue<'a>, hex_width: usize) -> egui::DragValue<'a> {
+        match self {
+            NumberBase::Hex => drag.hexadecimal(hex_width, false, true),
+            NumberBase::Decimal => drag,
+        }
+    }
+}
 
 /// Controls selection on BG tiles
 pub struct BgSelectData {
@@ -111,15 +382,15 @@ impl BgSelectData {
     }
 
     #[allow(clippy::wrong_self_convention)]
-    pub fn to_clipboard_tiles(&mut self, map_width: u16, map_tiles: &[MapTileRecordData]) -> Vec<BgClipboardSelectedTile> {
+    pub fn to_clipboard(&mut self, map_width: u16, map_tiles: &MapTileDataSegment) -> BgClipboard {
         let mut ret: Vec<BgClipboardSelectedTile> = Vec::new();
         if self.selected_map_indexes.is_empty() {
             log_write("Attempted to convert to clipboard tiles while empty", LogLevel::Warn);
-            return ret;
+            return BgClipboard::default();
         }
         let Some(top_left) = self.get_top_left(map_width) else {
             log_write("Could not get top left", LogLevel::Error);
-            return Vec::new();
+            return BgClipboard::default();
         };
         let top_abs_x = top_left.x as i32;
         let top_abs_y = top_left.y as i32;
@@ -129,13 +400,60 @@ impl BgSelectData {
             let rel_x = tile_abs_x - top_abs_x;
             let rel_y = tile_abs_y - top_abs_y;
             let clip = BgClipboardSelectedTile {
-                tile: map_tiles[*selected_map_index as usize],
+                tile: map_tiles.get_stored_tile(*selected_map_index, map_width),
                 x_offset: rel_x,
                 y_offset: rel_y
             };
             ret.push(clip);
         }
-        ret
+        BgClipboard { tiles: ret, top_left }
+    }
+
+    /// Leftmost, topmost, rightmost and bottommost tile, inclusive
+    fn bounds(&self, map_width: u16) -> Option<(u16, u16, u16, u16)> {
+        let map_width = map_width as u32;
+        let xs = self.selected_map_indexes.iter().map(|index| get_x_pos_of_map_index(*index, &map_width));
+        let ys = self.selected_map_indexes.iter().map(|index| get_y_pos_of_map_index(*index, &map_width));
+        Some((xs.clone().min()?, ys.clone().min()?, xs.max()?, ys.max()?))
+    }
+
+    fn select_rect(&mut self, map_width: u16, indexes: Vec<u32>) {
+        self.selected_map_indexes = indexes;
+        self.selected_map_indexes.sort();
+        self.selection_width = self.get_selection_width(map_width);
+        self.selection_height = self.get_selection_height(map_width);
+    }
+
+    /// Grows the selection to the even aligned rectangle around it, blank
+    /// tiles included, so it loads into a Brush without odd edges
+    pub fn expand_to_even(&mut self, map_width: u16, map_height: u16) {
+        let Some((min_x, min_y, max_x, max_y)) = self.bounds(map_width) else { return };
+        let (left, top) = (min_x & !1, min_y & !1);
+        // One past the edge, rounded up, but not off the map
+        let right = ((max_x as u32 + 2) & !1).min(map_width as u32);
+        let bottom = ((max_y as u32 + 2) & !1).min(map_height as u32);
+        let indexes = (top as u32..bottom)
+            .flat_map(|y| (left as u32..right).map(move |x| y * map_width as u32 + x))
+            .collect();
+        self.select_rect(map_width, indexes);
+    }
+
+    /// Drops the odd rows and columns on the edges of the selection. If that
+    /// leaves nothing, the selection is cleared
+    pub fn trim_to_even(&mut self, map_width: u16) {
+        let Some((min_x, min_y, max_x, max_y)) = self.bounds(map_width) else { return };
+        let (left, top) = ((min_x as u32 + 1) & !1, (min_y as u32 + 1) & !1);
+        let (right, bottom) = ((max_x as u32 + 1) & !1, (max_y as u32 + 1) & !1);
+        if left >= right || top >= bottom {
+            self.clear();
+            return;
+        }
+        let map_width_u32 = map_width as u32;
+        let indexes = self.selected_map_indexes.iter().copied().filter(|index| {
+            let (x, y) = (index % map_width_u32, index / map_width_u32);
+            (left..right).contains(&x) && (top..bottom).contains(&y)
+        }).collect();
+        self.select_rect(map_width, indexes);
     }
 
     pub fn clear(&mut self) {
@@ -149,6 +467,139 @@ impl BgSelectData {
     }
 }
 
+/// Settings kept between sessions by eframe, see Gui::save
+#[derive(Clone,Serialize,Deserialize)]
+#[serde(default)]
+pub struct PersistedSettings {
+    pub stork_theme: StorkTheme,
+    pub number_base: NumberBase,
+    pub sprite_box_mode: SpriteBoxMode,
+    /// Layer selected after a Level is loaded
+    pub start_layer: CurrentLayer,
+    pub auto_show_current_layer: bool,
+    pub snap_bg_paste_to_even: bool,
+    pub collision_tile_dim: TileDim,
+    pub keep_map_index: bool,
+    pub locked_layers: Vec<CurrentLayer>,
+    pub theme_colors: ThemeColorPreset,
+    pub max_undos: usize,
+    pub max_memory_mb: usize,
+    /// BG Tiles window palette for BG 1-3
+    pub tile_preview_pals: [usize; 3],
+    pub tile_cache_budget: usize,
+    pub grid_background: GridBackground,
+    pub grid_background_color: [u8; 3],
+    pub auto_open_companion: bool,
+    pub smooth_scrolling: bool
+}
+impl Default for PersistedSettings {
+    fn default() -> Self {
+        let display_settings = DisplaySettings::default();
+        let history = MapHistory::default();
+        Self {
+            stork_theme: display_settings.stork_theme,
+            number_base: display_settings.number_base,
+            sprite_box_mode: display_settings.sprite_box_mode,
+            start_layer: display_settings.start_layer,
+            auto_show_current_layer: display_settings.auto_show_current_layer,
+            snap_bg_paste_to_even: display_settings.snap_bg_paste_to_even,
+            collision_tile_dim: display_settings.collision_tile_dim,
+            keep_map_index: display_settings.keep_map_index,
+            locked_layers: display_settings.locked_layers.clone(),
+            theme_colors: display_settings.theme_colors.preset,
+            max_undos: history.max_undos,
+            max_memory_mb: history.max_memory_mb,
+            tile_preview_pals: [0; 3],
+            tile_cache_budget: display_settings.tile_cache_budget,
+            grid_background: display_settings.grid_background,
+            grid_background_color: display_settings.grid_background_color,
+            auto_open_companion: display_settings.auto_open_companion,
+            smooth_scrolling: display_settings.smooth_scrolling
+        }
+    }
+}
+const PERSISTED_SETTINGS_KEY: &str = "stork_settings";
+/// Ctrl+Shift+N saves bookmark N, Ctrl+N jumps to it
+const BOOKMARK_KEYS: [Key; BOOKMARK_SLOTS as usize] = [
+    Key::Num1, Key::Num2, Key::Num3, Key::Num4, Key::Num5, Key::Num6, Key::Num7, Key::Num8, Key::Num9
+];
+
+#[derive(Debug)]
+pub enum SaveError {
+    /// Usually read-only, or open in another program
+    Create(String, std::io::Error),
+    Write(String, std::io::Error),
+    Backup(String, std::io::Error)
+}
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Create(file, error) => write!(f, "Could not open '{file}' for writing: {error}"),
+            Self::Write(file, error) => write!(f, "Could not write '{file}': {error}"),
+            Self::Backup(file, error) => write!(f, "Could not back up '{file}', nothing was saved: {error}"),
+        }
+    }
+}
+impl std::error::Error for SaveError {}
+
+/// Something that would lose unsaved changes, held while the user decides
+#[derive(Clone,Debug,PartialEq)]
+pub enum PendingAction {
+    Exit,
+    Export,
+    /// Skips the file dialog if there was an export before
+    ExportToLast,
+    ChangeCourse,
+    ChangeMap,
+    Switch(SwitchTarget),
+    /// Reopens the project from where it was moved to
+    MoveProject(PathBuf),
+    /// A project script saved over the loaded map's file
+    ReloadMap
+}
+impl PendingAction {
+    /// Finishes "do you want to save before ..."
+    fn prompt(&self) -> &'static str {
+        match self {
+            Self::Exit => "you exit",
+            Self::Export | Self::ExportToLast => "export",
+            Self::ChangeCourse => "changing Course",
+            Self::ChangeMap => "changing map",
+            Self::Switch(_) => "switching",
+            Self::MoveProject(_) => "reopening the project",
+            Self::ReloadMap => "loading the script's changes to this map",
+        }
+    }
+}
+
+/// What a background map load was started by, each does a little more once loaded
+#[derive(Clone,Copy,Debug,PartialEq)]
+enum MapLoadKind {
+    /// World and Level index
+    Level(u32, u32),
+    Course,
+    /// Another map in the loaded Course
+    Map
+}
+
+pub struct PendingMapLoad {
+    receiver: Receiver<Result<LoadedCourseMap, LoadLevelError>>,
+    /// Shown while loading, like "Course 1-1 map 2"
+    description: String,
+    kind: MapLoadKind,
+    /// Run once it's swapped in, so Discard's reload finishes before the action
+    then: Option<PendingAction>
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+enum UnsavedChoice {
+    Cancel,
+    /// Reload from disk, then run the action
+    Discard,
+    /// Save, then run the action
+    Save
+}
+
 pub struct Gui {
     // Window states
     pub palette_window_open: bool,
@@ -160,44 +611,126 @@ pub struct Gui {
     pub sprites_window_open: bool,
     pub course_window_open: bool,
     pub area_window_open: bool,
+    // In their own OS window, see show_detachable
+    pub tile_preview_detached: bool,
+    pub stamps_detached: bool,
+    pub course_detached: bool,
     pub mpdz_window_open: bool,
     pub scen_window_open: bool,
+    pub modified_files_window_open: bool,
+    pub history_window_open: bool,
+    /// A .crsb or .mpdz opened outside of the project, see do_view_file
+    pub viewed_file: Option<ViewedFile>,
+    pub sprite_check_window_open: bool,
+    pub clipboard_window_open: bool,
+    pub bookmarks_window_open: bool,
+    /// If each layer's companion window was open when last leaving it, by layer - 1
+    companion_windows_open: [bool; 7],
+    /// Latest egui input time, for history timestamps
+    pub input_time: f64,
+    pub modified_files: ModifiedFilesState,
     // Modals
-    pub exit_changes_open: bool,
+    /// Waiting on the unsaved changes modal, see confirm_unsaved_changes
+    pub unsaved_changes_action: Option<PendingAction>,
     pub saving_progress: Option<f32>,
-    pub quit_when_saving_done: bool,
+    /// Shown in the saving modal in place of the progress bar, with a Retry
+    pub save_error: Option<String>,
+    /// BGs with the wrong MPBZ length, the repair modal shows while not empty
+    pub tile_length_issues: Vec<TileLengthIssue>,
+    /// The repair modal was opened by saving, which continues afterwards
+    pub tile_length_before_save: bool,
+    /// Layers with red error tiles, asks before saving while not empty
+    pub error_tile_issues: Vec<ErrorTileIssue>,
+    /// Run once saving finishes
+    pub after_save_action: Option<PendingAction>,
+    /// Closes the window next frame, since actions have no Context
+    pub quit_requested: bool,
     pub exporting_progress: Option<f32>,
     pub exporting_to: String,
-    pub export_changes_open: bool,
-    pub export_when_saving_done: bool,
+    /// Level report being written in the background
+    pub report_receiver: Option<Receiver<ReportMessage>>,
+    /// Maps done, total maps
+    pub report_progress: (usize, usize),
+    /// Project verification running in the background
+    pub verify_receiver: Option<Receiver<VerifyMessage>>,
+    /// Files done, total files
+    pub verify_progress: (usize, usize),
+    /// Shown until the results window is closed
+    pub verify_results: Option<Vec<VerifyFileResult>>,
+    pub script_window_open: bool,
+    pub script_state: ScriptWindowState,
+    /// Script running on every map in the background
+    pub script_receiver: Option<Receiver<ScriptMessage>>,
+    /// Maps done, total maps
+    pub script_progress: (usize, usize),
+    /// Course and map being read in the background
+    pub map_load: Option<PendingMapLoad>,
     pub change_course_open: bool,
     pub general_alert_popup: Option<String>,
     pub change_level_world_index: u32,
     pub change_level_level_index: u32,
-    pub change_course_unsaved_changes_show: bool,
-    pub change_map_unsaved_changes_show: bool,
+    /// Chosen instead of the World and Level, by Course file name
+    pub change_course_special: Option<String>,
     pub change_map_open: bool,
     pub map_change_selected_map: String,
+    pub copy_map_open: bool,
+    /// World and Level indexes of the Course to copy the loaded map into
+    pub copy_map_dest: (u32, u32),
+    /// Course file name to its contents, None if it failed to read
+    pub course_preview_cache: HashMap<String, Option<CourseInfo>>,
+    /// Map file name to its width and height, None if it failed to read
+    pub map_size_cache: HashMap<String, Option<(u16, u16)>>,
+    /// Map file name to whether its .mpdz is on disk
+    pub map_exists_cache: HashMap<String, bool>,
     pub cur_level: u32,
     pub cur_world: u32,
     pub about_modal_open: bool,
     pub bug_report_modal_open: bool,
+    /// Consent to put the current .mpdz in the bug report bundle
+    pub bug_bundle_include_map: bool,
     pub clear_modal_open: bool,
     pub help_modal_open: bool,
+    pub level_switcher: LevelSwitcher,
+    pub recent_maps: RecentMaps,
     /// This should be stored in Gui
     pub display_engine: DisplayEngine,
     pub project_open: bool,
-    pub export_directory: PathBuf, // Not yet fully mutable
+    /// Changed by opening a project, or moving it from Settings
+    pub export_directory: PathBuf,
+    /// The Settings field for export_directory, applied once it is valid
+    pub project_folder_edit: String,
     pub resize_settings: ResizeSettings,
+    pub shift_map_settings: ShiftMapSettings,
+    pub clear_selection_settings: ClearSelectionSettings,
     pub settings_open: bool,
     // Tile preview caching
     // pub needs_bg_tile_refresh: bool, in DisplayEngine
     pub bg1_tile_preview_cache: Vec<TextureHandle>,
     pub bg2_tile_preview_cache: Vec<TextureHandle>,
     pub bg3_tile_preview_cache: Vec<TextureHandle>,
+    /// MPBZ placement count per tile ID for BG 1-3, regenerated with the preview caches
+    pub bg_tile_usage: [Vec<u32>;3],
+    /// Preview caches to rebuild the next time the BG Tiles window shows them
+    pub bg_preview_stale: [bool;3],
+    /// The BG Tiles window has shown its spinner, so the stale preview can be built
+    pub bg_preview_waiting: bool,
+    pub sprite_list: SpriteListState,
+    /// Hide tiles in the BG Tiles window that are never placed
+    pub tiles_used_only: bool,
     // Tools
-    pub undoer: Undoer<MapData>,
-    pub scroll_to: Option<Pos2>
+    pub map_history: MapHistory,
+    pub scroll_to: Option<Pos2>,
+    /// Visible part of the main grid last frame, in grid pixels
+    pub grid_viewport: Rect,
+    /// The jump or keyboard scroll underway
+    grid_scroll: Option<GridScroll>,
+    /// The offset grid_scroll set last frame, to spot the user scrolling over it
+    grid_scroll_set: Option<Vec2>,
+    /// Where the main grid ended up scrolled to last frame
+    grid_scroll_offset: Vec2,
+    /// How far the main grid could scroll last frame
+    grid_max_offset: Vec2,
+    pub project_settings: ProjectSettings
 }
 impl Default for Gui {
     fn default() -> Self {
@@ -210,45 +743,152 @@ impl Default for Gui {
             path_window_open: false,
             sprites_window_open: false,
             course_window_open: false,
+            tile_preview_detached: false,
+            stamps_detached: false,
+            course_detached: false,
             area_window_open: false,
             mpdz_window_open: false,
             scen_window_open: false,
+            modified_files_window_open: false,
+            history_window_open: false,
+            viewed_file: None,
+            sprite_check_window_open: false,
+            clipboard_window_open: false,
+            bookmarks_window_open: false,
+            companion_windows_open: [true; 7],
+            input_time: 0.0,
+            modified_files: ModifiedFilesState::default(),
             project_open: false,
-            export_directory: PathBuf::new(), // Not yet fully mutable
+            export_directory: PathBuf::new(),
+            project_folder_edit: String::new(),
             resize_settings: ResizeSettings::default(),
+            shift_map_settings: ShiftMapSettings::default(),
+            clear_selection_settings: ClearSelectionSettings::default(),
             settings_open: false,
             display_engine: DisplayEngine::default(),
             bg1_tile_preview_cache: Vec::new(),
             bg2_tile_preview_cache: Vec::new(),
             bg3_tile_preview_cache: Vec::new(),
-            exit_changes_open: false,
+            bg_tile_usage: Default::default(),
+            bg_preview_stale: [true;3],
+            bg_preview_waiting: false,
+            sprite_list: SpriteListState::default(),
+            tiles_used_only: false,
+            unsaved_changes_action: Option::None,
             saving_progress: Option::None,
-            quit_when_saving_done: false,
+            save_error: Option::None,
+            tile_length_issues: Vec::new(),
+            tile_length_before_save: false,
+            error_tile_issues: Vec::new(),
+            after_save_action: Option::None,
+            quit_requested: false,
             exporting_progress: Option::None,
             exporting_to: String::from("ERROR"),
-            export_changes_open: false,
-            export_when_saving_done: false,
+            report_receiver: Option::None,
+            report_progress: (0, 0),
+            verify_receiver: Option::None,
+            verify_progress: (0, 0),
+            verify_results: Option::None,
+            script_window_open: false,
+            script_state: ScriptWindowState::default(),
+            script_receiver: Option::None,
+            script_progress: (0, 0),
+            map_load: Option::None,
             change_course_open: false,
             general_alert_popup: Option::None,
             change_level_world_index: 0,
             change_level_level_index: 0,
+            change_course_special: None,
             cur_level: 0,
             cur_world: 0,
-            change_course_unsaved_changes_show: false,
-            change_map_unsaved_changes_show: false,
             change_map_open: false,
             map_change_selected_map: String::from(""),
+            copy_map_open: false,
+            copy_map_dest: (0, 0),
+            course_preview_cache: HashMap::new(),
+            map_size_cache: HashMap::new(),
+            map_exists_cache: HashMap::new(),
             about_modal_open: false,
             bug_report_modal_open: false,
+            bug_bundle_include_map: false,
             clear_modal_open: false,
             help_modal_open: false,
-            undoer: Undoer::default(),
-            scroll_to: Option::None
+            level_switcher: LevelSwitcher::default(),
+            recent_maps: RecentMaps::default(),
+            map_history: MapHistory::default(),
+            scroll_to: Option::None,
+            grid_viewport: Rect::NOTHING,
+            grid_scroll: Option::None,
+            grid_scroll_set: Option::None,
+            grid_scroll_offset: Vec2::ZERO,
+            grid_max_offset: Vec2::ZERO,
+            project_settings: ProjectSettings::default()
         }
     }
 }
 
 impl Gui {
+    pub fn persisted_settings(&self) -> PersistedSettings {
+        let display_settings = &self.display_engine.display_settings;
+        PersistedSettings {
+            stork_theme: display_settings.stork_theme,
+            number_base: display_settings.number_base,
+            sprite_box_mode: display_settings.sprite_box_mode,
+            start_layer: display_settings.start_layer,
+            auto_show_current_layer: display_settings.auto_show_current_layer,
+            snap_bg_paste_to_even: display_settings.snap_bg_paste_to_even,
+            collision_tile_dim: display_settings.collision_tile_dim,
+            keep_map_index: display_settings.keep_map_index,
+            locked_layers: display_settings.locked_layers.clone(),
+            theme_colors: display_settings.theme_colors.preset,
+            max_undos: self.map_history.max_undos,
+            max_memory_mb: self.map_history.max_memory_mb,
+            tile_preview_pals: self.display_engine.preview_pals(),
+            tile_cache_budget: display_settings.tile_cache_budget,
+            grid_background: display_settings.grid_background,
+            grid_background_color: display_settings.grid_background_color,
+            auto_open_companion: display_settings.auto_open_companion,
+            smooth_scrolling: display_settings.smooth_scrolling
+        }
+    }
+    pub fn apply_persisted_settings(&mut self, settings: &PersistedSettings) {
+        let display_settings = &mut self.display_engine.display_settings;
+        display_settings.stork_theme = settings.stork_theme;
+        display_settings.number_base = settings.number_base;
+        display_settings.sprite_box_mode = settings.sprite_box_mode;
+        display_settings.start_layer = settings.start_layer;
+        display_settings.auto_show_current_layer = settings.auto_show_current_layer;
+        display_settings.snap_bg_paste_to_even = settings.snap_bg_paste_to_even;
+        display_settings.collision_tile_dim = settings.collision_tile_dim;
+        display_settings.keep_map_index = settings.keep_map_index;
+        display_settings.locked_layers = settings.locked_layers.clone();
+        display_settings.theme_colors = ThemeColors::new(settings.theme_colors);
+        display_settings.tile_cache_budget = settings.tile_cache_budget;
+        display_settings.grid_background = settings.grid_background;
+        display_settings.grid_background_color = settings.grid_background_color;
+        display_settings.auto_open_companion = settings.auto_open_companion;
+        display_settings.smooth_scrolling = settings.smooth_scrolling;
+        self.map_history.max_undos = settings.max_undos;
+        self.map_history.max_memory_mb = settings.max_memory_mb;
+        self.map_history.enforce_limits();
+        self.display_engine.set_preview_pals(settings.tile_preview_pals);
+        self.display_engine.apply_tile_cache_budget();
+    }
+    /// Called once on startup with eframe's storage
+    pub fn load_persisted_settings(&mut self, ctx: &egui::Context, storage: Option<&dyn eframe::Storage>) {
+        let Some(settings) = storage.and_then(|s| eframe::get_value::<PersistedSettings>(s, PERSISTED_SETTINGS_KEY)) else {
+            log_write("No saved settings found, using defaults", LogLevel::Debug);
+            return;
+        };
+        log_write("Loaded saved settings", LogLevel::Debug);
+        self.apply_persisted_settings(&settings);
+        settings.stork_theme.apply(ctx);
+    }
+    /// Sets the layer from the settings once a Level is loaded
+    fn apply_start_layer(&mut self) {
+        let start_layer = self.display_engine.display_settings.start_layer;
+        self.display_engine.change_layer(start_layer);
+    }
     pub fn exit(&self,ctx: &egui::Context) {
         log_write("Quitting Stork Editor".to_owned(), LogLevel::Log);
         ctx.send_viewport_cmd(egui::ViewportCommand::Close);
@@ -264,11 +904,43 @@ impl Gui {
             log_write("Did not get folder path", LogLevel::Warn);
         }
     }
+    /// Same as do_open_project, but from the config.yaml that Open ROM makes
+    pub fn do_open_project_config(&mut self) {
+        let Some(path) = FileDialog::new().set_title("Open Project from config.yaml")
+            .add_filter("Project config", &["yaml"]).pick_file() else {
+            return;
+        };
+        self.open_project(path);
+    }
+    /// Opens a single Course or Map file read-only, without a project
+    pub fn do_view_file(&mut self) {
+        let Some(path) = FileDialog::new().set_title("View Course or Map File")
+            .add_filter("Course or Map", &["crsb", "mpdz"]).pick_file() else {
+            return;
+        };
+        self.view_file(&path);
+    }
+    pub fn view_file(&mut self, path: &Path) {
+        match ViewedFile::open(path) {
+            Ok(viewed) => self.viewed_file = Some(viewed),
+            Err(error) => {
+                log_write(format!("Failed to view '{}': {error}",path.display()), LogLevel::Error);
+                self.do_alert(format!("Failed to view '{}': {error}",path.display()));
+            }
+        }
+    }
     pub fn do_alert(&mut self, alert_text: String) {
         log_write(format!("Launching alert window with message '{}'",alert_text), LogLevel::Debug);
         self.general_alert_popup = Some(alert_text);
     }
-    fn open_project(&mut self, path: PathBuf) {
+    /// Takes the project folder, or anything that leads to it, see resolve_project_root
+    pub fn open_project(&mut self, picked: PathBuf) {
+        let Some(path) = filesys::resolve_project_root(&picked) else {
+            log_write(format!("No project found from '{}'",picked.display()), LogLevel::Error);
+            self.do_alert(format!("'{}' is not a Stork project. Pick the folder Open ROM made, with header.yaml and config.yaml in it",
+                picked.display()));
+            return;
+        };
         log_write(format!("Opening Project at '{}'",path.display()), LogLevel::Log);
         self.export_directory = path.clone();
         // Handle extracted contents
@@ -276,8 +948,15 @@ impl Gui {
         match de {
             Ok(de) => {
                 let saved_brushes = std::mem::take(&mut self.display_engine.saved_brushes);
+                let settings = self.persisted_settings();
                 self.display_engine = de; // Move it on in!
+                // A load from the old project would land in this one
+                self.map_load = Option::None;
                 self.display_engine.saved_brushes = saved_brushes;
+                self.apply_persisted_settings(&settings);
+                self.project_settings = ProjectSettings::load(&path);
+                load_project_sprite_csv(&path);
+                self.record_project_folder(&path);
             }
             Err(e) => {
                 self.do_alert(e.to_string());
@@ -312,7 +991,12 @@ impl Gui {
                 return;
             }
         }
+        self.apply_start_layer();
+        // Recent maps from another project would not exist
+        self.recent_maps.maps.clear();
+        self.recent_maps.record(&self.display_engine);
         self.display_engine.needs_bg_tile_refresh = true;
+        self.check_tile_lengths();
         self.project_open = true;
     }
     pub fn export_rom_file(&mut self, path: String) {
@@ -321,42 +1005,570 @@ impl Gui {
             &format!("{}/config.yaml",&self.export_directory.display()), &path);
         if generate_result.is_err() {
             log_write("Failed to generate ROM", LogLevel::Error);
+            return;
         }
+        self.project_settings.last_rom_path = Some(PathBuf::from(path));
+        self.save_project_settings();
     }
     pub fn do_save(&mut self) {
+        if self.check_format_limits() {
+            return;
+        }
+        if self.check_tile_lengths() {
+            // Asks first, see resolve_tile_lengths
+            self.tile_length_before_save = true;
+            return;
+        }
+        self.start_save();
+    }
+    /// Last check before saving. Error tiles can be saved, but look broken in-game
+    fn start_save(&mut self) {
+        let issues = self.display_engine.loaded_map.error_tile_issues();
+        if !issues.is_empty() {
+            for issue in &issues {
+                log_write(issue.to_string(), LogLevel::Warn);
+            }
+            self.error_tile_issues = issues;
+            return;
+        }
         self.saving_progress = Some(0.0);
     }
+    /// Counts and lengths past what the files can store, true (and alerts) if saving should stop
+    fn check_format_limits(&mut self) -> bool {
+        let mut issues = self.display_engine.loaded_map.limit_issues();
+        issues.extend(self.display_engine.loaded_course.limit_issues());
+        if issues.is_empty() {
+            return false;
+        }
+        let mut alert_text = String::from("Not saved, these are past what the game's files can store:");
+        for issue in &issues {
+            log_write(issue.to_string(), LogLevel::Error);
+            alert_text.push_str(&format!("\n- {}",issue));
+        }
+        self.do_alert(alert_text);
+        true
+    }
+    /// Finds layers with the wrong tile count, true if the repair modal should show
+    fn check_tile_lengths(&mut self) -> bool {
+        let issues = self.display_engine.loaded_map.tile_length_issues();
+        for issue in &issues {
+            log_write(issue.to_string(), LogLevel::Warn);
+        }
+        self.tile_length_issues = issues;
+        !self.tile_length_issues.is_empty()
+    }
+    fn check_unknown_sprites(&mut self) {
+        let issues = self.display_engine.loaded_map.unknown_sprite_issues();
+        for issue in &issues {
+            log_write(issue.to_string(), LogLevel::Warn);
+        }
+        self.display_engine.unknown_sprite_issues = issues;
+    }
+    /// Repairing cuts off extra tiles, so it's up to the user
+    fn resolve_tile_lengths(&mut self, repair: bool) {
+        if repair {
+            self.display_engine.loaded_map.repair_tile_lengths();
+            self.display_engine.map_edited();
+            self.display_engine.graphics_update_needed = true;
+        }
+        self.tile_length_issues.clear();
+        if std::mem::take(&mut self.tile_length_before_save) {
+            self.start_save();
+        }
+    }
     pub fn do_undo(&mut self) {
-        if let Some(map_state) = self.undoer.undo(&self.display_engine.loaded_map) {
+        if let Some(step) = self.map_history.undo(&self.display_engine.loaded_map, self.input_time) {
             log_write("Undoing", LogLevel::Debug);
-            self.display_engine.loaded_map = map_state.clone();
-            self.display_engine.unsaved_changes = true; // In case you saved
-            self.display_engine.graphics_update_needed = true;
+            Self::apply_history_step(&mut self.display_engine, step);
+        }
+    }
+    pub fn do_history_jump(&mut self, index: usize) {
+        if let Some(step) = self.map_history.jump_to(index, &self.display_engine.loaded_map, self.input_time) {
+            log_write(format!("Jumping to history entry {}",index), LogLevel::Debug);
+            Self::apply_history_step(&mut self.display_engine, step);
+        }
+    }
+    fn apply_history_step(de: &mut DisplayEngine, step: HistoryStep) {
+        de.loaded_map = step.state.clone();
+        if let Some(warps) = step.warps {
+            if let Some(map_info) = de.map_index.and_then(|index| de.loaded_course.level_map_data.get_mut(index)) {
+                warps.apply(map_info);
+            }
+        }
+        de.map_edited(); // In case you saved
+        de.graphics_update_needed = true;
+    }
+    /// One undo step for the map and its Entrances and Exits
+    fn do_shift_map(&mut self) {
+        let (dx, dy) = (self.shift_map_settings.dx, self.shift_map_settings.dy);
+        self.shift_map_settings.preview = None;
+        self.shift_map_settings.window_open = false;
+        let de = &mut self.display_engine;
+        let Some(map_info) = de.map_index.and_then(|index| de.loaded_course.level_map_data.get_mut(index)) else {
+            log_write("Could not get Course map info when shifting map", LogLevel::Error);
+            return;
+        };
+        self.map_history.add_undo_with_warps(&de.loaded_map, self.input_time, &MapWarps::from_info(map_info));
+        let (width, height) = de.loaded_map.get_dimensions();
+        let mut report = de.loaded_map.shift_contents(dx, dy);
+        report.warps_clamped = map_info.shift_warps(dx, dy, width, height);
+        if !report.is_empty() {
+            log_write(format!("Shift Map went out of bounds: {report}"), LogLevel::Warn);
+        }
+        self.map_history.add_undo_with_warps(&de.loaded_map, self.input_time, &MapWarps::from_info(map_info));
+        // Selections may point at removed things
+        de.selected_sprite_uuids.clear();
+        de.bg_sel_data.selected_map_indexes.clear();
+        de.map_edited();
+        de.graphics_update_needed = true;
+    }
+    /// Its own undo point, so undoing a revert gets the changes back
+    pub fn do_segment_revert(&mut self, revert: SegmentRevert) {
+        self.map_history.add_undo(&self.display_engine.loaded_map, self.input_time);
+        let de = &mut self.display_engine;
+        let reverted = match revert {
+            SegmentRevert::TopLevel(index) => de.segment_baseline.revert_top_level(&mut de.loaded_map, index, &de.export_folder),
+            SegmentRevert::Scen { which_bg, index } => {
+                let Some(bg) = de.loaded_map.get_background(which_bg) else {
+                    log_write(format!("No BG {} to revert a segment on",which_bg), LogLevel::Error);
+                    return;
+                };
+                let reverted = de.segment_baseline.revert_scen(bg, index, &de.export_folder);
+                // The preview is separate from the segments it comes from
+                let is_tiles = matches!(bg.scen_segments[index], ScenSegmentWrapper::IMGB(_) | ScenSegmentWrapper::IMBZ(_) | ScenSegmentWrapper::ANMZ(_));
+                if reverted && is_tiles {
+                    bg.reload_pixel_tiles_preview(&de.export_folder);
+                }
+                reverted
+            }
+        };
+        if !reverted {
+            self.do_alert(String::from("Nothing to revert to, see the log for details"));
+            return;
+        }
+        de.map_edited();
+        de.graphics_update_needed = true;
+        self.map_history.add_undo(&self.display_engine.loaded_map, self.input_time);
+    }
+    pub fn do_redo(&mut self) {
+        if let Some(step) = self.map_history.redo(&self.display_engine.loaded_map) {
+            log_write("Redoing", LogLevel::Debug);
+            Self::apply_history_step(&mut self.display_engine, step);
+        }
+    }
+    pub fn do_export(&mut self) {
+        self.confirm_unsaved_changes(PendingAction::Export);
+    }
+    pub fn do_export_to_last(&mut self) {
+        self.confirm_unsaved_changes(PendingAction::ExportToLast);
+    }
+    fn pick_export_path(&mut self) {
+        let mut dialog = FileDialog::new().set_title("Export NDS ROM");
+        // Starts where the last export went
+        match &self.project_settings.last_rom_path {
+            Some(last_path) => {
+                if let Some(folder) = last_path.parent() {
+                    dialog = dialog.set_directory(folder);
+                }
+                let file_name = last_path.file_name().map_or(String::from("rom.nds"), |name| name.to_string_lossy().to_string());
+                dialog = dialog.set_file_name(file_name);
+            }
+            None => dialog = dialog.set_file_name("rom.nds")
+        }
+        if let Some(path) = dialog.save_file() {
+            self.exporting_to = path.display().to_string();
+            self.exporting_progress = Some(0.0);
+        }
+    }
+    fn export_to_last(&mut self) {
+        let Some(last_path) = self.project_settings.last_rom_path.clone() else {
+            log_write("No previous export, asking where to export", LogLevel::Debug);
+            self.pick_export_path();
+            return;
+        };
+        self.exporting_to = last_path.display().to_string();
+        self.exporting_progress = Some(0.0);
+    }
+    /// Runs the action now, or asks first if there are unsaved changes
+    pub fn confirm_unsaved_changes(&mut self, action: PendingAction) {
+        if self.display_engine.unsaved_changes {
+            self.unsaved_changes_action = Some(action);
+        } else {
+            self.run_pending_action(action);
+        }
+    }
+    fn run_pending_action(&mut self, action: PendingAction) {
+        log_write(format!("Running pending action {:?}",action), LogLevel::Debug);
+        match action {
+            PendingAction::Exit => self.quit_requested = true,
+            PendingAction::Export => self.pick_export_path(),
+            PendingAction::ExportToLast => self.export_to_last(),
+            PendingAction::ChangeCourse => self.change_course_open = true,
+            PendingAction::ChangeMap => self.change_map_open = true,
+            PendingAction::Switch(target) => self.switch_to(target),
+            PendingAction::MoveProject(new_folder) => self.open_project(new_folder),
+            PendingAction::ReloadMap => self.discard_changes(Option::None),
+        }
+    }
+    fn resolve_unsaved_changes(&mut self, choice: UnsavedChoice) {
+        let Some(action) = self.unsaved_changes_action.take() else { return };
+        match choice {
+            UnsavedChoice::Cancel => { /* Drop the action */ },
+            UnsavedChoice::Discard => match action {
+                // Nothing left to keep once it closes
+                PendingAction::Exit => {
+                    self.display_engine.unsaved_changes = false;
+                    self.run_pending_action(action);
+                }
+                // The reload is the action itself
+                PendingAction::ReloadMap => self.discard_changes(Option::None),
+                _ => self.discard_changes(Some(action)),
+            }
+            UnsavedChoice::Save => {
+                self.after_save_action = Some(action);
+                self.do_save();
+            }
+        }
+    }
+    /// Reloads the Course and map from disk, dropping everything unsaved,
+    /// then runs `then` once the reload is swapped in
+    fn discard_changes(&mut self, then: Option<PendingAction>) {
+        log_write("Discarding unsaved changes", LogLevel::Log);
+        if let Some(map_index) = self.display_engine.map_index {
+            self.change_map(map_index as u32);
+            if let Some(pending) = &mut self.map_load {
+                pending.then = then;
+            }
+            return;
+        }
+        self.display_engine.unsaved_changes = false;
+        if let Some(action) = then {
+            self.run_pending_action(action);
+        }
+    }
+    /// Includes unsaved changes, since it reads the loaded map
+    pub fn do_dump_map_json(&mut self) {
+        let de = &self.display_engine;
+        let file_name = format!("{}.json",de.loaded_map.map_name);
+        let Some(dump_path) = FileDialog::new().set_title("Dump Map to JSON").set_file_name(file_name)
+            .add_filter("JSON", &["json"]).save_file() else {
+            return;
+        };
+        let map_info = de.map_index.and_then(|map_index| de.loaded_course.level_map_data.get(map_index));
+        let dump = dump_map(&de.loaded_map, map_info);
+        if let Err(error) = write_map_dump(&dump_path, &dump) {
+            log_write(format!("Failed to dump map to JSON: {error}"), LogLevel::Error);
+            self.do_alert(format!("Failed to write '{}': {error}",dump_path.display()));
+        }
+    }
+    /// Adds Sprites from a JSON list, or a map dump's sprites
+    pub fn do_import_sprites_json(&mut self) {
+        let Some(import_path) = FileDialog::new().set_title("Import Sprites from JSON")
+            .add_filter("JSON", &["json"]).pick_file() else {
+            return;
+        };
+        let json = match fs::read_to_string(&import_path) {
+            Ok(json) => json,
+            Err(error) => {
+                self.do_alert(format!("Failed to read '{}': {error}",import_path.display()));
+                return;
+            }
+        };
+        if self.display_engine.loaded_map.get_setd().is_none() {
+            self.do_alert(String::from("This map has no Sprite data (SETD) to import into"));
+            return;
+        }
+        let (map_width, map_height) = self.display_engine.loaded_map.get_dimensions();
+        let (sprites, clamped_count) = match sprites_from_json(&json, map_width, map_height) {
+            Ok(result) => result,
+            Err(error) => {
+                log_write(format!("Failed to import Sprites: {error}"), LogLevel::Error);
+                self.do_alert(format!("Failed to import Sprites: {error}"));
+                return;
+            }
+        };
+        log_write(format!("Importing {} Sprites from '{}'",sprites.len(),import_path.display()), LogLevel::Log);
+        for sprite in sprites {
+            self.display_engine.loaded_map.add_sprite(sprite);
+        }
+        self.check_unknown_sprites();
+        self.display_engine.graphics_update_needed = true;
+        self.display_engine.map_edited();
+        if clamped_count > 0 {
+            let clamped_msg = format!("{clamped_count} imported Sprites were outside the map and were moved to the edge");
+            log_write(&clamped_msg, LogLevel::Warn);
+            self.do_alert(clamped_msg);
+        }
+    }
+    /// Zips up stork.log and what is loaded, and copies an issue template
+    pub fn do_create_bug_bundle(&mut self, ctx: &egui::Context) {
+        let de = &self.display_engine;
+        let mut info = BugBundleInfo {
+            editor_version: VERSION.to_owned(),
+            game_version: get_gameversion_prettyname(&de.game_version),
+            ..Default::default()
+        };
+        if self.project_open {
+            info.rom_stamp = Some(de.rom_stamp.to_string());
+            info.course_label = Some(de.loaded_course.label.clone());
+            info.course_file = Some(de.loaded_course_name());
+            info.map_file = Some(de.loaded_map.map_name.clone());
+            info.map_index = de.map_index;
+        }
+        let map_path = (self.project_open && self.bug_bundle_include_map).then(|| PathBuf::from(&de.loaded_map.src_file));
+        let file_name = match &info.map_file {
+            Some(map_file) => format!("stork_report_{}.zip",map_file),
+            None => String::from("stork_report.zip")
+        };
+        let Some(bundle_path) = FileDialog::new().set_title("Create Report Bundle").set_file_name(file_name)
+            .add_filter("Zip", &["zip"]).save_file() else {
+            return;
+        };
+        if let Err(error) = write_bug_bundle(&bundle_path, &info, map_path.as_deref()) {
+            log_write(format!("Failed to create bug report bundle: {error}"), LogLevel::Error);
+            self.do_alert(format!("Failed to write '{}': {error}",bundle_path.display()));
+            return;
+        }
+        ctx.copy_text(info.issue_template());
+        self.do_alert(format!("Saved '{}'. An issue template was copied to the clipboard",bundle_path.display()));
+    }
+    /// Writes the report on another thread, see poll_report_export
+    pub fn do_export_report(&mut self) {
+        if self.report_receiver.is_some() {
+            log_write("Level report is already being written", LogLevel::Warn);
+            return;
+        }
+        let de = &self.display_engine;
+        let Some(universal_palette) = de.get_universal_palette() else {
+            self.do_alert(String::from("Could not load the universal palette for the report"));
+            return;
+        };
+        let file_name = format!("{}_report.html",de.loaded_course_name());
+        let Some(report_path) = FileDialog::new().set_title("Export Level Report").set_file_name(file_name)
+            .add_filter("HTML", &["html"]).add_filter("Markdown", &["md"]).save_file() else {
+            return;
+        };
+        let job = ReportJob {
+            course: de.loaded_course.clone(),
+            export_folder: de.export_folder.clone(),
+            universal_palette,
+            loaded_map: Some(de.loaded_map.clone()),
+            format: ReportFormat::from_path(&report_path),
+            report_path
+        };
+        self.report_progress = (0, job.course.level_map_data.len());
+        self.report_receiver = Some(spawn_report(job));
+    }
+    fn poll_report_export(&mut self, ctx: &egui::Context) {
+        let Some(receiver) = &self.report_receiver else { return };
+        let mut finished: Option<Result<PathBuf, ReportError>> = Option::None;
+        while let Ok(message) = receiver.try_recv() {
+            match message {
+                ReportMessage::Progress(done, total) => self.report_progress = (done, total),
+                ReportMessage::Finished(result) => finished = Some(result),
+            }
+        }
+        if let Some(result) = finished {
+            self.report_receiver = Option::None;
+            match result {
+                Ok(path) => self.do_alert(format!("Level report written to '{}'",path.display())),
+                Err(e) => {
+                    log_write(format!("Level report failed: {e}"), LogLevel::Error);
+                    self.do_alert(e.to_string());
+                }
+            }
+            return;
+        }
+        let (done, total) = self.report_progress;
+        egui::Window::new("Level Report").resizable(false).collapsible(false).show(ctx, |ui| {
+            ui.label(format!("Writing map {} of {}...",(done + 1).min(total),total));
+            ProgressBar::new(done as f32 / total.max(1) as f32).ui(ui);
+        });
+        // The thread does not wake the UI
+        ctx.request_repaint_after(std::time::Duration::from_millis(100));
+    }
+    /// Checks the saved files on another thread, see poll_verify_project
+    pub fn do_verify_project(&mut self) {
+        if self.verify_receiver.is_some() {
+            log_write("Project is already being verified", LogLevel::Warn);
+            return;
+        }
+        if self.display_engine.unsaved_changes {
+            log_write("Verifying project with unsaved changes, only saved files are checked", LogLevel::Warn);
+        }
+        self.verify_results = Option::None;
+        self.verify_progress = (0, 0);
+        self.verify_receiver = Some(spawn_verify(self.export_directory.clone()));
+    }
+    fn poll_verify_project(&mut self, ctx: &egui::Context) {
+        if let Some(receiver) = &self.verify_receiver {
+            let mut finished: Option<Vec<VerifyFileResult>> = Option::None;
+            while let Ok(message) = receiver.try_recv() {
+                match message {
+                    VerifyMessage::Progress(done, total) => self.verify_progress = (done, total),
+                    VerifyMessage::Finished(results) => finished = Some(results),
+                }
+            }
+            if let Some(results) = finished {
+                log_write(summarize(&results), LogLevel::Log);
+                self.verify_receiver = Option::None;
+                self.verify_results = Some(results);
+            } else {
+                let (done, total) = self.verify_progress;
+                egui::Window::new("Verify Project").resizable(false).collapsible(false).show(ctx, |ui| {
+                    ui.label(format!("Checking file {} of {}...",(done + 1).min(total),total));
+                    ProgressBar::new(done as f32 / total.max(1) as f32).ui(ui);
+                });
+                ctx.request_repaint_after(std::time::Duration::from_millis(100));
+                return;
+            }
+        }
+        let Some(results) = &self.verify_results else { return };
+        let mut results_open = true;
+        let mut close_clicked = false;
+        egui::Window::new("Verify Project Results").open(&mut results_open).show(ctx, |ui| {
+            let issue_count = results.iter().filter(|r| r.issue.is_some()).count();
+            if results.is_empty() {
+                ui.colored_label(egui::Color32::ORANGE, "No Map or Course files were found");
+            } else if issue_count == 0 {
+                ui.label(format!("All {} files load and save without changes",results.len()));
+            } else {
+                ui.colored_label(egui::Color32::ORANGE, format!("{} of {} files have issues",issue_count,results.len()));
+            }
+            ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                egui::Grid::new("verify_results_grid").striped(true).show(ui, |ui| {
+                    for result in results {
+                        if let Some(issue) = &result.issue {
+                            ui.label(&result.file_name);
+                            ui.label(issue.to_string());
+                            ui.end_row();
+                        }
+                    }
+                });
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Copy Report").clicked() {
+                    ui.ctx().copy_text(summarize(results));
+                }
+                if ui.button("Close").clicked() {
+                    close_clicked = true;
+                }
+            });
+        });
+        if !results_open || close_clicked {
+            self.verify_results = Option::None;
+        }
+    }
+    /// The loaded map is changed in memory like any other edit, the whole
+    /// project runs in the background and saves as it goes
+    pub fn do_run_script(&mut self) {
+        let script = match parse_script(&self.script_state.text) {
+            Ok(s) => s,
+            Err(e) => {
+                self.script_state.output = e.to_string();
+                return;
+            }
+        };
+        let dry_run = self.script_state.dry_run;
+        if self.script_state.on_project {
+            if self.script_receiver.is_some() {
+                log_write("A script is already running", LogLevel::Warn);
+                return;
+            }
+            if !dry_run && self.display_engine.unsaved_changes {
+                self.script_state.output = String::from("Save or discard the loaded map's changes first, the script may change its file");
+                return;
+            }
+            self.script_state.output.clear();
+            self.script_progress = (0, 0);
+            self.script_receiver = Some(spawn_project_script(script, self.export_directory.clone(), dry_run));
+            return;
+        }
+        let map_name = script_map_name(&self.display_engine.loaded_map);
+        if !script.applies_to(&map_name) {
+            self.script_state.output = format!("'{map_name}' doesn't match the script's maps lines, nothing was run");
+            return;
+        }
+        let (result, edited) = run_checked(&script, &self.display_engine.loaded_map);
+        let mut report = ScriptReport { dry_run, maps_run: 1, results: Vec::new() };
+        if result.error.is_some() || !result.changes.is_empty() {
+            report.results.push(result.clone());
+        }
+        self.script_state.output = report.to_string();
+        let Some(edited) = edited else { return };
+        if dry_run || result.changes.is_empty() {
+            return;
         }
+        log_write(format!("Script changed the loaded map: {}",result.changes.join(", ")), LogLevel::Log);
+        // Its own undo point, like a segment revert
+        self.map_history.add_undo(&self.display_engine.loaded_map, self.input_time);
+        self.display_engine.loaded_map = edited;
+        self.display_engine.map_edited();
+        self.display_engine.graphics_update_needed = true;
+        self.map_history.add_undo(&self.display_engine.loaded_map, self.input_time);
     }
-    pub fn do_redo(&mut self) {
-        if let Some(map_state) = self.undoer.redo(&self.display_engine.loaded_map) {
-            log_write("Redoing", LogLevel::Debug);
-            self.display_engine.loaded_map = map_state.clone();
-            self.display_engine.unsaved_changes = true; // In case you saved
-            self.display_engine.graphics_update_needed = true;
+    fn poll_project_script(&mut self, ctx: &egui::Context) {
+        let Some(receiver) = &self.script_receiver else { return };
+        let mut finished: Option<Result<ScriptReport, ScriptError>> = Option::None;
+        while let Ok(message) = receiver.try_recv() {
+            match message {
+                ScriptMessage::Progress(done, total) => self.script_progress = (done, total),
+                ScriptMessage::Finished(result) => finished = Some(result),
+            }
         }
-    }
-    pub fn do_export(&mut self) {
-        if self.display_engine.unsaved_changes {
-            self.export_changes_open = true;
-        } else {
-            if let Some(path) = FileDialog::new().set_title("Export NDS ROM").set_file_name("rom.nds").save_file() {
-                self.exporting_to = path.display().to_string();
-                self.exporting_progress = Some(0.0);
+        let Some(result) = finished else {
+            let (done, total) = self.script_progress;
+            egui::Window::new("Running Script").resizable(false).collapsible(false).show(ctx, |ui| {
+                ui.label(format!("Map {} of {}...",(done + 1).min(total),total));
+                ProgressBar::new(done as f32 / total.max(1) as f32).ui(ui);
+            });
+            ctx.request_repaint_after(std::time::Duration::from_millis(100));
+            return;
+        };
+        self.script_receiver = Option::None;
+        match result {
+            Err(e) => self.script_state.output = e.to_string(),
+            Ok(report) => {
+                log_write(report.to_string(), LogLevel::Log);
+                let map_name = script_map_name(&self.display_engine.loaded_map);
+                let loaded_saved = report.results.iter().any(|r| r.saved && r.map_name == map_name);
+                self.script_state.output = report.to_string();
+                // The map can still be edited while it runs, so ask again before reloading
+                if loaded_saved {
+                    log_write("Script saved the loaded map, reloading", LogLevel::Log);
+                    self.confirm_unsaved_changes(PendingAction::ReloadMap);
+                }
             }
         }
     }
     pub fn do_change_course(&mut self) {
-        if self.display_engine.unsaved_changes {
-            self.change_course_unsaved_changes_show = true;
-        } else {
-            self.change_course_open = true;
+        self.confirm_unsaved_changes(PendingAction::ChangeCourse);
+    }
+    /// Copies the loaded map's saved file into another Course, which is saved
+    /// without being opened
+    pub fn do_copy_map_to_course(&mut self) {
+        let (world_index, level_index) = self.copy_map_dest;
+        let dest_course = self.display_engine.get_level_filename(&world_index, &level_index);
+        if dest_course == self.display_engine.loaded_course_name() {
+            self.do_alert(String::from("That is the open Course, use New in Course Settings instead"));
+            return;
+        }
+        let Some(source) = self.display_engine.map_index
+            .and_then(|map_index| self.display_engine.loaded_course.level_map_data.get(map_index)).cloned() else {
+            log_write("No loaded map to copy", LogLevel::Error);
+            return;
+        };
+        match copy_map_to_course(&self.export_directory, &source, &dest_course) {
+            Ok(new_name) => {
+                self.course_preview_cache.remove(&dest_course);
+                self.map_exists_cache.remove(&new_name);
+                self.do_alert(format!("Copied {} to Course {}-{} ({}) as {}",
+                    source.map_filename_noext,world_index+1,level_index+1,dest_course,new_name));
+            }
+            Err(error) => {
+                log_write(error.to_string(), LogLevel::Error);
+                self.do_alert(format!("{error}. Nothing was changed"));
+            }
         }
     }
     pub fn change_level(&mut self, world_index: u32, level_index: u32) {
@@ -369,23 +1581,189 @@ impl Gui {
             log_write(format!("Attempted to load level greater than 10: {}",level_index+1), LogLevel::Error);
             return;
         }
-        self.clear_map_data();
-        match self.display_engine.load_level(world_index, level_index,0) {
-            Ok(_) => { /* Do nothing, it worked */},
+        let course_name = self.display_engine.get_level_filename(&world_index, &level_index);
+        let map_index = self.display_engine.map_index_for_course(&course_name);
+        let label = format!("Course {}-{}",world_index+1,level_index+1);
+        self.start_map_load(course_name, label, map_index, true, MapLoadKind::Level(world_index, level_index));
+    }
+    /// Load a Course with no World/Level index, like kuppa
+    pub fn change_special_course(&mut self, course_name_noext: &str) {
+        log_write(format!("Changing to special Course '{}'",course_name_noext), LogLevel::Log);
+        let map_index = self.display_engine.map_index_for_course(course_name_noext);
+        self.change_course_map(course_name_noext, special_course_title(course_name_noext), map_index);
+    }
+    /// Load a map in any Course by file name, falling back to the first map
+    fn change_course_map(&mut self, course_name_noext: &str, course_label: String, map_index: u32) {
+        self.start_map_load(course_name_noext.to_string(), course_label, map_index, true, MapLoadKind::Course);
+    }
+    /// Reads the Course and map on another thread, poll_map_load swaps them in
+    ///
+    /// The latest load wins, Discard's reload holds what comes after it
+    fn start_map_load(&mut self, course_name_noext: String, label: String, map_index: u32, fallback_to_first: bool, kind: MapLoadKind) {
+        if let Some(previous) = self.map_load.take() {
+            log_write(format!("Dropping the load of {} for a newer one",previous.description), LogLevel::Debug);
+        }
+        let description = format!("{} map {}",label,map_index + 1);
+        let job = MapLoadJob {
+            export_folder: self.display_engine.export_folder.clone(),
+            course_name_noext, label, map_index, fallback_to_first
+        };
+        self.map_load = Some(PendingMapLoad { receiver: spawn_map_load(job), description, kind, then: Option::None });
+    }
+    fn poll_map_load(&mut self, ctx: &egui::Context) {
+        let Some(pending) = &self.map_load else { return };
+        let result = match pending.receiver.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => {
+                let description = pending.description.clone();
+                Modal::new(Id::new("map_load_modal")).show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label(format!("Loading {}...",description));
+                    });
+                });
+                return;
+            }
+            Err(TryRecvError::Disconnected) => {
+                self.map_load = Option::None;
+                log_write("Map loading thread stopped without a result", LogLevel::Error);
+                self.do_alert(String::from("Loading the map failed unexpectedly"));
+                return;
+            }
+        };
+        let Some(pending) = self.map_load.take() else { return };
+        self.finish_map_load(pending.kind, pending.then, result);
+    }
+    fn finish_map_load(&mut self, kind: MapLoadKind, then: Option<PendingAction>, result: Result<LoadedCourseMap, LoadLevelError>) {
+        let loaded = match result {
+            Ok(loaded) => loaded,
             Err(e) => {
+                // Nothing was swapped in, so the current map and its edits stay,
+                // and whatever was waiting on the reload is dropped
                 self.do_alert(e.to_string());
-                // It will have reverted, refresh
-                self.display_engine.graphics_update_needed = true;
                 return;
             }
+        };
+        self.clear_map_data();
+        self.display_engine.apply_course_map(loaded);
+        self.display_engine.unsaved_changes = false;
+        match kind {
+            MapLoadKind::Level(world_index, level_index) => {
+                self.cur_level = level_index;
+                self.cur_world = world_index;
+                self.apply_start_layer();
+            }
+            MapLoadKind::Course => self.apply_start_layer(),
+            MapLoadKind::Map => {}
         }
-        self.cur_level = level_index;
-        self.cur_world = world_index;
+        self.recent_maps.record(&self.display_engine);
         self.display_engine.needs_bg_tile_refresh = true;
+        self.check_tile_lengths();
+        self.check_unknown_sprites();
         if !self.display_engine.loaded_map.unhandled_headers.is_empty() {
             let segments_str = self.display_engine.loaded_map.unhandled_headers.join(", ");
             self.do_alert(format!("Found unhandled map segments {}. Do not save!",segments_str));
         }
+        if let Some(action) = then {
+            self.run_pending_action(action);
+        }
+    }
+    /// Goes through the unsaved changes guard first
+    pub fn do_switch(&mut self, target: SwitchTarget) {
+        self.confirm_unsaved_changes(PendingAction::Switch(target));
+    }
+    fn switch_to(&mut self, target: SwitchTarget) {
+        match target {
+            SwitchTarget::Course(world_index, level_index) => self.change_level(world_index, level_index),
+            SwitchTarget::SpecialCourse(course_name) => self.change_special_course(&course_name),
+            SwitchTarget::Map(map_index) => {
+                // Same as the map modal, exit targeting uses the saved data
+                if self.save_course_if_changed() {
+                    self.change_map(map_index);
+                }
+            }
+            SwitchTarget::Recent(recent) => {
+                if recent.course_name == self.display_engine.loaded_course_name() {
+                    if self.save_course_if_changed() {
+                        self.change_map(recent.map_index);
+                    }
+                } else {
+                    self.change_course_map(&recent.course_name, recent.course_label, recent.map_index);
+                }
+            }
+        }
+    }
+    /// Redraws everything from MapData, for when the view looks stale
+    pub fn do_refresh_graphics(&mut self) {
+        if !self.project_open {
+            return;
+        }
+        log_write("Manually refreshing graphics", LogLevel::Log);
+        wipe_tile_cache(&mut self.display_engine.tile_cache_bg1);
+        wipe_tile_cache(&mut self.display_engine.tile_cache_bg2);
+        wipe_tile_cache(&mut self.display_engine.tile_cache_bg3);
+        self.display_engine.graphics_update_needed = true;
+        self.display_engine.needs_bg_tile_refresh = true;
+    }
+    /// Reloads the current BG's tileset, for after replacing it outside the editor
+    pub fn do_reload_tileset(&mut self) {
+        let which_bg: u8 = match self.display_engine.display_settings.current_layer {
+            CurrentLayer::BG1 => 1,
+            CurrentLayer::BG2 => 2,
+            CurrentLayer::BG3 => 3,
+            _ => return
+        };
+        if !self.project_open {
+            return;
+        }
+        if !self.display_engine.reload_tileset(which_bg) {
+            self.do_alert(format!("Could not reload the tileset for BG {}, see the log",which_bg));
+        }
+    }
+    /// Saves the view center and layer into a slot for the loaded map
+    pub fn save_bookmark(&mut self, slot: u8) {
+        if !self.project_open || self.grid_viewport == Rect::NOTHING {
+            return;
+        }
+        let center = self.grid_viewport.center();
+        let map_name = self.display_engine.loaded_map.map_name.clone();
+        log_write(format!("Saving bookmark {} on '{}' at {}/{}",slot,map_name,center.x,center.y), LogLevel::Log);
+        self.project_settings.set_bookmark(&map_name, slot, center.x, center.y, self.display_engine.display_settings.current_layer);
+        self.save_project_settings();
+    }
+    pub fn jump_to_bookmark(&mut self, slot: u8) {
+        if !self.project_open {
+            return;
+        }
+        let Some(bookmark) = self.project_settings.get_bookmark(&self.display_engine.loaded_map.map_name, slot) else {
+            log_write(format!("No bookmark {} on this map",slot), LogLevel::Debug);
+            return;
+        };
+        self.scroll_to = Some(Pos2::new(bookmark.x, bookmark.y));
+        let layer = bookmark.layer;
+        self.display_engine.change_layer(layer);
+    }
+    /// Eases the main grid to offset, picking up from wherever it is now
+    fn animate_grid_scroll(&mut self, offset: Vec2, time: f64) {
+        let duration = if self.display_engine.display_settings.smooth_scrolling { GRID_SCROLL_SECS } else { 0.0 };
+        self.grid_scroll = Some(GridScroll { from: self.grid_scroll_offset, to: offset, start_time: time, duration });
+    }
+    /// Keeps the project settings and the Settings field in step with where the project is
+    fn record_project_folder(&mut self, path: &Path) {
+        self.project_folder_edit = path.display().to_string();
+        if self.project_settings.export_directory.as_deref() == Some(path) {
+            return;
+        }
+        if let Some(old_folder) = &self.project_settings.export_directory {
+            log_write(format!("Project moved from '{}' to '{}'",old_folder.display(),path.display()), LogLevel::Log);
+        }
+        self.project_settings.export_directory = Some(path.to_path_buf());
+        self.save_project_settings();
+    }
+    pub fn save_project_settings(&self) {
+        if let Err(error) = self.project_settings.save(&self.export_directory) {
+            log_write(format!("Failed to save {}: {error}",PROJECT_SETTINGS_FILENAME), LogLevel::Error);
+        }
     }
     pub fn clear_map_data(&mut self) {
         wipe_tile_cache(&mut self.display_engine.tile_cache_bg1);
@@ -394,11 +1772,15 @@ impl Gui {
         self.bg2_tile_preview_cache.clear();
         wipe_tile_cache(&mut self.display_engine.tile_cache_bg3);
         self.bg3_tile_preview_cache.clear();
+        self.bg_tile_usage = Default::default();
+        self.bg_preview_stale = [true;3];
         self.display_engine.bg_layer_1 = Option::None;
         self.display_engine.bg_layer_2 = Option::None;
         self.display_engine.bg_layer_3 = Option::None;
         self.display_engine.bg_palettes = [Palette::default();16];
+        self.display_engine.segment_baseline = Default::default();
         self.display_engine.path_data = Option::None;
+        self.display_engine.path_geometry = PathGeometry::default();
         self.display_engine.level_sprites.clear();
         self.display_engine.gradient_data = Option::None;
         self.display_engine.sprite_drag_status.dragging_uuid = Uuid::nil();
@@ -406,90 +1788,215 @@ impl Gui {
         self.display_engine.brush_settings.cur_search_string.clear();
         self.display_engine.brush_settings.pos_brush_name.clear();
         self.display_engine.brush_settings.cur_selected_brush = Option::None;
+        self.display_engine.brush_settings.current_brush_edited = false;
+        self.display_engine.brush_settings.preview_cache.clear();
         self.display_engine.current_brush.clear();
         self.display_engine.selected_preview_tile = None;
-        self.undoer = Undoer::default(); // Contains references to the map
+        self.display_engine.col_fill_anchor = None;
+        self.display_engine.grid_menu = None;
+        self.map_history.clear(); // Contains references to the map
     }
-    pub fn do_change_map(&mut self) {
-        if self.display_engine.unsaved_changes {
-            self.change_map_unsaved_changes_show = true;
-        } else {
-            self.change_map_open = true;
+    /// The loaded data would overwrite the reverted file on save otherwise
+    fn reload_if_reverted(&mut self, rel_path: &str) {
+        let reverted_path = self.export_directory.join(rel_path);
+        let map_path = PathBuf::from(&self.display_engine.loaded_map.src_file);
+        let course_path = PathBuf::from(&self.display_engine.loaded_course.src_filename);
+        if reverted_path != map_path && reverted_path != course_path {
+            return;
         }
+        let Some(map_index) = self.display_engine.map_index else {
+            return;
+        };
+        log_write("Reverted a loaded file, reloading", LogLevel::Log);
+        self.change_map(map_index as u32);
+        self.display_engine.unsaved_changes = false;
     }
-    pub fn change_map(&mut self, map_index: u32) {
-        self.clear_map_data();
-        match self.display_engine.load_level(self.cur_world, self.cur_level, map_index) {
-            Ok(_) => { /* Do nothing, it worked */},
-            Err(e) => {
-                self.do_alert(e.to_string());
-                // It will have reverted, refresh
-                self.display_engine.graphics_update_needed = true;
+
+    /// Selects the first of the pair, changing maps if needed
+    fn jump_to_warp_overlap(&mut self, overlap: &WarpOverlap) {
+        log_write(format!("Jumping to overlap '{}'",overlap.label), LogLevel::Debug);
+        if self.display_engine.map_index != Some(overlap.map_index) {
+            if self.display_engine.unsaved_changes {
+                self.do_alert("Save the current map before jumping to another one".to_owned());
                 return;
             }
+            self.change_map(overlap.map_index as u32);
         }
-        self.display_engine.needs_bg_tile_refresh = true;
-        if !self.display_engine.loaded_map.unhandled_headers.is_empty() {
-            let segments_str = self.display_engine.loaded_map.unhandled_headers.join(", ");
-            self.do_alert(format!("Found unhandled map segments {}. Do not save!",segments_str));
+        self.display_engine.course_settings.selected_map = Some(overlap.map_index);
+        match overlap.kind {
+            WarpKind::Entrance => self.display_engine.course_settings.selected_entrance = Some(overlap.first),
+            WarpKind::Exit => self.display_engine.course_settings.selected_exit = Some(overlap.first),
+        }
+        self.scroll_to = Some(Pos2::new(overlap.x as f32 * 8.0, overlap.y as f32 * 8.0));
+        self.display_engine.graphics_update_needed = true;
+    }
+
+    pub fn do_change_map(&mut self) {
+        self.confirm_unsaved_changes(PendingAction::ChangeMap);
+    }
+    pub fn change_map(&mut self, map_index: u32) {
+        // Reload from the Course file itself, since special Courses have no World/Level
+        let course_name = self.display_engine.loaded_course_name();
+        let course_label = self.display_engine.loaded_course.label.clone();
+        self.start_map_load(course_name, course_label, map_index, false, MapLoadKind::Map);
+    }
+    /// Drops a Course entry whose .mpdz is gone. Saved with the rest of the Course
+    pub fn remove_missing_map(&mut self, map_index: usize) {
+        let de = &mut self.display_engine;
+        if de.map_index == Some(map_index) {
+            log_write("Cannot remove the loaded map's entry", LogLevel::Warn);
+            return;
+        }
+        let Some(map) = de.loaded_course.level_map_data.get(map_index) else {
+            log_write(format!("No map {} to remove",map_index), LogLevel::Error);
+            return;
+        };
+        log_write(format!("Removing Course entry for missing map '{}'",&map.map_filename_noext), LogLevel::Log);
+        if !de.loaded_course.delete_map_info_by_index(map_index) {
+            return;
+        }
+        // Entries after it moved up one
+        if let Some(loaded_index) = de.map_index.filter(|loaded_index| *loaded_index > map_index) {
+            de.map_index = Some(loaded_index - 1);
+        }
+        de.unsaved_changes = true;
+        self.map_exists_cache.clear();
+        self.map_size_cache.clear();
+    }
+    fn do_repoint_map(&mut self, map_index: usize) {
+        let folder = nitrofs_abs(self.display_engine.export_folder.to_path_buf(), "");
+        let Some(path) = FileDialog::new().set_title("Choose Map File").set_directory(&folder)
+            .add_filter("Map", &["mpdz"]).pick_file() else {
+            return;
+        };
+        let name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        if path.parent() != Some(folder.as_path()) {
+            self.do_alert(format!("'{}' is not in the project's file folder, copy it there first",path.display()));
+            return;
         }
+        self.repoint_map(map_index, name);
+    }
+    /// Points a Course entry at another .mpdz, keeping its warps and music
+    pub fn repoint_map(&mut self, map_index: usize, map_filename_noext: String) {
+        let Some(map) = self.display_engine.loaded_course.level_map_data.get_mut(map_index) else {
+            log_write(format!("No map {} to point at '{}'",map_index,&map_filename_noext), LogLevel::Error);
+            return;
+        };
+        log_write(format!("Pointing map {} from '{}' to '{}'",map_index,&map.map_filename_noext,&map_filename_noext), LogLevel::Log);
+        map.map_filename_noext = map_filename_noext;
+        self.display_engine.unsaved_changes = true;
+        self.map_exists_cache.clear();
+        self.map_size_cache.clear();
     }
-    fn save_map(&mut self) {
+    fn save_map(&mut self) -> Result<(), SaveError> {
         log_write("Saving Map file", LogLevel::Debug);
         let file_name_ext: String = self.display_engine.loaded_map.src_file.clone();
-        let _backup_res = self.backup_map();
+        // Don't overwrite anything that couldn't be backed up
+        self.backup_map()?;
         // Create Map file
         let file_data = self.display_engine.loaded_map.package();
-        let mut file = match File::create(&file_name_ext) {
-            Err(error) => {
-                log_write(format!("Failed to create Map file: '{error}'"), LogLevel::Error);
-                return;
-            }
-            Ok(f) => f,
-        };
+        let mut file = File::create(&file_name_ext).map_err(|error| SaveError::Create(file_name_ext.clone(), error))?;
         // Write file
-        match file.write_all(&file_data) {
-            Err(error) => {
-                log_write(format!("Failed to write Map file: '{error}'"), LogLevel::Error);
-            }
-            Ok(_) => {
-                log_write(format!("Map file saved to '{}'",&file_name_ext), LogLevel::Log);
-                self.display_engine.unsaved_changes = false;
-            }
-        };
+        file.write_all(&file_data).map_err(|error| SaveError::Write(file_name_ext.clone(), error))?;
+        log_write(format!("Map file saved to '{}'",&file_name_ext), LogLevel::Log);
+        Ok(())
     }
 
-    fn backup_map(&mut self) -> Option<PathBuf> {
+    /// Copies the Map file on disk into the backups folder, if there is one
+    fn backup_map(&mut self) -> Result<Option<PathBuf>, SaveError> {
         log_write("Backing up current map file...", LogLevel::Debug);
-        let mut backup_folder = get_backup_folder(&self.export_directory)?;
-        let filename_path = Path::new(&self.display_engine.loaded_map.src_file);
-        let file_name = filename_path.file_name().expect("Should be a file name for the path");
-        let file_name = file_name.to_string_lossy().to_string();
-        let time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time Travel").as_secs();
-        backup_folder.push(format!("{}.{:?}.bak",file_name,time));
-        let _copy_res = fs::copy(&self.display_engine.loaded_map.src_file, &backup_folder);
-        log_write(format!("Backed up {} to {}",&self.display_engine.loaded_map.src_file,backup_folder.display()), LogLevel::Log);
-        Some(backup_folder)
-    }
-
-    fn save_course(&mut self) {
+        let src_file = &self.display_engine.loaded_map.src_file;
+        backup_file(&self.export_directory, Path::new(src_file))
+            .map_err(|error| SaveError::Backup(src_file.clone(), error))
+    }
+
+    fn save_course(&mut self) -> Result<(), SaveError> {
         let file_name_ext = self.display_engine.loaded_course.src_filename.clone();
         log_write(format!("Saving Course file '{}'",&file_name_ext), LogLevel::Log);
         let packed_level_file = self.display_engine.loaded_course.wrap();
-        let mut file = match File::create(&file_name_ext) {
+        let mut file = File::create(&file_name_ext).map_err(|error| SaveError::Create(file_name_ext.clone(), error))?;
+        // Write file
+        file.write_all(&packed_level_file).map_err(|error| SaveError::Write(file_name_ext.clone(), error))?;
+        log_write(format!("Course file saved to '{}'",&file_name_ext), LogLevel::Log);
+        self.display_engine.saved_course = packed_level_file;
+        Ok(())
+    }
+    /// Saves the Course alone, alerting on failure. Used before changing maps,
+    /// since exit targeting reads the saved Course
+    fn save_course_or_alert(&mut self) -> bool {
+        // Same refusal as a normal save
+        if self.check_format_limits() {
+            return false;
+        }
+        match self.save_course() {
+            Ok(()) => true,
             Err(error) => {
-                log_write(format!("Failed to create Course file: '{error}'"), LogLevel::Error);
-                return;
+                log_write(error.to_string(), LogLevel::Error);
+                self.do_alert(format!("{error}. The map was not changed"));
+                false
             }
-            Ok(f) => f,
+        }
+    }
+    /// Saves the Course before a map change only if it was edited, so
+    /// merely switching maps leaves the file on disk alone
+    fn save_course_if_changed(&mut self) -> bool {
+        !self.display_engine.course_changed() || self.save_course_or_alert()
+    }
+    /// Map then Course, stopping at the first failure
+    fn save_all(&mut self) -> Result<(), SaveError> {
+        self.save_map()?;
+        self.save_course()?;
+        self.display_engine.unsaved_changes = false;
+        Ok(())
+    }
+    /// Builds the preview cache the BG Tiles window is showing, if it's stale.
+    /// The others wait until they're shown, since each one can take a while
+    fn refresh_bg_preview(&mut self, ctx: &egui::Context) {
+        puffin::profile_function!();
+        if self.display_engine.needs_bg_tile_refresh {
+            self.display_engine.needs_bg_tile_refresh = false;
+            self.bg_preview_stale = [true;3];
+        }
+        if !self.tile_preview_window_open || !self.bg_preview_waiting {
+            return;
+        }
+        self.bg_preview_waiting = false;
+        let which_bg: u8 = match self.display_engine.display_settings.current_layer {
+            CurrentLayer::BG1 => 1,
+            CurrentLayer::BG2 => 2,
+            CurrentLayer::BG3 => 3,
+            _ => return
         };
-        // Write file
-        if let Err(error) = file.write_all(&packed_level_file) {
-            log_write(format!("Failed to write Course file: '{error}'"), LogLevel::Error);
-        } else {
-            log_write(format!("Course file saved to '{}'",&file_name_ext), LogLevel::Log);
-            self.display_engine.unsaved_changes = false;
+        let slot = which_bg as usize - 1;
+        if !self.bg_preview_stale[slot] {
+            return;
+        }
+        if self.display_engine.tile_preview_pal >= 16 {
+            // Should be completely impossible
+            log_write(format!("Tiles preview palette too high: '{}'",self.display_engine.tile_preview_pal), LogLevel::Fatal);
+            return;
+        }
+        log_write(format!("Regenerating BG {} tile cache",which_bg), LogLevel::Log);
+        let bg_pals: &Palette = &self.display_engine.bg_palettes[self.display_engine.tile_preview_pal];
+        let tex_hands = self.generate_bg_cache(ctx, which_bg, bg_pals);
+        match which_bg {
+            1 => self.bg1_tile_preview_cache = tex_hands,
+            2 => self.bg2_tile_preview_cache = tex_hands,
+            _ => self.bg3_tile_preview_cache = tex_hands
         }
+        // Usage counts for the used-only filter
+        self.bg_tile_usage[slot] = self.get_bg_tile_usage(which_bg);
+        self.bg_preview_stale[slot] = false;
+    }
+    /// Empty if the BG or its MPBZ is missing
+    fn get_bg_tile_usage(&self, which_bg: u8) -> Vec<u32> {
+        let layer = match which_bg {
+            0x1 => self.display_engine.bg_layer_1.as_ref(),
+            0x2 => self.display_engine.bg_layer_2.as_ref(),
+            0x3 => self.display_engine.bg_layer_3.as_ref(),
+            _ => Option::None
+        };
+        layer.and_then(|l| l.get_mpbz()).map(|mpbz| mpbz.tile_usage_counts()).unwrap_or_default()
     }
     pub fn generate_bg_cache(&self, ctx: &egui::Context, which_bg: u8, bg_pal: &Palette) -> Vec<TextureHandle> {
         puffin::profile_function!();
@@ -570,11 +2077,14 @@ impl Gui {
     fn handle_input(&mut self, ctx: &egui::Context) {
         puffin::profile_function!();
         if self.project_open { // Don't make loading the level an undo
-            self.undoer.feed_state(ctx.input(|input| input.time), &self.display_engine.loaded_map);
+            self.input_time = ctx.input(|input| input.time);
+            self.map_history.feed_state(self.input_time, &self.display_engine.loaded_map);
         }
-        let main_grid_focused = !*NON_MAIN_FOCUSED.lock().unwrap();
+        let main_grid_focused = !*NON_MAIN_FOCUSED.lock().unwrap() && !self.level_switcher.window_open;
         // Stupid workaround for text copy crashing in input_mut
         let mut should_copy = false;
+        // Loading inside input_mut would hold the input lock
+        let mut recent_switch: Option<RecentMap> = Option::None;
         ctx.input_mut(|i| {
             // if i.events.len() != 0 {
             //     println!("{:?}",i.events);
@@ -589,8 +2099,15 @@ impl Gui {
                 should_copy = true;
             }
             // God DAMN this is fucking janky, why Egui why
-            if i.events.iter().any(|e| matches!(e, egui::Event::Paste(_))) && main_grid_focused {
-                self.do_paste();
+            // Ctrl+Shift+V also sends a Paste event on most platforms
+            let paste_in_place = i.consume_shortcut(&KeyboardShortcut::new(Modifiers::CTRL | Modifiers::SHIFT, Key::V));
+            let paste_event = i.events.iter().any(|e| matches!(e, egui::Event::Paste(_)));
+            if (paste_event || paste_in_place) && main_grid_focused {
+                if paste_in_place || i.modifiers.shift {
+                    self.do_paste_in_place();
+                } else {
+                    self.do_paste();
+                }
             }
             // Save
             if i.consume_shortcut(&KeyboardShortcut::new(Modifiers::CTRL, Key::S)) {
@@ -598,6 +2115,13 @@ impl Gui {
                     self.do_save();
                 }
             }
+            // Export to last location
+            if i.consume_shortcut(&KeyboardShortcut::new(Modifiers::CTRL, Key::E)) {
+                if self.project_open {
+                    self.do_export_to_last();
+                }
+                return;
+            }
             // Open ROM
             if i.consume_shortcut(&KeyboardShortcut::new(Modifiers::CTRL | Modifiers::SHIFT, Key::O)) {
                 if let Err(error) = self.do_open_rom() {
@@ -610,8 +2134,45 @@ impl Gui {
                 self.do_open_project();
                 return;
             }
+            // Level switcher
+            if i.consume_shortcut(&KeyboardShortcut::new(Modifiers::CTRL, Key::G)) {
+                if self.project_open {
+                    self.level_switcher.open(&self.display_engine);
+                }
+                return;
+            }
+            // Refresh graphics
+            if i.consume_shortcut(&KeyboardShortcut::new(Modifiers::NONE, Key::F5)) {
+                self.do_refresh_graphics();
+                return;
+            }
             // These all work normally outside of the main grid
             if main_grid_focused {
+                // Back to the previous map, pressing again returns
+                if i.consume_shortcut(&KeyboardShortcut::new(Modifiers::CTRL, Key::Tab)) {
+                    if self.project_open {
+                        if let Some(previous) = self.recent_maps.maps.get(1).cloned() {
+                            recent_switch = Some(previous);
+                        }
+                    }
+                    return;
+                }
+                // Bookmarks, saving first since Ctrl+N would also consume Ctrl+Shift+N
+                for (slot, key) in BOOKMARK_KEYS.iter().enumerate() {
+                    if i.consume_shortcut(&KeyboardShortcut::new(Modifiers::CTRL | Modifiers::SHIFT, *key)) {
+                        self.save_bookmark(slot as u8 + 1);
+                        return;
+                    }
+                    if i.consume_shortcut(&KeyboardShortcut::new(Modifiers::CTRL, *key)) {
+                        self.jump_to_bookmark(slot as u8 + 1);
+                        return;
+                    }
+                }
+                // Redo, before Undo since Ctrl+Z would also consume Ctrl+Shift+Z
+                if i.consume_shortcut(&KeyboardShortcut::new(Modifiers::CTRL | Modifiers::SHIFT, Key::Z)) {
+                    self.do_redo();
+                    return;
+                }
                 // Undo
                 if i.consume_shortcut(&KeyboardShortcut::new(Modifiers::CTRL, Key::Z)) {
                     self.do_undo();
@@ -632,52 +2193,53 @@ impl Gui {
                     self.do_select_all();
                     return;
                 }
+                // Place Sprite
+                if i.consume_shortcut(&KeyboardShortcut::new(Modifiers::NONE, Key::P)) {
+                    self.do_place_sprite_at_cursor();
+                    return;
+                }
+                // Scrolling, before the Sprite controls since Ctrl+arrows would also move them
+                if self.project_open && self.grid_viewport != Rect::NOTHING {
+                    for (modifiers, key) in GRID_SCROLL_KEYS {
+                        if !i.consume_key(modifiers, key) {
+                            continue;
+                        }
+                        // Repeated presses stack up instead of restarting from mid-flight
+                        let offset = self.grid_scroll.map_or(self.grid_scroll_offset, |scroll| scroll.to);
+                        if let Some(target) = grid_scroll_target(key, offset, self.grid_viewport.size(), self.grid_max_offset) {
+                            self.animate_grid_scroll(target, i.time);
+                        }
+                    }
+                }
                 // SPRITE CONTROLS //
                 if
                     self.display_engine.display_settings.current_layer == CurrentLayer::Sprites
+                    && !self.display_engine.display_settings.is_cur_layer_blocked()
                     && !self.display_engine.selected_sprite_uuids.is_empty()
                 {
-                    let mut should_update: bool = false;
-                    let mut should_deselect: bool = false;
-                    for s in &self.display_engine.selected_sprite_uuids {
-                        if let Some(s) = &self.display_engine.loaded_map.get_sprite_by_uuid(*s) {
-                            if i.key_pressed(egui::Key::ArrowUp) {
-                                self.display_engine.loaded_map.move_sprite(s.uuid, s.x_position, s.y_position - 1);
-                                should_update = true;
-                                self.display_engine.unsaved_changes = true;
-                            } else if i.key_pressed(egui::Key::ArrowLeft) {
-                                self.display_engine.loaded_map.move_sprite(s.uuid, s.x_position - 1, s.y_position);
-                                should_update = true;
-                                self.display_engine.unsaved_changes = true;
-                            } else if i.key_pressed(egui::Key::ArrowRight) {
-                                self.display_engine.loaded_map.move_sprite(s.uuid, s.x_position + 1, s.y_position);
-                                should_update = true;
-                                self.display_engine.unsaved_changes = true;
-                            } else if i.key_pressed(egui::Key::ArrowDown) {
-                                self.display_engine.loaded_map.move_sprite(s.uuid, s.x_position, s.y_position + 1);
-                                should_update = true;
-                                self.display_engine.unsaved_changes = true;
-                            } else if i.key_pressed(egui::Key::Delete) {
-                                let _ = self.display_engine.loaded_map.delete_sprite_by_uuid(s.uuid);
-                                should_deselect = true;
-                                should_update = true;
-                                self.display_engine.unsaved_changes = true;
-                            }
-                        } else {
-                            log_write("Something is very wrong in handle_input, sprite_data unwrap failed", LogLevel::Error);
-                        }
-                    }
-                    if should_update {
-                        self.display_engine.graphics_update_needed = true;
-                    }
-                    if should_deselect {
+                    // Read the keys once, so every selected Sprite gets the same step
+                    let dx = i.key_pressed(Key::ArrowRight) as i32 - i.key_pressed(Key::ArrowLeft) as i32;
+                    let dy = i.key_pressed(Key::ArrowDown) as i32 - i.key_pressed(Key::ArrowUp) as i32;
+                    let selected = &self.display_engine.selected_sprite_uuids;
+                    if i.key_pressed(Key::Delete) {
+                        let deleted = self.display_engine.loaded_map.delete_sprites(selected);
+                        log_write(format!("Deleted {deleted} selected Sprites"), LogLevel::Log);
                         self.display_engine.selected_sprite_uuids.clear();
+                        self.display_engine.graphics_update_needed = true;
+                        self.display_engine.map_edited();
+                    } else if (dx != 0 || dy != 0) && self.display_engine.loaded_map.move_sprites(selected, dx, dy) > 0 {
+                        self.display_engine.graphics_update_needed = true;
+                        self.display_engine.map_edited();
                     }
                 }
                 // BG CONTROLS //
                 if self.is_cur_layer_bg() {
                     if !self.display_engine.bg_sel_data.selected_map_indexes.is_empty() && !self.display_engine.bg_sel_data.dragging {
                         if i.key_pressed(egui::Key::Delete) {
+                            if self.display_engine.display_settings.is_cur_layer_blocked() {
+                                log_write("Cannot delete tiles on a hidden or locked layer", LogLevel::Warn);
+                                return;
+                            }
                             log_write(format!("Deleting selection with {} tiles",self.display_engine.bg_sel_data.selected_map_indexes.len()), LogLevel::Log);
                             for tile_index in &self.display_engine.bg_sel_data.selected_map_indexes {
                                 self.display_engine.loaded_map.delete_bg_tile_by_map_index(
@@ -685,7 +2247,7 @@ impl Gui {
                             }
                             self.display_engine.bg_sel_data.clear();
                             self.display_engine.graphics_update_needed = true;
-                            self.display_engine.unsaved_changes = true;
+                            self.display_engine.map_edited();
                         }
                     }
                 }
@@ -696,6 +2258,9 @@ impl Gui {
         if should_copy {
             ctx.copy_text(String::from("StorkCopy"));
         }
+        if let Some(recent) = recent_switch {
+            self.do_switch(SwitchTarget::Recent(recent));
+        }
     }
 
     fn is_cur_layer_bg(&self) -> bool {
@@ -775,11 +2340,43 @@ impl Gui {
         self.display_engine.selected_sprite_uuids.push(*sprite_uuid);
         if let Some(spr_res) = self.display_engine.loaded_map.get_sprite_by_uuid(*sprite_uuid) {
             self.display_engine.latest_sprite_settings = bytes_to_hex_string(&spr_res.settings);
+            self.display_engine.latest_sprite_object_id = spr_res.object_id;
         } else {
             log_write("Failed to get sprite by UUID in select_sprite_from_list", LogLevel::Error);
         }
     }
 
+    /// Same as right clicking, for placing many quickly
+    pub fn do_place_sprite_at_cursor(&mut self) {
+        if self.display_engine.display_settings.current_layer != CurrentLayer::Sprites {
+            return;
+        }
+        if self.display_engine.display_settings.is_cur_layer_blocked() {
+            log_write("Cannot place Sprites on a hidden or locked layer", LogLevel::Warn);
+            return;
+        }
+        let Some(new_sprite_id) = self.display_engine.selected_sprite_to_place else {
+            log_write("Could not place sprite, none selected to add", LogLevel::Debug);
+            return;
+        };
+        let Some(hover_tile) = self.display_engine.sprite_hover_tile else {
+            log_write("Could not place sprite, pointer is not on the map", LogLevel::Debug);
+            return;
+        };
+        let new_uuid = self.display_engine.loaded_map.add_new_sprite_at(new_sprite_id, hover_tile.x as u16, hover_tile.y as u16);
+        if new_uuid.is_nil() {
+            return;
+        }
+        log_write(format!("Placed sprite with UUID {new_uuid} from shortcut"), LogLevel::Debug);
+        self.display_engine.selected_sprite_uuids = vec![new_uuid];
+        if let Some(new_sprite) = self.display_engine.loaded_map.get_sprite_by_uuid(new_uuid) {
+            self.display_engine.latest_sprite_settings = bytes_to_hex_string(&new_sprite.settings);
+            self.display_engine.latest_sprite_object_id = new_sprite.object_id;
+        }
+        self.display_engine.map_edited();
+        self.display_engine.graphics_update_needed = true;
+    }
+
     pub fn do_select_all(&mut self) {
         if self.display_engine.display_settings.current_layer == CurrentLayer::Sprites {
             self.display_engine.selected_sprite_uuids.clear(); // So we don't get duplicates
@@ -790,10 +2387,12 @@ impl Gui {
             let which_bg = self.display_engine.display_settings.current_layer as u8;
             let bg_res = self.display_engine.loaded_map.get_background(which_bg);
             if let Some(bg) = bg_res {
-                if let Some(tiles) = bg.get_mpbz() {
-                    let all_indexes: Vec<u32> = (0..tiles.tiles.len() as u32).collect();
+                if bg.get_mpbz().is_some() {
+                    // Use the full layer, the tiles may not cover it all
+                    let info = bg.get_info().expect("Select All INFO");
+                    let all_indexes: Vec<u32> = (0..(info.layer_width as u32 * info.layer_height as u32)).collect();
                     self.display_engine.bg_sel_data.selected_map_indexes = all_indexes;
-                    self.display_engine.bg_sel_data.selection_width = bg.get_info().expect("Select All INFO").layer_width;
+                    self.display_engine.bg_sel_data.selection_width = info.layer_width;
                 } else {
                     log_write("MapTiles were not retrieved when seleting all", LogLevel::Error);
                 }
@@ -856,9 +2455,8 @@ impl Gui {
             let bg_res = self.display_engine.loaded_map.get_background(which_bg);
             if let Some(bg) = bg_res {
                 if let Some(tiles) = bg.get_mpbz() {
-                    let clips = self.display_engine.bg_sel_data.to_clipboard_tiles(
-                        bg.get_info().expect("Copy BG info guarantee").layer_width, &tiles.tiles);
-                    self.display_engine.clipboard.bg_clip.tiles = clips;
+                    self.display_engine.clipboard.bg_clip = self.display_engine.bg_sel_data.to_clipboard(
+                        bg.get_info().expect("Copy BG info guarantee").layer_width, tiles);
                     log_write(format!("Copied {} MapTiles to clipboard",
                         self.display_engine.clipboard.bg_clip.tiles.len()
                     ), LogLevel::Log);
@@ -874,7 +2472,9 @@ impl Gui {
     }
 
     pub fn is_cut_possible(&self) -> bool {
-        if self.display_engine.display_settings.current_layer == CurrentLayer::Sprites {
+        if self.display_engine.display_settings.is_cur_layer_blocked() {
+            false
+        } else if self.display_engine.display_settings.current_layer == CurrentLayer::Sprites {
             !self.display_engine.selected_sprite_uuids.is_empty()
         } else if self.display_engine.display_settings.is_cur_layer_bg() {
             !self.display_engine.bg_sel_data.selected_map_indexes.is_empty()
@@ -884,6 +2484,10 @@ impl Gui {
     }
 
     pub fn do_cut(&mut self) {
+        if self.display_engine.display_settings.is_cur_layer_blocked() {
+            log_write("Cannot cut from a hidden or locked layer", LogLevel::Warn);
+            return;
+        }
         // SPRITES
         if self.display_engine.display_settings.current_layer == CurrentLayer::Sprites {
             self.display_engine.clipboard.sprite_clip.sprites.clear();
@@ -914,7 +2518,7 @@ impl Gui {
             // The selection should no longer exist
             self.display_engine.selected_sprite_uuids.clear();
             self.display_engine.graphics_update_needed = true;
-            self.display_engine.unsaved_changes = true;
+            self.display_engine.map_edited();
             log_write(format!("Cut {} Sprites onto the clipboard",self.display_engine.clipboard.sprite_clip.sprites.len()), LogLevel::Log);
             return;
         }
@@ -928,15 +2532,14 @@ impl Gui {
             if let Some(bg) = bg_res {
                 let width = bg.get_info().expect("Guaranteed INFO in BG").layer_width;
                 if let Some(tiles) = bg.get_mpbz_mut() {
-                    let clips = self.display_engine.bg_sel_data.to_clipboard_tiles(width, &tiles.tiles);
-                    self.display_engine.clipboard.bg_clip.tiles = clips;
+                    self.display_engine.clipboard.bg_clip = self.display_engine.bg_sel_data.to_clipboard(width, tiles);
                     // Delete tiles that were selected
                     for tile_index in &self.display_engine.bg_sel_data.selected_map_indexes {
                         self.display_engine.loaded_map.delete_bg_tile_by_map_index(
                             self.display_engine.display_settings.current_layer as u8, *tile_index);
                     }
                     self.display_engine.bg_sel_data.clear();
-                    self.display_engine.unsaved_changes = true;
+                    self.display_engine.map_edited();
                     self.display_engine.graphics_update_needed = true;
                 } else {
                     log_write("MapTiles not retrieved when attempting to cut", LogLevel::Error);
@@ -951,7 +2554,9 @@ impl Gui {
     }
 
     pub fn is_paste_possible(&self) -> bool {
-        if self.display_engine.display_settings.current_layer == CurrentLayer::Sprites {
+        if self.display_engine.display_settings.is_cur_layer_blocked() {
+            false
+        } else if self.display_engine.display_settings.current_layer == CurrentLayer::Sprites {
             !self.display_engine.clipboard.sprite_clip.sprites.is_empty()
         } else if self.is_cur_layer_bg() {
             !self.display_engine.clipboard.bg_clip.tiles.is_empty()
@@ -961,64 +2566,180 @@ impl Gui {
     }
 
     pub fn do_paste(&mut self) {
+        self.paste_clipboard(false);
+    }
+    /// Pastes at the copied positions instead of at the cursor
+    pub fn do_paste_in_place(&mut self) {
+        self.paste_clipboard(true);
+    }
+    fn paste_clipboard(&mut self, in_place: bool) {
         if !self.project_open {
             log_write("Cannot paste while project is closed", LogLevel::Log);
             return;
         }
+        if self.display_engine.display_settings.is_cur_layer_blocked() {
+            log_write("Cannot paste onto a hidden or locked layer", LogLevel::Warn);
+            return;
+        }
+        let cursor_level_pos = self.display_engine.latest_square_pos_level_space;
+        let mut clipped_count: usize = 0;
         if self.display_engine.display_settings.current_layer == CurrentLayer::Sprites {
             log_write(format!("Pasting {} Sprites",self.display_engine.clipboard.sprite_clip.sprites.len()),LogLevel::Log);
-            let tl_x = self.display_engine.clipboard.sprite_clip.top_left_pos.x as i32;
-            let tl_y = self.display_engine.clipboard.sprite_clip.top_left_pos.y as i32;
-            let cursor_level_x = self.display_engine.latest_square_pos_level_space.x as i32;
-            let cursor_level_y = self.display_engine.latest_square_pos_level_space.y as i32;
-            for copied_sprite in &mut self.display_engine.clipboard.sprite_clip.sprites {
-                let stored_x = copied_sprite.x_position as i32;
-                let stored_y = copied_sprite.y_position as i32;
-                let x_offset = stored_x - tl_x;
-                let y_offset = stored_y - tl_y;
-                let true_level_x = cursor_level_x + x_offset;
-                let true_level_y = cursor_level_y + y_offset;
-                copied_sprite.x_position = true_level_x as u16;
-                copied_sprite.y_position = true_level_y as u16;
-                copied_sprite.uuid = Uuid::new_v4();
-                self.display_engine.loaded_map.add_sprite(copied_sprite.clone());
+            let top_left = self.display_engine.clipboard.sprite_clip.top_left_pos;
+            let origin = if in_place { top_left } else { cursor_level_pos };
+            let (map_width, map_height) = self.display_engine.loaded_map.get_dimensions();
+            for copied_sprite in &self.display_engine.clipboard.sprite_clip.sprites {
+                // Keep the clipboard at the copied positions
+                let mut pasted_sprite = copied_sprite.clone();
+                let true_level_x = origin.x as i32 + (copied_sprite.x_position as i32 - top_left.x as i32);
+                let true_level_y = origin.y as i32 + (copied_sprite.y_position as i32 - top_left.y as i32);
+                if true_level_x < 0 || true_level_y < 0 || true_level_x >= map_width as i32 || true_level_y >= map_height as i32 {
+                    clipped_count += 1;
+                    continue;
+                }
+                pasted_sprite.x_position = true_level_x as u16;
+                pasted_sprite.y_position = true_level_y as u16;
+                pasted_sprite.uuid = new_uuid();
+                self.display_engine.loaded_map.add_sprite(pasted_sprite);
             }
             self.display_engine.graphics_update_needed = true;
-            self.display_engine.unsaved_changes = true;
+            self.display_engine.map_edited();
         } else if self.is_cur_layer_bg() {
             if self.display_engine.clipboard.bg_clip.tiles.is_empty() {
                 log_write("Could not paste tiles, clipboard empty", LogLevel::Debug);
                 return;
             }
             log_write(format!("Pasting {} MapTiles",self.display_engine.clipboard.bg_clip.tiles.len()), LogLevel::Log);
-            let cursor_level_x = self.display_engine.latest_square_pos_level_space.x as i32;
-            let cursor_level_y = self.display_engine.latest_square_pos_level_space.y as i32;
-            //let mut tile_index: u32 = 0;
+            let origin = if in_place {
+                self.display_engine.clipboard.bg_clip.top_left
+            } else {
+                self.display_engine.clipboard.bg_clip.paste_origin(
+                    self.display_engine.tile_hover_pos, self.display_engine.display_settings.snap_bg_paste_to_even)
+            };
             let which_bg = self.display_engine.display_settings.current_layer as u8;
             let info_ro = self.display_engine.loaded_map.get_background(which_bg)
                 .expect("BG should exist").get_info().expect("Info guar.");
             let layer_width = info_ro.layer_width;
             let layer_height = info_ro.layer_height;
             for tile_data in &self.display_engine.clipboard.bg_clip.tiles {
-                let true_x = cursor_level_x + tile_data.x_offset;
-                if true_x >= layer_width as i32 {
-                    continue;
-                }
-                let true_y = cursor_level_y + tile_data.y_offset;
-                if true_y >= layer_height as i32 {
+                let true_x = origin.x as i32 + tile_data.x_offset;
+                let true_y = origin.y as i32 + tile_data.y_offset;
+                if true_x >= layer_width as i32 || true_y >= layer_height as i32 {
+                    clipped_count += 1;
                     continue;
                 }
                 let where_to_place_in_layer = xy_to_index(true_x as u32, true_y as u32, &(layer_width as u32));
-                if tile_data.tile.to_short() != 0x0000 { // Dont paste blank tiles
+                // Only skip missing tiles, tile 0 may be visible
+                if let Some(tile) = tile_data.tile {
                     self.display_engine.loaded_map.place_bg_tile_at_map_index(
-                        which_bg, where_to_place_in_layer, tile_data.tile.to_short());
+                        which_bg, where_to_place_in_layer, tile.to_short());
                 }
             }
             self.display_engine.graphics_update_needed = true;
-            self.display_engine.unsaved_changes = true;
+            self.display_engine.map_edited();
         } else {
             log_write("Paste not yet implemented for this layer", LogLevel::Warn);
+            return;
+        }
+        if clipped_count > 0 {
+            let clipped_msg = format!("{clipped_count} pasted items were outside the layer and were skipped");
+            log_write(&clipped_msg, LogLevel::Warn);
+            // Silent before, but it matters when pasting in place between maps
+            if in_place {
+                self.do_alert(clipped_msg);
+            }
+        }
+    }
+
+    /// The window each layer is edited with, like Collision Tiles for Collision
+    fn companion_window_open(&mut self, layer: CurrentLayer) -> &mut bool {
+        match layer {
+            CurrentLayer::BG1 | CurrentLayer::BG2 | CurrentLayer::BG3 => &mut self.brush_window_open,
+            CurrentLayer::Sprites => &mut self.sprites_window_open,
+            CurrentLayer::Collision => &mut self.collision_window_open,
+            CurrentLayer::Paths => &mut self.path_window_open,
+            CurrentLayer::Triggers => &mut self.area_window_open
+        }
+    }
+
+    /// Runs once after DisplayEngine::change_layer, wherever the layer was changed from.
+    /// Opens the new layer's companion window unless it was closed when the
+    /// layer was last left
+    fn on_layer_changed(&mut self) {
+        let Some(old_layer) = self.display_engine.left_layer.take() else {
+            return;
+        };
+        let layer = self.display_engine.display_settings.current_layer;
+        if layer == old_layer {
+            return;
+        }
+        self.companion_windows_open[old_layer as usize - 1] = *self.companion_window_open(old_layer);
+        if !self.display_engine.display_settings.auto_open_companion {
+            return;
+        }
+        let remembered_open = self.companion_windows_open[layer as usize - 1];
+        // The BG layers share the Brush window, so it can be closed for one and open for another
+        let shared_window = std::ptr::eq(self.companion_window_open(layer), self.companion_window_open(old_layer));
+        let window_open = self.companion_window_open(layer);
+        if remembered_open && !*window_open {
+            log_write(format!("Opening companion window for layer {:?}",layer), LogLevel::Debug);
+            *window_open = true;
+        } else if !remembered_open && shared_window {
+            *window_open = false;
+        }
+    }
+
+    /// Bounds come from the BG selection on the current layer, or the selected Sprites
+    pub fn do_open_clear_selection(&mut self) {
+        let is_bg = self.is_cur_layer_bg();
+        let de = &mut self.display_engine;
+        let mut selected: Option<(u8, Vec<u32>)> = Option::None;
+        let mut bounds: Option<TileBounds> = Option::None;
+        let which_bg = de.display_settings.current_layer as u8;
+        if is_bg && !de.bg_sel_data.selected_map_indexes.is_empty() {
+            if let Some(info) = de.loaded_map.get_background(which_bg).and_then(|bg| bg.get_info()) {
+                let indexes = de.bg_sel_data.selected_map_indexes.clone();
+                bounds = TileBounds::from_indexes(&indexes, info.layer_width as u32);
+                selected = Some((which_bg, indexes));
+            }
+        } else if de.display_settings.current_layer == CurrentLayer::Sprites && !de.selected_sprite_uuids.is_empty() {
+            let positions: Vec<(u32, u32)> = de.level_sprites.iter()
+                .filter(|spr| de.selected_sprite_uuids.contains(&spr.uuid))
+                .map(|spr| (spr.x_position as u32, spr.y_position as u32))
+                .collect();
+            bounds = TileBounds::from_positions(&positions);
+        }
+        if bounds.is_none() {
+            self.do_alert(String::from("Select BG tiles or Sprites to clear around first"));
+            return;
+        }
+        let settings = &mut self.clear_selection_settings;
+        settings.bounds = bounds;
+        settings.selected = selected;
+        settings.preview = None;
+        settings.window_open = true;
+    }
+
+    /// One undo step for every layer
+    fn do_clear_selection(&mut self) {
+        let settings = &mut self.clear_selection_settings;
+        settings.window_open = false;
+        settings.preview = None;
+        let Some(bounds) = settings.bounds else {
+            log_write("No bounds when clearing selection", LogLevel::Error);
+            return;
+        };
+        self.map_history.add_undo(&self.display_engine.loaded_map, self.input_time);
+        let report = self.display_engine.loaded_map.clear_selection(bounds, settings.selected_ref(), settings.layers);
+        if report.is_empty() {
+            return;
         }
+        // Selections may point at removed things
+        self.display_engine.selected_sprite_uuids.clear();
+        self.display_engine.bg_sel_data.clear();
+        self.display_engine.map_edited();
+        self.display_engine.graphics_update_needed = true;
+        self.map_history.add_undo(&self.display_engine.loaded_map, self.input_time);
     }
 
     fn do_clear_layer(&mut self) {
@@ -1043,7 +2764,7 @@ impl Gui {
                 colz.col_tiles.clear();
                 log_write("COLZ Layer cleared", LogLevel::Debug);
                 self.display_engine.graphics_update_needed = true;
-                self.display_engine.unsaved_changes = true;
+                self.display_engine.map_edited();
             }
             _ => {
                 let msg = format!("Clear Layer not yet supported for {:?}",self.display_engine.display_settings.current_layer);
@@ -1059,21 +2780,28 @@ impl Gui {
             log_write(format!("No BG {} loaded to clear",which_bg), LogLevel::Warn);
             return;
         };
+        let Some(info) = bg.get_info() else {
+            log_write(format!("No INFO on layer {} when clearing",which_bg), LogLevel::Error);
+            return;
+        };
+        let (layer_width, layer_height) = (info.layer_width, info.layer_height);
         let Some(map_tiles) = bg.get_mpbz_mut() else {
             log_write(format!("No map tiles on layer {} when clearing",which_bg), LogLevel::Error);
             return;
         };
-        // If you do .clear it will break the tile flow
-        for x in &mut map_tiles.tiles {
-            *x = MapTileRecordData::new(0x0000);
-        }
+        // Full size rather than empty, or it will break the tile flow
+        map_tiles.clear(layer_width, layer_height);
         log_write(format!("Cleared map tiles for bg {}",which_bg), LogLevel::Log);
-        self.display_engine.unsaved_changes = true;
+        self.display_engine.map_edited();
         self.display_engine.graphics_update_needed = true;
     }
 }
 
 impl eframe::App for Gui {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, PERSISTED_SETTINGS_KEY, &self.persisted_settings());
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         puffin::profile_function!();
 
@@ -1086,42 +2814,28 @@ impl eframe::App for Gui {
             }
         }
         ctx.send_viewport_cmd(egui::ViewportCommand::Title(window_title));
+        if self.quit_requested {
+            self.quit_requested = false;
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        }
         // X button on window pressed
         if ctx.input(|i| i.viewport().close_requested())  {
             if self.display_engine.unsaved_changes {
                 ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
-                self.exit_changes_open = true;
+                self.unsaved_changes_action = Some(PendingAction::Exit);
             } else {
                 self.exit(ctx);
             }
         }
-        // Keyboard input
-        self.handle_input(ctx);
+        // Keyboard input, edits made during an async load would be thrown away with the old map
+        if self.map_load.is_none() {
+            self.handle_input(ctx);
+        }
         *NON_MAIN_FOCUSED.lock().unwrap() = false; // Reset
+        self.on_layer_changed();
 
         // Tile storage //
-        if self.display_engine.needs_bg_tile_refresh {
-            log_write("Regenerating BG tile cache", LogLevel::Log);
-            self.display_engine.needs_bg_tile_refresh = false;
-            if self.display_engine.tile_preview_pal >= 16 {
-                // Should be completely impossible
-                log_write(format!("Tiles preview palette too high: '{}'",self.display_engine.tile_preview_pal), LogLevel::Fatal);
-                return;
-            }
-            let bg_pals: &Palette = &self.display_engine.bg_palettes[self.display_engine.tile_preview_pal];
-            // Layer 1
-            let tex_hands_1 = self.generate_bg_cache(ctx, 1, bg_pals);
-            self.bg1_tile_preview_cache.clear();
-            self.bg1_tile_preview_cache = tex_hands_1;
-            // Layer 2
-            let tex_hands_2 = self.generate_bg_cache(ctx, 2, bg_pals);
-            self.bg2_tile_preview_cache.clear();
-            self.bg2_tile_preview_cache = tex_hands_2;
-            // Layer 3
-            let tex_hands_3 = self.generate_bg_cache(ctx, 3, bg_pals);
-            self.bg3_tile_preview_cache.clear();
-            self.bg3_tile_preview_cache = tex_hands_3;
-        }
+        self.refresh_bg_preview(ctx);
         if self.display_engine.graphics_update_needed {
             self.display_engine.update_graphics_from_mapdata();
             self.display_engine.graphics_update_needed = false;
@@ -1134,11 +2848,11 @@ impl eframe::App for Gui {
                 ui.set_min_size(Vec2::new(260.0, 235.0));
                 palette_window_show(ui,&self.display_engine);  
             });
-        egui::Window::new("BG Tiles")
-            .open(&mut self.tile_preview_window_open)
+        let tiles_window = egui::Window::new("BG Tiles")
             .resizable(false)
-            .vscroll(false)
-            .show(ctx, |ui: &mut egui::Ui| {
+            .vscroll(false);
+        show_detachable(ctx, tiles_window, "BG Tiles", Vec2::new(340.0, 620.0),
+            &mut self.tile_preview_window_open, &mut self.tile_preview_detached, |ui: &mut egui::Ui| {
                 puffin::profile_scope!("BG Tiles");
                 ui.set_min_size(Vec2::new(300.0,500.0));
                 if !self.display_engine.display_settings.is_cur_layer_bg() {
@@ -1146,22 +2860,42 @@ impl eframe::App for Gui {
                     return;
                 }
                 let cur_palette = self.display_engine.tile_preview_pal;
+                let base = self.display_engine.display_settings.number_base;
                 egui::ComboBox::from_label("Palette")
-                    .selected_text(format!("{:X}",self.display_engine.tile_preview_pal))
+                    .selected_text(base.fmt_num(self.display_engine.tile_preview_pal as u32, 1))
                     .show_ui(ui, |ui| {
                         for x in 0..16 {
-                            ui.selectable_value(&mut self.display_engine.tile_preview_pal, x, format!("0x{:X}",x));
+                            ui.selectable_value(&mut self.display_engine.tile_preview_pal, x, base.fmt_num(x as u32, 1));
                         }
                     });
                 if cur_palette != self.display_engine.tile_preview_pal {
                     self.display_engine.needs_bg_tile_refresh = true;
                 }
                 ui.horizontal(|ui| {
-                    ui.checkbox(&mut self.display_engine.brush_settings.flip_x_place, "Flip H");
-                    ui.checkbox(&mut self.display_engine.brush_settings.flip_y_place, "Flip V");
+                    ui.checkbox(&mut self.display_engine.brush_settings.flip_x_place, "Flip H")
+                        .on_hover_text("For tiles added to the Brush. Hold X while stamping to mirror the whole Brush");
+                    ui.checkbox(&mut self.display_engine.brush_settings.flip_y_place, "Flip V")
+                        .on_hover_text("For tiles added to the Brush. Hold Y while stamping to mirror the whole Brush");
+                    ui.checkbox(&mut self.tiles_used_only, "Used only")
+                        .on_hover_text("Hide tiles that are not placed on this layer");
                 });
+                let stale = match self.display_engine.display_settings.current_layer {
+                    CurrentLayer::BG1 => self.bg_preview_stale[0],
+                    CurrentLayer::BG2 => self.bg_preview_stale[1],
+                    _ => self.bg_preview_stale[2]
+                };
+                if stale || self.display_engine.needs_bg_tile_refresh {
+                    // Built next frame, so the spinner gets drawn first
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Generating tiles...");
+                    });
+                    self.bg_preview_waiting = true;
+                    ui.ctx().request_repaint();
+                    return;
+                }
                 if let Some(sel_tile) = self.display_engine.selected_preview_tile {
-                    ui.label(format!("Current Tile Index: 0x{:03X}",sel_tile));
+                    ui.label(format!("Current Tile Index: {}",base.fmt_num(sel_tile as u32, 3)));
                 } else {
                     ui.label("Current Tile Index: N/A");
                 }
@@ -1174,13 +2908,13 @@ impl eframe::App for Gui {
                         // TODO: In the future, add custom UI spacing inside tiles_window_show to make that uneeded
                         match self.display_engine.display_settings.current_layer {
                             CurrentLayer::BG1 => {
-                                tiles_window_show(ui, &self.bg1_tile_preview_cache,&mut self.display_engine);
+                                tiles_window_show(ui, &self.bg1_tile_preview_cache, &self.bg_tile_usage[0], self.tiles_used_only, &mut self.display_engine);
                             }
                             CurrentLayer::BG2 => {
-                                tiles_window_show(ui, &self.bg2_tile_preview_cache,&mut self.display_engine);
+                                tiles_window_show(ui, &self.bg2_tile_preview_cache, &self.bg_tile_usage[1], self.tiles_used_only, &mut self.display_engine);
                             }
                             CurrentLayer::BG3 => {
-                                tiles_window_show(ui, &self.bg3_tile_preview_cache,&mut self.display_engine);
+                                tiles_window_show(ui, &self.bg3_tile_preview_cache, &self.bg_tile_usage[2], self.tiles_used_only, &mut self.display_engine);
                             }
                             _ => {
                                 /* Do nothing */
@@ -1205,12 +2939,15 @@ impl eframe::App for Gui {
             .show(ctx,|ui| {
                 collision_tiles_window(ui, &mut self.display_engine);
             });
-        egui::Window::new("Stork Settings")
+        let moved_project = egui::Window::new("Stork Settings")
             .open(&mut self.settings_open)
             .resizable(false)
             .show(ctx,|ui| {
-                stork_settings_window(ui, &mut self.display_engine);
-            });
+                stork_settings_window(ui, &mut self.display_engine, &mut self.map_history, &mut self.project_folder_edit)
+            }).and_then(|res| res.inner).flatten();
+        if let Some(new_folder) = moved_project {
+            self.confirm_unsaved_changes(PendingAction::MoveProject(new_folder));
+        }
         egui::Window::new("BG Brush")
             .open(&mut self.brush_window_open)
             .resizable(false)
@@ -1219,22 +2956,25 @@ impl eframe::App for Gui {
             .show(ctx, |ui| {
                 show_brushes_window(ui, &mut self.display_engine);
             });
-        egui::Window::new("Saved Brushes")
-            .open(&mut self.stamps_window_open)
+        let stamps_window = egui::Window::new("Saved Brushes")
             .resizable(false)
             .drag_to_scroll(false)
             .min_height(300.0)
-            .max_height(500.0)
-            .show(ctx, |ui| {
+            .max_height(500.0);
+        show_detachable(ctx, stamps_window, "Saved Brushes", Vec2::new(360.0, 500.0),
+            &mut self.stamps_window_open, &mut self.stamps_detached, |ui| {
                 show_saved_brushes_window(ui, &mut self.display_engine);
             });
-        egui::Window::new("Course Settings")
-            .open(&mut self.course_window_open)
+        let course_window = egui::Window::new("Course Settings")
             .min_width(300.0)
-            .drag_to_scroll(false)
-            .show(ctx, |ui| {
-                show_course_settings_window(ui, &mut self.display_engine, self.project_open);
+            .drag_to_scroll(false);
+        let course_jump = show_detachable(ctx, course_window, "Course Settings", Vec2::new(420.0, 600.0),
+            &mut self.course_window_open, &mut self.course_detached, |ui| {
+                show_course_settings_window(ui, &mut self.display_engine, self.project_open)
             });
+        if let Some(overlap) = course_jump.flatten() {
+            self.jump_to_warp_overlap(&overlap);
+        }
         egui::Window::new("Triggers")
             .open(&mut self.area_window_open)
             .min_width(300.0)
@@ -1249,21 +2989,132 @@ impl eframe::App for Gui {
             .show(ctx, |ui| {
                 show_paths_window(ui, &mut self.display_engine);
             });
-        egui::Window::new("Map Segments")
+        let map_revert = egui::Window::new("Map Segments")
             .open(&mut self.mpdz_window_open)
             .min_width(300.0)
             .drag_to_scroll(false)
             .show(ctx, |ui| {
-                show_map_segments_window(ui, &mut self.display_engine);
-            });
+                show_map_segments_window(ui, &mut self.display_engine)
+            })
+            .and_then(|res| res.inner)
+            .flatten();
         let current_layer = self.display_engine.display_settings.current_layer;
-        egui::Window::new("BG Segments")
+        let scen_revert = egui::Window::new("BG Segments")
             .open(&mut self.scen_window_open)
             .min_width(300.0)
             .drag_to_scroll(false)
             .show(ctx, |ui| {
-                show_scen_segments_window(ui, &mut self.display_engine,&current_layer);
+                show_scen_segments_window(ui, &mut self.display_engine,&current_layer)
+            })
+            .and_then(|res| res.inner)
+            .flatten();
+        if let Some(revert) = map_revert.or(scen_revert) {
+            self.do_segment_revert(revert);
+        }
+        let mut viewer_open = self.viewed_file.is_some();
+        if let Some(viewed) = &self.viewed_file {
+            egui::Window::new("File Viewer (Read-Only)")
+                .open(&mut viewer_open)
+                .default_width(400.0)
+                .default_height(400.0)
+                .show(ctx, |ui| {
+                    show_file_viewer_window(ui, viewed, self.display_engine.display_settings.number_base);
+                });
+        }
+        if !viewer_open {
+            self.viewed_file = None;
+        }
+        let history_jump = egui::Window::new("History")
+            .open(&mut self.history_window_open)
+            .min_width(250.0)
+            .default_height(300.0)
+            .drag_to_scroll(false)
+            .show(ctx, |ui| {
+                show_history_window(ui, &self.map_history, &self.display_engine.loaded_map)
+            })
+            .and_then(|res| res.inner)
+            .flatten();
+        if let Some(index) = history_jump {
+            self.do_history_jump(index);
+        }
+        egui::Window::new("Clipboard")
+            .open(&mut self.clipboard_window_open)
+            .min_width(200.0)
+            .default_height(300.0)
+            .drag_to_scroll(false)
+            .show(ctx, |ui| {
+                show_clipboard_window(ui, &mut self.display_engine);
             });
+        let map_name = self.display_engine.loaded_map.map_name.clone();
+        let bookmark_action = egui::Window::new("Bookmarks")
+            .open(&mut self.bookmarks_window_open)
+            .min_width(250.0)
+            .drag_to_scroll(false)
+            .show(ctx, |ui| {
+                if !self.project_open {
+                    ui.label("No project open");
+                    return None;
+                }
+                show_bookmarks_window(ui, &mut self.project_settings, &map_name)
+            })
+            .and_then(|res| res.inner)
+            .flatten();
+        match bookmark_action {
+            Some(BookmarkAction::Jump(slot)) => self.jump_to_bookmark(slot),
+            Some(BookmarkAction::Changed) => self.save_project_settings(),
+            None => {}
+        }
+        let sprite_issue = egui::Window::new("Sprite Check")
+            .open(&mut self.sprite_check_window_open)
+            .min_width(250.0)
+            .default_height(200.0)
+            .drag_to_scroll(false)
+            .show(ctx, |ui| {
+                show_sprite_check_window(ui, &mut self.display_engine)
+            })
+            .and_then(|res| res.inner)
+            .flatten();
+        if let Some(issue) = sprite_issue {
+            log_write(format!("Selecting {} Sprites from Sprite Check",issue.uuids.len()), LogLevel::Debug);
+            self.display_engine.change_layer(CurrentLayer::Sprites);
+            self.display_engine.selected_sprite_uuids = issue.uuids.clone();
+            self.scroll_to = Some(Pos2::new(issue.x_position as f32 * 8.0, issue.y_position as f32 * 8.0));
+            self.display_engine.graphics_update_needed = true;
+        }
+        let project_open = self.project_open;
+        let reverted = egui::Window::new("Modified Files")
+            .open(&mut self.modified_files_window_open)
+            .min_width(400.0)
+            .drag_to_scroll(false)
+            .show(ctx, |ui| {
+                if !project_open {
+                    ui.label("No project open");
+                    return None;
+                }
+                show_modified_files_window(ui, &self.export_directory, &mut self.modified_files)
+            })
+            .and_then(|res| res.inner)
+            .flatten();
+        if let Some(rel_path) = reverted {
+            self.reload_if_reverted(&rel_path);
+        }
+        let script_running = self.script_receiver.is_some();
+        let run_script = egui::Window::new("Run Script")
+            .open(&mut self.script_window_open)
+            .min_width(400.0)
+            .drag_to_scroll(false)
+            .show(ctx, |ui| {
+                if !project_open {
+                    ui.label("No project open");
+                    return false;
+                }
+                show_script_window(ui, &mut self.script_state, script_running)
+            })
+            .and_then(|res| res.inner)
+            .unwrap_or(false);
+        if run_script {
+            self.do_run_script();
+        }
         // Panels //
         egui::TopBottomPanel::top("top_panel")
             .resizable(false)
@@ -1287,22 +3138,68 @@ impl eframe::App for Gui {
                     sprite_panel_show(ui, self);
                 });
         }
-        egui::CentralPanel::default()
+        self.display_engine.course_settings.window_open = self.course_window_open;
+        let grid_menu_action = egui::CentralPanel::default()
             .show(ctx, |ui| {
-                ScrollArea::both()
+                let show_rulers = self.project_open && self.display_engine.display_settings.show_rulers;
+                let panel_rect = ui.available_rect_before_wrap();
+                let mut grid_rect = panel_rect;
+                if show_rulers {
+                    grid_rect.min += Vec2::splat(RULER_THICKNESS);
+                }
+                let now = ui.input(|i| i.time);
+                // The first frame has no viewport to center in yet
+                if let Some(scroll_to) = self.scroll_to.take_if(|_| self.grid_viewport != Rect::NOTHING) {
+                    let centered = scroll_to.to_vec2() + Vec2::splat(5.0) - self.grid_viewport.size() / 2.0;
+                    self.animate_grid_scroll(centered.max(Vec2::ZERO), now);
+                }
+                if self.grid_scroll.is_some() {
+                    let wheeled = ui.rect_contains_pointer(grid_rect) && ui.input(|i| i.raw_scroll_delta != Vec2::ZERO);
+                    // Scrollbar drags and hitting the edge both move it off course
+                    let moved = self.grid_scroll_set.is_some_and(|set| (set - self.grid_scroll_offset).length() > 1.0);
+                    if wheeled || moved {
+                        log_write("Scrolled over a grid scroll, stopping it", LogLevel::Debug);
+                        self.grid_scroll = None;
+                    }
+                }
+                let mut scroll_area = ScrollArea::both()
                     .auto_shrink([false,false])
-                    .drag_to_scroll(false)
-                    .show_viewport(ui, |ui,viewport_rect| {
-                        if let Some(scroll_to) = self.scroll_to {
-                            let real_pos = ui.min_rect().left_top() + scroll_to.to_vec2();
-                            ui.scroll_to_rect(Rect::from_min_size(real_pos, Vec2::new(10.0, 10.0)), Some(Align::Center));
-                            self.scroll_to = Option::None;
-                        }
+                    .drag_to_scroll(false);
+                self.grid_scroll_set = None;
+                if let Some(scroll) = self.grid_scroll {
+                    let (offset, landed) = scroll.offset_at(now);
+                    scroll_area = scroll_area.scroll_offset(offset);
+                    self.grid_scroll_set = Some(offset);
+                    if landed {
+                        self.grid_scroll = None;
+                    } else {
+                        ui.ctx().request_repaint();
+                    }
+                }
+                let grid_menu_action = ui.scope_builder(egui::UiBuilder::new().max_rect(grid_rect), |ui| {
+                    let output = scroll_area.show_viewport(ui, |ui,viewport_rect| {
+                        self.grid_viewport = viewport_rect;
                         if self.project_open {
-                            render_primary_grid(ui, &mut self.display_engine, &viewport_rect);
+                            render_primary_grid(ui, &mut self.display_engine, &viewport_rect)
+                        } else {
+                            None
                         }
                     });
-            });
+                    self.grid_scroll_offset = output.state.offset;
+                    self.grid_max_offset = (output.content_size - output.inner_rect.size()).max(Vec2::ZERO);
+                    output.inner
+                }).inner;
+                if show_rulers {
+                    draw_rulers(ui, panel_rect, grid_rect.min, &self.grid_viewport, self.display_engine.display_settings.number_base);
+                }
+                grid_menu_action
+            }).inner;
+        match grid_menu_action {
+            Some(GridMenuAction::Copy) => self.do_copy(),
+            Some(GridMenuAction::Cut) => self.do_cut(),
+            Some(GridMenuAction::Paste) => self.do_paste(),
+            None => {}
+        }
         // Modals //
         if self.resize_settings.window_open {
             let _resize_modal = Modal::new(Id::new("resize_modal"))
@@ -1310,6 +3207,24 @@ impl eframe::App for Gui {
                     show_resize_modal(ui, &mut self.display_engine, &mut self.resize_settings);
                 });
         }
+        if self.shift_map_settings.window_open {
+            let shift_map_modal = Modal::new(Id::new("shift_map_modal"))
+                .show(ctx, |ui| {
+                    show_shift_map_modal(ui, &self.display_engine, &mut self.shift_map_settings)
+                });
+            if shift_map_modal.inner {
+                self.do_shift_map();
+            }
+        }
+        if self.clear_selection_settings.window_open {
+            let clear_selection_modal = Modal::new(Id::new("clear_selection_modal"))
+                .show(ctx, |ui| {
+                    show_clear_selection_modal(ui, &self.display_engine, &mut self.clear_selection_settings)
+                });
+            if clear_selection_modal.inner {
+                self.do_clear_selection();
+            }
+        }
         self.general_alert_popup.take_if(|alert| {
             let alert_modal = Modal::new(Id::new("alert_modal"))
                 .show(ctx, |ui| {
@@ -1320,49 +3235,30 @@ impl eframe::App for Gui {
                 });
             alert_modal.inner
         });
-        if self.exit_changes_open {
-            let _save_modal = Modal::new(Id::new("close_changes_modal"))
-                .show(ctx, |ui| {
-                    ui.set_width(200.0);
-                    ui.heading("Save Changes?");
-                    ui.label("You have unsaved changes, do you want to save before you exit?");
-                    ui.horizontal(|ui| {
-                        if ui.button("Cancel").clicked() {
-                            self.exit_changes_open = false;
-                        }
-                        if ui.button("Discard").clicked() {
-                            self.exit_changes_open = false;
-                            self.display_engine.unsaved_changes = false; // So it can actually close
-                            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
-                        }
-                        if ui.button("Save").clicked() {
-                            self.quit_when_saving_done = true;
-                            self.saving_progress = Some(0.0);
-                        }
-                    });
-                });
-        }
-        if self.export_changes_open {
-            let _export_change_modal = Modal::new(Id::new("export_changes_modal"))
+        if let Some(action) = &self.unsaved_changes_action {
+            let prompt = action.prompt();
+            let choice_modal = Modal::new(Id::new("unsaved_changes_modal"))
                 .show(ctx, |ui| {
-                    ui.set_width(200.0);
+                    ui.set_width(220.0);
                     ui.heading("Save Changes?");
-                    ui.label("You have unsaved changes, do you want to save before export?");
+                    ui.label(format!("You have unsaved changes, do you want to save before {prompt}?"));
+                    let mut choice: Option<UnsavedChoice> = Option::None;
                     ui.horizontal(|ui| {
                         if ui.button("Cancel").clicked() {
-                            self.export_changes_open = false;
+                            choice = Some(UnsavedChoice::Cancel);
                         }
-                        if ui.button("Continue").clicked() {
-                            self.exporting_progress = Some(0.0);
-                            self.export_changes_open = false;
+                        if ui.button("Discard").on_hover_text("Reload the last save, then continue").clicked() {
+                            choice = Some(UnsavedChoice::Discard);
                         }
                         if ui.button("Save and Continue").clicked() {
-                            self.export_when_saving_done = true;
-                            self.saving_progress = Some(0.0);
-                            self.export_changes_open = false;
+                            choice = Some(UnsavedChoice::Save);
                         }
                     });
+                    choice
                 });
+            if let Some(choice) = choice_modal.inner {
+                self.resolve_unsaved_changes(choice);
+            }
         }
         if let Some(exporting_progress) = self.exporting_progress {
             egui::Modal::new(Id::new("exporting_modal")).show(ctx, |ui| {
@@ -1381,7 +3277,73 @@ impl eframe::App for Gui {
                 }
             });
         }
-        if let Some(saving_progress) = self.saving_progress {
+        self.poll_report_export(ctx);
+        self.poll_verify_project(ctx);
+        self.poll_project_script(ctx);
+        self.poll_map_load(ctx);
+        if !self.tile_length_issues.is_empty() {
+            egui::Modal::new(Id::new("tile_length_modal")).show(ctx, |ui| {
+                ui.set_max_width(400.0);
+                ui.heading("Map Tile Count Mismatch");
+                ui.label("These layers don't have a tile for every spot in their width and height, which breaks drawing, selection and paste:");
+                for issue in &self.tile_length_issues {
+                    ui.colored_label(egui::Color32::YELLOW, issue.to_string());
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Repair").clicked() {
+                        self.resolve_tile_lengths(true);
+                    }
+                    let skip_text = if self.tile_length_before_save { "Save as is" } else { "Leave as is" };
+                    if ui.button(skip_text).clicked() {
+                        self.resolve_tile_lengths(false);
+                    }
+                    if self.tile_length_before_save && ui.button("Cancel").clicked() {
+                        self.tile_length_issues.clear();
+                        self.tile_length_before_save = false;
+                        self.after_save_action = Option::None;
+                    }
+                });
+            });
+        }
+        if !self.error_tile_issues.is_empty() {
+            egui::Modal::new(Id::new("error_tiles_modal")).show(ctx, |ui| {
+                ui.set_max_width(400.0);
+                ui.heading("Error Tiles");
+                ui.label("These layers use tiles their tileset doesn't have, often from missing ANMZ or IMBZ data. They will look broken in-game:");
+                for issue in &self.error_tile_issues {
+                    ui.colored_label(egui::Color32::YELLOW, issue.to_string());
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Save anyway").clicked() {
+                        self.error_tile_issues.clear();
+                        self.saving_progress = Some(0.0);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.error_tile_issues.clear();
+                        self.after_save_action = Option::None;
+                    }
+                });
+            });
+        }
+        if let Some(save_error) = self.save_error.clone() {
+            egui::Modal::new(Id::new("saving_modal")).show(ctx, |ui| {
+                ui.set_max_width(400.0);
+                ui.heading("Save Failed");
+                ui.colored_label(egui::Color32::RED, save_error);
+                ui.label("Check that the file isn't read-only or open in another program");
+                ui.horizontal(|ui| {
+                    if ui.button("Retry").clicked() {
+                        self.save_error = Option::None;
+                        self.do_save();
+                    }
+                    if ui.button("Cancel").clicked() {
+                        // Whatever was waiting on the save doesn't happen
+                        self.save_error = Option::None;
+                        self.after_save_action = Option::None;
+                    }
+                });
+            });
+        } else if let Some(saving_progress) = self.saving_progress {
             egui::Modal::new(Id::new("saving_modal")).show(ctx, |ui| {
                 ui.set_width(70.0);
                 ui.heading("Saving...");
@@ -1390,83 +3352,96 @@ impl eframe::App for Gui {
                     ctx.request_repaint();
                 }
                 if saving_progress == 0.4 {
-                    self.save_map();
-                    self.save_course();
+                    if let Err(error) = self.save_all() {
+                        log_write(error.to_string(), LogLevel::Error);
+                        self.save_error = Some(error.to_string());
+                        self.saving_progress = Option::None;
+                        return;
+                    }
                 }
                 if saving_progress >= 1.0 {
                     self.saving_progress = Option::None;
-                    self.display_engine.unsaved_changes = false;
-                    if self.quit_when_saving_done {
-                        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
-                    }
-                    if self.export_when_saving_done {
-                        self.export_when_saving_done = false;
-                        self.do_export();
+                    if let Some(action) = self.after_save_action.take() {
+                        self.run_pending_action(action);
                     }
                 } else {
                     self.saving_progress = Some(saving_progress + 0.2);
                 }
             });
         }
-        if self.change_course_unsaved_changes_show {
-            let _export_change_modal = Modal::new(Id::new("course_changes_modal"))
-            .show(ctx, |ui| {
-                ui.set_width(200.0);
-                ui.heading("Save Changes?");
-                ui.label("You have unsaved changes, do you want to save before changing Course?");
-                ui.horizontal(|ui| {
-                    if ui.button("Cancel").clicked() {
-                        self.change_course_unsaved_changes_show = false;
-                    }
-                    if ui.button("Continue").clicked() {
-                        self.change_course_unsaved_changes_show = false;
-                        self.change_course_open = true;
-                    }
-                    if ui.button("Save and Continue").clicked() {
-                        self.change_course_unsaved_changes_show = false;
-                        self.change_course_open = true;
-                        self.do_save();
-                    }
+        if self.level_switcher.window_open {
+            let switch_modal = Modal::new(Id::new("level_switcher_modal"))
+                .show(ctx, |ui| {
+                    show_level_switcher_modal(ui, &mut self.level_switcher)
                 });
-            });   
+            if let Some(target) = switch_modal.inner {
+                self.do_switch(target);
+            }
         }
-        if self.change_map_unsaved_changes_show {
-            let _export_change_modal = Modal::new(Id::new("map_changes_modal"))
-            .show(ctx, |ui| {
-                ui.set_width(200.0);
-                ui.heading("Save Changes?");
-                ui.label("You have unsaved changes, do you want to save before changing map?");
-                ui.horizontal(|ui| {
-                    if ui.button("Cancel").clicked() {
-                        self.change_map_unsaved_changes_show = false;
-                    }
-                    if ui.button("Continue").clicked() {
-                        self.change_map_unsaved_changes_show = false;
-                        self.change_map_open = true;
-                    }
-                    if ui.button("Save and Continue").clicked() {
-                        self.change_map_unsaved_changes_show = false;
-                        self.change_map_open = true;
-                        self.do_save();
-                    }
-                });
-            });  
+        // Previews can go stale once the modals close, so read them fresh each time
+        if !self.change_map_open {
+            self.map_size_cache.clear();
+            self.map_exists_cache.clear();
+        }
+        if !self.change_course_open {
+            self.course_preview_cache.clear();
         }
         if self.change_map_open {
             egui::Modal::new(Id::new("map_change_modal")).show(ctx, |ui| {
                 ui.heading("Select map");
-                ui.set_width(150.0);
-
+                ui.set_width(300.0);
+                let base = self.display_engine.display_settings.number_base;
                 let crsb = self.display_engine.loaded_course.level_map_data.clone();
+                let mut remove_index: Option<usize> = Option::None;
+                let mut repoint_index: Option<usize> = Option::None;
                 egui::ScrollArea::vertical().show(ui, |ui| {
                     for (map_index, map) in crsb.iter().enumerate() {
+                        let exists = *self.map_exists_cache.entry(map.map_filename_noext.clone())
+                            .or_insert_with(|| self.display_engine.map_file_exists(&map.map_filename_noext));
+                        if !exists {
+                            let is_loaded = self.display_engine.map_index == Some(map_index);
+                            ui.add_enabled(false, egui::Button::new(&map.map_filename_noext));
+                            ui.horizontal(|ui| {
+                                ui.colored_label(egui::Color32::ORANGE, "File missing");
+                                // The loaded one comes back on save
+                                if ui.add_enabled(!is_loaded, egui::Button::new("Remove"))
+                                    .on_hover_text("Removes this entry from the Course, exits to it are fixed up")
+                                    .on_disabled_hover_text("This map is loaded, saving will write its file again")
+                                    .clicked() {
+                                    remove_index = Some(map_index);
+                                }
+                                if ui.button("Point to file...").on_hover_text("Use another .mpdz in the project for this entry").clicked() {
+                                    repoint_index = Some(map_index);
+                                }
+                            });
+                            continue;
+                        }
+                        let map_size = *self.map_size_cache.entry(map.map_filename_noext.clone())
+                            .or_insert_with(|| {
+                                match self.display_engine.peek_map_dimensions(&map.map_filename_noext) {
+                                    Ok(dims) => Some(dims),
+                                    Err(e) => {
+                                        log_write(format!("Failed to read size of map '{}': {}",&map.map_filename_noext,e), LogLevel::Warn);
+                                        None
+                                    }
+                                }
+                            });
+                        let size_text = match map_size {
+                            Some((width,height)) => format!("{}x{}",base.fmt_num(width, 1),base.fmt_num(height, 1)),
+                            None => String::from("Unknown size"),
+                        };
                         let mut but = ui.button(&map.map_filename_noext);
                         if map.map_filename_noext == self.display_engine.loaded_map.map_name {
                             but = but.highlight();
                         }
+                        ui.label(egui::RichText::new(format!("{} | Music {} | {} entrances, {} exits",
+                            size_text,base.fmt_num(map.map_music, 2),map.map_entrances.len(),map.map_exits.len())).weak());
                         if but.clicked() {
                             // Since the targeting is done via GUI, but accesses the saved data
-                            self.save_course();
+                            if !self.save_course_if_changed() {
+                                self.change_map_open = false;
+                                return;
+                            }
                             // This is to be used once support for ALL map selection is working
                             self.map_change_selected_map = map.map_filename_noext.clone();
                             self.change_map(map_index as u32);
@@ -1474,6 +3449,12 @@ impl eframe::App for Gui {
                         }
                     }
                 });
+                if let Some(map_index) = remove_index {
+                    self.remove_missing_map(map_index);
+                }
+                if let Some(map_index) = repoint_index {
+                    self.do_repoint_map(map_index);
+                }
                 
                 // let _map_change_selected_map = egui::ComboBox::from_label("")
                 //     .selected_text(format!("{}",&self.map_change_selected_map))
@@ -1513,7 +3494,8 @@ impl eframe::App for Gui {
         if self.change_course_open {
             egui::Modal::new(Id::new("course_change_modal")).show(ctx, |ui| {
                 ui.heading("Select a Course");
-                ui.set_width(150.0);
+                ui.set_width(250.0);
+                let world_level = (self.change_level_world_index, self.change_level_level_index);
                 // World Selection //
                 let _combo_world = egui::ComboBox::new(
                     egui::Id::new("change_level_world"), "World")
@@ -1531,13 +3513,103 @@ impl eframe::App for Gui {
                             ui.selectable_value(&mut self.change_level_level_index, y, (y+1).to_string());
                         }
                     });
+                if world_level != (self.change_level_world_index, self.change_level_level_index) {
+                    self.change_course_special = None;
+                }
+                // Special Courses //
+                egui::CollapsingHeader::new("Special Courses").id_salt("change_course_special")
+                    .default_open(self.change_course_special.is_some())
+                    .show(ui, |ui| {
+                        for special in SPECIAL_COURSE_FILENAMES {
+                            let label = format!("{}: {}",special_course_label(special).unwrap_or("Special"),special);
+                            let selected = self.change_course_special.as_deref() == Some(special);
+                            if ui.selectable_label(selected, label).clicked() {
+                                self.change_course_special = Some(special.to_string());
+                            }
+                        }
+                    });
+                // Preview //
+                let course_name = match &self.change_course_special {
+                    Some(special) => special.clone(),
+                    None => self.display_engine.get_level_filename(&self.change_level_world_index, &self.change_level_level_index)
+                };
+                let is_loaded = course_name == self.display_engine.loaded_course_name();
+                let preview = self.course_preview_cache.entry(course_name.clone())
+                    .or_insert_with(|| {
+                        match self.display_engine.peek_course(&course_name) {
+                            Ok(course) => Some(course),
+                            Err(e) => {
+                                log_write(format!("Failed to preview Course '{}': {}",&course_name,e), LogLevel::Warn);
+                                None
+                            }
+                        }
+                    });
+                ui.label(format!("File: {}.crsb",&course_name));
+                if let Some(course) = preview {
+                    ui.label(format!("Maps: {}",course.level_map_data.len()));
+                    for map in &course.level_map_data {
+                        let is_loaded_map = is_loaded && map.map_filename_noext == self.display_engine.loaded_map.map_name;
+                        if is_loaded_map {
+                            ui.label(egui::RichText::new(format!("{} (loaded)",&map.map_filename_noext)).strong());
+                        } else {
+                            ui.label(&map.map_filename_noext);
+                        }
+                    }
+                } else {
+                    ui.label("Maps: Unknown");
+                }
                 ui.horizontal(|ui| {
                     if ui.button("Cancel").clicked() {
                         self.change_course_open = false;
                     }
-                    if ui.button("Okay").clicked() {
+                    // Only peeks when hovered, not every frame
+                    let okay_button = ui.button("Okay").on_hover_ui(|ui| {
+                        ui.label(format!("Opens map {}",self.display_engine.map_index_for_course(&course_name) + 1));
+                    });
+                    if okay_button.clicked() {
                         self.change_course_open = false;
-                        self.change_level(self.change_level_world_index, self.change_level_level_index);
+                        match self.change_course_special.clone() {
+                            Some(special) => self.change_special_course(&special),
+                            None => self.change_level(self.change_level_world_index, self.change_level_level_index)
+                        }
+                    }
+                });
+            });
+        }
+        if self.copy_map_open {
+            egui::Modal::new(Id::new("copy_map_modal")).show(ctx, |ui| {
+                ui.heading("Copy Map to Course");
+                ui.set_width(250.0);
+                egui::ComboBox::new(egui::Id::new("copy_map_world"), "World")
+                    .selected_text(format!("{}",self.copy_map_dest.0+1))
+                    .show_ui(ui, |ui| {
+                        for x in 0..5_u32 {
+                            ui.selectable_value(&mut self.copy_map_dest.0, x, (x+1).to_string());
+                        }
+                    });
+                egui::ComboBox::new(egui::Id::new("copy_map_level"), "Level")
+                    .selected_text(format!("{}",self.copy_map_dest.1+1))
+                    .show_ui(ui, |ui| {
+                        for y in 0..10_u32 {
+                            ui.selectable_value(&mut self.copy_map_dest.1, y, (y+1).to_string());
+                        }
+                    });
+                let course_name = self.display_engine.get_level_filename(&self.copy_map_dest.0, &self.copy_map_dest.1);
+                let is_loaded = course_name == self.display_engine.loaded_course_name();
+                ui.label(format!("File: {}.crsb",&course_name));
+                ui.label(format!("Copies {}.mpdz as a new map at the end of that Course",self.display_engine.loaded_map.map_name));
+                if self.display_engine.unsaved_changes {
+                    ui.colored_label(egui::Color32::ORANGE, "Unsaved changes are not copied, save first to include them");
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Cancel").clicked() {
+                        self.copy_map_open = false;
+                    }
+                    let copy_button = ui.add_enabled(!is_loaded, egui::Button::new("Copy"))
+                        .on_disabled_hover_text("This is the open Course, use New in Course Settings instead");
+                    if copy_button.clicked() {
+                        self.copy_map_open = false;
+                        self.do_copy_map_to_course();
                     }
                 });
             });
@@ -1549,6 +3621,29 @@ impl eframe::App for Gui {
                 ui.label("A ROM-hacking tool for Yoshi's Island DS");
                 ui.label("Created by YoshiDonoshi/Zolarch");
                 ui.add(Hyperlink::from_label_and_url("Source Code", env!("GITHUB_REPO")));
+                if self.project_open {
+                    ui.separator();
+                    let stamp = &self.display_engine.rom_stamp;
+                    egui::Grid::new("about_rom_grid").num_columns(2).show(ui, |ui| {
+                        ui.label("Game version");
+                        ui.label(get_gameversion_prettyname(&self.display_engine.game_version));
+                        ui.end_row();
+                        ui.label("Game code");
+                        ui.label(stamp.game_code.as_deref().unwrap_or("Unknown"));
+                        ui.end_row();
+                        ui.label("Maker code");
+                        ui.label(match stamp.maker_code.as_deref() {
+                            Some("01") => String::from("01 (unmodified)"),
+                            Some("63") => String::from("63 (edited with Stork)"),
+                            Some(code) => code.to_string(),
+                            None => String::from("Unknown")
+                        });
+                        ui.end_row();
+                        ui.label("Build date");
+                        ui.label(&stamp.build_date).on_hover_text("From stamp.rc, decides the revision");
+                        ui.end_row();
+                    });
+                }
                 ui.vertical_centered(|ui| {
                     let about_close_button = ui.button("Close");
                     if about_close_button.clicked() {
@@ -1569,7 +3664,14 @@ impl eframe::App for Gui {
                 ui.label("If those links has stopped working, find the thread here:");
                 ui.hyperlink(env!("SMWC_FORUM"));
                 ui.label("Thanks for helping to improve this tool!");
+                ui.separator();
+                ui.label("A report bundle zips stork.log with the loaded level and version info. Nothing is uploaded");
+                ui.add_enabled(self.project_open, egui::Checkbox::new(&mut self.bug_bundle_include_map, "Include the current map file"))
+                    .on_hover_text("The last saved .mpdz, unsaved changes are not included");
                 ui.vertical_centered(|ui| {
+                    if ui.button("Create report bundle...").clicked() {
+                        self.do_create_bug_bundle(ui.ctx());
+                    }
                     let bug_report_close_button = ui.button("Close");
                     if bug_report_close_button.clicked() {
                         self.bug_report_modal_open = false;
@@ -1612,6 +3714,35 @@ impl eframe::App for Gui {
         if self.display_engine.course_settings.add_window_open {
             let add_map_modal = Modal::new(egui::Id::new("add_map_modal"));
             add_map_modal.show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.display_engine.course_settings.add_map_blank, false, "From Template");
+                    ui.selectable_value(&mut self.display_engine.course_settings.add_map_blank, true, "Blank Map");
+                });
+                ui.separator();
+                if self.display_engine.course_settings.add_map_blank {
+                    ui.heading("Create a blank Map");
+                    let sources_valid = show_blank_map_settings(ui, &mut self.display_engine);
+                    ui.horizontal(|ui| {
+                        if ui.button("Cancel").clicked() {
+                            self.display_engine.course_settings.add_window_open = false;
+                        }
+                        if ui.add_enabled(sources_valid, egui::Button::new("Create")).clicked() {
+                            match self.display_engine.add_blank_map() {
+                                Ok(_) => {
+                                    self.display_engine.course_settings.add_window_open = false;
+                                    self.display_engine.unsaved_changes = true;
+                                    self.display_engine.graphics_update_needed = true;
+                                }
+                                Err(e) => {
+                                    // Close it so the alert is visible
+                                    self.display_engine.course_settings.add_window_open = false;
+                                    self.do_alert(format!("Failed to create blank map: {e}"));
+                                }
+                            }
+                        }
+                    });
+                    return;
+                }
                 ui.heading("Choose a Map template");
                 egui::ComboBox::new(egui::Id::new("add_map_combo_box"), "")
                     .selected_text(&self.display_engine.course_settings.add_map_selected)
@@ -1651,3 +3782,530 @@ impl eframe::App for Gui {
     }
 }
 
+
+#[cfg(test)]
+mod tests_gui {
+    use super::*;
+    use crate::data::{backgrounddata::BackgroundData, fixtures::{sample_course, write_sample_project, TempFolder, SAMPLE_COURSE_NAME}, course_file::{CourseMapInfo, MapEntrance}, mapfile::MapData, scendata::{info::ScenInfoData, pltb::PltbData}, types::MapTileRecordData};
+    use crate::engine::segment_state::{SegmentBaseline, SegmentState};
+
+    /// 4x4 16 color BG 2 with collision, saved to folder/test.mpdz
+    fn gui_with_blank_map(folder: &Path) -> Gui {
+        let mut gui = Gui::default();
+        let info = ScenInfoData { which_bg: 2, color_mode: 0, ..Default::default() };
+        let layer = BackgroundData::new_blank(&info, PltbData::from_pal_vec(Vec::new()), 4, 4, true);
+        gui.display_engine.loaded_map = MapData::new_blank(&folder.join("test.mpdz"), vec![layer]).expect("Blank map is valid");
+        gui.display_engine.display_settings.current_layer = CurrentLayer::BG2;
+        gui.export_directory = folder.to_path_buf();
+        gui.project_open = true;
+        gui
+    }
+
+    fn bg2_tile_count(gui: &mut Gui) -> usize {
+        gui.display_engine.loaded_map.get_background(2).and_then(|bg| bg.get_mpbz_mut()).map_or(0, |mpbz| mpbz.tiles.len())
+    }
+
+    #[test]
+    fn test_clipboard_keeps_tile_zero() {
+        // 2 wide, starts at row 1, then a real tile 0 and tile 3
+        let mpbz = MapTileDataSegment::from_decomped_vec(&[0xff, 0xff, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00], 2).expect("Valid MPBZ");
+        let mut sel = BgSelectData { selected_map_indexes: vec![0, 2, 3], ..Default::default() };
+        let clips = sel.to_clipboard(2, &mpbz);
+        assert_eq!(clips.top_left,Pos2::new(0.0, 0.0));
+        let tiles: Vec<Option<u16>> = clips.tiles.iter().map(|c| c.tile.map(|t| t.to_short())).collect();
+        // The padding row has nothing stored, the placed tile 0 is still there
+        assert_eq!(tiles,vec![None,Some(0),Some(3)]);
+    }
+
+    #[test]
+    fn test_even_selection_fixes() {
+        // 3x3 at 1,1 on an 8x6 map
+        let odd: Vec<u32> = (1..4).flat_map(|y| (1..4).map(move |x| y * 8 + x)).collect();
+        let mut sel = BgSelectData { selected_map_indexes: odd.clone(), ..Default::default() };
+        sel.expand_to_even(8, 6);
+        assert_eq!((sel.selection_width, sel.selection_height),(4, 4));
+        assert_eq!(sel.selected_map_indexes.first(),Some(&0));
+        assert_eq!(sel.selected_map_indexes.len(),16);
+        let mut sel = BgSelectData { selected_map_indexes: odd, ..Default::default() };
+        sel.trim_to_even(8);
+        assert_eq!((sel.selection_width, sel.selection_height),(2, 2));
+        assert_eq!(sel.selected_map_indexes,vec![2 * 8 + 2, 2 * 8 + 3, 3 * 8 + 2, 3 * 8 + 3]);
+        // Odd sized maps can't go past their edge
+        let mut sel = BgSelectData { selected_map_indexes: vec![4 * 5 + 4], ..Default::default() };
+        sel.expand_to_even(5, 5);
+        assert_eq!((sel.selection_width, sel.selection_height),(1, 1));
+        sel.trim_to_even(5);
+        assert!(sel.selected_map_indexes.is_empty());
+        assert_eq!(sel.selection_width,0);
+    }
+
+    #[test]
+    fn test_bg_paste_origin() {
+        let tile = |x_offset, y_offset| BgClipboardSelectedTile { tile: None, x_offset, y_offset };
+        let clip = BgClipboard { tiles: vec![tile(0, 0), tile(1, 1)], top_left: Pos2::new(5.0, 3.0) };
+        assert_eq!(clip.paste_origin(Pos2::new(7.0, 4.0), true),Pos2::new(6.0, 4.0));
+        assert_eq!(clip.paste_origin(Pos2::new(7.0, 4.0), false),Pos2::new(7.0, 4.0));
+        // Block starts 1 tile in, so that tile is the one made even
+        let offset_clip = BgClipboard { tiles: vec![tile(1, 1)], top_left: Pos2::ZERO };
+        assert_eq!(offset_clip.paste_origin(Pos2::new(2.0, 2.0), true),Pos2::new(1.0, 1.0));
+    }
+
+    #[test]
+    fn test_unsaved_changes_guard() {
+        let mut gui = Gui::default();
+        // Nothing to lose, runs right away
+        gui.confirm_unsaved_changes(PendingAction::ChangeMap);
+        assert!(gui.change_map_open);
+        assert!(gui.unsaved_changes_action.is_none());
+        gui.change_map_open = false;
+        gui.display_engine.unsaved_changes = true;
+        gui.confirm_unsaved_changes(PendingAction::ChangeCourse);
+        assert_eq!(gui.unsaved_changes_action,Some(PendingAction::ChangeCourse));
+        gui.resolve_unsaved_changes(UnsavedChoice::Cancel);
+        assert!(gui.unsaved_changes_action.is_none());
+        assert!(!gui.change_course_open);
+        // A finished project script asks before reloading over edits made while it ran
+        gui.confirm_unsaved_changes(PendingAction::ReloadMap);
+        assert_eq!(gui.unsaved_changes_action,Some(PendingAction::ReloadMap));
+        gui.resolve_unsaved_changes(UnsavedChoice::Cancel);
+        assert!(gui.display_engine.unsaved_changes);
+        // Saving first waits for the save to finish
+        gui.confirm_unsaved_changes(PendingAction::ChangeCourse);
+        gui.resolve_unsaved_changes(UnsavedChoice::Save);
+        assert_eq!(gui.after_save_action,Some(PendingAction::ChangeCourse));
+        assert_eq!(gui.saving_progress,Some(0.0));
+        assert!(!gui.change_course_open);
+    }
+
+    #[test]
+    fn test_quick_create_warp() {
+        let mut de = DisplayEngine::default();
+        let mut map_info = CourseMapInfo::from_template("01k0001".to_owned());
+        map_info.map_entrances.push(MapEntrance::default());
+        de.loaded_course.level_map_data = vec![map_info];
+        de.map_index = Some(0);
+        de.quick_create_warp(WarpKind::Exit, 0x12, 0x34);
+        let exit = de.get_selected_exit_mut().expect("New Exit should be selected");
+        assert_eq!((exit.exit_x,exit.exit_y),(0x12,0x34));
+        // Fixed up to point at a real entrance
+        assert!(!exit.target_map.is_nil());
+        assert_eq!(de.course_settings.focus_new_warp,Some(WarpKind::Exit));
+        assert!(de.unsaved_changes);
+    }
+
+    #[test]
+    fn test_save_course_failure() {
+        let mut gui = Gui::default();
+        gui.display_engine.loaded_course.src_filename = String::from("missing_folder_for_test/test.crsb");
+        gui.display_engine.unsaved_changes = true;
+        assert!(matches!(gui.save_course(),Err(SaveError::Create(..))));
+        assert!(!gui.save_course_or_alert());
+        assert!(gui.general_alert_popup.is_some());
+        assert!(gui.display_engine.unsaved_changes);
+    }
+
+    #[test]
+    fn test_clear_then_paste() {
+        let mut gui = gui_with_blank_map(Path::new("unused_for_test"));
+        // Like a third-party edit that emptied the MPBZ
+        gui.display_engine.loaded_map.get_background(2).unwrap().get_mpbz_mut().unwrap().tiles.clear();
+        gui.do_save();
+        assert_eq!(gui.tile_length_issues[0],TileLengthIssue { which_bg: 2, actual: 0, expected: 16 });
+        assert!(gui.saving_progress.is_none());
+        gui.resolve_tile_lengths(true);
+        assert_eq!(gui.saving_progress,Some(0.0));
+        assert_eq!(bg2_tile_count(&mut gui),16);
+        gui.saving_progress = None;
+        gui.clear_bg_layer(2);
+        assert_eq!(bg2_tile_count(&mut gui),16);
+        let pasted = BgClipboardSelectedTile { tile: Some(MapTileRecordData::new(5)), x_offset: 0, y_offset: 0 };
+        gui.display_engine.clipboard.bg_clip = BgClipboard { tiles: vec![pasted], top_left: Pos2::ZERO };
+        gui.display_engine.tile_hover_pos = Pos2::new(2.0, 2.0);
+        gui.do_paste();
+        let mpbz = gui.display_engine.loaded_map.get_background(2).unwrap().get_mpbz().unwrap();
+        assert_eq!(mpbz.tiles.len(),16);
+        assert_eq!(mpbz.get_tile(10).to_short(),5);
+    }
+
+    #[test]
+    fn test_clear_then_save_then_load() {
+        let folder = TempFolder::new("clear");
+        let mut gui = gui_with_blank_map(&folder);
+        // There has to be a file to back up first
+        fs::write(&gui.display_engine.loaded_map.src_file, gui.display_engine.loaded_map.package()).unwrap();
+        gui.display_engine.loaded_map.get_background(2).unwrap().get_mpbz_mut().unwrap().tiles.truncate(3);
+        gui.clear_bg_layer(2);
+        gui.save_map().expect("Map should save");
+        let mut reloaded = MapData::new(&folder.join("test.mpdz"), &folder).expect("Saved map should load");
+        assert!(reloaded.tile_length_issues().is_empty());
+        assert_eq!(reloaded.get_background(2).unwrap().get_mpbz().unwrap().tiles.len(),16);
+    }
+
+    #[test]
+    fn test_theme_colors_preset() {
+        let mut gui = Gui::default();
+        assert_eq!(gui.display_engine.display_settings.theme_colors,ThemeColors::new(ThemeColorPreset::Default));
+        gui.display_engine.display_settings.theme_colors = ThemeColors::new(ThemeColorPreset::HighContrast);
+        let settings = gui.persisted_settings();
+        let mut restored = Gui::default();
+        restored.apply_persisted_settings(&settings);
+        assert_eq!(restored.display_engine.display_settings.theme_colors.preset,ThemeColorPreset::HighContrast);
+        let faded = ThemeColors::with_alpha(Color32::from_rgb(0xe6, 0x9f, 0x00), 0x40);
+        assert_eq!(faded.a(),0x40);
+    }
+
+    #[test]
+    fn test_missing_map_entries() {
+        let mut gui = gui_with_blank_map(Path::new("unused"));
+        for name in ["gone", "other", "loaded"] {
+            gui.display_engine.loaded_course.add_map_info(name.to_string());
+        }
+        gui.display_engine.map_index = Some(2);
+        assert!(!gui.display_engine.map_file_exists("gone"));
+        gui.remove_missing_map(0);
+        assert_eq!(gui.display_engine.loaded_course.level_map_data.len(),2);
+        assert_eq!(gui.display_engine.map_index,Some(1));
+        assert!(gui.display_engine.unsaved_changes);
+        gui.remove_missing_map(1);
+        assert_eq!(gui.display_engine.loaded_course.level_map_data.len(),2);
+        gui.repoint_map(0, String::from("replacement"));
+        assert_eq!(gui.display_engine.loaded_course.level_map_data[0].map_filename_noext,"replacement");
+    }
+
+    #[test]
+    fn test_locked_layer() {
+        let mut gui = gui_with_blank_map(Path::new("unused"));
+        gui.display_engine.clipboard.bg_clip.tiles.push(BgClipboardSelectedTile { tile: Some(MapTileRecordData::new(1)), x_offset: 0, y_offset: 0 });
+        assert!(gui.is_paste_possible());
+        gui.display_engine.display_settings.set_layer_locked(CurrentLayer::BG2, true);
+        assert!(gui.display_engine.display_settings.is_cur_layer_blocked());
+        assert!(!gui.is_paste_possible());
+        gui.do_paste();
+        assert!(!gui.display_engine.unsaved_changes);
+        let settings = gui.persisted_settings();
+        let mut restored = Gui::default();
+        restored.apply_persisted_settings(&settings);
+        assert!(restored.display_engine.display_settings.is_layer_locked(CurrentLayer::BG2));
+        restored.display_engine.display_settings.set_layer_locked(CurrentLayer::BG2, false);
+        assert!(restored.display_engine.display_settings.locked_layers.is_empty());
+    }
+
+    #[test]
+    fn test_keep_map_index() {
+        let mut gui = Gui::default();
+        gui.display_engine.last_map_indexes.insert(String::from("1-4"), 3);
+        gui.display_engine.map_index = Some(1);
+        assert_eq!(gui.display_engine.map_index_for_course("1-4"),3);
+        assert_eq!(gui.display_engine.map_index_for_course("1-5"),0);
+        gui.display_engine.display_settings.keep_map_index = true;
+        assert_eq!(gui.display_engine.map_index_for_course("1-4"),1);
+        let settings = gui.persisted_settings();
+        let mut restored = Gui::default();
+        restored.apply_persisted_settings(&settings);
+        assert!(restored.display_engine.display_settings.keep_map_index);
+    }
+
+    #[test]
+    fn test_preview_pal_per_layer() {
+        let mut gui = Gui::default();
+        gui.display_engine.change_layer(CurrentLayer::BG1);
+        gui.display_engine.tile_preview_pal = 3;
+        gui.display_engine.change_layer(CurrentLayer::BG2);
+        assert_eq!(gui.display_engine.tile_preview_pal,0);
+        gui.display_engine.tile_preview_pal = 7;
+        gui.display_engine.change_layer(CurrentLayer::Sprites);
+        gui.display_engine.change_layer(CurrentLayer::BG1);
+        assert_eq!(gui.display_engine.tile_preview_pal,3);
+        let settings = gui.persisted_settings();
+        assert_eq!(settings.tile_preview_pals,[3, 7, 0]);
+        let mut restored = Gui::default();
+        restored.display_engine.change_layer(CurrentLayer::BG2);
+        restored.apply_persisted_settings(&settings);
+        assert_eq!(restored.display_engine.tile_preview_pal,7);
+    }
+
+    #[test]
+    fn test_bg_preview_only_when_shown() {
+        let ctx = egui::Context::default();
+        let mut gui = gui_with_blank_map(Path::new("unused"));
+        gui.bg_preview_stale = [false;3];
+        gui.display_engine.needs_bg_tile_refresh = true;
+        gui.refresh_bg_preview(&ctx);
+        assert_eq!(gui.bg_preview_stale,[true;3]);
+        // Window closed, nothing is built even once the spinner was up
+        gui.bg_preview_waiting = true;
+        gui.refresh_bg_preview(&ctx);
+        assert_eq!(gui.bg_preview_stale,[true;3]);
+        gui.tile_preview_window_open = true;
+        gui.refresh_bg_preview(&ctx);
+        assert_eq!(gui.bg_preview_stale,[true, false, true]);
+        assert!(!gui.bg_preview_waiting);
+    }
+
+    #[test]
+    fn test_refresh_graphics() {
+        let mut gui = Gui::default();
+        gui.do_refresh_graphics();
+        assert!(!gui.display_engine.graphics_update_needed);
+        let mut gui = gui_with_blank_map(Path::new("unused"));
+        gui.do_refresh_graphics();
+        assert!(gui.display_engine.graphics_update_needed);
+        assert!(gui.display_engine.needs_bg_tile_refresh);
+    }
+
+    #[test]
+    fn test_format_limits_block_save() {
+        let mut gui = gui_with_blank_map(Path::new("unused"));
+        gui.do_save();
+        assert!(gui.saving_progress.is_some());
+        gui.saving_progress = None;
+        assert!(gui.display_engine.loaded_map.limit_issues().is_empty());
+        let uuid = gui.display_engine.loaded_map.add_new_sprite_at(0x36, 1, 1);
+        gui.display_engine.loaded_map.get_setd().expect("Has SETD").sprites.iter_mut()
+            .find(|spr| spr.uuid == uuid).expect("Sprite was added").settings_length = 2;
+        gui.do_save();
+        assert!(gui.saving_progress.is_none());
+        assert!(gui.general_alert_popup.as_ref().is_some_and(|alert| alert.contains("says it has 2 bytes")));
+        // Saving the Course alone before a map change is refused too
+        gui.general_alert_popup = None;
+        assert!(!gui.save_course_or_alert());
+        assert!(gui.general_alert_popup.is_some());
+    }
+
+    #[test]
+    fn test_error_tiles_ask_before_save() {
+        let mut gui = gui_with_blank_map(Path::new("unused"));
+        let bg = gui.display_engine.loaded_map.get_background(2).expect("Has BG 2");
+        bg.pixel_tiles_preview = Some(vec![0; 32]);
+        bg.get_mpbz_mut().expect("Has MPBZ").tiles[5] = MapTileRecordData::new(0x0004);
+        gui.do_save();
+        assert!(gui.saving_progress.is_none());
+        assert_eq!(gui.error_tile_issues,vec![ErrorTileIssue { which_bg: 2, count: 1 }]);
+        gui.error_tile_issues.clear();
+        gui.display_engine.loaded_map.get_background(2).and_then(|bg| bg.get_mpbz_mut()).expect("Has MPBZ").tiles[5] = MapTileRecordData::new(0);
+        gui.do_save();
+        assert!(gui.saving_progress.is_some());
+    }
+
+    #[test]
+    fn test_segment_revert_is_undoable() {
+        let mut gui = gui_with_blank_map(Path::new("unused"));
+        gui.display_engine.segment_baseline = SegmentBaseline::new(&gui.display_engine.loaded_map);
+        let colz = |gui: &mut Gui| gui.display_engine.loaded_map.get_background(2).and_then(|bg| bg.get_colz_mut()).expect("Has COLZ").col_tiles[0];
+        gui.display_engine.loaded_map.get_background(2).and_then(|bg| bg.get_colz_mut()).expect("Has COLZ").col_tiles[0] = 0x01;
+        gui.display_engine.map_edited();
+        assert_eq!(gui.display_engine.segment_baseline.top_level_states(&gui.display_engine.loaded_map)[0],SegmentState::Modified);
+        gui.do_segment_revert(SegmentRevert::TopLevel(0));
+        assert_eq!(colz(&mut gui),0x00);
+        assert_eq!(gui.display_engine.segment_baseline.top_level_states(&gui.display_engine.loaded_map)[0],SegmentState::Unchanged);
+        assert!(gui.display_engine.graphics_update_needed);
+        gui.do_undo();
+        assert_eq!(colz(&mut gui),0x01);
+        gui.do_redo();
+        assert_eq!(colz(&mut gui),0x00);
+    }
+
+    #[test]
+    fn test_bookmarks() {
+        let folder = TempFolder::new("bookmarks");
+        let mut gui = gui_with_blank_map(&folder);
+        gui.grid_viewport = Rect::from_min_size(Pos2::new(100.0, 200.0), Vec2::new(50.0, 40.0));
+        gui.save_bookmark(2);
+        gui.display_engine.change_layer(CurrentLayer::Sprites);
+        gui.jump_to_bookmark(2);
+        assert_eq!(gui.scroll_to,Some(Pos2::new(125.0, 220.0)));
+        assert_eq!(gui.display_engine.display_settings.current_layer,CurrentLayer::BG2);
+        // Other maps don't share it, and it's read back from the project
+        let reloaded = ProjectSettings::load(&folder);
+        assert!(reloaded.get_bookmark("test", 2).is_some());
+        assert!(reloaded.get_bookmark("other", 2).is_none());
+    }
+
+    #[test]
+    fn test_grid_menu_bg_actions() {
+        let folder = TempFolder::new("grid_menu");
+        let mut gui = gui_with_blank_map(&folder);
+        let de = &mut gui.display_engine;
+        de.loaded_map.place_bg_tile_at_map_index(2, 5, 0x0005);
+        de.select_same_bg_tile(2, 5);
+        assert_eq!(de.bg_sel_data.selected_map_indexes,vec![5]);
+        // Not in the selection, so the 2x2 square
+        de.load_brush_from_bg(2, 0);
+        assert_eq!((de.current_brush.width, de.current_brush.height),(2, 2));
+        assert_eq!(de.current_brush.tiles,vec![Some(0), Some(0), Some(0), Some(5)]);
+        // Fills the blank tiles, with the Brush lined up on the even squares
+        de.flood_fill_bg(2, 0);
+        de.select_same_bg_tile(2, 5);
+        assert_eq!(de.bg_sel_data.selected_map_indexes,vec![5, 7, 13, 15]);
+        assert!(de.unsaved_changes);
+    }
+
+    #[test]
+    fn test_failed_map_load_keeps_map() {
+        let folder = TempFolder::new("map_load");
+        let mut gui = gui_with_blank_map(&folder);
+        gui.display_engine.export_folder = folder.to_path_buf();
+        gui.display_engine.loaded_map.place_bg_tile_at_map_index(2, 0, 0x0005);
+        gui.change_map(1);
+        let pending = gui.map_load.take().expect("Load was started");
+        let result = pending.receiver.recv().expect("Thread sends a result");
+        gui.finish_map_load(pending.kind, pending.then, result);
+        // The Course isn't there, so nothing was swapped in
+        assert!(gui.general_alert_popup.is_some());
+        let tile = gui.display_engine.loaded_map.get_background(2).and_then(|bg| bg.get_mpbz()).map(|mpbz| mpbz.get_tile(0));
+        assert_eq!(tile.map(|t| t.tile_id),Some(5));
+    }
+
+    #[test]
+    fn test_shift_map_undo() {
+        let mut gui = gui_with_blank_map(Path::new("stork_test_shift_map"));
+        gui.display_engine.loaded_course = sample_course();
+        gui.display_engine.map_index = Some(0);
+        let warps_before = MapWarps::from_info(&gui.display_engine.loaded_course.level_map_data[0]);
+        gui.shift_map_settings.dx = -2;
+        gui.do_shift_map();
+        let shifted = MapWarps::from_info(&gui.display_engine.loaded_course.level_map_data[0]);
+        assert_eq!(shifted.entrances[0].entrance_x + 2,warps_before.entrances[0].entrance_x);
+        // One step takes back both the map and its warps
+        gui.do_undo();
+        assert_eq!(MapWarps::from_info(&gui.display_engine.loaded_course.level_map_data[0]),warps_before);
+        assert!(!gui.map_history.has_undo(&gui.display_engine.loaded_map));
+        gui.do_redo();
+        assert_eq!(MapWarps::from_info(&gui.display_engine.loaded_course.level_map_data[0]),shifted);
+    }
+
+    #[test]
+    fn test_discard_waits_for_reload() {
+        let folder = TempFolder::new("discard_reload");
+        let (crsb_path, _) = write_sample_project(&folder);
+        let saved_course = fs::read(&crsb_path).expect("Sample was written");
+        let mut gui = gui_with_blank_map(&folder);
+        gui.display_engine.export_folder = folder.to_path_buf();
+        gui.display_engine.loaded_course = sample_course();
+        gui.display_engine.loaded_course.src_filename = crsb_path.display().to_string();
+        gui.display_engine.map_index = Some(0);
+        // An unsaved Course edit, which switching maps would otherwise write
+        gui.display_engine.loaded_course.level_map_data[0].map_music = 0x7;
+        gui.display_engine.unsaved_changes = true;
+        gui.do_switch(SwitchTarget::Map(1));
+        gui.resolve_unsaved_changes(UnsavedChoice::Discard);
+        let pending = gui.map_load.take().expect("Reload was started");
+        assert_eq!(pending.then,Some(PendingAction::Switch(SwitchTarget::Map(1))));
+        assert!(!gui.change_map_open);
+        let result = pending.receiver.recv().expect("Thread sends a result");
+        gui.finish_map_load(pending.kind, pending.then, result);
+        assert!(!gui.display_engine.unsaved_changes);
+        assert_eq!(gui.display_engine.loaded_course.level_map_data[0].map_music,0x3);
+        // The switch ran on the reloaded Course, so the edit never reached the disk
+        let pending = gui.map_load.take().expect("Switch was started");
+        assert_eq!(pending.kind,MapLoadKind::Map);
+        assert_eq!(pending.description,format!("{SAMPLE_COURSE_NAME} map 2"));
+        assert_eq!(fs::read(&crsb_path).expect("Course is still there"),saved_course);
+    }
+
+    #[test]
+    fn test_map_switch_skips_unchanged_course() {
+        let folder = TempFolder::new("switch_unchanged_course");
+        let (crsb_path, _) = write_sample_project(&folder);
+        let mut gui = gui_with_blank_map(&folder);
+        gui.display_engine.export_folder = folder.to_path_buf();
+        gui.display_engine.loaded_course.src_filename = crsb_path.display().to_string();
+        gui.change_map(0);
+        let pending = gui.map_load.take().expect("Load was started");
+        let result = pending.receiver.recv().expect("Thread sends a result");
+        gui.finish_map_load(pending.kind, pending.then, result);
+        assert!(!gui.display_engine.course_changed());
+        // Stands in for the file, to see whether it was written again
+        let marker = b"untouched".to_vec();
+        fs::write(&crsb_path, &marker).expect("Marker was written");
+        gui.do_switch(SwitchTarget::Map(1));
+        assert!(gui.map_load.take().is_some());
+        assert_eq!(fs::read(&crsb_path).expect("Course is still there"),marker);
+        // An edited Course is still saved first, since exit targeting reads it
+        gui.display_engine.loaded_course.level_map_data[0].map_music = 0x7;
+        assert!(gui.display_engine.course_changed());
+        gui.do_switch(SwitchTarget::Map(1));
+        assert!(gui.map_load.take().is_some());
+        assert_ne!(fs::read(&crsb_path).expect("Course is still there"),marker);
+        assert!(!gui.display_engine.course_changed());
+    }
+
+    #[test]
+    fn test_bg_clip_to_brush() {
+        let mut clip = BgClipboard::default();
+        assert!(clip.to_brush(String::new()).is_none());
+        clip.tiles.push(BgClipboardSelectedTile { tile: Some(MapTileRecordData::new(5)), x_offset: 2, y_offset: 1 });
+        clip.tiles.push(BgClipboardSelectedTile { tile: None, x_offset: 4, y_offset: 2 });
+        assert_eq!(clip.dimensions(),(3, 2));
+        let brush = clip.to_brush(String::from("test")).expect("Clip should fit a Brush");
+        assert_eq!(brush.tiles,vec![Some(5), None, None, None, None, None]);
+        assert_eq!(brush.tileset,"test");
+    }
+
+    #[test]
+    fn test_number_base_fmt() {
+        assert_eq!(NumberBase::Hex.fmt_num(0x1F_u16, 4),"0x001F");
+        assert_eq!(NumberBase::Hex.fmt_num(-2_i8, 1),"-0x2");
+        assert_eq!(NumberBase::Decimal.fmt_num(0x1F_u16, 4),"31");
+        assert_eq!(NumberBase::Decimal.fmt_num(-2_i8, 1),"-2");
+    }
+
+    #[test]
+    fn test_record_project_folder() {
+        let folder = TempFolder::new("project_folder");
+        let mut gui = Gui { export_directory: folder.to_path_buf(), ..Default::default() };
+        gui.project_settings.export_directory = Some(PathBuf::from("old/location"));
+        gui.project_settings.last_rom_path = Some(PathBuf::from("out/rom.nds"));
+        gui.record_project_folder(&folder);
+        assert_eq!(gui.project_folder_edit,folder.display().to_string());
+        let reloaded = ProjectSettings::load(&folder);
+        assert_eq!(reloaded.export_directory,Some(folder.to_path_buf()));
+        assert_eq!(reloaded.last_rom_path,Some(PathBuf::from("out/rom.nds")));
+    }
+
+    #[test]
+    fn test_companion_windows() {
+        let mut gui = Gui::default();
+        gui.display_engine.change_layer(CurrentLayer::Collision);
+        gui.on_layer_changed();
+        assert!(gui.collision_window_open);
+        // Closed while on the layer, so it stays closed next time
+        gui.collision_window_open = false;
+        gui.display_engine.change_layer(CurrentLayer::Paths);
+        gui.on_layer_changed();
+        assert!(gui.path_window_open);
+        gui.display_engine.change_layer(CurrentLayer::Collision);
+        gui.on_layer_changed();
+        assert!(!gui.collision_window_open);
+        // BG layers share the Brush window, but each remembers its own state
+        gui.display_engine.change_layer(CurrentLayer::BG1);
+        gui.on_layer_changed();
+        assert!(gui.brush_window_open);
+        gui.brush_window_open = false;
+        gui.display_engine.change_layer(CurrentLayer::BG2);
+        gui.on_layer_changed();
+        assert!(gui.brush_window_open);
+        gui.display_engine.change_layer(CurrentLayer::BG1);
+        gui.on_layer_changed();
+        assert!(!gui.brush_window_open);
+        // Only runs once per change
+        gui.brush_window_open = true;
+        gui.on_layer_changed();
+        assert!(gui.brush_window_open);
+        gui.display_engine.display_settings.auto_open_companion = false;
+        gui.brush_window_open = false;
+        gui.display_engine.change_layer(CurrentLayer::BG2);
+        gui.on_layer_changed();
+        assert!(!gui.brush_window_open);
+    }
+
+    #[test]
+    fn test_persisted_settings_partial() {
+        // Older saves may be missing newer keys
+        let settings: PersistedSettings = serde_json::from_str(r#"{"start_layer":"BG2"}"#).unwrap();
+        assert_eq!(settings.start_layer,CurrentLayer::BG2);
+        assert!(settings.number_base == NumberBase::Hex);
+        assert_eq!(settings.max_undos,MapHistory::default().max_undos);
+        assert!(settings.grid_background == GridBackground::Panel);
+    }
+}
@@ -1,9 +1,9 @@
-use std::f32::consts::PI;
+use std::{collections::HashMap, f32::consts::PI};
 
-use egui::{Align2, Color32, ColorImage, Context, FontId, Image, Painter, Pos2, Rect, Response, Stroke, Vec2};
+use egui::{emath::easing, Align2, Button, Color32, Context, FontId, Image, Key, Mesh, Modifiers, Painter, Pos2, Rect, Response, Shape, Stroke, TextureId, Vec2};
 use uuid::Uuid;
 
-use crate::{data::{area::{AREA_RECT_COLOR, AREA_RECT_COLOR_SELECTED}, backgrounddata::BackgroundData, path::PathPoint, scendata::colz::{self, draw_collision}, sprites::{draw_sprite, LevelSprite}, types::{get_cached_texture, set_cached_texture, CurrentLayer, MapTileRecordData, Palette, TileCache}}, engine::displayengine::DisplayEngine, utils::{self, log_write, LogLevel}};
+use crate::{data::{backgrounddata::BackgroundData, course_file::WarpKind, path::{PathDatabase, PathPoint}, scendata::colz::{self, draw_collision}, sprites::{draw_sprite, find_sprite_issues, LevelSprite, SpriteRangeArea}, types::{get_cached_pixels, get_cached_texture, set_cached_texture, CurrentLayer, MapTileRecordData, Palette, TileCache}}, engine::displayengine::{Arm9Tables, ColDragStatus, DisplayEngine}, gui::{gui::{GridBackground, NumberBase, SpriteBoxMode, ThemeColors, TileDim, TileNumberOverlay}, windows::course_win::QuickCreateWarp}, load::{sprite_meta, SPRITE_RANGES}, utils::{self, log_write, LogLevel}};
 
 const TILE_WIDTH_PX: f32 = 8.0;
 const TILE_HEIGHT_PX: f32 = 8.0;
@@ -11,22 +11,60 @@ const TILE_RECT: Vec2 = Vec2::new(TILE_WIDTH_PX, TILE_HEIGHT_PX);
 const TILE_OUTER_PADDING: f32 = 10.0;
 const RECT_TRIM_PADDING_TILE: f32 = 1.0;
 const SPRITE_RECT: Vec2 = Vec2::new(TILE_WIDTH_PX * 2.0, TILE_HEIGHT_PX * 2.0);
-const SPRITE_BG_COLOR: Color32 = Color32::from_rgba_premultiplied(0xff, 0x00, 0xff, 0x40);
-const SPRITE_BG_COLOR_SELECTED: Color32 = Color32::from_rgba_premultiplied(0x00, 0xff, 0x00, 0xff);
 const FONT: FontId = FontId { size: 12.0, family: egui::FontFamily::Monospace };
-const BG_SELECTION_FILL: Color32 = Color32::from_rgba_premultiplied(0x80, 0x65, 0xb5, 0xA0);
-const BG_SELECTION_FILL_INVERT: Color32 = Color32::from_rgba_premultiplied(0x65, 0x80, 0xb5, 0xA0);
-const BG_SELECTION_STROKE: Color32 = Color32::WHITE;
+/// Small enough for 3 hex digits in one tile
+const TILE_NUMBER_FONT: FontId = FontId { size: 4.0, family: egui::FontFamily::Monospace };
+const RULER_FONT: FontId = FontId { size: 8.0, family: egui::FontFamily::Monospace };
+/// Height of the top ruler and width of the left one, see draw_rulers
+pub const RULER_THICKNESS: f32 = 28.0;
+/// Closest ruler labels can be, in screen pixels
+const RULER_LABEL_SPACING: f32 = 48.0;
+
+/// Holding X or Y mirrors the Brush for that stamp. Ctrl+Y is Redo
+fn stamp_flip_held(ui: &egui::Ui) -> (bool, bool) {
+    if ui.ctx().wants_keyboard_input() {
+        return (false, false);
+    }
+    ui.input(|i| (i.key_down(egui::Key::X), i.key_down(egui::Key::Y) && !i.modifiers.command))
+}
 
 /// Active drawing for various visible data layers
 /// 
 /// Each one takes in the display data plus a UI reference, then combines the two
 /// to create a drawn layer. This also includes logic to disable drawing the layer.
-pub fn render_primary_grid(ui: &mut egui::Ui, de: &mut DisplayEngine, vrect: &Rect) {
+///
+/// Input routing: only the current layer creates interact widgets (see
+/// receives_input), so overlays from other layers can never eat its clicks.
+/// The ids are "col_tile_click", "AREA_click", "PATH_click", "sprite_click_fallback",
+/// "sprite_click_*"/"sprite_hover_*" and "map_tile_interact_*", one layer each.
+/// Holding Alt turns all of those off, and a click instead goes to whatever is
+/// under the cursor on another layer, switching to it (see handle_click_through).
+/// A Sprite drag that already started keeps going, with Alt placing it freely
+///
+/// Right-click opens the grid menu instead of stamping or placing when there is
+/// nothing to stamp or place, or when Shift is held. Copy, Cut and Paste from it
+/// are returned, since those go through Gui
+pub fn render_primary_grid(ui: &mut egui::Ui, de: &mut DisplayEngine, vrect: &Rect) -> Option<GridMenuAction> {
     puffin::profile_function!();
-    draw_background(ui, de, vrect, 3, de.display_settings.show_bg3);
-    draw_background(ui, de, vrect, 2, de.display_settings.show_bg2);
-    draw_background(ui, de, vrect, 1, de.display_settings.show_bg1);
+    // Before the layers, so they know to leave the right-click alone
+    de.course_settings.warp_right_click = take_warp_right_click(ui, de);
+    let menu_opened = !de.course_settings.warp_right_click && open_grid_menu(ui, de);
+    de.tile_cache_bg1.begin_frame();
+    de.tile_cache_bg2.begin_frame();
+    de.tile_cache_bg3.begin_frame();
+    draw_grid_background(ui, de, vrect);
+    if de.display_settings.show_gradient {
+        draw_gradient(ui, de);
+    }
+    // By priority, which some maps change from the usual BG3, BG2, BG1
+    for which_bg in de.loaded_map.bg_draw_order() {
+        let show = match which_bg {
+            1 => de.display_settings.show_bg1,
+            2 => de.display_settings.show_bg2,
+            _ => de.display_settings.show_bg3
+        };
+        draw_background(ui, de, vrect, which_bg, show);
+    }
     if de.display_settings.show_breakable_rock {
         draw_breakable_rock(ui, de);
     }
@@ -48,11 +86,735 @@ pub fn render_primary_grid(ui: &mut egui::Ui, de: &mut DisplayEngine, vrect: &Re
     if de.display_settings.show_triggers {
         draw_triggers(ui, de);
     }
+    draw_paste_preview(ui, de);
+    handle_warp_quick_create(ui, de);
+    handle_click_through(ui, de);
+    show_grid_menu(ui, de, menu_opened)
+}
+
+/// Where the grid menu was opened, in tiles on the current layer
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct GridMenu {
+    pub screen_pos: Pos2,
+    pub layer: CurrentLayer,
+    pub tile_x: u32,
+    pub tile_y: u32
+}
+
+/// Grid menu items that Gui carries out
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum GridMenuAction {
+    Copy,
+    Cut,
+    /// At the tile the menu was opened on, already set as the paste position
+    Paste
+}
+
+#[derive(Clone,Copy)]
+enum GridMenuItem {
+    Copy,
+    Cut,
+    PasteHere,
+    FloodFill,
+    SelectSameTile,
+    LoadIntoBrush,
+    AddSprite(u16),
+    SelectSameSprite(u16),
+    CopyCollisionType(u8),
+    FillCollisionRect
+}
+
+/// Opens the grid menu on a right-click that the current layer would otherwise
+/// do nothing with, or with Shift held. Collision always deletes, so it needs Shift
+fn open_grid_menu(ui: &egui::Ui, de: &mut DisplayEngine) -> bool {
+    let layer = de.display_settings.current_layer;
+    if !receives_input(ui, de, layer) || !ui.input(|i| i.pointer.secondary_clicked()) {
+        return false;
+    }
+    // The layers haven't allocated their space yet, so the visible area it is
+    if !ui.rect_contains_pointer(ui.clip_rect()) {
+        return false;
+    }
+    let Some(pointer_pos) = ui.input(|i| i.pointer.interact_pos()) else { return false };
+    let right_click_free = match layer {
+        CurrentLayer::BG1 | CurrentLayer::BG2 | CurrentLayer::BG3 => de.current_brush.tiles.iter().all(Option::is_none),
+        CurrentLayer::Sprites => de.selected_sprite_to_place.is_none(),
+        CurrentLayer::Collision => false,
+        _ => return false
+    };
+    if !right_click_free && !ui.input(|i| i.modifiers.shift) {
+        return false;
+    }
+    let mut local_pos = pointer_pos - ui.min_rect().min;
+    let (mut layer_width, mut layer_height) = de.loaded_map.get_dimensions();
+    if de.display_settings.is_cur_layer_bg() {
+        let Some(info) = get_display_layer(de, layer as u8).and_then(|bg| bg.get_info()) else { return false };
+        // Same offset as draw_background
+        local_pos += Vec2::new(info.x_offset_px as f32, info.y_offset_px as f32);
+        (layer_width, layer_height) = (info.layer_width, info.layer_height);
+    }
+    if local_pos.x < 0.0 || local_pos.y < 0.0 {
+        return false;
+    }
+    let tile_x = (local_pos.x / TILE_WIDTH_PX) as u32;
+    let tile_y = (local_pos.y / TILE_HEIGHT_PX) as u32;
+    if tile_x >= layer_width as u32 || tile_y >= layer_height as u32 {
+        return false;
+    }
+    de.grid_menu = Some(GridMenu { screen_pos: pointer_pos, layer, tile_x, tile_y });
+    true
+}
+
+/// Collision index under a grid menu, which is in BG tiles
+fn grid_menu_col_index(de: &mut DisplayEngine, menu: &GridMenu) -> Option<usize> {
+    let which_bg = de.loaded_map.get_bg_with_colz()?;
+    let layer_width = de.loaded_map.get_background(which_bg)?.get_info()?.layer_width as u32;
+    Some(((menu.tile_y / 2) * (layer_width / 2) + menu.tile_x / 2) as usize)
+}
+
+fn show_grid_menu(ui: &mut egui::Ui, de: &mut DisplayEngine, just_opened: bool) -> Option<GridMenuAction> {
+    let menu = de.grid_menu?;
+    if menu.layer != de.display_settings.current_layer || de.display_settings.is_cur_layer_blocked() {
+        de.grid_menu = None;
+        return None;
+    }
+    let base = de.display_settings.number_base;
+    let popup = egui::Area::new(egui::Id::new("grid_context_menu"))
+        .order(egui::Order::Foreground)
+        .fixed_pos(menu.screen_pos)
+        .show(ui.ctx(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.label(format!("{}/{}",base.fmt_num(menu.tile_x, 1),base.fmt_num(menu.tile_y, 1)));
+                ui.separator();
+                match menu.layer {
+                    CurrentLayer::BG1 | CurrentLayer::BG2 | CurrentLayer::BG3 => grid_menu_bg_items(ui, de),
+                    CurrentLayer::Sprites => grid_menu_sprite_items(ui, de, &menu),
+                    CurrentLayer::Collision => grid_menu_collision_items(ui, de, &menu),
+                    _ => None
+                }
+            }).inner
+        });
+    if let Some(item) = popup.inner {
+        de.grid_menu = None;
+        return apply_grid_menu_item(de, &menu, item);
+    }
+    let clicked_away = ui.input(|i| i.pointer.any_click()) && !popup.response.contains_pointer();
+    if (clicked_away && !just_opened) || ui.input(|i| i.key_pressed(Key::Escape)) {
+        de.grid_menu = None;
+    }
+    None
+}
+
+fn grid_menu_bg_items(ui: &mut egui::Ui, de: &DisplayEngine) -> Option<GridMenuItem> {
+    let mut item: Option<GridMenuItem> = None;
+    let has_selection = !de.bg_sel_data.selected_map_indexes.is_empty();
+    if ui.add_enabled(has_selection, Button::new("Copy")).clicked() {
+        item = Some(GridMenuItem::Copy);
+    }
+    if ui.add_enabled(has_selection, Button::new("Cut")).clicked() {
+        item = Some(GridMenuItem::Cut);
+    }
+    if ui.add_enabled(!de.clipboard.bg_clip.tiles.is_empty(), Button::new("Paste here")).clicked() {
+        item = Some(GridMenuItem::PasteHere);
+    }
+    ui.separator();
+    let has_brush = de.current_brush.tiles.iter().any(Option::is_some);
+    let flood_fill = ui.add_enabled(has_brush, Button::new("Flood fill"))
+        .on_hover_text("Fills the matching tiles touching this one with the Brush")
+        .on_disabled_hover_text("Needs a Brush to fill with");
+    if flood_fill.clicked() {
+        item = Some(GridMenuItem::FloodFill);
+    }
+    if ui.button("Select same tile").clicked() {
+        item = Some(GridMenuItem::SelectSameTile);
+    }
+    let load_brush = ui.button("Load into Brush")
+        .on_hover_text("The selection if this tile is in it, otherwise the 2x2 square here");
+    if load_brush.clicked() {
+        item = Some(GridMenuItem::LoadIntoBrush);
+    }
+    item
+}
+
+fn grid_menu_sprite_items(ui: &mut egui::Ui, de: &DisplayEngine, menu: &GridMenu) -> Option<GridMenuItem> {
+    let mut item: Option<GridMenuItem> = None;
+    let sprite_name = |object_id: u16| sprite_meta(object_id).map(|meta| meta.name.clone());
+    let add_id = de.selected_sprite_to_place.unwrap_or(de.latest_sprite_object_id);
+    match sprite_name(add_id) {
+        Some(name) => if ui.button(format!("Add {} here",name)).clicked() {
+            item = Some(GridMenuItem::AddSprite(add_id));
+        }
+        None => {
+            ui.add_enabled(false, Button::new("Add Sprite here"));
+        }
+    }
+    if ui.add_enabled(!de.clipboard.sprite_clip.sprites.is_empty(), Button::new("Paste here")).clicked() {
+        item = Some(GridMenuItem::PasteHere);
+    }
+    // Last drawn is on top
+    let (tile_x, tile_y) = (menu.tile_x as u16, menu.tile_y as u16);
+    let under = de.level_sprites.iter().rev().find(|sprite| {
+        (sprite.x_position..sprite.x_position.saturating_add(2)).contains(&tile_x) &&
+            (sprite.y_position..sprite.y_position.saturating_add(2)).contains(&tile_y)
+    });
+    match under {
+        Some(sprite) => {
+            let name = sprite_name(sprite.object_id).unwrap_or_else(|| String::from("Unknown"));
+            if ui.button(format!("Select all {}",name)).clicked() {
+                item = Some(GridMenuItem::SelectSameSprite(sprite.object_id));
+            }
+        }
+        None => {
+            ui.add_enabled(false, Button::new("Select all of this type"))
+                .on_disabled_hover_text("No Sprite here");
+        }
+    }
+    item
+}
+
+fn grid_menu_collision_items(ui: &mut egui::Ui, de: &mut DisplayEngine, menu: &GridMenu) -> Option<GridMenuItem> {
+    let mut item: Option<GridMenuItem> = None;
+    let col_type = grid_menu_col_index(de, menu).and_then(|col_index| {
+        let which_bg = de.loaded_map.get_bg_with_colz()?;
+        de.loaded_map.get_background(which_bg)?.get_colz()?.col_tiles.get(col_index).copied()
+    }).unwrap_or(0x00);
+    let copy_type = ui.add_enabled(col_type != 0x00, Button::new(format!("Copy type 0x{:X}",col_type)))
+        .on_disabled_hover_text("No collision here");
+    if copy_type.clicked() {
+        item = Some(GridMenuItem::CopyCollisionType(col_type));
+    }
+    let fill_rect = ui.button("Fill rect from here")
+        .on_hover_text(format!("Click another tile to fill between them with 0x{:X}",de.col_tile_to_place));
+    if fill_rect.clicked() {
+        item = Some(GridMenuItem::FillCollisionRect);
+    }
+    item
+}
+
+fn apply_grid_menu_item(de: &mut DisplayEngine, menu: &GridMenu, item: GridMenuItem) -> Option<GridMenuAction> {
+    let tile_pos = Pos2::new(menu.tile_x as f32, menu.tile_y as f32);
+    let which_bg = menu.layer as u8;
+    let bg_map_index = || {
+        let layer_width = get_display_layer(de, which_bg).and_then(|bg| bg.get_info()).map_or(0, |info| info.layer_width as u32);
+        menu.tile_y * layer_width + menu.tile_x
+    };
+    match item {
+        GridMenuItem::Copy => return Some(GridMenuAction::Copy),
+        GridMenuItem::Cut => return Some(GridMenuAction::Cut),
+        GridMenuItem::PasteHere => {
+            // The pointer has since moved onto the menu
+            if de.display_settings.is_cur_layer_bg() {
+                de.tile_hover_pos = tile_pos;
+            } else {
+                de.latest_square_pos_level_space = tile_pos;
+            }
+            return Some(GridMenuAction::Paste);
+        }
+        GridMenuItem::FloodFill => {
+            let map_index = bg_map_index();
+            de.flood_fill_bg(which_bg, map_index);
+        }
+        GridMenuItem::SelectSameTile => {
+            let map_index = bg_map_index();
+            de.select_same_bg_tile(which_bg, map_index);
+        }
+        GridMenuItem::LoadIntoBrush => {
+            let map_index = bg_map_index();
+            de.load_brush_from_bg(which_bg, map_index);
+        }
+        GridMenuItem::AddSprite(object_id) => {
+            let new_uuid = de.loaded_map.add_new_sprite_at(object_id, menu.tile_x as u16, menu.tile_y as u16);
+            if new_uuid.is_nil() {
+                return None;
+            }
+            log_write(format!("Placed sprite with UUID {new_uuid} from grid menu"), LogLevel::Debug);
+            de.selected_sprite_uuids = vec![new_uuid];
+            if let Some(new_sprite) = de.loaded_map.get_sprite_by_uuid(new_uuid) {
+                de.latest_sprite_settings = utils::bytes_to_hex_string(&new_sprite.settings);
+                de.latest_sprite_object_id = new_sprite.object_id;
+            }
+            de.map_edited();
+            de.graphics_update_needed = true;
+        }
+        GridMenuItem::SelectSameSprite(object_id) => {
+            de.selected_sprite_uuids = de.level_sprites.iter()
+                .filter(|sprite| sprite.object_id == object_id).map(|sprite| sprite.uuid).collect();
+            log_write(format!("Selected {} Sprites of type 0x{:X}",de.selected_sprite_uuids.len(),object_id), LogLevel::Log);
+        }
+        GridMenuItem::CopyCollisionType(col_type) => {
+            log_write(format!("Copied collision type '0x{:X}'",col_type), LogLevel::Log);
+            de.col_tile_to_place = col_type;
+        }
+        GridMenuItem::FillCollisionRect => {
+            de.col_fill_anchor = grid_menu_col_index(de, menu);
+        }
+    }
+    None
+}
+
+/// Faint outline of where a paste would land, shown while Ctrl is held.
+/// Holding Shift too previews Paste in Place
+fn draw_paste_preview(ui: &mut egui::Ui, de: &DisplayEngine) {
+    let (ctrl, shift) = ui.input(|i| (i.modifiers.command, i.modifiers.shift));
+    if !ctrl || de.display_settings.is_cur_layer_blocked() || !ui.rect_contains_pointer(ui.min_rect()) {
+        return;
+    }
+    let top_left: Pos2 = ui.min_rect().min;
+    let tile_to_screen = |x: f32, y: f32| top_left + Vec2::new(x * TILE_WIDTH_PX, y * TILE_HEIGHT_PX);
+    let cursor = de.latest_square_pos_level_space;
+    let colors = &de.display_settings.theme_colors;
+    match de.display_settings.current_layer {
+        CurrentLayer::Sprites => {
+            let clip = &de.clipboard.sprite_clip;
+            let origin = if shift { clip.top_left_pos } else { cursor };
+            for sprite in &clip.sprites {
+                let x = origin.x + (sprite.x_position as f32 - clip.top_left_pos.x);
+                let y = origin.y + (sprite.y_position as f32 - clip.top_left_pos.y);
+                let rect = Rect::from_min_size(tile_to_screen(x, y), SPRITE_RECT);
+                ui.painter().rect_filled(rect, 0.0, colors.paste_preview_fill);
+                ui.painter().rect_stroke(rect, 0.0, Stroke::new(1.0, colors.paste_preview_stroke), egui::StrokeKind::Inside);
+            }
+        }
+        CurrentLayer::BG1 | CurrentLayer::BG2 | CurrentLayer::BG3 => {
+            let clip = &de.clipboard.bg_clip;
+            if clip.tiles.is_empty() {
+                return;
+            }
+            let origin = if shift {
+                clip.top_left
+            } else {
+                clip.paste_origin(de.tile_hover_pos, de.display_settings.snap_bg_paste_to_even)
+            };
+            let mut block = Rect::NOTHING;
+            for tile in &clip.tiles {
+                let rect = Rect::from_min_size(
+                    tile_to_screen(origin.x + tile.x_offset as f32, origin.y + tile.y_offset as f32), TILE_RECT);
+                block = block.union(rect);
+                // Spots with nothing stored are left alone by paste
+                if tile.tile.is_some() {
+                    ui.painter().rect_filled(rect, 0.0, colors.paste_preview_fill);
+                }
+            }
+            ui.painter().rect_stroke(block, 0.0, Stroke::new(1.0, colors.paste_preview_stroke), egui::StrokeKind::Outside);
+        }
+        _ => {}
+    }
+}
+
+/// Whether the current layer does something itself on a right-click
+/// True if neither the grid menu nor quick-create took this frame's right-click
+fn right_click_free(de: &DisplayEngine) -> bool {
+    de.grid_menu.is_none() && !de.course_settings.warp_right_click
+}
+
+/// Right-click on empty space with Course Settings open creates an Entrance or
+/// Exit there, with a chooser if both overlays are visible. Runs before the
+/// layers, so true means they should leave the right-click alone
+fn take_warp_right_click(ui: &egui::Ui, de: &mut DisplayEngine) -> bool {
+    let show_entrances = de.display_settings.show_entrances;
+    let show_exits = de.display_settings.show_exits;
+    if !de.course_settings.window_open || (!show_entrances && !show_exits) || de.course_settings.quick_create.is_some() {
+        return false;
+    }
+    if !ui.input(|i| i.pointer.secondary_clicked()) {
+        return false;
+    }
+    let Some(pointer_pos) = ui.input(|i| i.pointer.interact_pos()) else { return false };
+    // The layers haven't allocated their space yet, so the visible area it is
+    if !ui.rect_contains_pointer(ui.clip_rect()) {
+        return false;
+    }
+    let Some(map_index) = de.map_index else { return false };
+    let Some(map_info) = de.loaded_course.level_map_data.get(map_index) else { return false };
+    let top_left: Pos2 = ui.min_rect().min;
+    let warp_rect = |x: u16, y: u16| Rect::from_min_size(
+        top_left + Vec2::new(x as f32 * TILE_WIDTH_PX, y as f32 * TILE_HEIGHT_PX), SPRITE_RECT);
+    let on_entrance = show_entrances && map_info.map_entrances.iter()
+        .any(|e| warp_rect(e.entrance_x, e.entrance_y).contains(pointer_pos));
+    let on_exit = show_exits && map_info.map_exits.iter()
+        .any(|e| warp_rect(e.exit_x, e.exit_y).contains(pointer_pos));
+    if on_entrance || on_exit {
+        return false; // Not empty space
+    }
+    let local_pos = pointer_pos - top_left;
+    if local_pos.x < 0.0 || local_pos.y < 0.0 {
+        return false;
+    }
+    let tile_x = (local_pos.x / TILE_WIDTH_PX) as u16;
+    let tile_y = (local_pos.y / TILE_HEIGHT_PX) as u16;
+    let (map_width, map_height) = de.loaded_map.get_dimensions();
+    if tile_x >= map_width || tile_y >= map_height {
+        return false;
+    }
+    match (show_entrances, show_exits) {
+        (true, true) => {
+            de.course_settings.quick_create = Some(QuickCreateWarp { screen_pos: pointer_pos, tile_x, tile_y });
+        }
+        (true, false) => de.quick_create_warp(WarpKind::Entrance, tile_x, tile_y),
+        _ => de.quick_create_warp(WarpKind::Exit, tile_x, tile_y)
+    }
+    true
+}
+
+/// The Entrance/Exit chooser from take_warp_right_click
+fn handle_warp_quick_create(ui: &mut egui::Ui, de: &mut DisplayEngine) {
+    if !de.course_settings.window_open || (!de.display_settings.show_entrances && !de.display_settings.show_exits) {
+        de.course_settings.quick_create = None;
+        return;
+    }
+    let Some(pending) = de.course_settings.quick_create else { return };
+    let chooser = egui::Area::new(egui::Id::new("warp_quick_create"))
+        .order(egui::Order::Foreground)
+        .fixed_pos(pending.screen_pos)
+        .show(ui.ctx(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                let base = de.display_settings.number_base;
+                ui.label(format!("New at {}/{}",base.fmt_num(pending.tile_x, 1),base.fmt_num(pending.tile_y, 1)));
+                if ui.button("Entrance").clicked() {
+                    return Some(WarpKind::Entrance);
+                }
+                if ui.button("Exit").clicked() {
+                    return Some(WarpKind::Exit);
+                }
+                None
+            }).inner
+        });
+    if let Some(kind) = chooser.inner {
+        de.course_settings.quick_create = None;
+        de.quick_create_warp(kind, pending.tile_x, pending.tile_y);
+    } else if ui.input(|i| i.pointer.any_click()) && !chooser.response.contains_pointer() && !de.course_settings.warp_right_click {
+        de.course_settings.quick_create = None;
+    }
+}
+
+/// Whether this layer gets clicks and drags this frame
+///
+/// A Sprite drag keeps going when Alt is pressed partway, since Alt then means
+/// free placement instead of click-through (see sprite_drag_target)
+fn receives_input(ui: &egui::Ui, de: &DisplayEngine, layer: CurrentLayer) -> bool {
+    let sprite_dragging = layer == CurrentLayer::Sprites && !de.sprite_drag_status.dragging_uuid.is_nil();
+    de.display_settings.current_layer == layer
+        && !de.display_settings.is_cur_layer_blocked()
+        && (sprite_dragging || !ui.input(|i| i.modifiers.alt))
+}
+
+/// Where a dragged Sprite's preview goes and the tile it lands on, both relative
+/// to the grid. Shift keeps it on the axis moved furthest along since the drag
+/// started. Alt lands on the tile under the preview's corner instead of the nearest
+fn sprite_drag_target(start: Vec2, cur: Vec2, sprite_tile: (u16, u16), axis_lock: bool, free: bool) -> (Vec2, (i32, i32)) {
+    let mut preview_pos = cur;
+    if axis_lock {
+        let delta = cur - start;
+        if delta.x.abs() >= delta.y.abs() {
+            preview_pos.y = sprite_tile.1 as f32 * TILE_HEIGHT_PX;
+        } else {
+            preview_pos.x = sprite_tile.0 as f32 * TILE_WIDTH_PX;
+        }
+    }
+    let to_tile = |px: f32, tile_px: f32| if free {
+        (px / tile_px).floor() as i32
+    } else {
+        (px / tile_px).round() as i32
+    };
+    (preview_pos, (to_tile(preview_pos.x, TILE_WIDTH_PX), to_tile(preview_pos.y, TILE_HEIGHT_PX)))
+}
+
+enum ClickThroughTarget {
+    Trigger(Uuid),
+    Collision,
+    Sprite(LevelSprite),
+    BgTile(u8, u32)
+}
+
+/// Alt+click selects the topmost visible thing under the cursor that is not on the
+/// current layer, in the order they are drawn (Triggers, Collision, Sprites, BGs)
+fn handle_click_through(ui: &mut egui::Ui, de: &mut DisplayEngine) {
+    let (alt, clicked) = ui.input(|i| (i.modifiers.alt, i.pointer.primary_clicked()));
+    if !alt || !clicked {
+        return;
+    }
+    let Some(pointer_pos) = ui.input(|i| i.pointer.interact_pos()) else { return };
+    if !ui.rect_contains_pointer(ui.min_rect()) {
+        return;
+    }
+    let top_left: Pos2 = ui.min_rect().min;
+    let current = de.display_settings.current_layer;
+    let Some(target) = find_click_through_target(de, top_left, pointer_pos, current) else {
+        log_write("Nothing to click through to", LogLevel::Debug);
+        return;
+    };
+    match target {
+        ClickThroughTarget::Trigger(uuid) => {
+            de.change_layer(CurrentLayer::Triggers);
+            de.trigger_settings.selected_uuid = uuid;
+        }
+        ClickThroughTarget::Collision => {
+            de.change_layer(CurrentLayer::Collision);
+        }
+        ClickThroughTarget::Sprite(sprite) => {
+            de.change_layer(CurrentLayer::Sprites);
+            de.selected_sprite_uuids = vec![sprite.uuid];
+            de.latest_sprite_settings = utils::bytes_to_hex_string(&sprite.settings);
+            de.latest_sprite_object_id = sprite.object_id;
+        }
+        ClickThroughTarget::BgTile(which_bg, map_index) => {
+            let layer = match which_bg {
+                1 => CurrentLayer::BG1,
+                2 => CurrentLayer::BG2,
+                _ => CurrentLayer::BG3
+            };
+            de.change_layer(layer);
+            de.bg_sel_data.selected_map_indexes = vec![map_index];
+            de.bg_sel_data.selection_width = 1;
+            de.bg_sel_data.selection_height = 1;
+        }
+    }
+    log_write(format!("Clicked through to layer {:?}",de.display_settings.current_layer), LogLevel::Log);
+    de.graphics_update_needed = true;
+}
+
+fn find_click_through_target(de: &DisplayEngine, top_left: Pos2, pointer_pos: Pos2, current: CurrentLayer) -> Option<ClickThroughTarget> {
+    let local_pos = pointer_pos - top_left;
+    if de.display_settings.show_triggers && current != CurrentLayer::Triggers {
+        if let Some(area) = de.loaded_map.get_area() {
+            let found = area.triggers.iter().rev()
+                .find(|trigger| trigger.get_rect(top_left, TILE_WIDTH_PX, TILE_HEIGHT_PX).contains(pointer_pos));
+            if let Some(trigger) = found {
+                return Some(ClickThroughTarget::Trigger(trigger.uuid));
+            }
+        }
+    }
+    if de.display_settings.show_col && current != CurrentLayer::Collision {
+        let col_bg = de.loaded_map.get_bg_with_colz().and_then(|which_bg| get_display_layer(de, which_bg));
+        if let Some(bg) = col_bg {
+            if let (Some(info), Some(col)) = (bg.get_info(), bg.get_colz()) {
+                let col_index = local_pos_to_col_index(&local_pos, info.layer_width as u32) as usize;
+                if col.col_tiles.get(col_index).is_some_and(|tile| *tile != 0x00) {
+                    return Some(ClickThroughTarget::Collision);
+                }
+            }
+        }
+    }
+    if de.display_settings.show_sprites && current != CurrentLayer::Sprites {
+        // Last drawn is on top
+        let found = de.level_sprites.iter().rev().find(|sprite| {
+            let sprite_pos = top_left + Vec2::new(sprite.x_position as f32 * TILE_WIDTH_PX, sprite.y_position as f32 * TILE_HEIGHT_PX);
+            Rect::from_min_size(sprite_pos, SPRITE_RECT).contains(pointer_pos)
+        });
+        if let Some(sprite) = found {
+            return Some(ClickThroughTarget::Sprite(sprite.clone()));
+        }
+    }
+    // Front to back
+    for which_bg in de.loaded_map.bg_draw_order().into_iter().rev() {
+        let (shown, layer) = match which_bg {
+            1 => (de.display_settings.show_bg1, CurrentLayer::BG1),
+            2 => (de.display_settings.show_bg2, CurrentLayer::BG2),
+            _ => (de.display_settings.show_bg3, CurrentLayer::BG3)
+        };
+        if !shown || current == layer {
+            continue;
+        }
+        let Some(bg) = get_display_layer(de, which_bg) else { continue };
+        let (Some(info), Some(map_tiles)) = (bg.get_info(), bg.get_mpbz()) else { continue };
+        // Same offset as draw_background
+        let layer_pos = local_pos + Vec2::new(info.x_offset_px as f32, info.y_offset_px as f32);
+        if layer_pos.x < 0.0 || layer_pos.y < 0.0 {
+            continue;
+        }
+        let tile_x = (layer_pos.x / TILE_WIDTH_PX) as u32;
+        let tile_y = (layer_pos.y / TILE_HEIGHT_PX) as u32;
+        if tile_x >= info.layer_width as u32 || tile_y >= info.layer_height as u32 {
+            continue;
+        }
+        let map_index = tile_y * (info.layer_width as u32) + tile_x;
+        if map_tiles.get_tile(map_index).tile_id != 0 {
+            return Some(ClickThroughTarget::BgTile(which_bg, map_index));
+        }
+    }
+    None
+}
+
+fn get_display_layer(de: &DisplayEngine, which_bg: u8) -> Option<&BackgroundData> {
+    match which_bg {
+        1 => de.bg_layer_1.as_ref(),
+        2 => de.bg_layer_2.as_ref(),
+        3 => de.bg_layer_3.as_ref(),
+        _ => None
+    }
+}
+
+/// Tiles between ruler labels, so they don't crowd at small tile sizes
+fn ruler_label_step(tile_px: f32) -> i64 {
+    let mut step: i64 = 2;
+    while (step as f32) * tile_px < RULER_LABEL_SPACING {
+        step *= 2;
+    }
+    step
+}
+
+/// Tile coordinates along the top and left of the grid, following the scroll
+///
+/// grid_origin is where the ScrollArea starts on screen, with the rulers in
+/// the space above and left of it. viewport is the scrolled to part of the grid
+pub fn draw_rulers(ui: &egui::Ui, panel_rect: Rect, grid_origin: Pos2, viewport: &Rect, base: NumberBase) {
+    puffin::profile_function!();
+    // Zoom would change this
+    let tile_px = TILE_WIDTH_PX;
+    let step = ruler_label_step(tile_px);
+    let fill = ui.visuals().extreme_bg_color;
+    let text_color = ui.visuals().weak_text_color();
+    let tick_stroke = Stroke::new(1.0, text_color);
+    let top = Rect::from_min_max(Pos2::new(grid_origin.x, panel_rect.top()), Pos2::new(panel_rect.right(), grid_origin.y));
+    let left = Rect::from_min_max(Pos2::new(panel_rect.left(), grid_origin.y), Pos2::new(grid_origin.x, panel_rect.bottom()));
+    let corner = Rect::from_min_max(panel_rect.min, grid_origin);
+    ui.painter().rect_filled(corner.union(top).union(left), 0.0, fill);
+    let tick_len = |tile: i64| if tile % step == 0 { RULER_THICKNESS } else if tile % 2 == 0 { 6.0 } else { 3.0 };
+    let top_painter = ui.painter().with_clip_rect(top);
+    let first_x = (viewport.left() / tile_px).floor().max(0.0) as i64;
+    let last_x = (viewport.right() / tile_px).ceil() as i64;
+    for tile in first_x..=last_x {
+        let x = grid_origin.x + tile as f32 * tile_px - viewport.left();
+        top_painter.line_segment([Pos2::new(x, top.bottom() - tick_len(tile)), Pos2::new(x, top.bottom())], tick_stroke);
+        if tile % step == 0 {
+            top_painter.text(Pos2::new(x + 2.0, top.top() + 2.0), Align2::LEFT_TOP, base.fmt_num(tile, 0), RULER_FONT, text_color);
+        }
+    }
+    let left_painter = ui.painter().with_clip_rect(left);
+    let first_y = (viewport.top() / tile_px).floor().max(0.0) as i64;
+    let last_y = (viewport.bottom() / tile_px).ceil() as i64;
+    for tile in first_y..=last_y {
+        let y = grid_origin.y + tile as f32 * tile_px - viewport.top();
+        left_painter.line_segment([Pos2::new(left.right() - tick_len(tile), y), Pos2::new(left.right(), y)], tick_stroke);
+        if tile % step == 0 {
+            left_painter.text(Pos2::new(left.left() + 2.0, y + 2.0), Align2::LEFT_TOP, base.fmt_num(tile, 0), RULER_FONT, text_color);
+        }
+    }
+}
+
+/// How long jumps and keyboard scrolling take to land, in seconds
+pub const GRID_SCROLL_SECS: f64 = 0.3;
+
+/// Keys that scroll the main grid, see grid_scroll_target
+pub const GRID_SCROLL_KEYS: [(Modifiers, Key); 8] = [
+    (Modifiers::NONE, Key::PageUp), (Modifiers::NONE, Key::PageDown),
+    (Modifiers::NONE, Key::Home), (Modifiers::NONE, Key::End),
+    (Modifiers::CTRL, Key::ArrowUp), (Modifiers::CTRL, Key::ArrowDown),
+    (Modifiers::CTRL, Key::ArrowLeft), (Modifiers::CTRL, Key::ArrowRight)
+];
+
+/// An eased move of the main grid's scroll offset, so a jump doesn't lose
+/// track of where the view was. Any scrolling by the user cancels it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridScroll {
+    pub from: Vec2,
+    pub to: Vec2,
+    pub start_time: f64,
+    /// 0 to jump straight there
+    pub duration: f64
+}
+impl GridScroll {
+    /// The offset at time, and if it has landed
+    pub fn offset_at(&self, time: f64) -> (Vec2, bool) {
+        if self.duration <= 0.0 {
+            return (self.to, true);
+        }
+        let t = ((time - self.start_time) / self.duration).clamp(0.0, 1.0) as f32;
+        (self.from + (self.to - self.from) * easing::cubic_in_out(t), t >= 1.0)
+    }
+}
+
+/// Where a key from GRID_SCROLL_KEYS moves the offset. The page keys and
+/// arrows move a screen, Home and End go to the top and bottom
+pub fn grid_scroll_target(key: Key, offset: Vec2, page: Vec2, max_offset: Vec2) -> Option<Vec2> {
+    let target = match key {
+        Key::PageUp | Key::ArrowUp => offset - Vec2::new(0.0, page.y),
+        Key::PageDown | Key::ArrowDown => offset + Vec2::new(0.0, page.y),
+        Key::ArrowLeft => offset - Vec2::new(page.x, 0.0),
+        Key::ArrowRight => offset + Vec2::new(page.x, 0.0),
+        Key::Home => Vec2::new(offset.x, 0.0),
+        Key::End => Vec2::new(offset.x, max_offset.y),
+        _ => return None
+    };
+    Some(target.clamp(Vec2::ZERO, max_offset.max(Vec2::ZERO)))
+}
+
+/// One mesh for the whole map, blending between each GRAD color
+fn draw_gradient(ui: &mut egui::Ui, de: &mut DisplayEngine) {
+    puffin::profile_function!();
+    let (width, height) = de.loaded_map.get_dimensions();
+    if width == 0 || height == 0 {
+        return;
+    }
+    let map_rect = Rect::from_min_size(ui.min_rect().min,
+        Vec2::new(width as f32 * TILE_WIDTH_PX, height as f32 * TILE_HEIGHT_PX));
+    let mut colors: Vec<Color32> = de.loaded_map.get_grad().map(|grad| grad.get_colors()).unwrap_or_default();
+    if colors.is_empty() {
+        // Backdrop is the first color of the first BG palette
+        colors.push(de.bg_palettes[0].colors[0].color);
+    }
+    if colors.len() == 1 {
+        // Needs a top and bottom
+        colors.push(colors[0]);
+    }
+    let band_height = map_rect.height() / (colors.len() - 1) as f32;
+    let mut mesh = Mesh::default();
+    for (index, color) in colors.iter().enumerate() {
+        let y = map_rect.top() + band_height * index as f32;
+        mesh.colored_vertex(Pos2::new(map_rect.left(), y), *color);
+        mesh.colored_vertex(Pos2::new(map_rect.right(), y), *color);
+        if index > 0 {
+            let top_left = (index as u32 - 1) * 2;
+            mesh.add_triangle(top_left, top_left + 1, top_left + 2);
+            mesh.add_triangle(top_left + 1, top_left + 3, top_left + 2);
+        }
+    }
+    ui.painter().with_clip_rect(map_rect).add(mesh);
+}
+
+fn draw_grid_background(ui: &mut egui::Ui, de: &DisplayEngine, vrect: &Rect) {
+    puffin::profile_function!();
+    let (width, height) = de.loaded_map.get_dimensions();
+    if width == 0 || height == 0 {
+        return;
+    }
+    let top_left = ui.min_rect().min;
+    let map_rect = Rect::from_min_size(top_left,
+        Vec2::new(width as f32 * TILE_WIDTH_PX, height as f32 * TILE_HEIGHT_PX));
+    let painter = ui.painter().with_clip_rect(map_rect);
+    match de.display_settings.grid_background {
+        GridBackground::Panel => {}
+        GridBackground::Solid => {
+            let [r, g, b] = de.display_settings.grid_background_color;
+            painter.rect_filled(map_rect, 0.0, Color32::from_rgb(r, g, b));
+        }
+        GridBackground::Checkerboard => {
+            painter.rect_filled(map_rect, 0.0, Color32::from_gray(0xCC));
+            // Only the visible squares, one per tile so they line up with the grid
+            let first_x = (vrect.left() / TILE_WIDTH_PX).floor().max(0.0) as u32;
+            let last_x = ((vrect.right() / TILE_WIDTH_PX).ceil().max(0.0) as u32).min(width as u32);
+            let first_y = (vrect.top() / TILE_HEIGHT_PX).floor().max(0.0) as u32;
+            let last_y = ((vrect.bottom() / TILE_HEIGHT_PX).ceil().max(0.0) as u32).min(height as u32);
+            let mut mesh = Mesh::default();
+            for y in first_y..last_y {
+                for x in first_x..last_x {
+                    if (x + y) % 2 == 1 {
+                        let tile_pos = top_left + Vec2::new(x as f32 * TILE_WIDTH_PX, y as f32 * TILE_HEIGHT_PX);
+                        mesh.add_colored_rect(Rect::from_min_size(tile_pos, TILE_RECT), Color32::from_gray(0x99));
+                    }
+                }
+            }
+            painter.add(mesh);
+        }
+    }
 }
 
 fn draw_collision_layer(ui: &mut egui::Ui, de: &mut DisplayEngine,vrect: &Rect) {
     puffin::profile_function!();
     let Some(bg_with_col) = de.loaded_map.get_bg_with_colz() else { return };
+    let col_receives_input = receives_input(ui, de, CurrentLayer::Collision);
+    let col_right_click_free = right_click_free(de);
+    let col_colors = de.display_settings.theme_colors.collision;
     let Some(bg) = de.loaded_map.get_background(bg_with_col) else { return };
     let Some(info_c) = bg.get_info() else { return };
     let grid_width = info_c.layer_width as u32;
@@ -68,7 +830,7 @@ fn draw_collision_layer(ui: &mut egui::Ui, de: &mut DisplayEngine,vrect: &Rect)
     // Start!
     let mut col_index: u32 = 0;
     // Include the image cached, and tint it light blue to show it's different
-    let image: Image<'_> = egui::Image::new(egui::include_image!("../../assets/collision_coin.png")).tint(Color32::LIGHT_BLUE);
+    let image: Image<'_> = egui::Image::new(egui::include_image!("../../assets/collision_coin.png")).tint(de.display_settings.theme_colors.collision_coin);
     for col_u8 in &mut col.col_tiles {
         if *col_u8 != 0 { // 0x0 = Nothing, skip render
             let painter: &Painter = ui.painter();
@@ -98,38 +860,39 @@ fn draw_collision_layer(ui: &mut egui::Ui, de: &mut DisplayEngine,vrect: &Rect)
             let tile_x_px: f32 = tile_x * (TILE_WIDTH_PX*2.0);
             let tile_y_px: f32 = tile_y * (TILE_HEIGHT_PX*2.0);
             let rect: Rect = Rect::from_min_size(top_left + Vec2::new(tile_x_px, tile_y_px), colz::COLLISION_SQUARE);
-            let col_bg_color = colz::COLLISION_BG_COLOR;
             if *col_u8 == 0x1 { // Square, 95% of non-empty colliders (I checked)
-                painter.rect_filled(rect, 0.0, col_bg_color);
-                painter.rect_stroke(rect, 0.0, Stroke::new(1.0, colz::COLLISION_OUTLINE_COLOR), egui::StrokeKind::Middle);
+                painter.rect_filled(rect, 0.0, col_colors.solid);
+                painter.rect_stroke(rect, 0.0, Stroke::new(1.0, col_colors.outline), egui::StrokeKind::Middle);
             } else if *col_u8 == 0x1A { // 0x1A is the Collision coin
                 image.paint_at(ui, rect);
             } else {
-                draw_collision(painter, &rect, *col_u8);
-            }
-            // If it overlaps the deletion rectangle... delete it
-            if
-                *col_u8 != 0x00
-                && de.col_selector_status.delete_under
-                && de.col_selector_status.selecting_rect.intersects(rect)
-            {
-                //let _ = de.loaded_map.set_col_tile(bg_with_col, col_index as u16, 0x00);
-                *col_u8 = 0x00;
-                de.graphics_update_needed = true;
-                de.unsaved_changes = true;
+                draw_collision(painter, &rect, *col_u8, &col_colors);
             }
         }
         col_index += 1;
     }
-    if de.col_selector_status.delete_under {
-        // Now that it deleted what it should, disable it all
-        de.col_selector_status.delete_under = false;
-        de.col_selector_status.dragging = false;
-        de.col_selector_status.selecting_rect = Rect::NOTHING;
-    }
     // COLZ Interactivity //
-    if de.display_settings.current_layer == CurrentLayer::Collision {
+    if col_receives_input {
         let col_sense_resp: Response = ui.interact(true_rect, egui::Id::new("col_tile_click"), egui::Sense::all());
+        // Fill Rect from the grid menu, waiting on the second corner
+        if let Some(anchor) = de.col_fill_anchor {
+            if ui.input(|i| i.key_pressed(Key::Escape)) {
+                log_write("Cancelled collision fill", LogLevel::Log);
+                de.col_fill_anchor = None;
+            } else if let Some(hover_pos) = col_sense_resp.hover_pos() {
+                let col_width = grid_width / 2;
+                let hovered = local_pos_to_col_index(&(hover_pos - true_rect.min), grid_width);
+                let corner_rect = |index: u32| Rect::from_min_size(
+                    top_left + Vec2::new((index % col_width) as f32, (index / col_width) as f32) * colz::COLLISION_SQUARE, colz::COLLISION_SQUARE);
+                let fill_rect = corner_rect(anchor as u32).union(corner_rect(hovered));
+                ui.painter().rect_filled(fill_rect, 0.0, de.display_settings.theme_colors.bg_selection_fill);
+                let fill_label = de.display_settings.number_base.fmt_num(de.col_tile_to_place as u32, 2);
+                egui::show_tooltip(ui.ctx(), ui.layer_id(), egui::Id::new("col_fill_rect"), |ui| {
+                    ui.label(format!("Click to fill with {fill_label}"));
+                    ui.label("Escape to cancel");
+                });
+            }
+        }
         // Do it in three separate ones to avoid repeated input checking that won't be used
         if col_sense_resp.clicked() {
             // Add a new tile 
@@ -140,11 +903,16 @@ fn draw_collision_layer(ui: &mut egui::Ui, de: &mut DisplayEngine,vrect: &Rect)
                     log_write(format!("Index out of bounds: {} >= {}",tile_index,col.col_tiles.len()), LogLevel::Error);
                     return;
                 }
-                de.loaded_map.set_col_tile(bg_with_col, tile_index as u16, de.col_tile_to_place);
+                if let Some(anchor) = de.col_fill_anchor.take() {
+                    let changed = col.fill_rect(anchor, tile_index as usize, grid_width as u16, de.col_tile_to_place);
+                    log_write(format!("Filled {} collision tiles with 0x{:X}",changed,de.col_tile_to_place), LogLevel::Log);
+                } else {
+                    de.loaded_map.set_col_tile(bg_with_col, tile_index as u16, de.col_tile_to_place);
+                }
                 de.graphics_update_needed = true;
-                de.unsaved_changes = true;
+                de.map_edited();
             }
-        } else if col_sense_resp.secondary_clicked() {
+        } else if col_sense_resp.secondary_clicked() && col_right_click_free {
             // Clear the tile
             if let Some(pointer_pos) = ui.input(|i| i.pointer.latest_pos()) {
                 let local_pos = pointer_pos - true_rect.min;
@@ -156,7 +924,7 @@ fn draw_collision_layer(ui: &mut egui::Ui, de: &mut DisplayEngine,vrect: &Rect)
                 // 0x00 is empty
                 de.loaded_map.set_col_tile(bg_with_col, tile_index as u16, 0x00);
                 de.graphics_update_needed = true;
-                de.unsaved_changes = true;
+                de.map_edited();
             }
         } else if col_sense_resp.middle_clicked() {
             // Copy the tile (and show info)
@@ -181,6 +949,7 @@ fn draw_collision_layer(ui: &mut egui::Ui, de: &mut DisplayEngine,vrect: &Rect)
                 return;
             }
             de.col_selector_status.dragging = true;
+            de.col_selector_status.cancelled = false;
             let cur_pos: Pos2 = ui.ctx().pointer_interact_pos().expect("Failed to get pointer interaction position");
             de.col_selector_status.start_pos = cur_pos;
             de.col_selector_status.end_pos = cur_pos; // Starts as empty square
@@ -194,20 +963,48 @@ fn draw_collision_layer(ui: &mut egui::Ui, de: &mut DisplayEngine,vrect: &Rect)
                 log_write("Failed to get pointer_interact_pos in col .dragged", LogLevel::Error);
                 return;
             };
+            if de.col_selector_status.cancelled {
+                return;
+            }
+            if ui.input(|i| i.key_pressed(Key::Escape)) {
+                de.col_selector_status.cancelled = true;
+                de.col_selector_status.selecting_rect = Rect::NOTHING;
+                return;
+            }
             de.col_selector_status.end_pos = cur_pos;
             // Draw
             let drag_rect: Rect = Rect::from_two_pos(de.col_selector_status.start_pos, de.col_selector_status.end_pos);
-            ui.painter().rect_filled(drag_rect, 0.0, BG_SELECTION_FILL);
+            ui.painter().rect_filled(drag_rect, 0.0, de.display_settings.theme_colors.bg_selection_fill);
             // Store
             de.col_selector_status.selecting_rect = drag_rect;
+            de.col_selector_status.pending_count = de.loaded_map.get_background(bg_with_col)
+                .and_then(|bg| bg.get_colz_mut())
+                .map_or(0, |col| col.indexes_in_rect(drag_rect.translate(-true_rect.min.to_vec2()), grid_width as u16).len());
+            let pending_count = de.col_selector_status.pending_count;
+            egui::show_tooltip(ui.ctx(), ui.layer_id(), egui::Id::new("col_delete_count"), |ui| {
+                ui.label(format!("Release to delete {} collision tile{}",pending_count,if pending_count == 1 { "" } else { "s" }));
+                ui.label("Escape to cancel");
+            });
         }
         if col_sense_resp.drag_stopped() {
-            de.col_selector_status.dragging = false;
-            de.col_selector_status.start_pos = Pos2::new(0.0, 0.0);
-            de.col_selector_status.end_pos = Pos2::new(0.0, 0.0);
-            de.col_selector_status.delete_under = true;
-            // Set this once deletion done, so you can do the deletions
-            //de.col_selector_status.selecting_rect = Rect::NOTHING;
+            let drag_rect = de.col_selector_status.selecting_rect;
+            let cancelled = de.col_selector_status.cancelled;
+            de.col_selector_status = ColDragStatus::default();
+            if cancelled || drag_rect == Rect::NOTHING {
+                return;
+            }
+            // All at once, so it's a single undo step
+            let Some(col) = de.loaded_map.get_background(bg_with_col).and_then(|bg| bg.get_colz_mut()) else { return };
+            let indexes = col.indexes_in_rect(drag_rect.translate(-true_rect.min.to_vec2()), grid_width as u16);
+            if indexes.is_empty() {
+                return;
+            }
+            for index in &indexes {
+                col.col_tiles[*index] = 0x00;
+            }
+            log_write(format!("Deleted {} collision tiles",indexes.len()), LogLevel::Log);
+            de.graphics_update_needed = true;
+            de.map_edited();
         }
     }
 }
@@ -219,13 +1016,13 @@ fn draw_triggers(ui: &mut egui::Ui, de: &mut DisplayEngine) {
     for trigger in &area.triggers {
         let rect = trigger.get_rect(top_left_screen, TILE_WIDTH_PX, TILE_HEIGHT_PX);
         if de.trigger_settings.selected_uuid == trigger.uuid {
-            ui.painter().rect_filled(rect, 0.0, AREA_RECT_COLOR_SELECTED);
+            ui.painter().rect_filled(rect, 0.0, de.display_settings.theme_colors.trigger_selected);
         } else {
-            ui.painter().rect_filled(rect, 0.0, AREA_RECT_COLOR);
+            ui.painter().rect_filled(rect, 0.0, de.display_settings.theme_colors.trigger);
         }
     }
 
-    if de.display_settings.current_layer == CurrentLayer::Triggers {
+    if receives_input(ui, de, CurrentLayer::Triggers) {
         let click_response = ui.interact(ui.min_rect(), egui::Id::new("AREA_click"), egui::Sense::click());
         if click_response.clicked() {
             if let Some(pointer_pos) = ui.input(|i| i.pointer.latest_pos()) {
@@ -309,6 +1106,8 @@ fn draw_entrances(ui: &mut egui::Ui, de: &mut DisplayEngine) {
             &map_index,&maps_count), LogLevel::Fatal);
         return;
     }
+    let overlapping = overlapping_warp_uuids(de, map_index);
+    let colors = &de.display_settings.theme_colors;
     let entrances = &de.loaded_course.level_map_data[map_index].map_entrances;
     for entrance in entrances {
         let x_no_offset = (entrance.entrance_x as f32) * TILE_WIDTH_PX;
@@ -317,12 +1116,15 @@ fn draw_entrances(ui: &mut egui::Ui, de: &mut DisplayEngine) {
         let rect = Rect::from_min_size(true_pos, SPRITE_RECT);
 
         if entrance.uuid == de.course_settings.selected_entrance.unwrap_or(Uuid::nil()) {
-            ui.painter().rect_filled(rect, 2.0, Color32::from_rgba_unmultiplied(0x00, 0xff, 0, 0xA0));
+            ui.painter().rect_filled(rect, 2.0, ThemeColors::with_alpha(colors.entrance, 0xA0));
             ui.painter().rect_stroke(rect, 2.0, Stroke::new(2.0, Color32::WHITE), egui::StrokeKind::Middle);
         } else {
-            ui.painter().rect_filled(rect, 2.0, Color32::from_rgba_unmultiplied(0x00, 0xff, 0, 0x40));
+            ui.painter().rect_filled(rect, 2.0, ThemeColors::with_alpha(colors.entrance, 0x40));
             ui.painter().rect_stroke(rect, 2.0, Stroke::new(1.0, Color32::WHITE), egui::StrokeKind::Middle);
         }
+        if overlapping.contains(&entrance.uuid) {
+            draw_warning_stripes(ui.painter(), rect, colors.warp_overlap);
+        }
     }
 }
 
@@ -338,6 +1140,8 @@ fn draw_exits(ui: &mut egui::Ui, de: &mut DisplayEngine) {
             &map_index,&maps_count), LogLevel::Fatal);
         return;
     }
+    let overlapping = overlapping_warp_uuids(de, map_index);
+    let colors = &de.display_settings.theme_colors;
     let exits = &de.loaded_course.level_map_data[map_index].map_exits;
     for exit in exits {
         let x_no_offset = (exit.exit_x as f32) * TILE_WIDTH_PX;
@@ -345,103 +1149,175 @@ fn draw_exits(ui: &mut egui::Ui, de: &mut DisplayEngine) {
         let true_pos: Pos2 = top_left + Vec2::new(x_no_offset, y_no_offset);
         let rect = Rect::from_min_size(true_pos, SPRITE_RECT);
         if exit.uuid == de.course_settings.selected_exit.unwrap_or(Uuid::nil()) {
-            ui.painter().rect_filled(rect, 2.0, Color32::from_rgba_unmultiplied(0xff, 0, 0, 0xA0));
+            ui.painter().rect_filled(rect, 2.0, ThemeColors::with_alpha(colors.exit, 0xA0));
             ui.painter().rect_stroke(rect, 2.0, Stroke::new(2.0, Color32::WHITE), egui::StrokeKind::Middle);
         } else {
-            ui.painter().rect_filled(rect, 2.0, Color32::from_rgba_unmultiplied(0xff, 0, 0, 0x40));
+            ui.painter().rect_filled(rect, 2.0, ThemeColors::with_alpha(colors.exit, 0x40));
             ui.painter().rect_stroke(rect, 2.0, Stroke::new(1.0, Color32::WHITE), egui::StrokeKind::Middle);
         }
+        if overlapping.contains(&exit.uuid) {
+            draw_warning_stripes(ui.painter(), rect, colors.warp_overlap);
+        }
+        // Leads to the Entrance selected in Course Settings
+        if de.course_settings.selected_entrance.is_some_and(|uuid| uuid == exit.target_map_entrance) {
+            ui.painter().rect_stroke(rect.expand(2.0), 2.0, Stroke::new(2.0, colors.exit_target), egui::StrokeKind::Outside);
+        }
+    }
+}
+
+/// Entrances and Exits that share tiles with another of the same kind
+fn overlapping_warp_uuids(de: &DisplayEngine, map_index: usize) -> Vec<Uuid> {
+    de.loaded_course.level_map_data[map_index]
+        .find_overlaps(map_index, de.course_settings.allow_stacked_exit_types).iter()
+        .flat_map(|overlap| [overlap.first, overlap.second])
+        .collect()
+}
+
+/// Diagonal lines, so stacked boxes stand out
+fn draw_warning_stripes(painter: &Painter, rect: Rect, color: Color32) {
+    let clipped = painter.with_clip_rect(rect);
+    let stroke = Stroke::new(2.0, color);
+    let mut offset = 0.0;
+    while offset < rect.width() + rect.height() {
+        clipped.line_segment([
+            rect.left_top() + Vec2::new(offset, 0.0),
+            rect.left_top() + Vec2::new(offset - rect.height(), rect.height())
+        ], stroke);
+        offset += 6.0;
     }
+    painter.rect_stroke(rect, 2.0, Stroke::new(1.0, color), egui::StrokeKind::Middle);
 }
 
 const PATH_SELECTION_DISTANCE: f32 = 20.0;
 
+/// Where a point and its angle line go, relative to the top left of the grid
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathPointGeometry {
+    pub uuid: Uuid,
+    pub pos: Vec2,
+    /// End of the angle line, for straight points
+    pub angle_end: Option<Vec2>
+}
+
+/// A curve from one point to the next, as a polyline
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathCurveGeometry {
+    /// The point the curve starts at
+    pub uuid: Uuid,
+    pub polyline: Vec<Vec2>
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PathLineGeometry {
+    pub uuid: Uuid,
+    pub points: Vec<PathPointGeometry>,
+    pub curves: Vec<PathCurveGeometry>
+}
+
+/// Everything draw_paths needs, built when the path data changes rather than every frame
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PathGeometry {
+    pub lines: Vec<PathLineGeometry>
+}
+impl PathGeometry {
+    pub fn build(path_database: &PathDatabase, tables: Option<&Arm9Tables>) -> Self {
+        puffin::profile_function!();
+        let lines = path_database.lines.iter().map(|line| PathLineGeometry {
+            uuid: line.uuid,
+            points: line.points.iter().map(|point| point_geometry(point, tables)).collect(),
+            curves: line.points.windows(2)
+                // A negative distance is a curve to the next point
+                .filter(|pair| pair[0].distance < 0)
+                .map(|pair| curve_geometry(&pair[0], &pair[1]))
+                .collect()
+        }).collect();
+        Self { lines }
+    }
+}
+
+fn point_geometry(point: &PathPoint, tables: Option<&Arm9Tables>) -> PathPointGeometry {
+    let pos: Vec2 = Vec2::new(
+        ((point.x_fine >> 15) as f32) * TILE_WIDTH_PX,
+        ((point.y_fine >> 15) as f32) * TILE_HEIGHT_PX
+    );
+    // Parsed on load, so a failure there was already reported
+    let angle_end = match tables {
+        Some(tables) if point.distance > 0 => {
+            let test_val = tables.sin_cos(point.angle as u16);
+            let x_offset = ((test_val.x as i32) * (point.distance as i32)) >> 12; // Note: this includes the tile width
+            let y_offset = ((test_val.y as i32) * (point.distance as i32)) >> 12; // This will need changing once zoom is added
+            Some(pos + Vec2::new(x_offset as f32, y_offset as f32))
+        }
+        // Point distance is negative
+        // Calculations done here: 02054b34
+        _ => None
+    };
+    PathPointGeometry { uuid: point.uuid, pos, angle_end }
+}
+
+fn curve_geometry(cur_point: &PathPoint, next_point: &PathPoint) -> PathCurveGeometry {
+    let (circle_point_fine,radius,rads) = utils::get_curve_fine(cur_point, next_point);
+    let circle_radius = (radius >> 12) as f32;
+    let circle_vec: Vec2 = Vec2::new(
+        ((circle_point_fine.x as u32 >> 15) as f32) * TILE_WIDTH_PX,
+        ((circle_point_fine.y as u32 >> 15) as f32) * TILE_HEIGHT_PX
+    );
+    let segments: usize = 5;
+    let mut polyline: Vec<Vec2> = vec![];
+    const RAD_UNIT: f32 = PI / 2.0; // 90 degrees in Radians
+    for i in 0..=segments {
+        // Divide into segments, then do radian offset
+        let angle = ((i as f32) / (segments as f32) * RAD_UNIT)+rads;
+        let x = circle_vec.x + circle_radius * angle.cos();
+        let y = circle_vec.y - circle_radius * angle.sin();
+        polyline.push(Vec2 { x, y });
+    }
+    PathCurveGeometry { uuid: cur_point.uuid, polyline }
+}
+
 fn draw_paths(ui: &mut egui::Ui, de: &mut DisplayEngine) {
     puffin::profile_function!();
-    let arm9 = de.loaded_arm9.as_ref().expect("ARM9 must exist");
     let top_left: Pos2 = ui.min_rect().min;
+    let colors = &de.display_settings.theme_colors;
     if let Some(path_database) = &de.path_data {
-        for line in &path_database.lines {
-            let mut line_points: Vec<Pos2> = Vec::new();
+        for line in &de.path_geometry.lines {
             let path_selected = de.path_settings.selected_line == line.uuid;
             for point in &line.points {
-                let placement_vec: Vec2 = Vec2::new(
-                    ((point.x_fine >> 15) as f32) * TILE_WIDTH_PX,
-                    ((point.y_fine >> 15) as f32) * TILE_HEIGHT_PX
-                );
-                let true_pos: Pos2 = top_left + placement_vec;
-                line_points.push(true_pos);
+                let true_pos: Pos2 = top_left + point.pos;
                 let rect = Rect::from_min_size(true_pos, Vec2 { x: 6.0, y: 6.0 });
                 let point_selected = de.path_settings.selected_point == point.uuid;
                 if point_selected {
-                    ui.painter().rect_filled(rect, 0.0, Color32::ORANGE);
+                    ui.painter().rect_filled(rect, 0.0, colors.path_point_selected);
                 }
                 ui.painter().rect_stroke(rect, 0.0,
                     Stroke::new(1.0,
-                        if path_selected { Color32::LIGHT_RED } else { Color32::RED }
+                        if path_selected { colors.path_selected } else { colors.path }
                     ),
                     egui::StrokeKind::Outside
                 );
-                if point.distance >= 0 && point.distance != 0 {
-                    let test_val = utils::get_sin_cos_table_value(arm9, point.angle as u16,de.game_version);
-                    let x_offset = ((test_val.x as i32) * (point.distance as i32)) >> 12; // Note: this includes the tile width
-                    let y_offset = ((test_val.y as i32) * (point.distance as i32)) >> 12; // This will need changing once zoom is added
-                    //println!("test_val: {:?}", test_val);
-                    let end_pos: Pos2 = Pos2::new(true_pos.x + (x_offset as f32), true_pos.y + (y_offset as f32));//
+                if let Some(angle_end) = point.angle_end {
                     let stroke = Stroke::new(
                         if point_selected { 2.0 } else { 1.0 },
-                        if point_selected {Color32::GREEN} else { Color32::RED }
+                        if point_selected { colors.path_point_active } else { colors.path }
                     );
-                    ui.painter().line(vec![true_pos,end_pos], stroke);
-                } else {
-                    // Point distance is negative
-                    // Calculations done here: 02054b34
+                    ui.painter().line(vec![true_pos,top_left + angle_end], stroke);
                 }
             }
             // Circles
-            for (i, cur_point) in line.points.iter().enumerate() {
-                if i == line.points.len() - 1 {
-                    // Skip the last one
-                    break;
-                }
-                if cur_point.distance >= 0 {
-                    // It's a straight line, skip
-                    continue;
-                }
-                // Copy
-                let next_point: PathPoint = line.points[i+1];
-                let (circle_point_fine,radius,rads) = utils::get_curve_fine(cur_point, &next_point);
-                let circle_radius = (radius >> 12) as f32;
-                let circle_vec: Vec2 = Vec2::new(
-                    ((circle_point_fine.x as u32 >> 15) as f32) * TILE_WIDTH_PX,
-                    ((circle_point_fine.y as u32 >> 15) as f32) * TILE_HEIGHT_PX
-                );
-                let circle_pos: Pos2 = top_left + circle_vec;
-                let point_selected = de.path_settings.selected_point == cur_point.uuid;
-                // This is the general circle
-                //ui.painter().circle_stroke(circle_pos, circle_radius, egui::Stroke::new(1.0, Color32::from_rgba_unmultiplied(0xff, 0, 0, 0x05)));
-                
+            for curve in &line.curves {
+                let point_selected = de.path_settings.selected_point == curve.uuid;
                 let circle_stroke = egui::Stroke::new(if point_selected { 2.0 } else { 1.0 },
                 if point_selected {
-                    Color32::GREEN
+                    colors.path_point_active
                 } else {
-                    Color32::from_rgba_unmultiplied(0xff, 0, 0, 0x55)
+                    ThemeColors::with_alpha(colors.path, 0x55)
                 });
-                let segments: usize = 5;
-                let mut points: Vec<Pos2> = vec![];
-                const RAD_UNIT: f32 = PI / 2.0; // 90 degrees in Radians
-                for i in 0..=segments {
-                    // Divide into segments, then do radian offset
-                    let angle = ((i as f32) / (segments as f32) * RAD_UNIT)+rads;
-                    let x = circle_pos.x + circle_radius * angle.cos();
-                    let y = circle_pos.y - circle_radius * angle.sin();
-                    points.push(Pos2 { x, y });
-                }
+                let points: Vec<Pos2> = curve.polyline.iter().map(|offset| top_left + *offset).collect();
                 ui.painter().add(egui::Shape::line(points, circle_stroke));
             }
         }
         // Interactivity
-        if de.display_settings.current_layer == CurrentLayer::Paths {
+        if receives_input(ui, de, CurrentLayer::Paths) {
             let click_response = ui.interact(ui.min_rect(), egui::Id::new("PATH_click"), egui::Sense::click());
             if click_response.clicked() {
                 if let Some(pointer_pos) = ui.input(|i| i.pointer.latest_pos()) {
@@ -470,7 +1346,7 @@ fn draw_paths(ui: &mut egui::Ui, de: &mut DisplayEngine) {
                     }
                 }
             }
-            if click_response.secondary_clicked() && !de.path_settings.selected_line.is_nil() {
+            if click_response.secondary_clicked() && right_click_free(de) && !de.path_settings.selected_line.is_nil() {
                 if let Some(pointer_pos) = ui.input(|i| i.pointer.latest_pos()) {
                     let local_pos = pointer_pos - ui.min_rect().min;
                     let x_fine = ((local_pos.x / TILE_WIDTH_PX) as u32) << 15;
@@ -487,7 +1363,7 @@ fn draw_paths(ui: &mut egui::Ui, de: &mut DisplayEngine) {
                         l.points.push(p);
                         de.path_settings.selected_point = puuid;
                         de.graphics_update_needed = true;
-                        de.unsaved_changes = true;
+                        de.map_edited();
                     } else {
                         log_write("Failed to get PathLine for new PathPoint", LogLevel::Error);
                     }
@@ -497,14 +1373,49 @@ fn draw_paths(ui: &mut egui::Ui, de: &mut DisplayEngine) {
     }
 }
 
+fn draw_anchor_cross(painter: &Painter, anchor: Pos2) {
+    let stroke = Stroke::new(1.0, Color32::WHITE);
+    painter.line_segment([anchor - Vec2::new(3.0, 0.0), anchor + Vec2::new(3.0, 0.0)], stroke);
+    painter.line_segment([anchor - Vec2::new(0.0, 3.0), anchor + Vec2::new(0.0, 3.0)], stroke);
+}
+
+fn draw_sprite_range(painter: &Painter, sprite: &LevelSprite, sprite_rect: Rect, color: Color32) {
+    let Some(area) = SPRITE_RANGES.for_sprite(sprite.object_id).and_then(|range| range.area(sprite, sprite_rect)) else {
+        return;
+    };
+    let stroke = Stroke::new(1.5, color);
+    let fill = ThemeColors::with_alpha(color, 0x20);
+    match area {
+        SpriteRangeArea::Circle(center, radius) => {
+            painter.circle(center, radius, fill, stroke);
+        }
+        SpriteRangeArea::Rect(rect) => {
+            painter.rect(rect, 0.0, fill, stroke, egui::StrokeKind::Inside);
+        }
+    }
+}
+
 fn draw_sprites(ui: &mut egui::Ui, de: &mut DisplayEngine, vrect: &Rect) {
     puffin::profile_function!();
     let top_left: Pos2 = ui.min_rect().min;
     let mut update_map: bool = false;
     // If this always fires, it will block COLZ clicks
     let mut click_fallback_response: Option<Response> = Option::None;
-    if de.display_settings.current_layer == CurrentLayer::Sprites {
-        click_fallback_response = Some(ui.interact(ui.min_rect(), egui::Id::new("sprite_click_fallback"), egui::Sense::click()));
+    de.sprite_hover_tile = Option::None;
+    if receives_input(ui, de, CurrentLayer::Sprites) {
+        let fallback = ui.interact(ui.min_rect(), egui::Id::new("sprite_click_fallback"), egui::Sense::click());
+        // For placing with the keyboard
+        if let Some(hover_pos) = fallback.hover_pos() {
+            let local_pos = hover_pos - ui.min_rect().min;
+            de.sprite_hover_tile = Some(Pos2::new((local_pos.x/TILE_WIDTH_PX).floor(), (local_pos.y/TILE_HEIGHT_PX).floor()));
+            // Paste lands here, BG layers set their own
+            de.latest_square_pos_level_space = de.sprite_hover_tile.unwrap_or_default();
+        }
+        click_fallback_response = Some(fallback);
+    }
+    let mut issue_uuids: Vec<Uuid> = Vec::new();
+    if de.display_settings.highlight_sprite_issues {
+        issue_uuids = find_sprite_issues(&de.level_sprites).into_iter().flat_map(|issue| issue.uuids).collect();
     }
     // It's one way, don't mutable borrow
     let sprite_list: Vec<LevelSprite> = de.level_sprites.clone();
@@ -513,6 +1424,7 @@ fn draw_sprites(ui: &mut egui::Ui, de: &mut DisplayEngine, vrect: &Rect) {
             let leftmost_tile = vrect.left() / TILE_WIDTH_PX;
             let uppermost_tile = vrect.top() / TILE_HEIGHT_PX;
             de.loaded_map.move_sprite(level_sprite.uuid, leftmost_tile as u16 + 2, uppermost_tile as u16 + 2);
+            de.segment_baseline.mark_dirty();
             de.graphics_update_needed = true;
             // Cancel the update drawing
             return;
@@ -524,29 +1436,51 @@ fn draw_sprites(ui: &mut egui::Ui, de: &mut DisplayEngine, vrect: &Rect) {
         let true_pos: Pos2 = top_left + placement_vec;
         let rect = Rect::from_min_size(true_pos, SPRITE_RECT);
 
+        let is_selected = de.selected_sprite_uuids.contains(&level_sprite.uuid);
+        // Some objects anchor partway into their tile in-game
+        let render_offset = sprite_meta(level_sprite.object_id).map_or(Vec2::ZERO, |meta| meta.render_offset());
         let mut drawn_rects = draw_sprite(
-            ui, &rect, &level_sprite, de,8.0,
-            de.selected_sprite_uuids.contains(&level_sprite.uuid)
+            ui, &rect.translate(render_offset), &level_sprite, de,8.0,
+            is_selected
         );
+        let box_wanted = match de.display_settings.sprite_box_mode {
+            SpriteBoxMode::All => true,
+            SpriteBoxMode::Selected => is_selected,
+            SpriteBoxMode::Hidden => false,
+        };
         // No render for it, do square (or do it anyway)
-        if drawn_rects.is_empty() || de.display_settings.show_box_for_rendered {
+        if drawn_rects.is_empty() || box_wanted {
             // We want the source rect to be clickable too
             drawn_rects.push(rect);
 
             if de.selected_sprite_uuids.contains(&level_sprite.uuid) {
-                ui.painter().rect_filled(rect, 0.0, SPRITE_BG_COLOR_SELECTED);
+                ui.painter().rect_filled(rect, 0.0, de.display_settings.theme_colors.sprite_fill_selected);
             } else {
-                ui.painter().rect_filled(rect, 0.0, SPRITE_BG_COLOR);
+                ui.painter().rect_filled(rect, 0.0, de.display_settings.theme_colors.sprite_fill);
             }
             ui.painter().text(
                 true_pos, Align2::LEFT_TOP,
-                format!("{:02X}",level_sprite.object_id),
+                match de.display_settings.number_base {
+                    NumberBase::Hex => format!("{:02X}",level_sprite.object_id),
+                    NumberBase::Decimal => format!("{}",level_sprite.object_id)
+                },
                 FONT, Color32::WHITE
             );
+            // Where the game actually places it
+            if render_offset != Vec2::ZERO {
+                draw_anchor_cross(ui.painter(), true_pos);
+            }
+        }
+
+        if issue_uuids.contains(&level_sprite.uuid) {
+            ui.painter().rect_stroke(rect.expand(1.0), 0.0, Stroke::new(2.0, de.display_settings.theme_colors.sprite_issue), egui::StrokeKind::Outside);
+        }
+        if de.display_settings.show_sprite_ranges {
+            draw_sprite_range(ui.painter(), &level_sprite, rect.translate(render_offset), de.display_settings.theme_colors.sprite_range);
         }
 
         // Interactivity
-        if de.display_settings.current_layer == CurrentLayer::Sprites {
+        if receives_input(ui, de, CurrentLayer::Sprites) {
             let is_shift = ui.ctx().input(|i| i.modifiers.shift);
             for (i,r) in drawn_rects.iter().enumerate() {
                 let click_response = ui.interact(*r, egui::Id::new(format!("sprite_click_{}_{}",level_sprite.uuid,i)), egui::Sense::click());
@@ -562,6 +1496,7 @@ fn draw_sprites(ui: &mut egui::Ui, de: &mut DisplayEngine, vrect: &Rect) {
                     // If length is one, handle gui
                     if de.selected_sprite_uuids.len() == 1 {
                         de.latest_sprite_settings = utils::bytes_to_hex_string(&level_sprite.settings);
+                        de.latest_sprite_object_id = level_sprite.object_id;
                     }
                 }
                 // Debug
@@ -586,27 +1521,33 @@ fn draw_sprites(ui: &mut egui::Ui, de: &mut DisplayEngine, vrect: &Rect) {
                         de.sprite_drag_status.start_x = cur_pos.x;
                         de.sprite_drag_status.start_y = cur_pos.y;
                     }
+                    let drag_start = Vec2::new(de.sprite_drag_status.start_x, de.sprite_drag_status.start_y) - top_left.to_vec2();
+                    let sprite_tile = (level_sprite.x_position, level_sprite.y_position);
+                    let (axis_lock, free) = ui.input(|i| (i.modifiers.shift, i.modifiers.alt));
                     if interaction.dragged() {
                         //println!("Drag moving");
                         ui.output_mut(|o| o.cursor_icon = egui::CursorIcon::Move);
                         let cur_pos = ui.ctx().pointer_interact_pos().expect("Failed to get dragged cursor");
-                        let preview_rect = Rect::from_min_size(cur_pos, SPRITE_RECT);
-                        ui.painter().rect_filled(preview_rect, 0.0, SPRITE_BG_COLOR_SELECTED);
+                        let (preview_pos, (landing_x, landing_y)) = sprite_drag_target(drag_start, cur_pos - top_left, sprite_tile, axis_lock, free);
+                        let preview_rect = Rect::from_min_size(top_left + preview_pos, SPRITE_RECT);
+                        ui.painter().rect_filled(preview_rect, 0.0, de.display_settings.theme_colors.sprite_fill_selected);
+                        if free {
+                            let landing_pos = top_left + Vec2::new(landing_x.max(0) as f32 * TILE_WIDTH_PX, landing_y.max(0) as f32 * TILE_HEIGHT_PX);
+                            ui.painter().rect_stroke(Rect::from_min_size(landing_pos, SPRITE_RECT), 0.0,
+                                Stroke::new(1.0, de.display_settings.theme_colors.sprite_fill_selected), egui::StrokeKind::Inside);
+                        }
                     }
                     if interaction.drag_stopped() {
                         //println!("Drag stopped");
                         de.sprite_drag_status.dragging_uuid = Uuid::nil();
                         let latest_pos: Pos2 = ui.ctx().pointer_interact_pos().expect("CTX should hold pointer interaction position");
-                        let drag_stop_pos: Vec2 = latest_pos.to_vec2() - top_left.to_vec2();
-                        // 0.5 makes it round to nearest when slicing off the precision
-                        let true_new_x: u16 = ((drag_stop_pos.x + 0.5) / TILE_WIDTH_PX) as u16;
-                        let true_new_y: u16 = ((drag_stop_pos.y + 0.5) / TILE_HEIGHT_PX) as u16;
+                        let (_, (true_new_x, true_new_y)) = sprite_drag_target(drag_start, latest_pos - top_left, sprite_tile, axis_lock, free);
                         de.sprite_drag_status.start_x = 0.0;
                         de.sprite_drag_status.start_y = 0.0;
                         let og_sprite_tile_x = level_sprite.x_position as i32;
                         let og_sprite_tile_y = level_sprite.y_position as i32;
-                        let x_tile_movement = (true_new_x as i32) - og_sprite_tile_x;
-                        let y_tile_movement = (true_new_y as i32) - og_sprite_tile_y;
+                        let x_tile_movement = true_new_x - og_sprite_tile_x;
+                        let y_tile_movement = true_new_y - og_sprite_tile_y;
                         for selspr in &de.selected_sprite_uuids {
                             let Some(og_sprite_data) = de.get_loaded_sprite_by_uuid(selspr) else {
                                 log_write(format!("Sprite Uuid '{}' not found when moving",selspr), LogLevel::Error);
@@ -622,7 +1563,7 @@ fn draw_sprites(ui: &mut egui::Ui, de: &mut DisplayEngine, vrect: &Rect) {
                             }
                             de.loaded_map.move_sprite(*selspr, move_to_x as u16, move_to_y as u16);
                         }
-                        de.unsaved_changes = true;
+                        de.map_edited();
                         update_map = true;
                     }
                 }
@@ -635,7 +1576,7 @@ fn draw_sprites(ui: &mut egui::Ui, de: &mut DisplayEngine, vrect: &Rect) {
             if cfr.clicked() { // Clicked on empty background
                 de.selected_sprite_uuids.clear();
             }
-            if cfr.secondary_clicked() { // Right clicked on empty background = place
+            if cfr.secondary_clicked() && right_click_free(de) { // Right clicked on empty background = place
                 log_write("Placing new sprite from right click...", LogLevel::Debug);
                 // Retrieve the base sprite ID to create, usually set by Add Sprite
                 let Some(new_sprite_id) = de.selected_sprite_to_place else {
@@ -649,7 +1590,7 @@ fn draw_sprites(ui: &mut egui::Ui, de: &mut DisplayEngine, vrect: &Rect) {
                     let new_uuid = de.loaded_map.add_new_sprite_at(new_sprite_id, base_tile_x, base_tile_y);
                     log_write(format!("Placed sprite with UUID {new_uuid}"), LogLevel::Debug);
                     de.selected_sprite_uuids = vec![new_uuid]; // Select only it
-                    de.unsaved_changes = true;
+                    de.map_edited();
                     update_map = true;
                 } else {
                     log_write("Could not get pointer pos when right clicking Sprite", LogLevel::Error);
@@ -668,6 +1609,8 @@ fn draw_background(
     show: bool
 ) {
     puffin::profile_function!();
+    // Copied, since de is borrowed mutably below
+    let colors = de.display_settings.theme_colors;
     // These will be used for rendering fewer tiles to save CPU
     let leftmost_tile = vrect.left() / TILE_WIDTH_PX;
     let rightmost_tile = vrect.right() / TILE_WIDTH_PX;
@@ -695,6 +1638,8 @@ fn draw_background(
             return;
         }
     }
+    // The layer stays borrowed below, so map_edited waits until the end
+    let mut stamped = false;
     if let Some(layer) = bg_layer_opt {
         let info = layer.get_info().expect("INFO is guaranteed in SCENs");
         let is_selected_layer: bool = (de.display_settings.current_layer as u8) == whichbg;
@@ -708,7 +1653,7 @@ fn draw_background(
         }
         let mut true_grid_rect = ui.min_rect();
         if info.x_offset_px != 0 || info.y_offset_px != 0 {
-            true_grid_rect = true_grid_rect.translate(Vec2::new((info.x_offset_px * -1) as f32, (info.y_offset_px * -1) as f32));
+            true_grid_rect = true_grid_rect.translate(Vec2::new(-info.x_offset_px as f32, -info.y_offset_px as f32));
         }
         let mut temp_selected_indexes: Vec<u32> = Vec::new();
         // MAP TILES //
@@ -718,6 +1663,9 @@ fn draw_background(
                 let mut map_index: u32 = 0;
                 let ctx = ui.ctx();
                 let painter = ui.painter();
+                // Reserved now so numbers and edges drawn in the loop go on top
+                let tiles_shape_idx = painter.add(Shape::Noop);
+                let mut batch = TileBatch::default();
                 for map_tile in &map_tiles.tiles {
                     let tile_x: f32 = (map_index % grid_width) as f32;
                     let tile_y: f32 = (map_index / grid_width) as f32;
@@ -777,36 +1725,54 @@ fn draw_background(
                         selected = true;
                     }
                     let is_cur_lay_bg = de.display_settings.is_cur_layer_bg();
-                    let dim = (!is_selected_layer && is_cur_lay_bg) || de.display_settings.current_layer == CurrentLayer::Collision;
+                    let dim = if de.display_settings.current_layer == CurrentLayer::Collision {
+                        Some(de.display_settings.collision_tile_dim)
+                    } else if !is_selected_layer && is_cur_lay_bg {
+                        Some(TileDim::Light)
+                    } else {
+                        None
+                    };
                     if let Some(tilecache) = &mut tc {
                         if !info.is_256_colorpal_mode() {
                             draw_tile_16(
                                 map_tile, cur_pal, ctx, pixel_tiles,
-                                painter, tilecache,
-                                &true_tile_rect, selected,dim);
+                                &mut batch, tilecache,
+                                &true_tile_rect, selected.then_some(colors.bg_selected_tile), dim);
                         } else if let Some(pltb) = layer.get_pltb() {
                             if pltb.palettes.is_empty() {
                                 log_write("PLTB palettes were empty when trying to draw 256 tile!".to_owned(), LogLevel::Error);
                             } else {
                                 draw_tile_256(
                                     map_tile, &pltb.palettes[0], ctx,
-                                    pixel_tiles, painter, tilecache,
-                                    &true_tile_rect, selected, dim);
+                                    pixel_tiles, &mut batch, tilecache,
+                                    &true_tile_rect, selected.then_some(colors.bg_selected_tile), dim);
                             }
                         } else {
                             log_write(format!("Failed to find PLTB data for tile drawing on bg '{}'",info.which_bg), LogLevel::Error);
                         }
                         
                     }
+                    // Only reached for tiles inside the viewport
+                    if is_selected_layer {
+                        let tile_number = match de.display_settings.tile_number_overlay {
+                            TileNumberOverlay::Off => None,
+                            TileNumberOverlay::TileId => Some(map_tile.tile_id as u32),
+                            TileNumberOverlay::MapIndex => Some(map_index)
+                        };
+                        if let Some(tile_number) = tile_number {
+                            painter.text(true_tile_rect.center(), Align2::CENTER_CENTER,
+                                format!("{:X}",tile_number), TILE_NUMBER_FONT, Color32::WHITE);
+                        }
+                    }
                     // Draw lines to show true edges of layers //
                     if tile_y as u32 == info.layer_height as u32 - 1 {
                         // True rect is the bottommost tile
                         let point_1 = true_tile_rect.left_bottom() + Vec2::new(1.0, 1.0);
                         let point_2 = true_tile_rect.right_bottom() + Vec2::new(-1.0, 1.0);
                         ui.painter().line(vec![point_1,point_2], egui::Stroke::new(1.0, if is_selected_layer {
-                            Color32::RED
+                            colors.layer_edge_current
                         } else {
-                            Color32::BLUE
+                            colors.layer_edge_other
                         }));
                     }
                     if tile_x as u32 == info.layer_width as u32 - 1 {
@@ -814,16 +1780,17 @@ fn draw_background(
                         let point_1 = true_tile_rect.right_top() + Vec2::new(1.0, 1.0);
                         let point_2 = true_tile_rect.right_bottom() + Vec2::new(1.0, -1.0);
                         ui.painter().line(vec![point_1,point_2], egui::Stroke::new(1.0, if is_selected_layer {
-                            Color32::RED
+                            colors.layer_edge_current
                         } else {
-                            Color32::BLUE
+                            colors.layer_edge_other
                         }));
                     }
 
                     map_index += 1;
                 }
+                painter.set(tiles_shape_idx, batch.into_shape());
                 // Interactivity //
-                if is_selected_layer {
+                if is_selected_layer && !de.display_settings.is_cur_layer_locked() && !ui.input(|i| i.modifiers.alt) {
                     let interaction_id = egui::Id::new(format!("map_tile_interact_{}",whichbg));
                     // all() because it uses click, drag, and hover
                     let bg_interaction = ui.interact(true_grid_rect, interaction_id, egui::Sense::all());
@@ -847,11 +1814,11 @@ fn draw_background(
                         let drag_rect: Rect = Rect::from_two_pos(de.bg_sel_data.start_pos, de.bg_sel_data.end_pos);
                         // Selection rectangle should look different if Control is held
                         if ui.input(|i| i.modifiers.ctrl) {
-                            painter.rect_filled(drag_rect, 0.0, BG_SELECTION_FILL_INVERT);
+                            painter.rect_filled(drag_rect, 0.0, colors.bg_selection_fill_invert);
                         } else {
-                            painter.rect_filled(drag_rect, 0.0, BG_SELECTION_FILL);
+                            painter.rect_filled(drag_rect, 0.0, colors.bg_selection_fill);
                         }
-                        painter.rect_stroke(drag_rect, 0.0, Stroke::new(1.0, BG_SELECTION_STROKE), egui::StrokeKind::Outside);
+                        painter.rect_stroke(drag_rect, 0.0, Stroke::new(1.0, colors.bg_selection_stroke), egui::StrokeKind::Outside);
                         de.bg_sel_data.selecting_rect = drag_rect; // Pass the data on in
                     }
                     if bg_interaction.drag_stopped() {
@@ -888,24 +1855,26 @@ fn draw_background(
                         //log_write(format!("Clearing BG selection"), LogLevel::Debug);
                         de.bg_sel_data.clear();
                     }
-                    if bg_interaction.secondary_clicked() {
+                    if bg_interaction.secondary_clicked() && right_click_free(de) {
                         // Place tile //
                         // Lots of opportunities to crash here, so include Debug
                         log_write("Stamping Brush to BG", LogLevel::Debug);
                         if let Some(pointer_pos) = ui.input(|i| i.pointer.latest_pos()) {
                             let local_pos = pointer_pos - true_grid_rect.min;
                             let mut base_tile_x: u32 = (local_pos.x/TILE_WIDTH_PX) as u32;
-                            if base_tile_x % 2 != 0 { // Don't paste at odd positions
+                            if !base_tile_x.is_multiple_of(2) { // Don't paste at odd positions
                                 base_tile_x -= 1; // Move to even position
                             }
                             let mut base_tile_y: u32 = (local_pos.y/TILE_HEIGHT_PX) as u32;
-                            if base_tile_y % 2 != 0 { // Don't paste at odd positions
+                            if !base_tile_y.is_multiple_of(2) { // Don't paste at odd positions
                                 base_tile_y -= 1; // Move to even position
                             }
+                            let (flip_h, flip_v) = stamp_flip_held(ui);
+                            let brush = de.current_brush.flipped(flip_h, flip_v);
                             let mut tile_index: u32 = 0;
-                            for tile in &de.current_brush.tiles {
-                                let offset_x = tile_index % (de.current_brush.width as u32);
-                                let offset_y = tile_index / (de.current_brush.width as u32);
+                            for tile in &brush.tiles {
+                                let offset_x = tile_index % (brush.width as u32);
+                                let offset_y = tile_index / (brush.width as u32);
                                 let true_x = base_tile_x + offset_x;
                                 let true_y = base_tile_y + offset_y;
                                 if true_y >= info.layer_height as u32 {
@@ -917,13 +1886,13 @@ fn draw_background(
                                     continue;
                                 }
                                 let map_index = true_y * (info.layer_width as u32) + true_x;
-                                if *tile != 0x0000 { // Don't overwrite tiles with blanks
+                                // Empty Brush spots leave the map alone, tile 0 is placed
+                                if let Some(tile) = tile {
                                     de.loaded_map.place_bg_tile_at_map_index(info.which_bg, map_index, *tile);
                                 }
                                 tile_index += 1;
                             }
-                            de.graphics_update_needed = true;
-                            de.unsaved_changes = true;
+                            stamped = true;
                         } else {
                             log_write("Failed to get pointer when stamping Brush", LogLevel::Error);
                         }
@@ -979,10 +1948,10 @@ fn draw_background(
                 de.tile_hover_pos.x = tile_x as f32;
                 de.tile_hover_pos.y = tile_y as f32;
                 // Ensure its position is even
-                if tile_x % 2 != 0 {
+                if !tile_x.is_multiple_of(2) {
                     tile_x -= 1;
                 }
-                if tile_y % 2 != 0 {
+                if !tile_y.is_multiple_of(2) {
                     tile_y -= 1;
                 }
                 de.latest_square_pos_level_space = Pos2::new(tile_x as f32, tile_y as f32);
@@ -992,15 +1961,32 @@ fn draw_background(
                     let brush_rect = Rect::from_min_size(
                     true_grid_rect.min + Vec2::new((tile_x as f32) * TILE_WIDTH_PX, (tile_y as f32) * TILE_HEIGHT_PX),
                     Vec2 { x: TILE_WIDTH_PX * width, y: TILE_HEIGHT_PX * height });
-                    ui.painter().rect_stroke(brush_rect, 0.0, Stroke::new(1.0, Color32::GREEN), egui::StrokeKind::Outside);
+                    ui.painter().rect_stroke(brush_rect, 0.0, Stroke::new(1.0, colors.brush_outline), egui::StrokeKind::Outside);
+                    let (flip_h, flip_v) = stamp_flip_held(ui);
+                    if flip_h || flip_v {
+                        // Show which spots the flipped Brush fills, since empty spots move too
+                        let brush = de.current_brush.flipped(flip_h, flip_v);
+                        for (index, tile) in brush.tiles.iter().enumerate() {
+                            if tile.is_none() {
+                                continue;
+                            }
+                            let offset = Vec2::new((index % brush.width as usize) as f32, (index / brush.width as usize) as f32);
+                            let cell_rect = Rect::from_min_size(brush_rect.min + offset * TILE_RECT, TILE_RECT);
+                            ui.painter().rect_filled(cell_rect, 0.0, colors.paste_preview_fill);
+                        }
+                    }
                 }
                 let square_rect = Rect::from_min_size(
                     true_grid_rect.min + Vec2::new((tile_x as f32) * TILE_WIDTH_PX, (tile_y as f32) * TILE_HEIGHT_PX),
                     Vec2 { x: TILE_WIDTH_PX * 2.0, y: TILE_HEIGHT_PX * 2.0 });
-                ui.painter().rect_stroke(square_rect, 0.0, Stroke::new(1.0, Color32::RED), egui::StrokeKind::Outside);
+                ui.painter().rect_stroke(square_rect, 0.0, Stroke::new(1.0, colors.cursor_square), egui::StrokeKind::Outside);
             }
         }
     }
+    if stamped {
+        de.graphics_update_needed = true;
+        de.map_edited();
+    }
 }
 
 fn local_pos_to_col_index(local_pos: &Vec2, std_grid_width: u32) -> u32 {
@@ -1010,25 +1996,58 @@ fn local_pos_to_col_index(local_pos: &Vec2, std_grid_width: u32) -> u32 {
     tile_index
 }
 
+/// A layer's tiles, with one mesh per texture instead of an image call per tile.
+/// Tiles in a layer never overlap, so the order between meshes doesn't matter
+#[derive(Default)]
+pub struct TileBatch {
+    meshes: HashMap<TextureId, Mesh>,
+    /// Drawn over the tiles, for TileDim::Gray
+    overlay: Mesh
+}
+impl TileBatch {
+    fn add_tile(&mut self, texture: TextureId, rect: Rect, uvs: Rect, color: Color32) {
+        self.meshes.entry(texture).or_insert_with(|| Mesh::with_texture(texture))
+            .add_rect_with_uv(rect, uvs, color);
+    }
+
+    fn add_overlay(&mut self, rect: Rect, color: Color32) {
+        self.overlay.add_colored_rect(rect, color);
+    }
+
+    fn into_shape(self) -> Shape {
+        puffin::profile_function!();
+        let mut shapes: Vec<Shape> = self.meshes.into_values().map(Shape::mesh).collect();
+        if !self.overlay.is_empty() {
+            shapes.push(Shape::mesh(self.overlay));
+        }
+        Shape::Vec(shapes)
+    }
+}
+
 fn draw_tile(
     tile: &MapTileRecordData,
     ctx: &Context, pixel_tiles: &[u8],
-    painter: &Painter, tc: &mut TileCache,
-    true_rect: &Rect, selected: bool,
-    dim: bool,
-    create_texture_image: impl Fn(&MapTileRecordData, &[u8]) -> ColorImage, texture_name: &str
+    batch: &mut TileBatch, tc: &mut TileCache,
+    true_rect: &Rect, selected_tint: Option<Color32>,
+    dim: Option<TileDim>,
+    palette: &Palette, decode: impl FnOnce(&MapTileRecordData, &[u8]) -> Vec<u8>, texture_name: &str
 ) {
-    puffin::profile_function!();
     if let Some(t) = get_cached_texture(tc,tile.palette_id as usize, tile.tile_id as usize) {
         let uvs = utils::get_uvs_from_tile(tile);
-        let color = match (dim, selected) {
-            (true, _) => Color32::from_rgba_unmultiplied(0xff, 0xff, 0xff, 0x40),
-            (_, true) => Color32::PURPLE,
+        // Per-vertex colors, which multiply the texture like painter.image's tint
+        let color = match (dim, selected_tint) {
+            (Some(TileDim::Strong), _) => Color32::from_rgba_unmultiplied(0xff, 0xff, 0xff, 0x18),
+            (Some(_), _) => Color32::from_rgba_unmultiplied(0xff, 0xff, 0xff, 0x40),
+            (_, Some(tint)) => tint,
             _ => Color32::WHITE,
         };
-        painter.image(t.id(), *true_rect, uvs, color);
+        batch.add_tile(t.id(), *true_rect, uvs, color);
+        if dim == Some(TileDim::Gray) {
+            batch.add_overlay(*true_rect, Color32::from_rgba_unmultiplied(0x80, 0x80, 0x80, 0xA0));
+        }
     } else {
-        let color_image = create_texture_image(tile, pixel_tiles);
+        let pal_indexes = get_cached_pixels(tc, tile.tile_id as usize, || decode(tile, pixel_tiles));
+        let color_image = utils::color_image_from_pal(palette, &pal_indexes);
         set_cached_texture(
             tc, tile.palette_id as usize, tile.tile_id as usize,
             ctx.load_texture(texture_name, color_image, egui::TextureOptions::NEAREST),
@@ -1039,16 +2058,14 @@ fn draw_tile(
 pub fn draw_tile_16(
     tile: &MapTileRecordData, palette: &Palette,
     ctx: &Context, pixel_tiles: &[u8],
-    painter: &Painter, tc: &mut TileCache,
-    true_rect: &Rect, selected: bool,
-    dim: bool
+    batch: &mut TileBatch, tc: &mut TileCache,
+    true_rect: &Rect, selected_tint: Option<Color32>,
+    dim: Option<TileDim>
 ) {
-    puffin::profile_function!();
-    draw_tile(tile, ctx, pixel_tiles, painter, tc, true_rect, selected, dim,
+    draw_tile(tile, ctx, pixel_tiles, batch, tc, true_rect, selected_tint, dim, palette,
         |tile, pixel_tiles| {
             let byte_array = utils::get_pixel_bytes_16(pixel_tiles, &tile.tile_id);
-            let nibble_array = utils::pixel_byte_array_to_nibbles(&byte_array);
-            utils::color_image_from_pal(palette, &nibble_array)
+            utils::pixel_byte_array_to_nibbles(&byte_array)
         }, "tile16"
     );
 }
@@ -1056,15 +2073,101 @@ pub fn draw_tile_16(
 pub fn draw_tile_256(
     tile: &MapTileRecordData, palette256: &Palette,
     ctx: &Context, pixel_tiles: &[u8],
-    painter: &Painter, tc: &mut TileCache,
-    true_rect: &Rect, selected: bool,
-    dim: bool
+    batch: &mut TileBatch, tc: &mut TileCache,
+    true_rect: &Rect, selected_tint: Option<Color32>,
+    dim: Option<TileDim>
 ) {
-    puffin::profile_function!();
-    draw_tile(tile, ctx, pixel_tiles, painter, tc, true_rect, selected, dim,
-        |tile, pixel_tiles| {
-            let byte_array = utils::get_pixel_bytes_256(pixel_tiles, &tile.tile_id);
-            utils::color_image_from_pal(palette256, &byte_array)
-        }, "tile256"
+    draw_tile(tile, ctx, pixel_tiles, batch, tc, true_rect, selected_tint, dim, palette256,
+        |tile, pixel_tiles| utils::get_pixel_bytes_256(pixel_tiles, &tile.tile_id), "tile256"
     );
 }
+
+#[cfg(test)]
+mod tests_maingrid {
+    use super::*;
+    use crate::data::path::PathLine;
+
+    #[test]
+    fn test_grid_scroll() {
+        let scroll = GridScroll { from: Vec2::new(0.0, 100.0), to: Vec2::new(200.0, 0.0), start_time: 1.0, duration: GRID_SCROLL_SECS };
+        assert_eq!(scroll.offset_at(0.5),(Vec2::new(0.0, 100.0), false));
+        assert_eq!(scroll.offset_at(1.0 + GRID_SCROLL_SECS / 2.0),(Vec2::new(100.0, 50.0), false));
+        assert_eq!(scroll.offset_at(2.0),(Vec2::new(200.0, 0.0), true));
+        let jump = GridScroll { duration: 0.0, ..scroll };
+        assert_eq!(jump.offset_at(1.0),(Vec2::new(200.0, 0.0), true));
+        let (offset, page, max_offset) = (Vec2::new(50.0, 300.0), Vec2::new(400.0, 250.0), Vec2::new(1000.0, 600.0));
+        assert_eq!(grid_scroll_target(Key::PageDown, offset, page, max_offset),Some(Vec2::new(50.0, 550.0)));
+        // Stops at the edges
+        assert_eq!(grid_scroll_target(Key::ArrowDown, Vec2::new(50.0, 550.0), page, max_offset),Some(Vec2::new(50.0, 600.0)));
+        assert_eq!(grid_scroll_target(Key::ArrowLeft, offset, page, max_offset),Some(Vec2::new(0.0, 300.0)));
+        assert_eq!(grid_scroll_target(Key::Home, offset, page, max_offset),Some(Vec2::new(50.0, 0.0)));
+        assert_eq!(grid_scroll_target(Key::End, offset, page, max_offset),Some(Vec2::new(50.0, 600.0)));
+        // A map smaller than the view can't scroll
+        assert_eq!(grid_scroll_target(Key::PageDown, Vec2::ZERO, page, Vec2::new(-10.0, -10.0)),Some(Vec2::ZERO));
+        assert_eq!(grid_scroll_target(Key::Enter, offset, page, max_offset),None);
+    }
+
+    #[test]
+    fn test_sprite_drag_target() {
+        let start = Vec2::new(20.0, 20.0);
+        // Nearest tile by default
+        assert_eq!(sprite_drag_target(start, Vec2::new(45.0, 13.0), (2, 2), false, false),(Vec2::new(45.0, 13.0), (6, 2)));
+        // Mostly sideways, so the row stays put
+        assert_eq!(sprite_drag_target(start, Vec2::new(45.0, 13.0), (2, 2), true, false),(Vec2::new(45.0, 16.0), (6, 2)));
+        assert_eq!(sprite_drag_target(start, Vec2::new(25.0, 60.0), (2, 2), true, false),(Vec2::new(16.0, 60.0), (2, 8)));
+        // Free placement takes the tile the corner is over
+        assert_eq!(sprite_drag_target(start, Vec2::new(45.0, 13.0), (2, 2), false, true).1,(5, 1));
+        assert_eq!(sprite_drag_target(start, Vec2::new(-3.0, 13.0), (2, 2), false, true).1,(-1, 1));
+    }
+
+    #[test]
+    fn test_ruler_label_step() {
+        assert_eq!(ruler_label_step(TILE_WIDTH_PX),8);
+        assert_eq!(ruler_label_step(24.0),2);
+        assert_eq!(ruler_label_step(2.0),32);
+        assert_eq!(48 % ruler_label_step(TILE_WIDTH_PX),0);
+    }
+
+    #[test]
+    fn test_path_geometry() {
+        let straight = PathPoint::new(0, 0x10, 2 << 15, 3 << 15);
+        let curve = PathPoint::new(0, -0x10, 4 << 15, 3 << 15);
+        let last = PathPoint::new(0, 0, 6 << 15, 1 << 15);
+        let path = PathDatabase { path_count: 1, lines: vec![PathLine { points: vec![straight, curve, last], uuid: Uuid::new_v4() }] };
+        let geometry = PathGeometry::build(&path, None);
+        let line = &geometry.lines[0];
+        assert_eq!(line.points.iter().map(|p| p.pos).collect::<Vec<Vec2>>(),
+            vec![Vec2::new(16.0, 24.0), Vec2::new(32.0, 24.0), Vec2::new(48.0, 8.0)]);
+        // Angle lines need the ARM9 tables
+        assert!(line.points.iter().all(|p| p.angle_end.is_none()));
+        assert_eq!(line.curves.len(),1);
+        assert_eq!(line.curves[0].uuid,curve.uuid);
+        assert_eq!(line.curves[0].polyline.len(),6);
+        assert_eq!(PathGeometry::build(&PathDatabase::default(), None),PathGeometry::default());
+    }
+
+    #[test]
+    fn test_tile_batch() {
+        let tex_a = TextureId::Managed(1);
+        let tex_b = TextureId::Managed(2);
+        let uvs = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+        let mut batch = TileBatch::default();
+        batch.add_tile(tex_a, Rect::from_min_size(Pos2::ZERO, Vec2::splat(8.0)), uvs, Color32::WHITE);
+        batch.add_tile(tex_b, Rect::from_min_size(Pos2::new(8.0, 0.0), Vec2::splat(8.0)), uvs, Color32::WHITE);
+        batch.add_tile(tex_a, Rect::from_min_size(Pos2::new(16.0, 0.0), Vec2::splat(8.0)), uvs, Color32::RED);
+        assert_eq!(batch.meshes.len(),2);
+        assert_eq!(batch.meshes[&tex_a].vertices.len(),8);
+        assert_eq!(batch.meshes[&tex_a].vertices[4].color,Color32::RED);
+        let Shape::Vec(shapes) = batch.into_shape() else {
+            panic!("Batch should be a list of meshes");
+        };
+        // No overlay mesh without gray tiles
+        assert_eq!(shapes.len(),2);
+        let mut gray = TileBatch::default();
+        gray.add_overlay(Rect::from_min_size(Pos2::ZERO, Vec2::splat(8.0)), Color32::GRAY);
+        let Shape::Vec(shapes) = gray.into_shape() else {
+            panic!("Batch should be a list of meshes");
+        };
+        assert_eq!(shapes.len(),1);
+    }
+}
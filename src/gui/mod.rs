@@ -1,5 +1,7 @@
 use crate::data::sprites::LevelSprite;
 
+use self::gui::NumberBase;
+
 #[allow(clippy::module_inception)]
 pub mod gui;
 pub mod toppanel;
@@ -8,10 +10,11 @@ pub mod windows;
 pub mod maingrid;
 pub mod spritepanel;
 pub mod spritesettings;
+pub mod detach;
 
 pub trait SpriteSettings {
     /// Generate a UI that modifies it
-    fn show_ui(&mut self, ui: &mut egui::Ui) -> egui::Response;
+    fn show_ui(&mut self, ui: &mut egui::Ui, base: NumberBase) -> egui::Response;
     /// Create 4-padded settings vector
     fn compile(&self) -> Vec<u8>;
     /// Create it from the Sprite
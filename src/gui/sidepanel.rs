@@ -1,7 +1,81 @@
+use crate::data::{mapfile::TopLevelSegmentWrapper, types::CurrentLayer};
+
 use super::gui::Gui;
 
+/// What the side panel shows about one BG layer, read from its INFO
+struct LayerSummary {
+    layer: CurrentLayer,
+    width: u16,
+    height: u16,
+    tileset: String,
+    is_256: bool,
+    has_colz: bool
+}
+
+fn layer_summaries(gui_state: &Gui) -> Vec<LayerSummary> {
+    let mut summaries: Vec<LayerSummary> = gui_state.display_engine.loaded_map.segments.iter().filter_map(|seg| {
+        let TopLevelSegmentWrapper::SCEN(bg) = seg else {
+            return None;
+        };
+        let info = bg.get_info()?;
+        let layer = match info.which_bg {
+            1 => CurrentLayer::BG1,
+            2 => CurrentLayer::BG2,
+            3 => CurrentLayer::BG3,
+            _ => return None
+        };
+        Some(LayerSummary {
+            layer,
+            width: info.layer_width,
+            height: info.layer_height,
+            tileset: info.imbz_filename_noext.clone().unwrap_or_else(|| "N/A".to_string()),
+            is_256: info.is_256_colorpal_mode(),
+            has_colz: bg.get_colz().is_some()
+        })
+    }).collect();
+    summaries.sort_by_key(|summary| summary.layer as u8);
+    summaries
+}
+
+/// One block per BG layer. Clicking one makes it current and shows its quick actions
+fn layer_summary_show(ui: &mut egui::Ui, gui_state: &mut Gui) {
+    let summaries = layer_summaries(gui_state);
+    if summaries.is_empty() {
+        return;
+    }
+    for summary in summaries {
+        let is_current = gui_state.display_engine.display_settings.current_layer == summary.layer;
+        let mut text = format!("{:?}: {}x{}\n{}\n{}",summary.layer,summary.width,summary.height,
+            summary.tileset,if summary.is_256 { "256 color" } else { "16 color" });
+        if summary.has_colz {
+            text.push_str(", COLZ");
+        }
+        if ui.selectable_label(is_current, egui::RichText::new(text).small()).clicked() {
+            gui_state.display_engine.change_layer(summary.layer);
+        }
+        if !is_current {
+            continue;
+        }
+        ui.horizontal_wrapped(|ui| {
+            ui.spacing_mut().item_spacing.x = 2.0;
+            if ui.small_button("Clear").clicked() {
+                gui_state.clear_modal_open = true;
+            }
+            if ui.small_button("Resize").clicked() {
+                gui_state.resize_settings.reset_needed = true;
+                gui_state.resize_settings.window_open = true;
+            }
+            if ui.small_button("Segments").clicked() {
+                gui_state.scen_window_open = true;
+            }
+        });
+    }
+    ui.separator();
+}
+
 pub fn side_panel_show(ui: &mut egui::Ui, gui_state: &mut Gui) {
     puffin::profile_function!();
+    layer_summary_show(ui, gui_state);
     ui.toggle_value(&mut gui_state.palette_window_open, "Palettes");
     ui.toggle_value(&mut gui_state.tile_preview_window_open, "Tiles");
     ui.toggle_value(&mut gui_state.brush_window_open, "Brush");
@@ -13,4 +87,12 @@ pub fn side_panel_show(ui: &mut egui::Ui, gui_state: &mut Gui) {
     ui.toggle_value(&mut gui_state.area_window_open, "Triggers");
     ui.toggle_value(&mut gui_state.mpdz_window_open, "Map Data");
     ui.toggle_value(&mut gui_state.scen_window_open, "BG Data");
+    ui.toggle_value(&mut gui_state.history_window_open, "History");
+    ui.toggle_value(&mut gui_state.sprite_check_window_open, "Sprite Check");
+    ui.toggle_value(&mut gui_state.clipboard_window_open, "Clipboard");
+    ui.toggle_value(&mut gui_state.bookmarks_window_open, "Bookmarks");
+    let modified_toggle = ui.toggle_value(&mut gui_state.modified_files_window_open, "Modified Files");
+    if modified_toggle.changed() && gui_state.modified_files_window_open {
+        gui_state.modified_files.refresh_needed = true;
+    }
 }
\ No newline at end of file
@@ -1,19 +1,21 @@
-use std::f32;
+use std::{f32, sync::Arc};
 
-use egui::ScrollArea;
+use egui::{Color32, Pos2, RichText, ScrollArea};
 use egui_extras::{Column, Size, StripBuilder, TableBuilder};
+use uuid::Uuid;
 
-use crate::{data::sprites::{LevelSprite, SpriteMetadata}, gui::{spritesettings, SpriteSettings}, load::SPRITE_METADATA, utils::{self, bytes_to_hex_string, is_debug, log_write, string_to_settings, LogLevel}, NON_MAIN_FOCUSED};
+use crate::{data::sprites::{LevelSprite, SpriteMetadata}, engine::displayengine::SpriteSettingsClipboard, gui::{spritesettings, SpriteSettings}, load::{sprite_meta, SPRITE_PRESETS}, utils::{self, bytes_to_hex_string, is_debug, log_write, string_to_settings, LogLevel}, NON_MAIN_FOCUSED};
 
 use super::gui::Gui;
 
 pub fn sprite_panel_show(ui: &mut egui::Ui, gui_state: &mut Gui) {
     puffin::profile_function!();
     StripBuilder::new(ui)
-        .size(Size::exact(100.0))
+        .size(Size::exact(140.0))
         .size(Size::remainder())
         .vertical(|mut strip| {
             strip.cell(|ui| {
+                let base = gui_state.display_engine.display_settings.number_base;
                 let sprites_len = gui_state.display_engine.selected_sprite_uuids.len();
                 if sprites_len == 1 {
                     let Some(sprite) = &gui_state.display_engine.loaded_map
@@ -26,37 +28,66 @@ pub fn sprite_panel_show(ui: &mut egui::Ui, gui_state: &mut Gui) {
                             gui_state.display_engine.graphics_update_needed = true;
                             return;
                         };
-                    let Some(sprite_meta) = SPRITE_METADATA.get(&sprite.object_id) else {
-                        log_write(format!("Failed to get sprite_meta for ID 0x{:X} on panel",&sprite.object_id), LogLevel::Error);
-                        return;
-                    };
-                    ui.label(format!("[0x{:03X}]: {}",&sprite.object_id,&sprite_meta.name));
-                    ui.label(&sprite_meta.description);
-                    ui.label(format!("X/Y Position: 0x{:X}/0x{:X}",&sprite.x_position,&sprite.y_position));
+                    let known_meta = sprite_meta(sprite.object_id);
+                    let is_missing = known_meta.is_none();
+                    let meta = known_meta.unwrap_or_else(|| Arc::new(SpriteMetadata::inferred(sprite)));
+                    let name_text = RichText::new(format!("[{}]: {}",base.fmt_num(sprite.object_id, 3),&meta.name));
+                    if is_missing {
+                        ui.label(name_text.color(Color32::ORANGE))
+                            .on_hover_text("Add a stub in Sprite Check to name it in the project's sprites.csv");
+                    } else {
+                        ui.label(name_text);
+                    }
+                    ui.label(&meta.description);
+                    show_settings_clipboard_buttons(ui, gui_state);
+                    show_presets_combo(ui, gui_state);
+                    ui.horizontal(|ui| {
+                        let id_drag = base.drag_value(egui::DragValue::new(&mut gui_state.display_engine.latest_sprite_object_id), 3)
+                            .range(0..=0xffff);
+                        let id_res = ui.add(id_drag);
+                        if id_res.has_focus() {
+                            *NON_MAIN_FOCUSED.lock().unwrap() = true;
+                        }
+                        let new_id = gui_state.display_engine.latest_sprite_object_id;
+                        let new_meta = sprite_meta(new_id);
+                        let change_button = ui.add_enabled(
+                            new_meta.is_some() && new_id != sprite.object_id,
+                            egui::Button::new("Change ID")
+                        ).on_hover_text(new_meta.map_or(String::from("Unknown Sprite ID"), |meta| meta.name.clone()));
+                        if change_button.clicked() &&
+                            gui_state.display_engine.loaded_map.change_sprite_object_id(sprite.uuid, new_id) {
+                            if let Some(changed) = gui_state.display_engine.loaded_map.get_sprite_by_uuid(sprite.uuid) {
+                                gui_state.display_engine.latest_sprite_settings = bytes_to_hex_string(&changed.settings);
+                            }
+                            gui_state.display_engine.map_edited();
+                            gui_state.display_engine.graphics_update_needed = true;
+                        }
+                    });
+                    ui.label(format!("X/Y Position: {}/{}",base.fmt_num(sprite.x_position, 1),base.fmt_num(sprite.y_position, 1)));
                     if sprite.settings_length != 0 {
                         #[allow(clippy::manual_range_patterns)]
                         match sprite.object_id {
                             0x23 => {
                                 let mut pipe = spritesettings::GreenPipe::from_sprite(sprite);
-                                pipe.show_ui(ui);
+                                pipe.show_ui(ui, base);
                                 let comp = pipe.compile();
                                 settings_save_check(gui_state, comp, sprite);
                             }
                             0x36 | 0x37 | 0x38 | 0x39 => {
                                 let mut shyguy = spritesettings::ShyGuy::from_sprite(sprite);
-                                shyguy.show_ui(ui);
+                                shyguy.show_ui(ui, base);
                                 let comp = shyguy.compile();
                                 settings_save_check(gui_state, comp, sprite);
                             }
                             0x9A => {
                                 let mut red_arrow_sign = spritesettings::RedArrowSign::from_sprite(sprite);
-                                red_arrow_sign.show_ui(ui);
+                                red_arrow_sign.show_ui(ui, base);
                                 let comp = red_arrow_sign.compile();
                                 settings_save_check(gui_state, comp, sprite);
                             }
                             0x9F => {
                                 let mut hint_block = spritesettings::HintBlock::from_sprite(sprite);
-                                hint_block.show_ui(ui);
+                                hint_block.show_ui(ui, base);
                                 let comp = hint_block.compile();
                                 settings_save_check(gui_state, comp, sprite);
                             }
@@ -78,7 +109,7 @@ pub fn sprite_panel_show(ui: &mut egui::Ui, gui_state: &mut Gui) {
                                         Err(error) => log_write(format!("Still had bad settings somehow: '{error}'"), LogLevel::Error),
                                         Ok(new_settings) => {
                                             gui_state.display_engine.loaded_map.update_sprite_settings(sprite.uuid, new_settings);
-                                            gui_state.display_engine.unsaved_changes = true;
+                                            gui_state.display_engine.map_edited();
                                             gui_state.display_engine.graphics_update_needed = true;
                                         }
                                     };
@@ -91,7 +122,9 @@ pub fn sprite_panel_show(ui: &mut egui::Ui, gui_state: &mut Gui) {
                 } else if sprites_len == 0 {
                     ui.label("No sprites selected");
                 } else {
-                    ui.label("Multiple sprites selected");
+                    ui.label(format!("{} sprites selected",sprites_len));
+                    show_settings_clipboard_buttons(ui, gui_state);
+                    show_bulk_settings(ui, gui_state);
                 }
             });
             strip.cell(|ui| {
@@ -102,6 +135,131 @@ pub fn sprite_panel_show(ui: &mut egui::Ui, gui_state: &mut Gui) {
 
 }
 
+/// Copying needs a single Sprite, pasting goes to every selected Sprite
+fn show_settings_clipboard_buttons(ui: &mut egui::Ui, gui_state: &mut Gui) {
+    let selected: Vec<LevelSprite> = gui_state.display_engine.selected_sprite_uuids.clone().iter()
+        .filter_map(|uuid| gui_state.display_engine.loaded_map.get_sprite_by_uuid(*uuid))
+        .collect();
+    ui.horizontal(|ui| {
+        let can_copy = selected.len() == 1 && selected[0].settings_length != 0;
+        if ui.add_enabled(can_copy, egui::Button::new("Copy Settings")).clicked() {
+            log_write(format!("Copied settings of Sprite 0x{:X}",selected[0].object_id), LogLevel::Log);
+            gui_state.display_engine.clipboard.settings_clip = Some(SpriteSettingsClipboard {
+                object_id: selected[0].object_id,
+                settings: selected[0].settings.clone()
+            });
+        }
+        let clip = gui_state.display_engine.clipboard.settings_clip.clone();
+        let can_paste = clip.as_ref().is_some_and(|clip| selected.iter().all(|spr| clip.fits(spr)));
+        let paste_res = ui.add_enabled(can_paste, egui::Button::new("Paste Settings"));
+        let paste_res = match &clip {
+            Some(clip) => paste_res
+                .on_hover_text(format!("Copied from Sprite 0x{:X}",clip.object_id))
+                .on_disabled_hover_text("Copied settings are a different length than the selected Sprites use"),
+            None => paste_res
+        };
+        if paste_res.clicked() {
+            let clip = clip.expect("can_paste checks for clip");
+            log_write(format!("Pasting settings onto {} Sprites",selected.len()), LogLevel::Log);
+            for spr in &selected {
+                gui_state.display_engine.loaded_map.update_sprite_settings(spr.uuid, clip.settings.clone());
+            }
+            gui_state.display_engine.latest_sprite_settings = bytes_to_hex_string(&clip.settings);
+            gui_state.display_engine.map_edited();
+            gui_state.display_engine.graphics_update_needed = true;
+        }
+    });
+}
+
+/// Applies a stored preset to every selected Sprite, hidden if the type has none
+fn show_presets_combo(ui: &mut egui::Ui, gui_state: &mut Gui) {
+    let selected: Vec<LevelSprite> = gui_state.display_engine.selected_sprite_uuids.clone().iter()
+        .filter_map(|uuid| gui_state.display_engine.loaded_map.get_sprite_by_uuid(*uuid))
+        .collect();
+    let Some(first) = selected.first() else {
+        return;
+    };
+    // Presets must fit every selected Sprite exactly
+    let presets: Vec<_> = SPRITE_PRESETS.for_sprite(first.object_id).into_iter()
+        .filter(|preset| selected.iter().all(|spr| {
+            spr.object_id == preset.object_id && spr.settings_length as usize == preset.settings.len()
+        }))
+        .collect();
+    if presets.is_empty() {
+        return;
+    }
+    let mut chosen: Option<Vec<u8>> = Option::None;
+    egui::ComboBox::from_label("Presets")
+        .selected_text(presets.iter().find(|preset| preset.settings == first.settings)
+            .map_or("Custom", |preset| preset.label.as_str()))
+        .show_ui(ui, |ui| {
+            for preset in &presets {
+                let res = ui.selectable_label(preset.settings == first.settings, &preset.label)
+                    .on_hover_text(bytes_to_hex_string(&preset.settings));
+                if res.clicked() {
+                    chosen = Some(preset.settings.clone());
+                }
+            }
+        });
+    if let Some(new_settings) = chosen {
+        log_write(format!("Applying preset to {} Sprites",selected.len()), LogLevel::Log);
+        for spr in &selected {
+            gui_state.display_engine.loaded_map.update_sprite_settings(spr.uuid, new_settings.clone());
+        }
+        gui_state.display_engine.latest_sprite_settings = bytes_to_hex_string(&new_settings);
+        gui_state.display_engine.map_edited();
+        gui_state.display_engine.graphics_update_needed = true;
+    }
+}
+
+/// Edits the settings of every selected Sprite at once, only if they are all the same type
+fn show_bulk_settings(ui: &mut egui::Ui, gui_state: &mut Gui) {
+    let selected: Vec<LevelSprite> = gui_state.display_engine.selected_sprite_uuids.clone().iter()
+        .filter_map(|uuid| gui_state.display_engine.loaded_map.get_sprite_by_uuid(*uuid))
+        .collect();
+    let Some(first) = selected.first() else {
+        return;
+    };
+    let is_homogeneous = selected.iter()
+        .all(|spr| spr.object_id == first.object_id && spr.settings_length == first.settings_length);
+    if !is_homogeneous {
+        ui.label("Settings can only be edited together when all selected Sprites are the same type");
+        return;
+    }
+    if first.settings_length == 0 {
+        ui.label("No Settings");
+        return;
+    }
+    if selected.iter().any(|spr| spr.settings != first.settings) {
+        ui.label(egui::RichText::new("Selected Sprites have differing settings").weak());
+    }
+    show_presets_combo(ui, gui_state);
+    let ml = ui.add(egui::TextEdit::multiline(&mut gui_state.display_engine.latest_sprite_settings).desired_width(120.0));
+    if ml.has_focus() {
+        *NON_MAIN_FOCUSED.lock().unwrap() = true;
+    }
+    let res = ui.add_enabled(
+        is_settings_string_valid(
+            &gui_state.display_engine.latest_sprite_settings,
+            first.settings_length as usize
+        ),
+        egui::Button::new("Update All Settings")
+    );
+    if res.clicked() {
+        match string_to_settings(&gui_state.display_engine.latest_sprite_settings) {
+            Err(error) => log_write(format!("Still had bad settings somehow: '{error}'"), LogLevel::Error),
+            Ok(new_settings) => {
+                log_write(format!("Updating settings of {} selected Sprites",selected.len()), LogLevel::Log);
+                for spr in &selected {
+                    gui_state.display_engine.loaded_map.update_sprite_settings(spr.uuid, new_settings.clone());
+                }
+                gui_state.display_engine.map_edited();
+                gui_state.display_engine.graphics_update_needed = true;
+            }
+        }
+    }
+}
+
 fn is_settings_string_valid(settings_string: &str, ideal_len: usize) -> bool {
     let mut test_settings: Vec<u8> = Vec::new();
     let split: Vec<&str> = settings_string.split(' ').collect();
@@ -112,59 +270,147 @@ fn is_settings_string_valid(settings_string: &str, ideal_len: usize) -> bool {
     test_settings.len() == ideal_len
 }
 
+/// Which Sprites the list on the left shows
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpriteListFilter {
+    #[default]
+    All,
+    Selected,
+    /// Inside the main grid's visible area
+    InView
+}
+
+#[derive(Default)]
+pub struct SpriteListState {
+    pub filter: SpriteListFilter,
+    /// Scroll the list to the newest selected Sprite when it changes
+    pub follow_selection: bool,
+    /// Where a Shift-click range starts
+    anchor: Option<Uuid>,
+    /// Newest selected Sprite already scrolled to
+    followed: Option<Uuid>
+}
+
+/// New selection after clicking `clicked` in the list. Shift picks everything shown
+/// between the anchor and it, Ctrl toggles it, and both adds the range
+fn list_click_selection(selected: &[Uuid], shown: &[Uuid], anchor: Option<Uuid>, clicked: Uuid, shift: bool, command: bool) -> Vec<Uuid> {
+    let anchor_pos = anchor.and_then(|a| shown.iter().position(|&uuid| uuid == a));
+    let clicked_pos = shown.iter().position(|&uuid| uuid == clicked);
+    if let (true, Some(anchor_pos), Some(clicked_pos)) = (shift, anchor_pos, clicked_pos) {
+        let range = &shown[anchor_pos.min(clicked_pos)..=anchor_pos.max(clicked_pos)];
+        let mut new_selection: Vec<Uuid> = if command { selected.to_vec() } else { Vec::new() };
+        for uuid in range {
+            if !new_selection.contains(uuid) {
+                new_selection.push(*uuid);
+            }
+        }
+        return new_selection;
+    }
+    if command {
+        let mut new_selection = selected.to_vec();
+        if let Some(pos) = new_selection.iter().position(|&uuid| uuid == clicked) {
+            new_selection.remove(pos);
+        } else {
+            new_selection.push(clicked);
+        }
+        return new_selection;
+    }
+    vec![clicked]
+}
+
+fn handle_row_click(ctx: &egui::Context, gui_state: &mut Gui, shown: &[Uuid], index: usize, uuid: Uuid) {
+    let modifiers = ctx.input(|i| i.modifiers);
+    if !modifiers.shift && !modifiers.command {
+        gui_state.select_sprite_from_list(&index, &uuid);
+        gui_state.sprite_list.anchor = Some(uuid);
+        gui_state.sprite_list.followed = Some(uuid);
+        return;
+    }
+    let de = &mut gui_state.display_engine;
+    de.selected_sprite_uuids = list_click_selection(&de.selected_sprite_uuids, shown, gui_state.sprite_list.anchor, uuid, modifiers.shift, modifiers.command);
+    if !modifiers.shift {
+        gui_state.sprite_list.anchor = Some(uuid);
+    }
+    // Already on screen, no need to follow it
+    gui_state.sprite_list.followed = de.selected_sprite_uuids.last().copied();
+    if let [only] = de.selected_sprite_uuids[..] {
+        if let Some(spr) = de.loaded_map.get_sprite_by_uuid(only) {
+            de.latest_sprite_settings = bytes_to_hex_string(&spr.settings);
+            de.latest_sprite_object_id = spr.object_id;
+        }
+    }
+}
+
 fn render_table(ui: &mut egui::Ui, gui_state: &mut Gui) {
     let row_height = 20.0;
-    let sprite_count = &gui_state.display_engine.level_sprites.len();
+    let base = gui_state.display_engine.display_settings.number_base;
+    ui.label(format!("{} sprites, {} selected",gui_state.display_engine.level_sprites.len(),gui_state.display_engine.selected_sprite_uuids.len()));
+    ui.horizontal_wrapped(|ui| {
+        ui.selectable_value(&mut gui_state.sprite_list.filter, SpriteListFilter::All, "All");
+        ui.selectable_value(&mut gui_state.sprite_list.filter, SpriteListFilter::Selected, "Selected");
+        ui.selectable_value(&mut gui_state.sprite_list.filter, SpriteListFilter::InView, "In view")
+            .on_hover_text("Only Sprites in the visible part of the map");
+    });
+    ui.checkbox(&mut gui_state.sprite_list.follow_selection, "Follow selection")
+        .on_hover_text("Scroll the list to the newest selected Sprite");
+    let viewport = gui_state.grid_viewport;
+    let shown: Vec<usize> = gui_state.display_engine.level_sprites.iter().enumerate().filter(|(_, spr)| {
+        match gui_state.sprite_list.filter {
+            SpriteListFilter::All => true,
+            SpriteListFilter::Selected => gui_state.display_engine.selected_sprite_uuids.contains(&spr.uuid),
+            SpriteListFilter::InView => viewport.contains(Pos2::new(spr.x_position as f32 * 8.0, spr.y_position as f32 * 8.0))
+        }
+    }).map(|(index, _)| index).collect();
+    let shown_uuids: Vec<Uuid> = shown.iter().map(|&index| gui_state.display_engine.level_sprites[index].uuid).collect();
+    let mut scroll_to_row: Option<usize> = Option::None;
+    let newest = gui_state.display_engine.selected_sprite_uuids.last().copied();
+    if gui_state.sprite_list.follow_selection && newest.is_some() && newest != gui_state.sprite_list.followed {
+        scroll_to_row = shown_uuids.iter().position(|&uuid| Some(uuid) == newest);
+        gui_state.sprite_list.followed = newest;
+    }
     ScrollArea::vertical().max_height(f32::INFINITY).show(ui, |ui| {
-        let _table = TableBuilder::new(ui)
+        let mut table = TableBuilder::new(ui)
             .striped(false)
             .resizable(false)
             .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
             .column(Column::exact(145.0))
             //.min_scrolled_height(0.0)
-            .sense(egui::Sense::click())
+            .sense(egui::Sense::click());
+        if let Some(row) = scroll_to_row {
+            table = table.scroll_to_row(row, Some(egui::Align::Center));
+        }
+        let _table = table
             .body(|body| {
-                body.heterogeneous_rows((0..*sprite_count).map(|_| row_height), |mut row| {
-                    let index = row.index();
+                body.heterogeneous_rows(shown.iter().map(|_| row_height), |mut row| {
+                    let index = shown[row.index()];
                     let cur_sprite = gui_state.display_engine.level_sprites[index].clone();
-                    if !SPRITE_METADATA.contains_key(&cur_sprite.object_id) {
-                        row.col(|ui| {
-                            let missing_sprite = ui.label(format!("Missing metadata (0x{:X}, len {:X})",
-                                &cur_sprite.object_id,&cur_sprite.settings_length));
-                            if missing_sprite.clicked() {
-                                log_write(format!("Could not get sprite metadata for object ID '0x{:X}'",&cur_sprite.object_id), LogLevel::Error);
-                                log_write(format!("Settings length: 0x{:X}; data: {:?}",&cur_sprite.settings_length,&cur_sprite.settings), LogLevel::Log);
-                            }
-                        });
-                        return;
-                    }
-                    let sprite_meta: &SpriteMetadata = &SPRITE_METADATA[&cur_sprite.object_id];
+                    let known_meta = sprite_meta(cur_sprite.object_id);
+                    let is_missing = known_meta.is_none();
+                    let meta = known_meta.unwrap_or_else(|| Arc::new(SpriteMetadata::inferred(&cur_sprite)));
+                    let mut label_clicked = false;
                     let (_,row_res) = row.col(|ui| {
-                        if gui_state.display_engine.selected_sprite_uuids.contains(&cur_sprite.uuid) {
-                            let res = ui.label(&sprite_meta.name)
-                                .interact(egui::Sense::hover())
-                                .interact(egui::Sense::click())
-                                .highlight();
-                            if res.hovered() {
-                                ui.output_mut(|o| o.cursor_icon = egui::CursorIcon::PointingHand);
-                            }
-                            if res.clicked() {
-                                gui_state.select_sprite_from_list(&index, &cur_sprite.uuid);
-                            }
+                        let name_text = if is_missing {
+                            // Still selectable, so it can be moved or deleted
+                            RichText::new(format!("{} (len {})",meta.name,base.fmt_num(cur_sprite.settings_length, 1))).color(Color32::ORANGE)
                         } else {
-                            let res = ui.label(&sprite_meta.name)
-                                .interact(egui::Sense::hover())
-                                .interact(egui::Sense::click());
-                            if res.hovered() {
-                                ui.output_mut(|o| o.cursor_icon = egui::CursorIcon::PointingHand);
-                            }
-                            if res.clicked() {
-                                gui_state.select_sprite_from_list(&index, &cur_sprite.uuid);
-                            }
+                            RichText::new(&meta.name)
+                        };
+                        let mut res = ui.label(name_text)
+                            .interact(egui::Sense::hover())
+                            .interact(egui::Sense::click());
+                        if gui_state.display_engine.selected_sprite_uuids.contains(&cur_sprite.uuid) {
+                            res = res.highlight();
+                        }
+                        if res.hovered() {
+                            ui.output_mut(|o| o.cursor_icon = egui::CursorIcon::PointingHand);
+                        }
+                        if res.clicked() {
+                            label_clicked = true;
+                            handle_row_click(ui.ctx(), gui_state, &shown_uuids, index, cur_sprite.uuid);
                         }
                     });
-                    if row_res.clicked() {
-                        gui_state.select_sprite_from_list(&index, &cur_sprite.uuid);
+                    if row_res.clicked() && !label_clicked {
+                        handle_row_click(&row_res.ctx, gui_state, &shown_uuids, index, cur_sprite.uuid);
                     }
                 });
             });
@@ -178,8 +424,28 @@ fn settings_save_check(gui_state: &mut Gui, comp: Vec<u8>, sprite: &LevelSprite)
             utils::print_vector_u8(&sprite.settings);
             utils::print_vector_u8(&comp);
         }
-        gui_state.display_engine.unsaved_changes = true;
+        gui_state.display_engine.map_edited();
         gui_state.display_engine.graphics_update_needed = true;
         gui_state.display_engine.loaded_map.update_sprite_settings(sprite.uuid, comp);
     }
 }
+
+#[cfg(test)]
+mod tests_spritepanel {
+    use super::*;
+
+    #[test]
+    fn test_list_click_selection() {
+        let ids: Vec<Uuid> = (0..5).map(|_| Uuid::new_v4()).collect();
+        let shown = &ids[..4];
+        // Plain click replaces, Ctrl toggles
+        assert_eq!(list_click_selection(&[ids[0]], shown, None, ids[2], false, false),vec![ids[2]]);
+        assert_eq!(list_click_selection(&[ids[0]], shown, None, ids[2], false, true),vec![ids[0], ids[2]]);
+        assert_eq!(list_click_selection(&[ids[0], ids[2]], shown, None, ids[0], false, true),vec![ids[2]]);
+        // Shift takes the shown range either way round
+        assert_eq!(list_click_selection(&[ids[3]], shown, Some(ids[3]), ids[1], true, false),vec![ids[1], ids[2], ids[3]]);
+        assert_eq!(list_click_selection(&[ids[4]], shown, Some(ids[0]), ids[1], true, true),vec![ids[4], ids[0], ids[1]]);
+        // Anchor filtered out of the list, so it's a plain click
+        assert_eq!(list_click_selection(&[ids[4]], shown, Some(ids[4]), ids[1], true, false),vec![ids[1]]);
+    }
+}
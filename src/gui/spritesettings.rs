@@ -2,7 +2,7 @@ use byteorder::{LittleEndian, WriteBytesExt};
 
 use crate::{data::sprites::LevelSprite, NON_MAIN_FOCUSED};
 
-use super::SpriteSettings;
+use super::{gui::NumberBase, SpriteSettings};
 
 
 
@@ -23,7 +23,7 @@ pub struct ShyGuy {
     pub behavior: u8
 }
 impl SpriteSettings for ShyGuy {
-    fn show_ui(&mut self, ui: &mut egui::Ui) -> egui::Response {
+    fn show_ui(&mut self, ui: &mut egui::Ui, _base: NumberBase) -> egui::Response {
         ui.label("Behavior");
         egui::ComboBox::from_label("")
             .selected_text(match self.behavior {
@@ -54,10 +54,9 @@ pub struct HintBlock {
     pub message: u16
 }
 impl SpriteSettings for HintBlock {
-    fn show_ui(&mut self, ui: &mut egui::Ui) -> egui::Response {
+    fn show_ui(&mut self, ui: &mut egui::Ui, base: NumberBase) -> egui::Response {
         ui.horizontal(|ui| {
-            let drag_val = egui::DragValue::new(&mut self.message)
-                .hexadecimal(2, false, true)
+            let drag_val = base.drag_value(egui::DragValue::new(&mut self.message), 2)
                 .range(0..=0x150);
             let dvres = ui.add(drag_val);
             if dvres.has_focus() {
@@ -85,7 +84,7 @@ pub struct RedArrowSign {
     pub order: i8
 }
 impl SpriteSettings for RedArrowSign {
-    fn show_ui(&mut self, ui: &mut egui::Ui) -> egui::Response {
+    fn show_ui(&mut self, ui: &mut egui::Ui, base: NumberBase) -> egui::Response {
         ui.label("Kind");
         egui::ComboBox::new(egui::Id::new("kind"), "")
             .selected_text(match self.kind {
@@ -99,7 +98,7 @@ impl SpriteSettings for RedArrowSign {
                 0x7 => "Down Left Decal".to_string(),
                 0x8 => "Left Decal".to_string(),
                 0x9 => "Up Left Decal".to_string(),
-                _ => format!("Unknown: 0x{:X}",self.kind)
+                _ => format!("Unknown: {}",base.fmt_num(self.kind, 1))
             })
             .show_ui(ui, |ui| {
                 ui.selectable_value(&mut self.kind, 0, "Left Signpost");
@@ -119,7 +118,7 @@ impl SpriteSettings for RedArrowSign {
             .selected_text(match self.order {
                 -2 => "Before Yoshi".to_string(),
                 -1 => "Behind Yoshi".to_string(),
-                _ => format!("Unknown value: 0x{:X}",self.order)
+                _ => format!("Unknown value: {}",base.fmt_num(self.order, 1))
             })
             .show_ui(ui, |ui| {
                 ui.selectable_value(&mut self.order, -2, "Before Yoshi");
@@ -149,7 +148,7 @@ pub struct GreenPipe {
     pub length: u16
 }
 impl SpriteSettings for GreenPipe {
-    fn show_ui(&mut self, ui: &mut egui::Ui) -> egui::Response {
+    fn show_ui(&mut self, ui: &mut egui::Ui, base: NumberBase) -> egui::Response {
         ui.label("Direction");
         egui::ComboBox::new(egui::Id::new("direction_combo_box"), "")
             .selected_text(match self.direction {
@@ -157,7 +156,7 @@ impl SpriteSettings for GreenPipe {
                 1 => "Up".to_string(),
                 2 => "Right".to_string(),
                 3 => "Left".to_string(),
-                _ => format!("Unknown value: 0x{:X}",self.direction)
+                _ => format!("Unknown value: {}",base.fmt_num(self.direction, 1))
             })
             .show_ui(ui, |ui| {
                 ui.selectable_value(&mut self.direction, 0, "Down");
@@ -167,8 +166,7 @@ impl SpriteSettings for GreenPipe {
             }            
         );
         ui.label("Length");
-        let drag_val = egui::DragValue::new(&mut self.length)
-            .hexadecimal(4, false, true)
+        let drag_val = base.drag_value(egui::DragValue::new(&mut self.length), 4)
             .range(0..=0xffff);
         let dvres = ui.add(drag_val);
         if dvres.has_focus() {
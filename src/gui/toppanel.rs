@@ -1,6 +1,6 @@
-use crate::{data::{course_file::CourseInfo, mapfile::MapData, types::CurrentLayer}, engine::displayengine::GameVersion, utils::{self, log_write, LogLevel}};
+use crate::{data::{course_file::CourseInfo, mapfile::MapData, types::CurrentLayer}, engine::displayengine::{DisplaySettings, GameVersion}, utils::{self, log_write, LogLevel}};
 
-use super::gui::Gui;
+use super::{gui::Gui, windows::level_switcher::{RecentMap, SwitchTarget}};
 use egui::Button;
 use strum::IntoEnumIterator;
 
@@ -22,6 +22,17 @@ pub fn top_panel_show(ui: &mut egui::Ui, gui_state: &mut Gui) {
                 ui.close_menu();
                 gui_state.do_open_project();
             }
+            let button_open_config = ui.button("Open Project from config.yaml...");
+            if button_open_config.clicked() {
+                ui.close_menu();
+                gui_state.do_open_project_config();
+            }
+            let button_view_file = ui.button("View File (Read-Only)...")
+                .on_hover_text("Look inside a single .crsb or .mpdz without opening its project");
+            if button_view_file.clicked() {
+                ui.close_menu();
+                gui_state.do_view_file();
+            }
             ui.separator();
             let button_change_course = ui.add_enabled(gui_state.project_open, Button::new("Change Course"));
             if button_change_course.clicked() {
@@ -33,6 +44,36 @@ pub fn top_panel_show(ui: &mut egui::Ui, gui_state: &mut Gui) {
                 gui_state.do_change_map();
                 ui.close_menu();
             }
+            let button_go_to = ui.add_enabled(gui_state.project_open, Button::new("Go to...").shortcut_text("Ctrl+G"));
+            if button_go_to.clicked() {
+                ui.close_menu();
+                gui_state.level_switcher.open(&gui_state.display_engine);
+            }
+            let button_copy_map = ui.add_enabled(gui_state.project_open, Button::new("Copy Map to Course..."))
+                .on_hover_text("Adds a copy of this map to another Course, without leaving this one");
+            if button_copy_map.clicked() {
+                ui.close_menu();
+                gui_state.copy_map_open = true;
+            }
+            ui.add_enabled_ui(gui_state.project_open && gui_state.recent_maps.maps.len() > 1, |ui| {
+                ui.menu_button("Recent Maps", |ui| {
+                    let mut chosen: Option<RecentMap> = Option::None;
+                    // The first is the loaded map
+                    for (recent_index, recent) in gui_state.recent_maps.maps.iter().enumerate().skip(1) {
+                        let mut button = Button::new(recent.label());
+                        if recent_index == 1 {
+                            button = button.shortcut_text("Ctrl+Tab");
+                        }
+                        if ui.add(button).clicked() {
+                            chosen = Some(recent.clone());
+                        }
+                    }
+                    if let Some(recent) = chosen {
+                        ui.close_menu();
+                        gui_state.do_switch(SwitchTarget::Recent(recent));
+                    }
+                });
+            });
             ui.separator();
             let button_save = ui.add_enabled(gui_state.project_open, Button::new("Save"));
             if button_save.clicked() {
@@ -44,6 +85,31 @@ pub fn top_panel_show(ui: &mut egui::Ui, gui_state: &mut Gui) {
                 ui.close_menu();
                 gui_state.do_export();
             }
+            let last_rom_hover = gui_state.project_settings.last_rom_path.as_ref()
+                .map_or(String::from("Asks where the first time"), |path| path.display().to_string());
+            let button_export_last = ui.add_enabled(gui_state.project_open, Button::new("Export to Last Location").shortcut_text("Ctrl+E"))
+                .on_hover_text(last_rom_hover);
+            if button_export_last.clicked() {
+                ui.close_menu();
+                gui_state.do_export_to_last();
+            }
+            let button_report = ui.add_enabled(gui_state.project_open && gui_state.report_receiver.is_none(), Button::new("Export Level Report..."));
+            if button_report.clicked() {
+                ui.close_menu();
+                gui_state.do_export_report();
+            }
+            let button_dump = ui.add_enabled(gui_state.project_open, Button::new("Dump Map to JSON..."))
+                .on_hover_text("Tiles, Sprites, collision, paths and warps of the current map, for other tools");
+            if button_dump.clicked() {
+                ui.close_menu();
+                gui_state.do_dump_map_json();
+            }
+            let button_import_sprites = ui.add_enabled(gui_state.project_open, Button::new("Import Sprites from JSON..."))
+                .on_hover_text("Adds Sprites from a list of object_id, x, y and settings, or from a map dump");
+            if button_import_sprites.clicked() {
+                ui.close_menu();
+                gui_state.do_import_sprites_json();
+            }
             ui.separator();
             let button_project_settings = ui.add_enabled(gui_state.project_open, Button::new("Settings"));
             if button_project_settings.clicked() {
@@ -56,6 +122,7 @@ pub fn top_panel_show(ui: &mut egui::Ui, gui_state: &mut Gui) {
                 gui_state.clear_map_data();
                 gui_state.display_engine.loaded_map = MapData::default();
                 gui_state.display_engine.loaded_course = CourseInfo::default();
+                gui_state.display_engine.saved_course = Vec::new();
                 gui_state.project_open = false;
                 gui_state.display_engine.game_version = GameVersion::Unknown;
             }
@@ -69,14 +136,14 @@ pub fn top_panel_show(ui: &mut egui::Ui, gui_state: &mut Gui) {
             if !gui_state.project_open {
                 ui.disable();
             }
-            let has_undos = gui_state.undoer.has_undo(&gui_state.display_engine.loaded_map);
-            let button_undo = ui.add_enabled(has_undos, Button::new("Undo"));
+            let has_undos = gui_state.map_history.has_undo(&gui_state.display_engine.loaded_map);
+            let button_undo = ui.add_enabled(has_undos, Button::new("Undo").shortcut_text("Ctrl+Z"));
             if button_undo.clicked() {
                 ui.close_menu();
                 gui_state.do_undo();
             }
-            let has_redos = gui_state.undoer.has_redo(&gui_state.display_engine.loaded_map);
-            let button_redo = ui.add_enabled(has_redos, Button::new("Redo"));
+            let has_redos = gui_state.map_history.has_redo(&gui_state.display_engine.loaded_map);
+            let button_redo = ui.add_enabled(has_redos, Button::new("Redo").shortcut_text("Ctrl+Y"));
             if button_redo.clicked() {
                 ui.close_menu();
                 gui_state.do_redo();
@@ -92,11 +159,17 @@ pub fn top_panel_show(ui: &mut egui::Ui, gui_state: &mut Gui) {
                 ui.close_menu();
                 gui_state.do_copy();
             }
-            let button_paste = ui.add_enabled(gui_state.is_paste_possible(), Button::new("Paste"));
+            let button_paste = ui.add_enabled(gui_state.is_paste_possible(), Button::new("Paste").shortcut_text("Ctrl+V"));
             if button_paste.clicked() {
                 ui.close_menu();
                 gui_state.do_paste();
             }
+            let button_paste_in_place = ui.add_enabled(gui_state.is_paste_possible(), Button::new("Paste in Place").shortcut_text("Ctrl+Shift+V"))
+                .on_hover_text("Paste at the copied position instead of the cursor");
+            if button_paste_in_place.clicked() {
+                ui.close_menu();
+                gui_state.do_paste_in_place();
+            }
             ui.separator();
             let button_select_all = ui.button("Select All");
             if button_select_all.clicked() {
@@ -114,6 +187,10 @@ pub fn top_panel_show(ui: &mut egui::Ui, gui_state: &mut Gui) {
                 gui_state.clear_modal_open = true;
                 ui.close_menu();
             }
+            if ui.button("Clear Selection...").clicked() {
+                gui_state.do_open_clear_selection();
+                ui.close_menu();
+            }
             let button_resize = ui.button("Resize layer");
             if button_resize.clicked() {
                 if gui_state.display_engine.display_settings.is_cur_layer_bg() {
@@ -132,6 +209,12 @@ pub fn top_panel_show(ui: &mut egui::Ui, gui_state: &mut Gui) {
                     gui_state.do_alert(format!("Cannot resize on layer '{:?}', dimensions controlled by BG layers",cur_layer));
                 }
             }
+            let button_shift_map = ui.button("Shift Map...");
+            if button_shift_map.clicked() {
+                gui_state.shift_map_settings.preview = None;
+                gui_state.shift_map_settings.window_open = true;
+                ui.close_menu();
+            }
         });
         // View Menu //
         ui.menu_button("View", |ui| {
@@ -142,6 +225,33 @@ pub fn top_panel_show(ui: &mut egui::Ui, gui_state: &mut Gui) {
             let _button_close_windows = ui.button("Close Windows");
             let _button_sort_windows = ui.button("Sort Windows");
         });
+        // Tools Menu //
+        ui.menu_button("Tools", |ui| {
+            let button_verify = ui.add_enabled(gui_state.project_open && gui_state.verify_receiver.is_none(), Button::new("Verify Project"))
+                .on_hover_text("Checks that every saved Map and Course reloads and saves unchanged");
+            if button_verify.clicked() {
+                ui.close_menu();
+                gui_state.do_verify_project();
+            }
+            let button_script = ui.add_enabled(gui_state.project_open, Button::new("Run Script..."))
+                .on_hover_text("Batch edits on the loaded map or every map, with a dry run to check first");
+            if button_script.clicked() {
+                ui.close_menu();
+                gui_state.script_window_open = true;
+            }
+            let button_refresh = ui.add_enabled(gui_state.project_open, Button::new("Refresh Graphics").shortcut_text("F5"))
+                .on_hover_text("Redraws the Map from its data, if something looks out of date");
+            if button_refresh.clicked() {
+                ui.close_menu();
+                gui_state.do_refresh_graphics();
+            }
+            let button_reload_tileset = ui.add_enabled(gui_state.project_open && gui_state.display_engine.display_settings.is_cur_layer_bg(), Button::new("Reload Tileset"))
+                .on_hover_text("Reads the current BG's tiles again, after changing its IMBZ outside Stork");
+            if button_reload_tileset.clicked() {
+                ui.close_menu();
+                gui_state.do_reload_tileset();
+            }
+        });
         // Help Menu //
         ui.menu_button("Help", |ui| {
             let button_about = ui.button("About");
@@ -168,42 +278,73 @@ pub fn top_panel_show(ui: &mut egui::Ui, gui_state: &mut Gui) {
     }); // End top menu bar
 
     ui.horizontal(|ui|{
+        let has_undos = gui_state.project_open && gui_state.map_history.has_undo(&gui_state.display_engine.loaded_map);
+        if ui.add_enabled(has_undos, Button::new("Undo")).on_hover_text("Ctrl+Z").clicked() {
+            gui_state.do_undo();
+        }
+        let has_redos = gui_state.project_open && gui_state.map_history.has_redo(&gui_state.display_engine.loaded_map);
+        if ui.add_enabled(has_redos, Button::new("Redo")).on_hover_text("Ctrl+Y").clicked() {
+            gui_state.do_redo();
+        }
+        ui.separator();
         ui.label("Layer").on_hover_ui(|ui|{
             ui.label("This dropdown determines what layer to work with, and locks the rest");
         });
-        let selected_bg: &mut CurrentLayer = &mut gui_state.display_engine.display_settings.current_layer;
-        let old_selected_bg = *selected_bg;
+        let mut selected_bg: CurrentLayer = gui_state.display_engine.display_settings.current_layer;
         let _cur_layer_combo = egui::ComboBox::from_label("")
             .selected_text(format!("{selected_bg:?}"))
             .show_ui(ui, |ui| {
                 for layer in CurrentLayer::iter() {
-                    ui.selectable_value(selected_bg, layer, format!("{layer:?}"));
+                    ui.selectable_value(&mut selected_bg, layer, format!("{layer:?}"));
                 }
             });
-        if *selected_bg != old_selected_bg {
-            log_write("Cleaning up due to layer change", LogLevel::Debug);
-            gui_state.display_engine.brush_settings.cur_selected_brush = Option::None;
-            gui_state.display_engine.current_brush.clear();
-            gui_state.display_engine.clipboard.bg_clip.clear();
-            gui_state.display_engine.bg_sel_data.clear();
-            gui_state.display_engine.selected_preview_tile = None;
-        }
+        gui_state.display_engine.change_layer(selected_bg);
         egui::ComboBox::new(egui::Id::new("visible_layers_drop"), "")
             .selected_text("Visible layers")
             .show_ui(ui, |ui| {
-                ui.checkbox(&mut gui_state.display_engine.display_settings.show_col, "Collision");
-                ui.checkbox(&mut gui_state.display_engine.display_settings.show_sprites, "Sprites");
-                ui.checkbox(&mut gui_state.display_engine.display_settings.show_bg1, "BG 1");
-                ui.checkbox(&mut gui_state.display_engine.display_settings.show_bg2, "BG 2");
-                ui.checkbox(&mut gui_state.display_engine.display_settings.show_bg3, "BG 3");
-                ui.checkbox(&mut gui_state.display_engine.display_settings.show_paths, "Paths");
-                ui.checkbox(&mut gui_state.display_engine.display_settings.show_triggers, "Triggers");
+                let display_settings = &mut gui_state.display_engine.display_settings;
+                layer_visibility_row(ui, display_settings, CurrentLayer::Collision, "Collision");
+                layer_visibility_row(ui, display_settings, CurrentLayer::Sprites, "Sprites");
+                layer_visibility_row(ui, display_settings, CurrentLayer::BG1, "BG 1");
+                layer_visibility_row(ui, display_settings, CurrentLayer::BG2, "BG 2");
+                layer_visibility_row(ui, display_settings, CurrentLayer::BG3, "BG 3");
+                ui.checkbox(&mut gui_state.display_engine.display_settings.show_gradient, "Gradient");
+                let display_settings = &mut gui_state.display_engine.display_settings;
+                layer_visibility_row(ui, display_settings, CurrentLayer::Paths, "Paths");
+                layer_visibility_row(ui, display_settings, CurrentLayer::Triggers, "Triggers");
                 ui.checkbox(&mut gui_state.display_engine.display_settings.show_entrances, "Entrances");
                 ui.checkbox(&mut gui_state.display_engine.display_settings.show_exits, "Exits");
                 ui.checkbox(&mut gui_state.display_engine.display_settings.show_breakable_rock, "Soft Rock Back");
+                ui.checkbox(&mut gui_state.display_engine.display_settings.show_sprite_ranges, "Sprite ranges")
+                    .on_hover_text("How far Pipes, Pinwheels and other known Sprites reach");
+                ui.checkbox(&mut gui_state.display_engine.display_settings.show_rulers, "Rulers")
+                    .on_hover_text("Tile coordinates along the top and left of the map");
             });
         let x = gui_state.display_engine.tile_hover_pos.x as u16;
         let y = gui_state.display_engine.tile_hover_pos.y as u16;
-        ui.label(format!("Tile x/y: {:04X}/{:04X}",x,y));
+        let base = gui_state.display_engine.display_settings.number_base;
+        ui.label(format!("Tile x/y: {}/{}",base.fmt_num(x, 4),base.fmt_num(y, 4)));
+        if gui_state.display_engine.display_settings.is_cur_layer_hidden() {
+            ui.colored_label(egui::Color32::ORANGE, "Layer hidden, editing disabled")
+                .on_hover_text("Show it in Visible layers, or turn on auto-show in Settings");
+        } else if gui_state.display_engine.display_settings.is_cur_layer_locked() {
+            ui.colored_label(egui::Color32::ORANGE, "Layer locked, editing disabled")
+                .on_hover_text("Unlock it in Visible layers");
+        }
+    });
+}
+
+/// Visibility checkbox with a lock toggle next to it
+fn layer_visibility_row(ui: &mut egui::Ui, display_settings: &mut DisplaySettings, layer: CurrentLayer, label: &str) {
+    ui.horizontal(|ui| {
+        let mut locked = display_settings.is_layer_locked(layer);
+        if ui.toggle_value(&mut locked, "🔒").on_hover_text("Locked layers are still drawn, but ignore clicks and edits").changed() {
+            log_write(format!("{} layer {:?}",if locked { "Locking" } else { "Unlocking" },layer), LogLevel::Debug);
+            display_settings.set_layer_locked(layer, locked);
+        }
+        let mut visible = display_settings.is_layer_visible(layer);
+        if ui.checkbox(&mut visible, label).changed() {
+            display_settings.set_layer_visible(layer, visible);
+        }
     });
 }
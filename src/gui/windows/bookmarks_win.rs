@@ -0,0 +1,40 @@
+use crate::engine::project_settings::ProjectSettings;
+
+pub enum BookmarkAction {
+    Jump(u8),
+    /// Renamed or deleted, so the project settings need saving
+    Changed
+}
+
+pub fn show_bookmarks_window(ui: &mut egui::Ui, settings: &mut ProjectSettings, map_name: &str) -> Option<BookmarkAction> {
+    puffin::profile_function!();
+    let mut action: Option<BookmarkAction> = Option::None;
+    let Some(map_bookmarks) = settings.bookmarks.get_mut(map_name) else {
+        ui.label("No bookmarks on this map");
+        ui.label("Ctrl+Shift+1-9 saves the current view");
+        return None;
+    };
+    let mut to_remove: Option<u8> = Option::None;
+    egui::Grid::new("bookmarks_grid").num_columns(4).show(ui, |ui| {
+        for bookmark in map_bookmarks.iter_mut() {
+            ui.label(format!("Ctrl+{}",bookmark.slot));
+            let name_edit = ui.add(egui::TextEdit::singleline(&mut bookmark.name).desired_width(140.0));
+            if name_edit.lost_focus() {
+                action = Some(BookmarkAction::Changed);
+            }
+            if ui.button("Go").on_hover_text(format!("Switches to {:?}",bookmark.layer)).clicked() {
+                action = Some(BookmarkAction::Jump(bookmark.slot));
+            }
+            if ui.button("Delete").clicked() {
+                to_remove = Some(bookmark.slot);
+            }
+            ui.end_row();
+        }
+    });
+    if let Some(slot) = to_remove {
+        settings.remove_bookmark(map_name, slot);
+        action = Some(BookmarkAction::Changed);
+    }
+    ui.label("Ctrl+Shift+1-9 saves the current view");
+    action
+}
@@ -1,9 +1,9 @@
-use std::{fmt, sync::LazyLock};
+use std::{collections::HashMap, fmt, sync::LazyLock};
 
-use egui::{Color32, Painter, Pos2, Rect, Response, RichText, Stroke, Vec2};
+use egui::{Color32, ColorImage, Painter, Pos2, Rect, Response, RichText, Stroke, TextureHandle, Vec2};
 use serde::{Deserialize, Serialize};
 
-use crate::{data::types::{MapTileRecordData, Palette}, engine::displayengine::DisplayEngine, utils::{color_image_from_pal, get_pixel_bytes_16, get_uvs_from_tile, log_write, pixel_byte_array_to_nibbles, LogLevel}};
+use crate::{data::types::{MapTileRecordData, Palette}, engine::displayengine::DisplayEngine, gui::gui::BgSelectData, utils::{color_image_from_pal, get_pixel_bytes_16, get_pixel_bytes_256, get_uvs_from_tile, log_write, pixel_byte_array_to_nibbles, LogLevel}};
 
 #[derive(Serialize,Deserialize,Clone,Debug)]
 pub struct StoredBrushes {
@@ -13,9 +13,48 @@ pub struct StoredBrushes {
 pub static STORED_BRUSHES: LazyLock<StoredBrushes> = LazyLock::new(|| {
     let value = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/stored_brushes.json"));
     // log_write("Loaded stored brushes JSON, not parsed yet", LogLevel::Debug);
-    serde_json::from_str(value).expect("Valid stored_brushes.json file")
+    let mut stored: StoredBrushes = serde_json::from_str(value).expect("Valid stored_brushes.json file");
+    stored.brushes.iter_mut().for_each(Brush::upgrade_format);
+    stored
 });
 
+/// Version 1 stores empty spots as null, before that tile 0 meant empty
+pub const BRUSH_FORMAT_VERSION: u8 = 1;
+
+/// Brushes with the same name on different tilesets, matched up tile by tile
+pub static TILE_MAPPINGS: LazyLock<TileMappings> = LazyLock::new(|| {
+    let value = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/tile_mappings.json"));
+    serde_json::from_str(value).expect("Valid tile_mappings.json file")
+});
+
+/// The flip bits of a Brush tile, which mappings leave out
+const TILE_FLIP_BITS: u16 = 0x0C00;
+
+#[derive(Serialize,Deserialize,Clone,Debug,Default)]
+pub struct TileMappings {
+    pub mappings: Vec<TileMapping>
+}
+impl TileMappings {
+    pub fn find(&self, from_tileset: &str, to_tileset: &str) -> Option<&TileMapping> {
+        self.mappings.iter().find(|mapping| mapping.from == from_tileset && mapping.to == to_tileset)
+    }
+}
+
+/// Equivalent tiles going one way between two tilesets, as Brush tiles without
+/// the flip bits. A flipped tile gets its match flipped the same way
+#[derive(Serialize,Deserialize,Clone,Debug,Default)]
+pub struct TileMapping {
+    pub from: String,
+    pub to: String,
+    pub tiles: Vec<(u16, u16)>
+}
+impl TileMapping {
+    pub fn map_tile(&self, short: u16) -> Option<u16> {
+        let flips = short & TILE_FLIP_BITS;
+        self.tiles.iter().find(|(from, _)| *from == short & !TILE_FLIP_BITS).map(|(_, to)| to ^ flips)
+    }
+}
+
 #[derive(Serialize,Deserialize,Clone,Debug)]
 pub struct Brush {
     pub tileset: String,
@@ -24,7 +63,11 @@ pub struct Brush {
     pub height: u8,
     /// Is this needed?
     pub palette_offset: u8,
-    pub tiles: Vec<u16>
+    /// None is an empty spot, which stamping skips. Some(0) places tile 0
+    pub tiles: Vec<Option<u16>>,
+    /// Missing in older JSON files, see upgrade_format
+    #[serde(default)]
+    pub format_version: u8
 }
 impl Default for Brush {
     fn default() -> Self {
@@ -34,14 +77,15 @@ impl Default for Brush {
             width: 0,
             height: 0,
             palette_offset: 0,
-            tiles: vec![]
+            tiles: vec![],
+            format_version: BRUSH_FORMAT_VERSION
         }
     }
 }
 impl fmt::Display for Brush {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f,"Brush [ name='{}', tileset={}, width/height=0x{:X}/0x{:X}, first_tile={:04X} ]",
-            self.name,self.tileset,self.width,self.height,self.tiles[0])
+        write!(f,"Brush [ name='{}', tileset={}, width/height=0x{:X}/0x{:X}, first_tile={:04X?} ]",
+            self.name,self.tileset,self.width,self.height,self.tiles.first().copied().flatten())
     }
 }
 impl Brush {
@@ -51,9 +95,62 @@ impl Brush {
         self.width = 0;
         self.name = String::from("NAME CLEARED");
     }
+
+    /// Older Brushes used tile 0 for empty spots, call after loading JSON
+    pub fn upgrade_format(&mut self) {
+        if self.format_version == 0 {
+            for tile in &mut self.tiles {
+                if *tile == Some(0x0000) {
+                    *tile = None;
+                }
+            }
+        }
+        self.format_version = BRUSH_FORMAT_VERSION;
+    }
+
+    /// Copy with its tiles remapped onto another tileset, and how many had no
+    /// match. Those become empty spots. None if nothing maps between the two
+    pub fn convert_tileset(&self, to_tileset: &str, mappings: &TileMappings) -> Option<(Brush, usize)> {
+        let mapping = mappings.find(&self.tileset, to_tileset)?;
+        let mut converted = self.clone();
+        converted.tileset = to_tileset.to_string();
+        let mut unmapped: usize = 0;
+        for tile in &mut converted.tiles {
+            let Some(short) = *tile else { continue };
+            *tile = mapping.map_tile(short);
+            if tile.is_none() {
+                unmapped += 1;
+            }
+        }
+        Some((converted, unmapped))
+    }
+
+    /// Copy mirrored as a whole, so each tile moves and gets its flip bit toggled
+    pub fn flipped(&self, flip_h: bool, flip_v: bool) -> Brush {
+        let mut flipped = self.clone();
+        if !flip_h && !flip_v {
+            return flipped;
+        }
+        let (width, height) = (self.width as usize, self.height as usize);
+        for (index, tile) in self.tiles.iter().enumerate() {
+            let (x, y) = (index % width.max(1), index / width.max(1));
+            let new_x = if flip_h { width - 1 - x } else { x };
+            let new_y = if flip_v { height.saturating_sub(1 + y) } else { y };
+            let Some(new_tile) = flipped.tiles.get_mut(new_y * width + new_x) else {
+                continue;
+            };
+            *new_tile = tile.map(|short| {
+                let mut record = MapTileRecordData::new(short);
+                record.flip_h ^= flip_h;
+                record.flip_v ^= flip_v;
+                record.to_short()
+            });
+        }
+        flipped
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum BrushType {
     Stored,
     Saved,
@@ -65,7 +162,17 @@ pub struct BrushSettings {
     pub cur_search_string: String,
     pub only_show_same_tileset: bool,
     pub flip_x_place: bool,
-    pub flip_y_place: bool
+    pub flip_y_place: bool,
+    /// Tiles were placed or removed by hand since the Brush was loaded or stored
+    pub current_brush_edited: bool,
+    /// Waiting on the overwrite prompt, since the current Brush was edited
+    pub pending_load: Option<(BrushType, usize)>,
+    /// Hover previews in Saved Brushes, made when first hovered
+    pub preview_cache: HashMap<(BrushType, usize), TextureHandle>,
+    /// Previews are drawn with this BG's tiles and palettes
+    pub preview_cache_bg: u8,
+    /// What the last tileset conversion left blank, cleared on load
+    pub conversion_report: Option<String>
 }
 impl Default for BrushSettings {
     fn default() -> Self {
@@ -74,11 +181,39 @@ impl Default for BrushSettings {
             pos_brush_name: String::from("Untitled Brush"),
             cur_search_string: String::from(""),
             only_show_same_tileset: true,
-            flip_x_place: false, flip_y_place: false
+            flip_x_place: false, flip_y_place: false,
+            current_brush_edited: false,
+            pending_load: Option::None,
+            preview_cache: HashMap::new(),
+            preview_cache_bg: 0,
+            conversion_report: Option::None
         }
     }
 }
 
+impl BrushSettings {
+    /// Remaps the current Brush onto another tileset, see Brush::convert_tileset
+    pub fn convert_brush(&mut self, brush: &mut Brush, to_tileset: &str) -> bool {
+        let from_tileset = brush.tileset.clone();
+        let Some((converted, unmapped)) = brush.convert_tileset(to_tileset, &TILE_MAPPINGS) else {
+            log_write(format!("No tile mapping from '{}' to '{}'",from_tileset,to_tileset), LogLevel::Warn);
+            return false;
+        };
+        log_write(format!("Converted Brush '{}' from '{}' to '{}', {} tiles unmapped",
+            converted.name,from_tileset,to_tileset,unmapped), LogLevel::Log);
+        self.conversion_report = Some(if unmapped == 0 {
+            format!("Converted from '{from_tileset}'")
+        } else {
+            format!("Converted from '{from_tileset}', {unmapped} tiles had no match and were left blank")
+        });
+        *brush = converted;
+        // It no longer matches the Brush it was loaded from
+        self.cur_selected_brush = None;
+        self.current_brush_edited = true;
+        true
+    }
+}
+
 const BRUSH_TILE_DIM: f32 = 16.0;
 const BRUSH_TILES_WIDE: i32 = 16;
 const BRUSH_TILE_RECT: Vec2 = Vec2::new(BRUSH_TILE_DIM, BRUSH_TILE_DIM);
@@ -89,7 +224,6 @@ pub fn show_brushes_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
         // Technically unnecessary, but good for appearance
         ui.disable();
     }
-    let top_left = ui.min_rect().min;
     ui.allocate_space(Vec2 { x:260.0, y: 000.0 });
     let cur_layer = de.display_settings.current_layer as u8;
     if !de.display_settings.is_cur_layer_bg() {
@@ -101,10 +235,41 @@ pub fn show_brushes_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
             return;
         }
         let info = layer.get_info().expect("Brush layer must have INFO");
+        // Header, so it's always clear what the Brush will be stamped into
+        let tileset_name = info.imbz_filename_noext.clone().unwrap_or_else(|| "N/A".to_string());
+        let header_label = ui.label(format!("Tileset: '{}' | Color mode: 0x{:X} ({})",
+            tileset_name,info.color_mode,color_mode_name(info.color_mode)));
+        if header_label.hovered() {
+            egui::show_tooltip(ui.ctx(), ui.layer_id(), egui::Id::new("brush_header_tip"), |ui| {
+                ui.label("The tileset and color mode of the current layer");
+                ui.label("Brushes made on a different tileset will not look correct here");
+            });
+        }
+        if !de.current_brush.tiles.is_empty() && de.current_brush.tileset != tileset_name {
+            ui.horizontal(|ui| {
+                ui.colored_label(Color32::YELLOW, format!("Brush is from '{}'",de.current_brush.tileset));
+                let can_convert = TILE_MAPPINGS.find(&de.current_brush.tileset, &tileset_name).is_some();
+                let convert_button = ui.add_enabled(can_convert, egui::Button::new("Convert to Current Tileset"))
+                    .on_disabled_hover_text("No tile mapping between these tilesets");
+                if convert_button.clicked() {
+                    de.brush_settings.convert_brush(&mut de.current_brush, &tileset_name);
+                }
+            });
+        }
+        if let Some(report) = &de.brush_settings.conversion_report {
+            ui.label(report);
+        }
+        // 256 color layers use the first PLTB palette, like the main grid
+        let palette256 = layer.get_pltb().and_then(|pltb| pltb.palettes.first());
+        let is_256 = info.is_256_colorpal_mode();
+        if is_256 && palette256.is_none() {
+            ui.colored_label(Color32::YELLOW, "No 256 color palette on this layer, Brush tiles can't be previewed");
+        }
+        let top_left = ui.cursor().min;
         if let Some(tiles) = &layer.pixel_tiles_preview {
             do_tile_draw(
-                ui, &mut de.current_brush, &de.bg_palettes,
-                tiles,&info.color_mode,&layer._pal_offset
+                ui, top_left, &mut de.current_brush, &de.bg_palettes, palette256,
+                tiles,is_256,&layer._pal_offset
             );
         }
         let mut push_height: f32 = 260.0;
@@ -114,7 +279,8 @@ pub fn show_brushes_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
         }
         ui.add_space(push_height);
         // Interactivity
-        let click_response: Response = ui.interact(ui.min_rect(), egui::Id::new("saved_brushes_window_click"), egui::Sense::click());
+        let grid_rect = Rect::from_min_max(top_left, Pos2::new(ui.min_rect().max.x, top_left.y + push_height));
+        let click_response: Response = ui.interact(grid_rect, egui::Id::new("saved_brushes_window_click"), egui::Sense::click());
         // Left Click = Place New
         if click_response.clicked() {
             if let Some(pointer_pos) = ui.input(|i| i.pointer.latest_pos()) {
@@ -135,7 +301,7 @@ pub fn show_brushes_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
                     de.current_brush.height = (tile_y + 1) as u8;
                     let new_tile_count = (tile_x + 1) * (tile_y + 1);
                     for _ in 0..new_tile_count {
-                        de.current_brush.tiles.push(0x0000);
+                        de.current_brush.tiles.push(None);
                     }
                 }
                 if tile_x >= de.current_brush.width as u32 {
@@ -144,10 +310,10 @@ pub fn show_brushes_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
                     let old_width = de.current_brush.width as usize;
                     let increase_by = lines_to_add as usize;
                     let mut idx: usize = old_width;
-                    de.current_brush.tiles.resize(((de.current_brush.width + lines_to_add as u8) * de.current_brush.height) as usize, 0x0000);
+                    de.current_brush.tiles.resize(((de.current_brush.width + lines_to_add as u8) * de.current_brush.height) as usize, None);
                     while idx <= de.current_brush.tiles.len() {
                         for _ in 0..increase_by {
-                            de.current_brush.tiles.insert(idx, 0x0000);
+                            de.current_brush.tiles.insert(idx, None);
                         }
                         idx += old_width + increase_by;
                     }
@@ -159,7 +325,7 @@ pub fn show_brushes_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
                     let lines_to_add = tile_y - (de.current_brush.height as u32) + 1;
                     de.current_brush.height += lines_to_add as u8;
                     for _ in 0..(lines_to_add * de.current_brush.width as u32) {
-                        de.current_brush.tiles.push(0x0000);
+                        de.current_brush.tiles.push(None);
                     }
                     // Continue with new height
                 }
@@ -185,7 +351,8 @@ pub fn show_brushes_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
                     flip_v: de.brush_settings.flip_y_place
                 };
                 log_write(format!("Placing new tile to Brush: {}",new_tile), LogLevel::Debug);
-                de.current_brush.tiles[tile_index as usize] = new_tile.to_short();
+                de.current_brush.tiles[tile_index as usize] = Some(new_tile.to_short());
+                de.brush_settings.current_brush_edited = true;
             }
         }
         // Right Click = Delete
@@ -209,7 +376,9 @@ pub fn show_brushes_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
                     should_delete = false;
                 }
                 if should_delete {
-                    de.current_brush.tiles[tile_index as usize] = 0x0000;
+                    // Empty, so stamping leaves the map tile alone
+                    de.current_brush.tiles[tile_index as usize] = None;
+                    de.brush_settings.current_brush_edited = true;
                 }
             } else {
                 log_write("Failed to get pointer input when right clicking Saved Brushes grid", LogLevel::Error);
@@ -219,17 +388,19 @@ pub fn show_brushes_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
         if !de.bg_sel_data.selected_map_indexes.is_empty() {
             let sel_width: u16 = de.bg_sel_data.selection_width;
             let sel_height: u16 = de.bg_sel_data.selection_height;
-            let odd_size = sel_width % 2 != 0 || sel_height % 2 != 0;
+            let odd_size = !sel_width.is_multiple_of(2) || !sel_height.is_multiple_of(2);
             let raw_text = format!("Selection width/height: {}/{}",sel_width,sel_height);
             let Some(top_left) = de.bg_sel_data.get_top_left(layer.get_info().expect("Layer has INFO").layer_width) else {
                 log_write("Unable to get top left from bg selection in brushes", LogLevel::Error);
                 return;
             };
-            let odd_pos = (top_left.x as u32) % 2 != 0 || (top_left.y as u32) % 2 != 0;
+            let odd_pos = !(top_left.x as u32).is_multiple_of(2) || !(top_left.y as u32).is_multiple_of(2);
             let mut rich_text = RichText::new(raw_text);
+            let mut even_fixed = false;
             if odd_pos {
                 rich_text = rich_text.color(Color32::RED).underline();
                 let odd_pos_label = ui.label(rich_text);
+                even_fixed = even_fix_buttons(ui, &mut de.bg_sel_data, info.layer_width, info.layer_height);
                 if odd_pos_label.hovered() {
                     egui::show_tooltip(ui.ctx(), ui.layer_id(), egui::Id::new("odd_sel_pos"), |ui| {
                         ui.label("The top left corner of your selection is odd, this is very unoptimal for Brushes");
@@ -241,6 +412,7 @@ pub fn show_brushes_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
             } else if odd_size {
                 rich_text = rich_text.color(Color32::ORANGE).underline();
                 let odd_size_label = ui.label(rich_text);
+                even_fixed = even_fix_buttons(ui, &mut de.bg_sel_data, info.layer_width, info.layer_height);
                 if odd_size_label.hovered() {
                     egui::show_tooltip(ui.ctx(), ui.layer_id(), egui::Id::new("odd_sel_size"), |ui| {
                         ui.label("Your selection's dimensions are odd (not divisible by 2), this is unoptimal for Brushes");
@@ -251,6 +423,10 @@ pub fn show_brushes_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
             } else {
                 let _good_label = ui.label(rich_text);
             }
+            if even_fixed {
+                // Redraw the highlight, so it's clear what Load Selection takes
+                de.graphics_update_needed = true;
+            }
         } else {
             ui.label("Selection width/height: N/A");
         }
@@ -298,9 +474,10 @@ pub fn show_brushes_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
                 de.current_brush.height = height as u8;
                 de.current_brush.tileset = info.imbz_filename_noext.clone().unwrap_or_else(|| "N/A".to_string());
                 for selected_index in &de.bg_sel_data.selected_map_indexes {
-                    let tile_data = &maptiles.tiles[*selected_index as usize];
-                    de.current_brush.tiles.push(tile_data.to_short());
+                    let tile_data = maptiles.get_stored_tile(*selected_index, info.layer_width);
+                    de.current_brush.tiles.push(tile_data.map(|t| t.to_short()));
                 }
+                de.brush_settings.current_brush_edited = false;
             }
         });
         ui.horizontal(|ui| {
@@ -308,13 +485,42 @@ pub fn show_brushes_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
             if ui.button("Clear Brush").clicked() {
                 log_write("Clearing current Brush", LogLevel::Log);
                 de.current_brush.clear();
+                de.brush_settings.current_brush_edited = false;
             }
         });
     }
 }
 
-fn do_tile_draw(ui: &mut egui::Ui, brush: &mut Brush, palette: &[Palette;16], tiles: &[u8], col_mode: &u32, pal_offset: &u8) {
-    let top_left: Pos2 = ui.min_rect().min;
+/// Auto-fix buttons for odd selections, returns true if the selection changed
+fn even_fix_buttons(ui: &mut egui::Ui, sel: &mut BgSelectData, layer_width: u16, layer_height: u16) -> bool {
+    ui.horizontal(|ui| {
+        ui.label("Auto-fix:");
+        let expand = ui.button("Expand to even")
+            .on_hover_text("Grows the selection to the even rectangle around it, blank tiles included");
+        if expand.clicked() {
+            sel.expand_to_even(layer_width, layer_height);
+            return true;
+        }
+        let trim = ui.button("Trim to even")
+            .on_hover_text("Drops the odd rows and columns on the edges");
+        if trim.clicked() {
+            sel.trim_to_even(layer_width);
+            return true;
+        }
+        false
+    }).inner
+}
+
+/// Short human readable name for an INFO color_mode value
+fn color_mode_name(color_mode: u32) -> &'static str {
+    match color_mode {
+        0x0 | 0x2 => "16 color",
+        0x1 | 0x3 => "256 color",
+        _ => "Unknown"
+    }
+}
+
+fn do_tile_draw(ui: &mut egui::Ui, top_left: Pos2, brush: &mut Brush, palette: &[Palette;16], palette256: Option<&Palette>, tiles: &[u8], is_256: bool, pal_offset: &u8) {
     // First, draw the entire thing
     for y in 0..BRUSH_TILES_WIDE {
         for x in 0..BRUSH_TILES_WIDE {
@@ -337,20 +543,25 @@ fn do_tile_draw(ui: &mut egui::Ui, brush: &mut Brush, palette: &[Palette;16], ti
         }
     }
     // Then draw the tiles themselves
+    draw_brush_tiles(ui, top_left, BRUSH_TILE_DIM, brush, palette, palette256, tiles, is_256, pal_offset);
+}
+
+/// Draws each tile of the Brush at tile_dim pixels, with a green edge on the bottom and right
+pub fn draw_brush_tiles(ui: &egui::Ui, top_left: Pos2, tile_dim: f32, brush: &Brush, palette: &[Palette;16], palette256: Option<&Palette>, tiles: &[u8], is_256: bool, pal_offset: &u8) {
     for y in 0..brush.height {
         for x in 0..brush.width {
             let painter: &Painter = ui.painter();
-            let true_position: Pos2 = top_left + Vec2::new((x as f32) * BRUSH_TILE_DIM, (y as f32) * BRUSH_TILE_DIM);
-            let rect: Rect = Rect::from_min_size(true_position, BRUSH_TILE_RECT);
+            let true_position: Pos2 = top_left + Vec2::new((x as f32) * tile_dim, (y as f32) * tile_dim);
+            let rect: Rect = Rect::from_min_size(true_position, Vec2::splat(tile_dim));
             let index: usize = (y as usize) * (brush.width as usize) + (x as usize);
 
             if index >= brush.tiles.len() {
                 log_write(format!("Brush index is out of bounds, was {} but len is {}; calc'ed with x/y/brsw: {}/{}/{}",
                 index,brush.tiles.len(),&x,&y,brush.width), LogLevel::Error);
             } else {
-                // Do the actual tile draw
-                if *col_mode == 0x0 {
-                    let tile: MapTileRecordData = MapTileRecordData::new(brush.tiles[index]);
+                // Do the actual tile draw, empty spots have nothing to draw
+                if let (false, Some(tile_short)) = (is_256, brush.tiles[index]) {
+                    let tile: MapTileRecordData = MapTileRecordData::new(tile_short);
                     // Check if out of bounds (subtract palette offset, +1 for universal palette)
                     let pal_id_signed = tile.palette_id as i32 + *pal_offset as i32 + 1;
                     #[allow(clippy::manual_range_contains)]
@@ -369,20 +580,56 @@ fn do_tile_draw(ui: &mut egui::Ui, brush: &mut Brush, palette: &[Palette;16], ti
                     let t = ui.ctx().load_texture("brushtile16", color_image, egui::TextureOptions::NEAREST);
                     let uvs = get_uvs_from_tile(&tile);
                     painter.image(t.id(), rect, uvs, Color32::WHITE);
-                    if y + 1 == brush.height {
-                        painter.line(vec![rect.left_bottom(),rect.right_bottom()], egui::Stroke::new(2.0, Color32::GREEN));
-                    }
-                    if x + 1 == brush.width {
-                        painter.line(vec![rect.right_top(),rect.right_bottom()], egui::Stroke::new(2.0, Color32::GREEN));
-                    }
-                } else if *col_mode == 0x1 {
-                    // 256 colors
+                } else if let (Some(palette256), Some(tile_short)) = (palette256, brush.tiles[index]) {
+                    // 256 colors, the palette ID is unused
+                    let tile: MapTileRecordData = MapTileRecordData::new(tile_short);
+                    let byte_array = get_pixel_bytes_256(tiles, &tile.tile_id);
+                    let color_image = color_image_from_pal(palette256, &byte_array);
+                    let t = ui.ctx().load_texture("brushtile256", color_image, egui::TextureOptions::NEAREST);
+                    let uvs = get_uvs_from_tile(&tile);
+                    painter.image(t.id(), rect, uvs, Color32::WHITE);
+                }
+                if y + 1 == brush.height {
+                    painter.line(vec![rect.left_bottom(),rect.right_bottom()], egui::Stroke::new(2.0, Color32::GREEN));
+                }
+                if x + 1 == brush.width {
+                    painter.line(vec![rect.right_top(),rect.right_bottom()], egui::Stroke::new(2.0, Color32::GREEN));
                 }
             }
         }
     }
 }
 
+/// The whole Brush as one image, 8 pixels per tile. Empty spots are transparent
+pub fn render_brush_image(brush: &Brush, palette: &[Palette;16], palette256: Option<&Palette>, tiles: &[u8], is_256: bool, pal_offset: u8) -> ColorImage {
+    let (width, height) = (brush.width as usize, brush.height as usize);
+    let mut image = ColorImage::new([width * 8, height * 8], Color32::TRANSPARENT);
+    for (index, tile_short) in brush.tiles.iter().enumerate() {
+        let Some(tile_short) = tile_short else { continue };
+        let (x, y) = (index % width.max(1), index / width.max(1));
+        if y >= height {
+            break;
+        }
+        let tile = MapTileRecordData::new(*tile_short);
+        let tile_image = if is_256 {
+            let Some(palette256) = palette256 else { continue };
+            color_image_from_pal(palette256, &get_pixel_bytes_256(tiles, &tile.tile_id))
+        } else {
+            // Same palette math as draw_brush_tiles
+            let Some(cur_pal) = palette.get(tile.palette_id as usize + pal_offset as usize + 1) else { continue };
+            color_image_from_pal(cur_pal, &pixel_byte_array_to_nibbles(&get_pixel_bytes_16(tiles, &tile.tile_id)))
+        };
+        for py in 0..8 {
+            for px in 0..8 {
+                let src_x = if tile.flip_h { 7 - px } else { px };
+                let src_y = if tile.flip_v { 7 - py } else { py };
+                image[(x * 8 + px, y * 8 + py)] = tile_image[(src_x, src_y)];
+            }
+        }
+    }
+    image
+}
+
 #[cfg(test)]
 mod tests_brushes {
     use super::*;
@@ -411,8 +658,59 @@ mod tests_brushes {
         assert_eq!(b.height,2);
         assert_eq!(b.palette_offset,3);
         assert_eq!(b.tiles.len(),4);
-        assert_eq!(b.tiles[0],1234);
-        assert_eq!(b.tiles[3],2222);
+        assert_eq!(b.tiles[0],Some(1234));
+        assert_eq!(b.tiles[3],Some(2222));
+    }
+
+    #[test]
+    fn test_upgrade_format() {
+        // Before format_version, tile 0 was always empty
+        let legacy_json_str = r#"{ "tileset": "t", "name": "old", "width": 2, "height": 1, "palette_offset": 0, "tiles": [0, 5] }"#;
+        let mut legacy: Brush = serde_json::from_str(legacy_json_str).expect("Legacy Brush should parse");
+        legacy.upgrade_format();
+        assert_eq!(legacy.tiles,vec![None,Some(5)]);
+        // Now tile 0 is kept, and null is empty
+        let mut current = legacy.clone();
+        current.tiles = vec![Some(0), None];
+        let current_json_str = serde_json::to_string(&current).expect("Brush should serialize");
+        let mut reloaded: Brush = serde_json::from_str(&current_json_str).expect("Brush should parse");
+        reloaded.upgrade_format();
+        assert_eq!(reloaded.tiles,vec![Some(0),None]);
+    }
+
+    #[test]
+    fn test_flipped() {
+        let brush = Brush { width: 2, height: 2, tiles: vec![Some(1), None, Some(0x0403), Some(4)], ..Default::default() };
+        let h = brush.flipped(true, false);
+        assert_eq!(h.tiles,vec![None, Some(0x0401), Some(0x0404), Some(0x0003)]);
+        let v = brush.flipped(false, true);
+        assert_eq!(v.tiles,vec![Some(0x0c03), Some(0x0804), Some(0x0801), None]);
+        // Both is the same as turning it around
+        assert_eq!(brush.flipped(true, true).flipped(true, true).tiles,brush.tiles);
+        assert_eq!(brush.flipped(false, false).tiles,brush.tiles);
+    }
+
+    #[test]
+    fn test_render_brush_image() {
+        let mut palettes = [Palette::default(); 16];
+        palettes[1].colors[1].color = Color32::BLUE;
+        palettes[1].colors[2].color = Color32::GREEN;
+        // Tile 1 is color 1 on the left column, color 2 everywhere else
+        let mut tiles = vec![0u8; 64];
+        for row in 0..8 {
+            tiles[32 + row * 4] = 0x21;
+            tiles[32 + row * 4 + 1..32 + row * 4 + 4].fill(0x22);
+        }
+        let brush = Brush { width: 2, height: 1, tiles: vec![Some(0x0001), Some(0x0401)], ..Default::default() };
+        let image = render_brush_image(&brush, &palettes, None, &tiles, false, 0);
+        assert_eq!(image.size,[16, 8]);
+        assert_eq!(image[(0, 0)],Color32::BLUE);
+        assert_eq!(image[(7, 0)],Color32::GREEN);
+        // Flipped, so the blue column is on its right
+        assert_eq!(image[(8, 3)],Color32::GREEN);
+        assert_eq!(image[(15, 3)],Color32::BLUE);
+        let empty = Brush { width: 1, height: 1, tiles: vec![None], ..Default::default() };
+        assert_eq!(render_brush_image(&empty, &palettes, None, &tiles, false, 0)[(0, 0)],Color32::TRANSPARENT);
     }
 
     #[test]
@@ -435,4 +733,33 @@ mod tests_brushes {
         "#;
         let _b: Brush = serde_json::from_str(test_json_str).expect("Brush should parse properly");
     }
+
+    #[test]
+    fn test_convert_tileset() {
+        let mappings = TileMappings { mappings: vec![TileMapping {
+            from: String::from("set_a"), to: String::from("set_b"),
+            tiles: vec![(0x0010, 0x2020), (0x0011, 0x0421)]
+        }]};
+        let brush = Brush { tileset: String::from("set_a"), width: 2, height: 2,
+            tiles: vec![Some(0x0010), Some(0x0811), None, Some(0x0099)], ..Default::default() };
+        let (converted, unmapped) = brush.convert_tileset("set_b", &mappings).expect("Mapped one way");
+        assert_eq!(converted.tileset,"set_b");
+        // Flips carry over onto the match, and the unmatched tile is blanked
+        assert_eq!(converted.tiles,vec![Some(0x2020), Some(0x0C21), None, None]);
+        assert_eq!(unmapped,1);
+        assert!(brush.convert_tileset("set_c", &mappings).is_none());
+        assert!(converted.convert_tileset("set_a", &mappings).is_none());
+        // The shipped mappings parse, and each source tile has one match
+        for mapping in &TILE_MAPPINGS.mappings {
+            assert_ne!(mapping.from,mapping.to);
+            let mut sources: Vec<u16> = mapping.tiles.iter().map(|(from, _)| *from).collect();
+            sources.sort();
+            sources.dedup();
+            assert_eq!(sources.len(),mapping.tiles.len());
+        }
+        let stored = STORED_BRUSHES.brushes.iter().find(|brush| brush.tileset == "char01a" && brush.name == "Brown Platform Left").expect("Stored Brush");
+        let (on_char03a, unmapped) = stored.convert_tileset("char03a", &TILE_MAPPINGS).expect("Shipped mapping");
+        assert_eq!(unmapped,0);
+        assert_eq!(on_char03a.tiles,vec![Some(459), Some(306), Some(461), Some(309)]);
+    }
 }
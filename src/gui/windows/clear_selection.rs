@@ -0,0 +1,69 @@
+use egui::Color32;
+
+use crate::{data::mapfile::{ClearLayers, MapClearReport, TileBounds}, engine::displayengine::DisplayEngine};
+
+#[derive(Default)]
+pub struct ClearSelectionSettings {
+    pub window_open: bool,
+    pub layers: ClearLayers,
+    /// Around the selection, in tiles
+    pub bounds: Option<TileBounds>,
+    /// The BG the selection was made on and its exact indexes
+    pub selected: Option<(u8, Vec<u32>)>,
+    /// What the checked layers would lose, recalculated when they change
+    pub preview: Option<(ClearLayers, MapClearReport)>
+}
+impl ClearSelectionSettings {
+    pub fn selected_ref(&self) -> Option<(u8, &[u32])> {
+        self.selected.as_ref().map(|(which_bg, indexes)| (*which_bg, indexes.as_slice()))
+    }
+}
+
+/// True if Clear was clicked
+pub fn show_clear_selection_modal(ui: &mut egui::Ui, de: &DisplayEngine, settings: &mut ClearSelectionSettings) -> bool {
+    puffin::profile_function!();
+    ui.heading("Clear Selection");
+    let Some(bounds) = settings.bounds else {
+        ui.label("Nothing is selected");
+        if ui.button("Close").clicked() {
+            settings.window_open = false;
+        }
+        return false;
+    };
+    ui.label(format!("Deletes everything from {},{} to {},{} on the checked layers",
+        bounds.left, bounds.top, bounds.right, bounds.bottom));
+    for which_bg in 1..=3u8 {
+        let is_selected_bg = settings.selected.as_ref().is_some_and(|(selected_bg, _)| *selected_bg == which_bg);
+        let bg_text = if is_selected_bg { format!("BG {which_bg} (selected tiles)") } else { format!("BG {which_bg}") };
+        ui.checkbox(&mut settings.layers.bgs[which_bg as usize - 1], bg_text);
+    }
+    ui.checkbox(&mut settings.layers.collision, "Collision");
+    ui.checkbox(&mut settings.layers.sprites, "Sprites");
+    ui.checkbox(&mut settings.layers.paths, "Paths");
+    let preview_stale = settings.preview.is_none_or(|(layers, _)| layers != settings.layers);
+    if preview_stale {
+        let mut map = de.loaded_map.clone();
+        let report = map.clear_selection(bounds, settings.selected_ref(), settings.layers);
+        settings.preview = Some((settings.layers, report));
+    }
+    let mut report_empty = true;
+    if let Some((_, report)) = &settings.preview {
+        report_empty = report.is_empty();
+        if report_empty {
+            ui.label("Nothing to delete");
+        } else {
+            ui.label(egui::RichText::new(format!("Deletes {report}")).color(Color32::ORANGE));
+        }
+    }
+    ui.add_space(5.0);
+    let mut do_clear = false;
+    ui.horizontal(|ui| {
+        if ui.button("Cancel").clicked() {
+            settings.window_open = false;
+        }
+        if ui.add_enabled(!report_empty, egui::Button::new("Clear")).clicked() {
+            do_clear = true;
+        }
+    });
+    do_clear
+}
@@ -0,0 +1,95 @@
+use egui::{Button, Pos2, Vec2};
+
+use crate::{engine::displayengine::{DisplayEngine, SpriteClipboard}, gui::windows::brushes::draw_brush_tiles, load::sprite_meta, utils::{log_write, LogLevel}};
+
+/// Loading a texture per tile each frame gets slow past this
+const THUMBNAIL_MAX_TILES: usize = 0x400;
+const THUMBNAIL_MAX_SIZE: f32 = 256.0;
+/// Same as the Brush window
+const BRUSH_MAX_DIM: u16 = 16;
+
+pub fn show_clipboard_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
+    puffin::profile_function!();
+    let base = de.display_settings.number_base;
+    ui.heading("Sprites");
+    let sprite_clip = &de.clipboard.sprite_clip;
+    if sprite_clip.sprites.is_empty() {
+        ui.label("No Sprites copied");
+    } else {
+        let max_x = sprite_clip.sprites.iter().map(|s| s.x_position).max().unwrap_or(0);
+        let max_y = sprite_clip.sprites.iter().map(|s| s.y_position).max().unwrap_or(0);
+        let top_left = sprite_clip.top_left_pos;
+        ui.label(format!("{} Sprites, {}x{} tiles",sprite_clip.sprites.len(),
+            base.fmt_num(max_x.saturating_sub(top_left.x as u16) + 1, 1),base.fmt_num(max_y.saturating_sub(top_left.y as u16) + 1, 1)));
+        egui::ScrollArea::vertical().id_salt("clipboard_sprites").max_height(120.0).show(ui, |ui| {
+            for sprite in &sprite_clip.sprites {
+                let name = sprite_meta(sprite.object_id).map_or(String::from("Unknown"), |meta| meta.name.clone());
+                ui.label(format!("{} ({}) at +{}/+{}",name,base.fmt_num(sprite.object_id, 4),
+                    base.fmt_num(sprite.x_position.saturating_sub(top_left.x as u16), 1),
+                    base.fmt_num(sprite.y_position.saturating_sub(top_left.y as u16), 1)));
+            }
+        });
+    }
+    if ui.add_enabled(!de.clipboard.sprite_clip.sprites.is_empty(), Button::new("Clear Sprites")).clicked() {
+        log_write("Clearing Sprite clipboard", LogLevel::Log);
+        de.clipboard.sprite_clip = SpriteClipboard::default();
+    }
+    ui.separator();
+    ui.heading("BG Tiles");
+    let (width, height) = de.clipboard.bg_clip.dimensions();
+    if de.clipboard.bg_clip.tiles.is_empty() {
+        ui.label("No BG tiles copied");
+    } else {
+        let stored_count = de.clipboard.bg_clip.tiles.iter().filter(|t| t.tile.is_some()).count();
+        ui.label(format!("{}x{} tiles, {} stored",base.fmt_num(width, 1),base.fmt_num(height, 1),stored_count));
+        draw_bg_clip_thumbnail(ui, de, width, height);
+    }
+    ui.horizontal(|ui| {
+        if ui.add_enabled(!de.clipboard.bg_clip.tiles.is_empty(), Button::new("Clear BG Tiles")).clicked() {
+            log_write("Clearing BG clipboard", LogLevel::Log);
+            de.clipboard.bg_clip.clear();
+        }
+        let fits_brush = width > 0 && width <= BRUSH_MAX_DIM && height <= BRUSH_MAX_DIM;
+        let to_brush = ui.add_enabled(fits_brush, Button::new("Make Brush"))
+            .on_hover_text("Replaces the current Brush with the copied tiles")
+            .on_disabled_hover_text("Brushes are 16x16 tiles at most");
+        if to_brush.clicked() {
+            let tileset = de.loaded_map.get_background(de.display_settings.current_layer as u8)
+                .and_then(|bg| bg.get_info().and_then(|info| info.imbz_filename_noext.clone()))
+                .unwrap_or_else(|| "N/A".to_string());
+            if let Some(brush) = de.clipboard.bg_clip.to_brush(tileset) {
+                log_write(format!("Loaded Brush from clipboard: {}",brush), LogLevel::Log);
+                de.current_brush = brush;
+                de.brush_settings.cur_selected_brush = Option::None;
+                de.brush_settings.current_brush_edited = false;
+            }
+        }
+    });
+}
+
+/// Uses the current layer's tileset, since the clipboard doesn't keep one
+fn draw_bg_clip_thumbnail(ui: &mut egui::Ui, de: &mut DisplayEngine, width: u16, height: u16) {
+    if !de.display_settings.is_cur_layer_bg() {
+        ui.label("Switch to a BG layer to see the tiles");
+        return;
+    }
+    if width as usize * height as usize > THUMBNAIL_MAX_TILES {
+        ui.label("Too large to preview");
+        return;
+    }
+    let Some(layer) = de.loaded_map.get_background(de.display_settings.current_layer as u8) else {
+        return;
+    };
+    let (Some(info), Some(tiles)) = (layer.get_info(), &layer.pixel_tiles_preview) else {
+        return;
+    };
+    let Some(brush) = de.clipboard.bg_clip.to_brush(String::new()) else {
+        return;
+    };
+    let tile_dim = (THUMBNAIL_MAX_SIZE / width.max(height) as f32).clamp(2.0, 8.0);
+    let palette256 = layer.get_pltb().and_then(|pltb| pltb.palettes.first());
+    let top_left: Pos2 = ui.cursor().min;
+    ui.allocate_space(Vec2::new(width as f32 * tile_dim, height as f32 * tile_dim));
+    draw_brush_tiles(ui, top_left, tile_dim, &brush, &de.bg_palettes, palette256, tiles, info.is_256_colorpal_mode(), &layer._pal_offset);
+    ui.label("Drawn with the current layer's tileset");
+}
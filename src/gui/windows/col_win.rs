@@ -26,10 +26,10 @@ pub fn collision_tiles_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
                 // Draw the tile
                 let selected = de.col_tile_to_place as usize == col_type_index;
                 if col_type_index == 0x1A { // COIN
-                    let image: Image<'_> = Image::new(egui::include_image!("../../../assets/collision_coin.png")).tint(Color32::LIGHT_BLUE);
+                    let image: Image<'_> = Image::new(egui::include_image!("../../../assets/collision_coin.png")).tint(de.display_settings.theme_colors.collision_coin);
                     image.paint_at(ui, rect);
                 } else {
-                    draw_collision(painter, &rect, col_type_index as u8);
+                    draw_collision(painter, &rect, col_type_index as u8, &de.display_settings.theme_colors.collision);
                 }
                 if selected {
                     painter.rect_stroke(rect, 0.0, Stroke::new(1.5, Color32::RED), egui::StrokeKind::Inside);
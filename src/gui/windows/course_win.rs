@@ -4,7 +4,7 @@ use egui::Color32;
 use egui_extras::{Column, Size, StripBuilder, TableBuilder};
 use uuid::Uuid;
 
-use crate::{data::course_file::{exit_type_name, CourseMapInfo, MapEntrance, MapExit}, engine::displayengine::DisplayEngine, utils::{self, log_write, nitrofs_abs, LogLevel}, NON_MAIN_FOCUSED};
+use crate::{data::course_file::{exit_type_name, CourseMapInfo, FlowStep, MapEntrance, MapExit, WarpKind, WarpOverlap}, engine::displayengine::{special_course_label, DisplayEngine}, gui::gui::NumberBase, utils::{self, log_write, nitrofs_abs, LogLevel}, NON_MAIN_FOCUSED};
 
 pub struct CourseSettings {
     pub selected_map: Option<usize>,
@@ -12,7 +12,22 @@ pub struct CourseSettings {
     pub selected_exit: Option<Uuid>,
     pub add_window_open: bool,
     pub map_templates: HashMap<String,String>,
-    pub add_map_selected: String
+    pub add_map_selected: String,
+    /// If false, the add window uses templates
+    pub add_map_blank: bool,
+    pub blank_map: BlankMapSettings,
+    /// Don't warn about stacked Exits with different types, sometimes intentional
+    pub allow_stacked_exit_types: bool,
+    /// Mirrors the window's open state, right-click quick-create only works while it is open
+    pub window_open: bool,
+    /// Waiting on the Entrance/Exit chooser after a right-click
+    pub quick_create: Option<QuickCreateWarp>,
+    /// This frame's right-click went to quick-create, so the layers leave it alone
+    pub warp_right_click: bool,
+    /// Focus this kind's editable field next frame, after quick-create
+    pub focus_new_warp: Option<WarpKind>,
+    /// Exits picked so far in "Walk the Level", see CourseInfo::walk_flow
+    pub flow_walk: Vec<Uuid>
 }
 impl Default for CourseSettings {
     fn default() -> Self {
@@ -20,12 +35,128 @@ impl Default for CourseSettings {
             selected_map: None, selected_entrance: None,
             selected_exit: None, add_window_open: false,
             map_templates: utils::get_map_templates(),
-            add_map_selected: "".to_string()
+            add_map_selected: "".to_string(),
+            add_map_blank: false,
+            blank_map: BlankMapSettings::default(),
+            allow_stacked_exit_types: false,
+            window_open: false,
+            quick_create: None,
+            warp_right_click: false,
+            focus_new_warp: None,
+            flow_walk: Vec::new()
         }
     }
 }
 
-fn get_course_music_name(music: u8) -> String {
+impl CourseSettings {
+    /// Drop choices past where the walk stopped, so they don't come back.
+    /// A choice that hit a broken link is kept, so its error stays shown
+    pub fn trim_flow_walk(&mut self, steps: &[FlowStep]) {
+        let taken = steps.iter().filter(|step| step.exit.is_some()).count();
+        self.flow_walk.truncate(taken);
+    }
+}
+
+/// Where a right-click asked for a new Entrance or Exit
+#[derive(Clone,Copy,Debug)]
+pub struct QuickCreateWarp {
+    /// Where the chooser is shown
+    pub screen_pos: egui::Pos2,
+    pub tile_x: u16,
+    pub tile_y: u16
+}
+
+/// Settings for creating a map from scratch rather than from a template
+#[derive(Clone,Debug)]
+pub struct BlankMapSettings {
+    /// In tiles, must be even
+    pub width: u16,
+    /// In tiles, must be even
+    pub height: u16,
+    pub layer_count: u8,
+    /// Which BG of the current map each new layer gets its tileset and palettes from
+    pub source_layers: [u8; 3]
+}
+impl Default for BlankMapSettings {
+    fn default() -> Self {
+        Self {
+            width: 0x40, height: 0x20,
+            layer_count: 1,
+            source_layers: [1, 2, 3]
+        }
+    }
+}
+
+/// The blank map half of the "New Map" modal
+/// 
+/// Returns true once every layer has a tileset picked that the current map has
+pub fn show_blank_map_settings(ui: &mut egui::Ui, de: &mut DisplayEngine) -> bool {
+    puffin::profile_function!();
+    // Only tilesets already loaded are offered, since their palettes come with them
+    let mut tileset_options: Vec<(u8, String)> = Vec::new();
+    for which_bg in 1..=3_u8 {
+        if let Some(bg) = de.loaded_map.get_background(which_bg) {
+            let info = bg.get_info().expect("Every BG has INFO");
+            if let Some(imbz_name) = &info.imbz_filename_noext {
+                tileset_options.push((which_bg, imbz_name.clone()));
+            }
+        }
+    }
+    if tileset_options.is_empty() {
+        ui.label("The current map has no IMBZ tilesets to use");
+        return false;
+    }
+    let base = de.display_settings.number_base;
+    let settings = &mut de.course_settings.blank_map;
+    egui::Grid::new("blank_map_grid").num_columns(2).show(ui, |ui| {
+        ui.label("Width (tiles)");
+        let width = base.drag_value(egui::DragValue::new(&mut settings.width), 4)
+            .range(0x20..=0x800);
+        let wres = ui.add(width);
+        if wres.has_focus() {
+            *NON_MAIN_FOCUSED.lock().unwrap() = true;
+        }
+        ui.end_row();
+        ui.label("Height (tiles)");
+        let height = base.drag_value(egui::DragValue::new(&mut settings.height), 4)
+            .range(0x18..=0x800);
+        let hres = ui.add(height);
+        if hres.has_focus() {
+            *NON_MAIN_FOCUSED.lock().unwrap() = true;
+        }
+        ui.end_row();
+        ui.label("BG layers");
+        ui.add(egui::Slider::new(&mut settings.layer_count, 1..=3));
+        ui.end_row();
+        for layer_index in 0..settings.layer_count as usize {
+            ui.label(format!("BG {} tileset",layer_index + 1));
+            let cur_source = settings.source_layers[layer_index];
+            let selected_text = tileset_options.iter()
+                .find(|(which_bg,_)| *which_bg == cur_source)
+                .map(|(which_bg,name)| format!("{} (BG {})",name,which_bg))
+                .unwrap_or_else(|| "None".to_string());
+            egui::ComboBox::new(egui::Id::new("blank_map_tileset").with(layer_index), "")
+                .selected_text(selected_text)
+                .show_ui(ui, |ui| {
+                    for (which_bg, name) in &tileset_options {
+                        ui.selectable_value(&mut settings.source_layers[layer_index], *which_bg, format!("{} (BG {})",name,which_bg));
+                    }
+                });
+            ui.end_row();
+        }
+    });
+    if !settings.width.is_multiple_of(2) || !settings.height.is_multiple_of(2) {
+        ui.colored_label(Color32::ORANGE, "Width and height will be rounded down to even values");
+    }
+    let sources_valid = settings.source_layers[..settings.layer_count as usize].iter()
+        .all(|source| tileset_options.iter().any(|(which_bg,_)| which_bg == source));
+    if !sources_valid {
+        ui.colored_label(Color32::ORANGE, "Pick a tileset for every BG layer");
+    }
+    sources_valid
+}
+
+pub fn get_course_music_name(music: u8) -> String {
     let name = match music {
         0x0	=> "Flower Garden (dup?)",
         0x1	=> "Story Music Box",
@@ -58,8 +189,11 @@ fn get_course_music_name(music: u8) -> String {
     String::from(name)
 }
 
-pub fn show_course_settings_window(ui: &mut egui::Ui, de: &mut DisplayEngine, project_open: bool) {
+/// Returns an overlap to jump to, if one was clicked
+pub fn show_course_settings_window(ui: &mut egui::Ui, de: &mut DisplayEngine, project_open: bool) -> Option<WarpOverlap> {
     puffin::profile_function!();
+    let jump_to = draw_validation_section(ui, de);
+    draw_flow_walk_section(ui, de);
     StripBuilder::new(ui)
         .size(Size::exact(100.0))
         .size(Size::remainder())
@@ -71,6 +205,98 @@ pub fn show_course_settings_window(ui: &mut egui::Ui, de: &mut DisplayEngine, pr
                 draw_settings_section(ui, de);
             });
         });
+    jump_to
+}
+
+fn draw_validation_section(ui: &mut egui::Ui, de: &mut DisplayEngine) -> Option<WarpOverlap> {
+    let overlaps = de.loaded_course.find_overlaps(de.course_settings.allow_stacked_exit_types);
+    let header = if overlaps.is_empty() {
+        String::from("Validation")
+    } else {
+        format!("Validation ({} warnings)",overlaps.len())
+    };
+    let mut jump_to: Option<WarpOverlap> = Option::None;
+    egui::CollapsingHeader::new(header).id_salt("course_validation").show(ui, |ui| {
+        ui.checkbox(&mut de.course_settings.allow_stacked_exit_types, "Allow stacked Exits of different types");
+        if let Some(special) = special_course_label(&de.loaded_course_name()) {
+            ui.label(egui::RichText::new(format!("{special} Courses are started by the game directly, not through another Course's Exits")).weak());
+        }
+        if overlaps.is_empty() {
+            ui.label("No overlapping Entrances or Exits");
+            return;
+        }
+        for overlap in overlaps {
+            ui.horizontal(|ui| {
+                let kind = match overlap.kind {
+                    WarpKind::Entrance => "Entrances",
+                    WarpKind::Exit => "Exits"
+                };
+                let map_label = de.loaded_course.level_map_data.get(overlap.map_index)
+                    .map_or("?", |map| map.label.as_str());
+                ui.colored_label(Color32::YELLOW, format!("{}: overlapping {} {}",map_label,kind,overlap.label));
+                if ui.button("Jump").clicked() {
+                    jump_to = Some(overlap.clone());
+                }
+            });
+        }
+    });
+    ui.separator();
+    jump_to
+}
+
+/// Follows chosen Exits from the level start, one map at a time
+fn draw_flow_walk_section(ui: &mut egui::Ui, de: &mut DisplayEngine) {
+    egui::CollapsingHeader::new("Walk the Level").id_salt("course_flow_walk").show(ui, |ui| {
+        let steps = de.loaded_course.walk_flow(&de.course_settings.flow_walk);
+        if steps.is_empty() {
+            if special_course_label(&de.loaded_course_name()).is_some() {
+                // Not every special Course is entered like a level
+                ui.label(egui::RichText::new("The first map has no Entrance 0, which is expected for some special Courses").weak());
+            } else {
+                ui.colored_label(Color32::RED, "The first map has no Entrance 0 to start from");
+            }
+            return;
+        }
+        de.course_settings.trim_flow_walk(&steps);
+        let mut new_walk: Option<Vec<Uuid>> = Option::None;
+        for (step_index, step) in steps.iter().enumerate() {
+            let map = &de.loaded_course.level_map_data[step.map_index];
+            let entrance_label = map.get_entrance(&step.entrance).map_or("?", |e| e.label.as_str());
+            ui.horizontal(|ui| {
+                ui.label(format!("{}. {} at {}",step_index + 1,map.label,entrance_label));
+                if step.revisit {
+                    ui.colored_label(Color32::YELLOW, "(loops back)");
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.add_space(15.0);
+                let exit_label = step.exit
+                    .and_then(|exit_uuid| map.map_exits.iter().find(|exit| exit.uuid == exit_uuid))
+                    .map_or("Take an Exit...", |exit| exit.label.as_str());
+                egui::ComboBox::new(egui::Id::new("flow_walk_exit").with(step_index), "")
+                    .selected_text(exit_label)
+                    .show_ui(ui, |ui| {
+                        for exit in &map.map_exits {
+                            if ui.selectable_label(step.exit == Some(exit.uuid), &exit.label).clicked() {
+                                let mut walk = de.course_settings.flow_walk[..step_index].to_vec();
+                                walk.push(exit.uuid);
+                                new_walk = Some(walk);
+                            }
+                        }
+                    });
+                if let Some(link_error) = step.link_error {
+                    ui.colored_label(Color32::RED, link_error.to_string());
+                }
+            });
+        }
+        if let Some(walk) = new_walk {
+            de.course_settings.flow_walk = walk;
+        }
+        if ui.button("Restart").clicked() {
+            de.course_settings.flow_walk.clear();
+        }
+    });
+    ui.separator();
 }
 
 fn draw_map_section(ui: &mut egui::Ui, de: &mut DisplayEngine, project_open: bool) {
@@ -151,6 +377,7 @@ fn draw_map_section(ui: &mut egui::Ui, de: &mut DisplayEngine, project_open: boo
 }
 
 fn draw_settings_section(ui: &mut egui::Ui, de: &mut DisplayEngine) {
+    let base = de.display_settings.number_base;
     let Some(selected_map_index) = de.course_settings.selected_map else {
         ui.label("No Map selected");
         return;
@@ -165,7 +392,7 @@ fn draw_settings_section(ui: &mut egui::Ui, de: &mut DisplayEngine) {
     let old_map_music_val = selected_map_data.map_music;
     ui.heading("Music");
     egui::ComboBox::from_label("")
-        .selected_text(format!("0x{:02X} - {}",selected_map_data.map_music,get_course_music_name(selected_map_data.map_music)))
+        .selected_text(format!("{} - {}",base.fmt_num(selected_map_data.map_music, 2),get_course_music_name(selected_map_data.map_music)))
         .show_ui(ui, |ui| {
             for x in 0..=23 { // 23 is the highest value found in all CRSBs via script
                 ui.selectable_value(&mut selected_map_data.map_music, x, get_course_music_name(x));
@@ -175,6 +402,14 @@ fn draw_settings_section(ui: &mut egui::Ui, de: &mut DisplayEngine) {
         log_write(format!("Changed Map music index to '{}'",&selected_map_data.map_music), LogLevel::Log);
         de.unsaved_changes = true;
     }
+    // Read-only, only kept so other tools' data survives saving
+    let reserved = &stored_map_data.reserved;
+    let reserved_title = if reserved.is_all_zero() { "Reserved bytes (all zero)" } else { "Reserved bytes" };
+    egui::CollapsingHeader::new(reserved_title).id_salt("cscn_reserved").show(ui, |ui| {
+        ui.label(format!("Name field: {}",utils::bytes_to_hex_string(&reserved.name_field)));
+        ui.label(format!("Entrance padding: {}",utils::bytes_to_hex_string(&reserved.entrance_padding)));
+        ui.label(format!("After Exits: {}",utils::bytes_to_hex_string(&reserved.trailing)));
+    }).header_response.on_hover_text("Unknown CSCN data, saved back unchanged");
     ui.separator();
     // ENTRANCES //
     ui.heading("Entrances");
@@ -208,6 +443,11 @@ fn draw_settings_section(ui: &mut egui::Ui, de: &mut DisplayEngine) {
             de.unsaved_changes = true;
         }
     });
+    let targeted_by: Vec<String> = de.course_settings.selected_entrance
+        .map(|entrance_uuid| de.loaded_course.exits_targeting(&entrance_uuid).iter()
+            .map(|(map_index, exit)| format!("{}: {}",de.loaded_course.level_map_data[*map_index].label,exit.label))
+            .collect())
+        .unwrap_or_default();
     ui.horizontal(|ui| {
         let selected_map_data = &mut de.loaded_course.level_map_data[selected_map_index];
         let _table_entrances = TableBuilder::new(ui)
@@ -241,22 +481,36 @@ fn draw_settings_section(ui: &mut egui::Ui, de: &mut DisplayEngine) {
             let Some(selected_entrance) = selected_map_data.get_entrance_mut(&selected_entrance_uuid) else { return };
             // Begin selected Entrance settings
             ui.horizontal(|ui| {
-                let drag_value_x = egui::DragValue::new(&mut selected_entrance.entrance_x)
-                    .hexadecimal(4, false, true)
+                let drag_value_x = base.drag_value(egui::DragValue::new(&mut selected_entrance.entrance_x), 4)
                     .range(0..=0xffff);
                 let dvx = ui.add(drag_value_x);
                 if dvx.has_focus() {
                     *NON_MAIN_FOCUSED.lock().unwrap() = true;
                 }
-                let drag_value_y = egui::DragValue::new(&mut selected_entrance.entrance_y)
-                    .hexadecimal(4, false, true)
+                let drag_value_y = base.drag_value(egui::DragValue::new(&mut selected_entrance.entrance_y), 4)
                     .range(0..=0xffff);
                 let dvy = ui.add(drag_value_y);
                 if dvy.has_focus() {
                     *NON_MAIN_FOCUSED.lock().unwrap() = true;
                 }
             });
-            show_selected_entrance_settings(ui, selected_entrance);
+            let flags_res = show_selected_entrance_settings(ui, selected_entrance, base);
+            if selected_map_index == 0 && selected_map_data.map_entrances.first().map(|e| e.uuid) == Some(selected_entrance_uuid) {
+                ui.colored_label(Color32::LIGHT_GREEN, "Level start");
+            }
+            if targeted_by.is_empty() {
+                ui.label("No Exits lead here");
+            } else {
+                ui.label("Exits leading here:");
+                for exit_label in &targeted_by {
+                    ui.label(format!("- {exit_label}"));
+                }
+            }
+            if de.course_settings.focus_new_warp == Some(WarpKind::Entrance) {
+                flags_res.request_focus();
+                flags_res.scroll_to_me(Some(egui::Align::Center));
+                de.course_settings.focus_new_warp = None;
+            }
         });
     });
     ui.separator();
@@ -318,15 +572,21 @@ fn draw_settings_section(ui: &mut egui::Ui, de: &mut DisplayEngine) {
         });
         ui.vertical(|ui| {
             let ro_map_data = de.loaded_course.level_map_data.clone();
+            let focus_type = de.course_settings.focus_new_warp == Some(WarpKind::Exit);
             let Some(selected_exit) = de.get_selected_exit_mut() else {
                 ui.label("No Exit selected");
                 return;
             };
             // Here is where the Exit settings are once selected
-            show_exit_pos(ui, selected_exit);
-            show_exit_type(ui, selected_exit);
+            show_exit_pos(ui, selected_exit, base);
+            let type_res = show_exit_type(ui, selected_exit);
             show_exit_target_map(ui, selected_exit,&ro_map_data);
             show_exit_target_entrance(ui, selected_exit, &ro_map_data);
+            if focus_type {
+                type_res.request_focus();
+                type_res.scroll_to_me(Some(egui::Align::Center));
+                de.course_settings.focus_new_warp = None;
+            }
         });
     });
     ui.separator();
@@ -335,25 +595,33 @@ fn draw_settings_section(ui: &mut egui::Ui, de: &mut DisplayEngine) {
     }
 }
 
-fn show_selected_entrance_settings(ui: &mut egui::Ui, selected_entrance: &mut MapEntrance) {
+/// Returns the Raw Flags field, so a new Entrance can focus it
+fn show_selected_entrance_settings(ui: &mut egui::Ui, selected_entrance: &mut MapEntrance, base: NumberBase) -> egui::Response {
+    let flags_res = ui.horizontal(|ui| {
+        ui.label("Raw Flags");
+        let flags_drag = base.drag_value(egui::DragValue::new(&mut selected_entrance.entrance_flags), 4);
+        let flags_res = ui.add(flags_drag);
+        if flags_res.has_focus() {
+            *NON_MAIN_FOCUSED.lock().unwrap() = true;
+        }
+        flags_res
+    }).inner;
     let which_screen = selected_entrance.entrance_flags >> 14;
     let enter_map_anim = selected_entrance.entrance_flags % 0x1000;
-    ui.label(format!("Raw Flags: {:X}",selected_entrance.entrance_flags));
-    ui.label(format!("Which Screen: {:X}",which_screen));
-    ui.label(format!("Entrance Animation: {:X}",enter_map_anim));
+    ui.label(format!("Which Screen: {}",base.fmt_num(which_screen, 1)));
+    ui.label(format!("Entrance Animation: {}",base.fmt_num(enter_map_anim, 1)));
+    flags_res
 }
 
-fn show_exit_pos(ui: &mut egui::Ui, selected_exit: &mut MapExit) {
+fn show_exit_pos(ui: &mut egui::Ui, selected_exit: &mut MapExit, base: NumberBase) {
     ui.horizontal(|ui| {
-        let drag_value_x = egui::DragValue::new(&mut selected_exit.exit_x)
-            .hexadecimal(4, false, true)
+        let drag_value_x = base.drag_value(egui::DragValue::new(&mut selected_exit.exit_x), 4)
             .range(0..=0xffff);
         let dvx = ui.add(drag_value_x);
         if dvx.has_focus() {
             *NON_MAIN_FOCUSED.lock().unwrap() = true;
         }
-        let drag_value_y = egui::DragValue::new(&mut selected_exit.exit_y)
-            .hexadecimal(4, false, true)
+        let drag_value_y = base.drag_value(egui::DragValue::new(&mut selected_exit.exit_y), 4)
             .range(0..=0xffff);
         let dvy = ui.add(drag_value_y);
         if dvy.has_focus() {
@@ -362,8 +630,8 @@ fn show_exit_pos(ui: &mut egui::Ui, selected_exit: &mut MapExit) {
     });
 }
 
-fn show_exit_type(ui: &mut egui::Ui, selected_exit: &mut MapExit) {
-    let _exit_type_dropdown = egui::ComboBox::from_label("Type")
+fn show_exit_type(ui: &mut egui::Ui, selected_exit: &mut MapExit) -> egui::Response {
+    let exit_type_dropdown = egui::ComboBox::from_label("Type")
         .selected_text(exit_type_name(selected_exit.exit_type))
         .show_ui(ui, |ui| {
             for x in 0..=0xE { // 0xE is highest found in previous Stork, confirmed by script
@@ -371,6 +639,7 @@ fn show_exit_type(ui: &mut egui::Ui, selected_exit: &mut MapExit) {
                     x, exit_type_name(x));
             }
         });
+    exit_type_dropdown.response
 }
 
 fn show_exit_target_map(ui: &mut egui::Ui, selected_exit: &mut MapExit, maps: &[CourseMapInfo]) {
@@ -417,3 +686,38 @@ fn show_exit_target_entrance(ui: &mut egui::Ui, selected_exit: &mut MapExit, map
             }
         });
 }
+
+#[cfg(test)]
+mod tests_course_win {
+    use uuid::Uuid;
+
+    use crate::data::course_file::{CourseInfo, CourseMapInfo, FlowLinkError, MapExit};
+
+    use super::CourseSettings;
+
+    #[test]
+    fn test_broken_link_stays_in_walk() {
+        let mut first = CourseMapInfo::from_template("01k0001".to_owned());
+        let second = CourseMapInfo::from_template("01k0002".to_owned());
+        // Links to a map that isn't in the Course
+        let broken = MapExit { target_map: Uuid::new_v4(), ..Default::default() };
+        let good = MapExit { target_map: second.uuid, target_map_entrance: second.map_entrances[0].uuid, ..Default::default() };
+        first.map_exits.push(broken.clone());
+        first.map_exits.push(good.clone());
+        let course = CourseInfo { level_map_data: vec![first, second], ..Default::default() };
+        let mut settings = CourseSettings { flow_walk: vec![broken.uuid, good.uuid], ..Default::default() };
+        let steps = course.walk_flow(&settings.flow_walk);
+        settings.trim_flow_walk(&steps);
+        // The broken choice is kept, the one after it is dropped
+        assert_eq!(settings.flow_walk,vec![broken.uuid]);
+        let steps = course.walk_flow(&settings.flow_walk);
+        assert_eq!(steps.len(),1);
+        assert_eq!(steps[0].exit,Some(broken.uuid));
+        assert_eq!(steps[0].link_error,Some(FlowLinkError::MissingMap));
+        // A working choice is kept too
+        settings.flow_walk = vec![good.uuid];
+        let steps = course.walk_flow(&settings.flow_walk);
+        settings.trim_flow_walk(&steps);
+        assert_eq!(settings.flow_walk,vec![good.uuid]);
+    }
+}
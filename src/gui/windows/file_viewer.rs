@@ -0,0 +1,121 @@
+// Look inside a single .crsb or .mpdz without opening its project
+//
+// Nothing here can be saved, since saving needs the rest of the project
+
+use std::path::{Path, PathBuf};
+
+use egui::Color32;
+
+use crate::{data::{course_file::{exit_type_name, CourseInfo}, mapfile::MapData}, engine::json_dump::{dump_map, MapDump}, gui::{gui::NumberBase, windows::course_win::get_course_music_name}, utils::{bytes_to_hex_string, log_write, LogLevel}};
+
+pub enum ViewedFile {
+    Course { path: PathBuf, course: CourseInfo },
+    Map { path: PathBuf, dump: MapDump }
+}
+impl ViewedFile {
+    /// Picks the parser from the extension
+    pub fn open(path: &Path) -> Result<Self, String> {
+        log_write(format!("Viewing '{}' read-only",path.display()), LogLevel::Log);
+        let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default().to_ascii_lowercase();
+        let name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        match ext.as_str() {
+            "crsb" => CourseInfo::new(&path.to_path_buf(), name)
+                .map(|course| Self::Course { path: path.to_path_buf(), course })
+                .map_err(|e| e.to_string()),
+            "mpdz" => MapData::new(&path.to_path_buf(), &guess_project_folder(path))
+                .map(|map| Self::Map { path: path.to_path_buf(), dump: dump_map(&map, None) })
+                .map_err(|e| e.to_string()),
+            _ => Err(format!("Only .crsb and .mpdz files can be viewed, not '{}'",path.display()))
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Course { path, .. } | Self::Map { path, .. } => path
+        }
+    }
+}
+
+/// Project files live in files/file, so tilesets can load if it's from one.
+/// Otherwise the tilesets are just missing, which doesn't matter here
+fn guess_project_folder(path: &Path) -> PathBuf {
+    let parent = path.parent().unwrap_or(Path::new("."));
+    let in_project = parent.file_name().is_some_and(|n| n == "file")
+        && parent.parent().and_then(|p| p.file_name()).is_some_and(|n| n == "files");
+    match parent.parent().and_then(|p| p.parent()) {
+        Some(project) if in_project => project.to_path_buf(),
+        _ => parent.to_path_buf()
+    }
+}
+
+pub fn show_file_viewer_window(ui: &mut egui::Ui, viewed: &ViewedFile, base: NumberBase) {
+    puffin::profile_function!();
+    ui.colored_label(Color32::ORANGE, "Read-only: open the project to edit and save this file");
+    ui.label(format!("{}",viewed.path().display()));
+    ui.separator();
+    egui::ScrollArea::vertical()
+    .auto_shrink(false)
+    .min_scrolled_height(1.0)
+    .show(ui, |ui| {
+        match viewed {
+            ViewedFile::Course { course, .. } => show_course(ui, course, base),
+            ViewedFile::Map { dump, .. } => show_map(ui, dump, base)
+        }
+    });
+}
+
+fn show_course(ui: &mut egui::Ui, course: &CourseInfo, base: NumberBase) {
+    ui.label(format!("{} maps",course.level_map_data.len()));
+    for (map_index, map_info) in course.level_map_data.iter().enumerate() {
+        egui::CollapsingHeader::new(format!("{}: {}",map_index,&map_info.map_filename_noext))
+            .default_open(true)
+            .show(ui, |ui| {
+                ui.label(format!("Music: {}",get_course_music_name(map_info.map_music)));
+                for (i, entrance) in map_info.map_entrances.iter().enumerate() {
+                    ui.label(format!("Entrance {}: {}/{}, flags {}",i,
+                        base.fmt_num(entrance.entrance_x, 1),base.fmt_num(entrance.entrance_y, 1),base.fmt_num(entrance.entrance_flags, 4)));
+                }
+                for (i, exit) in map_info.map_exits.iter().enumerate() {
+                    ui.label(format!("Exit {}: {}/{}, {} to map {} entrance {}",i,
+                        base.fmt_num(exit.exit_x, 1),base.fmt_num(exit.exit_y, 1),exit_type_name(exit.exit_type),
+                        exit.target_map_raw,exit.target_map_entrance_raw));
+                }
+            });
+    }
+}
+
+fn show_map(ui: &mut egui::Ui, dump: &MapDump, base: NumberBase) {
+    ui.label(format!("Size: {}x{}",base.fmt_num(dump.width, 1),base.fmt_num(dump.height, 1)));
+    if !dump.unknown_segments.is_empty() {
+        ui.label(format!("Unknown segments: {}",dump.unknown_segments.join(", ")));
+    }
+    egui::CollapsingHeader::new("Layers").default_open(true).show(ui, |ui| {
+        for layer in &dump.layers {
+            ui.label(format!("BG {}: {}x{}, tileset {}, color mode {}, {} tiles{}",layer.which_bg,
+                base.fmt_num(layer.layer_width, 1),base.fmt_num(layer.layer_height, 1),
+                layer.tileset.as_deref().unwrap_or("none"),layer.color_mode,layer.tiles.len(),
+                if layer.collision.is_some() { ", has collision" } else { "" }));
+        }
+    });
+    egui::CollapsingHeader::new(format!("Sprites ({})",dump.sprites.len())).show(ui, |ui| {
+        for sprite in &dump.sprites {
+            ui.label(format!("{} ({}) at {}/{} [{}]",sprite.name.as_deref().unwrap_or("Unknown"),
+                base.fmt_num(sprite.object_id, 4),base.fmt_num(sprite.x, 1),base.fmt_num(sprite.y, 1),
+                bytes_to_hex_string(&sprite.settings)));
+        }
+    });
+    ui.label(format!("Path Lines: {}",dump.paths.len()));
+    ui.label(format!("Triggers: {}",dump.triggers.len()));
+}
+
+#[cfg(test)]
+mod tests_file_viewer {
+    use super::*;
+
+    #[test]
+    fn test_guess_project_folder() {
+        assert_eq!(guess_project_folder(Path::new("/proj/files/file/1-1_main.mpdz")),PathBuf::from("/proj"));
+        assert_eq!(guess_project_folder(Path::new("/downloads/1-1_main.mpdz")),PathBuf::from("/downloads"));
+        assert!(ViewedFile::open(Path::new("/downloads/notes.txt")).is_err());
+    }
+}
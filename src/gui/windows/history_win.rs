@@ -0,0 +1,34 @@
+use crate::{data::mapfile::MapData, engine::history::MapHistory};
+
+/// Returns the index of the entry to jump to, if one was clicked
+pub fn show_history_window(ui: &mut egui::Ui, history: &MapHistory, current_state: &MapData) -> Option<usize> {
+    puffin::profile_function!();
+    let now = ui.input(|i| i.time);
+    let entries = history.entries();
+    let current_index = history.current_index(current_state);
+    if current_index.is_none() && !entries.is_empty() {
+        ui.label(egui::RichText::new("Current changes are not yet recorded").italics());
+    }
+    let mut clicked: Option<usize> = Option::None;
+    egui::ScrollArea::vertical()
+        .auto_shrink(false)
+        .min_scrolled_height(1.0)
+        .stick_to_bottom(true)
+        .show(ui, |ui| {
+            for (index, entry) in entries.iter().enumerate() {
+                let is_redo = current_index.is_some_and(|cur| index > cur);
+                let seconds_ago = (now - entry.time).max(0.0) as u64;
+                let text = format!("{}: {} ({}s ago)",index,entry.label,seconds_ago);
+                let text = if is_redo {
+                    egui::RichText::new(text).weak()
+                } else {
+                    egui::RichText::new(text)
+                };
+                let res = ui.selectable_label(current_index == Some(index), text);
+                if res.clicked() && current_index != Some(index) {
+                    clicked = Some(index);
+                }
+            }
+        });
+    clicked
+}
@@ -0,0 +1,255 @@
+use egui::{Key, RichText};
+
+use crate::{engine::displayengine::{special_course_title, DisplayEngine, SPECIAL_COURSE_FILENAMES}, utils::{log_write, LogLevel}, NON_MAIN_FOCUSED};
+
+/// How many matches to show at once
+const MAX_SHOWN_MATCHES: usize = 12;
+/// How many recently opened maps to remember
+const MAX_RECENT_MAPS: usize = 8;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SwitchTarget {
+    /// World and Level index, starting at 0
+    Course(u32, u32),
+    /// Course file name without extension
+    SpecialCourse(String),
+    /// Map index in the current Course
+    Map(u32),
+    /// A map opened earlier this session, in any Course
+    Recent(RecentMap),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecentMap {
+    /// Course file name without extension
+    pub course_name: String,
+    pub course_label: String,
+    pub map_index: u32,
+    pub map_name: String
+}
+impl RecentMap {
+    pub fn label(&self) -> String {
+        format!("{}, Map {}: {}",self.course_label,self.map_index,self.map_name)
+    }
+}
+
+/// Maps opened this session, most recent first
+#[derive(Default)]
+pub struct RecentMaps {
+    pub maps: Vec<RecentMap>
+}
+impl RecentMaps {
+    /// Call after a map loads successfully
+    pub fn record(&mut self, de: &DisplayEngine) {
+        let Some(map_index) = de.map_index else {
+            log_write("No map index when recording recent map", LogLevel::Warn);
+            return;
+        };
+        self.push(RecentMap {
+            course_name: de.loaded_course_name(),
+            course_label: de.loaded_course.label.clone(),
+            map_index: map_index as u32,
+            map_name: de.loaded_map.map_name.clone()
+        });
+    }
+
+    pub fn push(&mut self, recent: RecentMap) {
+        self.maps.retain(|m| m.course_name != recent.course_name || m.map_index != recent.map_index);
+        self.maps.insert(0, recent);
+        self.maps.truncate(MAX_RECENT_MAPS);
+    }
+}
+
+#[derive(Default)]
+pub struct LevelSwitcher {
+    pub window_open: bool,
+    pub query: String,
+    pub selected_index: usize,
+    /// Generated when opened, label then target
+    pub candidates: Vec<(String, SwitchTarget)>,
+    pub focus_needed: bool
+}
+
+impl LevelSwitcher {
+    pub fn open(&mut self, de: &DisplayEngine) {
+        log_write("Opening level switcher", LogLevel::Debug);
+        self.query.clear();
+        self.selected_index = 0;
+        self.candidates.clear();
+        self.focus_needed = true;
+        // Maps in the current course go first, they're the most likely target
+        for (map_index, map) in de.loaded_course.level_map_data.iter().enumerate() {
+            self.candidates.push((
+                format!("Map {}: {}",map_index,map.map_filename_noext),
+                SwitchTarget::Map(map_index as u32)
+            ));
+        }
+        for world_index in 0..5_u32 {
+            for level_index in 0..10_u32 {
+                let file_name = de.get_level_filename(&world_index, &level_index);
+                self.candidates.push((
+                    format!("{}-{}: {}",world_index+1,level_index+1,file_name),
+                    SwitchTarget::Course(world_index, level_index)
+                ));
+            }
+        }
+        for special in SPECIAL_COURSE_FILENAMES {
+            self.candidates.push((
+                format!("Special: {}",special_course_title(special)),
+                SwitchTarget::SpecialCourse(special.to_string())
+            ));
+        }
+        self.window_open = true;
+    }
+
+    /// Sorted best first
+    pub fn get_matches(&self) -> Vec<&(String, SwitchTarget)> {
+        let mut scored: Vec<(i32, &(String, SwitchTarget))> = self.candidates.iter()
+            .filter_map(|candidate| fuzzy_score(&self.query, &candidate.0).map(|score| (score, candidate)))
+            .collect();
+        // Stable, so ties stay in the original order
+        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        scored.into_iter().map(|(_, candidate)| candidate).collect()
+    }
+}
+
+/// Returns None if it does not match at all, higher is better
+///
+/// Whole substrings beat scattered characters, and earlier matches beat later ones
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i32> {
+    let query = query.trim().to_lowercase();
+    let candidate = candidate.to_lowercase();
+    if query.is_empty() {
+        return Some(0);
+    }
+    if let Some(pos) = candidate.find(&query) {
+        return Some(1000 - (pos as i32));
+    }
+    // Subsequence match
+    let mut score: i32 = 0;
+    let mut last_match: Option<usize> = Option::None;
+    let mut cand_chars = candidate.chars().enumerate();
+    for query_char in query.chars() {
+        let (index, _) = cand_chars.by_ref().find(|(_, c)| *c == query_char)?;
+        if last_match.is_some_and(|last| last + 1 == index) {
+            score += 5; // Consecutive
+        } else {
+            score -= index as i32 - last_match.map_or(0, |last| last as i32);
+        }
+        last_match = Some(index);
+    }
+    Some(score)
+}
+
+/// Returns the target once chosen
+pub fn show_level_switcher_modal(ui: &mut egui::Ui, switcher: &mut LevelSwitcher) -> Option<SwitchTarget> {
+    puffin::profile_function!();
+    ui.set_width(300.0);
+    ui.heading("Go to Level or Map");
+    let query_res = ui.add(egui::TextEdit::singleline(&mut switcher.query).hint_text("3-5, kuppa, 01k..."));
+    if switcher.focus_needed {
+        query_res.request_focus();
+        switcher.focus_needed = false;
+    }
+    if query_res.has_focus() {
+        *NON_MAIN_FOCUSED.lock().unwrap() = true;
+    }
+    if query_res.changed() {
+        switcher.selected_index = 0;
+    }
+    let matches: Vec<(String, SwitchTarget)> = switcher.get_matches().into_iter()
+        .take(MAX_SHOWN_MATCHES).cloned().collect();
+    let (up, down, enter, escape) = ui.input(|i| (
+        i.key_pressed(Key::ArrowUp), i.key_pressed(Key::ArrowDown),
+        i.key_pressed(Key::Enter), i.key_pressed(Key::Escape)
+    ));
+    if escape {
+        switcher.window_open = false;
+        return None;
+    }
+    if down && switcher.selected_index + 1 < matches.len() {
+        switcher.selected_index += 1;
+    }
+    if up && switcher.selected_index > 0 {
+        switcher.selected_index -= 1;
+    }
+    let mut chosen: Option<SwitchTarget> = Option::None;
+    ui.separator();
+    if matches.is_empty() {
+        ui.label(RichText::new("No matches").italics());
+    }
+    for (index, (label, target)) in matches.iter().enumerate() {
+        let res = ui.selectable_label(index == switcher.selected_index, label);
+        if res.clicked() {
+            chosen = Some(target.clone());
+        }
+    }
+    if enter {
+        if let Some((_, target)) = matches.get(switcher.selected_index) {
+            chosen = Some(target.clone());
+        }
+    }
+    ui.separator();
+    ui.horizontal(|ui| {
+        if ui.button("Cancel").clicked() {
+            switcher.window_open = false;
+        }
+        ui.label(RichText::new("Enter to go, Esc to cancel").weak());
+    });
+    if chosen.is_some() {
+        switcher.window_open = false;
+    }
+    chosen
+}
+
+#[cfg(test)]
+mod tests_level_switcher {
+    use super::*;
+
+    fn recent(course_name: &str, map_index: u32) -> RecentMap {
+        RecentMap {
+            course_name: course_name.to_string(),
+            course_label: course_name.to_string(),
+            map_index,
+            map_name: String::new()
+        }
+    }
+
+    #[test]
+    fn test_recent_maps_order() {
+        let mut recent_maps = RecentMaps::default();
+        recent_maps.push(recent("1-1_D3", 0));
+        recent_maps.push(recent("1-2_D3", 1));
+        // Reopening moves it to the front instead of duplicating
+        recent_maps.push(recent("1-1_D3", 0));
+        assert_eq!(recent_maps.maps.len(), 2);
+        assert_eq!(recent_maps.maps[0], recent("1-1_D3", 0));
+        for map_index in 0..20 {
+            recent_maps.push(recent("kuppa", map_index));
+        }
+        assert_eq!(recent_maps.maps.len(), MAX_RECENT_MAPS);
+        assert_eq!(recent_maps.maps[0].map_index, 19);
+    }
+
+    #[test]
+    fn test_fuzzy_substring() {
+        assert!(fuzzy_score("3-5", "3-5: 3-5_D3").is_some());
+        assert!(fuzzy_score("kuppa", "Special: kuppa").is_some());
+        assert!(fuzzy_score("KUPPA", "Special: kuppa").is_some());
+        assert!(fuzzy_score("zzz", "Special: kuppa").is_none());
+    }
+
+    #[test]
+    fn test_fuzzy_ordering() {
+        let exact = fuzzy_score("3-5", "3-5: file").unwrap();
+        let later = fuzzy_score("3-5", "Map 3: 3-5file").unwrap();
+        let scattered = fuzzy_score("3-5", "3-1: 05file").unwrap();
+        assert!(exact > later);
+        assert!(later > scattered);
+    }
+
+    #[test]
+    fn test_fuzzy_empty() {
+        assert_eq!(fuzzy_score("", "anything"),Some(0));
+    }
+}
@@ -1,11 +1,34 @@
-use egui::Color32;
+use egui::{Color32, RichText};
 
-use crate::{data::{backgrounddata::BackgroundData, mapfile::TopLevelSegmentWrapper, TopLevelSegment}, engine::displayengine::DisplayEngine, utils::{log_write, LogLevel}};
+use crate::{data::{backgrounddata::BackgroundData, mapfile::TopLevelSegmentWrapper, TopLevelSegment}, engine::{displayengine::DisplayEngine, segment_state::{SegmentRevert, SegmentState}}, gui::gui::NumberBase, utils::{log_write, LogLevel}};
 
-pub fn show_map_segments_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
+/// Shows if the segment changed since the map was loaded, with a way back if
+/// it did. True if that was clicked
+pub fn segment_state_badge(ui: &mut egui::Ui, state: SegmentState) -> bool {
+    match state {
+        SegmentState::Unchanged => {
+            ui.label(RichText::new("Unchanged").weak());
+            false
+        }
+        SegmentState::Added => {
+            ui.label(RichText::new("Added").color(Color32::GREEN)).on_hover_text("Not in the map when it was loaded");
+            false
+        }
+        SegmentState::Modified => {
+            ui.label(RichText::new("Modified").color(Color32::YELLOW));
+            ui.button("Revert to loaded").on_hover_text("Puts back this segment as it was when the map was loaded").clicked()
+        }
+    }
+}
+
+/// Returns a segment to revert, which the Gui does so it can make an undo point
+pub fn show_map_segments_window(ui: &mut egui::Ui, de: &mut DisplayEngine) -> Option<SegmentRevert> {
     puffin::profile_function!();
     ui.label(format!("Map location: {}",de.loaded_map.src_file));
     let mut do_del: Option<usize> = Option::None;
+    let mut revert: Option<SegmentRevert> = Option::None;
+    let base = de.display_settings.number_base;
+    let states: Vec<SegmentState> = de.segment_baseline.top_level_states(&de.loaded_map).to_vec();
     egui::ScrollArea::vertical()
         .auto_shrink(false)
         .min_scrolled_height(1.0)
@@ -16,21 +39,21 @@ pub fn show_map_segments_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
                     "SCEN" => {
                         ui.heading("SCEN");
                         if let TopLevelSegmentWrapper::SCEN(scendata) = seg {
-                            show_scen_data(ui, scendata);
+                            show_scen_data(ui, scendata, base);
                         }
                     }
                     "ALPH" => {
                         ui.heading("ALPH");
                         if let TopLevelSegmentWrapper::ALPH(alph) = seg {
-                            ui.label(format!("BLDALPHA: 0x{:X}",&alph.bldalpha));
-                            ui.label(format!("BLDCNT: 0x{:X}",&alph.bldcnt));
+                            ui.label(format!("BLDALPHA: {}",base.fmt_num(alph.bldalpha, 1)));
+                            ui.label(format!("BLDCNT: {}",base.fmt_num(alph.bldcnt, 1)));
                         }
                     }
                     "GRAD" => {
                         ui.heading("GRAD");
                         if let TopLevelSegmentWrapper::GRAD(grad) = seg {
-                            ui.label(format!("Color Count: 0x{:X}",grad.color_count));
-                            ui.label(format!("Y Offset: 0x{:X}",grad.y_offset));
+                            ui.label(format!("Color Count: {}",base.fmt_num(grad.color_count, 1)));
+                            ui.label(format!("Y Offset: {}",base.fmt_num(grad.y_offset, 1)));
                         }
                     }
                     "SETD" => {
@@ -42,14 +65,14 @@ pub fn show_map_segments_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
                     "BLKZ" => {
                         ui.heading("BLKZ");
                         if let TopLevelSegmentWrapper::BLKZ(blkz) = seg {
-                            ui.label(format!("Height/Width: 0x{:04X}/0x{:04X}",&blkz.height,&blkz.width));
-                            ui.label(format!("X/Y Offset: 0x{:04X}/0x{:04X}",&blkz.x_offset,&blkz.y_offset));
+                            ui.label(format!("Height/Width: {}/{}",base.fmt_num(blkz.height, 4),base.fmt_num(blkz.width, 4)));
+                            ui.label(format!("X/Y Offset: {}/{}",base.fmt_num(blkz.x_offset, 4),base.fmt_num(blkz.y_offset, 4)));
                         }
                     }
                     "BRAK" => {
                         ui.heading("BRAK");
                         if let TopLevelSegmentWrapper::BRAK(brak) = seg {
-                            ui.label(format!("Size in bytes: 0x{:X}",brak.raw_bytes.len()));
+                            ui.label(format!("Size in bytes: {}",base.fmt_num(brak.raw_bytes.len() as u32, 1)));
                         }
                     }
                     "AREA" => {
@@ -68,12 +91,17 @@ pub fn show_map_segments_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
                         ui.label(format!("Unhandled: {}",seg.header()));
                     }
                 }
-                ui.style_mut().visuals.widgets.hovered.weak_bg_fill = Color32::RED;
-                let is_undeletable = header.eq("SETD") || header.eq("SCEN");
-                let del_button = ui.add_enabled(!is_undeletable, egui::Button::new("Delete"));
-                if del_button.clicked() {
-                    do_del = Some(i);
-                }
+                ui.horizontal(|ui| {
+                    if segment_state_badge(ui, states[i]) {
+                        revert = Some(SegmentRevert::TopLevel(i));
+                    }
+                    ui.style_mut().visuals.widgets.hovered.weak_bg_fill = Color32::RED;
+                    let is_undeletable = header.eq("SETD") || header.eq("SCEN");
+                    let del_button = ui.add_enabled(!is_undeletable, egui::Button::new("Delete"));
+                    if del_button.clicked() {
+                        do_del = Some(i);
+                    }
+                });
                 ui.separator();
             }
         });
@@ -84,25 +112,26 @@ pub fn show_map_segments_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
         match header.as_str() {
             "SETD" => {
                 log_write("Cannot delete Sprite database", LogLevel::Warn);
-                return;
+                return revert;
             }
             "SCEN" => {
                 log_write("Cannot delete Background", LogLevel::Warn);
-                return;
+                return revert;
             }
             _ => { /* Do nothing */ }
         }
         de.loaded_map.segments.remove(to_del);
         de.graphics_update_needed = true;
-        de.unsaved_changes = true;
+        de.map_edited();
     }
+    revert
 }
 
-fn show_scen_data(ui: &mut egui::Ui, scen: &mut BackgroundData) {
+fn show_scen_data(ui: &mut egui::Ui, scen: &mut BackgroundData, base: NumberBase) {
     let info = scen.get_info().expect("INFO is guaranteed");
     ui.label(format!("BG Index: {}",info.which_bg));
     let charset = info.imbz_filename_noext.as_deref().unwrap_or("N/A");
     ui.label(format!("Charset: {charset}"));
-    ui.label(format!("X Scroll Speed: 0x{:X}",info.x_scroll));
-    ui.label(format!("Y Scroll Speed: 0x{:X}",info.y_scroll));
+    ui.label(format!("X Scroll Speed: {}",base.fmt_num(info.x_scroll, 1)));
+    ui.label(format!("Y Scroll Speed: {}",base.fmt_num(info.y_scroll, 1)));
 }
@@ -11,3 +11,13 @@ pub mod map_segs;
 pub mod scen_segs;
 pub mod resize;
 pub mod settings;
+pub mod level_switcher;
+pub mod modified_files;
+pub mod history_win;
+pub mod sprite_check;
+pub mod shift_map;
+pub mod file_viewer;
+pub mod clipboard_win;
+pub mod bookmarks_win;
+pub mod script_win;
+pub mod clear_selection;
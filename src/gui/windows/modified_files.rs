@@ -0,0 +1,110 @@
+use std::path::Path;
+
+use egui::{Color32, RichText};
+use egui_extras::{Column, TableBuilder};
+
+use crate::{engine::filesys::{self, FileChange, ManifestError, ModifiedFile}, utils::{log_write, LogLevel}};
+
+#[derive(Default)]
+pub struct ModifiedFilesState {
+    pub changes: Vec<ModifiedFile>,
+    pub manifest_missing: bool,
+    pub refresh_needed: bool
+}
+
+impl ModifiedFilesState {
+    pub fn refresh(&mut self, project_dir: &Path) -> Result<(), ManifestError> {
+        log_write("Comparing project files against manifest", LogLevel::Debug);
+        self.refresh_needed = false;
+        self.changes.clear();
+        let original = match filesys::read_manifest(project_dir) {
+            Ok(m) => m,
+            Err(ManifestError::Io(_)) => {
+                self.manifest_missing = true;
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        self.manifest_missing = false;
+        let current = filesys::build_manifest(project_dir)?;
+        self.changes = filesys::compare_manifests(&original, &current);
+        Ok(())
+    }
+}
+
+fn format_size(size: Option<u64>) -> String {
+    match size {
+        Some(size) => format!("0x{:X}",size),
+        None => String::from("-"),
+    }
+}
+
+/// Returns the relative path of a file that was reverted
+pub fn show_modified_files_window(ui: &mut egui::Ui, project_dir: &Path, state: &mut ModifiedFilesState) -> Option<String> {
+    puffin::profile_function!();
+    if state.refresh_needed {
+        if let Err(error) = state.refresh(project_dir) {
+            log_write(format!("Failed to compare project files: {error}"), LogLevel::Error);
+        }
+    }
+    if state.manifest_missing {
+        ui.label("This project has no file manifest, it was likely extracted with an older version");
+        if ui.button("Use current files as originals").clicked() {
+            if let Err(error) = filesys::create_manifest(project_dir) {
+                log_write(format!("Failed to create manifest: {error}"), LogLevel::Error);
+            }
+            state.refresh_needed = true;
+        }
+        return None;
+    }
+    ui.horizontal(|ui| {
+        if ui.button("Refresh").clicked() {
+            state.refresh_needed = true;
+        }
+        ui.label(format!("{} files differ from the original",state.changes.len()));
+    });
+    let mut reverted: Option<String> = Option::None;
+    TableBuilder::new(ui)
+        .striped(true)
+        .column(Column::remainder().at_least(150.0))
+        .column(Column::auto())
+        .column(Column::auto())
+        .column(Column::auto())
+        .header(20.0, |mut header| {
+            header.col(|ui| { ui.strong("File"); });
+            header.col(|ui| { ui.strong("Change"); });
+            header.col(|ui| { ui.strong("Size"); });
+            header.col(|ui| { ui.strong(""); });
+        })
+        .body(|mut body| {
+            for change in &state.changes {
+                body.row(20.0, |mut row| {
+                    row.col(|ui| { ui.label(&change.rel_path); });
+                    row.col(|ui| {
+                        let _ = match change.change {
+                            FileChange::Modified => ui.label(RichText::new("Modified").color(Color32::YELLOW)),
+                            FileChange::Added => ui.label(RichText::new("Added").color(Color32::GREEN)),
+                            FileChange::Removed => ui.label(RichText::new("Removed").color(Color32::RED)),
+                        };
+                    });
+                    row.col(|ui| {
+                        ui.label(format!("{} -> {}",format_size(change.original_size),format_size(change.current_size)));
+                    });
+                    row.col(|ui| {
+                        // Added files have nothing to go back to
+                        let can_revert = change.change != FileChange::Added;
+                        if ui.add_enabled(can_revert, egui::Button::new("Revert")).clicked() {
+                            match filesys::revert_file(project_dir, &change.rel_path) {
+                                Ok(_) => reverted = Some(change.rel_path.clone()),
+                                Err(error) => log_write(format!("Failed to revert file: {error}"), LogLevel::Error),
+                            }
+                        }
+                    });
+                });
+            }
+        });
+    if reverted.is_some() {
+        state.refresh_needed = true;
+    }
+    reverted
+}
@@ -9,6 +9,7 @@ const PAL_RECT: Vec2 = Vec2::new(PAL_BOX_WIDTH, PAL_BOX_HEIGHT);
 pub fn palette_window_show(ui: &mut egui::Ui, de: &DisplayEngine) {
     puffin::profile_function!();
     let top_left: Pos2 = ui.min_rect().min;
+    let base = de.display_settings.number_base;
     for y in 0..16 {
         for x in 0..16 {
             let col = &de.bg_palettes[y].colors[x].color;
@@ -20,7 +21,7 @@ pub fn palette_window_show(ui: &mut egui::Ui, de: &DisplayEngine) {
                 top_left.y + 2.0 + (y as f32) * PAL_BOX_HEIGHT
             ),
             Align2::LEFT_TOP,
-            format!("0x{:X}",y as u32),
+            base.fmt_num(y as u32, 1),
             FontId::monospace(10.0),
             Color32::WHITE
         );
@@ -42,7 +43,7 @@ pub fn palette_window_show(ui: &mut egui::Ui, de: &DisplayEngine) {
 
         //println!("x: {:X}, y: {:X}",mouse_x,mouse_y);
         if mouse_x <= 0xF && mouse_y <= 0xF {
-            hover_label = format!("BGP {:X} - Color {:X} - 0x{:04X} - 0x{:08X}",mouse_y,mouse_x,short_val,addr_val);
+            hover_label = format!("BGP {} - Color {} - 0x{:04X} - 0x{:08X}",base.fmt_num(mouse_y, 1),base.fmt_num(mouse_x, 1),short_val,addr_val);
         }
     }
     ui.label(hover_label);
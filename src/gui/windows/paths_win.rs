@@ -23,6 +23,7 @@ pub fn show_paths_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
         if create.clicked() {
             let pd = PathDatabase::default();
             de.loaded_map.segments.push(TopLevelSegmentWrapper::PATH(pd));
+            de.map_edited();
             log_write("Create PATH database", LogLevel::Log);
             return;
         }
@@ -46,6 +47,7 @@ pub fn show_paths_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
 }
 
 fn draw_path_list(ui: &mut egui::Ui, de: &mut DisplayEngine) {
+    let base = de.display_settings.number_base;
     ui.horizontal(|ui| {
         let btn_add = ui.add(egui::Button::new("New"));
         if btn_add.clicked() {
@@ -61,7 +63,7 @@ fn draw_path_list(ui: &mut egui::Ui, de: &mut DisplayEngine) {
             path.lines.push(new_blank_line);
             path.fix_term();
             de.graphics_update_needed = true;
-            de.unsaved_changes = true;
+            de.map_edited();
             log_write("New PathLine created", LogLevel::Debug);
         }
         ui.style_mut().visuals.widgets.hovered.weak_bg_fill = Color32::RED;
@@ -75,9 +77,9 @@ fn draw_path_list(ui: &mut egui::Ui, de: &mut DisplayEngine) {
             let _ = path.delete_line(de.path_settings.selected_line);
             de.path_settings.selected_line = Uuid::nil();
             de.path_settings.selected_point = Uuid::nil();
-            de.unsaved_changes = true;
             de.graphics_update_needed = true;
             path.fix_term();
+            de.map_edited();
             log_write("Line deleted", LogLevel::Log);
         }
     });
@@ -96,7 +98,7 @@ fn draw_path_list(ui: &mut egui::Ui, de: &mut DisplayEngine) {
                     let row_index = row.index();
                     row.set_selected(de.path_settings.selected_line == path.uuid);
                     row.col(|ui| {
-                        let label = ui.label(format!("Path 0x{:X}",row_index));
+                        let label = ui.label(format!("Path {}",base.fmt_num(row_index as u32, 1)));
                         if label.clicked() {
                             de.path_settings.selected_line = path.uuid;
                             de.path_settings.selected_point = Uuid::nil();
@@ -112,6 +114,7 @@ fn draw_path_list(ui: &mut egui::Ui, de: &mut DisplayEngine) {
 }
 
 fn draw_point_list(ui: &mut egui::Ui, de: &mut DisplayEngine) {
+    let base = de.display_settings.number_base;
     ui.horizontal(|ui| {
         let new_btn = ui.add(egui::Button::new("New"));
         if new_btn.clicked() {
@@ -127,9 +130,9 @@ fn draw_point_list(ui: &mut egui::Ui, de: &mut DisplayEngine) {
             };
             let new_point = PathPoint::default();
             line.points.push(new_point);
-            de.unsaved_changes = true;
             de.graphics_update_needed = true;
             path.fix_term();
+            de.map_edited();
             log_write("PathPoint created", LogLevel::Log);
         }
         ui.style_mut().visuals.widgets.hovered.weak_bg_fill = Color32::RED;
@@ -160,8 +163,8 @@ fn draw_point_list(ui: &mut egui::Ui, de: &mut DisplayEngine) {
             line.points.remove(point_pos);
             de.path_settings.selected_point = Uuid::nil();
             de.graphics_update_needed = true;
-            de.unsaved_changes = true;
             path.fix_term();
+            de.map_edited();
             log_write("Point deleted", LogLevel::Log);
         }
     });
@@ -182,7 +185,7 @@ fn draw_point_list(ui: &mut egui::Ui, de: &mut DisplayEngine) {
                         let row_index = row.index();
                         row.set_selected(de.path_settings.selected_point == point.uuid);
                         row.col(|ui| {
-                            let label = ui.label(format!("Point 0x{:X}",row_index));
+                            let label = ui.label(format!("Point {}",base.fmt_num(row_index as u32, 1)));
                             if label.clicked() {
                                 de.path_settings.selected_point = point.uuid;
                             }
@@ -197,6 +200,7 @@ fn draw_point_list(ui: &mut egui::Ui, de: &mut DisplayEngine) {
 }
 
 fn draw_point_settings(ui: &mut egui::Ui, de: &mut DisplayEngine) {
+    let base = de.display_settings.number_base;
     if de.path_settings.selected_line.is_nil() {
         return;
     }
@@ -210,9 +214,8 @@ fn draw_point_settings(ui: &mut egui::Ui, de: &mut DisplayEngine) {
             let point_before = *point;
             //ui.label("Warning: This section is WIP, red connecting line is not accurate");
             ui.horizontal(|ui| {
-                let angle = egui::DragValue::new(&mut point.angle)
-                    .speed(0x10)
-                    .hexadecimal(5, false, true);
+                let angle = base.drag_value(egui::DragValue::new(&mut point.angle), 5)
+                    .speed(0x10);
                 let angleres = ui.add(angle);
                 if angleres.has_focus() {
                     *NON_MAIN_FOCUSED.lock().unwrap() = true;
@@ -220,8 +223,7 @@ fn draw_point_settings(ui: &mut egui::Ui, de: &mut DisplayEngine) {
                 ui.label("Angle");
             });
             ui.horizontal(|ui| {
-                let distance = egui::DragValue::new(&mut point.distance)
-                    .hexadecimal(4, false,true);
+                let distance = base.drag_value(egui::DragValue::new(&mut point.distance), 4);
                 let distres = ui.add(distance);
                 if distres.has_focus() {
                     *NON_MAIN_FOCUSED.lock().unwrap() = true;
@@ -230,8 +232,7 @@ fn draw_point_settings(ui: &mut egui::Ui, de: &mut DisplayEngine) {
             });
             // Then X and Y
             ui.horizontal(|ui| {
-                let x_drag = egui::DragValue::new(&mut point.x_fine)
-                    .hexadecimal(8, false, true)
+                let x_drag = base.drag_value(egui::DragValue::new(&mut point.x_fine), 8)
                     .speed(CHANGE_RATE)
                     .range(0..=u32::MAX);
                 let xres = ui.label("X (Fine)");
@@ -241,8 +242,7 @@ fn draw_point_settings(ui: &mut egui::Ui, de: &mut DisplayEngine) {
                 ui.add(x_drag);
             });
             ui.horizontal(|ui| {
-                let y_drag = egui::DragValue::new(&mut point.y_fine)
-                    .hexadecimal(8, false, true)
+                let y_drag = base.drag_value(egui::DragValue::new(&mut point.y_fine), 8)
                     .speed(CHANGE_RATE)
                     .range(0..=u32::MAX);
                 let yres = ui.label("Y (Fine)");
@@ -253,7 +253,7 @@ fn draw_point_settings(ui: &mut egui::Ui, de: &mut DisplayEngine) {
             });
             if point_before != *point {
                 path_db.fix_term();
-                de.unsaved_changes = true;
+                de.map_edited();
                 de.graphics_update_needed = true;
             }
         }
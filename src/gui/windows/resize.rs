@@ -39,15 +39,15 @@ pub fn show_resize_modal(ui: &mut egui::Ui, de: &mut DisplayEngine, settings: &m
     }
     ui.heading("Resize Current Layer");
     ui.label("Width and height must both be even numbers");
-    ui.label(format!("Current Width and Height: 0x{:X}/0x{:X}",info.layer_width,info.layer_height));
+    let base = de.display_settings.number_base;
+    ui.label(format!("Current Width and Height: {}/{}",base.fmt_num(info.layer_width, 1),base.fmt_num(info.layer_height, 1)));
     if settings.new_height < info.layer_height || settings.new_width < info.layer_width {
         ui.label(egui::RichText::new("Warning: this action is highly destructive").color(Color32::RED));
     } else {
         ui.label(" ");
     }
     ui.horizontal(|ui| {
-        let width = egui::DragValue::new(&mut settings.new_width)
-            .hexadecimal(4, false, true)
+        let width = base.drag_value(egui::DragValue::new(&mut settings.new_width), 4)
             .range(0..=0xffff);
         let wres = ui.add(width);
         if wres.has_focus() {
@@ -56,8 +56,7 @@ pub fn show_resize_modal(ui: &mut egui::Ui, de: &mut DisplayEngine, settings: &m
         ui.label("Width")
     });
     ui.horizontal(|ui| {
-        let height = egui::DragValue::new(&mut settings.new_height)
-            .hexadecimal(4, false, true)
+        let height = base.drag_value(egui::DragValue::new(&mut settings.new_height), 4)
             .range(0..=0xffff);
         let lres = ui.add(height);
         if lres.has_focus() {
@@ -74,10 +73,10 @@ pub fn show_resize_modal(ui: &mut egui::Ui, de: &mut DisplayEngine, settings: &m
             settings.window_open = false;
         }
         // No odd values
-        if settings.new_height % 2 != 0 {
+        if !settings.new_height.is_multiple_of(2) {
             okay_enabled = false;
         }
-        if settings.new_width % 2 != 0 {
+        if !settings.new_width.is_multiple_of(2) {
             okay_enabled = false;
         }
         let button_ok = ui.add_enabled(okay_enabled, egui::Button::new("Okay"));
@@ -144,7 +143,7 @@ pub fn show_resize_modal(ui: &mut egui::Ui, de: &mut DisplayEngine, settings: &m
             log_write(format!("Trimmed {} Sprites on resize",trimmed), LogLevel::Debug);
             // Do things to trigger updates
             log_write("graphics updated", LogLevel::Debug);
-            de.unsaved_changes = true;
+            de.map_edited();
             de.graphics_update_needed = true;
             settings.reset_needed = true;
             settings.window_open = false;
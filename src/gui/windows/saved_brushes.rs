@@ -1,12 +1,15 @@
 use std::{cmp::Ordering, error::Error, fs::File, io::{BufReader, Write}, ops::Deref, sync::LazyLock};
 
-use egui::{CursorIcon, TextEdit};
+use egui::{CursorIcon, Modal, TextEdit};
 use egui_extras::{Column, TableBuilder};
 use serde_json::json;
 
-use crate::{data::backgrounddata::BackgroundData, engine::displayengine::DisplayEngine, gui::windows::brushes::{BrushType, STORED_BRUSHES}, utils::{is_debug, log_write, LogLevel}, NON_MAIN_FOCUSED};
+use crate::{data::backgrounddata::BackgroundData, engine::displayengine::DisplayEngine, gui::windows::brushes::{BrushType, STORED_BRUSHES, TILE_MAPPINGS}, utils::{is_debug, log_write, LogLevel}, NON_MAIN_FOCUSED};
 
-use super::brushes::{Brush, StoredBrushes};
+use super::brushes::{render_brush_image, Brush, StoredBrushes};
+
+/// Longest side of the hover preview, in screen pixels
+const BRUSH_PREVIEW_MAX: f32 = 128.0;
 
 pub fn show_saved_brushes_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
     puffin::profile_function!();
@@ -37,6 +40,10 @@ pub fn show_saved_brushes_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
         ui.label(format!("Current layer is not loaded: '{}'",which_bg));
         return;
     }
+    if de.brush_settings.preview_cache_bg != which_bg {
+        de.brush_settings.preview_cache.clear();
+        de.brush_settings.preview_cache_bg = which_bg;
+    }
     ui.label(format!("Current tileset file: '{}'", tileset_name));
     let checkbox = ui.checkbox(&mut de.brush_settings.only_show_same_tileset, "Only show same tileset");
     if checkbox.hovered() {
@@ -51,6 +58,9 @@ pub fn show_saved_brushes_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
             *NON_MAIN_FOCUSED.lock().unwrap() = true;
         }
     });
+    let mut load_request: Option<(BrushType, usize)> = Option::None;
+    let mut convert_request: Option<(BrushType, usize)> = Option::None;
+    let mut list_changed = false;
     let _table = TableBuilder::new(ui)
         .striped(true)
         .column(Column::remainder())
@@ -86,39 +96,57 @@ pub fn show_saved_brushes_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
                         if !tileset_match {
                             ui.disable();
                         }
-                        // TODO: remove interaction
-                        let label_name = ui.label(&stamp.name);
-                        if label_name.clicked() {
-                            if tileset_match {
-                                de.brush_settings.cur_selected_brush = Some((brush_type, i));
-                                de.current_brush = stamp.clone();
-                            }
-                        }
+                        // Clicks go to the row
+                        ui.add(egui::Label::new(&stamp.name).selectable(false));
                     });
                     row.col(|ui| {
                         if !tileset_match {
                             ui.disable();
                         }
-                        // TODO: remove interaction
-                        let tileset_label = ui.label(&stamp.tileset);
-                        if tileset_label.clicked() {
-                            if tileset_match {
-                                de.brush_settings.cur_selected_brush = Some((brush_type, i));
-                                de.current_brush = stamp.clone();
-                            }
-                        }
+                        ui.add(egui::Label::new(&stamp.tileset).selectable(false));
                     });
 
                     let response = row.response();
 
-                    if response.clicked() {
-                        if tileset_match {
-                            de.brush_settings.cur_selected_brush = Some((brush_type, i));
-                            de.current_brush = stamp.clone();
-                        }
+                    // Clicking only selects, so peeking doesn't lose the current Brush
+                    if response.clicked() && tileset_match {
+                        de.brush_settings.cur_selected_brush = Some((brush_type, i));
                     }
+                    if response.double_clicked() && tileset_match {
+                        load_request = Some((brush_type, i));
+                    }
+                    let response = response.on_hover_ui(|ui| {
+                        let Some(bg_layer) = layer else { return };
+                        let Some(pixel_tiles) = &bg_layer.pixel_tiles_preview else {
+                            ui.label("Tiles not loaded");
+                            return;
+                        };
+                        let is_256 = bg_layer.get_info().is_some_and(|info| info.is_256_colorpal_mode());
+                        let palette256 = bg_layer.get_pltb().and_then(|pltb| pltb.palettes.first());
+                        let texture = de.brush_settings.preview_cache.entry((brush_type, i)).or_insert_with(|| {
+                            let image = render_brush_image(stamp, &de.bg_palettes, palette256, pixel_tiles, is_256, bg_layer._pal_offset);
+                            ui.ctx().load_texture("brush_preview", image, egui::TextureOptions::NEAREST)
+                        });
+                        let size = texture.size_vec2();
+                        if size.x == 0.0 || size.y == 0.0 {
+                            ui.label("Empty Brush");
+                            return;
+                        }
+                        let scale = (BRUSH_PREVIEW_MAX / size.x.max(size.y)).min(4.0);
+                        ui.add(egui::Image::new((texture.id(), size * scale)));
+                        ui.label(format!("{}x{} tiles, double-click to load",stamp.width,stamp.height));
+                    });
 
                     response.context_menu(|ui| {
+                        if !tileset_match {
+                            let can_convert = TILE_MAPPINGS.find(&stamp.tileset, &tileset_name).is_some();
+                            let convert = ui.add_enabled(can_convert, egui::Button::new("Load Converted to Current Tileset"))
+                                .on_disabled_hover_text("No tile mapping between these tilesets");
+                            if convert.clicked() {
+                                convert_request = Some((brush_type, i));
+                                ui.close_menu();
+                            }
+                        }
                         ui.add_enabled_ui(saved_brushes.is_some(), |ui| {
                             let overwrite = ui.add_enabled_ui(
                                 de.brush_settings.cur_selected_brush.is_some(),
@@ -132,10 +160,13 @@ pub fn show_saved_brushes_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
                                     saved_brushes[i] = de.current_brush.clone(); // this also clones the string name :/
                                     saved_brushes[i].name = name;
                                     save_brushes_to_file(saved_brushes);
+                                    de.brush_settings.current_brush_edited = false;
+                                    list_changed = true;
                                 }
                                 if delete.clicked() {
                                     saved_brushes.remove(i);
                                     save_brushes_to_file(saved_brushes);
+                                    list_changed = true;
                                     // update selected brush index
                                     if let Some((_, ref mut sel_i)) = de.brush_settings.cur_selected_brush {
                                         match sel_i.deref().cmp(&i) {
@@ -160,7 +191,21 @@ pub fn show_saved_brushes_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
                 create_brush_row(i, BrushType::Saved, &stamp, Some(&mut de.saved_brushes));
             }
         });
+    if list_changed {
+        // Indexes moved
+        de.brush_settings.preview_cache.clear();
+    }
     ui.add_space(5.0);
+    ui.horizontal(|ui| {
+        let button_load = ui.add_enabled(de.brush_settings.cur_selected_brush.is_some(), egui::Button::new("Load"))
+            .on_hover_text("Replaces the current Brush with the selected one");
+        if button_load.clicked() {
+            load_request = de.brush_settings.cur_selected_brush;
+        }
+        if de.brush_settings.current_brush_edited {
+            ui.label("Current Brush has unsaved edits");
+        }
+    });
     ui.horizontal(|ui| {
         let store_enabled = !de.current_brush.tiles.is_empty();
         let button_store = ui.add_enabled(store_enabled, egui::Button::new("Store Current Brush"));
@@ -176,6 +221,7 @@ pub fn show_saved_brushes_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
             // Height, Width, Tiles already set in Brush window
             de.saved_brushes.push(de.current_brush.clone());
             de.brush_settings.pos_brush_name.clear();
+            de.brush_settings.current_brush_edited = false;
             save_brushes_to_file(&de.saved_brushes);
         }
         if store_enabled {
@@ -199,6 +245,28 @@ pub fn show_saved_brushes_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
             }
         });
     }
+    if let Some((brush_type, index)) = load_request {
+        de.request_brush_load(brush_type, index);
+    }
+    // Converted right away unless the overwrite prompt is up, which loads it as is
+    if let Some((brush_type, index)) = convert_request && de.request_brush_load(brush_type, index) {
+        de.brush_settings.convert_brush(&mut de.current_brush, &tileset_name);
+    }
+    if let Some((brush_type, index)) = de.brush_settings.pending_load {
+        Modal::new(egui::Id::new("brush_overwrite_modal")).show(ui.ctx(), |ui| {
+            ui.heading("Replace current Brush?");
+            ui.label("The current Brush has tiles placed by hand that were never stored");
+            ui.horizontal(|ui| {
+                if ui.button("Cancel").clicked() {
+                    de.brush_settings.pending_load = Option::None;
+                }
+                if ui.button("Replace").clicked() {
+                    de.brush_settings.pending_load = Option::None;
+                    de.load_brush(brush_type, index);
+                }
+            });
+        });
+    }
 }
 
 pub fn load_stored_brushes() {
@@ -207,6 +275,12 @@ pub fn load_stored_brushes() {
     log_write("Loaded stored brushes successfully", LogLevel::Log);
 }
 
+pub fn load_tile_mappings() {
+    log_write("Loading tile mappings...", LogLevel::Debug);
+    LazyLock::force(&TILE_MAPPINGS);
+    log_write("Loaded tile mappings successfully", LogLevel::Log);
+}
+
 const SAVED_BRUSHES_FILE: &str = "saved_brushes.json";
 
 pub fn save_brushes_to_file(brushes: &[Brush]) {
@@ -230,11 +304,40 @@ fn load_saved_brushes() -> Result<Vec<Brush>,Box<dyn Error>> {
         Ok(f) => f,
     };
     let reader = BufReader::new(file);
-    let saved_brushes: StoredBrushes = serde_json::from_reader(reader)?;
+    let mut saved_brushes: StoredBrushes = serde_json::from_reader(reader)?;
+    saved_brushes.brushes.iter_mut().for_each(Brush::upgrade_format);
     Ok(saved_brushes.brushes)
 }
 
 impl DisplayEngine {
+    /// Loads the Brush, unless that would lose hand placed tiles, then it waits
+    /// on the overwrite prompt instead. Returns true if it loaded
+    pub fn request_brush_load(&mut self, brush_type: BrushType, index: usize) -> bool {
+        if self.brush_settings.current_brush_edited {
+            self.brush_settings.pending_load = Some((brush_type, index));
+            return false;
+        }
+        self.load_brush(brush_type, index)
+    }
+
+    /// Replaces current_brush with a Stored or Saved Brush
+    pub fn load_brush(&mut self, brush_type: BrushType, index: usize) -> bool {
+        let brush = match brush_type {
+            BrushType::Stored => STORED_BRUSHES.brushes.get(index),
+            BrushType::Saved => self.saved_brushes.get(index)
+        };
+        let Some(brush) = brush.cloned() else {
+            log_write(format!("No {:?} Brush at index {}",brush_type,index), LogLevel::Warn);
+            return false;
+        };
+        log_write(format!("Loading Brush: {}",brush), LogLevel::Debug);
+        self.current_brush = brush;
+        self.brush_settings.conversion_report = None;
+        self.brush_settings.cur_selected_brush = Some((brush_type, index));
+        self.brush_settings.current_brush_edited = false;
+        true
+    }
+
     pub fn load_saved_brushes(&mut self) {
         log_write("Loading Saved brushes...", LogLevel::Debug);
         match load_saved_brushes() {
@@ -248,3 +351,26 @@ impl DisplayEngine {
         }
     }
 }
+
+#[cfg(test)]
+mod tests_saved_brushes {
+    use super::*;
+
+    #[test]
+    fn test_request_brush_load() {
+        let saved = Brush { name: String::from("Saved"), width: 1, height: 1, tiles: vec![Some(3)], ..Default::default() };
+        let mut de = DisplayEngine { saved_brushes: vec![saved], ..Default::default() };
+        assert!(de.request_brush_load(BrushType::Saved, 0));
+        assert_eq!(de.current_brush.name,"Saved");
+        assert!(!de.load_brush(BrushType::Saved, 4));
+        // Hand placed tiles wait on the prompt
+        de.current_brush.tiles[0] = Some(7);
+        de.brush_settings.current_brush_edited = true;
+        assert!(!de.request_brush_load(BrushType::Saved, 0));
+        assert_eq!(de.current_brush.tiles,vec![Some(7)]);
+        assert_eq!(de.brush_settings.pending_load,Some((BrushType::Saved, 0)));
+        assert!(de.load_brush(BrushType::Saved, 0));
+        assert!(!de.brush_settings.current_brush_edited);
+        assert_eq!(de.brush_settings.cur_selected_brush,Some((BrushType::Saved, 0)));
+    }
+}
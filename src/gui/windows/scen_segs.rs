@@ -1,10 +1,19 @@
 use egui::Color32;
 
-use crate::{data::{scendata::{info::ScenInfoData, ScenSegment, ScenSegmentWrapper}, types::CurrentLayer}, engine::displayengine::DisplayEngine, utils::{log_write, LogLevel}, NON_MAIN_FOCUSED};
+use crate::{data::{scendata::{info::ScenInfoData, ScenSegment, ScenSegmentWrapper}, types::CurrentLayer}, engine::{displayengine::DisplayEngine, segment_state::{SegmentRevert, SegmentState}}, gui::gui::NumberBase, utils::{log_write, LogLevel}, NON_MAIN_FOCUSED};
 
-pub fn show_scen_segments_window(ui: &mut egui::Ui, de: &mut DisplayEngine, layer: &CurrentLayer) {
+use super::map_segs::segment_state_badge;
+
+/// Returns a segment to revert, same as show_map_segments_window
+pub fn show_scen_segments_window(ui: &mut egui::Ui, de: &mut DisplayEngine, layer: &CurrentLayer) -> Option<SegmentRevert> {
     puffin::profile_function!();
     let mut do_del: Option<usize> = Option::None;
+    let mut revert: Option<SegmentRevert> = Option::None;
+    let base = de.display_settings.number_base;
+    show_palette_shift(ui, de, layer);
+    let priorities = de.loaded_map.bg_priorities();
+    // The layer stays borrowed through the loop, so map_edited waits until after
+    let mut edited = false;
     egui::ScrollArea::vertical()
     .auto_shrink(false)
     .min_scrolled_height(1.0)
@@ -13,6 +22,12 @@ pub fn show_scen_segments_window(ui: &mut egui::Ui, de: &mut DisplayEngine, laye
             ui.label("Not on a loaded background layer");
             return;
         };
+        let Some((layer_width, layer_height)) = bg.get_info().map(|info| (info.layer_width, info.layer_height)) else {
+            ui.label("ERROR: Could not retrieve INFO");
+            return;
+        };
+        let which_bg = *layer as u8;
+        let states: Vec<SegmentState> = de.segment_baseline.scen_states(bg).to_vec();
         for (i,seg) in &mut bg.scen_segments.iter_mut().enumerate() {
             let header = seg.header();
             let header = header.as_str();
@@ -20,11 +35,10 @@ pub fn show_scen_segments_window(ui: &mut egui::Ui, de: &mut DisplayEngine, laye
                 "INFO" => {
                     ui.heading("INFO");
                     if let ScenSegmentWrapper::INFO(info) = seg {
-                        let changed = show_info_segment(ui, info);
+                        let changed = show_info_segment(ui, info, base, &priorities);
                         if changed {
                             log_write("Changed INFO", LogLevel::Debug);
-                            de.unsaved_changes = true;
-                            de.graphics_update_needed = true;
+                            edited = true;
                         }
                     } else {
                         ui.label("ERROR: Could not retrieve INFO");
@@ -53,8 +67,34 @@ pub fn show_scen_segments_window(ui: &mut egui::Ui, de: &mut DisplayEngine, laye
                     if let ScenSegmentWrapper::MPBZ(mpbz) = seg {
                         let map_tile_count = mpbz.tiles.len();
                         ui.label(format!("Map Tile count: 0x{:X} ({})",map_tile_count,map_tile_count));
-                        ui.label(format!("Bottom Trim: 0x{:X} ({})",mpbz.bottom_trim,mpbz.bottom_trim));
-                        ui.label(format!("Tile Offset: 0x{:X} ({})",mpbz.tile_offset,mpbz.tile_offset));
+                        let mut start_row = mpbz.start_row();
+                        ui.horizontal(|ui| {
+                            let start_drag = base.drag_value(egui::DragValue::new(&mut start_row), 4)
+                                .speed(0.1)
+                                .range(0..=layer_height.saturating_sub(mpbz.bottom_trim));
+                            if ui.add(start_drag).has_focus() {
+                                *NON_MAIN_FOCUSED.lock().unwrap() = true;
+                            }
+                            ui.label("Start Row (Tile Offset)");
+                        }).response.on_hover_text("Blank rows above the saved tiles. Tiles above this row are not saved");
+                        if start_row != mpbz.start_row() {
+                            mpbz.set_start_row(start_row, layer_width);
+                            edited = true;
+                        }
+                        let mut bottom_trim = mpbz.bottom_trim;
+                        ui.horizontal(|ui| {
+                            let trim_drag = base.drag_value(egui::DragValue::new(&mut bottom_trim), 4)
+                                .speed(0.1)
+                                .range(0..=layer_height.saturating_sub(mpbz.start_row()));
+                            if ui.add(trim_drag).has_focus() {
+                                *NON_MAIN_FOCUSED.lock().unwrap() = true;
+                            }
+                            ui.label("Bottom Trim");
+                        }).response.on_hover_text("Rows cut off the bottom of the layer. They show as blank and are not saved");
+                        if bottom_trim != mpbz.bottom_trim {
+                            mpbz.set_bottom_trim(bottom_trim, layer_width, layer_height);
+                            edited = true;
+                        }
                     } else {
                         ui.label("ERROR: Could not retrieve MPBZ");
                     }
@@ -114,61 +154,102 @@ pub fn show_scen_segments_window(ui: &mut egui::Ui, de: &mut DisplayEngine, laye
                     }
                 }
                 _ => {
-                    ui.label(format!("Unhandled segment: '{}'",&seg.header()));
+                    ui.heading(header);
+                    if let ScenSegmentWrapper::Unknown(unkn) = seg {
+                        ui.label(format!("Raw Size in Bytes: 0x{:X}",unkn.raw_bytes.len()));
+                        ui.label("Unhandled, saved back unchanged");
+                    } else {
+                        ui.label(format!("Unhandled segment: '{}'",header));
+                    }
                 }
             }
-            ui.style_mut().visuals.widgets.hovered.weak_bg_fill = Color32::RED;
-            // Most SCEN segments are just too important to delete; all connected
-            let is_deletable = header.eq("SCRL"); // So far this is the only easy one to handle
-            let del_button = ui.add_enabled(is_deletable, egui::Button::new("Delete"));
-            if del_button.clicked() {
-                do_del = Some(i);
-            }
+            ui.horizontal(|ui| {
+                if segment_state_badge(ui, states[i]) {
+                    revert = Some(SegmentRevert::Scen { which_bg, index: i });
+                }
+                ui.style_mut().visuals.widgets.hovered.weak_bg_fill = Color32::RED;
+                // Most SCEN segments are just too important to delete; all connected
+                let is_deletable = header.eq("SCRL"); // So far this is the only easy one to handle
+                let del_button = ui.add_enabled(is_deletable, egui::Button::new("Delete"));
+                if del_button.clicked() {
+                    do_del = Some(i);
+                }
+            });
             ui.separator();
         }
     });
+    if edited {
+        de.graphics_update_needed = true;
+        de.map_edited();
+    }
     if let Some(to_del) = do_del {
         let bg = de.loaded_map.get_background(*layer as u8).expect("BG missing canceled earlier");
         let header = bg.scen_segments[to_del].header();
         log_write(format!("Deleting segment '{}' at index {}",header,to_del), LogLevel::Log);
         bg.scen_segments.remove(to_del);
         de.graphics_update_needed = true;
-        de.unsaved_changes = true;
+        de.map_edited();
     }
+    revert
 }
 
-fn show_info_segment(ui: &mut egui::Ui, info: &mut ScenInfoData) -> bool {
+/// Fixes art placed on the wrong palette row, only while BG tiles are selected
+fn show_palette_shift(ui: &mut egui::Ui, de: &mut DisplayEngine, layer: &CurrentLayer) {
+    if de.bg_sel_data.selected_map_indexes.is_empty() || de.display_settings.current_layer != *layer {
+        return;
+    }
+    let Some(bg) = de.loaded_map.get_background(*layer as u8) else {
+        return;
+    };
+    if bg.get_info().is_some_and(|info| info.is_256_colorpal_mode()) {
+        ui.label("256 color layers do not use palette rows");
+        ui.separator();
+        return;
+    }
+    ui.horizontal(|ui| {
+        let delta_res = ui.add(egui::DragValue::new(&mut de.palette_shift_delta).range(-15..=15));
+        if delta_res.has_focus() {
+            *NON_MAIN_FOCUSED.lock().unwrap() = true;
+        }
+        let shift_button = ui.add_enabled(de.palette_shift_delta != 0, egui::Button::new("Shift palette"))
+            .on_hover_text(format!("Adds to the palette row of the {} selected tiles",de.bg_sel_data.selected_map_indexes.len()));
+        if shift_button.clicked() {
+            de.shift_selected_palettes(*layer as u8, de.palette_shift_delta);
+        }
+    });
+    ui.separator();
+}
+
+/// Priorities are every BG's, for warning about ties
+fn show_info_segment(ui: &mut egui::Ui, info: &mut ScenInfoData, base: NumberBase, priorities: &[(u8, u8)]) -> bool {
     let pre_change = info.clone();
     ui.horizontal(|ui| {
-        ui.label(format!("0x{:04X}",info.layer_width));
+        ui.label(base.fmt_num(info.layer_width, 4));
         ui.label("Layer Width");
     });
     ui.horizontal(|ui| {
-        ui.label(format!("0x{:04X}",info.layer_height));
+        ui.label(base.fmt_num(info.layer_height, 4));
         ui.label("Layer Height");
     });
     // Offset
     ui.horizontal(|ui| {
-        let x_offset_drag = egui::DragValue::new(&mut info.x_offset_px)
+        let x_offset_drag = base.drag_value(egui::DragValue::new(&mut info.x_offset_px), 4)
             .speed(0x1)
-            .hexadecimal(4, false, true)
             .range(i16::MIN..=i16::MAX);
         ui.add(x_offset_drag);
         ui.label("X Offset (px)");
     });
     ui.horizontal(|ui| {
-        let y_offset_drag = egui::DragValue::new(&mut info.y_offset_px)
+        let y_offset_drag = base.drag_value(egui::DragValue::new(&mut info.y_offset_px), 4)
             .speed(0x1)
-            .hexadecimal(4, false, true)
             .range(i16::MIN..=i16::MAX);
         ui.add(y_offset_drag);
         ui.label("Y Offset (px)");
     });
     // Scroll
     ui.horizontal(|ui| {
-        let scroll_drag = egui::DragValue::new(&mut info.x_scroll)
+        let scroll_drag = base.drag_value(egui::DragValue::new(&mut info.x_scroll), 8)
             .speed(0x100)
-            .hexadecimal(8, false, true)
             .range(0..=0xffffff);
         ui.add(scroll_drag);
         ui.label("X Scroll");
@@ -177,9 +258,8 @@ fn show_info_segment(ui: &mut egui::Ui, info: &mut ScenInfoData) -> bool {
         }
     });
     ui.horizontal(|ui| {
-        let scroll_drag = egui::DragValue::new(&mut info.y_scroll)
+        let scroll_drag = base.drag_value(egui::DragValue::new(&mut info.y_scroll), 8)
             .speed(0x100)
-            .hexadecimal(8, false, true)
             .range(0..=0xffffff);
         ui.add(scroll_drag);
         ui.label("Y Scroll");
@@ -192,9 +272,21 @@ fn show_info_segment(ui: &mut egui::Ui, info: &mut ScenInfoData) -> bool {
         ui.label("BG Index");
     });
     ui.horizontal(|ui| {
-        ui.label(format!("{}",info.layer_order));
+        // Left alone until edited, in case a map uses something odd
+        let order_drag = egui::DragValue::new(&mut info.layer_order)
+            .speed(0.05)
+            .range(0..=3)
+            .clamp_existing_to_range(false);
+        ui.add(order_drag);
         ui.label("Layer Order");
-    });
+    }).response.on_hover_text("Draw priority, 0 is in front");
+    let shared: Vec<String> = priorities.iter()
+        .filter(|(which_bg, order)| *which_bg != info.which_bg && *order == info.layer_order)
+        .map(|(which_bg, _)| format!("BG {which_bg}"))
+        .collect();
+    if !shared.is_empty() {
+        ui.colored_label(Color32::ORANGE, format!("Same Layer Order as {}, the lower BG is drawn in front",shared.join(", ")));
+    }
     ui.horizontal(|ui| {
         ui.label(format!("{}",info.char_base_block));
         ui.label("Char Base Block");
@@ -0,0 +1,74 @@
+use std::fs;
+
+use egui::{RichText, TextEdit};
+use rfd::FileDialog;
+
+use crate::{utils::{log_write, LogLevel}, NON_MAIN_FOCUSED};
+
+const SCRIPT_HELP: &str = "Rhai, with these on top of the standard functions:
+map_name(), sprites() or sprites(id), each a #{uuid, id, x, y, settings}
+move_sprites(list, dx, dy), delete_sprites(list), set_sprite_id(list, id)
+set_sprite_settings(list, \"hex bytes\"), add_sprite(id, x, y)
+tile(bg, x, y), set_tile(bg, x, y, tile), clear_tile(bg, x, y)
+collision(x, y), set_collision(x, y, type)
+const MAPS = \"01k*\"; limits the maps, a trailing * matches any ending";
+
+pub struct ScriptWindowState {
+    pub text: String,
+    /// Every saved map instead of the loaded one
+    pub on_project: bool,
+    pub dry_run: bool,
+    /// The last run's report or error
+    pub output: String
+}
+impl Default for ScriptWindowState {
+    fn default() -> Self {
+        Self {
+            text: String::from("// Move every Sprite 0x52 up a tile\nmove_sprites(sprites(0x52), 0, -1);\n"),
+            on_project: false,
+            // Look before you leap
+            dry_run: true,
+            output: String::new()
+        }
+    }
+}
+
+/// True if Run was clicked
+pub fn show_script_window(ui: &mut egui::Ui, state: &mut ScriptWindowState, running: bool) -> bool {
+    puffin::profile_function!();
+    ui.horizontal(|ui| {
+        if ui.button("Open...").clicked() {
+            if let Some(path) = FileDialog::new().set_title("Open Script").add_filter("Script", &["rhai", "txt"]).pick_file() {
+                match fs::read_to_string(&path) {
+                    Ok(text) => state.text = text,
+                    Err(error) => log_write(format!("Failed to read script '{}': {error}",path.display()), LogLevel::Error),
+                }
+            }
+        }
+        ui.label("Functions").on_hover_text(SCRIPT_HELP);
+    });
+    let script_res = ui.add(TextEdit::multiline(&mut state.text)
+        .code_editor()
+        .desired_rows(10)
+        .desired_width(f32::INFINITY));
+    if script_res.has_focus() {
+        *NON_MAIN_FOCUSED.lock().unwrap() = true;
+    }
+    ui.horizontal(|ui| {
+        ui.radio_value(&mut state.on_project, false, "Loaded map");
+        ui.radio_value(&mut state.on_project, true, "Every map in the project")
+            .on_hover_text("Each changed map is backed up, then saved");
+    });
+    ui.checkbox(&mut state.dry_run, "Dry run").on_hover_text("Lists what would change without changing anything");
+    let run_clicked = ui.add_enabled(!running, egui::Button::new("Run")).clicked();
+    if !state.output.is_empty() {
+        ui.separator();
+        egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+            ui.label(RichText::new(&state.output).monospace());
+        });
+        if ui.button("Copy Output").clicked() {
+            ui.ctx().copy_text(state.output.clone());
+        }
+    }
+    run_clicked
+}
@@ -1,8 +1,13 @@
+use std::path::PathBuf;
+
+use egui::Color32;
+use rfd::FileDialog;
 use strum::IntoEnumIterator;
 
-use crate::{engine::displayengine::DisplayEngine, gui::gui::StorkTheme};
+use crate::{data::types::{CurrentLayer, TILE_CACHE_PALETTES, TILE_CACHE_TILES}, engine::{displayengine::DisplayEngine, filesys::has_project_config, history::MapHistory}, gui::gui::{GridBackground, NumberBase, SpriteBoxMode, StorkTheme, ThemeColorPreset, ThemeColors, TileDim, TileNumberOverlay}, NON_MAIN_FOCUSED};
 
-pub fn stork_settings_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
+/// Returns the folder to reopen the project from, once it has been moved there
+pub fn stork_settings_window(ui: &mut egui::Ui, de: &mut DisplayEngine, history: &mut MapHistory, project_folder: &mut String) -> Option<PathBuf> {
     puffin::profile_function!();
     ui.heading("Settings");
     let _cur_layer_combo = egui::ComboBox::from_label("Theme")
@@ -12,14 +17,150 @@ pub fn stork_settings_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
                 ui.selectable_value(&mut de.display_settings.stork_theme, theme, theme.to_string());
             }
         });
-    let sys_theme = ui.ctx().system_theme().unwrap_or(egui::Theme::Dark);
-    ui.ctx().set_theme(match de.display_settings.stork_theme {
-        StorkTheme::Dark => egui::Theme::Dark,
-        StorkTheme::Light => egui::Theme::Light,
-        StorkTheme::Auto => sys_theme,
-    });
+    de.display_settings.stork_theme.apply(ui.ctx());
+    let _number_base_combo = egui::ComboBox::from_label("Number Display")
+        .selected_text(format!("{}",de.display_settings.number_base))
+        .show_ui(ui, |ui| {
+            for base in NumberBase::iter() {
+                ui.selectable_value(&mut de.display_settings.number_base, base, base.to_string());
+            }
+        });
+    let mut color_preset = de.display_settings.theme_colors.preset;
+    let _overlay_colors_combo = egui::ComboBox::from_label("Overlay colors")
+        .selected_text(format!("{}",color_preset))
+        .show_ui(ui, |ui| {
+            for preset in ThemeColorPreset::iter() {
+                ui.selectable_value(&mut color_preset, preset, preset.to_string());
+            }
+        })
+        .response.on_hover_text("Selections, Entrances/Exits, Paths and layer edges on the map");
+    if color_preset != de.display_settings.theme_colors.preset {
+        de.display_settings.theme_colors = ThemeColors::new(color_preset);
+    }
     // TODO: Sticky backgrounds. Check for primary layers with 256
     // Sprite Graphics Render Mode
-    let show_cb = egui::Checkbox::new(&mut de.display_settings.show_box_for_rendered, "Show true position of rendered Sprites");
-    ui.add(show_cb);
-}
\ No newline at end of file
+    let _sprite_box_combo = egui::ComboBox::from_label("Show true position of")
+        .selected_text(format!("{}",de.display_settings.sprite_box_mode))
+        .show_ui(ui, |ui| {
+            for mode in SpriteBoxMode::iter() {
+                ui.selectable_value(&mut de.display_settings.sprite_box_mode, mode, mode.to_string());
+            }
+        });
+    let _start_layer_combo = egui::ComboBox::from_label("Start on layer")
+        .selected_text(format!("{:?}",de.display_settings.start_layer))
+        .show_ui(ui, |ui| {
+            for layer in CurrentLayer::iter() {
+                ui.selectable_value(&mut de.display_settings.start_layer, layer, format!("{:?}",layer));
+            }
+        })
+        .response.on_hover_text("Selected after a Level is loaded");
+    let _collision_dim_combo = egui::ComboBox::from_label("BG tiles while editing Collision")
+        .selected_text(format!("{}",de.display_settings.collision_tile_dim))
+        .show_ui(ui, |ui| {
+            for dim in TileDim::iter() {
+                ui.selectable_value(&mut de.display_settings.collision_tile_dim, dim, dim.to_string());
+            }
+        });
+    ui.horizontal(|ui| {
+        let _grid_background_combo = egui::ComboBox::from_label("Map background")
+            .selected_text(format!("{}",de.display_settings.grid_background))
+            .show_ui(ui, |ui| {
+                for background in GridBackground::iter() {
+                    ui.selectable_value(&mut de.display_settings.grid_background, background, background.to_string());
+                }
+            })
+            .response.on_hover_text("Shows through transparent tiles. Only in the editor, never saved or exported");
+        if de.display_settings.grid_background == GridBackground::Solid {
+            ui.color_edit_button_srgb(&mut de.display_settings.grid_background_color);
+        }
+    });
+    ui.checkbox(&mut de.display_settings.auto_show_current_layer, "Show the current layer when switching to it")
+        .on_hover_text("Otherwise editing is disabled on hidden layers");
+    ui.checkbox(&mut de.display_settings.auto_open_companion, "Open a layer's tool window when switching to it")
+        .on_hover_text("Brush, Add Sprites, Collision, Paths or Triggers. Closing one keeps it closed for that layer");
+    ui.checkbox(&mut de.display_settings.smooth_scrolling, "Ease jumps and keyboard scrolling")
+        .on_hover_text("PageUp/PageDown, Home/End and Ctrl+arrows scroll the map. Off cuts straight there");
+    ui.checkbox(&mut de.display_settings.snap_bg_paste_to_even, "Snap pasted BG tiles to even positions")
+        .on_hover_text("Like Brush stamping. Turn off to paste at odd tiles exactly as copied");
+    ui.checkbox(&mut de.display_settings.keep_map_index, "Keep the map index when changing Level")
+        .on_hover_text("For comparing parallel rooms. Otherwise each Level opens on the last map viewed there");
+    // Undo History
+    ui.separator();
+    ui.label("Undo History");
+    let limits_before = (history.max_undos, history.max_memory_mb);
+    ui.horizontal(|ui| {
+        let undos_res = ui.add(egui::DragValue::new(&mut history.max_undos).range(1..=1000));
+        if undos_res.has_focus() {
+            *NON_MAIN_FOCUSED.lock().unwrap() = true;
+        }
+        ui.label("Max undo steps");
+    });
+    ui.horizontal(|ui| {
+        let memory_res = ui.add(egui::DragValue::new(&mut history.max_memory_mb).range(16..=8192).suffix(" MB"));
+        if memory_res.has_focus() {
+            *NON_MAIN_FOCUSED.lock().unwrap() = true;
+        }
+        ui.label("Max undo memory");
+    });
+    if limits_before != (history.max_undos, history.max_memory_mb) {
+        history.enforce_limits();
+    }
+    let usage_mb = history.memory_usage() as f64 / (1024.0 * 1024.0);
+    ui.label(format!("Currently using about {:.1} MB for {} steps",usage_mb,history.entries().len()));
+    // Tile Cache
+    ui.separator();
+    ui.label("Tile Cache");
+    let budget_before = de.display_settings.tile_cache_budget;
+    ui.horizontal(|ui| {
+        let budget_res = ui.add(egui::DragValue::new(&mut de.display_settings.tile_cache_budget).range(256..=TILE_CACHE_PALETTES * TILE_CACHE_TILES));
+        if budget_res.has_focus() {
+            *NON_MAIN_FOCUSED.lock().unwrap() = true;
+        }
+        ui.label("Max textures per BG layer")
+            .on_hover_text("Lower uses less memory, but tiles not drawn recently have to be rebuilt");
+    });
+    if budget_before != de.display_settings.tile_cache_budget {
+        de.apply_tile_cache_budget();
+    }
+    ui.label(format!("Currently holding {} textures",de.tile_cache_texture_count()));
+    // Project
+    ui.separator();
+    ui.label("Project Folder");
+    let mut moved_to: Option<PathBuf> = Option::None;
+    ui.add_enabled_ui(!de.export_folder.as_os_str().is_empty(), |ui| {
+        ui.horizontal(|ui| {
+            let folder_res = ui.add(egui::TextEdit::singleline(project_folder).desired_width(220.0));
+            if folder_res.has_focus() {
+                *NON_MAIN_FOCUSED.lock().unwrap() = true;
+            }
+            if ui.button("Browse...").clicked() {
+                if let Some(path) = FileDialog::new().set_title("Choose the moved project folder").pick_folder() {
+                    *project_folder = path.display().to_string();
+                }
+            }
+        });
+        let new_folder = PathBuf::from(project_folder.trim());
+        let changed = new_folder != de.export_folder;
+        let valid = has_project_config(&new_folder);
+        if changed && !valid {
+            ui.colored_label(Color32::ORANGE, "No config.yaml in that folder");
+        }
+        let move_button = ui.add_enabled(changed && valid, egui::Button::new("Use This Folder"))
+            .on_hover_text("After moving the project folder, reopens it from the new location");
+        if move_button.clicked() {
+            moved_to = Some(new_folder);
+        }
+    });
+    // Debug
+    ui.separator();
+    ui.label("Debug");
+    let _tile_overlay_combo = egui::ComboBox::from_label("Number on each BG tile")
+        .selected_text(format!("{}",de.display_settings.tile_number_overlay))
+        .show_ui(ui, |ui| {
+            for overlay in TileNumberOverlay::iter() {
+                ui.selectable_value(&mut de.display_settings.tile_number_overlay, overlay, overlay.to_string());
+            }
+        })
+        .response.on_hover_text("Current BG layer only. Slow when zoomed out on large maps");
+    moved_to
+}
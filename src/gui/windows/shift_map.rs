@@ -0,0 +1,76 @@
+use egui::Color32;
+
+use crate::{data::mapfile::MapShiftReport, engine::displayengine::DisplayEngine, NON_MAIN_FOCUSED};
+
+#[derive(Default)]
+pub struct ShiftMapSettings {
+    pub dx: i32,
+    pub dy: i32,
+    pub window_open: bool,
+    /// What a shift by dx/dy would lose, recalculated when they change
+    pub preview: Option<(i32, i32, MapShiftReport)>
+}
+
+/// Shifts a copy to see what would go out of bounds
+fn preview_shift(de: &DisplayEngine, dx: i32, dy: i32) -> MapShiftReport {
+    let mut map = de.loaded_map.clone();
+    let (width, height) = map.get_dimensions();
+    let mut report = map.shift_contents(dx, dy);
+    if let Some(map_info) = de.map_index.and_then(|index| de.loaded_course.level_map_data.get(index)) {
+        report.warps_clamped = map_info.clone().shift_warps(dx, dy, width, height);
+    }
+    report
+}
+
+/// True if Okay was clicked, see Gui::do_shift_map
+pub fn show_shift_map_modal(ui: &mut egui::Ui, de: &DisplayEngine, settings: &mut ShiftMapSettings) -> bool {
+    puffin::profile_function!();
+    ui.heading("Shift Map");
+    ui.label("Moves tiles, collision, Sprites, Paths, Triggers, Entrances, and Exits");
+    let base = de.display_settings.number_base;
+    ui.horizontal(|ui| {
+        let x_drag = base.drag_value(egui::DragValue::new(&mut settings.dx), 4).range(-0xffff..=0xffff);
+        let xres = ui.add(x_drag);
+        if xres.has_focus() {
+            *NON_MAIN_FOCUSED.lock().unwrap() = true;
+        }
+        ui.label("X (tiles)");
+    });
+    ui.horizontal(|ui| {
+        let y_drag = base.drag_value(egui::DragValue::new(&mut settings.dy), 4).range(-0xffff..=0xffff);
+        let yres = ui.add(y_drag);
+        if yres.has_focus() {
+            *NON_MAIN_FOCUSED.lock().unwrap() = true;
+        }
+        ui.label("Y (tiles)");
+    });
+    // Collision is 2x2
+    let is_even = settings.dx % 2 == 0 && settings.dy % 2 == 0;
+    if !is_even {
+        ui.label(egui::RichText::new("X and Y must both be even numbers").color(Color32::RED));
+    } else {
+        let preview_stale = settings.preview.is_none_or(|(dx, dy, _)| dx != settings.dx || dy != settings.dy);
+        if preview_stale {
+            settings.preview = Some((settings.dx, settings.dy, preview_shift(de, settings.dx, settings.dy)));
+        }
+        if let Some((_, _, report)) = &settings.preview {
+            if report.is_empty() {
+                ui.label("Everything stays in bounds");
+            } else {
+                ui.label(egui::RichText::new(format!("Warning: {report}")).color(Color32::ORANGE));
+            }
+        }
+    }
+    ui.add_space(5.0);
+    let mut do_shift = false;
+    ui.horizontal(|ui| {
+        if ui.button("Cancel").clicked() {
+            settings.window_open = false;
+        }
+        let can_shift = is_even && (settings.dx != 0 || settings.dy != 0);
+        if ui.add_enabled(can_shift, egui::Button::new("Okay")).clicked() {
+            do_shift = true;
+        }
+    });
+    do_shift
+}
@@ -1,14 +1,22 @@
-use egui::{Hyperlink, ScrollArea};
+use egui::{Color32, Hyperlink, RichText, ScrollArea};
 use egui_extras::{Column, TableBuilder};
 
-use crate::{data::types::CurrentLayer, engine::displayengine::DisplayEngine, load::SPRITE_METADATA, NON_MAIN_FOCUSED};
+use crate::{data::types::CurrentLayer, engine::displayengine::DisplayEngine, load::{sprite_meta, sprite_meta_ids}, NON_MAIN_FOCUSED};
 
 pub fn sprite_add_window_show(ui: &mut egui::Ui, de: &mut DisplayEngine) {
     puffin::profile_function!();
     ui.add(Hyperlink::from_label_and_url("Sprite Documentation", env!("SPRITE_DOC")));
+    ui.label(RichText::new("Right click or press P on the map to place").weak());
     if de.display_settings.current_layer != CurrentLayer::Sprites {
         ui.disable();
     }
+    if let Some(to_place) = de.selected_sprite_to_place {
+        let is_unknown = sprite_meta(to_place).is_none_or(|meta| meta.is_unknown());
+        if is_unknown {
+            ui.label(RichText::new("Warning: this Sprite is unknown and untested, it may crash the game")
+                .color(Color32::ORANGE));
+        }
+    }
     let search_bar = ui.text_edit_singleline(&mut de.sprite_search_query);
     if search_bar.has_focus() {
         *NON_MAIN_FOCUSED.lock().unwrap() = true;
@@ -22,6 +30,7 @@ pub fn sprite_add_window_show(ui: &mut egui::Ui, de: &mut DisplayEngine) {
 }
 
 fn create_table(ui: &mut egui::Ui, de: &mut DisplayEngine, query: &str) {
+    let base = de.display_settings.number_base;
     let _table = TableBuilder::new(ui)
     .striped(true)
     .resizable(false)
@@ -31,10 +40,9 @@ fn create_table(ui: &mut egui::Ui, de: &mut DisplayEngine, query: &str) {
     .column(Column::exact(200.0))
     .sense(egui::Sense::click())
     .body(|mut body| {
-        let max: u16 = 0x140;
-        for sprite_index in 0..max {
-            let sprite_meta = SPRITE_METADATA.get(&sprite_index);
-            if let Some(sprite) = sprite_meta {
+        // Includes the project's own entries, which can be past the game's IDs
+        for sprite_index in sprite_meta_ids() {
+            if let Some(sprite) = sprite_meta(sprite_index) {
                 if sprite.name == "Null" {
                     continue;
                 }
@@ -56,7 +64,7 @@ fn create_table(ui: &mut egui::Ui, de: &mut DisplayEngine, query: &str) {
                     // ID
                     row.col(|ui| {
                         ui.output_mut(|o| o.cursor_icon = egui::CursorIcon::PointingHand);
-                        let res = ui.label(format!("0x{:03X}",sprite.sprite_id));
+                        let res = ui.label(base.fmt_num(sprite.sprite_id, 3));
                         if res.clicked() {
                             de.selected_sprite_to_place = Some(sprite_index);
                         }
@@ -64,7 +72,12 @@ fn create_table(ui: &mut egui::Ui, de: &mut DisplayEngine, query: &str) {
                     // Name
                     row.col(|ui| {
                         ui.output_mut(|o| o.cursor_icon = egui::CursorIcon::PointingHand);
-                        let res = ui.label(&sprite.name);
+                        let res = if sprite.is_unknown() {
+                            ui.label(RichText::new(&sprite.name).color(Color32::ORANGE))
+                                .on_hover_text("Unknown Sprite, behavior and settings are untested")
+                        } else {
+                            ui.label(&sprite.name)
+                        };
                         if res.clicked() {
                             de.selected_sprite_to_place = Some(sprite_index);
                         }
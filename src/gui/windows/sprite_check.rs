@@ -0,0 +1,57 @@
+use egui::{Color32, RichText};
+
+use crate::{data::{mapfile::UnknownSpriteIssue, sprites::{find_sprite_issues, SpriteIssue, SpriteIssueKind}}, engine::displayengine::DisplayEngine, load::sprite_meta};
+
+/// Returns the issue to select, if one was clicked
+pub fn show_sprite_check_window(ui: &mut egui::Ui, de: &mut DisplayEngine) -> Option<SpriteIssue> {
+    puffin::profile_function!();
+    let base = de.display_settings.number_base;
+    ui.checkbox(&mut de.display_settings.highlight_sprite_issues, "Highlight on map");
+    show_unknown_sprites(ui, de);
+    let issues = find_sprite_issues(&de.level_sprites);
+    if issues.is_empty() {
+        ui.label("No duplicate or stacked Sprites found");
+        return None;
+    }
+    ui.label(format!("{} issues found",issues.len()));
+    ui.separator();
+    let mut clicked: Option<SpriteIssue> = Option::None;
+    egui::ScrollArea::vertical().auto_shrink(false).min_scrolled_height(1.0).show(ui, |ui| {
+        for issue in issues {
+            ui.horizontal(|ui| {
+                let name = sprite_meta(issue.object_id).map_or(String::from("Unknown"), |meta| meta.name.clone());
+                let text = match issue.kind {
+                    SpriteIssueKind::DuplicateUuid => RichText::new(format!("Duplicate UUID: {}",name)).color(Color32::RED),
+                    SpriteIssueKind::Stacked => RichText::new(format!("{} stacked: {}",issue.uuids.len(),name)).color(Color32::YELLOW),
+                };
+                ui.label(text);
+                ui.label(format!("at {}/{}",base.fmt_num(issue.x_position, 1),base.fmt_num(issue.y_position, 1)));
+                if ui.button("Select").clicked() {
+                    clicked = Some(issue.clone());
+                }
+            });
+        }
+    });
+    clicked
+}
+
+/// IDs missing from sprites.csv, each can get a stub in the project's own
+fn show_unknown_sprites(ui: &mut egui::Ui, de: &mut DisplayEngine) {
+    if de.unknown_sprite_issues.is_empty() {
+        return;
+    }
+    ui.label(RichText::new(format!("{} unknown Sprite IDs",de.unknown_sprite_issues.len())).color(Color32::ORANGE));
+    let mut stub_wanted: Option<UnknownSpriteIssue> = Option::None;
+    for issue in &de.unknown_sprite_issues {
+        ui.horizontal(|ui| {
+            ui.label(issue.to_string());
+            if ui.button("Add Stub Entry").clicked() {
+                stub_wanted = Some(*issue);
+            }
+        });
+    }
+    if let Some(issue) = stub_wanted {
+        de.add_sprite_stub(&issue);
+    }
+    ui.separator();
+}
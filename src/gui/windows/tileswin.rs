@@ -11,17 +11,24 @@ const TILE_RECT: Vec2 = Vec2::new(TILE_WIDTH, TILE_HEIGHT);
 const TILES_ARRAY_WIDTH: usize = 0x10;
 const TOP_MARGIN: f32 = 1.0;
 
-pub fn tiles_window_show(ui: &mut egui::Ui, preview_tile_cache: &[TextureHandle], de: &mut DisplayEngine) {
+/// `tile_usage` is indexed by tile ID, and when `used_only` is set, tiles
+/// with no placements are skipped and the rest are packed together
+pub fn tiles_window_show(ui: &mut egui::Ui, preview_tile_cache: &[TextureHandle], tile_usage: &[u32], used_only: bool, de: &mut DisplayEngine) {
     puffin::profile_function!();
     let painter: &egui::Painter = ui.painter();
     let top_left: Pos2 = ui.min_rect().min + Vec2::new(0.0, TOP_MARGIN);
     // Unable to be equal to anything if 0xfffff
     let selected_tile_index = de.selected_preview_tile.unwrap_or(0xfffff);
     let mut outline_rect: Option<Rect> = None;
-    for (tile_index,tile) in preview_tile_cache.iter().enumerate() {
-        let tex_id = &tile.id();
-        let tile_col_offset = (tile_index % TILES_ARRAY_WIDTH) as f32 * TILE_WIDTH;
-        let tile_row_offset = (tile_index / TILES_ARRAY_WIDTH) as f32 * TILE_HEIGHT;
+    let usage_of = |tile_index: usize| tile_usage.get(tile_index).copied().unwrap_or(0);
+    // Tile index at each drawn slot
+    let shown_tiles: Vec<usize> = (0..preview_tile_cache.len())
+        .filter(|tile_index| !used_only || usage_of(*tile_index) > 0)
+        .collect();
+    for (slot, &tile_index) in shown_tiles.iter().enumerate() {
+        let tex_id = &preview_tile_cache[tile_index].id();
+        let tile_col_offset = (slot % TILES_ARRAY_WIDTH) as f32 * TILE_WIDTH;
+        let tile_row_offset = (slot / TILES_ARRAY_WIDTH) as f32 * TILE_HEIGHT;
         // Do the render
         let rect: Rect = Rect::from_min_size(top_left + Vec2::new(tile_col_offset, tile_row_offset), TILE_RECT);
         // Find the UV
@@ -47,14 +54,28 @@ pub fn tiles_window_show(ui: &mut egui::Ui, preview_tile_cache: &[TextureHandle]
     // Add more clickable space
     ui.allocate_space(Vec2::new(300.0, 0.0));
     let click_response = ui.interact(ui.min_rect(), egui::Id::new("Tiles_Window_Click"), egui::Sense::click());
+    let min_rect = ui.min_rect();
+    let tile_at = |pointer_pos: Pos2| -> Option<usize> {
+        let local_pos = pointer_pos - min_rect.min;
+        let base_tile_x = (local_pos.x/TILE_WIDTH) as usize;
+        let base_tile_y = (local_pos.y/TILE_HEIGHT) as usize;
+        if local_pos.x < 0.0 || local_pos.y < 0.0 || base_tile_x >= TILES_ARRAY_WIDTH {
+            return None;
+        }
+        shown_tiles.get(base_tile_x + (base_tile_y * TILES_ARRAY_WIDTH)).copied()
+    };
+    if let Some(hovered_tile) = click_response.hover_pos().and_then(tile_at) {
+        let base = de.display_settings.number_base;
+        let uses = usage_of(hovered_tile);
+        click_response.clone().on_hover_text_at_pointer(format!("Tile {}: used {} time{}",
+            base.fmt_num(hovered_tile as u32, 3),uses,if uses == 1 { "" } else { "s" }));
+    }
     if click_response.clicked() {
         if let Some(pointer_pos) = ui.input(|i| i.pointer.latest_pos()) {
-            let local_pos = pointer_pos - ui.min_rect().min;
-            let base_tile_x: u32 = (local_pos.x/TILE_WIDTH) as u32;
-            let base_tile_y: u32 = (local_pos.y/TILE_HEIGHT) as u32;
-            let tile_index = base_tile_x + (base_tile_y * TILES_ARRAY_WIDTH as u32);
-            log_write(format!("pos: {}/{}: 0x{:X}",base_tile_x,base_tile_y,tile_index),LogLevel::Debug);
-            de.selected_preview_tile = Some(tile_index as usize);
+            if let Some(tile_index) = tile_at(pointer_pos) {
+                log_write(format!("Selected preview tile 0x{:X}",tile_index),LogLevel::Debug);
+                de.selected_preview_tile = Some(tile_index);
+            }
         } else {
             log_write("Unable to get pointer_pos in tileswin", LogLevel::Error);
         }
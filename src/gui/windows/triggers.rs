@@ -3,7 +3,7 @@ use egui::Color32;
 use egui_extras::{Column, Size, StripBuilder, TableBuilder};
 use uuid::Uuid;
 
-use crate::{data::{area::{Trigger, TriggerData}, mapfile::TopLevelSegmentWrapper, types::CurrentLayer}, engine::displayengine::DisplayEngine, utils::{log_write, LogLevel}, NON_MAIN_FOCUSED};
+use crate::{data::{area::{Trigger, TriggerData}, mapfile::TopLevelSegmentWrapper, types::CurrentLayer}, engine::displayengine::DisplayEngine, utils::{log_write, new_uuid, LogLevel}, NON_MAIN_FOCUSED};
 
 pub fn show_triggers_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
     puffin::profile_function!();
@@ -15,6 +15,7 @@ pub fn show_triggers_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
         if create.clicked() {
             let t = TriggerData::default();
             de.loaded_map.segments.push(TopLevelSegmentWrapper::AREA(t));
+            de.map_edited();
             log_write("Created new AREA database", LogLevel::Log);
             return;
         }
@@ -36,15 +37,16 @@ pub fn show_triggers_window(ui: &mut egui::Ui, de: &mut DisplayEngine) {
 }
 
 fn draw_trigger_list(ui: &mut egui::Ui, de: &mut DisplayEngine) {
+    let base = de.display_settings.number_base;
     ui.horizontal(|ui| {
         let add_button = ui.add(egui::Button::new("New"));
         if add_button.clicked() {
             log_write("Adding new Trigger", LogLevel::Log);
             let Some(area) = de.loaded_map.get_area_mut() else { return };
-            let new_trigger = Trigger { left_x: 2, top_y: 2, right_x: 12, bottom_y: 12, uuid: Uuid::new_v4() };
+            let new_trigger = Trigger { left_x: 2, top_y: 2, right_x: 12, bottom_y: 12, uuid: new_uuid() };
             de.trigger_settings.selected_uuid = new_trigger.uuid;
             area.triggers.push(new_trigger);
-            de.unsaved_changes = true;
+            de.map_edited();
             de.graphics_update_needed = true;
         }
         ui.style_mut().visuals.widgets.hovered.weak_bg_fill = Color32::RED;
@@ -56,7 +58,7 @@ fn draw_trigger_list(ui: &mut egui::Ui, de: &mut DisplayEngine) {
             let _did_delete = area.delete(de.trigger_settings.selected_uuid);
             de.trigger_settings.selected_uuid = Uuid::nil();
             de.graphics_update_needed = true;
-            de.unsaved_changes = true;
+            de.map_edited();
         }
     });
     ui.add_space(5.0);
@@ -71,7 +73,7 @@ fn draw_trigger_list(ui: &mut egui::Ui, de: &mut DisplayEngine) {
                     let row_index = row.index();
                     row.set_selected(de.trigger_settings.selected_uuid == trigger.uuid);
                     row.col(|ui| {
-                        let label = ui.label(format!("Trigger 0x{:X}",row_index));
+                        let label = ui.label(format!("Trigger {}",base.fmt_num(row_index as u32, 1)));
                         if label.clicked() {
                             de.trigger_settings.selected_uuid = trigger.uuid;
                         }
@@ -85,6 +87,7 @@ fn draw_trigger_list(ui: &mut egui::Ui, de: &mut DisplayEngine) {
 }
 
 fn draw_trigger_settings(ui: &mut egui::Ui, de: &mut DisplayEngine, trigger_uuid: Uuid) {
+    let base = de.display_settings.number_base;
     let Some(trigger_data) = de.loaded_map.get_area_mut() else {
         de.trigger_settings.selected_uuid = Uuid::nil();
         return;
@@ -101,8 +104,7 @@ fn draw_trigger_settings(ui: &mut egui::Ui, de: &mut DisplayEngine, trigger_uuid
     let trigger_before = *t;
     // Left X
     ui.horizontal(|ui| {
-        let left_x = egui::DragValue::new(&mut t.left_x)
-            .hexadecimal(4, false, true)
+        let left_x = base.drag_value(egui::DragValue::new(&mut t.left_x), 4)
             .range(0..=(t.right_x-1));
         let lxres = ui.label("Left X");
         if lxres.has_focus() {
@@ -112,8 +114,7 @@ fn draw_trigger_settings(ui: &mut egui::Ui, de: &mut DisplayEngine, trigger_uuid
     });
     // Top Y
     ui.horizontal(|ui| {
-        let top_y = egui::DragValue::new(&mut t.top_y)
-            .hexadecimal(4, false, true)
+        let top_y = base.drag_value(egui::DragValue::new(&mut t.top_y), 4)
             .range(0..=(t.bottom_y-1));
         let tyres = ui.label("Top Y");
         if tyres.has_focus() {
@@ -123,8 +124,7 @@ fn draw_trigger_settings(ui: &mut egui::Ui, de: &mut DisplayEngine, trigger_uuid
     });
     // Right X
     ui.horizontal(|ui| {
-        let right_x = egui::DragValue::new(&mut t.right_x)
-            .hexadecimal(4, false, true)
+        let right_x = base.drag_value(egui::DragValue::new(&mut t.right_x), 4)
             .range((t.left_x+1)..=0xffff);
         let rxres = ui.label("Right X");
         if rxres.has_focus() {
@@ -134,8 +134,7 @@ fn draw_trigger_settings(ui: &mut egui::Ui, de: &mut DisplayEngine, trigger_uuid
     });
     // Bottom Y
     ui.horizontal(|ui| {
-        let bottom_y = egui::DragValue::new(&mut t.bottom_y)
-            .hexadecimal(4, false, true)
+        let bottom_y = base.drag_value(egui::DragValue::new(&mut t.bottom_y), 4)
             .range((t.top_y+1)..=0xffff);
         let byres = ui.label("Bottom Y");
         if byres.has_focus() {
@@ -144,6 +143,6 @@ fn draw_trigger_settings(ui: &mut egui::Ui, de: &mut DisplayEngine, trigger_uuid
         ui.add(bottom_y);
     });
     if *t != trigger_before {
-        de.unsaved_changes = true;
+        de.map_edited();
     }
 }
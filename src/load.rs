@@ -1,11 +1,19 @@
-use std::{sync::LazyLock, time::Instant};
+use std::{fs, io::{self, Write}, path::Path, sync::{Arc, LazyLock, RwLock}, time::Instant};
 
 use egui::ahash::{HashMap, HashMapExt};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
-use crate::{data::sprites::SpriteMetadata, gui::{gui::Gui, windows::saved_brushes::load_stored_brushes}, utils::{log_write, LogLevel}};
+use crate::{data::sprites::{SpriteMetadata, SpritePresets, SpriteRanges}, gui::{gui::Gui, windows::saved_brushes::{load_stored_brushes, load_tile_mappings}}, utils::{log_write, LogLevel}};
 
-pub static SPRITE_METADATA: LazyLock<HashMap<u16,SpriteMetadata>> = LazyLock::new(load_sprite_csv);
+pub static SPRITE_METADATA: LazyLock<HashMap<u16,Arc<SpriteMetadata>>> = LazyLock::new(load_sprite_csv);
+/// From the project's own sprites.csv, for custom objects, replaced on project open
+pub static PROJECT_SPRITE_METADATA: LazyLock<RwLock<HashMap<u16,Arc<SpriteMetadata>>>> = LazyLock::new(|| RwLock::new(HashMap::new()));
+pub static SPRITE_PRESETS: LazyLock<SpritePresets> = LazyLock::new(|| {
+    serde_json::from_str(SPRITE_PRESETS_JSON).expect("Valid sprite_presets.json file")
+});
+pub static SPRITE_RANGES: LazyLock<SpriteRanges> = LazyLock::new(|| {
+    serde_json::from_str(SPRITE_RANGES_JSON).expect("Valid sprite_ranges.json file")
+});
 
 pub fn initial_load(gui: &mut Gui) {
     let gui_loading_time = Instant::now();
@@ -16,6 +24,9 @@ pub fn initial_load(gui: &mut Gui) {
     [
         || load_sprite_metadata(),
         || load_stored_brushes(),
+        || load_tile_mappings(),
+        || load_sprite_presets(),
+        || load_sprite_ranges(),
     ]
         .into_par_iter()
         .for_each(|f| f());
@@ -23,6 +34,9 @@ pub fn initial_load(gui: &mut Gui) {
 }
 
 const SPRITE_CSV: &str = include_str!("../assets/sprites.csv");
+pub const PROJECT_SPRITE_CSV_FILENAME: &str = "sprites.csv";
+const SPRITE_PRESETS_JSON: &str = include_str!("../assets/sprite_presets.json");
+const SPRITE_RANGES_JSON: &str = include_str!("../assets/sprite_ranges.json");
 
 fn load_sprite_metadata() {
     log_write("Loading Sprite database...", LogLevel::Debug);
@@ -30,50 +44,198 @@ fn load_sprite_metadata() {
     log_write("Loaded sprite database successfully", LogLevel::Log);
 }
 
-fn load_sprite_csv() -> HashMap<u16, SpriteMetadata> {
+fn load_sprite_presets() {
+    log_write("Loading Sprite presets...", LogLevel::Debug);
+    LazyLock::force(&SPRITE_PRESETS);
+    log_write("Loaded sprite presets successfully", LogLevel::Log);
+}
+
+fn load_sprite_ranges() {
+    log_write("Loading Sprite ranges...", LogLevel::Debug);
+    LazyLock::force(&SPRITE_RANGES);
+    log_write("Loaded sprite ranges successfully", LogLevel::Log);
+}
+
+fn load_sprite_csv() -> HashMap<u16, Arc<SpriteMetadata>> {
     let mut sprite_metadata = HashMap::new(); 
 
     for line in SPRITE_CSV.lines().skip(1) {
-        let mut iter = line.split(',');
-
-        let [id, name, description, len, _construction_function] =
-            std::array::from_fn(|_| iter.next().unwrap_or_else(|| panic!("Invalid Sprite CSV, line '{line}', doesn't contain 5 or more columns")));
-        // let settings: Vec<&str> = iter.collect(); // this can get uncommented if needed
-
-        // ID parsing
-        let id_no_prefix = id.trim_start_matches("0x");
-        let true_id = match u16::from_str_radix(id_no_prefix, 16) {
-            Err(error) => {
-                log_write(format!("Failure in parsing '{id_no_prefix}' as a u16: '{error}'"), LogLevel::Error);
-                continue;
-            }
-            Ok(id) => id,
-        };
-
-        // LEN parsing
-        let is_hex = len.starts_with("0x");
-        let settings_len_base = match is_hex {
-            true => 16,
-            false => 10,
-        };
-        let default_settings_len = match u16::from_str_radix(len.trim_start_matches("0x"), settings_len_base) {
-            Err(error) => {
-                let kind = match is_hex {
-                    true => "hex",
-                    false => "decimal",
-                };
-                log_write(format!("Error parsing Settings length string '{len}' as {kind}: '{error}'"), LogLevel::Fatal);
-                unreachable!()
-            }
-            Ok(func) => func,
-        };
-        let sprite_meta = SpriteMetadata {
-            sprite_id: true_id,
-            name: name.to_string(), description: description.to_string(),
-            default_settings_len,
-        };
-        sprite_metadata.insert(true_id, sprite_meta);
+        let sprite_meta = parse_sprite_line(line).unwrap_or_else(|error| panic!("Invalid Sprite CSV: {error}"));
+        sprite_metadata.insert(sprite_meta.sprite_id, Arc::new(sprite_meta));
     }
 
     sprite_metadata
 }
+
+fn parse_sprite_line(line: &str) -> Result<SpriteMetadata, String> {
+    let all_columns: Vec<&str> = line.split(',').collect();
+    if all_columns.len() < 7 {
+        return Err(format!("line '{line}' doesn't contain 7 or more columns"));
+    }
+    let [id, name, description, len, _construction_function] = std::array::from_fn(|i| all_columns[i]);
+    // Render offsets are the last columns, after the settings (which can be quoted and contain commas)
+    let [render_x, render_y] = std::array::from_fn(|i| all_columns[all_columns.len() - 2 + i]);
+    // let settings = &all_columns[5..all_columns.len() - 2]; // this can get uncommented if needed
+
+    // ID parsing
+    let id_no_prefix = id.trim_start_matches("0x");
+    let true_id = u16::from_str_radix(id_no_prefix, 16)
+        .map_err(|error| format!("failure in parsing '{id_no_prefix}' as a u16: '{error}'"))?;
+
+    // LEN parsing
+    let is_hex = len.starts_with("0x");
+    let (settings_len_base, kind) = match is_hex {
+        true => (16, "hex"),
+        false => (10, "decimal"),
+    };
+    let default_settings_len = u16::from_str_radix(len.trim_start_matches("0x"), settings_len_base)
+        .map_err(|error| format!("error parsing Settings length string '{len}' as {kind}: '{error}'"))?;
+    // Render offsets, in pixels and optional
+    let parse_offset = |offset: &str| -> i16 {
+        if offset.trim().is_empty() {
+            return 0;
+        }
+        offset.trim().parse::<i16>().unwrap_or_else(|error| {
+            log_write(format!("Error parsing render offset '{offset}' for Sprite 0x{true_id:X}: '{error}'"), LogLevel::Error);
+            0
+        })
+    };
+    Ok(SpriteMetadata {
+        sprite_id: true_id,
+        name: name.to_string(), description: description.to_string(),
+        default_settings_len,
+        render_offset_x: parse_offset(render_x),
+        render_offset_y: parse_offset(render_y)
+    })
+}
+
+/// The project's entries win over the editor's, so a known ID can be renamed too
+/// 
+/// Shared rather than copied, since this runs for every drawn Sprite
+pub fn sprite_meta(object_id: u16) -> Option<Arc<SpriteMetadata>> {
+    if let Some(project_meta) = PROJECT_SPRITE_METADATA.read().unwrap().get(&object_id) {
+        return Some(Arc::clone(project_meta));
+    }
+    SPRITE_METADATA.get(&object_id).cloned()
+}
+
+/// Every ID with metadata, sorted
+pub fn sprite_meta_ids() -> Vec<u16> {
+    let mut ids: Vec<u16> = SPRITE_METADATA.keys().copied().collect();
+    ids.extend(PROJECT_SPRITE_METADATA.read().unwrap().keys().copied());
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+/// Replaces the project entries, a missing file just means there are none
+pub fn load_project_sprite_csv(project_dir: &Path) {
+    let mut project_meta = PROJECT_SPRITE_METADATA.write().unwrap();
+    project_meta.clear();
+    let path = project_dir.join(PROJECT_SPRITE_CSV_FILENAME);
+    let Ok(text) = fs::read_to_string(&path) else {
+        log_write(format!("No {} in project",PROJECT_SPRITE_CSV_FILENAME), LogLevel::Debug);
+        return;
+    };
+    for line in text.lines().skip(1).filter(|line| !line.trim().is_empty()) {
+        match parse_sprite_line(line) {
+            Ok(sprite_meta) => {
+                project_meta.insert(sprite_meta.sprite_id, Arc::new(sprite_meta));
+            }
+            Err(error) => log_write(format!("Skipping line in '{}': {error}",path.display()), LogLevel::Warn),
+        }
+    }
+    log_write(format!("Loaded {} project Sprite entries",project_meta.len()), LogLevel::Log);
+}
+
+/// Appends a placeholder row to the project's sprites.csv and uses it right away
+pub fn add_sprite_stub(project_dir: &Path, object_id: u16, settings_len: u16) -> io::Result<SpriteMetadata> {
+    let path = project_dir.join(PROJECT_SPRITE_CSV_FILENAME);
+    let existing = fs::read(&path).unwrap_or_default();
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(&path)?;
+    if existing.is_empty() {
+        // Same header as the editor's, so it can be copied in later
+        let header = SPRITE_CSV.lines().next().unwrap_or_default();
+        writeln!(file, "{header}")?;
+    } else if existing.last() != Some(&b'\n') {
+        writeln!(file)?;
+    }
+    let stub = SpriteMetadata {
+        sprite_id: object_id,
+        name: format!("Unknown 0x{object_id:X}"),
+        description: String::from("Stub with the settings length taken from the map"),
+        default_settings_len: settings_len,
+        render_offset_x: 0,
+        render_offset_y: 0
+    };
+    // Construction Function, 10 Settings, then Render X/Y, all empty
+    writeln!(file, "0x{:x},{},{},{}{}",object_id,stub.name,stub.description,settings_len,",".repeat(13))?;
+    log_write(format!("Added stub for Sprite 0x{:X} to '{}'",object_id,path.display()), LogLevel::Log);
+    PROJECT_SPRITE_METADATA.write().unwrap().insert(object_id, Arc::new(stub.clone()));
+    Ok(stub)
+}
+
+#[cfg(test)]
+mod tests_load {
+    use super::*;
+    use crate::data::{fixtures::{sample_map, TempFolder}, mapfile::UnknownSpriteIssue, sprites::{LevelSprite, SpriteRangeShape}};
+
+    #[test]
+    fn test_sprite_presets_fit() {
+        for preset in &SPRITE_PRESETS.presets {
+            let meta = SPRITE_METADATA.get(&preset.object_id)
+                .unwrap_or_else(|| panic!("Preset '{}' has unknown ID 0x{:X}",preset.label,preset.object_id));
+            assert_eq!(meta.default_settings_len as usize,preset.settings.len(),"Preset '{}' length",preset.label);
+        }
+    }
+
+    #[test]
+    fn test_sprite_ranges_fit() {
+        for range in &SPRITE_RANGES.ranges {
+            let meta = SPRITE_METADATA.get(&range.object_id)
+                .unwrap_or_else(|| panic!("Range '{}' has unknown ID 0x{:X}",range.label,range.object_id));
+            let settings_len = meta.default_settings_len as usize;
+            assert!(range.size_byte + range.size_len <= settings_len,"Range '{}' size is past the settings",range.label);
+            assert!(range.direction_byte.is_none_or(|byte| byte < settings_len),"Range '{}' direction is past the settings",range.label);
+            assert!(!range.directions.is_empty() || range.shape == SpriteRangeShape::Circle,"Range '{}' needs a direction",range.label);
+        }
+    }
+
+    #[test]
+    fn test_sprite_csv_columns() {
+        // Render offsets are the last columns, so quoted commas in the settings must not shift them
+        let coin = SPRITE_METADATA.get(&0x0).expect("Yellow Coin exists");
+        assert_eq!(coin.name,"Yellow Coin");
+        assert_eq!(coin.default_settings_len,0);
+        let cloud = SPRITE_METADATA.get(&0x13).expect("Winged Cloud exists");
+        assert_eq!((cloud.render_offset_x,cloud.render_offset_y),(0,0));
+        let flower = SPRITE_METADATA.get(&0x28).expect("Flower Collectible exists");
+        assert_eq!((flower.render_offset_x,flower.render_offset_y),(-8,-8));
+        assert!(SPRITE_METADATA.values().all(|meta| meta.render_offset_x.abs() < 0x100 && meta.render_offset_y.abs() < 0x100));
+    }
+
+    #[test]
+    fn test_project_sprite_stub() {
+        let project_dir = TempFolder::new("project_sprites");
+        let mut map = sample_map(Path::new("test.mpdz"));
+        let setd = map.get_setd().expect("Sample has SETD");
+        setd.sprites.push(LevelSprite::new(0xFFF0, 1, 1, vec![0; 8]));
+        setd.sprites.push(LevelSprite::new(0xFFF0, 2, 1, vec![0; 8]));
+        setd.sprites.push(LevelSprite::new(0xFFF0, 3, 1, vec![0; 4]));
+        // The length most of them use
+        assert_eq!(map.unknown_sprite_issues(),vec![UnknownSpriteIssue { object_id: 0xFFF0, settings_len: 8, count: 3 }]);
+        let stub = add_sprite_stub(&project_dir, 0xFFF0, 8).expect("Temp dir is writable");
+        assert!(stub.is_unknown());
+        assert!(map.unknown_sprite_issues().is_empty());
+        assert!(sprite_meta_ids().contains(&0xFFF0));
+        // Survives a reload, and the editor's own entries are untouched
+        load_project_sprite_csv(&project_dir);
+        let reloaded = sprite_meta(0xFFF0).expect("Stub was saved");
+        assert_eq!((reloaded.name.as_str(),reloaded.default_settings_len),("Unknown 0xFFF0", 8));
+        assert_eq!(sprite_meta(0x0).expect("Yellow Coin exists").name,"Yellow Coin");
+        let project_path = project_dir.to_path_buf();
+        drop(project_dir);
+        load_project_sprite_csv(&project_path);
+        assert!(sprite_meta(0xFFF0).is_none());
+    }
+}
@@ -6,7 +6,7 @@
 #![allow(clippy::collapsible_if)]
 #![allow(clippy::collapsible_else_if)]
 
-use std::sync::{LazyLock, Mutex};
+use std::{path::{Path, PathBuf}, sync::{LazyLock, Mutex}};
 
 use clap::Parser;
 use egui::Vec2;
@@ -14,7 +14,7 @@ use gui::gui::Gui;
 use log::LevelFilter;
 use utils::{log_write, LogLevel};
 
-use crate::load::initial_load;
+use crate::{engine::{bug_bundle::LOG_FILENAME, verify::{summarize, verify_project}}, load::initial_load};
 
 mod load;
 mod utils;
@@ -29,18 +29,44 @@ const VERSION: &str = env!("CARGO_PKG_VERSION");
 #[command(version, about, long_about = None)]
 pub struct Args {
     #[arg(short,long)]
-    debug: bool
+    debug: bool,
+    /// Verify every Map and Course in an extracted project folder, then exit
+    #[arg(long,value_name = "PROJECT_FOLDER")]
+    check: Option<PathBuf>,
+    /// Open a .crsb or .mpdz read-only, for file associations.
+    /// A project folder or its config.yaml opens the project instead
+    #[arg(value_name = "FILE")]
+    view: Option<PathBuf>
 }
 
 static CLI_ARGS: LazyLock<Args> = LazyLock::new(Args::parse);
 static NON_MAIN_FOCUSED: LazyLock<Mutex<bool>> = LazyLock::new(|| Mutex::new(false));
 
+/// Same as Tools > Verify Project, exits non-zero if any file has issues
+fn run_check(project_folder: &Path) -> ! {
+    match verify_project(project_folder, |_, _| {}) {
+        Ok(results) => {
+            println!("{}", summarize(&results));
+            let has_issues = results.iter().any(|r| r.issue.is_some());
+            std::process::exit(if has_issues { 1 } else { 0 });
+        }
+        Err(e) => {
+            eprintln!("Could not read project folder '{}': {e}", project_folder.display());
+            std::process::exit(2);
+        }
+    }
+}
+
 fn main() -> eframe::Result {
-    let _ = simple_logging::log_to_file("stork.log", LevelFilter::Info);
+    let _ = simple_logging::log_to_file(LOG_FILENAME, LevelFilter::Info);
     log_panics::init(); // We want it to go in stork.log
 
     log_write(format!("== Starting Stork Editor {} ==", VERSION), LogLevel::Log);
 
+    if let Some(project_folder) = &CLI_ARGS.check {
+        run_check(project_folder);
+    }
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size(Vec2::new(1000.0, 800.0))
@@ -61,7 +87,16 @@ fn main() -> eframe::Result {
                 log_write("No default system theme found, defaulting to Dark", LogLevel::Warn);
                 cc.egui_ctx.set_theme(egui::Theme::Dark);
             }
+            gui.load_persisted_settings(&cc.egui_ctx, cc.storage);
             initial_load(&mut gui);
+            if let Some(view_path) = &CLI_ARGS.view {
+                let is_project = view_path.is_dir() || view_path.extension().is_some_and(|ext| ext == "yaml");
+                if is_project {
+                    gui.open_project(view_path.clone());
+                } else {
+                    gui.view_file(view_path);
+                }
+            }
 
             Ok(gui)
         })
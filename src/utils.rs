@@ -1,8 +1,9 @@
-use std::{collections::HashMap, f32::consts::PI, fmt::{Display, Write}, fs::{self, write}, io::{Cursor, Read}, num::ParseIntError, path::PathBuf};
+use std::{collections::HashMap, f32::consts::PI, fmt::{Display, Write}, fs::{self, write}, io::{Cursor, Read}, num::ParseIntError, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
 
 use byteorder::{LittleEndian, ReadBytesExt};
 use colored::Colorize;
 use egui::{pos2, Color32, ColorImage, Pos2, Rect, TextureHandle};
+use uuid::Uuid;
 
 use crate::{data::{path::PathPoint, types::{MapTileRecordData, Palette}}, engine::displayengine::{get_gameversion_prettyname, GameVersion}, gui::windows::paths_win::PathAngle, CLI_ARGS};
 
@@ -68,8 +69,36 @@ pub fn print_vector_u8(byte_vector: &[u8]) {
     }
 }
 
-pub fn get_sin_cos_table_value(arm9: &[u8], value: u16, v: GameVersion) -> PathAngle {
-    let table_addr: u32 = match v {
+/// A read from a hardcoded ARM9 address that goes past the end of arm9.bin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Arm9ReadError {
+    pub what: &'static str,
+    /// Where the read would end
+    pub end: u64,
+    pub arm9_len: usize
+}
+impl Display for Arm9ReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("ARM9 smaller than expected for this version: {} needs 0x{:X} bytes, but arm9.bin is 0x{:X}",
+            self.what,self.end,self.arm9_len))
+    }
+}
+impl std::error::Error for Arm9ReadError {}
+
+/// Check before setting a cursor to a hardcoded address
+pub fn check_arm9_read(arm9: &[u8], what: &'static str, addr: u64, len: u64) -> Result<(), Arm9ReadError> {
+    let end = addr + len;
+    if end > arm9.len() as u64 {
+        return Err(Arm9ReadError { what, end, arm9_len: arm9.len() });
+    }
+    Ok(())
+}
+
+/// 0x1000 angles, each a pair of i16s
+pub const SIN_COS_TABLE_LEN: u64 = 0x4000;
+
+pub fn sin_cos_table_addr(v: GameVersion) -> u32 {
+    match v {
         // To find: look up 00 00 00 10 06 00 00 10 0d 00 00 10...
         GameVersion::USA10 => 0x0d1878, // 020d1878
         GameVersion::USA11 => 0x0d1ad0, // 020d1ad0
@@ -77,18 +106,23 @@ pub fn get_sin_cos_table_value(arm9: &[u8], value: u16, v: GameVersion) -> PathA
             log_write(format!("Attempted to get sincos table for {}",get_gameversion_prettyname(&v)), LogLevel::Fatal);
             unreachable!()
         }
-    };
+    }
+}
+
+pub fn get_sin_cos_table_value(arm9: &[u8], value: u16, v: GameVersion) -> Result<PathAngle, Arm9ReadError> {
+    let table_addr: u32 = sin_cos_table_addr(v);
+    check_arm9_read(arm9, "SinCos table", table_addr as u64, SIN_COS_TABLE_LEN)?;
     let mut rdr = Cursor::new(arm9);
     // Value 1
     let pos1 = table_addr + ((value as u32 >> 4) * 2 + 1) * 2;
     rdr.set_position(pos1 as u64);
-    let sh1 = rdr.read_i16::<LittleEndian>().expect("Reading SinCos value 1");
+    let sh1 = rdr.read_i16::<LittleEndian>().expect("Checked against the table length");
     // Value 2
     #[allow(clippy::identity_op)]
     let pos2 = table_addr + ((value as u32 >> 4) * 2 + 0) * 2;
     rdr.set_position(pos2 as u64);
-    let sh2 = rdr.read_i16::<LittleEndian>().expect("Reading SinCos value 2");
-    PathAngle { x: sh1, y: sh2 }
+    let sh2 = rdr.read_i16::<LittleEndian>().expect("Checked against the table length");
+    Ok(PathAngle { x: sh1, y: sh2 })
 }
 
 #[allow(dead_code)] // May not be used in final
@@ -247,9 +281,9 @@ pub fn read_c_string<T: ReadBytesExt>(rdr: &mut T) -> String {
 }
 
 pub fn read_address<T: ReadBytesExt>(rdr: &mut T)  -> Option<u32> {
-    let mut address: u32 = read_u32(rdr)?;
-    address -= 0x2000000;
-    Some(address)
+    let address: u32 = read_u32(rdr)?;
+    // Anything below is not in main RAM
+    address.checked_sub(0x2000000)
 }
 
 pub fn read_fixed_string(vec_data: &[u8], position: u64, length: u32) -> String {
@@ -371,6 +405,20 @@ pub fn get_backup_folder(export_dir: &PathBuf) -> Option<PathBuf> {
     Some(p)
 }
 
+/// Copies a file into the backups folder with the time appended. None if there
+/// is no backups folder, which is logged by get_backup_folder
+pub fn backup_file(export_dir: &PathBuf, file: &Path) -> std::io::Result<Option<PathBuf>> {
+    let Some(mut backup_path) = get_backup_folder(export_dir) else {
+        return Ok(None);
+    };
+    let file_name = file.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time Travel").as_secs();
+    backup_path.push(format!("{}.{:?}.bak",file_name,time));
+    fs::copy(file, &backup_path)?;
+    log_write(format!("Backed up {} to {}",file.display(),backup_path.display()), LogLevel::Log);
+    Ok(Some(backup_path))
+}
+
 pub fn get_template_folder(export_dir: &PathBuf) -> Option<PathBuf> {
     let mut p: PathBuf = PathBuf::from(export_dir);
     p.push("templates");
@@ -449,10 +497,21 @@ pub fn get_uvs_from_tile(tile: &MapTileRecordData) -> Rect {
     uvs
 }
 
+/// Bytes of pixel data for one 8x8 tile
+pub fn tile_pixel_len(is_256: bool) -> usize {
+    if is_256 { 64 } else { 32 }
+}
+
+/// Whether the tileset has pixels for tile_id. Tiles past the end (often from
+/// missing ANMZ or IMBZ data) are drawn as red error tiles
+pub fn tile_has_pixels(pixel_tiles_len: usize, tile_id: u16, is_256: bool) -> bool {
+    (tile_id as usize + 1) * tile_pixel_len(is_256) <= pixel_tiles_len
+}
+
 pub fn get_pixel_bytes_16(pixel_tiles: &[u8], tile_id: &u16) -> Vec<u8> {
     let array_start: usize = *tile_id as usize * 32;
     let array_end: usize = array_start + 32;
-    if array_end > pixel_tiles.len() {
+    if !tile_has_pixels(pixel_tiles.len(), *tile_id, false) {
         // Without ANMZ, this fired constantly
         log_write(format!("get_pixel_bytes_16 draw overflow, offending tile_id: 0x{:X}/{}",tile_id,tile_id), LogLevel::Error);
         return [1;64].to_vec();
@@ -463,7 +522,7 @@ pub fn get_pixel_bytes_16(pixel_tiles: &[u8], tile_id: &u16) -> Vec<u8> {
 pub fn get_pixel_bytes_256(pixel_tiles: &[u8], tile_id: &u16) -> Vec<u8> {
     let array_start: usize = *tile_id as usize * 64;
     let array_end: usize = array_start + 64;
-    if array_end > pixel_tiles.len() {
+    if !tile_has_pixels(pixel_tiles.len(), *tile_id, true) {
         // Without ANMZ, this fired constantly
         log_write(format!("get_pixel_bytes_256 draw overflow(0x{:X} >= 0x{:X}), offending tile_id: 0x{:X}/{}",
             array_end,pixel_tiles.len(),tile_id,tile_id), LogLevel::Error);
@@ -541,25 +600,81 @@ pub fn read_u32<T: ReadBytesExt>(rdr: &mut T) -> Option<u32> {
 
 #[inline]
 pub fn is_debug() -> bool {
+    // The argv is libtest's, which clap would reject
+    if cfg!(test) {
+        return false;
+    }
     CLI_ARGS.debug
 }
 
+#[cfg(test)]
+thread_local! {
+    static NEXT_TEST_UUID: std::cell::Cell<u128> = const { std::cell::Cell::new(1) };
+}
+
+/// Use this instead of Uuid::new_v4 for anything loaded or created
+///
+/// Tests count up from a per-thread seed instead, so loading the same file twice
+/// after seed_uuids gives identical data. See seed_uuids
+pub fn new_uuid() -> Uuid {
+    #[cfg(test)]
+    {
+        NEXT_TEST_UUID.with(|next| {
+            let id = next.get();
+            next.set(id + 1);
+            Uuid::from_u128(id)
+        })
+    }
+    #[cfg(not(test))]
+    {
+        Uuid::new_v4()
+    }
+}
+
+/// Restarts the UUIDs new_uuid gives on this thread, never nil
+#[cfg(test)]
+pub fn seed_uuids(seed: u128) {
+    NEXT_TEST_UUID.with(|next| next.set(seed.max(1)));
+}
+
 #[cfg(test)]
 mod tests_utils {
     use super::*;
 
+    #[test]
+    fn test_seeded_uuids() {
+        seed_uuids(5);
+        let first: Vec<Uuid> = (0..3).map(|_| new_uuid()).collect();
+        seed_uuids(5);
+        let second: Vec<Uuid> = (0..3).map(|_| new_uuid()).collect();
+        assert_eq!(first,second);
+        assert_ne!(first[0],first[1]);
+        seed_uuids(0);
+        assert!(!new_uuid().is_nil());
+    }
+
     #[test]
     fn test_sanity() {
         assert_eq!(true,true);
     }
 
+    #[test]
+    fn test_tile_has_pixels() {
+        assert!(tile_has_pixels(64, 1, false));
+        assert!(!tile_has_pixels(64, 2, false));
+        assert!(!tile_has_pixels(64, 1, true));
+        assert!(!tile_has_pixels(0, 0, false));
+        assert_eq!(get_pixel_bytes_16(&[0x12; 32], &1),vec![1; 64]);
+        assert_eq!(get_pixel_bytes_16(&[0x12; 32], &0),vec![0x12; 32]);
+    }
+
     #[test]
     fn test_abs() {
         let mut correct: PathBuf = PathBuf::from("yids_extract");
         correct.push("files");
         correct.push("file");
         correct.push("test.bin");
-        let maybe = nitrofs_abs(PathBuf::from("yids_extract"),&"test.bin".to_owned());
+        let maybe = nitrofs_abs(PathBuf::from("yids_extract"),"test.bin");
         assert_eq!(correct,maybe);
     }
 